@@ -66,10 +66,10 @@ function AppContainer() {
 	// Enables dark mode, and screen size breakpoints, etc. for tailwind
 	useDeviceContext(tw, { withDeviceColorScheme: false });
 
-	useInvalidateQuery();
-
 	const { id } = useSnapshot(currentLibraryStore);
 
+	useInvalidateQuery(id);
+
 	return (
 		<SafeAreaProvider style={tw`bg-app flex-1`}>
 			<GestureHandlerRootView style={tw`flex-1`}>
@@ -15,9 +15,11 @@ import FileRow from './FileRow';
 
 type ExplorerProps = {
 	data: ExplorerData | undefined;
+	/** Called when the end of the listing is reached, to load its next page */
+	onLoadMore?: () => void;
 };
 
-const Explorer = ({ data }: ExplorerProps) => {
+const Explorer = ({ data, onLoadMore }: ExplorerProps) => {
 	const navigation = useNavigation<SharedScreenProps<'Location'>['navigation']>();
 
 	const [layoutMode, setLayoutMode] = useState<'grid' | 'list'>(getExplorerStore().layoutMode);
@@ -69,6 +71,7 @@ const Explorer = ({ data }: ExplorerProps) => {
 						</Pressable>
 					)}
 					extraData={layoutMode}
+					onEndReached={onLoadMore}
 					estimatedItemSize={
 						layoutMode === 'grid'
 							? Layout.window.width / getExplorerStore().gridNumColumns
@@ -31,7 +31,7 @@ const DeleteLibraryModal = ({ trigger, onSubmit, libraryUuid }: Props) => {
 			title="Delete Library"
 			description="Deleting a library will permanently the database, the files themselves will not be deleted."
 			ctaLabel="Delete"
-			ctaAction={() => deleteLibrary(libraryUuid)}
+			ctaAction={() => deleteLibrary({ id: libraryUuid, purge: true })}
 			loading={deleteLibLoading}
 			trigger={trigger}
 			ctaDanger
@@ -1,5 +1,5 @@
 import { useEffect } from 'react';
-import { useLibraryQuery } from '@sd/client';
+import { useExplorerData } from '@sd/client';
 import Explorer from '~/components/explorer/Explorer';
 import { SharedScreenProps } from '~/navigation/SharedScreens';
 import { getExplorerStore } from '~/stores/explorerStore';
@@ -7,15 +7,13 @@ import { getExplorerStore } from '~/stores/explorerStore';
 export default function LocationScreen({ navigation, route }: SharedScreenProps<'Location'>) {
 	const { id, path } = route.params;
 
-	const { data } = useLibraryQuery([
-		'locations.getExplorerData',
-		{
-			location_id: id,
-			path: path ?? '',
-			limit: 100,
-			cursor: null
-		}
-	]);
+	const { data, hasNextPage, isFetchingNextPage, fetchNextPage } = useExplorerData({
+		location_id: id,
+		path: path || '/',
+		limit: 100,
+		filter: null,
+		show_hidden: null
+	});
 
 	useEffect(() => {
 		// Set screen title to location.
@@ -36,5 +34,12 @@ export default function LocationScreen({ navigation, route }: SharedScreenProps<
 		getExplorerStore().path = path ?? '';
 	}, [id, path]);
 
-	return <Explorer data={data} />;
+	return (
+		<Explorer
+			data={data}
+			onLoadMore={() => {
+				if (hasNextPage && !isFetchingNextPage) fetchNextPage();
+			}}
+		/>
+	);
 }
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use axum::{
+	extract::State,
+	http::{header::AUTHORIZATION, HeaderMap, Request, StatusCode},
+	middleware::Next,
+	response::Response,
+};
+use sd_core::Node;
+
+/// AuthState holds what clients can authenticate with.
+#[derive(Clone)]
+pub struct AuthState {
+	/// token is the node's auth token, which gives full access to the node.
+	pub token: Option<Arc<str>>,
+	pub node: Arc<Node>,
+}
+
+/// request_token returns the token carried by the `Authorization: Bearer <token>` header of a request.
+/// Tokens are never read from the URL, as it ends up in logs and browser history.
+pub fn request_token(headers: &HeaderMap) -> Option<&str> {
+	headers
+		.get(AUTHORIZATION)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// require_token rejects requests which don't carry the node's auth token or the token of one of its users.
+/// When neither are set up, every request is allowed.
+pub async fn require_token<B>(
+	State(state): State<AuthState>,
+	req: Request<B>,
+	next: Next<B>,
+) -> Result<Response, StatusCode> {
+	if state.token.is_none() && !state.node.has_users() {
+		return Ok(next.run(req).await);
+	}
+
+	let authenticated = match request_token(req.headers()) {
+		Some(provided) => {
+			state.token.as_ref().map_or(false, |token| {
+				constant_time_eq(provided.as_bytes(), token.as_bytes())
+			}) || state.node.authenticate(provided).is_some()
+		}
+		None => false,
+	};
+
+	match authenticated {
+		true => Ok(next.run(req).await),
+		false => Err(StatusCode::UNAUTHORIZED),
+	}
+}
+
+/// constant_time_eq compares two byte slices without leaking how many leading bytes match through timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -1,9 +1,12 @@
-use std::{env, net::SocketAddr, path::Path};
+use std::{env, net::SocketAddr, path::Path, sync::Arc, time::Duration};
 
-use axum::routing::get;
+use axum::{middleware, routing::get};
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+use httpz::Request;
 use sd_core::{custom_uri::create_custom_uri_endpoint, Node};
-use tracing::info;
+use tracing::{info, warn};
 
+mod auth;
 mod utils;
 
 #[tokio::main]
@@ -28,28 +31,107 @@ async fn main() {
 		.map(|port| port.parse::<u16>().unwrap_or(8080))
 		.unwrap_or(8080);
 
+	// Serving over HTTP when only one of them is set would silently expose the node unencrypted
+	let tls = match (env::var("TLS_CERT"), env::var("TLS_KEY")) {
+		(Ok(cert_path), Ok(key_path)) => Some((cert_path, key_path)),
+		(Err(_), Err(_)) => None,
+		_ => panic!("'$TLS_CERT' and '$TLS_KEY' must both be set to serve over HTTPS!"),
+	};
+
 	let (node, router) = Node::new(data_dir).await.expect("Unable to create node");
 	let signal = utils::axum_shutdown_signal(node.clone());
 
-	let app = axum::Router::new()
-		.route("/", get(|| async { "Spacedrive Server!" }))
-		.route("/health", get(|| async { "OK" }))
+	// Every client must present this token, or the token of one of the node's users, when set.
+	// This is required to safely expose the node on a network
+	let auth_token = env::var("AUTH_TOKEN")
+		.ok()
+		.filter(|token| !token.is_empty())
+		.map(Arc::<str>::from);
+
+	let mut api = axum::Router::new()
 		.nest(
 			"/spacedrive",
 			create_custom_uri_endpoint(node.clone()).axum(),
 		)
 		.nest(
 			"/rspc",
-			router.endpoint(move || node.get_request_context()).axum(),
-		)
+			router
+				.endpoint({
+					let node = node.clone();
+					// Requests made with a user's token are restricted to what that user can access
+					move |req: Request| {
+						let user = auth::request_token(req.headers())
+							.and_then(|token| node.authenticate(token));
+						node.get_user_request_context(user)
+					}
+				})
+				.axum(),
+		);
+
+	// Prometheus metrics are opt-in, and are protected by the auth token like the rest of the API
+	if env::var("METRICS").map(|v| v == "true").unwrap_or(false) {
+		api = api.route(
+			"/metrics",
+			get({
+				let node = node.clone();
+				move || {
+					let node = node.clone();
+					async move { node.metrics().await }
+				}
+			}),
+		);
+	}
+
+	if auth_token.is_none() && !node.has_users() {
+		warn!("'$AUTH_TOKEN' is not set and there are no users, anyone who can reach this server can access your libraries!");
+	}
+
+	let api = api.layer(middleware::from_fn_with_state(
+		auth::AuthState {
+			token: auth_token,
+			node,
+		},
+		auth::require_token,
+	));
+
+	let app = axum::Router::new()
+		.route("/", get(|| async { "Spacedrive Server!" }))
+		.route("/health", get(|| async { "OK" }))
+		.merge(api)
 		.fallback(|| async { "404 Not Found: We're past the event horizon..." });
 
 	let mut addr = "[::]:8080".parse::<SocketAddr>().unwrap(); // This listens on IPv6 and IPv4
 	addr.set_port(port);
-	info!("Listening on http://localhost:{}", port);
-	axum::Server::bind(&addr)
-		.serve(app.into_make_service())
-		.with_graceful_shutdown(signal)
-		.await
-		.expect("Error with HTTP server!");
+
+	match tls {
+		Some((cert_path, key_path)) => {
+			let tls_config = RustlsConfig::from_pem_file(cert_path, key_path)
+				.await
+				.expect("Unable to load the TLS certificate and key!");
+
+			let handle = Handle::new();
+			tokio::spawn({
+				let handle = handle.clone();
+				async move {
+					signal.await;
+					handle.graceful_shutdown(Some(Duration::from_secs(10)));
+				}
+			});
+
+			info!("Listening on https://localhost:{}", port);
+			axum_server::bind_rustls(addr, tls_config)
+				.handle(handle)
+				.serve(app.into_make_service())
+				.await
+				.expect("Error with HTTPS server!");
+		}
+		None => {
+			info!("Listening on http://localhost:{}", port);
+			axum::Server::bind(&addr)
+				.serve(app.into_make_service())
+				.with_graceful_shutdown(signal)
+				.await
+				.expect("Error with HTTP server!");
+		}
+	}
 }
@@ -0,0 +1,13 @@
+-- CreateTable
+CREATE TABLE "activity" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "kind" INTEGER NOT NULL,
+    "message" TEXT NOT NULL,
+    "data" BLOB,
+    "node_id" INTEGER NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "activity_node_id_fkey" FOREIGN KEY ("node_id") REFERENCES "node" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "activity_date_created_idx" ON "activity"("date_created");
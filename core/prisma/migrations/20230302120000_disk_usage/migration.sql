@@ -0,0 +1,24 @@
+-- CreateTable
+CREATE TABLE "disk_usage" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "root" TEXT NOT NULL,
+    "path" TEXT NOT NULL,
+    "parent_path" TEXT,
+    "depth" INTEGER NOT NULL,
+    "own_bytes" BIGINT NOT NULL,
+    "own_files" BIGINT NOT NULL,
+    "total_bytes" BIGINT NOT NULL,
+    "own_kinds" BLOB NOT NULL,
+    "kinds" BLOB NOT NULL,
+    "dir_modified" DATETIME NOT NULL,
+    "date_scanned" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "disk_usage_root_path_key" ON "disk_usage"("root", "path");
+
+-- CreateIndex
+CREATE INDEX "disk_usage_root_parent_path_idx" ON "disk_usage"("root", "parent_path");
+
+-- CreateIndex
+CREATE INDEX "disk_usage_root_own_bytes_idx" ON "disk_usage"("root", "own_bytes");
@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "location" ADD COLUMN "volume_uuid" TEXT;
+ALTER TABLE "location" ADD COLUMN "volume_subpath" TEXT;
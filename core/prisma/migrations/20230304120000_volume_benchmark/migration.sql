@@ -0,0 +1,12 @@
+-- CreateTable
+CREATE TABLE "volume_benchmark" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "node_id" INTEGER NOT NULL,
+    "mount_point" TEXT NOT NULL,
+    "sequential_read_mbps" REAL NOT NULL,
+    "random_read_iops" REAL NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateIndex
+CREATE INDEX "volume_benchmark_node_id_mount_point_idx" ON "volume_benchmark"("node_id", "mount_point");
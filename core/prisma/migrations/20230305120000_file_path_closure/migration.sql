@@ -0,0 +1,60 @@
+-- CreateTable
+CREATE TABLE "file_path_closure" (
+    "location_id" INTEGER NOT NULL,
+    "ancestor_id" INTEGER NOT NULL,
+    "descendant_id" INTEGER NOT NULL,
+    "depth" INTEGER NOT NULL,
+
+    PRIMARY KEY ("location_id", "ancestor_id", "descendant_id"),
+    CONSTRAINT "file_path_closure_location_id_ancestor_id_fkey" FOREIGN KEY ("location_id", "ancestor_id") REFERENCES "file_path" ("location_id", "id") ON DELETE CASCADE ON UPDATE CASCADE,
+    CONSTRAINT "file_path_closure_location_id_descendant_id_fkey" FOREIGN KEY ("location_id", "descendant_id") REFERENCES "file_path" ("location_id", "id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "file_path_closure_location_id_descendant_id_idx" ON "file_path_closure"("location_id", "descendant_id");
+
+-- CreateIndex
+CREATE INDEX "file_path_location_id_parent_id_idx" ON "file_path"("location_id", "parent_id");
+
+-- A new file path descends from itself, and from every ancestor of its parent
+CREATE TRIGGER "file_path_closure_insert" AFTER INSERT ON "file_path"
+BEGIN
+    INSERT OR IGNORE INTO "file_path_closure" ("location_id", "ancestor_id", "descendant_id", "depth")
+    SELECT NEW."location_id", NEW."id", NEW."id", 0
+    UNION ALL
+    SELECT "location_id", "ancestor_id", NEW."id", "depth" + 1 FROM "file_path_closure"
+    WHERE "location_id" = NEW."location_id" AND "descendant_id" = NEW."parent_id";
+END;
+
+-- A moved file path takes its subtree along, which is detached from its old ancestors and attached to the new ones
+CREATE TRIGGER "file_path_closure_move" AFTER UPDATE OF "parent_id" ON "file_path"
+WHEN OLD."parent_id" IS NOT NEW."parent_id"
+BEGIN
+    DELETE FROM "file_path_closure"
+    WHERE "location_id" = NEW."location_id"
+        AND "descendant_id" IN (
+            SELECT "descendant_id" FROM "file_path_closure"
+            WHERE "location_id" = NEW."location_id" AND "ancestor_id" = NEW."id"
+        )
+        AND "ancestor_id" NOT IN (
+            SELECT "descendant_id" FROM "file_path_closure"
+            WHERE "location_id" = NEW."location_id" AND "ancestor_id" = NEW."id"
+        );
+
+    INSERT OR IGNORE INTO "file_path_closure" ("location_id", "ancestor_id", "descendant_id", "depth")
+    SELECT NEW."location_id", "supertree"."ancestor_id", "subtree"."descendant_id", "supertree"."depth" + "subtree"."depth" + 1
+    FROM "file_path_closure" AS "supertree", "file_path_closure" AS "subtree"
+    WHERE "supertree"."location_id" = NEW."location_id" AND "supertree"."descendant_id" = NEW."parent_id"
+        AND "subtree"."location_id" = NEW."location_id" AND "subtree"."ancestor_id" = NEW."id";
+END;
+
+-- Fills the hierarchy from the "parent_id" of the existing file paths
+INSERT INTO "file_path_closure" ("location_id", "ancestor_id", "descendant_id", "depth")
+WITH RECURSIVE "tree" ("location_id", "ancestor_id", "descendant_id", "depth") AS (
+    SELECT "location_id", "id", "id", 0 FROM "file_path"
+    UNION ALL
+    SELECT "tree"."location_id", "file_path"."parent_id", "tree"."descendant_id", "tree"."depth" + 1
+    FROM "tree" JOIN "file_path" ON "file_path"."location_id" = "tree"."location_id" AND "file_path"."id" = "tree"."ancestor_id"
+    WHERE "file_path"."parent_id" IS NOT NULL
+)
+SELECT "location_id", "ancestor_id", "descendant_id", "depth" FROM "tree";
@@ -0,0 +1,23 @@
+-- AlterTable
+ALTER TABLE "statistics" ADD COLUMN "trash_bytes" TEXT NOT NULL DEFAULT '0';
+
+-- CreateTable
+CREATE TABLE "trashed_file" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "location_id" INTEGER NOT NULL,
+    "materialized_path" TEXT NOT NULL,
+    "name" TEXT NOT NULL,
+    "is_dir" BOOLEAN NOT NULL,
+    "size_in_bytes" TEXT NOT NULL,
+    "date_trashed" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "trashed_file_pub_id_key" ON "trashed_file"("pub_id");
+
+-- CreateIndex
+CREATE INDEX "trashed_file_location_id_idx" ON "trashed_file"("location_id");
+
+-- CreateIndex
+CREATE INDEX "trashed_file_date_trashed_idx" ON "trashed_file"("date_trashed");
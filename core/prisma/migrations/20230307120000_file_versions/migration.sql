@@ -0,0 +1,21 @@
+-- AlterTable
+ALTER TABLE "location" ADD COLUMN "file_versioning" BOOLEAN NOT NULL DEFAULT false;
+
+-- AlterTable
+ALTER TABLE "tag" ADD COLUMN "file_versioning" BOOLEAN NOT NULL DEFAULT false;
+
+-- CreateTable
+CREATE TABLE "file_version" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "object_id" INTEGER NOT NULL,
+    "checksum" TEXT NOT NULL,
+    "size_in_bytes" TEXT NOT NULL,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "file_version_object_id_fkey" FOREIGN KEY ("object_id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "file_version_checksum_idx" ON "file_version"("checksum");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "file_version_object_id_checksum_key" ON "file_version"("object_id", "checksum");
@@ -0,0 +1,18 @@
+-- CreateTable
+CREATE TABLE "bookmark" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "kind" INTEGER NOT NULL,
+    "name" TEXT,
+    "position" INTEGER NOT NULL DEFAULT 0,
+    "location_pub_id" BLOB,
+    "materialized_path" TEXT,
+    "search" TEXT,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "bookmark_pub_id_key" ON "bookmark"("pub_id");
+
+-- CreateIndex
+CREATE INDEX "bookmark_position_idx" ON "bookmark"("position");
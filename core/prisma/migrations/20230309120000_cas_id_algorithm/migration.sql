@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "cas_id_algorithm" INTEGER;
+
+-- Every cas_id generated so far is sampled
+UPDATE "file_path" SET "cas_id_algorithm" = 0 WHERE "cas_id" IS NOT NULL;
@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "object" ADD COLUMN "integrity_checksum" TEXT;
+ALTER TABLE "object" ADD COLUMN "last_verified_at" DATETIME;
@@ -0,0 +1,25 @@
+-- RedefineTables
+PRAGMA foreign_keys=OFF;
+CREATE TABLE "new_media_data" (
+    "id" INTEGER NOT NULL PRIMARY KEY,
+    "pixel_width" INTEGER,
+    "pixel_height" INTEGER,
+    "longitude" REAL,
+    "latitude" REAL,
+    "fps" INTEGER,
+    "capture_device_make" TEXT,
+    "capture_device_model" TEXT,
+    "capture_device_software" TEXT,
+    "duration_seconds" INTEGER,
+    "codecs" TEXT,
+    "streams" INTEGER,
+    "capture_date" DATETIME,
+    "tags" TEXT,
+    "date_extracted" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "media_data_id_fkey" FOREIGN KEY ("id") REFERENCES "object" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+INSERT INTO "new_media_data" ("id", "pixel_width", "pixel_height", "longitude", "latitude", "fps", "capture_device_make", "capture_device_model", "capture_device_software", "duration_seconds", "codecs", "streams") SELECT "id", "pixel_width", "pixel_height", "longitude", "latitude", "fps", "capture_device_make", "capture_device_model", "capture_device_software", "duration_seconds", "codecs", "streams" FROM "media_data";
+DROP TABLE "media_data";
+ALTER TABLE "new_media_data" RENAME TO "media_data";
+PRAGMA foreign_key_check;
+PRAGMA foreign_keys=ON;
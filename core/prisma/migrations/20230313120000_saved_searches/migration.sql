@@ -0,0 +1,13 @@
+-- CreateTable
+CREATE TABLE "saved_search" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "pub_id" BLOB NOT NULL,
+    "name" TEXT,
+    "filter" TEXT NOT NULL,
+    "smart" BOOLEAN NOT NULL DEFAULT false,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "date_modified" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "saved_search_pub_id_key" ON "saved_search"("pub_id");
@@ -0,0 +1,6 @@
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "inode" BLOB;
+ALTER TABLE "file_path" ADD COLUMN "device" BLOB;
+
+-- CreateIndex
+CREATE INDEX "file_path_location_id_inode_idx" ON "file_path"("location_id", "inode");
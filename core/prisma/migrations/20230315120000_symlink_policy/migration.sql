@@ -0,0 +1,6 @@
+-- AlterTable
+ALTER TABLE "location" ADD COLUMN "symlink_policy" INTEGER NOT NULL DEFAULT 0;
+
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "is_symlink" BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE "file_path" ADD COLUMN "symlink_target" TEXT;
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "in_archive" BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,10 @@
+-- CreateTable
+CREATE TABLE "thumbnail" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "cas_id" TEXT NOT NULL,
+    "variant" TEXT NOT NULL,
+    "generated_at" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "thumbnail_cas_id_variant_key" ON "thumbnail"("cas_id", "variant");
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "job" ADD COLUMN "step_retries" INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,10 @@
+-- CreateTable
+CREATE TABLE "job_schedule" (
+    "id" INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    "name" TEXT,
+    "job" TEXT NOT NULL,
+    "cron" TEXT NOT NULL,
+    "enabled" BOOLEAN NOT NULL DEFAULT true,
+    "last_run" DATETIME,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
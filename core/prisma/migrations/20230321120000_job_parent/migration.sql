@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "job" ADD COLUMN "parent_id" BLOB;
@@ -0,0 +1,4 @@
+-- AlterTable
+ALTER TABLE "job" ADD COLUMN "items_processed" INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE "job" ADD COLUMN "bytes_read" BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE "job" ADD COLUMN "error" TEXT;
@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "job" ADD COLUMN "total_bytes" BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE "job" ADD COLUMN "completed_bytes" BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- CreateTable
+CREATE TABLE "job_step_journal" (
+    "job_id" BLOB NOT NULL PRIMARY KEY,
+    "step_number" INTEGER NOT NULL,
+    "state" BLOB NOT NULL,
+    "step" BLOB NOT NULL,
+    "data" BLOB NOT NULL,
+    "file_paths" BLOB,
+    "date_created" DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT "job_step_journal_job_id_fkey" FOREIGN KEY ("job_id") REFERENCES "job" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
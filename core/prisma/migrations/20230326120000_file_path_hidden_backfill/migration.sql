@@ -0,0 +1,2 @@
+-- Flag the dotfiles indexed before hidden file paths were tracked
+UPDATE "file_path" SET "hidden" = true WHERE "name" LIKE '.%';
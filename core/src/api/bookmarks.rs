@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+
+use int_enum::IntEnum;
+use prisma_client_rust::Direction;
+use rspc::{ErrorCode, Type};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+	invalidate_query,
+	library::Library,
+	location::find_location,
+	prisma::{bookmark, file_path, location},
+	sync,
+};
+
+use super::{utils::LibraryRequest, RouterBuilder};
+
+/// BookmarkKind is what a bookmark points to.
+/// The values are stored in the database, so existing variants must never change their value.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, Eq, PartialEq, IntEnum)]
+pub enum BookmarkKind {
+	Location = 0,
+	FilePath = 1,
+	Search = 2,
+}
+
+/// BookmarkTarget is what a new bookmark points to.
+#[derive(Type, Deserialize)]
+#[serde(tag = "type")]
+pub enum BookmarkTarget {
+	Location {
+		location_id: i32,
+	},
+	FilePath {
+		location_id: i32,
+		file_path_id: i32,
+	},
+	/// search holds the search arguments as JSON, which the core stores as is.
+	Search {
+		search: String,
+	},
+}
+
+/// Bookmark is a bookmark with its target resolved to the ids of this node. They are `None` when the target
+/// isn't indexed on this node, or doesn't exist anymore.
+#[derive(Serialize, Type)]
+pub struct Bookmark {
+	pub id: i32,
+	pub kind: BookmarkKind,
+	pub name: Option<String>,
+	pub position: i32,
+	pub location_id: Option<i32>,
+	pub file_path_id: Option<i32>,
+	pub materialized_path: Option<String>,
+	pub search: Option<String>,
+}
+
+fn bookmark_not_found(id: i32) -> rspc::Error {
+	rspc::Error::new(ErrorCode::NotFound, format!("Bookmark <id={id}> not found"))
+}
+
+pub(crate) fn mount() -> RouterBuilder {
+	RouterBuilder::new()
+		.library_query("list", |t| {
+			t(|_, _: (), library| async move {
+				let Library { db, .. } = &library;
+
+				let bookmarks = db
+					.bookmark()
+					.find_many(vec![])
+					.order_by(bookmark::position::order(Direction::Asc))
+					.exec()
+					.await?;
+
+				let location_ids = db
+					.location()
+					.find_many(vec![location::pub_id::in_vec(
+						bookmarks
+							.iter()
+							.filter_map(|bookmark| bookmark.location_pub_id.clone())
+							.collect(),
+					)])
+					.select(location::select!({ id pub_id }))
+					.exec()
+					.await?
+					.into_iter()
+					.map(|location| (location.pub_id, location.id))
+					.collect::<HashMap<_, _>>();
+
+				let mut items = Vec::with_capacity(bookmarks.len());
+				for bookmark in bookmarks {
+					// Bookmarks synced from a node running a newer version may have kinds we don't know yet
+					let Ok(kind) = BookmarkKind::from_int(bookmark.kind) else {
+						continue;
+					};
+
+					let location_id = bookmark
+						.location_pub_id
+						.as_ref()
+						.and_then(|pub_id| location_ids.get(pub_id).copied());
+
+					let file_path_id = match (location_id, &bookmark.materialized_path) {
+						(Some(location_id), Some(materialized_path)) => db
+							.file_path()
+							.find_first(vec![
+								file_path::location_id::equals(location_id),
+								file_path::materialized_path::equals(materialized_path.clone()),
+							])
+							.select(file_path::select!({ id }))
+							.exec()
+							.await?
+							.map(|file_path| file_path.id),
+						_ => None,
+					};
+
+					items.push(Bookmark {
+						id: bookmark.id,
+						kind,
+						name: bookmark.name,
+						position: bookmark.position,
+						location_id,
+						file_path_id,
+						materialized_path: bookmark.materialized_path,
+						search: bookmark.search,
+					});
+				}
+
+				Ok(items)
+			})
+		})
+		.library_mutation("create", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct BookmarkCreateArgs {
+				pub name: Option<String>,
+				pub target: BookmarkTarget,
+			}
+
+			t(|_, args: BookmarkCreateArgs, library| async move {
+				let Library { db, sync, .. } = &library;
+
+				let (kind, location_pub_id, materialized_path, search) = match args.target {
+					BookmarkTarget::Location { location_id } => {
+						let location = find_location(&library, location_id)
+							.select(location::select!({ pub_id }))
+							.exec()
+							.await?
+							.ok_or_else(|| {
+								rspc::Error::new(
+									ErrorCode::NotFound,
+									format!("Location <id={location_id}> not found"),
+								)
+							})?;
+
+						(BookmarkKind::Location, Some(location.pub_id), None, None)
+					}
+					BookmarkTarget::FilePath {
+						location_id,
+						file_path_id,
+					} => {
+						let file_path = db
+							.file_path()
+							.find_unique(file_path::location_id_id(location_id, file_path_id))
+							.select(file_path::select!({
+								materialized_path
+								location: select { pub_id }
+							}))
+							.exec()
+							.await?
+							.ok_or_else(|| {
+								rspc::Error::new(
+									ErrorCode::NotFound,
+									format!("File path <id={file_path_id}> not found"),
+								)
+							})?;
+
+						(
+							BookmarkKind::FilePath,
+							Some(file_path.location.pub_id),
+							Some(file_path.materialized_path),
+							None,
+						)
+					}
+					BookmarkTarget::Search { search } => {
+						(BookmarkKind::Search, None, None, Some(search))
+					}
+				};
+
+				// New bookmarks go to the end of the list
+				let position = db
+					.bookmark()
+					.find_first(vec![])
+					.order_by(bookmark::position::order(Direction::Desc))
+					.select(bookmark::select!({ position }))
+					.exec()
+					.await?
+					.map_or(0, |last| last.position + 1);
+
+				let pub_id = Uuid::new_v4().as_bytes().to_vec();
+
+				let created_bookmark = sync
+					.write_op(
+						db,
+						sync.unique_shared_create(
+							sync::bookmark::SyncId {
+								pub_id: pub_id.clone(),
+							},
+							[
+								("kind", json!(kind.int_value())),
+								("name", json!(args.name)),
+								("position", json!(position)),
+								("location_pub_id", json!(location_pub_id)),
+								("materialized_path", json!(materialized_path)),
+								("search", json!(search)),
+							],
+						),
+						db.bookmark().create(
+							pub_id,
+							kind.int_value(),
+							vec![
+								bookmark::name::set(args.name),
+								bookmark::position::set(position),
+								bookmark::location_pub_id::set(location_pub_id),
+								bookmark::materialized_path::set(materialized_path),
+								bookmark::search::set(search),
+							],
+						),
+					)
+					.await?;
+
+				invalidate_query!(library, "bookmarks.list");
+
+				Ok(created_bookmark.id)
+			})
+		})
+		.library_mutation("rename", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct BookmarkRenameArgs {
+				pub id: i32,
+				pub name: Option<String>,
+			}
+
+			t(|_, args: BookmarkRenameArgs, library| async move {
+				let Library { db, sync, .. } = &library;
+
+				let bookmark = db
+					.bookmark()
+					.find_unique(bookmark::id::equals(args.id))
+					.select(bookmark::select!({ pub_id }))
+					.exec()
+					.await?
+					.ok_or_else(|| bookmark_not_found(args.id))?;
+
+				sync.write_op(
+					db,
+					sync.shared_update(
+						sync::bookmark::SyncId {
+							pub_id: bookmark.pub_id,
+						},
+						"name",
+						json!(args.name),
+					),
+					db.bookmark().update(
+						bookmark::id::equals(args.id),
+						vec![bookmark::name::set(args.name)],
+					),
+				)
+				.await?;
+
+				invalidate_query!(library, "bookmarks.list");
+
+				Ok(())
+			})
+		})
+		.library_mutation("reorder", |t| {
+			// The ids of the bookmarks in their new order, bookmarks which aren't in the list keep their position
+			t(|_, ids: Vec<i32>, library| async move {
+				let Library { db, sync, .. } = &library;
+
+				let bookmarks = db
+					.bookmark()
+					.find_many(vec![bookmark::id::in_vec(ids.clone())])
+					.select(bookmark::select!({ id pub_id position }))
+					.exec()
+					.await?
+					.into_iter()
+					.map(|bookmark| (bookmark.id, bookmark))
+					.collect::<HashMap<_, _>>();
+
+				for (position, id) in ids.into_iter().enumerate() {
+					let position = position as i32;
+
+					let Some(bookmark) = bookmarks.get(&id) else {
+						return Err(bookmark_not_found(id));
+					};
+					if bookmark.position == position {
+						continue;
+					}
+
+					sync.write_op(
+						db,
+						sync.shared_update(
+							sync::bookmark::SyncId {
+								pub_id: bookmark.pub_id.clone(),
+							},
+							"position",
+							json!(position),
+						),
+						db.bookmark().update(
+							bookmark::id::equals(id),
+							vec![bookmark::position::set(position)],
+						),
+					)
+					.await?;
+				}
+
+				invalidate_query!(library, "bookmarks.list");
+
+				Ok(())
+			})
+		})
+		.library_mutation("delete", |t| {
+			t(|_, id: i32, library| async move {
+				let Library { db, sync, .. } = &library;
+
+				let bookmark = db
+					.bookmark()
+					.find_unique(bookmark::id::equals(id))
+					.select(bookmark::select!({ pub_id }))
+					.exec()
+					.await?
+					.ok_or_else(|| bookmark_not_found(id))?;
+
+				sync.write_op(
+					db,
+					sync.shared_delete(sync::bookmark::SyncId {
+						pub_id: bookmark.pub_id,
+					}),
+					db.bookmark().delete(bookmark::id::equals(id)),
+				)
+				.await?;
+
+				invalidate_query!(library, "bookmarks.list");
+
+				Ok(())
+			})
+		})
+}
@@ -0,0 +1,116 @@
+use crate::{
+	extension::{
+		extension_job::{ExtensionJob, ExtensionJobInit},
+		Capability, ProcedureKind,
+	},
+	job::Job,
+	location::{find_location, LocationError},
+};
+
+use std::collections::BTreeSet;
+
+use rspc::Type;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{utils::LibraryRequest, RouterBuilder};
+
+#[derive(Type, Deserialize)]
+pub struct ExtensionProcedureArgs {
+	pub extension: String,
+	pub key: String,
+	pub arg: Value,
+}
+
+pub(crate) fn mount() -> RouterBuilder {
+	<RouterBuilder>::new()
+		.query("list", |t| {
+			t(|ctx, _: ()| {
+				ctx.authorize_node_admin()?;
+
+				Ok(ctx.extensions.list())
+			})
+		})
+		.mutation("reload", |t| {
+			t(|ctx, _: ()| {
+				ctx.authorize_node_admin()?;
+
+				Ok(ctx.extensions.reload()?)
+			})
+		})
+		.mutation("grant", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct GrantExtensionArgs {
+				pub extension: String,
+				/// capabilities replace the ones granted before, an empty set revokes all of them.
+				pub capabilities: BTreeSet<Capability>,
+			}
+
+			t(|ctx, args: GrantExtensionArgs| {
+				ctx.authorize_node_admin()?;
+
+				Ok(ctx.extensions.grant(args.extension, args.capabilities)?)
+			})
+		})
+		// Procedures registered by extensions can't be added to the router at runtime, so they're all called through these
+		.query("query", |t| {
+			t(|ctx, args: ExtensionProcedureArgs| async move {
+				ctx.authorize_node_admin()?;
+
+				Ok(ctx
+					.extensions
+					.call_procedure(&args.extension, &args.key, ProcedureKind::Query, args.arg)
+					.await?)
+			})
+		})
+		.mutation("mutation", |t| {
+			t(|ctx, args: ExtensionProcedureArgs| async move {
+				ctx.authorize_node_admin()?;
+
+				Ok(ctx
+					.extensions
+					.call_procedure(
+						&args.extension,
+						&args.key,
+						ProcedureKind::Mutation,
+						args.arg,
+					)
+					.await?)
+			})
+		})
+		.library_mutation("runStepHandler", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct RunStepHandlerArgs {
+				pub location_id: i32,
+				pub extension: String,
+				pub handler: String,
+			}
+
+			t(|ctx, args: RunStepHandlerArgs, library| async move {
+				// Fails early when the extension or its handler don't exist
+				ctx.extensions
+					.step_handler(&args.extension, &args.handler)?;
+
+				if find_location(&library, args.location_id)
+					.exec()
+					.await?
+					.is_none()
+				{
+					return Err(LocationError::IdNotFound(args.location_id).into());
+				}
+
+				library
+					.spawn_job(Job::new(
+						ExtensionJobInit {
+							location_id: args.location_id,
+							extension: args.extension,
+							handler: args.handler,
+						},
+						ExtensionJob {},
+					))
+					.await;
+
+				Ok(())
+			})
+		})
+}
@@ -1,20 +1,38 @@
 use crate::{
 	invalidate_query,
 	job::Job,
-	library::Library,
+	library::{delete_from_trash, empty_trash, restore_from_trash, ActivityKind, Library},
 	object::fs::{
+		archive::{
+			FileCompressorJob, FileCompressorJobInit, FileExtractorJob, FileExtractorJobInit,
+		},
 		copy::{FileCopierJob, FileCopierJobInit},
 		cut::{FileCutterJob, FileCutterJobInit},
 		decrypt::{FileDecryptorJob, FileDecryptorJobInit},
+		dedup::{find_duplicates, DuplicateEliminatorJob, DuplicateEliminatorJobInit},
 		delete::{FileDeleterJob, FileDeleterJobInit},
 		encrypt::{FileEncryptorJob, FileEncryptorJobInit},
 		erase::{FileEraserJob, FileEraserJobInit},
+		rename::{bulk_rename, preview_bulk_rename, BulkRenameArgs},
 	},
-	prisma::object,
+	object::{
+		convert::convert_job::{FileConverterJob, FileConverterJobInit},
+		perceptual_hash::{find_similar_images, DEFAULT_SIMILARITY_DISTANCE},
+		preview::{get_preview_strip, request_thumbnails},
+		version::restore_file_version,
+	},
+	prisma::{file_path, file_version, location, media_data, object, tag_on_object, trashed_file},
+};
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	path::{Component, Path},
 };
 
-use rspc::Type;
-use serde::Deserialize;
+use prisma_client_rust::Direction;
+use rspc::{ErrorCode, Type};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use tokio::sync::oneshot;
 
 use super::{utils::LibraryRequest, RouterBuilder};
@@ -36,6 +54,80 @@ pub(crate) fn mount() -> RouterBuilder {
 					.await?)
 			})
 		})
+		.library_query("mediaData", |t| {
+			t(|_, object_id: i32, library: Library| async move {
+				Ok(library
+					.db
+					.media_data()
+					.find_unique(media_data::id::equals(object_id))
+					.exec()
+					.await?)
+			})
+		})
+		.library_query("videoPreviewStrip", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct VideoPreviewStripArgs {
+				pub location_id: i32,
+				pub file_path_id: i32,
+			}
+
+			t(
+				|_, args: VideoPreviewStripArgs, library: Library| async move {
+					Ok(get_preview_strip(&library, args.location_id, args.file_path_id).await?)
+				},
+			)
+		})
+		.library_query("similarImages", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct SimilarImagesArgs {
+				pub id: i32,
+				/// max_distance is how many bits the perceptual hashes can differ by, 0 only finds identical looking images
+				pub max_distance: Option<u32>,
+			}
+
+			#[derive(Serialize, Type)]
+			pub struct SimilarImage {
+				pub object: object::Data,
+				pub distance: u32,
+			}
+
+			t(|_, args: SimilarImagesArgs, library: Library| async move {
+				let similar = find_similar_images(
+					&library.db,
+					args.id,
+					args.max_distance.unwrap_or(DEFAULT_SIMILARITY_DISTANCE),
+				)
+				.await?;
+
+				let mut objects = library
+					.db
+					.object()
+					.find_many(vec![object::id::in_vec(
+						similar.iter().map(|(id, _)| *id).collect(),
+					)])
+					.exec()
+					.await?
+					.into_iter()
+					.map(|object| (object.id, object))
+					.collect::<HashMap<_, _>>();
+
+				Ok(similar
+					.into_iter()
+					.filter_map(|(id, distance)| {
+						Some(SimilarImage {
+							object: objects.remove(&id)?,
+							distance,
+						})
+					})
+					.collect::<Vec<_>>())
+			})
+		})
+		.library_mutation("requestThumbnails", |t| {
+			// Takes the cas_ids of the files shown in the explorer, whose thumbnails are generated before any other
+			t(|_, cas_ids: Vec<String>, library: Library| async move {
+				Ok(request_thumbnails(&library, cas_ids).await?)
+			})
+		})
 		.library_mutation("setNote", |t| {
 			#[derive(Type, Deserialize)]
 			pub struct SetNoteArgs {
@@ -84,22 +176,202 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
+		.library_mutation("batch", |t| {
+			/// BatchMutation is a mutation of many objects at once, for the multi-selections of the explorer.
+			#[derive(Type, Deserialize)]
+			#[serde(tag = "type")]
+			pub enum BatchMutation {
+				AssignTag {
+					tag_id: i32,
+					object_ids: Vec<i32>,
+					unassign: bool,
+				},
+				SetFavorite {
+					object_ids: Vec<i32>,
+					favorite: bool,
+				},
+				SetNote {
+					object_ids: Vec<i32>,
+					note: Option<String>,
+				},
+			}
+
+			// All the mutations are written in a single transaction, and clients are told to refetch once for all of them
+			t(
+				|_, mutations: Vec<BatchMutation>, library: Library| async move {
+					let Library { db, .. } = &library;
+
+					// The latest mutation of a tag and an object wins, by whether it assigns or unassigns the tag
+					let mut tag_assignments = BTreeMap::<i32, HashMap<i32, bool>>::new();
+					let mut object_updates = vec![];
+					for mutation in mutations {
+						match mutation {
+							BatchMutation::AssignTag {
+								tag_id,
+								object_ids,
+								unassign,
+							} => tag_assignments.entry(tag_id).or_default().extend(
+								object_ids
+									.into_iter()
+									.map(|object_id| (object_id, !unassign)),
+							),
+							BatchMutation::SetFavorite {
+								object_ids,
+								favorite,
+							} => object_updates.push(db.object().update_many(
+								vec![object::id::in_vec(object_ids)],
+								vec![object::favorite::set(favorite)],
+							)),
+							BatchMutation::SetNote { object_ids, note } => {
+								object_updates.push(db.object().update_many(
+									vec![object::id::in_vec(object_ids)],
+									vec![object::note::set(note)],
+								))
+							}
+						}
+					}
+
+					// Assigned tags are removed first, so assigning a tag an object already has doesn't fail the transaction
+					let tag_deletes = tag_assignments
+						.iter()
+						.map(|(&tag_id, objects)| {
+							db.tag_on_object().delete_many(vec![
+								tag_on_object::tag_id::equals(tag_id),
+								tag_on_object::object_id::in_vec(objects.keys().copied().collect()),
+							])
+						})
+						.collect::<Vec<_>>();
+					let tag_creates = db.tag_on_object().create_many(
+						tag_assignments
+							.iter()
+							.flat_map(|(&tag_id, objects)| {
+								objects.iter().filter(|(_, &assigned)| assigned).map(
+									move |(&object_id, _)| {
+										tag_on_object::create_unchecked(tag_id, object_id, vec![])
+									},
+								)
+							})
+							.collect(),
+					);
+
+					db._batch((tag_deletes, tag_creates, object_updates))
+						.await?;
+
+					for (tag_id, objects) in &tag_assignments {
+						let object_ids = objects
+							.iter()
+							.filter(|(_, &assigned)| !assigned)
+							.map(|(&object_id, _)| object_id)
+							.collect::<Vec<_>>();
+						if object_ids.is_empty() {
+							continue;
+						}
+
+						library
+							.record_activity(
+								ActivityKind::TagRemoved,
+								format!(
+									"Removed tag <id={tag_id}> from {} objects",
+									object_ids.len()
+								),
+								Some(json!({ "tag_id": tag_id, "object_ids": object_ids })),
+							)
+							.await;
+					}
+
+					if !tag_assignments.is_empty() {
+						invalidate_query!(library, "tags.getForObject");
+					}
+					invalidate_query!(library, "locations.getExplorerData");
+					invalidate_query!(library, "tags.getExplorerData");
+
+					Ok(())
+				},
+			)
+		})
 		.library_mutation("delete", |t| {
-			t(|_, id: i32, library: Library| async move {
-				library
+			#[derive(Type, Deserialize)]
+			pub struct DeleteArgs {
+				pub id: i32,
+				/// permanent deletes the files of the object right away instead of moving them to the trash.
+				#[serde(default)]
+				pub permanent: bool,
+			}
+
+			t(|_, args: DeleteArgs, library: Library| async move {
+				let object = library
 					.db
 					.object()
-					.delete(object::id::equals(id))
+					.find_unique(object::id::equals(args.id))
+					.exec()
+					.await?
+					.ok_or_else(|| {
+						rspc::Error::new(
+							ErrorCode::NotFound,
+							format!("Object <id={}> not found", args.id),
+						)
+					})?;
+
+				// The files of the object go through the deleter job, so they're moved to the trash of their
+				// locations and can be restored, and the object is removed once it's left without files.
+				// Only the locations of this node can be reached from here.
+				let file_paths = library
+					.db
+					.file_path()
+					.find_many(vec![
+						file_path::object_id::equals(Some(args.id)),
+						file_path::in_archive::equals(false),
+						file_path::location::is(vec![location::node_id::equals(
+							library.node_local_id,
+						)]),
+					])
+					.select(file_path::select!({ id location_id }))
 					.exec()
 					.await?;
 
+				if file_paths.is_empty() {
+					library
+						.db
+						.object()
+						.delete(object::id::equals(args.id))
+						.exec()
+						.await?;
+				}
+
+				for file_path in file_paths {
+					library
+						.spawn_job(Job::new(
+							FileDeleterJobInit {
+								location_id: file_path.location_id,
+								path_id: file_path.id,
+								permanent: args.permanent,
+								dry_run: false,
+							},
+							FileDeleterJob {},
+						))
+						.await;
+				}
+
+				library
+					.record_activity(
+						ActivityKind::ObjectDeleted,
+						format!("Deleted object '{}'", object.name.unwrap_or_default()),
+						Some(json!({ "object_id": args.id, "permanent": args.permanent })),
+					)
+					.await;
+
 				invalidate_query!(library, "locations.getExplorerData");
 				Ok(())
 			})
 		})
 		.library_mutation("encryptFiles", |t| {
 			t(
-				|_, args: FileEncryptorJobInit, library: Library| async move {
+				|ctx, args: FileEncryptorJobInit, library: Library| async move {
+					// An output path can be anywhere on the node
+					if args.output_path.is_some() {
+						ctx.authorize_node_admin()?;
+					}
+
 					library.spawn_job(Job::new(args, FileEncryptorJob {})).await;
 					invalidate_query!(library, "locations.getExplorerData");
 
@@ -109,7 +381,11 @@ pub(crate) fn mount() -> RouterBuilder {
 		})
 		.library_mutation("decryptFiles", |t| {
 			t(
-				|_, args: FileDecryptorJobInit, library: Library| async move {
+				|ctx, args: FileDecryptorJobInit, library: Library| async move {
+					if args.output_path.is_some() {
+						ctx.authorize_node_admin()?;
+					}
+
 					library.spawn_job(Job::new(args, FileDecryptorJob {})).await;
 					invalidate_query!(library, "locations.getExplorerData");
 
@@ -125,16 +401,95 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
-		.library_mutation("eraseFiles", |t| {
+		.library_query("duplicates", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct DuplicatesArgs {
+				pub location_id: Option<i32>,
+			}
+
+			t(|_, args: DuplicatesArgs, library: Library| async move {
+				Ok(find_duplicates(&library, args.location_id, None).await?)
+			})
+		})
+		.library_mutation("eliminateDuplicates", |t| {
+			t(
+				|_, args: DuplicateEliminatorJobInit, library: Library| async move {
+					library
+						.spawn_job(Job::new(args, DuplicateEliminatorJob {}))
+						.await;
+
+					Ok(())
+				},
+			)
+		})
+		.library_query("previewBulkRename", |t| {
+			t(|_, args: BulkRenameArgs, library: Library| async move {
+				Ok(preview_bulk_rename(&library, &args).await?)
+			})
+		})
+		.library_mutation("bulkRename", |t| {
+			t(|_, args: BulkRenameArgs, library: Library| async move {
+				Ok(bulk_rename(&library, args).await?)
+			})
+		})
+		.library_mutation("secureDelete", |t| {
 			t(|_, args: FileEraserJobInit, library: Library| async move {
+				// Erasing runs in the background, the explorer is refreshed once it's done
 				library.spawn_job(Job::new(args, FileEraserJob {})).await;
-				invalidate_query!(library, "locations.getExplorerData");
+
+				Ok(())
+			})
+		})
+		.library_query("versions", |t| {
+			t(|_, object_id: i32, library: Library| async move {
+				Ok(library
+					.db
+					.file_version()
+					.find_many(vec![file_version::object_id::equals(object_id)])
+					.order_by(file_version::date_created::order(Direction::Desc))
+					.exec()
+					.await?)
+			})
+		})
+		.library_mutation("restoreVersion", |t| {
+			t(|_, id: i32, library: Library| async move {
+				Ok(restore_file_version(&library, id).await?)
+			})
+		})
+		.library_query("listTrash", |t| {
+			t(|_, _: (), library: Library| async move {
+				Ok(library
+					.db
+					.trashed_file()
+					.find_many(vec![])
+					.order_by(trashed_file::date_trashed::order(Direction::Desc))
+					.exec()
+					.await?)
+			})
+		})
+		.library_mutation("restoreFromTrash", |t| {
+			t(
+				|_, id: i32, library: Library| async move {
+					Ok(restore_from_trash(&library, id).await?)
+				},
+			)
+		})
+		.library_mutation("deleteFromTrash", |t| {
+			t(
+				|_, id: i32, library: Library| async move { Ok(delete_from_trash(&library, id).await?) },
+			)
+		})
+		.library_mutation("emptyTrash", |t| {
+			t(|_, _: (), library: Library| async move {
+				empty_trash(&library).await?;
 
 				Ok(())
 			})
 		})
 		.library_mutation("duplicateFiles", |t| {
 			t(|_, args: FileCopierJobInit, library: Library| async move {
+				ensure_within_location(&args.target_path)?;
+
 				let (done_tx, done_rx) = oneshot::channel();
 
 				library
@@ -152,8 +507,22 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
+		.library_mutation("copy", |t| {
+			t(|_, args: FileCopierJobInit, library: Library| async move {
+				ensure_within_location(&args.target_path)?;
+
+				// Copying runs in the background, its results are indexed once it's done
+				library
+					.spawn_job(Job::new(args, FileCopierJob { done_tx: None }))
+					.await;
+
+				Ok(())
+			})
+		})
 		.library_mutation("copyFiles", |t| {
 			t(|_, args: FileCopierJobInit, library: Library| async move {
+				ensure_within_location(&args.target_path)?;
+
 				let (done_tx, done_rx) = oneshot::channel();
 
 				library
@@ -171,12 +540,73 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
+		.library_mutation("move", |t| {
+			t(|_, args: FileCutterJobInit, library: Library| async move {
+				ensure_within_location(&args.target_path)?;
+
+				// Moving runs in the background, the explorer is refreshed once it's done
+				library.spawn_job(Job::new(args, FileCutterJob {})).await;
+
+				Ok(())
+			})
+		})
 		.library_mutation("cutFiles", |t| {
 			t(|_, args: FileCutterJobInit, library: Library| async move {
+				ensure_within_location(&args.target_path)?;
+
 				library.spawn_job(Job::new(args, FileCutterJob {})).await;
 				invalidate_query!(library, "locations.getExplorerData");
 
 				Ok(())
 			})
 		})
+		.library_mutation("compress", |t| {
+			t(
+				|_, args: FileCompressorJobInit, library: Library| async move {
+					ensure_within_location(&args.target_path)?;
+
+					library
+						.spawn_job(Job::new(args, FileCompressorJob::default()))
+						.await;
+
+					Ok(())
+				},
+			)
+		})
+		.library_mutation("convert", |t| {
+			t(
+				|_, args: FileConverterJobInit, library: Library| async move {
+					library.spawn_job(Job::new(args, FileConverterJob {})).await;
+
+					Ok(())
+				},
+			)
+		})
+		.library_mutation("extract", |t| {
+			t(
+				|_, args: FileExtractorJobInit, library: Library| async move {
+					ensure_within_location(&args.target_path)?;
+
+					library.spawn_job(Job::new(args, FileExtractorJob {})).await;
+
+					Ok(())
+				},
+			)
+		})
+}
+
+/// ensure_within_location fails when a target path, relative to its location, would escape the location.
+/// Absolute paths replace the location path when joined to it, which would let users write anywhere on the node.
+fn ensure_within_location(target_path: &Path) -> Result<(), rspc::Error> {
+	if target_path
+		.components()
+		.all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+	{
+		Ok(())
+	} else {
+		Err(rspc::Error::new(
+			ErrorCode::BadRequest,
+			"The target path must be relative to its location.".to_string(),
+		))
+	}
 }
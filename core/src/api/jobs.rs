@@ -1,24 +1,145 @@
 use crate::{
-	job::{Job, JobManager},
-	location::{find_location, LocationError},
+	invalidate_query,
+	job::{
+		schedule::{parse_cron, JobSchedule, JobScheduleError, ScheduledJob},
+		Job, JobManager, JobReport, JobStatus,
+	},
+	location::{
+		archive::archive_job::{ArchiveIndexerJob, ArchiveIndexerJobInit},
+		find_location, LocationError,
+	},
 	object::{
+		disk_usage::{DiskUsageJob, DiskUsageJobInit, DiskUsageTarget},
 		file_identifier::file_identifier_job::{FileIdentifierJob, FileIdentifierJobInit},
-		preview::thumbnailer_job::{ThumbnailerJob, ThumbnailerJobInit},
-		validation::validator_job::{ObjectValidatorJob, ObjectValidatorJobInit},
+		preview::{
+			media_data_job::{MediaDataJob, MediaDataJobInit},
+			thumbnailer_job::{ThumbnailerJob, ThumbnailerJobInit},
+		},
+		validation::{
+			validator_job::{ObjectValidatorJob, ObjectValidatorJobInit},
+			verifier_job::{ObjectVerifierJob, ObjectVerifierJobInit},
+		},
 	},
+	prisma::{job, job_schedule},
 };
 
+use chrono::{DateTime, Utc};
+use int_enum::IntEnum;
+use prisma_client_rust::Direction;
 use rspc::Type;
-use serde::Deserialize;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::Arc};
+use uuid::Uuid;
 
 use super::{utils::LibraryRequest, CoreEvent, RouterBuilder};
 
+/// How many jobs are in a page of the job history when the client doesn't say
+const JOB_HISTORY_PAGE_SIZE: i32 = 50;
+
 pub(crate) fn mount() -> RouterBuilder {
 	<RouterBuilder>::new()
 		.library_query("getRunning", |t| {
 			t(|ctx, _: (), _| async move { Ok(ctx.jobs.get_running().await) })
 		})
+		.library_query("getQueued", |t| {
+			t(|ctx, _: (), _| async move { Ok(ctx.jobs.get_queued().await) })
+		})
+		.library_query("history", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct JobHistoryArgs {
+				pub name: Option<String>,
+				/// status filters the jobs by status, leaving out the running ones when it's not set
+				pub status: Option<JobStatus>,
+				/// cursor is the last job of the previous page
+				pub cursor: Option<JobHistoryCursor>,
+				pub take: Option<i32>,
+			}
+
+			/// JobHistoryCursor points to a job of the history, which is ordered by creation date and then by id,
+			/// as jobs dispatched together can be created at the same time.
+			#[derive(Type, Serialize, Deserialize)]
+			pub struct JobHistoryCursor {
+				date_created: DateTime<Utc>,
+				id: Uuid,
+			}
+
+			#[derive(Serialize, Type)]
+			pub struct JobHistoryPage {
+				items: Vec<JobReport>,
+				next_cursor: Option<JobHistoryCursor>,
+			}
+
+			t(|_, args: JobHistoryArgs, library| async move {
+				let take = args.take.unwrap_or(JOB_HISTORY_PAGE_SIZE).clamp(1, 500) as usize;
+
+				let filters = || {
+					let mut params = vec![match args.status {
+						Some(status) => job::status::equals(status.int_value()),
+						None => job::status::not(JobStatus::Running.int_value()),
+					}];
+					if let Some(name) = &args.name {
+						params.push(job::name::equals(name.clone()));
+					}
+					params
+				};
+
+				let mut items = vec![];
+				let mut params = filters();
+
+				if let Some(cursor) = &args.cursor {
+					// The jobs created at the same time as the cursor come after it when their id is lower
+					let mut same_date_params = filters();
+					same_date_params.push(job::date_created::equals(cursor.date_created.into()));
+
+					items.extend(
+						library
+							.db
+							.job()
+							.find_many(same_date_params)
+							.order_by(job::id::order(Direction::Desc))
+							.exec()
+							.await?
+							.into_iter()
+							.map(JobReport::from)
+							.filter(|report| report.id < cursor.id)
+							.take(take + 1),
+					);
+
+					params.push(job::date_created::lt(cursor.date_created.into()));
+				}
+
+				if items.len() <= take {
+					items.extend(
+						library
+							.db
+							.job()
+							.find_many(params)
+							.order_by(job::date_created::order(Direction::Desc))
+							.order_by(job::id::order(Direction::Desc))
+							.take((take + 1 - items.len()) as i64)
+							.exec()
+							.await?
+							.into_iter()
+							.map(JobReport::from),
+					);
+				}
+
+				let next_cursor = if items.len() > take {
+					items.truncate(take);
+					items.last().map(|item| JobHistoryCursor {
+						date_created: item.date_created,
+						id: item.id,
+					})
+				} else {
+					None
+				};
+
+				Ok(JobHistoryPage { items, next_cursor })
+			})
+		})
+		.library_query("statistics", |t| {
+			t(|_, _: (), library| async move { Ok(JobManager::get_statistics(&library).await?) })
+		})
 		.library_query("isRunning", |t| {
 			t(|ctx, _: (), _| async move { Ok(!ctx.jobs.get_running().await.is_empty()) })
 		})
@@ -31,6 +152,24 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
+		.library_mutation("pause", |t| {
+			t(|ctx, id: Uuid, library| async move {
+				ctx.jobs.pause_job(&library, id).await?;
+				Ok(())
+			})
+		})
+		.library_mutation("resume", |t| {
+			t(|ctx, id: Uuid, library| async move {
+				Arc::clone(&ctx.jobs).resume_job(&library, id).await?;
+				Ok(())
+			})
+		})
+		.library_mutation("cancel", |t| {
+			t(|ctx, id: Uuid, library| async move {
+				ctx.jobs.cancel_job(&library, id).await?;
+				Ok(())
+			})
+		})
 		.library_mutation("generateThumbsForLocation", |t| {
 			#[derive(Type, Deserialize)]
 			pub struct GenerateThumbsForLocationArgs {
@@ -50,6 +189,7 @@ pub(crate) fn mount() -> RouterBuilder {
 								location,
 								sub_path: Some(args.path),
 								background: false,
+								regenerate: false,
 							},
 							ThumbnailerJob {},
 						))
@@ -59,6 +199,33 @@ pub(crate) fn mount() -> RouterBuilder {
 				},
 			)
 		})
+		.library_mutation("regenerateThumbnails", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct RegenerateThumbnailsArgs {
+				pub location_id: i32,
+				pub sub_path: Option<PathBuf>,
+			}
+
+			t(|_, args: RegenerateThumbnailsArgs, library| async move {
+				let Some(location) = find_location(&library, args.location_id).exec().await? else {
+					return Err(LocationError::IdNotFound(args.location_id).into());
+				};
+
+				library
+					.spawn_job(Job::new(
+						ThumbnailerJobInit {
+							location,
+							sub_path: args.sub_path,
+							background: false,
+							regenerate: true,
+						},
+						ThumbnailerJob {},
+					))
+					.await;
+
+				Ok(())
+			})
+		})
 		.library_mutation("objectValidator", |t| {
 			#[derive(Type, Deserialize)]
 			pub struct ObjectValidatorArgs {
@@ -85,6 +252,64 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
+		.library_mutation("extractMediaData", |t| {
+			t(|_, args: MediaDataJobInit, library| async move {
+				if find_location(&library, args.location_id)
+					.exec()
+					.await?
+					.is_none()
+				{
+					return Err(LocationError::IdNotFound(args.location_id).into());
+				}
+
+				library.spawn_job(Job::new(args, MediaDataJob {})).await;
+
+				Ok(())
+			})
+		})
+		.library_mutation("verifyLocation", |t| {
+			t(|_, id: i32, library| async move {
+				if find_location(&library, id).exec().await?.is_none() {
+					return Err(LocationError::IdNotFound(id).into());
+				}
+
+				library
+					.spawn_job(Job::new(
+						ObjectVerifierJobInit { location_id: id },
+						ObjectVerifierJob {},
+					))
+					.await;
+
+				Ok(())
+			})
+		})
+		.library_mutation("measureDiskUsage", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct MeasureDiskUsageArgs {
+				pub target: DiskUsageTarget,
+				pub full_rescan: bool,
+			}
+
+			t(|_, args: MeasureDiskUsageArgs, library| async move {
+				if let DiskUsageTarget::Location(id) = args.target {
+					if find_location(&library, id).exec().await?.is_none() {
+						return Err(LocationError::IdNotFound(id).into());
+					}
+				}
+
+				library
+					.spawn_job(Job::new(
+						DiskUsageJobInit {
+							target: args.target,
+							full_rescan: args.full_rescan,
+						},
+						DiskUsageJob {},
+					))
+					.await;
+
+				Ok(())
+			})
+		})
 		.library_mutation("identifyUniqueFiles", |t| {
 			#[derive(Type, Deserialize)]
 			pub struct IdentifyUniqueFilesArgs {
@@ -110,19 +335,156 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(())
 			})
 		})
+		.library_mutation("indexArchives", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct IndexArchivesArgs {
+				pub id: i32,
+				pub path: Option<PathBuf>,
+			}
+
+			t(|_, args: IndexArchivesArgs, library| async move {
+				let Some(location) = find_location(&library, args.id).exec().await? else {
+					return Err(LocationError::IdNotFound(args.id).into());
+				};
+
+				library
+					.spawn_job(Job::new(
+						ArchiveIndexerJobInit {
+							location,
+							sub_path: args.path,
+						},
+						ArchiveIndexerJob {},
+					))
+					.await;
+
+				Ok(())
+			})
+		})
 		.library_subscription("newThumbnail", |t| {
-			t(|ctx, _: (), _| {
+			// Yields the cas_ids of the new thumbnails of a variant, or of the default thumbnails without one
+			t(|ctx, variant: Option<String>, _| {
 				// TODO: Only return event for the library that was subscribed to
 
 				let mut event_bus_rx = ctx.event_bus.subscribe();
 				async_stream::stream! {
 					while let Ok(event) = event_bus_rx.recv().await {
 						match event {
-							CoreEvent::NewThumbnail { cas_id } => yield cas_id,
+							CoreEvent::NewThumbnail { cas_id, variant: thumbnail_variant }
+								if thumbnail_variant == variant =>
+							{
+								yield cas_id
+							}
+							_ => {}
+						}
+					}
+				}
+			})
+		})
+		.library_subscription("completed", |t| {
+			// Yields the jobs of the library which are done running, whether they completed, failed or were canceled
+			t(|ctx, _: (), library_id| {
+				let mut event_bus_rx = ctx.event_bus.subscribe();
+				async_stream::stream! {
+					while let Ok(event) = event_bus_rx.recv().await {
+						match event {
+							CoreEvent::JobCompleted(completed) if completed.library_id == library_id => {
+								yield completed
+							}
 							_ => {}
 						}
 					}
 				}
 			})
 		})
+		.merge("schedules.", mount_schedule_routes())
+}
+
+fn mount_schedule_routes() -> RouterBuilder {
+	<RouterBuilder>::new()
+		.library_query("list", |t| {
+			t(|_, _: (), library| async move {
+				Ok(library
+					.db
+					.job_schedule()
+					.find_many(vec![])
+					.order_by(job_schedule::date_created::order(Direction::Asc))
+					.exec()
+					.await?
+					.into_iter()
+					.map(JobSchedule::try_from)
+					.collect::<Result<Vec<_>, _>>()?)
+			})
+		})
+		.library_mutation("create", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct JobScheduleCreateArgs {
+				pub name: Option<String>,
+				pub job: ScheduledJob,
+				pub cron: String,
+			}
+
+			t(|_, args: JobScheduleCreateArgs, library| async move {
+				parse_cron(&args.cron)?;
+				let location_id = args.job.location_id();
+				if find_location(&library, location_id).exec().await?.is_none() {
+					return Err(LocationError::IdNotFound(location_id).into());
+				}
+
+				let schedule = library
+					.db
+					.job_schedule()
+					.create(
+						serde_json::to_string(&args.job).map_err(JobScheduleError::from)?,
+						args.cron,
+						vec![job_schedule::name::set(args.name)],
+					)
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "jobs.schedules.list");
+
+				Ok(schedule.id)
+			})
+		})
+		.library_mutation("setEnabled", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct JobScheduleSetEnabledArgs {
+				pub id: i32,
+				pub enabled: bool,
+			}
+
+			t(|_, args: JobScheduleSetEnabledArgs, library| async move {
+				// Like for a node which was off, a schedule enabled again catches up once with the runs it missed
+				let updated = library
+					.db
+					.job_schedule()
+					.update_many(
+						vec![job_schedule::id::equals(args.id)],
+						vec![job_schedule::enabled::set(args.enabled)],
+					)
+					.exec()
+					.await?;
+				if updated == 0 {
+					return Err(JobScheduleError::NotFound(args.id).into());
+				}
+
+				invalidate_query!(library, "jobs.schedules.list");
+
+				Ok(())
+			})
+		})
+		.library_mutation("delete", |t| {
+			t(|_, id: i32, library| async move {
+				library
+					.db
+					.job_schedule()
+					.delete_many(vec![job_schedule::id::equals(id)])
+					.exec()
+					.await?;
+
+				invalidate_query!(library, "jobs.schedules.list");
+
+				Ok(())
+			})
+		})
 }
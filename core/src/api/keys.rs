@@ -293,7 +293,9 @@ pub(crate) fn mount() -> RouterBuilder {
 			})
 		})
 		.library_mutation("backupKeystore", |t| {
-			t(|_, path: PathBuf, library| async move {
+			t(|ctx, path: PathBuf, library| async move {
+				ctx.authorize_node_admin()?;
+
 				// dump all stored keys that are in the key manager (maybe these should be taken from prisma as this will include even "non-sync with library" keys)
 				let mut stored_keys = library.key_manager.dump_keystore();
 
@@ -312,7 +314,9 @@ pub(crate) fn mount() -> RouterBuilder {
 			})
 		})
 		.library_mutation("restoreKeystore", |t| {
-			t(|_, args: RestoreBackupArgs, library| async move {
+			t(|ctx, args: RestoreBackupArgs, library| async move {
+				ctx.authorize_node_admin()?;
+
 				let mut input_file = File::open(args.path).await.map_err(Error::Io)?;
 
 				let mut backup = Vec::new();
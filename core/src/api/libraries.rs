@@ -1,8 +1,18 @@
 use crate::{
-	api::Ctx,
+	api::{CoreEvent, Ctx},
 	invalidate_query,
-	library::{Library, LibraryConfig},
-	prisma::statistics,
+	job::{Job, JobStatus},
+	library::{
+		delete_template, library_storage_usage, list_templates, read_template, save_template,
+		trash_bytes, DbMaintenanceJob, DbMaintenanceJobInit, FileVersionRetention, Library,
+		LibraryBackupJob, LibraryBackupJobInit, LibraryBackupSchedule, LibraryConfig,
+		LibraryConfigWrapped, LibraryExportJob, LibraryExportJobInit, LibraryImportReport,
+		LibraryMergeJob, LibraryMergeJobInit, LibraryStorageBudget, LibraryTemplate,
+	},
+	node::LibraryRole,
+	object::cas::CasIdAlgorithm,
+	prisma::{activity, job, object, statistics},
+	util::db::{estimated_count, CountedTable},
 	volume::{get_volumes, save_volume},
 };
 
@@ -11,9 +21,13 @@ use sd_crypto::{
 	Protected,
 };
 
+use std::path::PathBuf;
+
 use chrono::Utc;
+use int_enum::IntEnum;
+use prisma_client_rust::Direction;
 use rspc::{Error, ErrorCode, Type};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use tracing::debug;
 use uuid::Uuid;
 
@@ -22,10 +36,96 @@ use super::{
 	RouterBuilder,
 };
 
+const ACTIVITY_PAGE_SIZE: i32 = 50;
+
 pub(crate) fn mount() -> RouterBuilder {
 	<RouterBuilder>::new()
 		.query("list", |t| {
-			t(|ctx: Ctx, _: ()| async move { ctx.library_manager.get_all_libraries_config().await })
+			t(|ctx: Ctx, _: ()| async move {
+				ctx.library_manager
+					.get_all_libraries_config()
+					.await
+					.into_iter()
+					.filter(|library| ctx.can_access(library.uuid))
+					.collect::<Vec<_>>()
+			})
+		})
+		.query("getOverview", |t| {
+			#[derive(Serialize, Type)]
+			pub struct NodeOverview {
+				libraries: Vec<LibraryOverview>,
+				total_object_count: i32,
+				total_bytes_used: String,
+				total_library_db_size: String,
+				running_jobs: i32,
+			}
+
+			t(|ctx: Ctx, _: ()| async move {
+				let mut libraries = vec![];
+				for library in ctx.library_manager.get_all_libraries().await {
+					if !ctx.can_access(library.id) {
+						continue;
+					}
+
+					libraries.push(library_overview(&library).await?);
+				}
+
+				Ok(NodeOverview {
+					total_object_count: libraries.iter().map(|l| l.object_count).sum(),
+					total_bytes_used: libraries
+						.iter()
+						.map(|l| l.bytes_used.parse::<u64>().unwrap_or(0))
+						.sum::<u64>()
+						.to_string(),
+					total_library_db_size: libraries
+						.iter()
+						.map(|l| l.library_db_size.parse::<u64>().unwrap_or(0))
+						.sum::<u64>()
+						.to_string(),
+					running_jobs: ctx.jobs.get_running().await.len() as i32,
+					libraries,
+				})
+			})
+		})
+		.library_query("activity", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct ActivityArgs {
+				/// cursor is the id of the last activity of the previous page
+				pub cursor: Option<i32>,
+				pub take: Option<i32>,
+			}
+
+			#[derive(Serialize, Type)]
+			pub struct ActivityPage {
+				items: Vec<activity::Data>,
+				next_cursor: Option<i32>,
+			}
+
+			t(|_, args: ActivityArgs, library: Library| async move {
+				let take = args.take.unwrap_or(ACTIVITY_PAGE_SIZE).clamp(1, 500);
+
+				let mut items = library
+					.db
+					.activity()
+					.find_many(
+						args.cursor
+							.map(|cursor| vec![activity::id::lt(cursor)])
+							.unwrap_or_default(),
+					)
+					.order_by(activity::id::order(Direction::Desc))
+					.take(take as i64 + 1)
+					.exec()
+					.await?;
+
+				let next_cursor = if items.len() > take as usize {
+					items.truncate(take as usize);
+					items.last().map(|item| item.id)
+				} else {
+					None
+				};
+
+				Ok(ActivityPage { items, next_cursor })
+			})
 		})
 		.library_query("getStatistics", |t| {
 			t(|_, _: (), library: Library| async move {
@@ -64,17 +164,23 @@ pub(crate) fn mount() -> RouterBuilder {
 						.await
 						.unwrap_or(0);
 
+				let total_object_count =
+					estimated_count(&library.db, CountedTable::Object).await? as i32;
+
+				let trash_bytes = trash_bytes(&library).await?;
+
 				use statistics::*;
 				let params = vec![
 					id::set(1), // Each library is a database so only one of these ever exists
 					date_captured::set(Utc::now().into()),
-					total_object_count::set(0),
+					total_object_count::set(total_object_count),
 					library_db_size::set(library_db_size.to_string()),
 					total_bytes_used::set(0.to_string()),
 					total_bytes_capacity::set(total_capacity.to_string()),
 					total_unique_bytes::set(0.to_string()),
 					total_bytes_free::set(available_capacity.to_string()),
 					preview_media_bytes::set(thumbnail_folder_size.to_string()),
+					trash_bytes::set(trash_bytes.to_string()),
 				];
 
 				Ok(library
@@ -104,9 +210,12 @@ pub(crate) fn mount() -> RouterBuilder {
 				auth: AuthOption,
 				algorithm: Algorithm,
 				hashing_algorithm: HashingAlgorithm,
+				#[serde(default)]
+				template_id: Option<Uuid>,
 			}
 
 			t(|ctx: Ctx, args: CreateLibraryArgs| async move {
+				ctx.authorize_node_admin()?;
 				debug!("Creating library");
 
 				let password = match args.auth {
@@ -129,6 +238,11 @@ pub(crate) fn mount() -> RouterBuilder {
 					}
 				};
 
+				let template = match args.template_id {
+					Some(id) => Some(read_template(&ctx.config, id).await?),
+					None => None,
+				};
+
 				let new_library = ctx
 					.library_manager
 					.create(
@@ -141,6 +255,7 @@ pub(crate) fn mount() -> RouterBuilder {
 							algorithm: args.algorithm,
 							hashing_algorithm: args.hashing_algorithm,
 						},
+						template,
 					)
 					.await?;
 
@@ -153,22 +268,302 @@ pub(crate) fn mount() -> RouterBuilder {
 				Ok(new_library)
 			})
 		})
+		.query("templates.list", |t| {
+			t(|ctx: Ctx, _: ()| async move { Ok(list_templates(&ctx.config).await?) })
+		})
+		.library_mutation("templates.saveFromLibrary", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct SaveTemplateArgs {
+				pub name: String,
+				#[serde(default)]
+				pub description: String,
+			}
+
+			t(|ctx, args: SaveTemplateArgs, library: Library| async move {
+				let template =
+					LibraryTemplate::from_library(&library, args.name, args.description).await?;
+				save_template(&ctx.config, &template).await?;
+
+				invalidate_query!(library, "library.templates.list");
+
+				Ok(template)
+			})
+		})
+		.mutation("templates.delete", |t| {
+			t(|ctx: Ctx, id: Uuid| async move {
+				ctx.authorize_node_admin()?;
+
+				Ok(delete_template(&ctx.config, id).await?)
+			})
+		})
 		.mutation("edit", |t| {
 			#[derive(Type, Deserialize)]
 			pub struct EditLibraryArgs {
 				pub id: Uuid,
 				pub name: Option<String>,
 				pub description: Option<String>,
+				/// cas_id_algorithm only applies to the files identified after it changes
+				pub cas_id_algorithm: Option<CasIdAlgorithm>,
+				pub perceptual_hashing: Option<bool>,
 			}
 
 			t(|ctx: Ctx, args: EditLibraryArgs| async move {
+				ctx.authorize(args.id, LibraryRole::Admin)?;
+
 				Ok(ctx
 					.library_manager
-					.edit(args.id, args.name, args.description)
+					.edit(
+						args.id,
+						args.name,
+						args.description,
+						args.cas_id_algorithm,
+						args.perceptual_hashing,
+					)
 					.await?)
 			})
 		})
 		.mutation("delete", |t| {
-			t(|ctx: Ctx, id: Uuid| async move { Ok(ctx.library_manager.delete_library(id).await?) })
+			#[derive(Type, Deserialize)]
+			pub struct DeleteLibraryArgs {
+				pub id: Uuid,
+				/// purge also removes the thumbnails, watchers and keys of the library
+				#[serde(default)]
+				pub purge: bool,
+			}
+
+			t(|ctx: Ctx, args: DeleteLibraryArgs| async move {
+				ctx.authorize(args.id, LibraryRole::Admin)?;
+
+				ctx.library_manager
+					.delete_library(args.id, args.purge)
+					.await?;
+				ctx.users.remove_library(args.id)?;
+
+				Ok(())
+			})
+		})
+		.library_mutation("backup", |t| {
+			t(
+				|ctx, args: LibraryBackupJobInit, library: Library| async move {
+					// Backups can only be written outside of the node's backups directory by the node's admins
+					if args.output_dir.is_some() {
+						ctx.authorize_node_admin()?;
+					}
+
+					library.spawn_job(Job::new(args, LibraryBackupJob {})).await;
+
+					Ok(())
+				},
+			)
+		})
+		.library_mutation("setBackupSchedule", |t| {
+			t(
+				|ctx, schedule: Option<LibraryBackupSchedule>, library: Library| async move {
+					if schedule.as_ref().map_or(false, |s| s.output_dir.is_some()) {
+						ctx.authorize_node_admin()?;
+					}
+
+					Ok(ctx
+						.library_manager
+						.set_backup_schedule(library.id, schedule)
+						.await?)
+				},
+			)
+		})
+		.library_mutation("optimize", |t| {
+			t(|_, _: (), library: Library| async move {
+				library
+					.spawn_job(Job::new(DbMaintenanceJobInit {}, DbMaintenanceJob {}))
+					.await;
+
+				Ok(())
+			})
+		})
+		.library_mutation("setMaintenanceInterval", |t| {
+			t(
+				|ctx, interval_secs: Option<u32>, library: Library| async move {
+					Ok(ctx
+						.library_manager
+						.set_maintenance_interval(library.id, interval_secs)
+						.await?)
+				},
+			)
+		})
+		.library_query("storageUsage", |t| {
+			t(
+				|_, _: (), library: Library| async move { Ok(library_storage_usage(&library).await?) },
+			)
+		})
+		.library_mutation("setStorageBudget", |t| {
+			t(
+				|ctx, budget: Option<LibraryStorageBudget>, library: Library| async move {
+					Ok(ctx
+						.library_manager
+						.set_storage_budget(library.id, budget)
+						.await?)
+				},
+			)
+		})
+		.library_mutation("setTrashRetention", |t| {
+			t(
+				|ctx, retention_days: Option<u32>, library: Library| async move {
+					Ok(ctx
+						.library_manager
+						.set_trash_retention(library.id, retention_days)
+						.await?)
+				},
+			)
+		})
+		.library_mutation("setFileVersionRetention", |t| {
+			t(
+				|ctx, retention: FileVersionRetention, library: Library| async move {
+					Ok(ctx
+						.library_manager
+						.set_file_version_retention(library.id, retention)
+						.await?)
+				},
+			)
+		})
+		.library_subscription("storageBudgetAlerts", |t| {
+			t(|ctx, _: (), library_id| {
+				let mut event_bus_rx = ctx.event_bus.subscribe();
+				async_stream::stream! {
+					while let Ok(event) = event_bus_rx.recv().await {
+						match event {
+							CoreEvent::StorageBudgetAlert(alert) if alert.library_id == library_id => {
+								yield alert
+							}
+							_ => {}
+						}
+					}
+				}
+			})
+		})
+		.library_mutation("mergeInto", |t| {
+			t(
+				|ctx, target_library_id: Uuid, library: Library| async move {
+					if target_library_id == library.id {
+						return Err(Error::new(
+							ErrorCode::BadRequest,
+							"Can't merge a library into itself".to_string(),
+						));
+					}
+
+					// Merging writes to the target library, so it needs the same role as the source one
+					ctx.authorize(target_library_id, LibraryRole::ReadWrite)?;
+
+					let target = ctx
+						.library_manager
+						.get_ctx(target_library_id)
+						.await
+						.ok_or_else(|| {
+							Error::new(
+								ErrorCode::NotFound,
+								format!("Library <id={target_library_id}> not found"),
+							)
+						})?;
+
+					target
+						.spawn_job(Job::new(
+							LibraryMergeJobInit {
+								source_library_id: library.id,
+							},
+							LibraryMergeJob::default(),
+						))
+						.await;
+
+					Ok(())
+				},
+			)
 		})
+		.mutation("restore", |t| {
+			t(|ctx: Ctx, archive_path: PathBuf| async move {
+				ctx.authorize_node_admin()?;
+
+				Ok(ctx.library_manager.restore(archive_path).await?)
+			})
+		})
+		.library_mutation("export", |t| {
+			t(
+				|ctx, args: LibraryExportJobInit, library: Library| async move {
+					ctx.authorize_node_admin()?;
+
+					library.spawn_job(Job::new(args, LibraryExportJob {})).await;
+
+					Ok(())
+				},
+			)
+		})
+		.mutation("import", |t| {
+			#[derive(Serialize, Type)]
+			pub struct ImportLibraryResult {
+				library: LibraryConfigWrapped,
+				report: LibraryImportReport,
+			}
+
+			t(|ctx: Ctx, export_path: PathBuf| async move {
+				ctx.authorize_node_admin()?;
+
+				let (library, report) = ctx.library_manager.import(export_path).await?;
+
+				Ok(ImportLibraryResult { library, report })
+			})
+		})
+}
+
+#[derive(Serialize, Type)]
+pub struct LibraryOverview {
+	id: Uuid,
+	name: String,
+	object_count: i32,
+	bytes_used: String,
+	library_db_size: String,
+	queued_jobs: i32,
+	paused_jobs: i32,
+	failed_jobs: i32,
+}
+
+/// Aggregates the statistics of a single library for the `library.getOverview` query.
+async fn library_overview(library: &Library) -> Result<LibraryOverview, Error> {
+	let object_count = estimated_count(&library.db, CountedTable::Object).await?;
+
+	let bytes_used = library
+		.db
+		.object()
+		.find_many(vec![])
+		.select(object::select!({ size_in_bytes }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|object| object.size_in_bytes.parse::<u64>().unwrap_or(0))
+		.sum::<u64>();
+
+	let library_db_size = get_size(
+		library
+			.config()
+			.data_directory()
+			.join("libraries")
+			.join(&format!("{}.db", library.id)),
+	)
+	.await
+	.unwrap_or(0);
+
+	let count_jobs = |status: JobStatus| {
+		library
+			.db
+			.job()
+			.count(vec![job::status::equals(status.int_value())])
+			.exec()
+	};
+
+	Ok(LibraryOverview {
+		id: library.id,
+		name: library.config.name.clone(),
+		object_count: object_count as i32,
+		bytes_used: bytes_used.to_string(),
+		library_db_size: library_db_size.to_string(),
+		queued_jobs: count_jobs(JobStatus::Queued).await? as i32,
+		paused_jobs: count_jobs(JobStatus::Paused).await? as i32,
+		failed_jobs: count_jobs(JobStatus::Failed).await? as i32,
+	})
 }
@@ -1,19 +1,31 @@
 use crate::{
 	library::Library,
 	location::{
-		delete_location, find_location, indexer::rules::IndexerRuleCreateArgs, light_scan_location,
-		location_with_indexer_rules, relink_location, scan_location, LocationCreateArgs,
-		LocationError, LocationUpdateArgs,
+		delete_location, find_location,
+		indexer::rules::{IndexerRule, IndexerRuleCreateArgs},
+		light_scan_location, location_with_indexer_rules, relink_location, scan_location,
+		LocationCreateArgs, LocationError, LocationUpdateArgs,
 	},
 	prisma::{file_path, indexer_rule, indexer_rules_in_location, location, object, tag},
 };
 
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
+use prisma_client_rust::{
+	operator::{and, or},
+	Direction,
+};
 use rspc::{self, ErrorCode, RouterBuilderLike, Type};
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 
-use super::{utils::LibraryRequest, Ctx, RouterBuilder};
+use super::{
+	utils::{
+		cache_listing, cached_listing, ExplorerFilter, InvalidateOperationEvent, LibraryRequest,
+		ListingKey, PathScope,
+	},
+	CoreEvent, Ctx, RouterBuilder,
+};
 
 #[derive(Serialize, Deserialize, Type, Debug)]
 #[serde(tag = "type")]
@@ -23,7 +35,7 @@ pub enum ExplorerContext {
 	// Space(object_in_space::Data),
 }
 
-#[derive(Serialize, Deserialize, Type, Debug)]
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum ExplorerItem {
 	Path {
@@ -41,8 +53,28 @@ pub enum ExplorerItem {
 pub struct ExplorerData {
 	pub context: ExplorerContext,
 	pub items: Vec<ExplorerItem>,
+	/// cursor is where the next page of a paginated listing starts, it's `None` on the last page
+	#[serde(default)]
+	pub cursor: Option<String>,
+	/// total_count is how many items there are in every page of the listing
+	#[serde(default)]
+	pub total_count: usize,
+}
+
+/// ExplorerDataChunk is an event of `locations.streamExplorerData`, which sends the context first,
+/// then the items in batches as they're read from the database, and ends with `Done` or `Error`.
+#[derive(Serialize, Type, Debug)]
+#[serde(tag = "type")]
+pub enum ExplorerDataChunk {
+	Context { context: ExplorerContext },
+	Items { items: Vec<ExplorerItem> },
+	Done,
+	Error { message: String },
 }
 
+/// Items sent at once by `locations.streamExplorerData` when the batch size isn't set
+const EXPLORER_STREAM_BATCH_SIZE: i32 = 1000;
+
 file_path::include!(file_path_with_object { object });
 object::include!(object_with_file_paths { file_paths });
 
@@ -75,8 +107,14 @@ pub(crate) fn mount() -> impl RouterBuilderLike<Ctx> {
 			pub struct LocationExplorerArgs {
 				pub location_id: i32,
 				pub path: String,
+				/// limit is how many items are in a page of the listing
 				pub limit: i32,
+				/// cursor is the `cursor` of the previous page, the first page is returned if it's not set
 				pub cursor: Option<String>,
+				/// filter narrows down the items of the directory, which are all returned if it's not set
+				pub filter: Option<ExplorerFilter>,
+				/// show_hidden lists hidden items too, like dotfiles, which are only left out when it's false
+				pub show_hidden: Option<bool>,
 			}
 
 			t(|_, mut args: LocationExplorerArgs, library| async move {
@@ -91,12 +129,47 @@ pub(crate) fn mount() -> impl RouterBuilderLike<Ctx> {
 					args.path += "/";
 				}
 
+				let cache_key = ListingKey {
+					library_id: library.id,
+					location_id: location.id,
+					materialized_path: args.path.clone(),
+				};
+				let limit = args.limit.max(1) as usize;
+				let hide_hidden = args.show_hidden == Some(false);
+
+				// Only complete listings are cached
+				let cached_items = match &args.filter {
+					None if !hide_hidden => cached_listing(&cache_key),
+					_ => None,
+				};
+				if let Some(listing) = cached_items {
+					let (items, cursor) = explorer_page(&listing, args.cursor.as_deref(), limit);
+
+					return Ok(ExplorerData {
+						context: ExplorerContext::Location(location),
+						items,
+						cursor,
+						total_count: listing.len(),
+					});
+				}
+
+				// Archives with indexed contents are browsed like directories, by their path with a trailing `/`
 				let directory = db
 					.file_path()
 					.find_first(vec![
 						file_path::location_id::equals(location.id),
-						file_path::materialized_path::equals(args.path),
-						file_path::is_dir::equals(true),
+						or(vec![
+							and(vec![
+								file_path::materialized_path::equals(args.path.clone()),
+								file_path::is_dir::equals(true),
+							]),
+							and(vec![
+								file_path::materialized_path::equals(
+									args.path.trim_end_matches('/').to_string(),
+								),
+								file_path::is_dir::equals(false),
+							]),
+						]),
 					])
 					.exec()
 					.await?
@@ -104,42 +177,206 @@ pub(crate) fn mount() -> impl RouterBuilderLike<Ctx> {
 						rspc::Error::new(ErrorCode::NotFound, "Directory not found".into())
 					})?;
 
-				let file_paths = db
-					.file_path()
-					.find_many(vec![
+				let params = || {
+					let mut params = vec![
 						file_path::location_id::equals(location.id),
 						file_path::parent_id::equals(Some(directory.id)),
-					])
-					.include(file_path_with_object::include())
-					.exec()
-					.await?;
+					];
+					if hide_hidden {
+						params.push(file_path::hidden::equals(false));
+					}
+					if let Some(filter) = &args.filter {
+						params.extend(filter.params());
+					}
+					params
+				};
 
-				let mut items = Vec::with_capacity(file_paths.len());
+				// Pages are in the order of their materialized paths, so the last one of a page is where the next starts
+				let (mut file_paths, total_count) = match &args.filter {
+					None => {
+						let total_count = db.file_path().count(params()).exec().await? as usize;
 
-				for file_path in file_paths {
-					let has_thumbnail = if let Some(cas_id) = &file_path.cas_id {
-						library
-							.thumbnail_exists(cas_id)
-							.await
-							.map_err(LocationError::IOError)?
-					} else {
-						false
-					};
+						let mut params = params();
+						if let Some(cursor) = &args.cursor {
+							params.push(file_path::materialized_path::gt(cursor.clone()));
+						}
 
-					items.push(ExplorerItem::Path {
-						has_thumbnail,
-						item: file_path,
-					});
+						let file_paths = db
+							.file_path()
+							.find_many(params)
+							.order_by(file_path::materialized_path::order(Direction::Asc))
+							// One more item than the page holds tells if there's a next page
+							.take(limit as i64 + 1)
+							.include(file_path_with_object::include())
+							.exec()
+							.await?;
+
+						(file_paths, total_count)
+					}
+					// The size can't be filtered by the database, so filtered listings are paginated in memory
+					Some(filter) => {
+						let mut file_paths = db
+							.file_path()
+							.find_many(params())
+							.order_by(file_path::materialized_path::order(Direction::Asc))
+							.include(file_path_with_object::include())
+							.exec()
+							.await?;
+						file_paths.retain(|file_path| filter.matches_size(file_path));
+
+						let total_count = file_paths.len();
+						if let Some(cursor) = &args.cursor {
+							file_paths.retain(|file_path| &file_path.materialized_path > cursor);
+						}
+
+						(file_paths, total_count)
+					}
+				};
+
+				let is_complete = args.cursor.is_none() && file_paths.len() <= limit;
+
+				let cursor = (file_paths.len() > limit)
+					.then(|| file_paths[limit - 1].materialized_path.clone());
+				file_paths.truncate(limit);
+
+				let items = explorer_items(&library, file_paths).await?;
+
+				if args.filter.is_none() && !hide_hidden && is_complete {
+					cache_listing(cache_key, Arc::new(items.clone()));
 				}
 
 				Ok(ExplorerData {
 					context: ExplorerContext::Location(location),
 					items,
+					cursor,
+					total_count,
 				})
 			})
 		})
+		.library_subscription("streamExplorerData", |t| {
+			#[derive(Deserialize, Type)]
+			pub struct LocationExplorerStreamArgs {
+				pub location_id: i32,
+				pub path: String,
+				pub batch_size: Option<i32>,
+				pub filter: Option<ExplorerFilter>,
+			}
+
+			t(|ctx, mut args: LocationExplorerStreamArgs, library_id| {
+				let library_manager = ctx.library_manager.clone();
+
+				async_stream::stream! {
+					let library = match library_manager.get_ctx(library_id).await {
+						Some(library) => library,
+						None => {
+							yield ExplorerDataChunk::Error {
+								message: format!("Library <id={library_id}> not found"),
+							};
+							return;
+						}
+					};
+
+					if !args.path.ends_with('/') {
+						args.path += "/";
+					}
+					let batch_size = args
+						.batch_size
+						.unwrap_or(EXPLORER_STREAM_BATCH_SIZE)
+						.clamp(1, 10_000) as usize;
+
+					let (location, directory) =
+						match find_explorer_directory(&library, args.location_id, &args.path).await {
+							Ok(found) => found,
+							Err(e) => {
+								yield ExplorerDataChunk::Error { message: e.to_string() };
+								return;
+							}
+						};
+					let location_id = location.id;
+
+					yield ExplorerDataChunk::Context {
+						context: ExplorerContext::Location(location),
+					};
+
+					// A listing in the cache is complete, so it's sent without reading the database
+					let cache_key = ListingKey {
+						library_id,
+						location_id,
+						materialized_path: args.path,
+					};
+					let cached_items = match &args.filter {
+						None => cached_listing(&cache_key),
+						Some(_) => None,
+					};
+					if let Some(items) = cached_items {
+						for items in items.chunks(batch_size) {
+							yield ExplorerDataChunk::Items { items: items.to_vec() };
+						}
+						yield ExplorerDataChunk::Done;
+						return;
+					}
+
+					// Pages are read by id, so rows written while streaming don't shift the ones left to read
+					let mut cursor = None;
+					loop {
+						let mut params = vec![
+							file_path::location_id::equals(location_id),
+							file_path::parent_id::equals(Some(directory.id)),
+						];
+						if let Some(cursor) = cursor {
+							params.push(file_path::id::gt(cursor));
+						}
+						if let Some(filter) = &args.filter {
+							params.extend(filter.params());
+						}
+
+						let page = library
+							.db
+							.file_path()
+							.find_many(params)
+							.include(file_path_with_object::include())
+							.order_by(file_path::id::order(Direction::Asc))
+							.take(batch_size as i64)
+							.exec()
+							.await
+							.map_err(LocationError::from);
+
+						let mut page = match page {
+							Ok(page) => page,
+							Err(e) => {
+								yield ExplorerDataChunk::Error { message: e.to_string() };
+								return;
+							}
+						};
+						let is_last_page = page.len() < batch_size;
+						cursor = page.last().map(|file_path| file_path.id);
+						if let Some(filter) = &args.filter {
+							page.retain(|file_path| filter.matches_size(file_path));
+						}
+
+						match explorer_items(&library, page).await {
+							Ok(items) if !items.is_empty() => yield ExplorerDataChunk::Items { items },
+							Ok(_) => {}
+							Err(e) => {
+								yield ExplorerDataChunk::Error { message: e.to_string() };
+								return;
+							}
+						}
+
+						if is_last_page {
+							break;
+						}
+					}
+
+					yield ExplorerDataChunk::Done;
+				}
+			})
+		})
 		.library_mutation("create", |t| {
-			t(|_, args: LocationCreateArgs, library| async move {
+			t(|ctx, args: LocationCreateArgs, library| async move {
+				// A location can be created on any path of the node, so it's restricted to the node's admins
+				ctx.authorize_node_admin()?;
+
 				let location = args.create(&library).await?;
 				scan_location(&library, location).await?;
 				Ok(())
@@ -158,14 +395,18 @@ pub(crate) fn mount() -> impl RouterBuilderLike<Ctx> {
 			})
 		})
 		.library_mutation("relink", |t| {
-			t(|_, location_path: PathBuf, library| async move {
+			t(|ctx, location_path: PathBuf, library| async move {
+				ctx.authorize_node_admin()?;
+
 				relink_location(&library, location_path)
 					.await
 					.map_err(Into::into)
 			})
 		})
 		.library_mutation("addLibrary", |t| {
-			t(|_, args: LocationCreateArgs, library| async move {
+			t(|ctx, args: LocationCreateArgs, library| async move {
+				ctx.authorize_node_admin()?;
+
 				let location = args.add_library(&library).await?;
 				scan_location(&library, location).await?;
 				Ok(())
@@ -228,6 +469,117 @@ pub(crate) fn mount() -> impl RouterBuilderLike<Ctx> {
 		.merge("indexer_rules.", mount_indexer_rule_routes())
 }
 
+/// find_explorer_directory returns the location and the directory at `path` in it, which must end with a `/`.
+async fn find_explorer_directory(
+	library: &Library,
+	location_id: i32,
+	path: &str,
+) -> Result<(location::Data, file_path::Data), LocationError> {
+	let location = find_location(library, location_id)
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	let directory = library
+		.db
+		.file_path()
+		.find_first(vec![
+			file_path::location_id::equals(location.id),
+			file_path::materialized_path::equals(path.to_string()),
+			file_path::is_dir::equals(true),
+		])
+		.exec()
+		.await?
+		.ok_or_else(|| LocationError::DirectoryNotFound(path.to_string()))?;
+
+	Ok((location, directory))
+}
+
+/// explorer_items checks which of the file paths of a listing have a thumbnail.
+async fn explorer_items(
+	library: &Library,
+	file_paths: Vec<file_path_with_object::Data>,
+) -> Result<Vec<ExplorerItem>, LocationError> {
+	let mut items = Vec::with_capacity(file_paths.len());
+
+	for file_path in file_paths {
+		let has_thumbnail = if let Some(cas_id) = &file_path.cas_id {
+			library
+				.thumbnail_exists(cas_id)
+				.await
+				.map_err(LocationError::IOError)?
+		} else {
+			false
+		};
+
+		items.push(ExplorerItem::Path {
+			has_thumbnail,
+			item: file_path,
+		});
+	}
+
+	Ok(items)
+}
+
+/// explorer_page returns the page of a cached listing starting after `cursor`, with the cursor of the next page.
+fn explorer_page(
+	listing: &[ExplorerItem],
+	cursor: Option<&str>,
+	limit: usize,
+) -> (Vec<ExplorerItem>, Option<String>) {
+	let materialized_path = |item: &ExplorerItem| match item {
+		ExplorerItem::Path { item, .. } => item.materialized_path.clone(),
+		ExplorerItem::Object { .. } => String::new(),
+	};
+
+	let mut items = listing
+		.iter()
+		.filter(|item| cursor.map_or(true, |cursor| materialized_path(item).as_str() > cursor))
+		.take(limit + 1)
+		.cloned()
+		.collect::<Vec<_>>();
+
+	let cursor = (items.len() > limit).then(|| materialized_path(&items[limit - 1]));
+	items.truncate(limit);
+
+	(items, cursor)
+}
+
+/// invalidate_explorer_directory invalidates the listing of a single directory, instead of every listing of the library.
+/// `path` is the materialized path of the directory, ending with a `/`.
+pub(crate) fn invalidate_explorer_directory(library: &Library, location_id: i32, path: &str) {
+	library.emit(CoreEvent::InvalidateOperation(
+		InvalidateOperationEvent::dangerously_create_for_library(
+			library.id,
+			"locations.getExplorerData",
+			json!({
+				"location_id": location_id,
+				"path": path,
+			}),
+		),
+	));
+}
+
+/// invalidate_explorer_path invalidates the listings of a directory and of its subdirectories, or of the whole location
+/// without a path, instead of every listing of the library. `path_prefix` is the materialized path of the directory, ending with a `/`.
+pub(crate) fn invalidate_explorer_path(
+	library: &Library,
+	location_id: i32,
+	path_prefix: Option<String>,
+) {
+	library.emit(CoreEvent::InvalidateOperation(
+		InvalidateOperationEvent::dangerously_create_for_library(
+			library.id,
+			"locations.getExplorerData",
+			Value::Null,
+		)
+		.with_scope(PathScope {
+			location_id,
+			path_prefix,
+		}),
+	));
+}
+
 fn mount_indexer_rule_routes() -> RouterBuilder {
 	<RouterBuilder>::new()
 		.library_mutation("create", |t| {
@@ -283,6 +635,47 @@ fn mount_indexer_rule_routes() -> RouterBuilder {
 					.map_err(Into::into)
 			})
 		})
+		// tells which of the sample paths a rule would accept, so it can be checked before it's created
+		.library_query("test", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct IndexerRuleTestArgs {
+				pub rule: IndexerRuleCreateArgs,
+				/// paths are full paths, which must exist for the rules checking the children of directories or ignore files
+				pub paths: Vec<String>,
+			}
+
+			#[derive(Type, Serialize)]
+			pub struct IndexerRuleTestResult {
+				pub path: String,
+				pub accepted: bool,
+				/// error is why the rule couldn't be applied to the path, which isn't accepted then
+				pub error: Option<String>,
+			}
+
+			t(|_, args: IndexerRuleTestArgs, _| async move {
+				let rule = IndexerRule::new(
+					args.rule.kind,
+					args.rule.name.clone(),
+					args.rule.parameters()?,
+				);
+
+				let mut results = Vec::with_capacity(args.paths.len());
+				for path in args.paths {
+					let (accepted, error) = match rule.apply(&path).await {
+						Ok(accepted) => (accepted, None),
+						Err(e) => (false, Some(e.to_string())),
+					};
+
+					results.push(IndexerRuleTestResult {
+						path,
+						accepted,
+						error,
+					});
+				}
+
+				Ok(results)
+			})
+		})
 		// list indexer rules for location, returning the indexer rule
 		.library_query("listForLocation", |t| {
 			t(|_, location_id: i32, library| async move {
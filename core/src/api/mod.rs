@@ -1,21 +1,23 @@
-use std::{
-	sync::Arc,
-	time::{Duration, Instant},
-};
+use std::sync::Arc;
 
-use rspc::{Config, Type};
+use rspc::{Config, ErrorCode, Type};
 use serde::{Deserialize, Serialize};
-use tokio::sync::broadcast;
+use tokio::{
+	sync::broadcast::{self, error::RecvError},
+	time::{sleep_until, Instant},
+};
+use uuid::Uuid;
 
 use crate::{
-	job::JobManager,
-	library::LibraryManager,
-	node::{NodeConfig, NodeConfigManager},
+	extension::ExtensionHost,
+	job::{JobCompleted, JobManager},
+	library::{LibraryManager, StorageBudgetAlert},
+	node::{LibraryRole, NodeConfig, NodeConfigManager, NodeUser, ReleaseMetadata, UserManager},
 	p2p::P2PManager,
 	util::secure_temp_keystore::SecureTempKeystore,
 };
 
-use utils::{InvalidRequests, InvalidateOperationEvent};
+use utils::{InvalidRequests, InvalidateOperationEvent, InvalidationCoalescer};
 
 pub type Router = rspc::Router<Ctx>;
 pub(crate) type RouterBuilder = rspc::RouterBuilder<Ctx>;
@@ -23,9 +25,16 @@ pub(crate) type RouterBuilder = rspc::RouterBuilder<Ctx>;
 /// Represents an internal core event, these are exposed to client via a rspc subscription.
 #[derive(Debug, Clone, Serialize, Type)]
 pub enum CoreEvent {
-	NewThumbnail { cas_id: String },
+	/// A thumbnail was generated, `variant` is the name of its variant or `None` for the default thumbnails.
+	NewThumbnail {
+		cas_id: String,
+		variant: Option<String>,
+	},
 	InvalidateOperation(InvalidateOperationEvent),
 	InvalidateOperationDebounced(InvalidateOperationEvent),
+	StorageBudgetAlert(StorageBudgetAlert),
+	UpdateAvailable(ReleaseMetadata),
+	JobCompleted(JobCompleted),
 }
 
 /// Is provided when executing the router from the request.
@@ -36,16 +45,62 @@ pub struct Ctx {
 	pub event_bus: broadcast::Sender<CoreEvent>,
 	pub p2p: Arc<P2PManager>,
 	pub secure_temp_keystore: Arc<SecureTempKeystore>,
+	pub users: Arc<UserManager>,
+	pub extensions: Arc<ExtensionHost>,
+	/// user is the user making the request, `None` when the client has full access to the node.
+	pub user: Option<NodeUser>,
 }
 
+impl Ctx {
+	/// can_access returns whether the user making the request can access the given library.
+	pub(crate) fn can_access(&self, library_id: Uuid) -> bool {
+		self.user
+			.as_ref()
+			.map_or(true, |user| user.role(library_id).is_some())
+	}
+
+	/// authorize fails the request if the user making it doesn't have at least the given role in the library.
+	pub(crate) fn authorize(&self, library_id: Uuid, role: LibraryRole) -> Result<(), rspc::Error> {
+		match &self.user {
+			Some(user) if user.role(library_id).map_or(true, |r| r < role) => {
+				Err(rspc::Error::new(
+					ErrorCode::Forbidden,
+					format!("You need the '{role:?}' role in this library to use this operation."),
+				))
+			}
+			_ => Ok(()),
+		}
+	}
+
+	/// is_node_admin returns whether the user making the request is an admin of the node.
+	pub(crate) fn is_node_admin(&self) -> bool {
+		self.user.as_ref().map_or(true, |user| user.node_admin)
+	}
+
+	/// authorize_node_admin fails the request if the user making it isn't an admin of the node.
+	pub(crate) fn authorize_node_admin(&self) -> Result<(), rspc::Error> {
+		match &self.user {
+			Some(user) if !user.node_admin => Err(rspc::Error::new(
+				ErrorCode::Forbidden,
+				"You must be an admin of this node to use this operation.".to_string(),
+			)),
+			_ => Ok(()),
+		}
+	}
+}
+
+mod bookmarks;
+mod extensions;
 mod files;
 mod jobs;
 mod keys;
 mod libraries;
-mod locations;
+pub(crate) mod locations;
 mod nodes;
 mod p2p;
+mod search;
 mod tags;
+mod users;
 pub mod utils;
 pub mod volumes;
 
@@ -95,29 +150,52 @@ pub(crate) fn mount() -> Arc<Router> {
 		.yolo_merge("library.", libraries::mount())
 		.yolo_merge("volumes.", volumes::mount())
 		.yolo_merge("tags.", tags::mount())
+		.yolo_merge("bookmarks.", bookmarks::mount())
 		.yolo_merge("nodes.", nodes::mount())
+		.yolo_merge("users.", users::mount())
 		.yolo_merge("keys.", keys::mount())
 		.yolo_merge("locations.", locations::mount())
 		.yolo_merge("files.", files::mount())
+		.yolo_merge("search.", search::mount())
 		.yolo_merge("jobs.", jobs::mount())
 		.yolo_merge("p2p.", p2p::mount())
-		// TODO: Scope the invalidate queries to a specific library (filtered server side)
+		.yolo_merge("ext.", extensions::mount())
+		// Clients of a library are only sent its invalidations and the ones of the node, clients without a library only the ones of the node
 		.subscription("invalidateQuery", |t| {
-			t(|ctx, _: ()| {
+			t(|ctx, library_id: Option<Uuid>| {
 				let mut event_bus_rx = ctx.event_bus.subscribe();
-				let mut last = Instant::now();
+				let mut coalescer = InvalidationCoalescer::default();
 				async_stream::stream! {
-					while let Ok(event) = event_bus_rx.recv().await {
-						match event {
-							CoreEvent::InvalidateOperation(op) => yield op,
-							CoreEvent::InvalidateOperationDebounced(op) => {
-								let current = Instant::now();
-								if current.duration_since(last) > Duration::from_millis(1000 / 10) {
-									last = current;
-									yield op;
+					loop {
+						let deadline = coalescer.next_deadline();
+
+						let ops = tokio::select! {
+							event = event_bus_rx.recv() => match event {
+								Ok(
+									CoreEvent::InvalidateOperation(op)
+									| CoreEvent::InvalidateOperationDebounced(op),
+								) if !op.concerns(library_id)
+									|| op.library_id().map_or(false, |id| !ctx.can_access(id)) => vec![],
+								Ok(CoreEvent::InvalidateOperation(op)) => {
+									coalescer.sent(&op);
+									vec![op]
+								}
+								Ok(CoreEvent::InvalidateOperationDebounced(op)) => {
+									coalescer.push(op);
+									vec![]
 								}
+								Ok(_) => vec![],
+								// The missed invalidations can't be known, so the client refetches everything
+								Err(RecvError::Lagged(_)) => vec![InvalidateOperationEvent::all(library_id)],
+								Err(RecvError::Closed) => break,
 							},
-							_ => {}
+							_ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+								coalescer.take_due()
+							}
+						};
+
+						for op in ops {
+							yield op;
 						}
 					}
 				}
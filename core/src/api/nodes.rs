@@ -1,24 +1,179 @@
-use super::RouterBuilder;
-use rspc::Type;
+use crate::{
+	node::{
+		check_for_updates, clear_pending, pending_report, read_logs, send_report, set_telemetry,
+		LogQuery, TelemetryConfig, UpdateConfig,
+	},
+	object::preview::thumbnail_gc_job::thumbnail_cache_info,
+};
+
+use super::{utils::InvalidateOperationEvent, CoreEvent, Ctx, RouterBuilder};
+use rspc::{ErrorCode, Type};
 use serde::{Deserialize, Serialize};
 
+/// update_telemetry applies a change to the telemetry config and invalidates the node state which holds it.
+async fn update_telemetry(
+	ctx: &Ctx,
+	update: impl FnOnce(&mut TelemetryConfig),
+) -> Result<(), rspc::Error> {
+	let mut telemetry = ctx.config.get().await.telemetry;
+	update(&mut telemetry);
+
+	set_telemetry(&ctx.config, telemetry).await.map_err(|e| {
+		rspc::Error::with_cause(
+			ErrorCode::InternalServerError,
+			"Failed to save the telemetry config".to_string(),
+			e,
+		)
+	})?;
+
+	invalidate_node_state(ctx);
+	Ok(())
+}
+
+/// invalidate_node_state invalidates the node state, which has no library to invalidate it through.
+fn invalidate_node_state(ctx: &Ctx) {
+	ctx.event_bus
+		.send(CoreEvent::InvalidateOperation(
+			InvalidateOperationEvent::dangerously_create("nodeState", serde_json::Value::Null),
+		))
+		.ok();
+}
+
 pub(crate) fn mount() -> RouterBuilder {
-	<RouterBuilder>::new().mutation("tokenizeSensitiveKey", |t| {
-		#[derive(Deserialize, Type)]
-		pub struct TokenizeKeyArgs {
-			pub secret_key: String,
-		}
-		#[derive(Serialize, Type)]
-		pub struct TokenizeResponse {
-			pub token: String,
-		}
+	<RouterBuilder>::new()
+		.query("logs", |t| {
+			t(|ctx, query: LogQuery| async move {
+				ctx.authorize_node_admin()?;
+
+				read_logs(ctx.config.data_directory(), query)
+					.await
+					.map_err(|e| {
+						rspc::Error::with_cause(
+							ErrorCode::InternalServerError,
+							"Failed to read logs".to_string(),
+							e,
+						)
+					})
+			})
+		})
+		.query("cacheInfo", |t| {
+			t(|ctx, _: ()| async move {
+				thumbnail_cache_info(
+					ctx.config.data_directory(),
+					&ctx.config.get().await.thumbnail,
+				)
+				.await
+				.map_err(|e| {
+					rspc::Error::with_cause(
+						ErrorCode::InternalServerError,
+						"Failed to read the thumbnail cache".to_string(),
+						e,
+					)
+				})
+			})
+		})
+		.query("telemetry.get", |t| {
+			t(|ctx, _: ()| async move { Ok(ctx.config.get().await.telemetry) })
+		})
+		.query("telemetry.preview", |t| {
+			t(|ctx, _: ()| async move {
+				ctx.authorize_node_admin()?;
+
+				Ok(pending_report(&ctx.library_manager, &ctx.config).await)
+			})
+		})
+		.mutation("telemetry.setEnabled", |t| {
+			t(|ctx, enabled: bool| async move {
+				ctx.authorize_node_admin()?;
+
+				update_telemetry(&ctx, |telemetry| telemetry.enabled = enabled).await
+			})
+		})
+		.mutation("telemetry.setEndpoint", |t| {
+			t(|ctx, endpoint: Option<String>| async move {
+				ctx.authorize_node_admin()?;
+
+				update_telemetry(&ctx, |telemetry| telemetry.endpoint = endpoint).await
+			})
+		})
+		.mutation("telemetry.clear", |t| {
+			t(|ctx, _: ()| async move {
+				ctx.authorize_node_admin()?;
+
+				clear_pending(&ctx.config).await;
+				Ok(())
+			})
+		})
+		.mutation("telemetry.sendNow", |t| {
+			t(|ctx, _: ()| async move {
+				ctx.authorize_node_admin()?;
+
+				send_report(&ctx.library_manager, &ctx.config).await;
+				Ok(())
+			})
+		})
+		.mutation("checkForUpdates", |t| {
+			t(|ctx, _: ()| async move {
+				ctx.authorize_node_admin()?;
+
+				let release = check_for_updates(&ctx.config).await?;
+
+				if let Some(release) = &release {
+					ctx.event_bus
+						.send(CoreEvent::UpdateAvailable(release.clone()))
+						.ok();
+				}
+
+				Ok(release)
+			})
+		})
+		.mutation("setUpdateConfig", |t| {
+			t(|ctx, updates: UpdateConfig| async move {
+				ctx.authorize_node_admin()?;
+
+				ctx.config
+					.write(|mut config| config.updates = updates)
+					.await
+					.map_err(|e| {
+						rspc::Error::with_cause(
+							ErrorCode::InternalServerError,
+							"Failed to save the update config".to_string(),
+							e,
+						)
+					})?;
+
+				invalidate_node_state(&ctx);
+				Ok(())
+			})
+		})
+		.subscription("updateAvailable", |t| {
+			t(|ctx, _: ()| {
+				let mut event_bus_rx = ctx.event_bus.subscribe();
+				async_stream::stream! {
+					while let Ok(event) = event_bus_rx.recv().await {
+						if let CoreEvent::UpdateAvailable(release) = event {
+							yield release;
+						}
+					}
+				}
+			})
+		})
+		.mutation("tokenizeSensitiveKey", |t| {
+			#[derive(Deserialize, Type)]
+			pub struct TokenizeKeyArgs {
+				pub secret_key: String,
+			}
+			#[derive(Serialize, Type)]
+			pub struct TokenizeResponse {
+				pub token: String,
+			}
 
-		t(|ctx, args: TokenizeKeyArgs| async move {
-			let token = ctx.secure_temp_keystore.tokenize(args.secret_key);
+			t(|ctx, args: TokenizeKeyArgs| async move {
+				let token = ctx.secure_temp_keystore.tokenize(args.secret_key);
 
-			Ok(TokenizeResponse {
-				token: token.to_string(),
+				Ok(TokenizeResponse {
+					token: token.to_string(),
+				})
 			})
 		})
-	})
 }
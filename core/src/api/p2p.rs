@@ -12,7 +12,13 @@ pub(crate) fn mount() -> RouterBuilder {
 		.subscription("events", |t| {
 			t(|ctx, _: ()| {
 				let mut rx = ctx.p2p.subscribe();
+				// Subscriptions can't fail, so users who aren't admins of the node get a stream which ends right away
+				let authorized = ctx.is_node_admin();
 				async_stream::stream! {
+					if !authorized {
+						return;
+					}
+
 					// TODO: Don't block subscription start
 					for peer in ctx.p2p.manager.get_discovered_peers().await {
 						yield P2PEvent::DiscoveredPeer {
@@ -41,9 +47,13 @@ pub(crate) fn mount() -> RouterBuilder {
 			}
 
 			t(|ctx, args: SpacedropArgs| async move {
+				ctx.authorize_node_admin()?;
+
 				ctx.p2p
 					.big_bad_spacedrop(args.peer_id, PathBuf::from(args.file_path))
 					.await;
+
+				Ok(())
 			})
 		})
 }
@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::Direction;
+use rspc::{ErrorCode, Type};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+	invalidate_query,
+	library::Library,
+	object::search::{
+		paths::{search_paths, PathSearchCursor, PathSearchResult},
+		rebuild_content_index,
+		saved::SearchFilter,
+		search_content,
+	},
+	prisma::{object, saved_search},
+	sync,
+};
+
+use super::{locations::object_with_file_paths, utils::LibraryRequest, RouterBuilder};
+
+/// Saved searches return at most this many objects, most recently modified first
+const MAX_SAVED_SEARCH_RESULTS: usize = 1_000;
+
+#[derive(Serialize, Type)]
+pub struct SavedSearch {
+	pub id: i32,
+	pub name: Option<String>,
+	pub filter: SearchFilter,
+	pub smart: bool,
+	pub date_created: DateTime<Utc>,
+	pub date_modified: DateTime<Utc>,
+}
+
+fn saved_search_not_found(id: i32) -> rspc::Error {
+	rspc::Error::new(
+		ErrorCode::NotFound,
+		format!("Saved search <id={id}> not found"),
+	)
+}
+
+pub(crate) fn mount() -> RouterBuilder {
+	<RouterBuilder>::new()
+		.library_query("content", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct ContentSearchArgs {
+				pub query: String,
+				pub take: Option<u8>,
+				pub skip: Option<u32>,
+			}
+
+			#[derive(Serialize, Type)]
+			pub struct ContentSearchResult {
+				pub object: object_with_file_paths::Data,
+				pub score: f32,
+				/// snippet is the best matching excerpt of the contents, with the matched words in `<b>` tags
+				pub snippet: Option<String>,
+			}
+
+			t(|_, args: ContentSearchArgs, library: Library| async move {
+				let hits = search_content(
+					&library,
+					args.query,
+					args.take.unwrap_or(100) as usize,
+					args.skip.unwrap_or_default() as usize,
+				)
+				.await?;
+
+				let mut objects = library
+					.db
+					.object()
+					.find_many(vec![object::id::in_vec(
+						hits.iter().map(|hit| hit.object_id).collect(),
+					)])
+					.include(object_with_file_paths::include())
+					.exec()
+					.await?
+					.into_iter()
+					.map(|object| (object.id, object))
+					.collect::<HashMap<_, _>>();
+
+				// Objects deleted since they were indexed are left out
+				Ok(hits
+					.into_iter()
+					.filter_map(|hit| {
+						Some(ContentSearchResult {
+							object: objects.remove(&hit.object_id)?,
+							score: hit.score,
+							snippet: hit.snippet,
+						})
+					})
+					.collect::<Vec<_>>())
+			})
+		})
+		.library_query("paths", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct SearchPathsArgs {
+				pub query: String,
+				pub location_id: Option<i32>,
+				pub take: Option<u8>,
+				/// cursor is the `cursor` of the previous page, to get the next one
+				pub cursor: Option<PathSearchCursor>,
+			}
+
+			#[derive(Serialize, Type)]
+			pub struct SearchPaths {
+				pub items: Vec<PathSearchResult>,
+				/// cursor is `None` on the last page
+				pub cursor: Option<PathSearchCursor>,
+				/// truncated tells that too many file paths matched the query for all of them to be ranked,
+				/// so a more precise query could find better matches
+				pub truncated: bool,
+			}
+
+			t(|_, args: SearchPathsArgs, library: Library| async move {
+				let (items, cursor, truncated) = search_paths(
+					&library.db,
+					&args.query,
+					args.location_id,
+					args.take.unwrap_or(100) as usize,
+					args.cursor,
+				)
+				.await?;
+
+				Ok(SearchPaths {
+					items,
+					cursor,
+					truncated,
+				})
+			})
+		})
+		.library_mutation("rebuildIndex", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct RebuildIndexArgs {
+				/// location_id only reindexes a location, otherwise the whole index is rebuilt
+				pub location_id: Option<i32>,
+			}
+
+			t(|_, args: RebuildIndexArgs, library: Library| async move {
+				Ok(rebuild_content_index(&library, args.location_id).await?)
+			})
+		})
+		.merge("saved.", mount_saved_search_routes())
+}
+
+fn mount_saved_search_routes() -> RouterBuilder {
+	<RouterBuilder>::new()
+		.library_query("list", |t| {
+			t(|_, _: (), library: Library| async move {
+				let Library { db, .. } = &library;
+
+				let saved_searches = db
+					.saved_search()
+					.find_many(vec![])
+					.order_by(saved_search::date_created::order(Direction::Asc))
+					.exec()
+					.await?;
+
+				let mut items = Vec::with_capacity(saved_searches.len());
+				for saved_search in saved_searches {
+					items.push(SavedSearch {
+						id: saved_search.id,
+						name: saved_search.name,
+						filter: SearchFilter::from_stored(&saved_search.filter, db).await?,
+						smart: saved_search.smart,
+						date_created: saved_search.date_created.into(),
+						date_modified: saved_search.date_modified.into(),
+					});
+				}
+
+				Ok(items)
+			})
+		})
+		.library_query("run", |t| {
+			t(|_, id: i32, library: Library| async move {
+				let Library { db, .. } = &library;
+
+				let saved_search = db
+					.saved_search()
+					.find_unique(saved_search::id::equals(id))
+					.select(saved_search::select!({ filter }))
+					.exec()
+					.await?
+					.ok_or_else(|| saved_search_not_found(id))?;
+
+				let filter = SearchFilter::from_stored(&saved_search.filter, db).await?;
+
+				Ok(db
+					.object()
+					.find_many(filter.params())
+					.order_by(object::date_modified::order(Direction::Desc))
+					.include(object_with_file_paths::include())
+					.exec()
+					.await?
+					.into_iter()
+					.filter(|object| filter.matches_size(&object.size_in_bytes))
+					.take(MAX_SAVED_SEARCH_RESULTS)
+					.collect::<Vec<_>>())
+			})
+		})
+		.library_mutation("create", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct SavedSearchCreateArgs {
+				pub name: Option<String>,
+				pub filter: SearchFilter,
+				#[serde(default)]
+				pub smart: bool,
+			}
+
+			t(
+				|_, args: SavedSearchCreateArgs, library: Library| async move {
+					let Library { db, sync, .. } = &library;
+
+					let pub_id = Uuid::new_v4().as_bytes().to_vec();
+					let filter = args.filter.to_stored(db).await?;
+
+					let created_search = sync
+						.write_op(
+							db,
+							sync.unique_shared_create(
+								sync::saved_search::SyncId {
+									pub_id: pub_id.clone(),
+								},
+								[
+									("name", json!(args.name)),
+									("filter", json!(filter)),
+									("smart", json!(args.smart)),
+								],
+							),
+							db.saved_search().create(
+								pub_id,
+								filter,
+								vec![
+									saved_search::name::set(args.name),
+									saved_search::smart::set(args.smart),
+								],
+							),
+						)
+						.await?;
+
+					invalidate_query!(library, "search.saved.list");
+
+					Ok(created_search.id)
+				},
+			)
+		})
+		.library_mutation("update", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct SavedSearchUpdateArgs {
+				pub id: i32,
+				pub name: Option<String>,
+				pub filter: Option<SearchFilter>,
+				pub smart: Option<bool>,
+			}
+
+			t(
+				|_, args: SavedSearchUpdateArgs, library: Library| async move {
+					let Library { db, sync, .. } = &library;
+
+					let saved_search = db
+						.saved_search()
+						.find_unique(saved_search::id::equals(args.id))
+						.select(saved_search::select!({ pub_id }))
+						.exec()
+						.await?
+						.ok_or_else(|| saved_search_not_found(args.id))?;
+
+					let mut changes = vec![];
+					if let Some(name) = args.name {
+						changes.push(("name", json!(name), saved_search::name::set(Some(name))));
+					}
+					if let Some(filter) = args.filter {
+						let filter = filter.to_stored(db).await?;
+						changes.push(("filter", json!(filter), saved_search::filter::set(filter)));
+					}
+					if let Some(smart) = args.smart {
+						changes.push(("smart", json!(smart), saved_search::smart::set(smart)));
+					}
+
+					if changes.is_empty() {
+						return Ok(());
+					}
+
+					let date_modified = Utc::now();
+					changes.push((
+						"date_modified",
+						json!(date_modified),
+						saved_search::date_modified::set(date_modified.into()),
+					));
+
+					let (ops, params): (Vec<_>, Vec<_>) = changes
+						.into_iter()
+						.map(|(field, value, param)| {
+							(
+								sync.shared_update(
+									sync::saved_search::SyncId {
+										pub_id: saved_search.pub_id.clone(),
+									},
+									field,
+									value,
+								),
+								param,
+							)
+						})
+						.unzip();
+
+					sync.write_ops(
+						db,
+						(
+							ops,
+							db.saved_search()
+								.update(saved_search::id::equals(args.id), params),
+						),
+					)
+					.await?;
+
+					invalidate_query!(library, "search.saved.list");
+					invalidate_query!(library, "search.saved.run");
+
+					Ok(())
+				},
+			)
+		})
+		.library_mutation("delete", |t| {
+			t(|_, id: i32, library: Library| async move {
+				let Library { db, sync, .. } = &library;
+
+				let saved_search = db
+					.saved_search()
+					.find_unique(saved_search::id::equals(id))
+					.select(saved_search::select!({ pub_id }))
+					.exec()
+					.await?
+					.ok_or_else(|| saved_search_not_found(id))?;
+
+				sync.write_op(
+					db,
+					sync.shared_delete(sync::saved_search::SyncId {
+						pub_id: saved_search.pub_id,
+					}),
+					db.saved_search().delete(saved_search::id::equals(id)),
+				)
+				.await?;
+
+				invalidate_query!(library, "search.saved.list");
+
+				Ok(())
+			})
+		})
+}
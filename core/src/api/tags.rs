@@ -8,7 +8,7 @@ use uuid::Uuid;
 use crate::{
 	api::locations::{object_with_file_paths, ExplorerContext, ExplorerData, ExplorerItem},
 	invalidate_query,
-	library::Library,
+	library::{ActivityKind, Library},
 	prisma::{object, tag, tag_on_object},
 	sync,
 };
@@ -93,7 +93,9 @@ pub(crate) fn mount() -> RouterBuilder {
 
 				Ok(ExplorerData {
 					context: ExplorerContext::Tag(tag),
+					total_count: items.len(),
 					items,
+					cursor: None,
 				})
 			})
 		})
@@ -184,6 +186,17 @@ pub(crate) fn mount() -> RouterBuilder {
 						.delete(tag_on_object::tag_id_object_id(args.tag_id, args.object_id))
 						.exec()
 						.await?;
+
+					library
+						.record_activity(
+							ActivityKind::TagRemoved,
+							format!(
+								"Removed tag <id={}> from object <id={}>",
+								args.tag_id, args.object_id
+							),
+							Some(json!({ "tag_id": args.tag_id, "object_id": args.object_id })),
+						)
+						.await;
 				} else {
 					library
 						.db
@@ -208,6 +221,7 @@ pub(crate) fn mount() -> RouterBuilder {
 				pub id: i32,
 				pub name: Option<String>,
 				pub color: Option<String>,
+				pub file_versioning: Option<bool>,
 			}
 
 			t(|_, args: TagUpdateArgs, library| async move {
@@ -227,6 +241,7 @@ pub(crate) fn mount() -> RouterBuilder {
 						[
 							args.name.as_ref().map(|v| ("name", json!(v))),
 							args.color.as_ref().map(|v| ("color", json!(v))),
+							args.file_versioning.map(|v| ("file_versioning", json!(v))),
 						]
 						.into_iter()
 						.flatten()
@@ -242,7 +257,14 @@ pub(crate) fn mount() -> RouterBuilder {
 						.collect(),
 						db.tag().update(
 							tag::id::equals(args.id),
-							vec![tag::name::set(args.name), tag::color::set(args.color)],
+							[
+								Some(tag::name::set(args.name)),
+								Some(tag::color::set(args.color)),
+								args.file_versioning.map(tag::file_versioning::set),
+							]
+							.into_iter()
+							.flatten()
+							.collect(),
 						),
 					),
 				)
@@ -255,13 +277,21 @@ pub(crate) fn mount() -> RouterBuilder {
 		})
 		.library_mutation("delete", |t| {
 			t(|_, tag_id: i32, library| async move {
-				library
+				let tag = library
 					.db
 					.tag()
 					.delete(tag::id::equals(tag_id))
 					.exec()
 					.await?;
 
+				library
+					.record_activity(
+						ActivityKind::TagDeleted,
+						format!("Deleted tag '{}'", tag.name.unwrap_or_default()),
+						Some(json!({ "tag_id": tag_id })),
+					)
+					.await;
+
 				invalidate_query!(library, "tags.list");
 
 				Ok(())
@@ -0,0 +1,73 @@
+use crate::node::{LibraryRole, NodeUser};
+
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::RouterBuilder;
+
+pub(crate) fn mount() -> RouterBuilder {
+	<RouterBuilder>::new()
+		.query("me", |t| t(|ctx, _: ()| Ok(ctx.user)))
+		.query("list", |t| {
+			t(|ctx, _: ()| {
+				ctx.authorize_node_admin()?;
+
+				Ok(ctx.users.list())
+			})
+		})
+		.mutation("create", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct CreateUserArgs {
+				pub name: String,
+				#[serde(default)]
+				pub node_admin: bool,
+			}
+
+			#[derive(Serialize, Type)]
+			pub struct CreatedUser {
+				user: NodeUser,
+				/// token is only returned once, it must be given to the user so it can authenticate.
+				token: String,
+			}
+
+			t(|ctx, args: CreateUserArgs| {
+				ctx.authorize_node_admin()?;
+
+				let (user, token) = ctx.users.create(args.name, args.node_admin)?;
+
+				Ok(CreatedUser { user, token })
+			})
+		})
+		.mutation("delete", |t| {
+			t(|ctx, id: Uuid| {
+				ctx.authorize_node_admin()?;
+
+				Ok(ctx.users.delete(id)?)
+			})
+		})
+		.mutation("regenerateToken", |t| {
+			t(|ctx, id: Uuid| {
+				ctx.authorize_node_admin()?;
+
+				Ok(ctx.users.regenerate_token(id)?)
+			})
+		})
+		.mutation("setLibraryRole", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct SetLibraryRoleArgs {
+				pub user_id: Uuid,
+				pub library_id: Uuid,
+				/// role is the new role of the user in the library, `null` revokes its access.
+				pub role: Option<LibraryRole>,
+			}
+
+			t(|ctx, args: SetLibraryRoleArgs| {
+				ctx.authorize(args.library_id, LibraryRole::Admin)?;
+
+				Ok(ctx
+					.users
+					.set_library_role(args.user_id, args.library_id, args.role)?)
+			})
+		})
+}
@@ -0,0 +1,103 @@
+use crate::{api::CoreEvent, util::resources::resource_limiter};
+
+use std::sync::Arc;
+
+use mini_moka::sync::Cache;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use super::super::locations::ExplorerItem;
+
+/// The invalidated queries which can change a directory listing
+const INVALIDATING_QUERIES: [&str; 2] = ["locations.getExplorerData", "locations.list"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ListingKey {
+	pub library_id: Uuid,
+	pub location_id: i32,
+	pub materialized_path: String,
+}
+
+/// EXPLORER_CACHE holds the recent directory listings with their objects, so re-entering a directory doesn't query the database again.
+/// It's bounded by the cache memory of the node and evicts the least recently used listings first.
+static EXPLORER_CACHE: Lazy<Cache<ListingKey, Arc<Vec<ExplorerItem>>>> = Lazy::new(|| {
+	Cache::builder()
+		.weigher(|key: &ListingKey, items: &Arc<Vec<ExplorerItem>>| {
+			(std::mem::size_of::<ListingKey>()
+				+ key.materialized_path.len()
+				+ items.len() * std::mem::size_of::<ExplorerItem>())
+			.try_into()
+			.unwrap_or(u32::MAX)
+		})
+		.max_capacity(resource_limiter().cache_memory_bytes())
+		.build()
+});
+
+pub(crate) fn cached_listing(key: &ListingKey) -> Option<Arc<Vec<ExplorerItem>>> {
+	EXPLORER_CACHE.get(key)
+}
+
+pub(crate) fn cache_listing(key: ListingKey, items: Arc<Vec<ExplorerItem>>) {
+	EXPLORER_CACHE.insert(key, items);
+}
+
+/// invalidate_directories clears the cached listings of the directories matching a predicate, by their location id and materialized path,
+/// in the library the invalidation comes from, as locations ids are only unique within a library, or in every library without one.
+fn invalidate_directories(library_id: Option<Uuid>, matches: impl Fn(i64, &str) -> bool) {
+	let keys = EXPLORER_CACHE
+		.iter()
+		.filter(|entry| {
+			library_id.map_or(true, |library_id| entry.key().library_id == library_id)
+				&& matches(
+					entry.key().location_id as i64,
+					&entry.key().materialized_path,
+				)
+		})
+		.map(|entry| entry.key().clone())
+		.collect::<Vec<_>>();
+
+	for key in keys {
+		EXPLORER_CACHE.invalidate(&key);
+	}
+}
+
+/// spawn_explorer_cache_invalidator clears the cached listings whenever the events which invalidate them on clients are emitted.
+/// Invalidations scoped to a directory only clear its listing, the ones scoped to a path clear the listings under it, the others clear every listing.
+pub(crate) fn spawn_explorer_cache_invalidator(mut event_bus_rx: broadcast::Receiver<CoreEvent>) {
+	tokio::spawn(async move {
+		loop {
+			match event_bus_rx.recv().await {
+				Ok(
+					CoreEvent::InvalidateOperation(op)
+					| CoreEvent::InvalidateOperationDebounced(op),
+				) if INVALIDATING_QUERIES.contains(&op.key()) => {
+					match (
+						op.scope(),
+						op.arg().get("location_id").and_then(Value::as_i64),
+						op.arg().get("path").and_then(Value::as_str),
+					) {
+						(Some(scope), _, _) => {
+							invalidate_directories(op.library_id(), |location_id, path| {
+								scope.contains(location_id, path)
+							})
+						}
+						(None, Some(location_id), Some(path)) => {
+							invalidate_directories(op.library_id(), |key_location_id, key_path| {
+								key_location_id == location_id && key_path == path
+							})
+						}
+						_ => invalidate_directories(op.library_id(), |_, _| true),
+					}
+				}
+				// Listings hold whether their items have a thumbnail
+				Ok(CoreEvent::NewThumbnail { .. }) => EXPLORER_CACHE.invalidate_all(),
+				Ok(_) => {}
+				// Missed events could have invalidated any listing
+				Err(broadcast::error::RecvError::Lagged(_)) => EXPLORER_CACHE.invalidate_all(),
+				Err(broadcast::error::RecvError::Closed) => break,
+			}
+		}
+	});
+}
@@ -0,0 +1,97 @@
+use crate::prisma::{file_path, object, tag_on_object};
+
+use chrono::{DateTime, Utc};
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+
+use super::super::locations::file_path_with_object;
+
+/// ExplorerFilter narrows down the items of a directory listing, every field which is set must match.
+/// Conditions on objects exclude the file paths which have none, like directories.
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Default)]
+pub struct ExplorerFilter {
+	/// kinds are the `ObjectKind`s the objects can be of
+	#[serde(default)]
+	pub kinds: Vec<i32>,
+	/// extensions are matched without their leading dot
+	#[serde(default)]
+	pub extensions: Vec<String>,
+	pub min_size: Option<u64>,
+	pub max_size: Option<u64>,
+	pub created_after: Option<DateTime<Utc>>,
+	pub created_before: Option<DateTime<Utc>>,
+	pub modified_after: Option<DateTime<Utc>>,
+	pub modified_before: Option<DateTime<Utc>>,
+	/// tag_ids are tags of which the objects must have at least one
+	#[serde(default)]
+	pub tag_ids: Vec<i32>,
+	pub favorite: Option<bool>,
+	pub hidden: Option<bool>,
+}
+
+impl ExplorerFilter {
+	/// params compiles the filter into conditions on file paths, except for sizes which are checked by `matches_size`.
+	pub(crate) fn params(&self) -> Vec<file_path::WhereParam> {
+		let mut params = vec![];
+
+		if !self.extensions.is_empty() {
+			params.push(file_path::extension::in_vec(
+				self.extensions
+					.iter()
+					.map(|extension| extension.trim_start_matches('.').to_string())
+					.collect(),
+			));
+		}
+		if let Some(date) = self.created_after {
+			params.push(file_path::date_created::gte(date.into()));
+		}
+		if let Some(date) = self.created_before {
+			params.push(file_path::date_created::lt(date.into()));
+		}
+		if let Some(date) = self.modified_after {
+			params.push(file_path::date_modified::gte(date.into()));
+		}
+		if let Some(date) = self.modified_before {
+			params.push(file_path::date_modified::lt(date.into()));
+		}
+
+		let mut object_params = vec![];
+		if !self.kinds.is_empty() {
+			object_params.push(object::kind::in_vec(self.kinds.clone()));
+		}
+		if !self.tag_ids.is_empty() {
+			object_params.push(object::tags::some(vec![tag_on_object::tag_id::in_vec(
+				self.tag_ids.clone(),
+			)]));
+		}
+		if let Some(favorite) = self.favorite {
+			object_params.push(object::favorite::equals(favorite));
+		}
+		if let Some(hidden) = self.hidden {
+			object_params.push(object::hidden::equals(hidden));
+		}
+		if !object_params.is_empty() {
+			params.push(file_path::object::is(object_params));
+		}
+
+		params
+	}
+
+	/// matches_size checks the size of the object of a file path, which is stored as a string so the database can't compare it.
+	pub(crate) fn matches_size(&self, file_path: &file_path_with_object::Data) -> bool {
+		if self.min_size.is_none() && self.max_size.is_none() {
+			return true;
+		}
+
+		let Some(size) = file_path
+			.object
+			.as_ref()
+			.and_then(|object| object.size_in_bytes.parse::<u64>().ok())
+		else {
+			return false;
+		};
+
+		self.min_size.map_or(true, |min| size >= min)
+			&& self.max_size.map_or(true, |max| size <= max)
+	}
+}
@@ -3,7 +3,9 @@ use crate::api::Router;
 use rspc::{internal::specta::DataType, Type};
 use serde::Serialize;
 use serde_json::Value;
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::time::Instant;
+use uuid::Uuid;
 
 #[cfg(debug_assertions)]
 use std::sync::Mutex;
@@ -13,17 +15,165 @@ use std::sync::Mutex;
 pub(crate) static INVALIDATION_REQUESTS: Mutex<InvalidRequests> =
 	Mutex::new(InvalidRequests::new());
 
+/// Queries which aren't scoped to a library, although libraries invalidate them, so clients of every library must refetch them
+const NODE_QUERIES: &[&str] = &["library.list", "library.templates.list", "nodeState"];
+
+/// PathScope narrows down an invalidation of directory listings to the directories of a location which changed,
+/// so clients don't refetch the listings of the other directories.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Type)]
+pub struct PathScope {
+	pub location_id: i32,
+	/// path_prefix is the materialized path of the changed directory, whose listing and the ones of its subdirectories are invalidated.
+	/// Every listing of the location is invalidated if it's not set.
+	pub path_prefix: Option<String>,
+}
+
+impl PathScope {
+	/// contains tells if the listing of a directory, by its materialized path, is in the scope.
+	pub(crate) fn contains(&self, location_id: i64, path: &str) -> bool {
+		self.location_id as i64 == location_id
+			&& self
+				.path_prefix
+				.as_ref()
+				.map_or(true, |prefix| path.starts_with(prefix.as_str()))
+	}
+}
+
 #[derive(Debug, Clone, Serialize, Type)]
 pub struct InvalidateOperationEvent {
 	/// This fields are intentionally private.
 	key: &'static str,
 	arg: Value,
+	/// library_id is the library the invalidation comes from, `None` for the ones of the node.
+	library_id: Option<Uuid>,
+	/// scope is the part of a location the invalidation is about, `None` if it isn't about the directories of a location.
+	scope: Option<PathScope>,
+	/// all tells to invalidate every query, as some invalidations were missed.
+	all: bool,
 }
 
 impl InvalidateOperationEvent {
 	/// If you are using this function, your doing it wrong.
 	pub fn dangerously_create(key: &'static str, arg: Value) -> Self {
-		Self { key, arg }
+		Self {
+			key,
+			arg,
+			library_id: None,
+			scope: None,
+			all: false,
+		}
+	}
+
+	/// If you are using this function, your doing it wrong.
+	pub fn dangerously_create_for_library(library_id: Uuid, key: &'static str, arg: Value) -> Self {
+		Self {
+			key,
+			arg,
+			library_id: Some(library_id),
+			scope: None,
+			all: false,
+		}
+	}
+
+	/// all invalidates every query of the clients of a library, for when they can't be told which ones changed.
+	pub(crate) fn all(library_id: Option<Uuid>) -> Self {
+		Self {
+			key: "",
+			arg: Value::Null,
+			library_id,
+			scope: None,
+			all: true,
+		}
+	}
+
+	/// with_scope narrows down the invalidation to the listings of the directories of a location under a path.
+	pub(crate) fn with_scope(mut self, scope: PathScope) -> Self {
+		self.scope = Some(scope);
+		self
+	}
+
+	pub(crate) fn scope(&self) -> Option<&PathScope> {
+		self.scope.as_ref()
+	}
+
+	pub(crate) fn key(&self) -> &'static str {
+		self.key
+	}
+
+	pub(crate) fn arg(&self) -> &Value {
+		&self.arg
+	}
+
+	pub(crate) fn library_id(&self) -> Option<Uuid> {
+		self.library_id
+	}
+
+	/// concerns tells if clients of a library must be sent the invalidation, which they are if it comes from their library
+	/// or if it isn't scoped to a library. Clients without a library are only sent the invalidations of the node.
+	pub(crate) fn concerns(&self, library_id: Option<Uuid>) -> bool {
+		match (library_id, self.library_id) {
+			(_, None) => true,
+			(Some(library_id), Some(op_library_id)) if library_id == op_library_id => true,
+			(_, Some(_)) => NODE_QUERIES.contains(&self.key),
+		}
+	}
+
+	/// resource identifies what is invalidated, the query, its argument, its library and its scope, so invalidations of the same resource can be coalesced.
+	fn resource(&self) -> Resource {
+		(
+			self.key,
+			self.arg.to_string(),
+			self.library_id,
+			self.scope.clone(),
+		)
+	}
+}
+
+type Resource = (&'static str, String, Option<Uuid>, Option<PathScope>);
+
+/// How long debounced invalidations of a resource are collected before they are sent as one
+const DEBOUNCE_WINDOW: Duration = Duration::from_millis(100);
+
+/// InvalidationCoalescer collapses the debounced invalidations of each resource into a single one,
+/// which is sent once [`DEBOUNCE_WINDOW`] has passed since the first of them.
+/// Jobs emitting an invalidation per processed file cause a single refetch per window instead of one per file.
+#[derive(Default)]
+pub(crate) struct InvalidationCoalescer {
+	pending: HashMap<Resource, (Instant, InvalidateOperationEvent)>,
+}
+
+impl InvalidationCoalescer {
+	/// push adds a debounced invalidation, which is dropped if the same resource is already pending.
+	pub(crate) fn push(&mut self, op: InvalidateOperationEvent) {
+		self.pending
+			.entry(op.resource())
+			.or_insert_with(|| (Instant::now() + DEBOUNCE_WINDOW, op));
+	}
+
+	/// sent drops the pending invalidation of a resource which has just been invalidated without debouncing.
+	pub(crate) fn sent(&mut self, op: &InvalidateOperationEvent) {
+		self.pending.remove(&op.resource());
+	}
+
+	/// next_deadline is when the next pending invalidation is due.
+	pub(crate) fn next_deadline(&self) -> Option<Instant> {
+		self.pending.values().map(|(deadline, _)| *deadline).min()
+	}
+
+	/// take_due removes the pending invalidations which are due.
+	pub(crate) fn take_due(&mut self) -> Vec<InvalidateOperationEvent> {
+		let now = Instant::now();
+		let due = self
+			.pending
+			.iter()
+			.filter(|(_, (deadline, _))| *deadline <= now)
+			.map(|(resource, _)| resource.clone())
+			.collect::<Vec<_>>();
+
+		due.into_iter()
+			.filter_map(|resource| self.pending.remove(&resource))
+			.map(|(_, op)| op)
+			.collect()
 	}
 }
 
@@ -112,7 +262,7 @@ macro_rules! invalidate_query {
 
 		// The error are ignored here because they aren't mission critical. If they fail the UI might be outdated for a bit.
 		library.emit(crate::api::CoreEvent::InvalidateOperation(
-			crate::api::utils::InvalidateOperationEvent::dangerously_create($key, serde_json::Value::Null)
+			crate::api::utils::InvalidateOperationEvent::dangerously_create_for_library(library.id, $key, serde_json::Value::Null)
 		))
 	}};
 	($library:expr, $key:literal: $input_ty:ty, $input:expr $(,)?) => {{
@@ -142,7 +292,7 @@ macro_rules! invalidate_query {
 		let _ = serde_json::to_value($input)
 			.map(|v|
 				library.emit(crate::api::CoreEvent::InvalidateOperation(
-					crate::api::utils::InvalidateOperationEvent::dangerously_create($key, v),
+					crate::api::utils::InvalidateOperationEvent::dangerously_create_for_library(library.id, $key, v),
 				))
 			)
 			.map_err(|_| {
@@ -150,3 +300,37 @@ macro_rules! invalidate_query {
 			});
 	}};
 }
+
+/// `invalidate_query_debounced` is the same as [`invalidate_query!`] for queries without arguments, but the invalidations
+/// of the same query are coalesced by the `invalidateQuery` subscription. It's meant for jobs invalidating a query for every file they process.
+#[macro_export]
+#[allow(clippy::crate_in_macro_def)]
+macro_rules! invalidate_query_debounced {
+	($library:expr, $key:literal) => {{
+		let library: &crate::library::Library = &$library; // Assert the library is the correct type
+
+		#[cfg(debug_assertions)]
+		{
+			#[ctor::ctor]
+			fn invalidate() {
+				crate::api::utils::INVALIDATION_REQUESTS
+					.lock()
+					.unwrap()
+					.queries
+					.push(crate::api::utils::InvalidationRequest {
+						key: $key,
+						input_ty: None,
+						macro_src: concat!(file!(), ":", line!()),
+					})
+			}
+		}
+
+		library.emit(crate::api::CoreEvent::InvalidateOperationDebounced(
+			crate::api::utils::InvalidateOperationEvent::dangerously_create_for_library(
+				library.id,
+				$key,
+				serde_json::Value::Null,
+			),
+		))
+	}};
+}
@@ -1,6 +1,6 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
-use futures::Stream;
+use futures::{stream, Stream, StreamExt};
 use rspc::{
 	internal::{
 		specta, BuiltProcedureBuilder, MiddlewareBuilderLike, RequestResult,
@@ -9,9 +9,10 @@ use rspc::{
 	ErrorCode, Type,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{info_span, Instrument};
 use uuid::Uuid;
 
-use crate::{api::Ctx, library::Library};
+use crate::{api::Ctx, library::Library, node::LibraryRole, util::metrics::METRICS};
 
 /// Can wrap a query argument to require it to contain a `library_id` and provide helpers for working with libraries.
 #[derive(Clone, Serialize, Deserialize, Type)]
@@ -21,6 +22,7 @@ pub(crate) struct LibraryArgs<T> {
 }
 
 // WARNING: This is system is using internal API's which means it will break between rspc release. I would avoid copying it unless you understand the cost of maintaining it!
+/// Library queries require the user making the request to have a role in the library, and library mutations the `ReadWrite` role.
 pub trait LibraryRequest {
 	fn library_query<TUnbuiltResolver, TUnbuiltResult, TUnbuiltResultMarker, TBuiltResolver, TArg>(
 		self,
@@ -89,7 +91,11 @@ where
 
 			t(move |ctx, arg: LibraryArgs<TArg>| {
 				let resolver = resolver.clone();
+				let span =
+					info_span!("library_request", procedure = key, library_id = %arg.library_id);
 				async move {
+					ctx.authorize(arg.library_id, LibraryRole::ReadOnly)?;
+
 					let library = ctx
 						.library_manager
 						.get_ctx(arg.library_id)
@@ -102,11 +108,16 @@ where
 							)
 						})?;
 
-					Ok(resolver(ctx, arg.arg, library)
+					let start = Instant::now();
+					let result = resolver(ctx, arg.arg, library)
 						.into_request_future()?
 						.exec()
-						.await?)
+						.await;
+					METRICS.record_request_duration(key, start.elapsed());
+
+					Ok(result?)
 				}
+				.instrument(span)
 			})
 		})
 	}
@@ -135,7 +146,11 @@ where
 
 			t(move |ctx, arg: LibraryArgs<TArg>| {
 				let resolver = resolver.clone();
+				let span =
+					info_span!("library_request", procedure = key, library_id = %arg.library_id);
 				async move {
+					ctx.authorize(arg.library_id, LibraryRole::ReadWrite)?;
+
 					let library = ctx
 						.library_manager
 						.get_ctx(arg.library_id)
@@ -148,11 +163,16 @@ where
 							)
 						})?;
 
-					Ok(resolver(ctx, arg.arg, library)
+					let start = Instant::now();
+					let result = resolver(ctx, arg.arg, library)
 						.into_request_future()?
 						.exec()
-						.await?)
+						.await;
+					METRICS.record_request_duration(key, start.elapsed());
+
+					Ok(result?)
 				}
+				.instrument(span)
 			})
 		})
 	}
@@ -172,19 +192,12 @@ where
 			let resolver = Arc::new(builder(UnbuiltProcedureBuilder::from_builder(&t)).resolver);
 
 			t(move |ctx, arg: LibraryArgs<TArg>| {
-				// TODO(@Oscar): Upstream rspc work to allow this to work
-				// let library = ctx
-				// 	.library_manager
-				// 	.get_ctx(arg.library_id)
-				// 	.await
-				// 	.ok_or_else(|| {
-				// 		rspc::Error::new(
-				// 			ErrorCode::BadRequest,
-				// 			"You must specify a valid library to use this operation.".to_string(),
-				// 		)
-				// 	})?;
-
-				resolver(ctx, arg.arg, arg.library_id)
+				// Subscriptions can't fail, so users without a role in the library get a stream which ends right away,
+				// and the resolver isn't even called for them
+				// TODO(@Oscar): Upstream rspc work to allow subscriptions to return an error instead
+				let authorized = ctx.authorize(arg.library_id, LibraryRole::ReadOnly).is_ok();
+
+				stream::iter(authorized.then(|| resolver(ctx, arg.arg, arg.library_id))).flatten()
 			})
 		})
 	}
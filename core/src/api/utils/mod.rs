@@ -2,9 +2,13 @@ use std::path::Path;
 
 use tokio::{fs, io};
 
+mod explorer_cache;
+mod explorer_filter;
 mod invalidate;
 mod library;
 
+pub(crate) use explorer_cache::*;
+pub use explorer_filter::*;
 pub use invalidate::*;
 pub use library::*;
 
@@ -1,7 +1,107 @@
-use crate::volume::get_volumes;
+use crate::{
+	invalidate_query,
+	library::Library,
+	location::{scan_location, VolumeLocationCreateArgs},
+	object::disk_usage::{disk_usage_breakdown, DiskUsageTarget},
+	prisma::location,
+	volume::{benchmark_volume, get_volumes, list_benchmarks, probe_throughput, Volume},
+};
 
-use super::RouterBuilder;
+use std::path::{Path, PathBuf};
+
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+
+use super::{utils::LibraryRequest, RouterBuilder};
+
+/// LocationVolume is a volume which backs at least one location of the library.
+#[derive(Serialize, Type)]
+pub struct LocationVolume {
+	pub volume: Volume,
+	pub location_ids: Vec<i32>,
+}
 
 pub(crate) fn mount() -> RouterBuilder {
-	RouterBuilder::new().query("list", |t| t(|_, _: ()| Ok(get_volumes()?)))
+	RouterBuilder::new()
+		.query("list", |t| t(|_, _: ()| Ok(get_volumes()?)))
+		.mutation("probeThroughput", |t| {
+			t(|ctx, path: PathBuf| async move {
+				ctx.authorize_node_admin()?;
+
+				Ok(probe_throughput(path).await?)
+			})
+		})
+		.library_query("listForLocations", |t| {
+			t(|_, _: (), library: Library| async move {
+				let locations = library
+					.db
+					.location()
+					.find_many(vec![location::node_id::equals(library.node_local_id)])
+					.exec()
+					.await?;
+
+				let mut volumes = get_volumes()?
+					.into_iter()
+					.map(|volume| LocationVolume {
+						volume,
+						location_ids: vec![],
+					})
+					.collect::<Vec<_>>();
+
+				for location in locations {
+					// A location is backed by the volume with the longest mount point containing its path
+					if let Some(location_volume) = volumes
+						.iter_mut()
+						.filter(|location_volume| {
+							Path::new(&location.path)
+								.starts_with(&location_volume.volume.mount_point)
+						})
+						.max_by_key(|location_volume| location_volume.volume.mount_point.len())
+					{
+						location_volume.location_ids.push(location.id);
+					}
+				}
+
+				volumes.retain(|location_volume| !location_volume.location_ids.is_empty());
+
+				Ok(volumes)
+			})
+		})
+		.library_query("diskUsage", |t| {
+			#[derive(Type, Deserialize)]
+			pub struct DiskUsageArgs {
+				pub target: DiskUsageTarget,
+				pub path: Option<String>,
+			}
+
+			t(|_, args: DiskUsageArgs, library: Library| async move {
+				Ok(disk_usage_breakdown(&library.db, &args.target, args.path).await?)
+			})
+		})
+		.library_mutation("createLocation", |t| {
+			t(
+				|ctx, args: VolumeLocationCreateArgs, library: Library| async move {
+					// A location gives access to any directory of the volume
+					ctx.authorize_node_admin()?;
+
+					let location = args.create(&library).await?;
+					scan_location(&library, location).await?;
+					Ok(())
+				},
+			)
+		})
+		.library_mutation("benchmark", |t| {
+			t(|ctx, mount_point: String, library: Library| async move {
+				ctx.authorize_node_admin()?;
+
+				let benchmark = benchmark_volume(&library, mount_point).await?;
+
+				invalidate_query!(library, "volumes.benchmarks");
+
+				Ok(benchmark)
+			})
+		})
+		.library_query("benchmarks", |t| {
+			t(|_, _: (), library: Library| async move { Ok(list_benchmarks(&library).await?) })
+		})
 }
@@ -1,4 +1,11 @@
-use crate::{prisma::file_path, Node};
+use crate::{
+	api::Ctx,
+	node::{is_valid_variant_name, LibraryRole, NodeUser, ThumbnailFormat},
+	object::preview::{thumbnail_path, THUMBNAIL_CACHE_DIR_NAME},
+	prisma::file_path,
+	util::resources::resource_limiter,
+	Node,
+};
 
 use std::{
 	cmp::min,
@@ -28,8 +35,19 @@ use uuid::Uuid;
 // The main advantage of this LRU Cache is for video files. Video files are fetch in multiple chunks and the cache prevents a DB lookup on every chunk reducing the request time from 15-25ms to 1-10ms.
 type MetadataCacheKey = (Uuid, i32, i32);
 type NameAndExtension = (PathBuf, String);
-static FILE_METADATA_CACHE: Lazy<Cache<MetadataCacheKey, NameAndExtension>> =
-	Lazy::new(|| Cache::new(100));
+// Its memory is capped by the cache memory of the node's resource limits, entries are weighted by their approximate size.
+static FILE_METADATA_CACHE: Lazy<Cache<MetadataCacheKey, NameAndExtension>> = Lazy::new(|| {
+	Cache::builder()
+		.weigher(|_, (path, extension): &NameAndExtension| {
+			(std::mem::size_of::<(MetadataCacheKey, NameAndExtension)>()
+				+ path.as_os_str().len()
+				+ extension.len())
+			.try_into()
+			.unwrap_or(u32::MAX)
+		})
+		.max_capacity(resource_limiter().cache_memory_bytes())
+		.build()
+});
 
 // TODO: We should listen to events when deleting or moving a location and evict the cache accordingly.
 // TODO: Probs use this cache in rspc queries too!
@@ -43,46 +61,127 @@ async fn handler(node: Arc<Node>, req: Request) -> Result<Response<Vec<u8>>, Han
 		.split('/')
 		.collect::<Vec<_>>();
 
+	// Requests made with a user's token can only read the libraries that user has a role in
+	let ctx = node.get_user_request_context(request_user(&node, &req));
+
 	match path.first() {
-		Some(&"thumbnail") => handle_thumbnail(&node, &path).await,
-		Some(&"file") => handle_file(&node, &path, &req).await,
+		Some(&"thumbnail") => handle_thumbnail(&node, &ctx, &path).await,
+		Some(&"file") => handle_file(&node, &ctx, &path, &req).await,
+		Some(&"stream") => handle_stream(&node, &ctx, &path, &req).await,
 		_ => Err(HandleCustomUriError::BadRequest("Invalid operation!")),
 	}
 }
 
+/// request_user returns the user owning the token of a request, read from the `Authorization: Bearer <token>` header
+/// or from the `token` query parameter as the webview can't set headers on media elements.
+/// Requests without a user token have full access, which only the node's auth token or a local client can do.
+fn request_user(node: &Node, req: &Request) -> Option<NodeUser> {
+	let header_token = req
+		.headers()
+		.get("authorization")
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.strip_prefix("Bearer "));
+
+	let query_token = req.uri().query().and_then(|query| {
+		query
+			.split('&')
+			.find_map(|pair| pair.strip_prefix("token="))
+	});
+
+	header_token
+		.or(query_token)
+		.and_then(|token| node.authenticate(token))
+}
+
+/// authorize_thumbnail fails unless the user making the request can read a library holding a file with the given cas_id,
+/// as thumbnails are shared by every library of the node.
+async fn authorize_thumbnail(
+	node: &Node,
+	ctx: &Ctx,
+	cas_id: &str,
+) -> Result<(), HandleCustomUriError> {
+	if ctx.is_node_admin() {
+		return Ok(());
+	}
+
+	for library in node.library_manager.get_all_libraries().await {
+		if ctx.authorize(library.id, LibraryRole::ReadOnly).is_err() {
+			continue;
+		}
+
+		if library
+			.db
+			.file_path()
+			.find_first(vec![file_path::cas_id::equals(Some(cas_id.to_string()))])
+			.exec()
+			.await?
+			.is_some()
+		{
+			return Ok(());
+		}
+	}
+
+	Err(HandleCustomUriError::Forbidden)
+}
+
+/// handle_thumbnail serves `<cas_id>` as the default thumbnail and `<variant>/<cas_id>` as the thumbnail of a variant.
 async fn handle_thumbnail(
 	node: &Node,
+	ctx: &Ctx,
 	path: &[&str],
 ) -> Result<Response<Vec<u8>>, HandleCustomUriError> {
-	let file_cas_id = path
-		.get(1)
-		.ok_or_else(|| HandleCustomUriError::BadRequest("Invalid number of parameters!"))?;
-	let filename = node
-		.config
-		.data_directory()
-		.join("thumbnails")
-		.join(file_cas_id)
-		.with_extension("webp");
-
-	let buf = fs::read(&filename).await.map_err(|err| {
-		if err.kind() == io::ErrorKind::NotFound {
-			HandleCustomUriError::NotFound("file")
-		} else {
-			err.into()
+	let (variant, file_cas_id) = match path {
+		[_, file_cas_id] => (None, *file_cas_id),
+		[_, variant, file_cas_id] if is_valid_variant_name(variant) => {
+			(Some(*variant), *file_cas_id)
+		}
+		[_, _, _] => {
+			return Err(HandleCustomUriError::BadRequest(
+				"Invalid thumbnail variant!",
+			))
 		}
-	})?;
+		_ => {
+			return Err(HandleCustomUriError::BadRequest(
+				"Invalid number of parameters!",
+			))
+		}
+	};
 
-	Ok(Response::builder()
-		.header("Content-Type", "image/webp")
-		.status(StatusCode::OK)
-		.body(buf)?)
+	authorize_thumbnail(node, ctx, file_cas_id).await?;
+
+	let thumbnail_dir = node.config.data_directory().join(THUMBNAIL_CACHE_DIR_NAME);
+
+	// Thumbnails in the configured format are preferred, but the ones not converted yet are served too
+	let configured_format = node.config.get().await.thumbnail.format;
+	let formats = std::iter::once(configured_format).chain(
+		ThumbnailFormat::ALL
+			.into_iter()
+			.filter(|format| *format != configured_format),
+	);
+
+	for format in formats {
+		match fs::read(thumbnail_path(&thumbnail_dir, variant, file_cas_id, format)).await {
+			Ok(buf) => {
+				return Ok(Response::builder()
+					.header("Content-Type", format.mime_type())
+					.status(StatusCode::OK)
+					.body(buf)?)
+			}
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+			Err(e) => return Err(e.into()),
+		}
+	}
+
+	Err(HandleCustomUriError::NotFound("file"))
 }
 
-async fn handle_file(
+/// file_path_from_uri resolves the `<library_id>/<location_id>/<file_path_id>` parameters of a request
+/// to the path of the file on disk and its extension, if the user making the request can read the library.
+async fn file_path_from_uri(
 	node: &Node,
+	ctx: &Ctx,
 	path: &[&str],
-	req: &Request,
-) -> Result<Response<Vec<u8>>, HandleCustomUriError> {
+) -> Result<NameAndExtension, HandleCustomUriError> {
 	let library_id = path
 		.get(1)
 		.and_then(|id| Uuid::from_str(id).ok())
@@ -90,6 +189,9 @@ async fn handle_file(
 			HandleCustomUriError::BadRequest("Invalid number of parameters. Missing library_id!")
 		})?;
 
+	ctx.authorize(library_id, LibraryRole::ReadOnly)
+		.map_err(|_| HandleCustomUriError::Forbidden)?;
+
 	let location_id = path
 		.get(2)
 		.and_then(|id| id.parse::<i32>().ok())
@@ -106,32 +208,40 @@ async fn handle_file(
 
 	let lru_cache_key = (library_id, location_id, file_path_id);
 
-	let (file_path_materialized_path, extension) =
-		if let Some(entry) = FILE_METADATA_CACHE.get(&lru_cache_key) {
-			entry
-		} else {
-			let library = node
-				.library_manager
-				.get_ctx(library_id)
-				.await
-				.ok_or_else(|| HandleCustomUriError::NotFound("library"))?;
-			let file_path = library
-				.db
-				.file_path()
-				.find_unique(file_path::location_id_id(location_id, file_path_id))
-				.include(file_path::include!({ location }))
-				.exec()
-				.await?
-				.ok_or_else(|| HandleCustomUriError::NotFound("object"))?;
-
-			let lru_entry = (
-				Path::new(&file_path.location.path).join(&file_path.materialized_path),
-				file_path.extension,
-			);
-			FILE_METADATA_CACHE.insert(lru_cache_key, lru_entry.clone());
-
-			lru_entry
-		};
+	if let Some(entry) = FILE_METADATA_CACHE.get(&lru_cache_key) {
+		return Ok(entry);
+	}
+
+	let library = node
+		.library_manager
+		.get_ctx(library_id)
+		.await
+		.ok_or_else(|| HandleCustomUriError::NotFound("library"))?;
+	let file_path = library
+		.db
+		.file_path()
+		.find_unique(file_path::location_id_id(location_id, file_path_id))
+		.include(file_path::include!({ location }))
+		.exec()
+		.await?
+		.ok_or_else(|| HandleCustomUriError::NotFound("object"))?;
+
+	let lru_entry = (
+		Path::new(&file_path.location.path).join(&file_path.materialized_path),
+		file_path.extension,
+	);
+	FILE_METADATA_CACHE.insert(lru_cache_key, lru_entry.clone());
+
+	Ok(lru_entry)
+}
+
+async fn handle_file(
+	node: &Node,
+	ctx: &Ctx,
+	path: &[&str],
+	req: &Request,
+) -> Result<Response<Vec<u8>>, HandleCustomUriError> {
+	let (file_path_materialized_path, extension) = file_path_from_uri(node, ctx, path).await?;
 
 	let mut file = File::open(file_path_materialized_path)
 		.await
@@ -152,6 +262,13 @@ async fn handle_file(
 		"mkv" => ("video/x-matroska", true),
 		"avi" => ("video/x-msvideo", true),
 		"mov" => ("video/quicktime", true),
+		"mp3" => ("audio/mpeg", true),
+		"m4a" => ("audio/mp4", true),
+		"aac" => ("audio/aac", true),
+		"flac" => ("audio/flac", true),
+		"ogg" => ("audio/ogg", true),
+		"opus" => ("audio/ogg", true),
+		"wav" => ("audio/wav", true),
 		"png" => ("image/png", false),
 		"jpg" => ("image/jpeg", false),
 		"jpeg" => ("image/jpeg", false),
@@ -238,6 +355,96 @@ async fn handle_file(
 	}
 }
 
+/// handle_stream serves a video or audio file as HLS, transcoding the streams the client can't play.
+/// `stream/<library_id>/<location_id>/<file_path_id>/index.m3u8?video=h264,hevc&audio=aac,opus` opens a session and
+/// returns its playlist, which points to the segments served by `stream/session/<session_id>/<segment>`.
+/// A `token` query parameter is passed on to the segments of the playlist.
+#[cfg(feature = "ffmpeg")]
+async fn handle_stream(
+	node: &Node,
+	ctx: &Ctx,
+	path: &[&str],
+	req: &Request,
+) -> Result<Response<Vec<u8>>, HandleCustomUriError> {
+	use crate::object::preview::transcode::{get_session, open_session, SupportedCodecs};
+
+	if path.get(1) == Some(&"session") {
+		let session = path
+			.get(2)
+			.and_then(|id| Uuid::from_str(id).ok())
+			.ok_or_else(|| {
+				HandleCustomUriError::BadRequest(
+					"Invalid number of parameters. Missing session_id!",
+				)
+			})?;
+		let session = get_session(session)
+			.await
+			.ok_or_else(|| HandleCustomUriError::NotFound("session"))?;
+
+		let (content_type, buf) = match path.get(3) {
+			Some(&"init.mp4") => ("video/mp4", session.init_segment().await?),
+			Some(segment) => {
+				let index = segment
+					.strip_suffix(".m4s")
+					.and_then(|index| index.parse::<u32>().ok())
+					.ok_or_else(|| HandleCustomUriError::BadRequest("Invalid segment!"))?;
+
+				("video/iso.segment", session.segment(index).await?)
+			}
+			None => {
+				return Err(HandleCustomUriError::BadRequest(
+					"Invalid number of parameters. Missing segment!",
+				))
+			}
+		};
+
+		return Ok(Response::builder()
+			.header("Content-Type", content_type)
+			.status(StatusCode::OK)
+			.body(buf)?);
+	}
+
+	let (source, _) = file_path_from_uri(node, ctx, path).await?;
+
+	let mut codecs = SupportedCodecs::default();
+	let mut token = None;
+	for (key, value) in req
+		.uri()
+		.query()
+		.unwrap_or_default()
+		.split('&')
+		.filter_map(|pair| pair.split_once('='))
+	{
+		let names = value.split(',').map(str::to_string).collect();
+		match key {
+			"video" => codecs.video = names,
+			"audio" => codecs.audio = names,
+			// Players request segments without the headers of the playlist, so they carry its token instead
+			"token" => token = Some(value),
+			_ => {}
+		}
+	}
+
+	let session = open_session(node.config.data_directory(), source, codecs).await?;
+
+	Ok(Response::builder()
+		.header("Content-Type", "application/vnd.apple.mpegurl")
+		.status(StatusCode::OK)
+		.body(session.playlist(token).into_bytes())?)
+}
+
+#[cfg(not(feature = "ffmpeg"))]
+async fn handle_stream(
+	_node: &Node,
+	_ctx: &Ctx,
+	_path: &[&str],
+	_req: &Request,
+) -> Result<Response<Vec<u8>>, HandleCustomUriError> {
+	Err(HandleCustomUriError::BadRequest(
+		"Streaming needs Spacedrive to be built with FFmpeg!",
+	))
+}
+
 pub fn create_custom_uri_endpoint(node: Arc<Node>) -> Endpoint<impl HttpEndpoint> {
 	GenericEndpoint::new("/*any", [Method::GET, Method::POST], move |req: Request| {
 		let node = node.clone();
@@ -257,6 +464,11 @@ pub enum HandleCustomUriError {
 	BadRequest(&'static str),
 	#[error("resource '{0}' not found")]
 	NotFound(&'static str),
+	#[error("forbidden")]
+	Forbidden,
+	#[cfg(feature = "ffmpeg")]
+	#[error("transcode error: {0}")]
+	Transcode(#[from] crate::object::preview::transcode::TranscodeError),
 }
 
 impl From<HandleCustomUriError> for Response<Vec<u8>> {
@@ -293,6 +505,28 @@ impl From<HandleCustomUriError> for Response<Vec<u8>> {
 					.as_bytes()
 					.to_vec(),
 			),
+			HandleCustomUriError::Forbidden => builder
+				.status(StatusCode::FORBIDDEN)
+				.body(b"You don't have access to this library".to_vec()),
+			#[cfg(feature = "ffmpeg")]
+			HandleCustomUriError::Transcode(err) => {
+				use crate::object::preview::transcode::TranscodeError;
+
+				match err {
+					TranscodeError::SegmentNotFound(_) => builder
+						.status(StatusCode::NOT_FOUND)
+						.body(err.to_string().into_bytes()),
+					TranscodeError::FfmpegNotFound => builder
+						.status(StatusCode::NOT_IMPLEMENTED)
+						.body(err.to_string().into_bytes()),
+					_ => {
+						error!("Transcode error: {}", err);
+						builder
+							.status(StatusCode::INTERNAL_SERVER_ERROR)
+							.body(b"Internal Server Error".to_vec())
+					}
+				}
+			}
 		})
 		// SAFETY: This unwrap is ok as we have an hardcoded the response builders.
 		.expect("internal error building hardcoded HTTP error response")
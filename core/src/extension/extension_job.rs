@@ -0,0 +1,185 @@
+use crate::{
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::Library,
+	location::file_path_helper::file_path_for_extension,
+	prisma::{file_path, location},
+	util::resources::resource_limiter,
+};
+
+use std::{collections::VecDeque, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use super::{StepFile, StepHandler, StepHandlerKind};
+
+pub const EXTENSION_JOB_NAME: &str = "extension";
+
+/// ExtensionJob runs a step handler registered by an extension on every file of a location it handles.
+pub struct ExtensionJob {}
+
+#[derive(Serialize, Deserialize, Debug, Hash)]
+pub struct ExtensionJobInit {
+	pub location_id: i32,
+	pub extension: String,
+	pub handler: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExtensionJobState {
+	root_path: PathBuf,
+	handler: StepHandler,
+	results: Vec<ExtensionStepResult>,
+}
+
+/// ExtensionStepResult is what the step handler returned for a file.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExtensionStepResult {
+	file_path_id: i32,
+	result: Option<Value>,
+	error: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ExtensionJob {
+	type Init = ExtensionJobInit;
+	type Data = ExtensionJobState;
+	type Step = file_path_for_extension::Data;
+
+	fn name(&self) -> &'static str {
+		EXTENSION_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let Library { db, .. } = &ctx.library;
+
+		let handler = ctx
+			.library
+			.extensions()
+			.step_handler(&state.init.extension, &state.init.handler)?;
+
+		let mut params = vec![
+			file_path::location_id::equals(state.init.location_id),
+			file_path::is_dir::equals(false),
+			file_path::in_archive::equals(false),
+		];
+		if !handler.extensions.is_empty() {
+			params.push(file_path::extension::in_vec(
+				handler
+					.extensions
+					.iter()
+					.map(|extension| extension.to_lowercase())
+					.collect(),
+			));
+		}
+
+		state.steps = db
+			.file_path()
+			.find_many(params)
+			.select(file_path_for_extension::select())
+			.exec()
+			.await?
+			.into_iter()
+			.collect::<VecDeque<_>>();
+
+		let location = db
+			.location()
+			.find_unique(location::id::equals(state.init.location_id))
+			.exec()
+			.await?
+			.ok_or(JobError::MissingData {
+				value: format!("location {}", state.init.location_id),
+			})?;
+
+		state.data = Some(ExtensionJobState {
+			root_path: location.path.into(),
+			handler,
+			results: vec![],
+		});
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let file_path = &state.steps[0];
+		let data = state.data.as_mut().expect("fatal: missing job state");
+
+		let file = StepFile {
+			path: data.root_path.join(&file_path.materialized_path),
+			materialized_path: file_path.materialized_path.clone(),
+			name: file_path.name.clone(),
+			extension: file_path.extension.clone(),
+		};
+
+		let result = {
+			let _permit = resource_limiter().open_file().await;
+			ctx.library
+				.extensions()
+				.run_step(&state.init.extension, &data.handler, file)
+				.await
+		};
+
+		// A file the extension fails on doesn't stop the others from being handled
+		data.results.push(match result {
+			Ok(result) => ExtensionStepResult {
+				file_path_id: file_path.id,
+				result: Some(result),
+				error: None,
+			},
+			Err(e) => {
+				warn!(
+					"Extension '{}' failed on file path {}: {e}",
+					state.init.extension, file_path.id
+				);
+				ExtensionStepResult {
+					file_path_id: file_path.id,
+					result: None,
+					error: Some(e.to_string()),
+				}
+			}
+		});
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!(
+			"finalizing extension job of '{}' at {}: {} files",
+			state.init.extension,
+			data.root_path.display(),
+			data.results.len()
+		);
+
+		let output = match data.handler.kind {
+			StepHandlerKind::MetadataExtractor => serde_json::to_value(&data.results)?,
+			StepHandlerKind::Exporter => {
+				ctx.library
+					.extensions()
+					.finish_export(
+						&state.init.extension,
+						&data.handler,
+						serde_json::to_value(&data.results)?,
+					)
+					.await?
+			}
+		};
+
+		Ok(Some(json!({ "init": state.init, "output": output })))
+	}
+}
@@ -0,0 +1,326 @@
+use std::{
+	collections::{BTreeSet, HashMap},
+	fs::{self, File},
+	io::{self, BufReader, Write},
+	path::{Path, PathBuf},
+	sync::{Arc, RwLock},
+};
+
+use rspc::Type;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tracing::{error, info};
+#[cfg(feature = "wasm-extensions")]
+use wasmtime::{Config, Engine, Module};
+
+#[cfg(feature = "wasm-extensions")]
+use super::sandbox::{run_module, HostState};
+use super::{
+	Capability, ExtensionError, ExtensionManifest, ProcedureKind, StepHandler, StepHandlerKind,
+};
+
+/// EXTENSIONS_DIR_NAME is the name of the directory in the data directory which holds a directory for every extension.
+pub const EXTENSIONS_DIR_NAME: &str = "extensions";
+/// EXTENSIONS_CONFIG_NAME is the name of the file which stores the capabilities granted to every extension.
+pub const EXTENSIONS_CONFIG_NAME: &str = "extensions.sdconfig";
+const MANIFEST_NAME: &str = "manifest.json";
+#[cfg(feature = "wasm-extensions")]
+const MODULE_NAME: &str = "extension.wasm";
+
+/// ExtensionInfo is an extension loaded by the node and the capabilities it has been granted.
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct ExtensionInfo {
+	pub manifest: ExtensionManifest,
+	pub granted: BTreeSet<Capability>,
+	/// enabled is whether all the capabilities the extension needs have been granted.
+	pub enabled: bool,
+}
+
+/// StepFile is the file a step handler is called with.
+#[derive(Debug, Clone, Serialize)]
+pub struct StepFile {
+	pub path: PathBuf,
+	pub materialized_path: String,
+	pub name: String,
+	pub extension: String,
+}
+
+struct LoadedExtension {
+	manifest: ExtensionManifest,
+	#[cfg(feature = "wasm-extensions")]
+	module: Module,
+}
+
+/// ExtensionHost loads the WASM extensions of the node and runs them in a sandbox.
+///
+/// An extension is a directory in [`EXTENSIONS_DIR_NAME`] holding a `manifest.json` and an `extension.wasm` module.
+/// The module must export its `memory`, `sd_alloc(len: i32) -> i32` to allocate the JSON request of a call,
+/// and `sd_call(ptr: i32, len: i32) -> i64` which handles the request and returns the pointer and length of its
+/// JSON response packed as `ptr << 32 | len`.
+///
+/// Without the `wasm-extensions` feature no extension is loaded, so the node still runs without the WASM runtime.
+pub struct ExtensionHost {
+	#[cfg(feature = "wasm-extensions")]
+	engine: Engine,
+	extensions: RwLock<HashMap<String, LoadedExtension>>,
+	grants: RwLock<HashMap<String, BTreeSet<Capability>>>,
+	data_path: PathBuf,
+}
+
+impl ExtensionHost {
+	/// new will load the extensions stored in the given data directory.
+	pub(crate) fn new(data_path: PathBuf) -> Result<Arc<Self>, ExtensionError> {
+		#[cfg(feature = "wasm-extensions")]
+		let engine = Engine::new(Config::new().consume_fuel(true))?;
+
+		let grants = match File::open(data_path.join(EXTENSIONS_CONFIG_NAME)) {
+			Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+			Err(e) => return Err(e.into()),
+		};
+
+		let host = Arc::new(Self {
+			#[cfg(feature = "wasm-extensions")]
+			engine,
+			extensions: RwLock::new(HashMap::new()),
+			grants: RwLock::new(grants),
+			data_path,
+		});
+		host.reload()?;
+
+		Ok(host)
+	}
+
+	/// reload loads the extensions from the filesystem again, so extensions can be installed without a restart.
+	/// Extensions which fail to load are skipped.
+	pub fn reload(&self) -> Result<(), ExtensionError> {
+		let extensions_dir = self.data_path.join(EXTENSIONS_DIR_NAME);
+		fs::create_dir_all(&extensions_dir)?;
+
+		let mut extensions = HashMap::new();
+		for entry in fs::read_dir(extensions_dir)? {
+			let path = entry?.path();
+			if !path.is_dir() {
+				continue;
+			}
+
+			match self.load_extension(&path) {
+				Ok(extension) => {
+					info!(
+						"Loaded extension '{}' v{}",
+						extension.manifest.name, extension.manifest.version
+					);
+					extensions.insert(extension.manifest.name.clone(), extension);
+				}
+				Err(e) => error!("Failed to load extension at {}: {e:#?}", path.display()),
+			}
+		}
+
+		*self.extensions.write().unwrap_or_else(|e| e.into_inner()) = extensions;
+
+		Ok(())
+	}
+
+	#[cfg(feature = "wasm-extensions")]
+	fn load_extension(&self, path: &Path) -> Result<LoadedExtension, ExtensionError> {
+		let manifest =
+			serde_json::from_reader(BufReader::new(File::open(path.join(MANIFEST_NAME))?))?;
+		let module = Module::from_file(&self.engine, path.join(MODULE_NAME))?;
+
+		Ok(LoadedExtension { manifest, module })
+	}
+
+	#[cfg(not(feature = "wasm-extensions"))]
+	fn load_extension(&self, path: &Path) -> Result<LoadedExtension, ExtensionError> {
+		let manifest: ExtensionManifest =
+			serde_json::from_reader(BufReader::new(File::open(path.join(MANIFEST_NAME))?))?;
+
+		Err(ExtensionError::Unsupported(manifest.name))
+	}
+
+	pub fn list(&self) -> Vec<ExtensionInfo> {
+		let grants = self.grants.read().unwrap_or_else(|e| e.into_inner());
+
+		self.extensions
+			.read()
+			.unwrap_or_else(|e| e.into_inner())
+			.values()
+			.map(|extension| {
+				let granted = grants
+					.get(&extension.manifest.name)
+					.cloned()
+					.unwrap_or_default();
+
+				ExtensionInfo {
+					enabled: extension.manifest.capabilities.is_subset(&granted),
+					manifest: extension.manifest.clone(),
+					granted,
+				}
+			})
+			.collect()
+	}
+
+	/// grant gives an extension the given capabilities, replacing the ones it was granted before.
+	pub fn grant(
+		&self,
+		extension: String,
+		capabilities: BTreeSet<Capability>,
+	) -> Result<(), ExtensionError> {
+		let mut grants = self.grants.write().unwrap_or_else(|e| e.into_inner());
+		if capabilities.is_empty() {
+			grants.remove(&extension);
+		} else {
+			grants.insert(extension, capabilities);
+		}
+
+		File::create(self.data_path.join(EXTENSIONS_CONFIG_NAME))?
+			.write_all(serde_json::to_string(&*grants)?.as_bytes())?;
+
+		Ok(())
+	}
+
+	/// step_handler returns the step handler registered by an extension with the given name.
+	pub fn step_handler(
+		&self,
+		extension: &str,
+		handler: &str,
+	) -> Result<StepHandler, ExtensionError> {
+		self.extensions
+			.read()
+			.unwrap_or_else(|e| e.into_inner())
+			.get(extension)
+			.ok_or_else(|| ExtensionError::NotFound(extension.to_string()))?
+			.manifest
+			.step_handlers
+			.iter()
+			.find(|step_handler| step_handler.name == handler)
+			.cloned()
+			.ok_or_else(|| {
+				ExtensionError::StepHandlerNotFound(extension.to_string(), handler.to_string())
+			})
+	}
+
+	/// call_procedure calls a procedure registered by an extension.
+	pub async fn call_procedure(
+		&self,
+		extension: &str,
+		key: &str,
+		kind: ProcedureKind,
+		arg: Value,
+	) -> Result<Value, ExtensionError> {
+		let registered = self
+			.extensions
+			.read()
+			.unwrap_or_else(|e| e.into_inner())
+			.get(extension)
+			.ok_or_else(|| ExtensionError::NotFound(extension.to_string()))?
+			.manifest
+			.procedures
+			.iter()
+			.any(|procedure| procedure.key == key && procedure.kind == kind);
+
+		if !registered {
+			return Err(ExtensionError::ProcedureNotFound(
+				extension.to_string(),
+				key.to_string(),
+				kind,
+			));
+		}
+
+		self.call(
+			extension,
+			json!({ "type": "procedure", "key": key, "arg": arg }),
+			None,
+		)
+		.await
+	}
+
+	/// run_step calls a step handler of an extension with a file.
+	pub async fn run_step(
+		&self,
+		extension: &str,
+		handler: &StepHandler,
+		file: StepFile,
+	) -> Result<Value, ExtensionError> {
+		let granted = self.granted(extension);
+
+		let request = json!({
+			"type": "step",
+			"handler": handler.name,
+			// The file is only described to extensions which can read the library
+			"file": granted.contains(&Capability::ReadLibrary).then_some(&file),
+		});
+
+		self.call(extension, request, Some(file.path)).await
+	}
+
+	/// finish_export calls an exporter once it was called for every file, with the results it returned for them.
+	/// Every call runs in a new sandbox, so the results are how an exporter gets back what it saw of the files.
+	pub async fn finish_export(
+		&self,
+		extension: &str,
+		handler: &StepHandler,
+		results: Value,
+	) -> Result<Value, ExtensionError> {
+		if handler.kind != StepHandlerKind::Exporter {
+			return Ok(Value::Null);
+		}
+
+		self.call(
+			extension,
+			json!({ "type": "finish", "handler": handler.name, "results": results }),
+			None,
+		)
+		.await
+	}
+
+	fn granted(&self, extension: &str) -> BTreeSet<Capability> {
+		self.grants
+			.read()
+			.unwrap_or_else(|e| e.into_inner())
+			.get(extension)
+			.cloned()
+			.unwrap_or_default()
+	}
+
+	#[cfg(feature = "wasm-extensions")]
+	async fn call(
+		&self,
+		extension: &str,
+		request: Value,
+		file: Option<PathBuf>,
+	) -> Result<Value, ExtensionError> {
+		let granted = self.granted(extension);
+
+		let module = {
+			let extensions = self.extensions.read().unwrap_or_else(|e| e.into_inner());
+			let loaded = extensions
+				.get(extension)
+				.ok_or_else(|| ExtensionError::NotFound(extension.to_string()))?;
+
+			if !loaded.manifest.capabilities.is_subset(&granted) {
+				return Err(ExtensionError::NotGranted(extension.to_string()));
+			}
+
+			loaded.module.clone()
+		};
+
+		let engine = self.engine.clone();
+		let state = HostState::new(extension.to_string(), file);
+
+		// Running WASM blocks until the call returns or runs out of fuel
+		tokio::task::spawn_blocking(move || run_module(&engine, &module, &granted, state, &request))
+			.await
+			.map_err(|e| ExtensionError::Failed(e.to_string()))?
+	}
+
+	#[cfg(not(feature = "wasm-extensions"))]
+	async fn call(
+		&self,
+		extension: &str,
+		_request: Value,
+		_file: Option<PathBuf>,
+	) -> Result<Value, ExtensionError> {
+		Err(ExtensionError::Unsupported(extension.to_string()))
+	}
+}
@@ -0,0 +1,115 @@
+use std::collections::BTreeSet;
+
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub mod extension_job;
+mod host;
+#[cfg(feature = "wasm-extensions")]
+mod sandbox;
+
+pub use host::*;
+
+/// Capability is something an extension can do on the node, which must be granted by the user before the extension runs.
+/// Extensions run in a WASM sandbox which only exposes the host functions of the granted capabilities.
+#[derive(
+	Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Type,
+)]
+pub enum Capability {
+	/// Log allows writing to the node logs, through the `spacedrive.log` host function.
+	Log,
+	/// ReadFiles allows reading the contents of the file a step handler is called with, through the `spacedrive.read_file` host function.
+	ReadFiles,
+	/// ReadLibrary allows step handlers to receive the name, extension and path of the file they are called with.
+	ReadLibrary,
+}
+
+/// StepHandlerKind is what a step handler does with the files of a location.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum StepHandlerKind {
+	/// MetadataExtractor is called for every file and its results are the output of the job.
+	MetadataExtractor,
+	/// Exporter is called for every file, then once more when the job finishes with the results of every file,
+	/// which result is the output of the job.
+	Exporter,
+}
+
+/// StepHandler is a job step registered by an extension, which can be run on a location with the extension job.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct StepHandler {
+	pub name: String,
+	pub kind: StepHandlerKind,
+	/// extensions are the file extensions the handler is called for, every file when empty.
+	#[serde(default)]
+	pub extensions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub enum ProcedureKind {
+	Query,
+	Mutation,
+}
+
+/// ExtensionProcedure is an rspc procedure registered by an extension, which is called through `ext.query` or `ext.mutation`.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct ExtensionProcedure {
+	pub key: String,
+	pub kind: ProcedureKind,
+}
+
+/// ExtensionManifest describes an extension. It's read from the `manifest.json` file next to the WASM module of the extension.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct ExtensionManifest {
+	pub name: String,
+	pub version: String,
+	pub description: Option<String>,
+	/// capabilities are what the extension needs, it only runs once all of them are granted.
+	#[serde(default)]
+	pub capabilities: BTreeSet<Capability>,
+	#[serde(default)]
+	pub step_handlers: Vec<StepHandler>,
+	#[serde(default)]
+	pub procedures: Vec<ExtensionProcedure>,
+}
+
+#[derive(Error, Debug)]
+pub enum ExtensionError {
+	#[error("error loading the extensions from the filesystem")]
+	IO(#[from] std::io::Error),
+	#[error("error serializing or deserializing extension JSON")]
+	Json(#[from] serde_json::Error),
+	#[error("extension '{0}' not found")]
+	NotFound(String),
+	#[error("extension '{0}' needs capabilities which haven't been granted")]
+	NotGranted(String),
+	#[error("extension '{0}' has no step handler '{1}'")]
+	StepHandlerNotFound(String, String),
+	#[error("extension '{0}' has no {2:?} procedure '{1}'")]
+	ProcedureNotFound(String, String, ProcedureKind),
+	#[cfg(feature = "wasm-extensions")]
+	#[error("extension runtime error: {0}")]
+	Runtime(#[from] wasmtime::Error),
+	#[cfg(feature = "wasm-extensions")]
+	#[error("extension accessed its memory out of bounds")]
+	MemoryAccess(#[from] wasmtime::MemoryAccessError),
+	#[cfg(not(feature = "wasm-extensions"))]
+	#[error("extension '{0}' can't run, the node was built without the `wasm-extensions` feature")]
+	Unsupported(String),
+	#[error("extension failed: {0}")]
+	Failed(String),
+}
+
+impl From<ExtensionError> for rspc::Error {
+	fn from(error: ExtensionError) -> Self {
+		let code = match error {
+			ExtensionError::NotFound(_)
+			| ExtensionError::StepHandlerNotFound(..)
+			| ExtensionError::ProcedureNotFound(..) => rspc::ErrorCode::NotFound,
+			ExtensionError::NotGranted(_) => rspc::ErrorCode::Forbidden,
+			_ => rspc::ErrorCode::InternalServerError,
+		};
+
+		rspc::Error::with_cause(code, error.to_string(), error)
+	}
+}
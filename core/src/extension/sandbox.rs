@@ -0,0 +1,145 @@
+use std::{
+	collections::BTreeSet,
+	fs::File,
+	io::{Read, Seek, SeekFrom},
+	path::PathBuf,
+};
+
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::info;
+use wasmtime::{
+	Caller, Engine, Extern, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder,
+};
+
+use super::{Capability, ExtensionError};
+
+/// How many WASM instructions a single call can run, so an extension stuck in a loop can't hang the node
+const CALL_FUEL: u64 = 10_000_000_000;
+const MAX_MEMORY_BYTES: usize = 256 * 1024 * 1024;
+/// The most bytes an extension can read from a file at once
+const MAX_READ_BYTES: usize = 1024 * 1024;
+/// The longest message an extension can log at once
+const MAX_LOG_BYTES: usize = 64 * 1024;
+
+/// ExtensionResponse is what an extension returns from `sd_call`, as `{ "ok": <value> }` or `{ "error": "<message>" }`.
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ExtensionResponse {
+	Ok(Value),
+	Error(String),
+}
+
+/// HostState is the state of the sandbox of a single call.
+pub(super) struct HostState {
+	extension: String,
+	file: Option<PathBuf>,
+	limits: StoreLimits,
+}
+
+impl HostState {
+	pub(super) fn new(extension: String, file: Option<PathBuf>) -> Self {
+		Self {
+			extension,
+			file,
+			limits: StoreLimitsBuilder::new()
+				.memory_size(MAX_MEMORY_BYTES)
+				.build(),
+		}
+	}
+}
+
+/// run_module instantiates an extension in a new sandbox and calls it with a request.
+pub(super) fn run_module(
+	engine: &Engine,
+	module: &Module,
+	granted: &BTreeSet<Capability>,
+	state: HostState,
+	request: &Value,
+) -> Result<Value, ExtensionError> {
+	let mut store = Store::new(engine, state);
+	store.limiter(|state| &mut state.limits);
+	store.add_fuel(CALL_FUEL)?;
+
+	// Only the host functions of granted capabilities are linked, so a module importing others fails to instantiate
+	let mut linker = Linker::new(engine);
+	if granted.contains(&Capability::Log) {
+		linker.func_wrap(
+			"spacedrive",
+			"log",
+			|mut caller: Caller<'_, HostState>, ptr: i32, len: i32| {
+				if let Some(memory) = guest_memory(&mut caller) {
+					// Longer messages are truncated, and can't be longer than the memory of the extension
+					let len = (len.max(0) as usize)
+						.min(MAX_LOG_BYTES)
+						.min(memory.data_size(&caller));
+					let mut message = vec![0; len];
+					if memory.read(&caller, ptr as usize, &mut message).is_ok() {
+						info!(
+							"[extension '{}'] {}",
+							caller.data().extension,
+							String::from_utf8_lossy(&message)
+						);
+					}
+				}
+			},
+		)?;
+	}
+	if granted.contains(&Capability::ReadFiles) {
+		linker.func_wrap(
+			"spacedrive",
+			"read_file",
+			|mut caller: Caller<'_, HostState>, offset: i64, ptr: i32, len: i32| -> i32 {
+				read_file(&mut caller, offset, ptr, len).unwrap_or(-1)
+			},
+		)?;
+	}
+
+	let instance = linker.instantiate(&mut store, module)?;
+	let memory = instance
+		.get_memory(&mut store, "memory")
+		.ok_or_else(|| ExtensionError::Failed("the module doesn't export its memory".into()))?;
+	let alloc = instance.get_typed_func::<i32, i32>(&mut store, "sd_alloc")?;
+	let call = instance.get_typed_func::<(i32, i32), i64>(&mut store, "sd_call")?;
+
+	let request = serde_json::to_vec(request)?;
+	let request_ptr = alloc.call(&mut store, request.len() as i32)?;
+	memory.write(&mut store, request_ptr as usize, &request)?;
+
+	let packed = call.call(&mut store, (request_ptr, request.len() as i32))? as u64;
+	// The response must lie in the memory of the extension, which is checked before allocating a buffer for it
+	let response_len = (packed & 0xFFFF_FFFF) as usize;
+	if response_len > memory.data_size(&store) {
+		return Err(ExtensionError::Failed(format!(
+			"the response length of {response_len} bytes exceeds the memory of the extension"
+		)));
+	}
+	let mut response = vec![0; response_len];
+	memory.read(&store, (packed >> 32) as usize, &mut response)?;
+
+	match serde_json::from_slice(&response)? {
+		ExtensionResponse::Ok(value) => Ok(value),
+		ExtensionResponse::Error(message) => Err(ExtensionError::Failed(message)),
+	}
+}
+
+fn guest_memory(caller: &mut Caller<'_, HostState>) -> Option<Memory> {
+	caller.get_export("memory").and_then(Extern::into_memory)
+}
+
+/// read_file reads the file the extension was called with into its memory, returning how many bytes were read.
+/// Extensions can't choose which file they read, so they can't access anything outside of the files they're given.
+fn read_file(caller: &mut Caller<'_, HostState>, offset: i64, ptr: i32, len: i32) -> Option<i32> {
+	let path = caller.data().file.clone()?;
+	let mut file = File::open(path).ok()?;
+	file.seek(SeekFrom::Start(offset.try_into().ok()?)).ok()?;
+
+	let mut buffer = vec![0; (len.max(0) as usize).min(MAX_READ_BYTES)];
+	let read = file.read(&mut buffer).ok()?;
+
+	guest_memory(caller)?
+		.write(caller, ptr as usize, &buffer[..read])
+		.ok()?;
+
+	Some(read as i32)
+}
@@ -1,12 +1,22 @@
 use crate::{
+	extension::extension_job::{ExtensionJob, EXTENSION_JOB_NAME},
 	invalidate_query,
-	job::{worker::Worker, DynJob, Job, JobError},
-	library::Library,
-	location::indexer::{
-		indexer_job::{IndexerJob, INDEXER_JOB_NAME},
-		shallow_indexer_job::{ShallowIndexerJob, SHALLOW_INDEXER_JOB_NAME},
+	job::{worker::Worker, DynJob, Job, JobError, WorkerCommand},
+	library::{
+		DbMaintenanceJob, Library, LibraryBackupJob, LibraryExportJob, LibraryMergeJob,
+		DB_MAINTENANCE_JOB_NAME, LIBRARY_BACKUP_JOB_NAME, LIBRARY_EXPORT_JOB_NAME,
+		LIBRARY_MERGE_JOB_NAME,
+	},
+	location::{
+		archive::archive_job::{ArchiveIndexerJob, ARCHIVE_INDEXER_JOB_NAME},
+		indexer::{
+			indexer_job::{IndexerJob, INDEXER_JOB_NAME},
+			shallow_indexer_job::{ShallowIndexerJob, SHALLOW_INDEXER_JOB_NAME},
+		},
 	},
 	object::{
+		convert::convert_job::{FileConverterJob, CONVERT_JOB_NAME},
+		disk_usage::{DiskUsageJob, DISK_USAGE_JOB_NAME},
 		file_identifier::{
 			file_identifier_job::{FileIdentifierJob, FILE_IDENTIFIER_JOB_NAME},
 			shallow_file_identifier_job::{
@@ -14,25 +24,38 @@ use crate::{
 			},
 		},
 		fs::{
+			archive::{FileCompressorJob, FileExtractorJob, COMPRESS_JOB_NAME, EXTRACT_JOB_NAME},
 			copy::{FileCopierJob, COPY_JOB_NAME},
 			cut::{FileCutterJob, CUT_JOB_NAME},
+			dedup::{DuplicateEliminatorJob, DUPLICATE_ELIMINATOR_JOB_NAME},
 			delete::{FileDeleterJob, DELETE_JOB_NAME},
 			erase::{FileEraserJob, ERASE_JOB_NAME},
 		},
 		preview::{
+			media_data_job::{MediaDataJob, MEDIA_DATA_JOB_NAME},
 			shallow_thumbnailer_job::{ShallowThumbnailerJob, SHALLOW_THUMBNAILER_JOB_NAME},
+			thumbnail_gc_job::{ThumbnailGcJob, THUMBNAIL_GC_JOB_NAME},
 			thumbnailer_job::{ThumbnailerJob, THUMBNAILER_JOB_NAME},
 		},
-		validation::validator_job::{ObjectValidatorJob, VALIDATOR_JOB_NAME},
+		validation::{
+			validator_job::{ObjectValidatorJob, VALIDATOR_JOB_NAME},
+			verifier_job::{ObjectVerifierJob, VERIFIER_JOB_NAME},
+		},
 	},
 	prisma::{job, node},
 };
 
+#[cfg(feature = "content-search")]
+use crate::object::search::content_indexer_job::{ContentIndexerJob, CONTENT_INDEXER_JOB_NAME};
+
 use std::{
-	collections::{HashMap, HashSet, VecDeque},
+	collections::{hash_map::Entry, BTreeMap, HashMap, HashSet, VecDeque},
 	fmt::Debug,
 	fmt::{Display, Formatter},
-	sync::Arc,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc,
+	},
 	time::Duration,
 };
 
@@ -40,6 +63,7 @@ use int_enum::IntEnum;
 use prisma_client_rust::Direction;
 use rspc::Type;
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
 use tokio::{
 	sync::{broadcast, mpsc, Mutex, RwLock},
 	time::sleep,
@@ -50,6 +74,22 @@ use uuid::Uuid;
 // db is single threaded, nerd
 const MAX_WORKERS: usize = 1;
 
+/// JobPriority is the class a job is scheduled in. Each class has its own queue and its own limit of running jobs,
+/// so a quick interactive job never waits behind a long running one of another class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+pub enum JobPriority {
+	/// Jobs the user is waiting on, like the shallow scan of the directory being browsed
+	Interactive,
+	/// Jobs started by the user, and full scans of locations
+	Normal,
+	/// Housekeeping jobs started by the node itself
+	Background,
+}
+
+impl JobPriority {
+	pub const ALL: [JobPriority; 3] = [Self::Interactive, Self::Normal, Self::Background];
+}
+
 pub enum JobManagerEvent {
 	IngestJob(Library, Box<dyn DynJob>),
 }
@@ -58,11 +98,17 @@ pub enum JobManagerEvent {
 /// Handling persisting JobReports to the database, pause/resuming, and
 ///
 pub struct JobManager {
-	current_jobs_hashes: RwLock<HashSet<u64>>,
-	job_queue: RwLock<VecDeque<Box<dyn DynJob>>>,
-	running_workers: RwLock<HashMap<Uuid, Arc<Mutex<Worker>>>>,
+	/// current_jobs_hashes maps the hash of every queued, waiting or running job to its id,
+	/// so a duplicate of one of them is dropped in favor of it
+	current_jobs_hashes: RwLock<HashMap<u64, Uuid>>,
+	/// job_queues are the jobs waiting for a free worker of their class, along with their library
+	job_queues: RwLock<HashMap<JobPriority, VecDeque<(Library, Box<dyn DynJob>)>>>,
+	/// waiting_jobs are the jobs which run after another one, by the id of the job they wait for
+	waiting_jobs: RwLock<HashMap<Uuid, Vec<Box<dyn DynJob>>>>,
+	running_workers: RwLock<HashMap<Uuid, (JobPriority, Arc<Mutex<Worker>>)>>,
 	internal_sender: mpsc::UnboundedSender<JobManagerEvent>,
 	shutdown_tx: Arc<broadcast::Sender<()>>,
+	max_workers: HashMap<JobPriority, AtomicUsize>,
 }
 
 impl JobManager {
@@ -70,11 +116,16 @@ impl JobManager {
 		let (shutdown_tx, _shutdown_rx) = broadcast::channel(1);
 		let (internal_sender, mut internal_receiver) = mpsc::unbounded_channel();
 		let this = Arc::new(Self {
-			current_jobs_hashes: RwLock::new(HashSet::new()),
-			job_queue: RwLock::new(VecDeque::new()),
+			current_jobs_hashes: RwLock::new(HashMap::new()),
+			job_queues: RwLock::new(HashMap::new()),
+			waiting_jobs: RwLock::new(HashMap::new()),
 			running_workers: RwLock::new(HashMap::new()),
 			internal_sender,
 			shutdown_tx: Arc::new(shutdown_tx),
+			max_workers: JobPriority::ALL
+				.into_iter()
+				.map(|priority| (priority, AtomicUsize::new(MAX_WORKERS)))
+				.collect(),
 		});
 
 		let this2 = this.clone();
@@ -94,7 +145,7 @@ impl JobManager {
 		this
 	}
 
-	pub async fn ingest(self: Arc<Self>, library: &Library, job: Box<dyn DynJob>) {
+	pub async fn ingest(self: Arc<Self>, library: &Library, mut job: Box<dyn DynJob>) {
 		let job_hash = job.hash();
 		debug!(
 			"Ingesting job: <name='{}', hash='{}'>",
@@ -102,42 +153,152 @@ impl JobManager {
 			job_hash
 		);
 
-		if !self.current_jobs_hashes.read().await.contains(&job_hash) {
-			self.current_jobs_hashes.write().await.insert(job_hash);
-			self.dispatch_job(library, job).await;
-		} else {
-			debug!(
-				"Job already in queue: <name='{}', hash='{}'>",
-				job.name(),
-				job_hash
-			);
+		match self.register_job(&mut job).await {
+			Ok(()) => self.dispatch_job(library, job).await,
+			Err(existing_id) => {
+				debug!(
+					"Job already in queue: <name='{}', hash='{}'>",
+					job.name(),
+					job_hash
+				);
+				// The jobs waiting for this one run once the same job already queued or running completes
+				self.move_waiting_jobs(library, job_id(&mut job), existing_id)
+					.await;
+			}
 		}
 	}
 
-	pub async fn ingest_queue(&self, job: Box<dyn DynJob>) {
+	/// ingest_queue queues a job, which runs once a job of its class completes or, if it was made to
+	/// [`Job::run_after`] another one, once that job completes.
+	pub async fn ingest_queue(&self, library: &Library, mut job: Box<dyn DynJob>) {
 		let job_hash = job.hash();
 		debug!("Queueing job: <name='{}', hash='{}'>", job.name(), job_hash);
 
-		if !self.current_jobs_hashes.read().await.contains(&job_hash) {
-			self.current_jobs_hashes.write().await.insert(job_hash);
-			self.job_queue.write().await.push_back(job);
-		} else {
+		if let Err(existing_id) = self.register_job(&mut job).await {
 			debug!(
 				"Job already in queue: <name='{}', hash='{}'>",
 				job.name(),
 				job_hash
 			);
+			self.move_waiting_jobs(library, job_id(&mut job), existing_id)
+				.await;
+			return;
+		}
+
+		match job.report().as_ref().and_then(|report| report.parent_id) {
+			Some(parent_id) => {
+				// Jobs waiting for another one are stored, so they're queued again if the node restarts before they run
+				if let Err(e) = store_waiting_job(library, &mut job).await {
+					error!("Failed to store job waiting for {parent_id}: {e:#?}");
+				}
+				self.waiting_jobs
+					.write()
+					.await
+					.entry(parent_id)
+					.or_default()
+					.push(job)
+			}
+			None => self
+				.job_queues
+				.write()
+				.await
+				.entry(job.priority())
+				.or_default()
+				.push_back((library.clone(), job)),
+		}
+	}
+
+	/// register_job records the hash of a job about to be queued or run. If the same job is already queued,
+	/// waiting or running, the id of that job is returned instead.
+	async fn register_job(&self, job: &mut Box<dyn DynJob>) -> Result<(), Uuid> {
+		let job_id = job_id(job);
+		match self.current_jobs_hashes.write().await.entry(job.hash()) {
+			Entry::Occupied(entry) => Err(*entry.get()),
+			Entry::Vacant(entry) => {
+				entry.insert(job_id);
+				Ok(())
+			}
+		}
+	}
+
+	/// move_waiting_jobs makes the jobs waiting for the job `from`, dropped as a duplicate, wait for the job `to`
+	/// it duplicates instead, so they run once that one completes.
+	async fn move_waiting_jobs(&self, library: &Library, from: Uuid, to: Uuid) {
+		let mut waiting_jobs = self.waiting_jobs.write().await;
+		let Some(mut jobs) = waiting_jobs.remove(&from) else {
+			return;
+		};
+
+		for job in &mut jobs {
+			if let Some(report) = job.report() {
+				report.parent_id = Some(to);
+			}
+		}
+		if let Err(e) = library
+			.db
+			.job()
+			.update_many(
+				vec![job::parent_id::equals(Some(from.as_bytes().to_vec()))],
+				vec![job::parent_id::set(Some(to.as_bytes().to_vec()))],
+			)
+			.exec()
+			.await
+		{
+			error!("Failed to move the jobs waiting for {from} to {to}: {e:#?}");
 		}
+
+		waiting_jobs.entry(to).or_default().extend(jobs);
 	}
 
-	pub async fn complete(self: Arc<Self>, library: &Library, job_id: Uuid, job_hash: u64) {
+	pub async fn complete(
+		self: Arc<Self>,
+		library: &Library,
+		job_id: Uuid,
+		job_hash: u64,
+		status: JobStatus,
+	) {
 		// remove worker from running workers and from current jobs hashes
 		self.current_jobs_hashes.write().await.remove(&job_hash);
-		self.running_workers.write().await.remove(&job_id);
-		// continue queue
-		let job = self.job_queue.write().await.pop_front();
-		if let Some(job) = job {
+		let finished_worker = self.running_workers.write().await.remove(&job_id);
+
+		match status {
+			JobStatus::Completed => self.release_waiting_jobs(library, job_id).await,
+			// The jobs waiting for a paused job run once it's resumed and completes
+			JobStatus::Paused => {}
+			_ => self.cancel_waiting_jobs(library, job_id).await,
+		}
+
+		let Some((priority, _)) = finished_worker else {
+			return;
+		};
+		// continue the queue of the class which has a free worker now
+		let job = self
+			.job_queues
+			.write()
+			.await
+			.get_mut(&priority)
+			.and_then(VecDeque::pop_front);
+		if let Some((library, job)) = job {
 			// We can't directly execute `self.ingest` here because it would cause an async cycle.
+			self.internal_sender
+				.send(JobManagerEvent::IngestJob(library, job))
+				.unwrap_or_else(|_| {
+					error!("Failed to ingest job!");
+				});
+		}
+	}
+
+	/// release_waiting_jobs dispatches the jobs which were waiting for the job with `parent_id` to complete.
+	async fn release_waiting_jobs(&self, library: &Library, parent_id: Uuid) {
+		let Some(jobs) = self.waiting_jobs.write().await.remove(&parent_id) else {
+			return;
+		};
+
+		for job in jobs {
+			debug!(
+				"Releasing job waiting for {parent_id}: <name='{}'>",
+				job.name()
+			);
 			self.internal_sender
 				.send(JobManagerEvent::IngestJob(library.clone(), job))
 				.unwrap_or_else(|_| {
@@ -146,10 +307,96 @@ impl JobManager {
 		}
 	}
 
+	/// cancel_waiting_jobs drops the jobs which were waiting for the job with `parent_id`, which won't complete,
+	/// along with the jobs waiting for them in turn.
+	async fn cancel_waiting_jobs(&self, library: &Library, parent_id: Uuid) {
+		let mut waiting_jobs = self.waiting_jobs.write().await;
+		let mut current_jobs_hashes = self.current_jobs_hashes.write().await;
+
+		let mut canceled_ids = vec![];
+		let mut parent_ids = vec![parent_id];
+		while let Some(parent_id) = parent_ids.pop() {
+			for mut job in waiting_jobs.remove(&parent_id).into_iter().flatten() {
+				info!(
+					"Canceled job waiting for {parent_id}: <name='{}'>",
+					job.name()
+				);
+				current_jobs_hashes.remove(&job.hash());
+				if let Some(report) = job.report() {
+					parent_ids.push(report.id);
+					canceled_ids.push(report.id.as_bytes().to_vec());
+				}
+			}
+		}
+
+		if canceled_ids.is_empty() {
+			return;
+		}
+		if let Err(e) = library
+			.db
+			.job()
+			.update_many(
+				vec![job::id::in_vec(canceled_ids)],
+				vec![job::status::set(JobStatus::Canceled.int_value())],
+			)
+			.exec()
+			.await
+		{
+			error!("Failed to cancel the jobs waiting for {parent_id}: {e:#?}");
+		}
+	}
+
+	/// set_max_workers changes how many jobs of the `priority` class can run at the same time,
+	/// starting from the next dispatched job. `None` restores the default.
+	pub fn set_max_workers(&self, priority: JobPriority, max_workers: Option<usize>) {
+		self.max_workers[&priority]
+			.store(max_workers.unwrap_or(MAX_WORKERS).max(1), Ordering::Relaxed);
+	}
+
+	/// queue_depth returns how many jobs are waiting to be run and how many are running.
+	pub async fn queue_depth(&self) -> (usize, usize) {
+		let queued = self
+			.job_queues
+			.read()
+			.await
+			.values()
+			.map(VecDeque::len)
+			.sum::<usize>();
+		let waiting = self
+			.waiting_jobs
+			.read()
+			.await
+			.values()
+			.map(Vec::len)
+			.sum::<usize>();
+
+		(queued + waiting, self.running_workers.read().await.len())
+	}
+
+	/// get_queued returns the reports of the jobs which haven't started yet, including the ones waiting for
+	/// another job, whose report has its `parent_id`.
+	pub async fn get_queued(&self) -> Vec<JobReport> {
+		let mut reports = vec![];
+
+		let mut job_queues = self.job_queues.write().await;
+		for priority in JobPriority::ALL {
+			if let Some(queue) = job_queues.get_mut(&priority) {
+				reports.extend(queue.iter_mut().filter_map(|(_, job)| job.report().clone()));
+			}
+		}
+		drop(job_queues);
+
+		for jobs in self.waiting_jobs.write().await.values_mut() {
+			reports.extend(jobs.iter_mut().filter_map(|job| job.report().clone()));
+		}
+
+		reports
+	}
+
 	pub async fn get_running(&self) -> Vec<JobReport> {
 		let mut ret = vec![];
 
-		for worker in self.running_workers.read().await.values() {
+		for (_, worker) in self.running_workers.read().await.values() {
 			let worker = worker.lock().await;
 			ret.push(worker.report());
 		}
@@ -172,10 +419,62 @@ impl JobManager {
 			.collect())
 	}
 
+	/// get_statistics sums up the finished jobs of the library by job name, for users to see what the node has been doing.
+	pub async fn get_statistics(
+		library: &Library,
+	) -> Result<Vec<JobStatistics>, prisma_client_rust::QueryError> {
+		let mut statistics = BTreeMap::<String, JobStatistics>::new();
+
+		for job in library
+			.db
+			.job()
+			.find_many(vec![job::status::not(JobStatus::Running.int_value())])
+			.select(job::select!({
+				name
+				status
+				seconds_elapsed
+				items_processed
+				bytes_read
+				date_created
+			}))
+			.exec()
+			.await?
+		{
+			let job_statistics =
+				statistics
+					.entry(job.name.clone())
+					.or_insert_with(|| JobStatistics {
+						name: job.name,
+						..Default::default()
+					});
+
+			match JobStatus::from_int(job.status) {
+				Ok(JobStatus::Completed) => job_statistics.completed += 1,
+				Ok(JobStatus::Failed) => job_statistics.failed += 1,
+				Ok(JobStatus::Canceled) => job_statistics.canceled += 1,
+				_ => {}
+			}
+			job_statistics.seconds_elapsed += job.seconds_elapsed as u64;
+			job_statistics.items_processed += job.items_processed as u64;
+			job_statistics.bytes_read += job.bytes_read as u64;
+
+			let date_created: chrono::DateTime<chrono::Utc> = job.date_created.into();
+			if job_statistics
+				.last_run
+				.map_or(true, |last_run| last_run < date_created)
+			{
+				job_statistics.last_run = Some(date_created);
+			}
+		}
+
+		Ok(statistics.into_values().collect())
+	}
+
 	pub async fn clear_all_jobs(library: &Library) -> Result<(), prisma_client_rust::QueryError> {
 		library.db.job().delete_many(vec![]).exec().await?;
 
 		invalidate_query!(library, "jobs.getHistory");
+		invalidate_query!(library, "jobs.history");
 		Ok(())
 	}
 
@@ -201,98 +500,330 @@ impl JobManager {
 		}
 	}
 
+	/// pause_library_jobs stops the jobs of a library before it's closed: its queued and waiting jobs are dropped
+	/// and its running jobs are paused, waiting for them to save their state and stop.
+	pub async fn pause_library_jobs(&self, library: &Library) -> Result<(), JobError> {
+		let mut dropped_hashes = vec![];
+		for queue in self.job_queues.write().await.values_mut() {
+			queue.retain_mut(|(job_library, job)| {
+				let keep = job_library.id != library.id;
+				if !keep {
+					dropped_hashes.push(job.hash());
+				}
+				keep
+			});
+		}
+
+		// Waiting jobs are stored in the database of their library
+		{
+			let mut waiting_jobs = self.waiting_jobs.write().await;
+			let waiting_ids = waiting_jobs
+				.values_mut()
+				.flatten()
+				.filter_map(|job| job.report().as_ref().map(|report| report.id))
+				.collect::<Vec<_>>();
+			let library_waiting_ids = library
+				.db
+				.job()
+				.find_many(vec![job::id::in_vec(
+					waiting_ids
+						.iter()
+						.map(|id| id.as_bytes().to_vec())
+						.collect(),
+				)])
+				.select(job::select!({ id }))
+				.exec()
+				.await?
+				.into_iter()
+				.filter_map(|job| Uuid::from_slice(&job.id).ok())
+				.collect::<HashSet<_>>();
+
+			for jobs in waiting_jobs.values_mut() {
+				jobs.retain_mut(|job| {
+					let keep = job
+						.report()
+						.as_ref()
+						.map_or(true, |report| !library_waiting_ids.contains(&report.id));
+					if !keep {
+						dropped_hashes.push(job.hash());
+					}
+					keep
+				});
+			}
+			waiting_jobs.retain(|_, jobs| !jobs.is_empty());
+		}
+
+		{
+			let mut current_jobs_hashes = self.current_jobs_hashes.write().await;
+			for hash in dropped_hashes {
+				current_jobs_hashes.remove(&hash);
+			}
+		}
+
+		let running_ids = self
+			.running_workers
+			.read()
+			.await
+			.keys()
+			.copied()
+			.collect::<Vec<_>>();
+		let mut paused_ids = vec![];
+		for job_id in running_ids {
+			if is_library_job(library, job_id).await? {
+				// The job may have completed in the meantime
+				if self
+					.command_running_job(library, job_id, WorkerCommand::Pause)
+					.await
+					.is_ok()
+				{
+					paused_ids.push(job_id);
+				}
+			}
+		}
+
+		loop {
+			{
+				let running_workers = self.running_workers.read().await;
+				if paused_ids
+					.iter()
+					.all(|id| !running_workers.contains_key(id))
+				{
+					break;
+				}
+			}
+			sleep(Duration::from_millis(50)).await;
+		}
+
+		Ok(())
+	}
+
 	pub async fn resume_jobs(self: Arc<Self>, library: &Library) -> Result<(), JobError> {
-		let paused_jobs = library
+		// Jobs waiting for another one are queued again first, so they wait for the paused jobs resumed below
+		let waiting_jobs = library
 			.db
 			.job()
-			.find_many(vec![job::status::equals(JobStatus::Paused.int_value())])
+			.find_many(vec![
+				job::status::equals(JobStatus::Queued.int_value()),
+				job::parent_id::not(None),
+			])
 			.exec()
 			.await?;
 
-		for paused_job_data in paused_jobs {
-			let paused_job = JobReport::from(paused_job_data);
+		for waiting_job_data in waiting_jobs {
+			let report = JobReport::from(waiting_job_data);
+			let Some(parent_id) = report.parent_id else {
+				continue;
+			};
 
-			info!("Resuming job: {}, id: {}", paused_job.name, paused_job.id);
-			match paused_job.name.as_str() {
-				THUMBNAILER_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(library, Job::resume(paused_job, ThumbnailerJob {})?)
-						.await;
-				}
-				SHALLOW_THUMBNAILER_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(library, Job::resume(paused_job, ShallowThumbnailerJob {})?)
-						.await;
-				}
-				INDEXER_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(library, Job::resume(paused_job, IndexerJob {})?)
-						.await;
-				}
-				SHALLOW_INDEXER_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(library, Job::resume(paused_job, ShallowIndexerJob {})?)
-						.await;
-				}
-				FILE_IDENTIFIER_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(library, Job::resume(paused_job, FileIdentifierJob {})?)
-						.await;
-				}
-				SHALLOW_FILE_IDENTIFIER_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(
-							library,
-							Job::resume(paused_job, ShallowFileIdentifierJob {})?,
-						)
-						.await;
-				}
-				VALIDATOR_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(library, Job::resume(paused_job, ObjectValidatorJob {})?)
-						.await;
-				}
-				CUT_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(library, Job::resume(paused_job, FileCutterJob {})?)
-						.await;
-				}
-				COPY_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(
-							library,
-							Job::resume(paused_job, FileCopierJob { done_tx: None })?,
-						)
-						.await;
-				}
-				DELETE_JOB_NAME => {
-					Arc::clone(&self)
-						.dispatch_job(library, Job::resume(paused_job, FileDeleterJob {})?)
-						.await;
+			let parent_status = library
+				.db
+				.job()
+				.find_unique(job::id::equals(parent_id.as_bytes().to_vec()))
+				.select(job::select!({ status }))
+				.exec()
+				.await?
+				.and_then(|parent| JobStatus::from_int(parent.status).ok());
+
+			match parent_status {
+				Some(JobStatus::Paused | JobStatus::Queued) => {
+					let mut job = job_from_report(report)?;
+					if self.register_job(&mut job).await.is_ok() {
+						self.waiting_jobs
+							.write()
+							.await
+							.entry(parent_id)
+							.or_default()
+							.push(job);
+					}
 				}
-				ERASE_JOB_NAME => {
+				// The node stopped before releasing it
+				Some(JobStatus::Completed) => {
 					Arc::clone(&self)
-						.dispatch_job(library, Job::resume(paused_job, FileEraserJob {})?)
+						.ingest(library, job_from_report(report)?)
 						.await;
 				}
 				_ => {
-					error!(
-						"Unknown job type: {}, id: {}",
-						paused_job.name, paused_job.id
+					info!(
+						"Canceled job waiting for {parent_id}, which won't complete: <name='{}'>",
+						report.name
 					);
-					return Err(JobError::UnknownJobName(paused_job.id, paused_job.name));
+					library
+						.db
+						.job()
+						.update(
+							job::id::equals(report.id.as_bytes().to_vec()),
+							vec![job::status::set(JobStatus::Canceled.int_value())],
+						)
+						.exec()
+						.await?;
+					self.cancel_waiting_jobs(library, report.id).await;
 				}
-			};
+			}
+		}
+
+		let paused_jobs = library
+			.db
+			.job()
+			.find_many(vec![job::status::equals(JobStatus::Paused.int_value())])
+			.exec()
+			.await?;
+
+		for paused_job_data in paused_jobs {
+			Arc::clone(&self)
+				.resume_paused_job(library, JobReport::from(paused_job_data))
+				.await?;
+		}
+
+		Ok(())
+	}
+
+	/// pause_job tells a running job of the library to save its state and stop once its current step is done,
+	/// so it can be resumed later with [`JobManager::resume_job`].
+	pub async fn pause_job(&self, library: &Library, job_id: Uuid) -> Result<(), JobError> {
+		self.command_running_job(library, job_id, WorkerCommand::Pause)
+			.await
+	}
+
+	/// cancel_job stops a running job of the library once its current step is done, dropping its state,
+	/// or takes a job out of the queue if it hasn't started yet.
+	pub async fn cancel_job(&self, library: &Library, job_id: Uuid) -> Result<(), JobError> {
+		let is_job = |job: &mut Box<dyn DynJob>| {
+			job.report()
+				.as_ref()
+				.map_or(false, |report| report.id == job_id)
+		};
+
+		let mut canceled = None;
+		for queue in self.job_queues.write().await.values_mut() {
+			if let Some(position) = queue
+				.iter_mut()
+				.position(|(job_library, job)| job_library.id == library.id && is_job(job))
+			{
+				canceled = queue.remove(position).map(|(_, job)| job);
+				break;
+			}
+		}
+		// Waiting jobs are stored in the database of their library
+		if canceled.is_none() && is_library_job(library, job_id).await? {
+			for jobs in self.waiting_jobs.write().await.values_mut() {
+				if let Some(position) = jobs.iter_mut().position(is_job) {
+					canceled = Some(jobs.remove(position));
+					break;
+				}
+			}
 		}
 
+		let Some(job) = canceled else {
+			return self
+				.command_running_job(library, job_id, WorkerCommand::Cancel)
+				.await;
+		};
+
+		info!("Canceled queued job: {}, id: {job_id}", job.name());
+		self.current_jobs_hashes.write().await.remove(&job.hash());
+		// The jobs waiting for it won't run either
+		self.cancel_waiting_jobs(library, job_id).await;
+
 		Ok(())
 	}
 
+	async fn command_running_job(
+		&self,
+		library: &Library,
+		job_id: Uuid,
+		command: WorkerCommand,
+	) -> Result<(), JobError> {
+		// The report of a running job is stored in the database of its library before it starts
+		if !is_library_job(library, job_id).await? {
+			return Err(JobError::JobNotFound(job_id));
+		}
+
+		let running_workers = self.running_workers.read().await;
+		let (_, worker) = running_workers
+			.get(&job_id)
+			.ok_or(JobError::JobNotFound(job_id))?;
+
+		if worker.lock().await.command(command) {
+			info!("Sent {command:?} to job: {job_id}");
+		}
+
+		Ok(())
+	}
+
+	/// resume_job runs a job paused with [`JobManager::pause_job`] again, from the state it saved.
+	/// Like the jobs paused on shutdown, paused jobs left over are resumed by [`JobManager::resume_jobs`]
+	/// the next time their library is loaded.
+	pub async fn resume_job(
+		self: Arc<Self>,
+		library: &Library,
+		job_id: Uuid,
+	) -> Result<(), JobError> {
+		// A resumed job stays paused in the database until it starts running, which it may wait for in a queue
+		let is_queued = self.job_queues.write().await.values_mut().any(|queue| {
+			queue.iter_mut().any(|(_, job)| {
+				job.report()
+					.as_ref()
+					.map_or(false, |report| report.id == job_id)
+			})
+		});
+		if is_queued || self.running_workers.read().await.contains_key(&job_id) {
+			return Err(JobError::JobNotPaused(job_id));
+		}
+
+		let paused_job = library
+			.db
+			.job()
+			.find_unique(job::id::equals(job_id.as_bytes().to_vec()))
+			.exec()
+			.await?
+			.map(JobReport::from)
+			.ok_or(JobError::JobNotFound(job_id))?;
+
+		if paused_job.status != JobStatus::Paused {
+			return Err(JobError::JobNotPaused(job_id));
+		}
+
+		if !self.resume_paused_job(library, paused_job).await? {
+			return Err(JobError::JobNotPaused(job_id));
+		}
+
+		invalidate_query!(library, "jobs.getHistory");
+		invalidate_query!(library, "jobs.history");
+
+		Ok(())
+	}
+
+	/// resume_paused_job runs a paused job again, unless it's already resumed or the same job is queued or
+	/// running. Returns whether it was resumed.
+	async fn resume_paused_job(
+		self: Arc<Self>,
+		library: &Library,
+		paused_job: JobReport,
+	) -> Result<bool, JobError> {
+		info!("Resuming job: {}, id: {}", paused_job.name, paused_job.id);
+		let mut job = job_from_report(paused_job)?;
+		if let Err(existing_id) = self.register_job(&mut job).await {
+			info!(
+				"Job already resumed or running: <name='{}', id='{existing_id}'>",
+				job.name()
+			);
+			return Ok(false);
+		}
+		self.dispatch_job(library, job).await;
+
+		Ok(true)
+	}
+
 	async fn dispatch_job(self: Arc<Self>, library: &Library, mut job: Box<dyn DynJob>) {
 		// create worker to process job
+		let priority = job.priority();
 		let mut running_workers = self.running_workers.write().await;
-		if running_workers.len() < MAX_WORKERS {
-			info!("Running job: {:?}", job.name());
+		let running_in_class = running_workers
+			.values()
+			.filter(|(running_priority, _)| *running_priority == priority)
+			.count();
+		if running_in_class < self.max_workers[&priority].load(Ordering::Relaxed) {
+			info!("Running job: {:?}, priority: {priority:?}", job.name());
 
 			let job_report = job
 				.report()
@@ -314,27 +845,132 @@ impl JobManager {
 			{
 				error!("Error spawning worker: {:?}", e);
 			} else {
-				running_workers.insert(job_id, wrapped_worker);
+				running_workers.insert(job_id, (priority, wrapped_worker));
 			}
 		} else {
 			debug!(
-				"Queueing job: <name='{}', hash='{}'>",
+				"Queueing job: <name='{}', hash='{}', priority='{priority:?}'>",
 				job.name(),
 				job.hash()
 			);
-			self.job_queue.write().await.push_back(job);
+			self.job_queues
+				.write()
+				.await
+				.entry(priority)
+				.or_default()
+				.push_back((library.clone(), job));
 		}
 	}
 }
 
+/// job_id returns the id of a job which hasn't been dispatched to a worker yet.
+fn job_id(job: &mut Box<dyn DynJob>) -> Uuid {
+	job.report()
+		.as_ref()
+		.map(|report| report.id)
+		.expect("critical error: missing job report")
+}
+
+/// is_library_job tells if the job with the given id was stored in the database of the library.
+async fn is_library_job(library: &Library, job_id: Uuid) -> Result<bool, JobError> {
+	Ok(library
+		.db
+		.job()
+		.count(vec![job::id::equals(job_id.as_bytes().to_vec())])
+		.exec()
+		.await?
+		> 0)
+}
+
+/// store_waiting_job stores a job waiting for another one with the state it starts from,
+/// for [`JobManager::resume_jobs`] to queue it again if the node restarts before it runs.
+async fn store_waiting_job(library: &Library, job: &mut Box<dyn DynJob>) -> Result<(), JobError> {
+	let state = job.serialize_state()?;
+	if let Some(report) = job.report() {
+		report.create_queued(library, state).await?;
+	}
+
+	Ok(())
+}
+
+/// job_from_report rebuilds a job from its stored report, which holds the state it saved when it was paused
+/// or, for a job waiting for another one, the state it starts from.
+fn job_from_report(report: JobReport) -> Result<Box<dyn DynJob>, JobError> {
+	let job: Box<dyn DynJob> = match report.name.as_str() {
+		THUMBNAILER_JOB_NAME => Job::resume(report, ThumbnailerJob {})?,
+		SHALLOW_THUMBNAILER_JOB_NAME => Job::resume(report, ShallowThumbnailerJob {})?,
+		THUMBNAIL_GC_JOB_NAME => Job::resume(report, ThumbnailGcJob {})?,
+		INDEXER_JOB_NAME => Job::resume(report, IndexerJob {})?,
+		SHALLOW_INDEXER_JOB_NAME => Job::resume(report, ShallowIndexerJob {})?,
+		ARCHIVE_INDEXER_JOB_NAME => Job::resume(report, ArchiveIndexerJob {})?,
+		FILE_IDENTIFIER_JOB_NAME => Job::resume(report, FileIdentifierJob {})?,
+		SHALLOW_FILE_IDENTIFIER_JOB_NAME => Job::resume(report, ShallowFileIdentifierJob {})?,
+		MEDIA_DATA_JOB_NAME => Job::resume(report, MediaDataJob {})?,
+		#[cfg(feature = "content-search")]
+		CONTENT_INDEXER_JOB_NAME => Job::resume(report, ContentIndexerJob {})?,
+		DISK_USAGE_JOB_NAME => Job::resume(report, DiskUsageJob {})?,
+		VALIDATOR_JOB_NAME => Job::resume(report, ObjectValidatorJob {})?,
+		VERIFIER_JOB_NAME => Job::resume(report, ObjectVerifierJob {})?,
+		CUT_JOB_NAME => Job::resume(report, FileCutterJob {})?,
+		COPY_JOB_NAME => Job::resume(report, FileCopierJob { done_tx: None })?,
+		COMPRESS_JOB_NAME => Job::resume(report, FileCompressorJob::default())?,
+		EXTRACT_JOB_NAME => Job::resume(report, FileExtractorJob {})?,
+		CONVERT_JOB_NAME => Job::resume(report, FileConverterJob {})?,
+		DUPLICATE_ELIMINATOR_JOB_NAME => Job::resume(report, DuplicateEliminatorJob {})?,
+		DELETE_JOB_NAME => Job::resume(report, FileDeleterJob {})?,
+		ERASE_JOB_NAME => Job::resume(report, FileEraserJob {})?,
+		LIBRARY_BACKUP_JOB_NAME => Job::resume(report, LibraryBackupJob {})?,
+		LIBRARY_EXPORT_JOB_NAME => Job::resume(report, LibraryExportJob {})?,
+		DB_MAINTENANCE_JOB_NAME => Job::resume(report, DbMaintenanceJob {})?,
+		LIBRARY_MERGE_JOB_NAME => Job::resume(report, LibraryMergeJob::default())?,
+		EXTENSION_JOB_NAME => Job::resume(report, ExtensionJob {})?,
+		_ => {
+			error!("Unknown job type: {}, id: {}", report.name, report.id);
+			return Err(JobError::UnknownJobName(report.id, report.name));
+		}
+	};
+
+	Ok(job)
+}
+
+/// JobStatistics sums up the finished jobs with a name.
+#[serde_as]
+#[derive(Serialize, Type, Default)]
+pub struct JobStatistics {
+	pub name: String,
+	pub completed: u32,
+	pub failed: u32,
+	pub canceled: u32,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub seconds_elapsed: u64,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub items_processed: u64,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub bytes_read: u64,
+	pub last_run: Option<chrono::DateTime<chrono::Utc>>,
+}
+
 #[derive(Debug)]
 pub enum JobReportUpdate {
 	TaskCount(usize),
 	CompletedTaskCount(usize),
 	Message(String),
 	SecondsElapsed(u64),
+	StepRetried,
+	/// ItemsProcessed adds to the count of items the job went through
+	ItemsProcessed(usize),
+	/// BytesRead adds to the amount of file data the job read
+	BytesRead(u64),
+	/// TotalBytes sets how many bytes the job goes through, so its remaining time is estimated from them
+	TotalBytes(u64),
+	/// CompletedBytes adds to the bytes the job went through
+	CompletedBytes(u64),
 }
 
+#[serde_as]
 #[derive(Debug, Serialize, Deserialize, Type, Clone)]
 pub struct JobReport {
 	pub id: Uuid,
@@ -353,6 +989,32 @@ pub struct JobReport {
 	// pub percentage_complete: f64,
 	// #[ts(type = "string")] // TODO: Make this work with specta
 	pub seconds_elapsed: i32,
+	/// step_retries is how many times steps of the job were tried again after a transient error.
+	pub step_retries: i32,
+	/// parent_id is the job this one ran after, see [`Job::run_after`].
+	pub parent_id: Option<Uuid>,
+	/// items_processed is how many files or other items the job went through, for the jobs which count them.
+	pub items_processed: i32,
+	/// bytes_read is how much file data the job read, for the jobs which measure it.
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub bytes_read: u64,
+	/// error is why the job failed, if it did.
+	pub error: Option<String>,
+	/// total_bytes is how many bytes the job goes through, for the jobs which know it.
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub total_bytes: u64,
+	/// completed_bytes is how many bytes the job went through so far, for the jobs which measure it.
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub completed_bytes: u64,
+	/// throughput is how many bytes per second the job went through over the last few seconds, while it runs.
+	#[specta(type = Option<String>)]
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	pub throughput: Option<u64>,
+	/// eta_seconds is how long the running job should take to complete, from the bytes or the tasks left.
+	pub eta_seconds: Option<u32>,
 }
 
 impl Display for JobReport {
@@ -386,6 +1048,17 @@ impl From<job::Data> for JobReport {
 			}),
 			message: String::new(),
 			seconds_elapsed: data.seconds_elapsed,
+			step_retries: data.step_retries,
+			parent_id: data
+				.parent_id
+				.and_then(|parent_id| Uuid::from_slice(&parent_id).ok()),
+			items_processed: data.items_processed,
+			bytes_read: data.bytes_read as u64,
+			error: data.error,
+			total_bytes: data.total_bytes as u64,
+			completed_bytes: data.completed_bytes as u64,
+			throughput: None,
+			eta_seconds: None,
 		}
 	}
 }
@@ -405,19 +1078,61 @@ impl JobReport {
 			completed_task_count: 0,
 			message: String::new(),
 			seconds_elapsed: 0,
+			step_retries: 0,
+			parent_id: None,
+			items_processed: 0,
+			bytes_read: 0,
+			error: None,
+			total_bytes: 0,
+			completed_bytes: 0,
+			throughput: None,
+			eta_seconds: None,
 		}
 	}
 
+	/// create stores the report of a job starting to run. Jobs which waited for another one were stored
+	/// while queued, see [`JobReport::create_queued`], so their report is updated instead.
 	pub async fn create(&self, library: &Library) -> Result<(), JobError> {
+		library
+			.db
+			.job()
+			.upsert(
+				job::id::equals(self.id.as_bytes().to_vec()),
+				(
+					self.id.as_bytes().to_vec(),
+					self.name.clone(),
+					JobStatus::Running as i32,
+					node::id::equals(library.node_local_id),
+					vec![
+						job::data::set(self.data.clone()),
+						job::parent_id::set(self.parent_id.map(|id| id.as_bytes().to_vec())),
+					],
+				),
+				vec![
+					job::status::set(JobStatus::Running as i32),
+					job::data::set(self.data.clone()),
+					job::parent_id::set(self.parent_id.map(|id| id.as_bytes().to_vec())),
+				],
+			)
+			.exec()
+			.await?;
+		Ok(())
+	}
+
+	/// create_queued stores the report of a job waiting for another one, with the state it starts from as its data.
+	pub async fn create_queued(&self, library: &Library, state: Vec<u8>) -> Result<(), JobError> {
 		library
 			.db
 			.job()
 			.create(
 				self.id.as_bytes().to_vec(),
 				self.name.clone(),
-				JobStatus::Running as i32,
+				JobStatus::Queued as i32,
 				node::id::equals(library.node_local_id),
-				vec![job::data::set(self.data.clone())],
+				vec![
+					job::data::set(Some(state)),
+					job::parent_id::set(self.parent_id.map(|id| id.as_bytes().to_vec())),
+				],
 			)
 			.exec()
 			.await?;
@@ -437,6 +1152,12 @@ impl JobReport {
 					job::completed_task_count::set(self.completed_task_count),
 					job::date_modified::set(chrono::Utc::now().into()),
 					job::seconds_elapsed::set(self.seconds_elapsed),
+					job::step_retries::set(self.step_retries),
+					job::items_processed::set(self.items_processed),
+					job::bytes_read::set(self.bytes_read as i64),
+					job::error::set(self.error.clone()),
+					job::total_bytes::set(self.total_bytes as i64),
+					job::completed_bytes::set(self.completed_bytes as i64),
 				],
 			)
 			.exec()
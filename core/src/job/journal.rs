@@ -0,0 +1,292 @@
+use crate::{
+	library::Library,
+	prisma::{file_path, job, job_step_journal, location},
+	sync,
+};
+
+use std::collections::BTreeMap;
+
+use int_enum::IntEnum;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use super::{JobError, JobStatus};
+
+/// InterruptedStep is the step a job was running when it was interrupted, as it was journaled.
+pub(crate) struct InterruptedStep {
+	pub step_number: usize,
+	pub step: Vec<u8>,
+	/// data is the data of the job before the step
+	pub data: Vec<u8>,
+}
+
+/// begin_journal journals the first step a job runs, along with the whole state of the job before it.
+/// The following steps only journal themselves through [`begin_step`], as the job is rebuilt from that state.
+pub(crate) async fn begin_journal(
+	library: &Library,
+	job_id: Uuid,
+	state: Vec<u8>,
+	step_number: usize,
+	step: Vec<u8>,
+	data: Vec<u8>,
+) -> Result<(), JobError> {
+	library
+		.db
+		.job_step_journal()
+		.create(
+			step_number as i32,
+			state,
+			step,
+			data,
+			job::id::equals(job_id.as_bytes().to_vec()),
+			vec![],
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// begin_step journals the step a job is about to run along with the data of the job before it,
+/// replacing the entry of its previous step.
+pub(crate) async fn begin_step(
+	library: &Library,
+	job_id: Uuid,
+	step_number: usize,
+	step: Vec<u8>,
+	data: Vec<u8>,
+) -> Result<(), JobError> {
+	library
+		.db
+		.job_step_journal()
+		.update(
+			job_step_journal::job_id::equals(job_id.as_bytes().to_vec()),
+			vec![
+				job_step_journal::step_number::set(step_number as i32),
+				job_step_journal::step::set(step),
+				job_step_journal::data::set(data),
+				job_step_journal::file_paths::set(None),
+			],
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// end_journal removes the journal entry of a job, once all of its steps completed.
+pub(crate) async fn end_journal(library: &Library, job_id: Uuid) -> Result<(), JobError> {
+	library
+		.db
+		.job_step_journal()
+		.delete_many(vec![job_step_journal::job_id::equals(
+			job_id.as_bytes().to_vec(),
+		)])
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// resume_step rolls back the file paths written by the step a job was running when it was interrupted,
+/// and returns that step for the job to run it again. Returns `None` if the job has no journal entry.
+pub(crate) async fn resume_step(
+	library: &Library,
+	job_id: Uuid,
+) -> Result<Option<InterruptedStep>, JobError> {
+	let Some(entry) = library
+		.db
+		.job_step_journal()
+		.find_unique(job_step_journal::job_id::equals(
+			job_id.as_bytes().to_vec(),
+		))
+		.exec()
+		.await?
+	else {
+		return Ok(None);
+	};
+
+	roll_back_file_paths(library, &entry.job_id, entry.file_paths).await?;
+
+	Ok(Some(InterruptedStep {
+		step_number: entry.step_number as usize,
+		step: entry.step,
+		data: entry.data,
+	}))
+}
+
+/// journal_file_paths adds file paths to the ones the running step of a job creates, so they are deleted
+/// if the step is interrupted. It must be called before they are written. Jobs which don't journal their steps
+/// have no journal entry, and nothing is recorded for them.
+pub(crate) async fn journal_file_paths(
+	library: &Library,
+	job_id: Uuid,
+	location_id: i32,
+	ids: impl IntoIterator<Item = i32>,
+) -> Result<(), JobError> {
+	let job_id = job_id.as_bytes().to_vec();
+
+	let Some(entry) = library
+		.db
+		.job_step_journal()
+		.find_unique(job_step_journal::job_id::equals(job_id.clone()))
+		.exec()
+		.await?
+	else {
+		return Ok(());
+	};
+
+	let mut file_paths = match entry.file_paths {
+		Some(file_paths) => rmp_serde::from_slice::<Vec<(i32, i32)>>(&file_paths)?,
+		None => vec![],
+	};
+	file_paths.extend(ids.into_iter().map(|id| (location_id, id)));
+
+	library
+		.db
+		.job_step_journal()
+		.update(
+			job_step_journal::job_id::equals(job_id),
+			vec![job_step_journal::file_paths::set(Some(rmp_serde::to_vec(
+				&file_paths,
+			)?))],
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// roll_back_file_paths deletes the file paths journaled by the step of a job, and clears them from its entry.
+async fn roll_back_file_paths(
+	library: &Library,
+	job_id: &[u8],
+	file_paths: Option<Vec<u8>>,
+) -> Result<(), JobError> {
+	let Library { db, sync, .. } = library;
+
+	let Some(file_paths) = file_paths else {
+		return Ok(());
+	};
+
+	let mut ids_by_location = BTreeMap::<_, Vec<_>>::new();
+	for (location_id, id) in rmp_serde::from_slice::<Vec<(i32, i32)>>(&file_paths)? {
+		ids_by_location.entry(location_id).or_default().push(id);
+	}
+
+	for (location_id, ids) in ids_by_location {
+		let Some(location) = db
+			.location()
+			.find_unique(location::id::equals(location_id))
+			.select(location::select!({ pub_id }))
+			.exec()
+			.await?
+		else {
+			// Deleting the location deleted its file paths
+			continue;
+		};
+
+		// Only the file paths which made it to the database before the interruption are deleted
+		let written = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(location_id),
+				file_path::id::in_vec(ids),
+			])
+			.select(file_path::select!({ id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|file_path| file_path.id)
+			.collect::<Vec<_>>();
+
+		if written.is_empty() {
+			continue;
+		}
+
+		sync.write_ops(
+			db,
+			(
+				written
+					.iter()
+					.map(|&id| {
+						sync.shared_delete(sync::file_path::SyncId {
+							id,
+							location: sync::location::SyncId {
+								pub_id: location.pub_id.clone(),
+							},
+						})
+					})
+					.collect(),
+				db.file_path().delete_many(vec![
+					file_path::location_id::equals(location_id),
+					file_path::id::in_vec(written.clone()),
+				]),
+			),
+		)
+		.await?;
+
+		info!(
+			"Rolled back {} file paths of location {location_id} written by an interrupted step",
+			written.len()
+		);
+	}
+
+	db.job_step_journal()
+		.update(
+			job_step_journal::job_id::equals(job_id.to_vec()),
+			vec![job_step_journal::file_paths::set(None)],
+		)
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// recover_interrupted_steps rolls back the writes of the steps which were interrupted by a crash. The jobs which were
+/// running are paused, with the state they journaled if they had none, so the step runs again when they resume.
+/// Returns how many steps were rolled back.
+pub(crate) async fn recover_interrupted_steps(library: &Library) -> Result<usize, JobError> {
+	let db = &library.db;
+
+	let entries = db
+		.job_step_journal()
+		.find_many(vec![])
+		.include(job_step_journal::include!({ job: select { name status data } }))
+		.exec()
+		.await?;
+
+	for entry in &entries {
+		roll_back_file_paths(library, &entry.job_id, entry.file_paths.clone()).await?;
+
+		let status = JobStatus::from_int(entry.job.status).ok();
+		if matches!(status, Some(JobStatus::Running | JobStatus::Paused)) {
+			// The job picks up its journaled step once it resumes, see `resume_step`
+			db.job()
+				.update(
+					job::id::equals(entry.job_id.clone()),
+					vec![
+						job::status::set(JobStatus::Paused.int_value()),
+						job::data::set(Some(
+							entry.job.data.clone().unwrap_or_else(|| entry.state.clone()),
+						)),
+					],
+				)
+				.exec()
+				.await?;
+		} else {
+			// Jobs which stopped for good only needed their writes rolled back
+			db.job_step_journal()
+				.delete(job_step_journal::job_id::equals(entry.job_id.clone()))
+				.exec()
+				.await?;
+		}
+
+		warn!(
+			"Rolled back step {} of an interrupted {} job",
+			entry.step_number, entry.job.name
+		);
+	}
+
+	Ok(entries.len())
+}
@@ -1,25 +1,40 @@
 use crate::{
-	location::{indexer::IndexerError, LocationError, LocationManagerError},
-	object::{file_identifier::FileIdentifierJobError, preview::ThumbnailerError},
+	extension::ExtensionError,
+	library::{LibraryMergeError, TrashError},
+	location::{archive::ArchiveError, indexer::IndexerError, LocationError, LocationManagerError},
+	object::{
+		convert::ConvertError, file_identifier::FileIdentifierJobError, preview::ThumbnailerError,
+		search::SearchError,
+	},
 };
 
 use std::{
 	collections::{hash_map::DefaultHasher, VecDeque},
 	fmt::Debug,
 	hash::{Hash, Hasher},
+	io,
+	path::PathBuf,
+	time::Duration,
 };
 
 use rmp_serde::{decode::Error as DecodeError, encode::Error as EncodeError};
+use rspc::ErrorCode;
 use sd_crypto::Error as CryptoError;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use thiserror::Error;
-use tracing::info;
+use tokio::time::sleep;
+use tracing::{info, warn};
 use uuid::Uuid;
 
 mod job_manager;
+mod journal;
+mod notification;
+pub mod schedule;
 mod worker;
 
 pub use job_manager::*;
+pub(crate) use journal::recover_interrupted_steps;
+pub use notification::*;
 pub use worker::*;
 
 #[derive(Error, Debug)]
@@ -43,6 +58,10 @@ pub enum JobError {
 		"Tried to resume a job that doesn't have saved state data: job <name='{1}', uuid='{0}'>"
 	)]
 	MissingJobDataState(Uuid, String),
+	#[error("Job not found: <uuid='{0}'>")]
+	JobNotFound(Uuid),
+	#[error("Tried to resume a job that isn't paused: <uuid='{0}'>")]
+	JobNotPaused(Uuid),
 	#[error("missing some job data: '{value}'")]
 	MissingData { value: String },
 	#[error("Location manager error: {0}")]
@@ -51,6 +70,10 @@ pub enum JobError {
 	OsStr,
 	#[error("error converting/handling paths")]
 	Path,
+	#[error("can't copy a directory into itself (path: {0:?})")]
+	CopyIntoItself(PathBuf),
+	#[error("copy of a moved file doesn't match its source, which was kept (path: {0:?})")]
+	MoveVerification(PathBuf),
 
 	// Specific job errors
 	#[error("Indexer error: {0}")]
@@ -63,6 +86,18 @@ pub enum JobError {
 	IdentifierError(#[from] FileIdentifierJobError),
 	#[error("Crypto error: {0}")]
 	CryptoError(#[from] CryptoError),
+	#[error("Library merge error: {0}")]
+	LibraryMergeError(#[from] LibraryMergeError),
+	#[error("Extension error: {0}")]
+	ExtensionError(#[from] ExtensionError),
+	#[error("Trash error: {0}")]
+	TrashError(#[from] TrashError),
+	#[error("Search error: {0}")]
+	SearchError(#[from] SearchError),
+	#[error("Archive error: {0}")]
+	ArchiveError(#[from] ArchiveError),
+	#[error("Conversion error: {0}")]
+	ConvertError(#[from] ConvertError),
 
 	// Not errors
 	#[error("Job had a early finish: <name='{name}', reason='{reason}'>")]
@@ -71,6 +106,102 @@ pub enum JobError {
 	JobDataNotFound(String),
 	#[error("Job paused")]
 	Paused(Vec<u8>),
+	#[error("Job canceled")]
+	Canceled,
+}
+
+impl JobError {
+	/// is_transient tells if the error can go away by itself, like a file locked by another program
+	/// or a network share which dropped for a moment, so the step which hit it is worth trying again.
+	pub fn is_transient(&self) -> bool {
+		let mut source: Option<&(dyn std::error::Error + 'static)> = Some(self);
+		while let Some(error) = source {
+			if let Some(e) = error.downcast_ref::<io::Error>() {
+				return is_transient_io_error(e);
+			}
+			source = error.source();
+		}
+
+		false
+	}
+}
+
+fn is_transient_io_error(e: &io::Error) -> bool {
+	if matches!(
+		e.kind(),
+		io::ErrorKind::Interrupted
+			| io::ErrorKind::WouldBlock
+			| io::ErrorKind::TimedOut
+			| io::ErrorKind::ConnectionReset
+			| io::ErrorKind::ConnectionAborted
+			| io::ErrorKind::NotConnected
+			| io::ErrorKind::BrokenPipe
+	) {
+		return true;
+	}
+
+	// Files locked by another program, which have no error kind of their own
+	#[cfg(windows)]
+	const LOCKED_FILE_ERRORS: &[i32] = &[
+		32, // ERROR_SHARING_VIOLATION
+		33, // ERROR_LOCK_VIOLATION
+	];
+	#[cfg(not(windows))]
+	const LOCKED_FILE_ERRORS: &[i32] = &[
+		16, // EBUSY
+		26, // ETXTBSY
+	];
+
+	e.raw_os_error()
+		.map_or(false, |code| LOCKED_FILE_ERRORS.contains(&code))
+}
+
+/// RetryPolicy is how many times a job step failing with a transient error is tried, and how long to wait in between.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+	/// max_attempts is how many times a step is tried in total, 1 meaning it isn't tried again
+	pub max_attempts: u32,
+	/// backoff is how long to wait before the first retry, doubling with each retry after it
+	pub backoff: Duration,
+	/// max_backoff caps the wait before a retry
+	pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+	pub const NONE: Self = Self {
+		max_attempts: 1,
+		backoff: Duration::ZERO,
+		max_backoff: Duration::ZERO,
+	};
+
+	/// backoff returns how long to wait before the `retry`th retry of a step, starting from 1.
+	fn backoff(&self, retry: u32) -> Duration {
+		self.backoff
+			.saturating_mul(2u32.saturating_pow(retry - 1))
+			.min(self.max_backoff)
+	}
+}
+
+impl Default for RetryPolicy {
+	fn default() -> Self {
+		Self {
+			max_attempts: 3,
+			backoff: Duration::from_secs(1),
+			max_backoff: Duration::from_secs(30),
+		}
+	}
+}
+
+impl From<JobError> for rspc::Error {
+	fn from(error: JobError) -> Self {
+		let code = match error {
+			JobError::JobNotFound(_) => ErrorCode::NotFound,
+			JobError::JobNotPaused(_) => ErrorCode::BadRequest,
+			_ => ErrorCode::InternalServerError,
+		};
+
+		rspc::Error::with_cause(code, error.to_string(), error)
+	}
 }
 
 pub type JobResult = Result<JobMetadata, JobError>;
@@ -92,14 +223,43 @@ pub trait StatefulJob: Send + Sync + Sized {
 	) -> Result<(), JobError>;
 
 	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult;
+
+	/// checkpoints tells if the state must be saved after every completed step, so the job can resume
+	/// from its last completed step if the node crashes, instead of only after a clean pause.
+	fn checkpoints(&self) -> bool {
+		false
+	}
+
+	/// journals tells if each step must be journaled before it runs, along with the file paths it creates through
+	/// [`WorkerContext::journal_file_paths`]. A step interrupted by a crash or a pause has its file paths rolled back
+	/// before it runs again, instead of leaving orphaned file paths. As the job is rebuilt from its state when its first
+	/// step was journaled, only jobs which prepare all of their steps before running them can journal.
+	fn journals(&self) -> bool {
+		false
+	}
+
+	/// retry_policy is how the steps of the job which fail with a transient error are tried again.
+	/// Steps aren't tried again by default, as a step failing midway may have already changed the state of the job
+	/// or the files it works on. Only jobs whose steps can safely run twice should opt in.
+	fn retry_policy(&self) -> RetryPolicy {
+		RetryPolicy::NONE
+	}
+
+	/// priority is the class the job is scheduled in, each class running as many jobs at once as the node config allows.
+	fn priority(&self, _init: &Self::Init) -> JobPriority {
+		JobPriority::Normal
+	}
 }
 
 #[async_trait::async_trait]
 pub trait DynJob: Send + Sync {
 	fn report(&mut self) -> &mut Option<JobReport>;
 	fn name(&self) -> &'static str;
+	fn priority(&self) -> JobPriority;
 	async fn run(&mut self, ctx: WorkerContext) -> JobResult;
 	fn hash(&self) -> u64;
+	/// serialize_state returns the state of the job, which [`Job::resume`] builds the job back from.
+	fn serialize_state(&self) -> Result<Vec<u8>, JobError>;
 }
 
 pub struct Job<SJob: StatefulJob> {
@@ -125,6 +285,16 @@ impl<SJob: StatefulJob> Job<SJob> {
 		})
 	}
 
+	/// run_after makes the job wait for `parent` to complete before it's dispatched, once it's queued
+	/// with [`Library::queue_job`](crate::library::Library::queue_job) before `parent` is spawned.
+	/// It's dropped if `parent` fails or is canceled.
+	pub fn run_after<Parent: StatefulJob>(mut self: Box<Self>, parent: &Job<Parent>) -> Box<Self> {
+		if let (Some(report), Some(parent_report)) = (&mut self.report, &parent.report) {
+			report.parent_id = Some(parent_report.id);
+		}
+		self
+	}
+
 	pub fn resume(mut report: JobReport, stateful_job: SJob) -> Result<Box<Self>, JobError> {
 		let job_state_data = if let Some(data) = report.data.take() {
 			data
@@ -171,6 +341,10 @@ impl<State: StatefulJob> DynJob for Job<State> {
 		self.stateful_job.name()
 	}
 
+	fn priority(&self) -> JobPriority {
+		self.stateful_job.priority(&self.state.init)
+	}
+
 	async fn run(&mut self, ctx: WorkerContext) -> JobResult {
 		let mut job_should_run = true;
 
@@ -189,19 +363,86 @@ impl<State: StatefulJob> DynJob for Job<State> {
 		let mut shutdown_rx = ctx.shutdown_rx();
 		let shutdown_rx_fut = shutdown_rx.recv();
 		tokio::pin!(shutdown_rx_fut);
+		let command_fut = ctx.command();
+		tokio::pin!(command_fut);
+
+		let retry_policy = self.stateful_job.retry_policy();
+		let journals = self.stateful_job.journals();
+		// journaled tells if the job has a journal entry, which holds its whole state from its first journaled step
+		let mut journaled = false;
+		if journals && job_should_run {
+			if let Some(interrupted) = journal::resume_step(&ctx.library, ctx.job_id).await? {
+				// Running the interrupted step again, from the data the job had before it
+				let completed = interrupted
+					.step_number
+					.saturating_sub(self.state.step_number)
+					.min(self.state.steps.len());
+				self.state.steps.drain(..completed);
+				self.state.steps.pop_front();
+				self.state
+					.steps
+					.push_front(rmp_serde::from_slice(&interrupted.step)?);
+				self.state.data = rmp_serde::from_slice(&interrupted.data)?;
+				self.state.step_number = interrupted.step_number;
+				journaled = true;
+			}
+		}
+		// attempt is which try of the current step this is, starting from 1
+		let mut attempt = 1;
 
 		while job_should_run && !self.state.steps.is_empty() {
 			tokio::select! {
-				step_result = self.stateful_job.execute_step(
-					ctx.clone(),
-					&mut self.state,
-				) => {
-					if matches!(step_result, Err(JobError::EarlyFinish { .. })) {
-						info!("{}", step_result.unwrap_err());
-						break;
-					} else {
-						step_result?;
-					};
+				step_result = async {
+					// Waiting as part of the step, so the job can still be paused while backing off
+					if attempt > 1 {
+						sleep(retry_policy.backoff(attempt - 1)).await;
+					}
+					// Retries keep the entry of the first attempt, with the file paths it wrote
+					if journals && attempt == 1 {
+						let step = rmp_serde::to_vec_named(&self.state.steps[0])?;
+						let data = rmp_serde::to_vec_named(&self.state.data)?;
+						if journaled {
+							journal::begin_step(
+								&ctx.library,
+								ctx.job_id,
+								self.state.step_number,
+								step,
+								data,
+							)
+							.await?;
+						} else {
+							journal::begin_journal(
+								&ctx.library,
+								ctx.job_id,
+								rmp_serde::to_vec_named(&self.state)?,
+								self.state.step_number,
+								step,
+								data,
+							)
+							.await?;
+							journaled = true;
+						}
+					}
+					self.stateful_job.execute_step(ctx.clone(), &mut self.state).await
+				} => {
+					match step_result {
+						Err(e @ JobError::EarlyFinish { .. }) => {
+							info!("{e}");
+							break;
+						}
+						Err(e) if e.is_transient() && attempt < retry_policy.max_attempts => {
+							warn!(
+								"Step {} failed with a transient error, retrying (attempt {attempt} of {}): {e:#?}",
+								self.state.step_number, retry_policy.max_attempts
+							);
+							ctx.progress(vec![JobReportUpdate::StepRetried]);
+							attempt += 1;
+							continue;
+						}
+						result => result?,
+					}
+					attempt = 1;
+					// The entry of the step is replaced by the next one, so the job is never left without one
 					self.state.steps.pop_front();
 				}
 				_ = &mut shutdown_rx_fut => {
@@ -211,8 +452,22 @@ impl<State: StatefulJob> DynJob for Job<State> {
 						)
 					);
 				}
+				command = &mut command_fut => {
+					return Err(match command {
+						WorkerCommand::Pause => JobError::Paused(rmp_serde::to_vec_named(&self.state)?),
+						WorkerCommand::Cancel => JobError::Canceled,
+					});
+				}
 			}
 			self.state.step_number += 1;
+
+			if self.stateful_job.checkpoints() && !self.state.steps.is_empty() {
+				ctx.checkpoint(rmp_serde::to_vec_named(&self.state)?);
+			}
+		}
+
+		if journaled {
+			journal::end_journal(&ctx.library, ctx.job_id).await?;
 		}
 
 		self.stateful_job
@@ -225,4 +480,8 @@ impl<State: StatefulJob> DynJob for Job<State> {
 		Hash::hash(self, &mut hasher);
 		hasher.finish()
 	}
+
+	fn serialize_state(&self) -> Result<Vec<u8>, JobError> {
+		Ok(rmp_serde::to_vec_named(&self.state)?)
+	}
 }
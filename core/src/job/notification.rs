@@ -0,0 +1,57 @@
+use crate::{api::CoreEvent, library::Library, util::webhook::send_webhook};
+
+use rspc::Type;
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::{JobReport, JobStatus};
+
+/// Used when the minimum duration of webhooked jobs isn't set, so the many short jobs of a scan aren't sent
+const DEFAULT_JOB_WEBHOOK_MIN_SECONDS: u32 = 60;
+
+/// JobCompleted tells that a job of a library is done running, whether it completed, failed or was canceled.
+#[derive(Serialize, Type, Debug, Clone)]
+pub struct JobCompleted {
+	pub library_id: Uuid,
+	pub id: Uuid,
+	pub name: String,
+	pub status: JobStatus,
+	pub seconds_elapsed: i32,
+	/// error is why the job failed, if it did.
+	pub error: Option<String>,
+}
+
+/// notify_job_completed emits a [`CoreEvent::JobCompleted`] for a job which is done running, and sends it to the job
+/// webhooks of the node if it failed, or if it completed after running for long enough.
+pub(crate) async fn notify_job_completed(library: &Library, report: &JobReport) {
+	let completed = JobCompleted {
+		library_id: library.id,
+		id: report.id,
+		name: report.name.clone(),
+		status: report.status,
+		seconds_elapsed: report.seconds_elapsed,
+		error: report.error.clone(),
+	};
+
+	library.emit(CoreEvent::JobCompleted(completed.clone()));
+
+	let webhooks = library.config().get().await.job_webhooks;
+	let min_seconds = webhooks
+		.min_seconds
+		.unwrap_or(DEFAULT_JOB_WEBHOOK_MIN_SECONDS);
+	let send = match completed.status {
+		JobStatus::Failed => true,
+		JobStatus::Completed => completed.seconds_elapsed >= min_seconds as i32,
+		_ => false,
+	};
+	if !send || webhooks.urls.is_empty() {
+		return;
+	}
+
+	// Slow webhooks must not hold up the worker of the job
+	tokio::spawn(async move {
+		for url in &webhooks.urls {
+			send_webhook(url, &completed).await;
+		}
+	});
+}
@@ -0,0 +1,290 @@
+use crate::{
+	job::Job,
+	library::{Library, LibraryManager},
+	location::{find_location, location_with_indexer_rules, scan_location, LocationError},
+	object::validation::{
+		validator_job::{ObjectValidatorJob, ObjectValidatorJobInit},
+		verifier_job::{ObjectVerifierJob, ObjectVerifierJobInit},
+	},
+	prisma::job_schedule,
+};
+
+use std::{path::PathBuf, str::FromStr, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::QueryError;
+use rspc::{ErrorCode, Type};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::time::interval;
+use tracing::{error, info};
+
+/// How often the schedules of every library are checked for jobs which are due
+const JOB_SCHEDULER_TICK: Duration = Duration::from_secs(60);
+
+/// ScheduledJob is a job which can be run on a schedule.
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum ScheduledJob {
+	/// Rescan indexes a location again, then identifies its files and generates their thumbnails
+	Rescan { location_id: i32 },
+	/// Verify checks the files of a location against their checksums, to find the ones which were corrupted
+	Verify { location_id: i32 },
+	/// Validate computes the checksums of the files of a location which don't have one yet
+	Validate { location_id: i32 },
+}
+
+/// JobSchedule is a rule running a job of a library whenever its cron expression matches.
+#[derive(Serialize, Type, Debug)]
+pub struct JobSchedule {
+	pub id: i32,
+	pub name: Option<String>,
+	pub job: ScheduledJob,
+	pub cron: String,
+	pub enabled: bool,
+	pub last_run: Option<DateTime<Utc>>,
+	/// next_run is when the job will be run next, `None` if the schedule is disabled or never matches again.
+	pub next_run: Option<DateTime<Utc>>,
+	pub date_created: DateTime<Utc>,
+}
+
+#[derive(Error, Debug)]
+pub enum JobScheduleError {
+	#[error("invalid cron expression '{0}': {1}")]
+	InvalidCron(String, cron::error::Error),
+	#[error("job schedule not found: <id='{0}'>")]
+	NotFound(i32),
+	#[error("invalid scheduled job: {0}")]
+	InvalidJob(#[from] serde_json::Error),
+	#[error("location error: {0}")]
+	Location(#[from] LocationError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+}
+
+impl From<JobScheduleError> for rspc::Error {
+	fn from(error: JobScheduleError) -> Self {
+		let code = match error {
+			JobScheduleError::InvalidCron(..) => ErrorCode::BadRequest,
+			JobScheduleError::NotFound(_) => ErrorCode::NotFound,
+			_ => ErrorCode::InternalServerError,
+		};
+
+		rspc::Error::with_cause(code, error.to_string(), error)
+	}
+}
+
+/// parse_cron parses a cron expression, evaluated in UTC. Like in a crontab, it can have 5 fields
+/// (minute, hour, day of month, month and day of week), or it can start with the seconds and end with the year.
+pub fn parse_cron(expression: &str) -> Result<cron::Schedule, JobScheduleError> {
+	let expression = expression.trim();
+	let full_expression = if expression.split_whitespace().count() == 5 {
+		format!("0 {expression}")
+	} else {
+		expression.to_string()
+	};
+
+	cron::Schedule::from_str(&full_expression)
+		.map_err(|e| JobScheduleError::InvalidCron(expression.to_string(), e))
+}
+
+impl TryFrom<job_schedule::Data> for JobSchedule {
+	type Error = JobScheduleError;
+
+	fn try_from(data: job_schedule::Data) -> Result<Self, Self::Error> {
+		let last_run = data.last_run.map(Into::into);
+		let date_created = data.date_created.into();
+		let next_run = if data.enabled {
+			parse_cron(&data.cron)?
+				.after(&last_run.unwrap_or(date_created))
+				.next()
+		} else {
+			None
+		};
+
+		Ok(Self {
+			id: data.id,
+			name: data.name,
+			job: serde_json::from_str(&data.job)?,
+			cron: data.cron,
+			enabled: data.enabled,
+			last_run,
+			next_run,
+			date_created,
+		})
+	}
+}
+
+impl ScheduledJob {
+	/// location_id is the location the job runs on.
+	pub fn location_id(&self) -> i32 {
+		match *self {
+			ScheduledJob::Rescan { location_id }
+			| ScheduledJob::Verify { location_id }
+			| ScheduledJob::Validate { location_id } => location_id,
+		}
+	}
+
+	async fn spawn(&self, library: &Library) -> Result<(), JobScheduleError> {
+		match *self {
+			ScheduledJob::Rescan { location_id } => {
+				let location = find_location(library, location_id)
+					.include(location_with_indexer_rules::include())
+					.exec()
+					.await?
+					.ok_or(LocationError::IdNotFound(location_id))?;
+
+				scan_location(library, location).await?;
+			}
+			ScheduledJob::Verify { location_id } => {
+				check_location_exists(library, location_id).await?;
+
+				library
+					.spawn_job(Job::new(
+						ObjectVerifierJobInit { location_id },
+						ObjectVerifierJob {},
+					))
+					.await;
+			}
+			ScheduledJob::Validate { location_id } => {
+				check_location_exists(library, location_id).await?;
+
+				library
+					.spawn_job(Job::new(
+						ObjectValidatorJobInit {
+							location_id,
+							path: PathBuf::new(),
+							background: true,
+						},
+						ObjectValidatorJob {},
+					))
+					.await;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+async fn check_location_exists(
+	library: &Library,
+	location_id: i32,
+) -> Result<(), JobScheduleError> {
+	find_location(library, location_id)
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	Ok(())
+}
+
+/// run_due_schedules spawns the jobs of the enabled schedules of `library` which were due since they last ran.
+/// Runs missed while the node was off are caught up with once.
+async fn run_due_schedules(library: &Library) -> Result<(), JobScheduleError> {
+	let now = Utc::now();
+
+	for data in library
+		.db
+		.job_schedule()
+		.find_many(vec![job_schedule::enabled::equals(true)])
+		.exec()
+		.await?
+	{
+		let id = data.id;
+		let schedule = match JobSchedule::try_from(data) {
+			Ok(schedule) => schedule,
+			Err(e) => {
+				error!(
+					"Skipping invalid job schedule {id} of library {}: {e:#?}",
+					library.id
+				);
+				continue;
+			}
+		};
+
+		if !schedule.next_run.map_or(false, |next_run| next_run <= now) {
+			continue;
+		}
+
+		info!(
+			"Running scheduled job {:?} of library {}",
+			schedule.job, library.id
+		);
+		if let Err(e) = schedule.job.spawn(library).await {
+			error!(
+				"Failed to run scheduled job {:?} of library {}: {e:#?}",
+				schedule.job, library.id
+			);
+		}
+
+		// Failed runs aren't tried again until the next time the schedule matches
+		library
+			.db
+			.job_schedule()
+			.update(
+				job_schedule::id::equals(id),
+				vec![job_schedule::last_run::set(Some(now.into()))],
+			)
+			.exec()
+			.await?;
+	}
+
+	Ok(())
+}
+
+/// spawn_job_scheduler periodically runs the jobs of the schedules of every loaded library which are due.
+pub fn spawn_job_scheduler(library_manager: Arc<LibraryManager>) {
+	tokio::spawn(async move {
+		let mut interval = interval(JOB_SCHEDULER_TICK);
+
+		loop {
+			interval.tick().await;
+
+			for library in library_manager.get_all_libraries().await {
+				if let Err(e) = run_due_schedules(&library).await {
+					error!(
+						"Failed to run the job schedules of library {}: {e:#?}",
+						library.id
+					);
+				}
+			}
+		}
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use chrono::TimeZone;
+
+	#[test]
+	fn parses_crontab_expressions() {
+		let schedule = parse_cron("30 3 * * *").unwrap();
+		let since = Utc.with_ymd_and_hms(2023, 3, 19, 12, 0, 0).unwrap();
+
+		assert_eq!(
+			schedule.after(&since).next(),
+			Some(Utc.with_ymd_and_hms(2023, 3, 20, 3, 30, 0).unwrap())
+		);
+	}
+
+	#[test]
+	fn parses_expressions_with_seconds() {
+		let schedule = parse_cron("15 30 3 * * *").unwrap();
+		let since = Utc.with_ymd_and_hms(2023, 3, 19, 12, 0, 0).unwrap();
+
+		assert_eq!(
+			schedule.after(&since).next(),
+			Some(Utc.with_ymd_and_hms(2023, 3, 20, 3, 30, 15).unwrap())
+		);
+	}
+
+	#[test]
+	fn rejects_invalid_expressions() {
+		assert!(matches!(
+			parse_cron("every night"),
+			Err(JobScheduleError::InvalidCron(..))
+		));
+	}
+}
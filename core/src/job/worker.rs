@@ -1,19 +1,70 @@
 use crate::invalidate_query;
-use crate::job::{DynJob, JobError, JobManager, JobReportUpdate, JobStatus};
+use crate::job::{notify_job_completed, DynJob, JobError, JobManager, JobReportUpdate, JobStatus};
 use crate::library::Library;
-use std::{sync::Arc, time::Duration};
+use crate::node::record_job_failure;
+use crate::util::{metrics::METRICS, rate_limit::with_job_read_limit};
+use std::{collections::VecDeque, sync::Arc, time::Duration};
 use tokio::sync::oneshot;
 use tokio::{
 	sync::{
 		broadcast,
 		mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
-		Mutex,
+		watch, Mutex,
 	},
 	time::{interval_at, Instant},
 };
-use tracing::{error, info, warn};
+use tracing::{error, info, info_span, warn, Instrument};
+use uuid::Uuid;
 
-use super::{JobMetadata, JobReport};
+use super::{journal, JobMetadata, JobReport};
+
+/// How far back the progress of a job is looked at to estimate its throughput and remaining time
+const PROGRESS_RATE_WINDOW: Duration = Duration::from_secs(10);
+
+/// ProgressRate estimates how fast a job goes from its recent progress, so the estimates follow a job
+/// which slows down or speeds up within a few seconds.
+#[derive(Default)]
+struct ProgressRate {
+	/// samples are when they were taken, with the completed bytes and tasks of the job at the time
+	samples: VecDeque<(Instant, u64, i32)>,
+}
+
+impl ProgressRate {
+	/// record takes a sample of the progress of the job and updates the estimates of its report.
+	fn record(&mut self, report: &mut JobReport) {
+		let now = Instant::now();
+		self.samples
+			.push_back((now, report.completed_bytes, report.completed_task_count));
+		while self.samples.len() > 2
+			&& now.duration_since(self.samples[1].0) >= PROGRESS_RATE_WINDOW
+		{
+			self.samples.pop_front();
+		}
+
+		let Some(&(since, bytes_since, tasks_since)) = self.samples.front() else {
+			return;
+		};
+		let elapsed = now.duration_since(since).as_secs_f64();
+		if elapsed < 1.0 {
+			return;
+		}
+
+		let bytes_per_second = report.completed_bytes.saturating_sub(bytes_since) as f64 / elapsed;
+		let tasks_per_second = (report.completed_task_count - tasks_since).max(0) as f64 / elapsed;
+
+		report.throughput = (bytes_per_second > 0.0).then_some(bytes_per_second as u64);
+		// Bytes are a better measure of the work left when the job knows them, as tasks can vary a lot in size
+		report.eta_seconds = if report.total_bytes > 0 && bytes_per_second > 0.0 {
+			let bytes_left = report.total_bytes.saturating_sub(report.completed_bytes);
+			Some((bytes_left as f64 / bytes_per_second) as u32)
+		} else if report.task_count > 0 && tasks_per_second > 0.0 {
+			let tasks_left = (report.task_count - report.completed_task_count).max(0);
+			Some((tasks_left as f64 / tasks_per_second) as u32)
+		} else {
+			None
+		};
+	}
+}
 
 // used to update the worker state from inside the worker thread
 #[derive(Debug)]
@@ -23,15 +74,29 @@ pub enum WorkerEvent {
 		debounce: bool,
 	},
 	Completed(oneshot::Sender<()>, JobMetadata),
-	Failed(oneshot::Sender<()>),
+	Failed(oneshot::Sender<()>, String),
 	Paused(Vec<u8>, oneshot::Sender<()>),
+	Canceled(oneshot::Sender<()>),
+	Checkpointed(Vec<u8>),
+}
+
+/// WorkerCommand is sent to the job of a single worker, which stops at the end of its current step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerCommand {
+	/// Pause saves the state of the job so it can be resumed later
+	Pause,
+	/// Cancel drops the state of the job
+	Cancel,
 }
 
 #[derive(Clone)]
 pub struct WorkerContext {
 	pub library: Library,
+	/// job_id is the id of the report of the running job
+	pub job_id: Uuid,
 	events_tx: UnboundedSender<WorkerEvent>,
 	shutdown_tx: Arc<broadcast::Sender<()>>,
+	commands_rx: watch::Receiver<Option<WorkerCommand>>,
 }
 
 impl WorkerContext {
@@ -52,9 +117,40 @@ impl WorkerContext {
 			.expect("critical error: failed to send worker worker progress event updates");
 	}
 
+	/// checkpoint saves the state of the running job, which is resumed from it if the node crashes.
+	pub fn checkpoint(&self, state: Vec<u8>) {
+		self.events_tx
+			.send(WorkerEvent::Checkpointed(state))
+			.expect("critical error: failed to send worker checkpoint event");
+	}
+
+	/// journal_file_paths records the file paths the running step is about to create in a location,
+	/// so they are rolled back if the step is interrupted. See [`super::StatefulJob::journals`].
+	pub async fn journal_file_paths(
+		&self,
+		location_id: i32,
+		ids: impl IntoIterator<Item = i32>,
+	) -> Result<(), JobError> {
+		journal::journal_file_paths(&self.library, self.job_id, location_id, ids).await
+	}
+
 	pub fn shutdown_rx(&self) -> broadcast::Receiver<()> {
 		self.shutdown_tx.subscribe()
 	}
+
+	/// command resolves once the job has been told to pause or to cancel, and never otherwise.
+	pub async fn command(&self) -> WorkerCommand {
+		let mut commands_rx = self.commands_rx.clone();
+		loop {
+			if let Some(command) = *commands_rx.borrow() {
+				return command;
+			}
+			if commands_rx.changed().await.is_err() {
+				// The worker is gone, so no command will ever come
+				return std::future::pending().await;
+			}
+		}
+	}
 }
 
 // a worker is a dedicated thread that runs a single job
@@ -64,23 +160,42 @@ pub struct Worker {
 	report: JobReport,
 	worker_events_tx: UnboundedSender<WorkerEvent>,
 	worker_events_rx: Option<UnboundedReceiver<WorkerEvent>>,
+	commands_tx: watch::Sender<Option<WorkerCommand>>,
 }
 
 impl Worker {
 	pub fn new(job: Box<dyn DynJob>, report: JobReport) -> Self {
 		let (worker_events_tx, worker_events_rx) = unbounded_channel();
+		let (commands_tx, _) = watch::channel(None);
 
 		Self {
 			job: Some(job),
 			report,
 			worker_events_tx,
 			worker_events_rx: Some(worker_events_rx),
+			commands_tx,
 		}
 	}
 
 	pub fn report(&self) -> JobReport {
 		self.report.clone()
 	}
+
+	/// command tells the job to pause or to cancel once its current step is done. The report shows the new status
+	/// right away, while the job winds down. Returns false if the job was already told to stop.
+	pub fn command(&mut self, command: WorkerCommand) -> bool {
+		if self.commands_tx.borrow().is_some() {
+			return false;
+		}
+
+		self.commands_tx.send_replace(Some(command));
+		self.report.status = match command {
+			WorkerCommand::Pause => JobStatus::Paused,
+			WorkerCommand::Cancel => JobStatus::Canceled,
+		};
+
+		true
+	}
 	// spawns a thread and extracts channel sender to communicate with it
 	pub async fn spawn(
 		job_manager: Arc<JobManager>,
@@ -100,8 +215,11 @@ impl Worker {
 			.take()
 			.expect("critical error: missing job on worker");
 
+		let commands_rx = worker.commands_tx.subscribe();
+
 		let job_hash = job.hash();
 		let job_id = worker.report.id;
+		let span = info_span!("job", name = %worker.report.name, id = %job_id);
 		let old_status = worker.report.status;
 
 		worker.report.status = JobStatus::Running;
@@ -126,8 +244,10 @@ impl Worker {
 		tokio::spawn(async move {
 			let worker_ctx = WorkerContext {
 				library: library.clone(),
+				job_id,
 				events_tx: worker_events_tx,
 				shutdown_tx: job_manager.shutdown_tx(),
+				commands_rx,
 			};
 
 			// track time
@@ -152,34 +272,57 @@ impl Worker {
 			});
 
 			let (done_tx, done_rx) = oneshot::channel();
-
-			match job.run(worker_ctx.clone()).await {
+			let job_name = job.name();
+
+			let read_limit = library
+				.config()
+				.get()
+				.await
+				.job_read_limits
+				.get(job_name)
+				.copied();
+			let run = with_job_read_limit(job_name, read_limit, job.run(worker_ctx.clone()));
+
+			let status = match run.instrument(span).await {
 				Ok(metadata) => {
 					// handle completion
 					worker_ctx
 						.events_tx
 						.send(WorkerEvent::Completed(done_tx, metadata))
 						.expect("critical error: failed to send worker complete event");
+					JobStatus::Completed
 				}
 				Err(JobError::Paused(state)) => {
 					worker_ctx
 						.events_tx
 						.send(WorkerEvent::Paused(state, done_tx))
 						.expect("critical error: failed to send worker pause event");
+					JobStatus::Paused
+				}
+				Err(JobError::Canceled) => {
+					worker_ctx
+						.events_tx
+						.send(WorkerEvent::Canceled(done_tx))
+						.expect("critical error: failed to send worker cancel event");
+					JobStatus::Canceled
 				}
 				Err(e) => {
 					error!("job '{}' failed with error: {:#?}", job_id, e);
+					record_job_failure(&worker_ctx.library.config(), job_name, &e).await;
 					worker_ctx
 						.events_tx
-						.send(WorkerEvent::Failed(done_tx))
+						.send(WorkerEvent::Failed(done_tx, e.to_string()))
 						.expect("critical error: failed to send worker fail event");
+					JobStatus::Failed
 				}
-			}
+			};
 
 			if let Err(e) = done_rx.await {
 				error!("failed to wait for worker completion: {:#?}", e);
 			}
-			job_manager.complete(&library, job_id, job_hash).await;
+			job_manager
+				.complete(&library, job_id, job_hash, status)
+				.await;
 		});
 
 		Ok(())
@@ -191,6 +334,10 @@ impl Worker {
 		library: Library,
 	) {
 		let mut last = Instant::now();
+		let started = Instant::now();
+		// The time taken by the previous runs of a resumed job
+		let previously_elapsed = worker.lock().await.report.seconds_elapsed;
+		let mut progress_rate = ProgressRate::default();
 
 		while let Some(command) = worker_events_rx.recv().await {
 			let mut worker = worker.lock().await;
@@ -222,14 +369,41 @@ impl Worker {
 							}
 							JobReportUpdate::SecondsElapsed(seconds) => {
 								worker.report.seconds_elapsed += seconds as i32;
+								progress_rate.record(&mut worker.report);
+							}
+							JobReportUpdate::StepRetried => {
+								worker.report.step_retries += 1;
+							}
+							JobReportUpdate::ItemsProcessed(items) => {
+								worker.report.items_processed += items as i32;
+							}
+							JobReportUpdate::BytesRead(bytes) => {
+								worker.report.bytes_read += bytes;
+							}
+							JobReportUpdate::TotalBytes(bytes) => {
+								worker.report.total_bytes = bytes;
+							}
+							JobReportUpdate::CompletedBytes(bytes) => {
+								worker.report.completed_bytes += bytes;
 							}
 						}
 					}
 
 					invalidate_query!(library, "jobs.getRunning");
 				}
+				WorkerEvent::Checkpointed(state) => {
+					if worker.report.status != JobStatus::Running {
+						continue;
+					};
+					worker.report.data = Some(state);
+					if let Err(e) = worker.report.update(&library).await {
+						error!("failed to save job checkpoint: {:#?}", e);
+					}
+				}
 				WorkerEvent::Completed(done_tx, metadata) => {
 					worker.report.status = JobStatus::Completed;
+					worker.report.seconds_elapsed =
+						previously_elapsed + started.elapsed().as_secs() as i32;
 					worker.report.data = None;
 					worker.report.metadata = metadata;
 					if let Err(e) = worker.report.update(&library).await {
@@ -239,8 +413,12 @@ impl Worker {
 					invalidate_query!(library, "jobs.isRunning");
 					invalidate_query!(library, "jobs.getRunning");
 					invalidate_query!(library, "jobs.getHistory");
+					invalidate_query!(library, "jobs.history");
+
+					METRICS.record_job_duration(&worker.report.name, started.elapsed());
 
 					info!("{}", worker.report);
+					notify_job_completed(&library, &worker.report).await;
 
 					done_tx
 						.send(())
@@ -248,16 +426,21 @@ impl Worker {
 
 					break;
 				}
-				WorkerEvent::Failed(done_tx) => {
+				WorkerEvent::Failed(done_tx, error) => {
 					worker.report.status = JobStatus::Failed;
+					worker.report.seconds_elapsed =
+						previously_elapsed + started.elapsed().as_secs() as i32;
 					worker.report.data = None;
+					worker.report.error = Some(error);
 					if let Err(e) = worker.report.update(&library).await {
 						error!("failed to update job report: {:#?}", e);
 					}
 
 					invalidate_query!(library, "library.list");
+					invalidate_query!(library, "jobs.history");
 
 					warn!("{}", worker.report);
+					notify_job_completed(&library, &worker.report).await;
 
 					done_tx
 						.send(())
@@ -267,6 +450,8 @@ impl Worker {
 				}
 				WorkerEvent::Paused(state, done_tx) => {
 					worker.report.status = JobStatus::Paused;
+					worker.report.seconds_elapsed =
+						previously_elapsed + started.elapsed().as_secs() as i32;
 					worker.report.data = Some(state);
 					if let Err(e) = worker.report.update(&library).await {
 						error!("failed to update job report: {:#?}", e);
@@ -274,7 +459,33 @@ impl Worker {
 
 					info!("{}", worker.report);
 
+					invalidate_query!(library, "jobs.isRunning");
+					invalidate_query!(library, "jobs.getRunning");
+					invalidate_query!(library, "jobs.getHistory");
+					invalidate_query!(library, "jobs.history");
+
+					done_tx
+						.send(())
+						.expect("critical error: failed to send worker completion");
+
+					break;
+				}
+				WorkerEvent::Canceled(done_tx) => {
+					worker.report.status = JobStatus::Canceled;
+					worker.report.seconds_elapsed =
+						previously_elapsed + started.elapsed().as_secs() as i32;
+					worker.report.data = None;
+					if let Err(e) = worker.report.update(&library).await {
+						error!("failed to update job report: {:#?}", e);
+					}
+
+					info!("{}", worker.report);
+					notify_job_completed(&library, &worker.report).await;
+
+					invalidate_query!(library, "jobs.isRunning");
+					invalidate_query!(library, "jobs.getRunning");
 					invalidate_query!(library, "jobs.getHistory");
+					invalidate_query!(library, "jobs.history");
 
 					done_tx
 						.send(())
@@ -1,21 +1,45 @@
 use crate::{
-	api::{CoreEvent, Ctx, Router},
-	job::JobManager,
-	library::LibraryManager,
+	api::{
+		utils::{spawn_explorer_cache_invalidator, InvalidateOperationEvent},
+		CoreEvent, Ctx, Router,
+	},
+	extension::ExtensionHost,
+	job::{schedule::spawn_job_scheduler, JobManager, JobPriority},
+	library::{
+		mark_node_running, mark_node_stopped, recover_from_crash, spawn_backup_scheduler,
+		spawn_maintenance_scheduler, spawn_storage_budget_monitor, spawn_trash_purger,
+		LibraryManager,
+	},
 	location::{LocationManager, LocationManagerError},
-	node::NodeConfigManager,
+	node::{
+		log_writer, otlp_layer, remove_old_logs, shutdown_otlp, spawn_telemetry_reporter,
+		NodeConfigManager, NodeUser, UserManager,
+	},
+	object::{
+		preview::thumbnail_gc_job::spawn_thumbnail_gc_scheduler,
+		search::saved::spawn_smart_search_invalidator, version::spawn_file_version_pruner,
+	},
 	p2p::P2PManager,
 };
-use util::secure_temp_keystore::SecureTempKeystore;
+use util::{
+	governor::governor, metrics::METRICS, resources::init_resource_limiter,
+	secure_temp_keystore::SecureTempKeystore,
+};
 
-use std::{path::Path, sync::Arc};
+use once_cell::sync::OnceCell;
+use std::{
+	path::Path,
+	sync::{Arc, Weak},
+};
 use thiserror::Error;
 use tokio::{fs, sync::broadcast};
-use tracing::{debug, error, info};
-use tracing_subscriber::{prelude::*, EnvFilter};
+use tracing::{debug, error, info, warn};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{prelude::*, reload, EnvFilter};
 
 pub mod api;
 pub mod custom_uri;
+pub(crate) mod extension;
 pub(crate) mod job;
 pub(crate) mod library;
 pub(crate) mod location;
@@ -35,6 +59,9 @@ pub struct NodeContext {
 	pub jobs: Arc<JobManager>,
 	pub location_manager: Arc<LocationManager>,
 	pub event_bus_tx: broadcast::Sender<CoreEvent>,
+	pub extensions: Arc<ExtensionHost>,
+	/// library_manager is set once the libraries are loaded, for the jobs which go through every library of the node
+	pub library_manager: Arc<OnceCell<Weak<LibraryManager>>>,
 }
 
 pub struct Node {
@@ -45,6 +72,10 @@ pub struct Node {
 	p2p: Arc<P2PManager>,
 	event_bus: (broadcast::Sender<CoreEvent>, broadcast::Receiver<CoreEvent>),
 	secure_temp_keystore: Arc<SecureTempKeystore>,
+	users: Arc<UserManager>,
+	extensions: Arc<ExtensionHost>,
+	// Flushes the log files when the node is dropped
+	_log_guard: WorkerGuard,
 }
 
 #[cfg(not(target_os = "android"))]
@@ -57,6 +88,53 @@ const CONSOLE_LOG_FILTER: tracing_subscriber::filter::LevelFilter = {
 	}
 };
 
+/// log_filter returns the default log filter, with the directives of the `log_level` set in the node config added to it.
+fn log_filter(log_level: Option<&str>) -> EnvFilter {
+	let filter = EnvFilter::from_default_env()
+		.add_directive("warn".parse().expect("Error invalid tracing directive!"))
+		.add_directive(
+			"sd_core=debug"
+				.parse()
+				.expect("Error invalid tracing directive!"),
+		)
+		.add_directive(
+			"sd_core::location::manager=info"
+				.parse()
+				.expect("Error invalid tracing directive!"),
+		)
+		.add_directive(
+			"sd_core_mobile=debug"
+				.parse()
+				.expect("Error invalid tracing directive!"),
+		)
+		.add_directive(
+			"sd-p2p=debug"
+				.parse()
+				.expect("Error invalid tracing directive!"),
+		)
+		.add_directive(
+			"server=debug"
+				.parse()
+				.expect("Error invalid tracing directive!"),
+		)
+		.add_directive(
+			"desktop=debug"
+				.parse()
+				.expect("Error invalid tracing directive!"),
+		);
+	// .add_directive(
+	// 	"rspc=debug"
+	// 		.parse()
+	// 		.expect("Error invalid tracing directive!"),
+	// );
+
+	log_level
+		.into_iter()
+		.flat_map(|log_level| log_level.split(','))
+		.filter_map(|directive| directive.trim().parse().ok())
+		.fold(filter, |filter, directive| filter.add_directive(directive))
+}
+
 impl Node {
 	pub async fn new(data_dir: impl AsRef<Path>) -> Result<(Arc<Node>, Arc<Router>), NodeError> {
 		let data_dir = data_dir.as_ref();
@@ -66,69 +144,56 @@ impl Node {
 
 		// dbg!(get_object_kind_from_extension("png"));
 
-		// let (non_blocking, _guard) = tracing_appender::non_blocking(rolling::daily(
-		// 	Path::new(&data_dir).join("logs"),
-		// 	"log",
-		// ));
-		// TODO: Make logs automatically delete after x time https://github.com/tokio-rs/tracing/pull/2169
-
-		let subscriber = tracing_subscriber::registry().with(
-			EnvFilter::from_default_env()
-				.add_directive("warn".parse().expect("Error invalid tracing directive!"))
-				.add_directive(
-					"sd_core=debug"
-						.parse()
-						.expect("Error invalid tracing directive!"),
-				)
-				.add_directive(
-					"sd_core::location::manager=info"
-						.parse()
-						.expect("Error invalid tracing directive!"),
-				)
-				.add_directive(
-					"sd_core_mobile=debug"
-						.parse()
-						.expect("Error invalid tracing directive!"),
-				)
-				.add_directive(
-					"sd-p2p=debug"
-						.parse()
-						.expect("Error invalid tracing directive!"),
-				)
-				.add_directive(
-					"server=debug"
-						.parse()
-						.expect("Error invalid tracing directive!"),
-				)
-				.add_directive(
-					"desktop=debug"
-						.parse()
-						.expect("Error invalid tracing directive!"),
-				), // .add_directive(
-			    // 	"rspc=debug"
-			    // 		.parse()
-			    // 		.expect("Error invalid tracing directive!"),
-			    // ),
-		);
+		// Structured logs are also written to daily files, so they can be queried with `nodes.logs`
+		let (log_writer, log_guard) = log_writer(data_dir);
+		remove_old_logs(data_dir).await;
+
+		let config = NodeConfigManager::new(data_dir.to_path_buf()).await?;
+		let users = UserManager::new(data_dir.to_path_buf())?;
+		let node_config = config.get().await;
+		init_resource_limiter(&node_config.resources);
+		governor().set_config(node_config.throttling.clone());
+
+		// Spans are only exported when an OTLP collector is set in the node config
+		let (otlp, otlp_error) = match node_config.otlp.as_ref().map(otlp_layer) {
+			Some(Ok(layer)) => (Some(layer), None),
+			Some(Err(e)) => (None, Some(e)),
+			None => (None, None),
+		};
+
+		// The log filter is reloaded when the log level is changed in the node config
+		let (log_filter, log_filter_handle) =
+			reload::Layer::new(log_filter(node_config.log_level.as_deref()));
+
+		let subscriber = tracing_subscriber::registry().with(log_filter);
 		#[cfg(not(target_os = "android"))]
-		let subscriber = subscriber.with(tracing_subscriber::fmt::layer().with_filter(CONSOLE_LOG_FILTER));
+		let subscriber =
+			subscriber.with(tracing_subscriber::fmt::layer().with_filter(CONSOLE_LOG_FILTER));
 		// #[cfg(target_os = "android")]
 		// let subscriber = subscriber.with(tracing_android::layer("com.spacedrive.app").unwrap()); // TODO: This is not working
 		subscriber
-			// .with(
-			// 	Layer::default()
-			// 		.with_writer(non_blocking)
-			// 		.with_ansi(false)
-			// 		.with_filter(LevelFilter::DEBUG),
-			// )
+			.with(
+				tracing_subscriber::fmt::layer()
+					.json()
+					.with_writer(log_writer)
+					.with_ansi(false),
+			)
+			.with(otlp)
 			.init();
 
+		if let Some(e) = otlp_error {
+			warn!("Failed to set up the OTLP exporter, spans won't be exported: {e:#?}");
+		}
+
 		let event_bus = broadcast::channel(1024);
-		let config = NodeConfigManager::new(data_dir.to_path_buf()).await?;
 
 		let jobs = JobManager::new();
+		for priority in JobPriority::ALL {
+			jobs.set_max_workers(priority, node_config.max_jobs(priority));
+		}
 		let location_manager = LocationManager::new();
 		let secure_temp_keystore = SecureTempKeystore::new();
+		let extensions = ExtensionHost::new(data_dir.to_path_buf())?;
 		let library_manager = LibraryManager::new(
 			data_dir.join("libraries"),
 			NodeContext {
@@ -136,10 +201,17 @@ impl Node {
 				jobs: Arc::clone(&jobs),
 				location_manager: Arc::clone(&location_manager),
 				event_bus_tx: event_bus.0.clone(),
+				extensions: Arc::clone(&extensions),
+				library_manager: Default::default(),
 			},
 		)
 		.await?;
 
+		// Must run before anything can spawn or resume jobs
+		if let Some(crashed_run_started) = mark_node_running(data_dir).await {
+			recover_from_crash(&library_manager, data_dir, crashed_run_started).await;
+		}
+
 		// Adding already existing locations for location management
 		for library in library_manager.get_all_libraries().await {
 			for location in library
@@ -174,6 +246,54 @@ impl Node {
 			}
 		});
 
+		spawn_backup_scheduler(Arc::clone(&library_manager));
+		spawn_maintenance_scheduler(Arc::clone(&library_manager));
+		spawn_storage_budget_monitor(Arc::clone(&library_manager));
+		spawn_trash_purger(Arc::clone(&library_manager));
+		spawn_file_version_pruner(Arc::clone(&library_manager));
+		spawn_thumbnail_gc_scheduler(Arc::clone(&library_manager));
+		spawn_job_scheduler(Arc::clone(&library_manager));
+		spawn_telemetry_reporter(Arc::clone(&library_manager), Arc::clone(&config));
+		spawn_explorer_cache_invalidator(event_bus.0.subscribe());
+		spawn_smart_search_invalidator(Arc::clone(&library_manager), event_bus.0.clone());
+
+		// Safe changes to the node config file are applied without a restart
+		config.watch();
+		tokio::spawn({
+			let mut config_changes = config.subscribe();
+			let jobs = Arc::clone(&jobs);
+			let event_bus_tx = event_bus.0.clone();
+			async move {
+				loop {
+					let node_config = match config_changes.recv().await {
+						Ok(node_config) => node_config,
+						Err(broadcast::error::RecvError::Lagged(_)) => continue,
+						Err(broadcast::error::RecvError::Closed) => break,
+					};
+
+					if let Err(e) =
+						log_filter_handle.reload(log_filter(node_config.log_level.as_deref()))
+					{
+						error!("Failed to reload the log filter: {e:#?}");
+					}
+					for priority in JobPriority::ALL {
+						jobs.set_max_workers(priority, node_config.max_jobs(priority));
+					}
+					governor().set_config(node_config.throttling.clone());
+					// Thumbnail settings are read from the config every time a thumbnail is generated
+
+					event_bus_tx
+						.send(CoreEvent::InvalidateOperation(
+							InvalidateOperationEvent::dangerously_create(
+								"nodeState",
+								serde_json::Value::Null,
+							),
+						))
+						.ok();
+				}
+			}
+		});
+
 		let p2p = P2PManager::new(config.clone()).await;
 
 		let router = api::mount();
@@ -184,13 +304,22 @@ impl Node {
 			p2p,
 			event_bus,
 			secure_temp_keystore,
+			users,
+			extensions,
+			_log_guard: log_guard,
 		};
 
 		info!("Spacedrive online.");
 		Ok((Arc::new(node), router))
 	}
 
+	/// get_request_context returns the context for a client with full access to the node, like the desktop app.
 	pub fn get_request_context(&self) -> Ctx {
+		self.get_user_request_context(None)
+	}
+
+	/// get_user_request_context returns the context for a request made by the given user, which restricts what it can access.
+	pub fn get_user_request_context(&self, user: Option<NodeUser>) -> Ctx {
 		Ctx {
 			library_manager: Arc::clone(&self.library_manager),
 			config: Arc::clone(&self.config),
@@ -198,12 +327,33 @@ impl Node {
 			p2p: Arc::clone(&self.p2p),
 			event_bus: self.event_bus.0.clone(),
 			secure_temp_keystore: Arc::clone(&self.secure_temp_keystore),
+			users: Arc::clone(&self.users),
+			extensions: Arc::clone(&self.extensions),
+			user,
 		}
 	}
 
+	/// has_users returns whether user accounts are set up on this node, in which case every remote client must authenticate as one of them.
+	pub fn has_users(&self) -> bool {
+		self.users.is_enabled()
+	}
+
+	/// authenticate returns the user owning the given token.
+	pub fn authenticate(&self, token: &str) -> Option<NodeUser> {
+		self.users.authenticate(token)
+	}
+
+	/// metrics returns the metrics of this node in the Prometheus text format.
+	pub async fn metrics(&self) -> String {
+		let (queued_jobs, running_jobs) = self.jobs.queue_depth().await;
+		METRICS.render(queued_jobs, running_jobs)
+	}
+
 	pub async fn shutdown(&self) {
 		info!("Spacedrive shutting down...");
 		self.jobs.pause().await;
+		mark_node_stopped(self.config.data_directory()).await;
+		shutdown_otlp();
 		info!("Spacedrive Core shutdown successful!");
 	}
 }
@@ -215,6 +365,10 @@ pub enum NodeError {
 	FailedToCreateDataDirectory(#[from] std::io::Error),
 	#[error("Failed to initialize config: {0}")]
 	FailedToInitializeConfig(#[from] node::NodeConfigError),
+	#[error("Failed to load users: {0}")]
+	FailedToLoadUsers(#[from] node::UserError),
+	#[error("Failed to load extensions: {0}")]
+	FailedToLoadExtensions(#[from] extension::ExtensionError),
 	#[error("Failed to initialize library manager: {0}")]
 	FailedToInitializeLibraryManager(#[from] library::LibraryManagerError),
 	#[error("Location manager error: {0}")]
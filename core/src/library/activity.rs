@@ -0,0 +1,57 @@
+use crate::{invalidate_query, prisma::activity};
+
+use int_enum::IntEnum;
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use super::Library;
+
+/// ActivityKind is the kind of action recorded in the library activity log.
+/// The values are stored in the database, so existing variants must never change their value.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type, Eq, PartialEq, IntEnum)]
+pub enum ActivityKind {
+	LocationAdded = 0,
+	LocationRemoved = 1,
+	FilesDeleted = 2,
+	FilesErased = 3,
+	ObjectDeleted = 4,
+	TagDeleted = 5,
+	TagRemoved = 6,
+	DevicePaired = 7,
+	FilesTrashed = 8,
+	FilesRestored = 9,
+	DuplicatesHardlinked = 10,
+}
+
+impl Library {
+	/// record_activity appends an entry to the library activity log.
+	/// Failing to record an activity is logged but never fails the action itself.
+	pub(crate) async fn record_activity(
+		&self,
+		kind: ActivityKind,
+		message: impl Into<String>,
+		data: Option<serde_json::Value>,
+	) {
+		let data = data.and_then(|data| serde_json::to_vec(&data).ok());
+
+		if let Err(e) = self
+			.db
+			.activity()
+			.create(
+				kind.int_value(),
+				message.into(),
+				crate::prisma::node::id::equals(self.node_local_id),
+				vec![activity::data::set(data)],
+			)
+			.exec()
+			.await
+		{
+			error!("Failed to record {kind:?} activity: {e:#?}");
+			return;
+		}
+
+		invalidate_query!(self, "library.activity");
+	}
+}
@@ -0,0 +1,460 @@
+use crate::{
+	job::{
+		Job, JobError, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob,
+		WorkerContext,
+	},
+	library::{Library, LibraryConfig, LibraryManager, LibraryManagerError},
+	node::ThumbnailFormat,
+	object::preview::{thumbnail_path, THUMBNAIL_CACHE_DIR_NAME},
+	prisma::file_path,
+};
+
+use std::{
+	collections::{HashSet, VecDeque},
+	fs::File,
+	hash::Hash,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::{raw, PrismaValue};
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::AsyncReadExt, task::spawn_blocking, time::interval};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+pub const LIBRARY_BACKUP_JOB_NAME: &str = "library_backup";
+/// BACKUPS_DIR_NAME is the name of the directory inside the node data directory used when no backup directory is configured.
+pub const BACKUPS_DIR_NAME: &str = "backups";
+pub const BACKUP_EXTENSION: &str = "sdbackup";
+pub const BACKUP_MANIFEST_NAME: &str = "manifest.json";
+pub const BACKUP_DB_NAME: &str = "library.db";
+pub const BACKUP_CONFIG_NAME: &str = "library.sdlibrary";
+
+/// How often the scheduler checks if any library is due for a backup
+const BACKUP_SCHEDULER_TICK: Duration = Duration::from_secs(60 * 15);
+const THUMBNAILS_CHUNK_SIZE: usize = 100;
+
+/// `BackupManifest` is stored at the root of every backup archive and describes its contents.
+#[derive(Serialize, Deserialize, Debug, Clone, Type)]
+pub struct BackupManifest {
+	pub library_id: Uuid,
+	pub library_name: String,
+	/// version of Spacedrive which created this backup.
+	pub version: String,
+	pub created_at: DateTime<Utc>,
+	pub includes_thumbnails: bool,
+}
+
+pub struct LibraryBackupJob {}
+
+#[derive(Serialize, Deserialize, Hash, Type)]
+pub struct LibraryBackupJobInit {
+	pub output_dir: Option<PathBuf>,
+	pub include_thumbnails: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LibraryBackupJobState {
+	staging_dir: PathBuf,
+	archive_path: PathBuf,
+	thumbnails_copied: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum LibraryBackupJobStep {
+	Database,
+	Config,
+	Thumbnails(Vec<String>),
+}
+
+/// `default_backups_dir` returns the directory used to store backups when the user didn't choose one.
+pub fn default_backups_dir(library: &Library) -> PathBuf {
+	library.config().data_directory().join(BACKUPS_DIR_NAME)
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for LibraryBackupJob {
+	type Init = LibraryBackupJobInit;
+	type Data = LibraryBackupJobState;
+	type Step = LibraryBackupJobStep;
+
+	fn name(&self) -> &'static str {
+		LIBRARY_BACKUP_JOB_NAME
+	}
+
+	fn priority(&self, _init: &Self::Init) -> JobPriority {
+		JobPriority::Background
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let library = &ctx.library;
+
+		let output_dir = state
+			.init
+			.output_dir
+			.clone()
+			.unwrap_or_else(|| default_backups_dir(library));
+
+		fs::create_dir_all(&output_dir).await?;
+
+		let created_at = Utc::now();
+		let archive_name = format!(
+			"{}-{}.{BACKUP_EXTENSION}",
+			library.id,
+			created_at.format("%Y%m%d%H%M%S")
+		);
+
+		// We stage every file in a hidden directory, so pausing and resuming the job is cheap
+		let staging_dir = output_dir.join(format!(".{archive_name}.partial"));
+		fs::create_dir_all(staging_dir.join(THUMBNAIL_CACHE_DIR_NAME)).await?;
+
+		fs::write(
+			staging_dir.join(BACKUP_MANIFEST_NAME),
+			serde_json::to_vec(&BackupManifest {
+				library_id: library.id,
+				library_name: library.config.name.clone(),
+				version: env!("CARGO_PKG_VERSION").to_string(),
+				created_at,
+				includes_thumbnails: state.init.include_thumbnails,
+			})?,
+		)
+		.await?;
+
+		let mut steps =
+			VecDeque::from([LibraryBackupJobStep::Database, LibraryBackupJobStep::Config]);
+
+		if state.init.include_thumbnails {
+			steps.extend(thumbnail_steps(library).await?);
+		}
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(steps.len()),
+			JobReportUpdate::Message(format!("Backing up library '{}'", library.config.name)),
+		]);
+
+		state.data = Some(LibraryBackupJobState {
+			staging_dir,
+			archive_path: output_dir.join(archive_name),
+			thumbnails_copied: 0,
+		});
+		state.steps = steps;
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let library = &ctx.library;
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		match &state.steps[0] {
+			LibraryBackupJobStep::Database => {
+				snapshot_database(library, data.staging_dir.join(BACKUP_DB_NAME)).await?
+			}
+			LibraryBackupJobStep::Config => {
+				copy_config(library, data.staging_dir.join(BACKUP_CONFIG_NAME)).await?
+			}
+			LibraryBackupJobStep::Thumbnails(cas_ids) => {
+				data.thumbnails_copied +=
+					copy_thumbnails(library, cas_ids, &data.staging_dir).await?;
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		let archive_size = pack_archive(&data.staging_dir, &data.archive_path).await?;
+
+		info!(
+			"Library backup created at {} ({} bytes, {} thumbnails)",
+			data.archive_path.display(),
+			archive_size,
+			data.thumbnails_copied
+		);
+
+		Ok(Some(serde_json::json!({
+			"archive_path": data.archive_path,
+			"archive_size": archive_size,
+			"thumbnails_copied": data.thumbnails_copied,
+		})))
+	}
+}
+
+/// `thumbnail_steps` splits the cas_ids of every identified file in the library into chunks of thumbnails to be copied.
+pub(super) async fn thumbnail_steps(
+	library: &Library,
+) -> Result<Vec<LibraryBackupJobStep>, JobError> {
+	let cas_ids = library
+		.db
+		.file_path()
+		.find_many(vec![file_path::cas_id::not(None)])
+		.select(file_path::select!({ cas_id }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|file_path| file_path.cas_id)
+		.collect::<HashSet<_>>()
+		.into_iter()
+		.collect::<Vec<_>>();
+
+	Ok(cas_ids
+		.chunks(THUMBNAILS_CHUNK_SIZE)
+		.map(|chunk| LibraryBackupJobStep::Thumbnails(chunk.to_vec()))
+		.collect())
+}
+
+/// `snapshot_database` writes a consistent copy of the library database to `path`.
+pub(super) async fn snapshot_database(
+	library: &Library,
+	path: impl AsRef<Path>,
+) -> Result<(), JobError> {
+	let path = path.as_ref();
+
+	// `VACUUM INTO` refuses to overwrite files, which can happen when resuming a job
+	if fs::metadata(path).await.is_ok() {
+		fs::remove_file(path).await?;
+	}
+
+	// `VACUUM INTO` uses SQLite's own read transaction, so we get a consistent snapshot
+	// even while other jobs keep writing to the library
+	library
+		.db
+		._execute_raw(raw!(
+			"VACUUM INTO {}",
+			PrismaValue::String(path.to_str().ok_or(JobError::Path)?.to_string())
+		))
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// `copy_config` copies the library config file to `path`.
+pub(super) async fn copy_config(library: &Library, path: impl AsRef<Path>) -> Result<(), JobError> {
+	fs::copy(
+		library
+			.config()
+			.data_directory()
+			.join("libraries")
+			.join(format!("{}.sdlibrary", library.id)),
+		path,
+	)
+	.await?;
+
+	Ok(())
+}
+
+/// `copy_thumbnails` copies the thumbnails of the given cas_ids into the thumbnails directory of `staging_dir`,
+/// returning how many were copied.
+pub(super) async fn copy_thumbnails(
+	library: &Library,
+	cas_ids: &[String],
+	staging_dir: impl AsRef<Path>,
+) -> Result<usize, JobError> {
+	let thumbnails_dir = library
+		.config()
+		.data_directory()
+		.join(THUMBNAIL_CACHE_DIR_NAME);
+	let target_dir = staging_dir.as_ref().join(THUMBNAIL_CACHE_DIR_NAME);
+
+	let mut copied = 0;
+	for cas_id in cas_ids {
+		// Only the default thumbnails are kept, variants are generated again after a restore
+		for format in ThumbnailFormat::ALL {
+			match fs::copy(
+				thumbnail_path(&thumbnails_dir, None, cas_id, format),
+				thumbnail_path(&target_dir, None, cas_id, format),
+			)
+			.await
+			{
+				Ok(_) => copied += 1,
+				// Not every object has a thumbnail
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+				Err(e) => return Err(e.into()),
+			}
+		}
+	}
+
+	Ok(copied)
+}
+
+/// `pack_archive` bundles `staging_dir` into a tar archive at `archive_path` and removes the staging directory,
+/// returning the size of the archive.
+pub(super) async fn pack_archive(
+	staging_dir: impl AsRef<Path>,
+	archive_path: impl AsRef<Path>,
+) -> Result<u64, JobError> {
+	let staging_dir = staging_dir.as_ref().to_path_buf();
+	let archive_path = archive_path.as_ref().to_path_buf();
+
+	{
+		let staging_dir = staging_dir.clone();
+		let archive_path = archive_path.clone();
+		// `tar` is a blocking API
+		spawn_blocking(move || -> std::io::Result<()> {
+			let mut builder = tar::Builder::new(File::create(&archive_path)?);
+			builder.append_dir_all(".", &staging_dir)?;
+			builder.into_inner()?.sync_all()
+		})
+		.await??;
+	}
+
+	fs::remove_dir_all(&staging_dir).await?;
+
+	Ok(fs::metadata(&archive_path).await?.len())
+}
+
+/// `last_backup_time` returns the modification time of the most recent backup of the given library in `dir`.
+pub async fn last_backup_time(dir: impl AsRef<Path>, library_id: Uuid) -> Option<SystemTime> {
+	let mut read_dir = fs::read_dir(dir).await.ok()?;
+	let prefix = format!("{library_id}-");
+
+	let mut last = None;
+	while let Ok(Some(entry)) = read_dir.next_entry().await {
+		let path = entry.path();
+		let is_backup = path
+			.file_name()
+			.and_then(|name| name.to_str())
+			.map(|name| name.starts_with(&prefix) && name.ends_with(BACKUP_EXTENSION))
+			.unwrap_or(false);
+
+		if !is_backup {
+			continue;
+		}
+
+		if let Ok(modified) = entry.metadata().await.and_then(|m| m.modified()) {
+			if last.map(|last| modified > last).unwrap_or(true) {
+				last = Some(modified);
+			}
+		}
+	}
+
+	last
+}
+
+/// `spawn_backup_scheduler` periodically checks every loaded library with a backup schedule and
+/// spawns a [`LibraryBackupJob`] for the ones that are due.
+pub fn spawn_backup_scheduler(library_manager: Arc<LibraryManager>) {
+	tokio::spawn(async move {
+		let mut interval = interval(BACKUP_SCHEDULER_TICK);
+
+		loop {
+			interval.tick().await;
+
+			for library in library_manager.get_all_libraries().await {
+				let Some(schedule) = library.config.backup.clone() else {
+					continue;
+				};
+
+				let output_dir = schedule
+					.output_dir
+					.clone()
+					.unwrap_or_else(|| default_backups_dir(&library));
+
+				let is_due = match last_backup_time(&output_dir, library.id).await {
+					Some(last) => match last.elapsed() {
+						Ok(elapsed) => {
+							elapsed >= Duration::from_secs(schedule.interval_secs.into())
+						}
+						Err(e) => {
+							warn!(
+								"Last backup of library {} is in the future: {e:#?}",
+								library.id
+							);
+							false
+						}
+					},
+					None => true,
+				};
+
+				if is_due {
+					info!("Running scheduled backup for library {}", library.id);
+					library
+						.spawn_job(Job::new(
+							LibraryBackupJobInit {
+								output_dir: Some(output_dir),
+								include_thumbnails: schedule.include_thumbnails,
+							},
+							LibraryBackupJob {},
+						))
+						.await;
+				}
+			}
+		}
+	});
+}
+
+/// `unpack_backup` extracts an archive created by [`LibraryBackupJob`] or [`super::LibraryExportJob`]
+/// into `target_dir`, and validates that it contains everything needed to load the library.
+pub(super) async fn unpack_backup(
+	archive_path: impl AsRef<Path>,
+	target_dir: impl AsRef<Path>,
+	extension: &str,
+) -> Result<BackupManifest, LibraryManagerError> {
+	let archive_path = archive_path.as_ref().to_path_buf();
+	let target_dir = target_dir.as_ref().to_path_buf();
+
+	if archive_path.extension().and_then(|ext| ext.to_str()) != Some(extension) {
+		return Err(LibraryManagerError::InvalidBackup(format!(
+			"'{}' is not a Spacedrive backup",
+			archive_path.display()
+		)));
+	}
+
+	{
+		let target_dir = target_dir.clone();
+		// `tar` is a blocking API
+		spawn_blocking(move || -> std::io::Result<()> {
+			std::fs::create_dir_all(&target_dir)?;
+			tar::Archive::new(File::open(archive_path)?).unpack(target_dir)
+		})
+		.await??;
+	}
+
+	let manifest = match fs::read(target_dir.join(BACKUP_MANIFEST_NAME)).await {
+		Ok(bytes) => serde_json::from_slice::<BackupManifest>(&bytes)?,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+			return Err(LibraryManagerError::InvalidBackup(
+				"backup is missing its manifest".to_string(),
+			))
+		}
+		Err(e) => return Err(e.into()),
+	};
+
+	// Every SQLite database starts with this 16 bytes header
+	let mut db_header = [0; 16];
+	let has_db_header = match fs::File::open(target_dir.join(BACKUP_DB_NAME)).await {
+		Ok(mut file) => file.read_exact(&mut db_header).await.is_ok(),
+		Err(_) => false,
+	};
+	if !has_db_header || db_header != *b"SQLite format 3\0" {
+		return Err(LibraryManagerError::InvalidBackup(
+			"backup doesn't contain a valid library database".to_string(),
+		));
+	}
+
+	// Making sure the config can be loaded before touching the live library
+	LibraryConfig::read(target_dir.join(BACKUP_CONFIG_NAME)).await?;
+
+	Ok(manifest)
+}
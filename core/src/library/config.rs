@@ -1,42 +1,157 @@
 use std::{
 	fs::File,
-	io::{BufReader, Seek},
-	path::PathBuf,
+	io::BufReader,
+	path::{Path, PathBuf},
 };
 
 use rspc::Type;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+use serde_with::{serde_as, DisplayFromStr};
 use std::io::Write;
+use tracing::{debug, warn};
 use uuid::Uuid;
 
-use crate::node::ConfigMetadata;
+use crate::{node::ConfigMetadata, object::cas::CasIdAlgorithm};
 
-use super::LibraryManagerError;
+use super::{LibraryManagerError, DEFAULT_TRASH_RETENTION_DAYS};
+
+/// LIBRARY_CONFIG_VERSION is the version of the library config format. It's independent of the Spacedrive version
+/// and must be bumped, alongside a new entry in `MIGRATIONS`, whenever the config format changes.
+pub const LIBRARY_CONFIG_VERSION: u32 = 1;
 
 /// LibraryConfig holds the configuration for a specific library. This is stored as a '{uuid}.sdlibrary' file.
-#[derive(Debug, Serialize, Deserialize, Clone, Type, Default)]
+#[derive(Debug, Serialize, Deserialize, Clone, Type)]
 pub struct LibraryConfig {
 	#[serde(flatten)]
 	pub metadata: ConfigMetadata,
+	/// config_version is the version of the format of this config. See [`LIBRARY_CONFIG_VERSION`].
+	pub config_version: u32,
 	/// name is the display name of the library. This is used in the UI and is set by the user.
 	pub name: String,
 	/// description is a user set description of the library. This is used in the UI and is set by the user.
+	#[serde(default)]
 	pub description: String,
+	/// backup holds the schedule used to automatically backup this library. If it's `None`, backups must be triggered manually.
+	#[serde(default)]
+	pub backup: Option<LibraryBackupSchedule>,
+	/// maintenance_interval_secs is the minimum amount of time between two automatic database maintenances. If it's `None`, maintenance must be triggered manually.
+	#[serde(default)]
+	pub maintenance_interval_secs: Option<u32>,
+	/// storage_budget is a soft cap on the disk space used by this library. If it's `None`, usage isn't monitored.
+	#[serde(default)]
+	pub storage_budget: Option<LibraryStorageBudget>,
+	/// trash_retention_days is how long deleted files are kept in the trash before being purged. If it's `None`, they're kept until the trash is emptied.
+	#[serde(default = "default_trash_retention_days")]
+	pub trash_retention_days: Option<u32>,
+	/// file_version_retention is how many of the previous contents of versioned files are kept.
+	#[serde(default)]
+	pub file_version_retention: FileVersionRetention,
+	/// cas_id_algorithm is how the cas_id of newly identified files is generated. Files identified before it changes
+	/// keep their cas_id, and are only deduplicated against files identified with the same algorithm.
+	#[serde(default)]
+	pub cas_id_algorithm: CasIdAlgorithm,
+	/// perceptual_hashing computes a perceptual hash of the images being identified, to find similar images.
+	#[serde(default)]
+	pub perceptual_hashing: bool,
 	// /// is_encrypted is a flag that is set to true if the library is encrypted.
 	// #[serde(default)]
 	// pub is_encrypted: bool,
 }
 
+impl Default for LibraryConfig {
+	fn default() -> Self {
+		Self {
+			metadata: ConfigMetadata::default(),
+			config_version: LIBRARY_CONFIG_VERSION,
+			name: String::new(),
+			description: String::new(),
+			backup: None,
+			maintenance_interval_secs: None,
+			storage_budget: None,
+			trash_retention_days: default_trash_retention_days(),
+			file_version_retention: FileVersionRetention::default(),
+			cas_id_algorithm: CasIdAlgorithm::default(),
+			perceptual_hashing: false,
+		}
+	}
+}
+
+fn default_trash_retention_days() -> Option<u32> {
+	Some(DEFAULT_TRASH_RETENTION_DAYS)
+}
+
+/// LibraryBackupSchedule is the configuration for automatic library backups.
+#[derive(Debug, Serialize, Deserialize, Clone, Type)]
+pub struct LibraryBackupSchedule {
+	/// interval_secs is the minimum amount of time between two automatic backups.
+	pub interval_secs: u32,
+	/// output_dir is the directory where backups are stored. Defaults to the `backups` directory inside the node data directory.
+	pub output_dir: Option<PathBuf>,
+	/// include_thumbnails will also copy the thumbnails of this library into the backup archive.
+	pub include_thumbnails: bool,
+}
+
+/// FileVersionRetention is how long the versions of a file are kept, the oldest ones being pruned first.
+/// The latest version of a file is never pruned.
+#[derive(Debug, Serialize, Deserialize, Clone, Type)]
+pub struct FileVersionRetention {
+	/// max_versions is how many versions are kept for each file. If it's `None`, there's no limit.
+	pub max_versions: Option<u32>,
+	/// max_age_days is how long versions are kept. If it's `None`, they're only pruned by `max_versions`.
+	pub max_age_days: Option<u32>,
+}
+
+impl Default for FileVersionRetention {
+	fn default() -> Self {
+		Self {
+			max_versions: Some(20),
+			max_age_days: None,
+		}
+	}
+}
+
+/// LibraryStorageBudget is a soft cap on the size of the thumbnails, database and backups of a library.
+/// Exceeding it never blocks anything, it only triggers alerts.
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, Clone, Type)]
+pub struct LibraryStorageBudget {
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub max_bytes: u64,
+	/// webhook_url will receive a JSON `POST` of the alert whenever a threshold is crossed.
+	pub webhook_url: Option<String>,
+}
+
 impl LibraryConfig {
-	/// read will read the configuration from disk and return it.
+	/// read will read the configuration from disk, migrate it to the current [`LIBRARY_CONFIG_VERSION`] and return it.
+	/// Unknown fields and invalid optional fields are reported and ignored instead of failing to load the library.
 	pub(super) async fn read(file_dir: PathBuf) -> Result<LibraryConfig, LibraryManagerError> {
-		let mut file = File::open(&file_dir)?;
-		let base_config: ConfigMetadata = serde_json::from_reader(BufReader::new(&mut file))?;
+		let mut config: Value = serde_json::from_reader(BufReader::new(File::open(&file_dir)?))?;
+
+		let Value::Object(fields) = &mut config else {
+			return Err(LibraryManagerError::InvalidConfig(
+				file_dir,
+				"expected a JSON object".to_string(),
+			));
+		};
+
+		if Self::migrate_config(fields, &file_dir)? {
+			File::create(&file_dir)?.write_all(serde_json::to_string(&fields)?.as_bytes())?;
+		}
 
-		Self::migrate_config(base_config.version, file_dir)?;
+		let report = Self::validate(fields)
+			.map_err(|reason| LibraryManagerError::InvalidConfig(file_dir.clone(), reason))?;
+		if !report.is_empty() {
+			warn!(
+				"Library config at '{}' has problems which were ignored: {:#?}",
+				file_dir.display(),
+				report
+			);
+		}
 
-		file.rewind()?;
-		Ok(serde_json::from_reader(BufReader::new(&mut file))?)
+		serde_json::from_value(config)
+			.map_err(|e| LibraryManagerError::InvalidConfig(file_dir, e.to_string()))
 	}
 
 	/// save will write the configuration back to disk
@@ -48,21 +163,159 @@ impl LibraryConfig {
 		Ok(())
 	}
 
-	/// migrate_config is a function used to apply breaking changes to the library config file.
+	/// migrate_config applies every [`ConfigMigration`] needed to bring the config up to [`LIBRARY_CONFIG_VERSION`].
+	/// Returns whether the config was changed and must be written back to disk.
 	fn migrate_config(
-		current_version: Option<String>,
-		config_path: PathBuf,
-	) -> Result<(), LibraryManagerError> {
-		match current_version {
-			None => Err(LibraryManagerError::Migration(format!(
-				"Your Spacedrive library at '{}' is missing the `version` field",
+		fields: &mut Map<String, Value>,
+		config_path: &Path,
+	) -> Result<bool, LibraryManagerError> {
+		let mut current_version = match fields.get("config_version") {
+			None => 0,
+			Some(version) => version
+				.as_u64()
+				.and_then(|version| u32::try_from(version).ok())
+				.ok_or_else(|| {
+					LibraryManagerError::Migration(format!(
+						"Your Spacedrive library at '{}' has an invalid `config_version` field",
+						config_path.display()
+					))
+				})?,
+		};
+
+		if current_version > LIBRARY_CONFIG_VERSION {
+			return Err(LibraryManagerError::Migration(format!(
+				"Your Spacedrive library at '{}' was created by a newer version of Spacedrive",
 				config_path.display()
-			))),
-			_ => Ok(()),
+			)));
 		}
+
+		let mut migrated = false;
+		for migration in MIGRATIONS.iter().filter(|m| m.from >= current_version) {
+			debug!(
+				"Migrating library config at '{}' from version {}: {}",
+				config_path.display(),
+				migration.from,
+				migration.description
+			);
+
+			(migration.apply)(fields).map_err(|reason| {
+				LibraryManagerError::Migration(format!(
+					"Failed to migrate your Spacedrive library at '{}' from version {}: {reason}",
+					config_path.display(),
+					migration.from
+				))
+			})?;
+
+			current_version = migration.from + 1;
+			fields.insert("config_version".to_string(), current_version.into());
+			migrated = true;
+		}
+
+		Ok(migrated)
+	}
+
+	/// validate checks every field of the config before deserializing it, removing invalid optional fields so they
+	/// fallback to their defaults. Only invalid required fields are considered an error.
+	fn validate(fields: &mut Map<String, Value>) -> Result<ConfigValidationReport, String> {
+		let mut report = ConfigValidationReport::default();
+
+		fn check<T: DeserializeOwned>(
+			fields: &mut Map<String, Value>,
+			report: &mut ConfigValidationReport,
+			key: &str,
+			required: bool,
+		) -> Result<(), String> {
+			let Some(value) = fields.get(key) else {
+				return match required {
+					true => Err(format!("missing required field `{key}`")),
+					false => Ok(()),
+				};
+			};
+
+			if let Err(e) = serde_json::from_value::<T>(value.clone()) {
+				if required {
+					return Err(format!("invalid field `{key}`: {e}"));
+				}
+
+				report.invalid_fields.push(format!("{key}: {e}"));
+				fields.remove(key);
+			}
+
+			Ok(())
+		}
+
+		check::<Option<String>>(fields, &mut report, "version", false)?;
+		check::<u32>(fields, &mut report, "config_version", true)?;
+		check::<String>(fields, &mut report, "name", true)?;
+		check::<String>(fields, &mut report, "description", false)?;
+		check::<Option<LibraryBackupSchedule>>(fields, &mut report, "backup", false)?;
+		check::<Option<u32>>(fields, &mut report, "maintenance_interval_secs", false)?;
+		check::<Option<LibraryStorageBudget>>(fields, &mut report, "storage_budget", false)?;
+		check::<Option<u32>>(fields, &mut report, "trash_retention_days", false)?;
+		check::<FileVersionRetention>(fields, &mut report, "file_version_retention", false)?;
+		check::<CasIdAlgorithm>(fields, &mut report, "cas_id_algorithm", false)?;
+		check::<bool>(fields, &mut report, "perceptual_hashing", false)?;
+
+		report.unknown_fields = fields
+			.keys()
+			.filter(|key| !KNOWN_FIELDS.contains(&key.as_str()))
+			.cloned()
+			.collect();
+
+		Ok(report)
+	}
+}
+
+/// ConfigValidationReport lists the problems found in a library config which didn't prevent it from loading.
+#[derive(Debug, Default)]
+struct ConfigValidationReport {
+	unknown_fields: Vec<String>,
+	invalid_fields: Vec<String>,
+}
+
+impl ConfigValidationReport {
+	fn is_empty(&self) -> bool {
+		self.unknown_fields.is_empty() && self.invalid_fields.is_empty()
 	}
 }
 
+/// ConfigMigration upgrades a library config from version `from` to `from + 1`.
+struct ConfigMigration {
+	from: u32,
+	description: &'static str,
+	apply: fn(&mut Map<String, Value>) -> Result<(), String>,
+}
+
+/// MIGRATIONS holds every library config migration, sorted by version. To make a breaking change to the config,
+/// bump [`LIBRARY_CONFIG_VERSION`] and append a migration from the previous version.
+const MIGRATIONS: &[ConfigMigration] = &[ConfigMigration {
+	from: 0,
+	description: "introduce `config_version` and require the `version` field",
+	apply: |fields| {
+		if !matches!(fields.get("version"), Some(Value::String(_))) {
+			fields.insert(
+				"version".to_string(),
+				Value::String(env!("CARGO_PKG_VERSION").to_string()),
+			);
+		}
+		Ok(())
+	},
+}];
+
+const KNOWN_FIELDS: &[&str] = &[
+	"version",
+	"config_version",
+	"name",
+	"description",
+	"backup",
+	"maintenance_interval_secs",
+	"storage_budget",
+	"trash_retention_days",
+	"file_version_retention",
+	"cas_id_algorithm",
+	"perceptual_hashing",
+];
+
 // used to return to the frontend with uuid context
 #[derive(Serialize, Deserialize, Debug, Type)]
 pub struct LibraryConfigWrapped {
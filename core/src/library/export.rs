@@ -0,0 +1,271 @@
+use crate::{
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::Library,
+	object::preview::THUMBNAIL_CACHE_DIR_NAME,
+	prisma::{file_path, location},
+};
+
+use std::{
+	collections::{HashMap, VecDeque},
+	hash::Hash,
+	path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{debug, info};
+
+use super::backup::{
+	copy_config, copy_thumbnails, pack_archive, snapshot_database, thumbnail_steps, BackupManifest,
+	LibraryBackupJobStep, BACKUP_CONFIG_NAME, BACKUP_DB_NAME, BACKUP_MANIFEST_NAME,
+};
+
+pub const LIBRARY_EXPORT_JOB_NAME: &str = "library_export";
+pub const EXPORT_EXTENSION: &str = "sdlibrary";
+
+/// How many cas_ids of each imported location are compared against the locations of this node
+const RECONCILE_SAMPLE_SIZE: i64 = 100;
+/// Minimum fraction of sampled cas_ids that must be found in a local location to consider it a match
+const RECONCILE_MATCH_THRESHOLD: f64 = 0.5;
+
+/// `LibraryExportJob` bundles the library database, config and optionally its thumbnails into a single
+/// portable `.sdlibrary` file that can be imported on another node with `library.import`.
+pub struct LibraryExportJob {}
+
+#[derive(Serialize, Deserialize, Hash, Type)]
+pub struct LibraryExportJobInit {
+	pub output_path: PathBuf,
+	pub include_thumbnails: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LibraryExportJobState {
+	staging_dir: PathBuf,
+	export_path: PathBuf,
+	thumbnails_copied: usize,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for LibraryExportJob {
+	type Init = LibraryExportJobInit;
+	type Data = LibraryExportJobState;
+	type Step = LibraryBackupJobStep;
+
+	fn name(&self) -> &'static str {
+		LIBRARY_EXPORT_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let library = &ctx.library;
+
+		let export_path = state.init.output_path.with_extension(EXPORT_EXTENSION);
+		let Some(output_dir) = export_path.parent() else {
+			return Err(JobError::Path);
+		};
+		let Some(export_name) = export_path.file_name().and_then(|name| name.to_str()) else {
+			return Err(JobError::Path);
+		};
+
+		fs::create_dir_all(output_dir).await?;
+
+		let staging_dir = output_dir.join(format!(".{export_name}.partial"));
+		fs::create_dir_all(staging_dir.join(THUMBNAIL_CACHE_DIR_NAME)).await?;
+
+		fs::write(
+			staging_dir.join(BACKUP_MANIFEST_NAME),
+			serde_json::to_vec(&BackupManifest {
+				library_id: library.id,
+				library_name: library.config.name.clone(),
+				version: env!("CARGO_PKG_VERSION").to_string(),
+				created_at: Utc::now(),
+				includes_thumbnails: state.init.include_thumbnails,
+			})?,
+		)
+		.await?;
+
+		let mut steps =
+			VecDeque::from([LibraryBackupJobStep::Database, LibraryBackupJobStep::Config]);
+
+		if state.init.include_thumbnails {
+			steps.extend(thumbnail_steps(library).await?);
+		}
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(steps.len()),
+			JobReportUpdate::Message(format!("Exporting library '{}'", library.config.name)),
+		]);
+
+		state.data = Some(LibraryExportJobState {
+			staging_dir,
+			export_path,
+			thumbnails_copied: 0,
+		});
+		state.steps = steps;
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let library = &ctx.library;
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		match &state.steps[0] {
+			LibraryBackupJobStep::Database => {
+				snapshot_database(library, data.staging_dir.join(BACKUP_DB_NAME)).await?
+			}
+			LibraryBackupJobStep::Config => {
+				copy_config(library, data.staging_dir.join(BACKUP_CONFIG_NAME)).await?
+			}
+			LibraryBackupJobStep::Thumbnails(cas_ids) => {
+				data.thumbnails_copied +=
+					copy_thumbnails(library, cas_ids, &data.staging_dir).await?;
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		let export_size = pack_archive(&data.staging_dir, &data.export_path).await?;
+
+		info!(
+			"Library exported to {} ({} bytes, {} thumbnails)",
+			data.export_path.display(),
+			export_size,
+			data.thumbnails_copied
+		);
+
+		Ok(Some(serde_json::json!({
+			"export_path": data.export_path,
+			"export_size": export_size,
+			"thumbnails_copied": data.thumbnails_copied,
+		})))
+	}
+}
+
+/// `LibraryImportReport` describes how the locations of an imported library were matched
+/// against the locations already present on this node.
+#[derive(Serialize, Debug, Default, Type)]
+pub struct LibraryImportReport {
+	/// locations which were pointed to a local location containing the same files.
+	pub relinked_locations: Vec<String>,
+	/// locations whose path exists on this node, so they were kept as is.
+	pub kept_locations: Vec<String>,
+	/// locations which couldn't be found on this node.
+	pub missing_locations: Vec<String>,
+}
+
+/// `reconcile_locations` tries to find every location of an imported library on this node.
+/// Locations whose path doesn't exist are matched by the cas_ids of their files against the
+/// locations of the libraries already loaded, and pointed to the best match.
+pub(super) async fn reconcile_locations(
+	imported: &Library,
+	local_libraries: &[Library],
+) -> Result<LibraryImportReport, prisma_client_rust::QueryError> {
+	let mut report = LibraryImportReport::default();
+
+	for location in imported.db.location().find_many(vec![]).exec().await? {
+		if Path::new(&location.path).is_dir() {
+			report.kept_locations.push(location.path);
+			continue;
+		}
+
+		let sample = imported
+			.db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(location.id),
+				file_path::cas_id::not(None),
+			])
+			.take(RECONCILE_SAMPLE_SIZE)
+			.select(file_path::select!({ cas_id }))
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|file_path| file_path.cas_id)
+			.collect::<Vec<_>>();
+
+		let mut best_match: Option<(String, usize)> = None;
+		if !sample.is_empty() {
+			for library in local_libraries {
+				let mut matches_per_location = HashMap::<i32, usize>::new();
+				for file_path in library
+					.db
+					.file_path()
+					.find_many(vec![file_path::cas_id::in_vec(sample.clone())])
+					.select(file_path::select!({ location_id }))
+					.exec()
+					.await?
+				{
+					*matches_per_location
+						.entry(file_path.location_id)
+						.or_default() += 1;
+				}
+
+				for (location_id, matches) in matches_per_location {
+					if best_match
+						.as_ref()
+						.map(|(_, best)| matches > *best)
+						.unwrap_or(true)
+					{
+						if let Some(local_location) = library
+							.db
+							.location()
+							.find_unique(location::id::equals(location_id))
+							.exec()
+							.await?
+						{
+							best_match = Some((local_location.path, matches));
+						}
+					}
+				}
+			}
+		}
+
+		match best_match {
+			Some((path, matches))
+				if matches as f64 / sample.len() as f64 >= RECONCILE_MATCH_THRESHOLD =>
+			{
+				debug!(
+					"Relinking imported location '{}' to '{}' ({matches}/{} files matched)",
+					location.path,
+					path,
+					sample.len()
+				);
+
+				imported
+					.db
+					.location()
+					.update(
+						location::id::equals(location.id),
+						vec![location::path::set(path.clone())],
+					)
+					.exec()
+					.await?;
+
+				report.relinked_locations.push(path);
+			}
+			_ => report.missing_locations.push(location.path),
+		}
+	}
+
+	Ok(report)
+}
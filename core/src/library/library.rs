@@ -1,9 +1,11 @@
 use crate::{
 	api::CoreEvent,
+	extension::ExtensionHost,
 	job::DynJob,
+	library::LibraryManager,
 	location::{file_path_helper::LastFilePathIdManager, LocationManager},
-	node::NodeConfigManager,
-	object::preview::THUMBNAIL_CACHE_DIR_NAME,
+	node::{NodeConfigManager, ThumbnailFormat},
+	object::preview::{thumbnail_path, THUMBNAIL_CACHE_DIR_NAME},
 	prisma::PrismaClient,
 	sync::SyncManager,
 	NodeContext,
@@ -11,7 +13,7 @@ use crate::{
 
 use std::{
 	fmt::{Debug, Formatter},
-	sync::Arc,
+	sync::{Arc, Weak},
 };
 
 use sd_crypto::keys::keymanager::KeyManager;
@@ -61,7 +63,7 @@ impl Library {
 	}
 
 	pub(crate) async fn queue_job(&self, job: Box<dyn DynJob>) {
-		self.node_context.jobs.ingest_queue(job).await;
+		self.node_context.jobs.ingest_queue(self, job).await;
 	}
 
 	pub(crate) fn emit(&self, event: CoreEvent) {
@@ -78,18 +80,33 @@ impl Library {
 		&self.node_context.location_manager
 	}
 
+	pub(crate) fn extensions(&self) -> &Arc<ExtensionHost> {
+		&self.node_context.extensions
+	}
+
+	/// library_manager returns the manager of the libraries loaded on the node, this one included.
+	pub(crate) fn library_manager(&self) -> Option<Arc<LibraryManager>> {
+		self.node_context
+			.library_manager
+			.get()
+			.and_then(Weak::upgrade)
+	}
+
+	/// thumbnail_exists tells if the default thumbnail of `cas_id` exists, in any format.
 	pub async fn thumbnail_exists(&self, cas_id: &str) -> tokio::io::Result<bool> {
-		let thumb_path = self
+		let thumbnail_dir = self
 			.config()
 			.data_directory()
-			.join(THUMBNAIL_CACHE_DIR_NAME)
-			.join(cas_id)
-			.with_extension("webp");
+			.join(THUMBNAIL_CACHE_DIR_NAME);
 
-		match tokio::fs::metadata(thumb_path).await {
-			Ok(_) => Ok(true),
-			Err(e) if e.kind() == tokio::io::ErrorKind::NotFound => Ok(false),
-			Err(e) => Err(e),
+		for format in ThumbnailFormat::ALL {
+			match tokio::fs::metadata(thumbnail_path(&thumbnail_dir, None, cas_id, format)).await {
+				Ok(_) => return Ok(true),
+				Err(e) if e.kind() == tokio::io::ErrorKind::NotFound => {}
+				Err(e) => return Err(e),
+			}
 		}
+
+		Ok(false)
 	}
 }
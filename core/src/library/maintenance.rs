@@ -0,0 +1,232 @@
+use crate::{
+	job::{
+		Job, JobError, JobPriority, JobReportUpdate, JobResult, JobState, JobStatus, StatefulJob,
+		WorkerContext,
+	},
+	library::{Library, LibraryManager},
+	prisma::{job, PrismaClient},
+};
+
+use std::{collections::VecDeque, path::PathBuf, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use int_enum::IntEnum;
+use prisma_client_rust::{raw, Direction, QueryError};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, time::interval};
+use tracing::{info, warn};
+
+pub const DB_MAINTENANCE_JOB_NAME: &str = "db_maintenance";
+
+/// How often the scheduler checks if any library is due for maintenance
+const MAINTENANCE_SCHEDULER_TICK: Duration = Duration::from_secs(60 * 60);
+
+/// `DbMaintenanceJob` checks the integrity of the library database, then rebuilds it with `VACUUM`
+/// to reclaim unused space and refreshes the query planner statistics with `ANALYZE`.
+pub struct DbMaintenanceJob {}
+
+#[derive(Serialize, Deserialize, Hash)]
+pub struct DbMaintenanceJobInit {}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DbMaintenanceJobState {
+	db_path: PathBuf,
+	size_before: u64,
+	integrity_issues: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum DbMaintenanceJobStep {
+	IntegrityCheck,
+	Vacuum,
+	Analyze,
+}
+
+#[derive(Deserialize)]
+struct IntegrityCheckRow {
+	integrity_check: String,
+}
+
+/// integrity_check returns the issues found by SQLite in the library database, which is empty for a healthy one.
+pub(super) async fn integrity_check(db: &PrismaClient) -> Result<Vec<String>, QueryError> {
+	// A healthy database returns a single row with "ok"
+	Ok(db
+		._query_raw::<IntegrityCheckRow>(raw!("PRAGMA integrity_check"))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|row| row.integrity_check)
+		.filter(|result| result != "ok")
+		.collect())
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for DbMaintenanceJob {
+	type Init = DbMaintenanceJobInit;
+	type Data = DbMaintenanceJobState;
+	type Step = DbMaintenanceJobStep;
+
+	fn name(&self) -> &'static str {
+		DB_MAINTENANCE_JOB_NAME
+	}
+
+	fn priority(&self, _init: &Self::Init) -> JobPriority {
+		JobPriority::Background
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let library = &ctx.library;
+
+		let db_path = library
+			.config()
+			.data_directory()
+			.join("libraries")
+			.join(format!("{}.db", library.id));
+
+		state.steps = VecDeque::from([
+			DbMaintenanceJobStep::IntegrityCheck,
+			DbMaintenanceJobStep::Vacuum,
+			DbMaintenanceJobStep::Analyze,
+		]);
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(state.steps.len()),
+			JobReportUpdate::Message(format!(
+				"Optimizing database of library '{}'",
+				library.config.name
+			)),
+		]);
+
+		state.data = Some(DbMaintenanceJobState {
+			size_before: fs::metadata(&db_path).await?.len(),
+			db_path,
+			integrity_issues: vec![],
+		});
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let db = &ctx.library.db;
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+
+		match &state.steps[0] {
+			DbMaintenanceJobStep::IntegrityCheck => {
+				data.integrity_issues = integrity_check(db).await?;
+
+				if !data.integrity_issues.is_empty() {
+					warn!(
+						"Found {} integrity issues in library {} database: {:#?}",
+						data.integrity_issues.len(),
+						ctx.library.id,
+						data.integrity_issues
+					);
+
+					// Rebuilding a corrupted database could make things worse, so we stop here
+					state.steps.truncate(1);
+				}
+			}
+			DbMaintenanceJobStep::Vacuum => {
+				db._execute_raw(raw!("VACUUM")).exec().await?;
+			}
+			DbMaintenanceJobStep::Analyze => {
+				db._execute_raw(raw!("ANALYZE")).exec().await?;
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		let size_after = fs::metadata(&data.db_path).await?.len();
+		let reclaimed_bytes = data.size_before.saturating_sub(size_after);
+
+		info!(
+			"Finished database maintenance of library {}: reclaimed {} bytes, {} integrity issues",
+			ctx.library.id,
+			reclaimed_bytes,
+			data.integrity_issues.len()
+		);
+
+		Ok(Some(serde_json::json!({
+			"size_before": data.size_before,
+			"size_after": size_after,
+			"reclaimed_bytes": reclaimed_bytes,
+			"integrity_issues": data.integrity_issues,
+		})))
+	}
+}
+
+/// `last_maintenance_time` returns when the last successful [`DbMaintenanceJob`] of the library was created.
+async fn last_maintenance_time(
+	library: &Library,
+) -> Result<Option<chrono::DateTime<Utc>>, prisma_client_rust::QueryError> {
+	Ok(library
+		.db
+		.job()
+		.find_first(vec![
+			job::name::equals(DB_MAINTENANCE_JOB_NAME.to_string()),
+			job::status::equals(JobStatus::Completed.int_value()),
+		])
+		.order_by(job::date_created::order(Direction::Desc))
+		.exec()
+		.await?
+		.map(|job| job.date_created.into()))
+}
+
+/// `spawn_maintenance_scheduler` periodically checks every loaded library with a maintenance interval
+/// and spawns a [`DbMaintenanceJob`] for the ones that are due.
+pub fn spawn_maintenance_scheduler(library_manager: Arc<LibraryManager>) {
+	tokio::spawn(async move {
+		let mut interval = interval(MAINTENANCE_SCHEDULER_TICK);
+
+		loop {
+			interval.tick().await;
+
+			for library in library_manager.get_all_libraries().await {
+				let Some(interval_secs) = library.config.maintenance_interval_secs else {
+					continue;
+				};
+
+				let is_due = match last_maintenance_time(&library).await {
+					Ok(Some(last)) => Utc::now()
+						.signed_duration_since(last)
+						.to_std()
+						.map(|elapsed| elapsed >= Duration::from_secs(interval_secs.into()))
+						.unwrap_or(false),
+					Ok(None) => true,
+					Err(e) => {
+						warn!(
+							"Failed to fetch last maintenance of library {}: {e:#?}",
+							library.id
+						);
+						false
+					}
+				};
+
+				if is_due {
+					info!("Running scheduled maintenance for library {}", library.id);
+					library
+						.spawn_job(Job::new(DbMaintenanceJobInit {}, DbMaintenanceJob {}))
+						.await;
+				}
+			}
+		}
+	});
+}
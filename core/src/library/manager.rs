@@ -1,11 +1,16 @@
 use crate::{
 	invalidate_query,
+	job::JobError,
 	location::file_path_helper::LastFilePathIdManager,
-	node::Platform,
-	prisma::{node, PrismaClient},
+	node::{Platform, ThumbnailFormat},
+	object::{
+		cas::CasIdAlgorithm,
+		preview::{preview_strip_path, thumbnail_path, thumbnail_specs, THUMBNAIL_CACHE_DIR_NAME},
+	},
+	prisma::{file_path, node, PrismaClient},
 	sync::SyncManager,
 	util::{
-		db::{load_and_migrate, write_storedkey_to_db},
+		db::{load_and_migrate, write_storedkey_to_db, MigrationError},
 		seeder::{indexer_rules_seeder, SeederError},
 	},
 	NodeContext,
@@ -16,17 +21,30 @@ use sd_crypto::{
 	types::{EncryptedKey, Nonce, OnboardingConfig, Salt},
 };
 use std::{
+	collections::HashSet,
 	env, fs, io,
 	path::{Path, PathBuf},
 	str::FromStr,
 	sync::Arc,
+	time::Duration,
 };
 use thiserror::Error;
-use tokio::sync::RwLock;
-use tracing::debug;
+use tokio::{
+	sync::RwLock,
+	time::{sleep, Instant},
+};
+use tracing::{debug, error, warn};
 use uuid::Uuid;
 
-use super::{Library, LibraryConfig, LibraryConfigWrapped};
+use super::{
+	backup::unpack_backup, export::reconcile_locations, BackupManifest, FileVersionRetention,
+	Library, LibraryBackupSchedule, LibraryConfig, LibraryConfigWrapped, LibraryImportReport,
+	LibraryStorageBudget, LibraryTemplate, BACKUP_CONFIG_NAME, BACKUP_DB_NAME, BACKUP_EXTENSION,
+	EXPORT_EXTENSION,
+};
+
+/// How long closing a library waits for the requests and watchers still using it to let it go
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// LibraryManager is a singleton that manages all libraries for a node.
 pub struct LibraryManager {
@@ -48,8 +66,10 @@ pub enum LibraryManagerError {
 	Database(#[from] prisma_client_rust::QueryError),
 	#[error("Library not found error")]
 	LibraryNotFound,
-	#[error("error migrating the config file")]
+	#[error("error migrating the config file: {0}")]
 	Migration(String),
+	#[error("invalid library config at '{}': {1}", .0.display())]
+	InvalidConfig(PathBuf, String),
 	#[error("failed to parse uuid")]
 	Uuid(#[from] uuid::Error),
 	#[error("error opening database as the path contains non-UTF-8 characters")]
@@ -58,6 +78,20 @@ pub enum LibraryManagerError {
 	Seeder(#[from] SeederError),
 	#[error("failed to initialise the key manager")]
 	KeyManager(#[from] sd_crypto::Error),
+	#[error("library {0} already exists on this node")]
+	LibraryAlreadyExists(Uuid),
+	#[error("library template {0} not found")]
+	TemplateNotFound(Uuid),
+	#[error("invalid library backup: {0}")]
+	InvalidBackup(String),
+	#[error("failed to join blocking task: {0}")]
+	Join(#[from] tokio::task::JoinError),
+	#[error("error opening the library database: {0}")]
+	DatabaseMigration(#[from] MigrationError),
+	#[error("failed to stop the jobs of the library: {0}")]
+	Jobs(#[from] JobError),
+	#[error("library {0} is still in use and can't be closed")]
+	LibraryInUse(Uuid),
 }
 
 impl From<LibraryManagerError> for rspc::Error {
@@ -162,7 +196,16 @@ impl LibraryManager {
 				continue;
 			}
 
-			let config = LibraryConfig::read(config_path).await?;
+			let config = match LibraryConfig::read(config_path.clone()).await {
+				Ok(config) => config,
+				Err(e) => {
+					error!(
+						"Failed to load library config at '{}', skipping library: {e}",
+						config_path.display()
+					);
+					continue;
+				}
+			};
 			libraries.push(Self::load(library_id, &db_path, config, node_context.clone()).await?);
 		}
 
@@ -171,6 +214,11 @@ impl LibraryManager {
 			libraries_dir,
 			node_context,
 		});
+		// A weak reference, as the manager owns the libraries
+		this.node_context
+			.library_manager
+			.set(Arc::downgrade(&this))
+			.ok();
 
 		debug!("LibraryManager initialized");
 
@@ -178,11 +226,17 @@ impl LibraryManager {
 	}
 
 	/// create creates a new library with the given config and mounts it into the running [LibraryManager].
+	/// If a template is given, its indexer rules, tags and settings are applied to the new library.
 	pub(crate) async fn create(
 		&self,
-		config: LibraryConfig,
+		mut config: LibraryConfig,
 		km_config: OnboardingConfig,
+		template: Option<LibraryTemplate>,
 	) -> Result<LibraryConfigWrapped, LibraryManagerError> {
+		if let Some(template) = &template {
+			template.apply_config(&mut config);
+		}
+
 		let id = Uuid::new_v4();
 		LibraryConfig::save(
 			Path::new(&self.libraries_dir).join(format!("{id}.sdlibrary")),
@@ -199,7 +253,16 @@ impl LibraryManager {
 		.await?;
 
 		// Run seeders
-		indexer_rules_seeder(&library.db).await?;
+		match &template {
+			Some(template) if template.has_indexer_rules() => {
+				template.apply_db(&library.db).await?
+			}
+			Some(template) => {
+				indexer_rules_seeder(&library.db).await?;
+				template.apply_db(&library.db).await?;
+			}
+			None => indexer_rules_seeder(&library.db).await?,
+		}
 
 		// setup master password
 		let verification_key = KeyManager::onboarding(km_config, library.id).await?;
@@ -236,6 +299,8 @@ impl LibraryManager {
 		id: Uuid,
 		name: Option<String>,
 		description: Option<String>,
+		cas_id_algorithm: Option<CasIdAlgorithm>,
+		perceptual_hashing: Option<bool>,
 	) -> Result<(), LibraryManagerError> {
 		// check library is valid
 		let mut libraries = self.libraries.write().await;
@@ -251,6 +316,84 @@ impl LibraryManager {
 		if let Some(description) = description {
 			library.config.description = description;
 		}
+		if let Some(cas_id_algorithm) = cas_id_algorithm {
+			library.config.cas_id_algorithm = cas_id_algorithm;
+		}
+		if let Some(perceptual_hashing) = perceptual_hashing {
+			library.config.perceptual_hashing = perceptual_hashing;
+		}
+
+		LibraryConfig::save(
+			Path::new(&self.libraries_dir).join(format!("{id}.sdlibrary")),
+			&library.config,
+		)
+		.await?;
+
+		invalidate_query!(library, "library.list");
+
+		Ok(())
+	}
+
+	pub(crate) async fn set_backup_schedule(
+		&self,
+		id: Uuid,
+		schedule: Option<LibraryBackupSchedule>,
+	) -> Result<(), LibraryManagerError> {
+		let mut libraries = self.libraries.write().await;
+		let library = libraries
+			.iter_mut()
+			.find(|lib| lib.id == id)
+			.ok_or(LibraryManagerError::LibraryNotFound)?;
+
+		library.config.backup = schedule;
+
+		LibraryConfig::save(
+			Path::new(&self.libraries_dir).join(format!("{id}.sdlibrary")),
+			&library.config,
+		)
+		.await?;
+
+		invalidate_query!(library, "library.list");
+
+		Ok(())
+	}
+
+	pub(crate) async fn set_maintenance_interval(
+		&self,
+		id: Uuid,
+		interval_secs: Option<u32>,
+	) -> Result<(), LibraryManagerError> {
+		let mut libraries = self.libraries.write().await;
+		let library = libraries
+			.iter_mut()
+			.find(|lib| lib.id == id)
+			.ok_or(LibraryManagerError::LibraryNotFound)?;
+
+		library.config.maintenance_interval_secs = interval_secs;
+
+		LibraryConfig::save(
+			Path::new(&self.libraries_dir).join(format!("{id}.sdlibrary")),
+			&library.config,
+		)
+		.await?;
+
+		invalidate_query!(library, "library.list");
+
+		Ok(())
+	}
+
+	pub(crate) async fn set_storage_budget(
+		&self,
+		id: Uuid,
+		budget: Option<LibraryStorageBudget>,
+	) -> Result<(), LibraryManagerError> {
+		let mut libraries = self.libraries.write().await;
+		let library = libraries
+			.iter_mut()
+			.find(|lib| lib.id == id)
+			.ok_or(LibraryManagerError::LibraryNotFound)?;
+
+		library.config.storage_budget = budget;
 
 		LibraryConfig::save(
 			Path::new(&self.libraries_dir).join(format!("{id}.sdlibrary")),
@@ -263,17 +406,130 @@ impl LibraryManager {
 		Ok(())
 	}
 
-	pub async fn delete_library(&self, id: Uuid) -> Result<(), LibraryManagerError> {
+	pub(crate) async fn set_trash_retention(
+		&self,
+		id: Uuid,
+		retention_days: Option<u32>,
+	) -> Result<(), LibraryManagerError> {
+		let mut libraries = self.libraries.write().await;
+		let library = libraries
+			.iter_mut()
+			.find(|lib| lib.id == id)
+			.ok_or(LibraryManagerError::LibraryNotFound)?;
+
+		library.config.trash_retention_days = retention_days;
+
+		LibraryConfig::save(
+			Path::new(&self.libraries_dir).join(format!("{id}.sdlibrary")),
+			&library.config,
+		)
+		.await?;
+
+		invalidate_query!(library, "library.list");
+
+		Ok(())
+	}
+
+	pub(crate) async fn set_file_version_retention(
+		&self,
+		id: Uuid,
+		retention: FileVersionRetention,
+	) -> Result<(), LibraryManagerError> {
+		let mut libraries = self.libraries.write().await;
+		let library = libraries
+			.iter_mut()
+			.find(|lib| lib.id == id)
+			.ok_or(LibraryManagerError::LibraryNotFound)?;
+
+		library.config.file_version_retention = retention;
+
+		LibraryConfig::save(
+			Path::new(&self.libraries_dir).join(format!("{id}.sdlibrary")),
+			&library.config,
+		)
+		.await?;
+
+		invalidate_query!(library, "library.list");
+
+		Ok(())
+	}
+
+	/// delete_library unloads the library and removes its database and config from disk.
+	/// With `purge`, it also stops its location watchers, clears its keys from memory, and removes
+	/// the thumbnails which aren't used by any other library, so nothing is left behind in the data directory.
+	pub async fn delete_library(&self, id: Uuid, purge: bool) -> Result<(), LibraryManagerError> {
 		let mut libraries = self.libraries.write().await;
 
 		let library = libraries
 			.iter()
 			.find(|l| l.id == id)
-			.ok_or(LibraryManagerError::LibraryNotFound)?;
+			.ok_or(LibraryManagerError::LibraryNotFound)?
+			.clone();
+
+		let orphan_cas_ids = if purge {
+			for location in library.db.location().find_many(vec![]).exec().await? {
+				if let Err(e) = self
+					.node_context
+					.location_manager
+					.remove(location.id, library.clone())
+					.await
+				{
+					error!("Failed to remove location from location manager: {:#?}", e);
+				}
+			}
+
+			library.key_manager.empty_keymount();
+			if let Err(e) = library.key_manager.clear_root_key().await {
+				warn!("Failed to clear root key of library {id}: {e:#?}");
+			}
+
+			let mut cas_ids = library_cas_ids(&library).await?;
+			for other in libraries.iter().filter(|l| l.id != id) {
+				for cas_id in library_cas_ids(other).await? {
+					cas_ids.remove(&cas_id);
+				}
+			}
+			cas_ids
+		} else {
+			HashSet::new()
+		};
 
+		// The job history and sync operations live in the library database, so they're removed with it
 		fs::remove_file(Path::new(&self.libraries_dir).join(format!("{}.db", library.id)))?;
 		fs::remove_file(Path::new(&self.libraries_dir).join(format!("{}.sdlibrary", library.id)))?;
 
+		if purge {
+			for suffix in ["-wal", "-shm", "-journal"] {
+				let sidecar_path = self.libraries_dir.join(format!("{id}.db{suffix}"));
+				if sidecar_path.try_exists()? {
+					fs::remove_file(sidecar_path)?;
+				}
+			}
+
+			let thumbnails_dir = self
+				.node_context
+				.config
+				.data_directory()
+				.join(THUMBNAIL_CACHE_DIR_NAME);
+			let thumbnail_config = self.node_context.config.get().await.thumbnail;
+			let mut removed_thumbnails = 0;
+			let mut remove_thumbnail = |path: PathBuf| match fs::remove_file(path) {
+				Ok(()) => removed_thumbnails += 1,
+				Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+				Err(e) => warn!("Failed to remove thumbnail of deleted library {id}: {e:#?}"),
+			};
+			for cas_id in orphan_cas_ids {
+				for (variant, _) in thumbnail_specs(&thumbnail_config) {
+					for format in ThumbnailFormat::ALL {
+						remove_thumbnail(thumbnail_path(&thumbnails_dir, variant, &cas_id, format));
+					}
+				}
+				remove_thumbnail(preview_strip_path(&thumbnails_dir, &cas_id));
+			}
+
+			debug!("Purged library {id}, removed {removed_thumbnails} thumbnails");
+		}
+
 		invalidate_query!(library, "library.list");
 
 		libraries.retain(|l| l.id != id);
@@ -281,6 +537,319 @@ impl LibraryManager {
 		Ok(())
 	}
 
+	/// restore replaces a library with the contents of a backup archive created by [`super::LibraryBackupJob`].
+	/// If the library is currently loaded, its watchers and jobs are stopped and its database closed first, and
+	/// it's reloaded from the restored database, running any pending migrations, so no restart is required.
+	/// If the restore fails, the library is reloaded from its previous files. The replaced database is kept as `{id}.db.bak`.
+	pub(crate) async fn restore(
+		&self,
+		archive_path: impl AsRef<Path>,
+	) -> Result<LibraryConfigWrapped, LibraryManagerError> {
+		let staging_dir = self
+			.libraries_dir
+			.join(format!(".restore-{}", Uuid::new_v4()));
+
+		let result = self.restore_from(archive_path.as_ref(), &staging_dir).await;
+
+		if let Err(e) = fs::remove_dir_all(&staging_dir) {
+			if e.kind() != io::ErrorKind::NotFound {
+				warn!(
+					"Failed to remove restore staging directory '{}': {e:#?}",
+					staging_dir.display()
+				);
+			}
+		}
+
+		result
+	}
+
+	async fn restore_from(
+		&self,
+		archive_path: &Path,
+		staging_dir: &Path,
+	) -> Result<LibraryConfigWrapped, LibraryManagerError> {
+		let manifest = unpack_backup(archive_path, staging_dir, BACKUP_EXTENSION).await?;
+		let id = manifest.library_id;
+
+		let mut libraries = self.libraries.write().await;
+
+		// The live library, if it's loaded, must let go of its files before they're replaced
+		let was_loaded = match libraries.iter().position(|lib| lib.id == id) {
+			Some(idx) => {
+				let library = libraries.remove(idx);
+				if let Err(e) = self.close_library(library).await {
+					libraries.push(self.reopen(id).await?);
+					return Err(e);
+				}
+				true
+			}
+			None => false,
+		};
+
+		let library = match self.install_unpacked(&manifest, staging_dir).await {
+			Ok(library) => library,
+			Err(e) => {
+				// The replaced files were put back, so the library is loaded again from them
+				if was_loaded {
+					match self.reopen(id).await {
+						Ok(library) => libraries.push(library),
+						Err(reopen_error) => {
+							error!("Failed to reload library {id} after a failed restore: {reopen_error:#?}")
+						}
+					}
+				}
+				return Err(e);
+			}
+		};
+		let config = library.config.clone();
+
+		self.start_library(&library).await;
+
+		invalidate_query!(library, "library.list");
+
+		libraries.push(library);
+
+		Ok(LibraryConfigWrapped { uuid: id, config })
+	}
+
+	/// close_library stops the location watchers and jobs of a library and waits for its database client
+	/// to be dropped, so its files can be replaced. Callers must hold the `libraries` write lock.
+	async fn close_library(&self, library: Library) -> Result<(), LibraryManagerError> {
+		for location in library.db.location().find_many(vec![]).exec().await? {
+			if let Err(e) = self
+				.node_context
+				.location_manager
+				.remove(location.id, library.clone())
+				.await
+			{
+				error!("Failed to remove location from location manager: {:#?}", e);
+			}
+		}
+
+		self.node_context.jobs.pause_library_jobs(&library).await?;
+
+		let id = library.id;
+		let db = Arc::downgrade(&library.db);
+		drop(library);
+
+		// Requests and watchers which were using the library hold it until they're done
+		let deadline = Instant::now() + CLOSE_TIMEOUT;
+		while db.strong_count() > 0 {
+			if Instant::now() >= deadline {
+				return Err(LibraryManagerError::LibraryInUse(id));
+			}
+			sleep(Duration::from_millis(50)).await;
+		}
+
+		Ok(())
+	}
+
+	/// reopen loads a library closed with [`LibraryManager::close_library`] from its files again, and starts it.
+	async fn reopen(&self, id: Uuid) -> Result<Library, LibraryManagerError> {
+		let config =
+			LibraryConfig::read(self.libraries_dir.join(format!("{id}.sdlibrary"))).await?;
+		let library = Self::load(
+			id,
+			self.libraries_dir.join(format!("{id}.db")),
+			config,
+			self.node_context.clone(),
+		)
+		.await?;
+
+		self.start_library(&library).await;
+
+		Ok(library)
+	}
+
+	/// start_library watches the locations of a library which was just loaded and resumes its paused jobs,
+	/// like it's done on startup.
+	async fn start_library(&self, library: &Library) {
+		match library.db.location().find_many(vec![]).exec().await {
+			Ok(locations) => {
+				for location in locations {
+					if let Err(e) = self
+						.node_context
+						.location_manager
+						.add(location.id, library.clone())
+						.await
+					{
+						error!("Failed to add location to location manager: {:#?}", e);
+					}
+				}
+			}
+			Err(e) => error!(
+				"Failed to get locations from database for location manager: {:#?}",
+				e
+			),
+		}
+
+		if let Err(e) = Arc::clone(&self.node_context.jobs)
+			.resume_jobs(library)
+			.await
+		{
+			error!("Failed to resume jobs for library. {:#?}", e);
+		}
+	}
+
+	/// import loads a library exported with [`super::LibraryExportJob`] on another node.
+	/// Locations whose path doesn't exist on this node are relinked to local locations containing the same files.
+	pub(crate) async fn import(
+		&self,
+		export_path: impl AsRef<Path>,
+	) -> Result<(LibraryConfigWrapped, LibraryImportReport), LibraryManagerError> {
+		let staging_dir = self
+			.libraries_dir
+			.join(format!(".import-{}", Uuid::new_v4()));
+
+		let result = self.import_from(export_path.as_ref(), &staging_dir).await;
+
+		if let Err(e) = fs::remove_dir_all(&staging_dir) {
+			if e.kind() != io::ErrorKind::NotFound {
+				warn!(
+					"Failed to remove import staging directory '{}': {e:#?}",
+					staging_dir.display()
+				);
+			}
+		}
+
+		result
+	}
+
+	async fn import_from(
+		&self,
+		export_path: &Path,
+		staging_dir: &Path,
+	) -> Result<(LibraryConfigWrapped, LibraryImportReport), LibraryManagerError> {
+		let manifest = unpack_backup(export_path, staging_dir, EXPORT_EXTENSION).await?;
+		let id = manifest.library_id;
+
+		let mut libraries = self.libraries.write().await;
+		if libraries.iter().any(|lib| lib.id == id) {
+			return Err(LibraryManagerError::LibraryAlreadyExists(id));
+		}
+
+		let library = self.install_unpacked(&manifest, staging_dir).await?;
+		let config = library.config.clone();
+
+		let report = reconcile_locations(&library, &libraries).await?;
+
+		for location in library.db.location().find_many(vec![]).exec().await? {
+			if let Err(e) = self
+				.node_context
+				.location_manager
+				.add(location.id, library.clone())
+				.await
+			{
+				error!("Failed to add location to location manager: {:#?}", e);
+			}
+		}
+
+		invalidate_query!(library, "library.list");
+
+		libraries.push(library);
+
+		Ok((LibraryConfigWrapped { uuid: id, config }, report))
+	}
+
+	/// install_unpacked moves the database, config and thumbnails of an unpacked archive into place and loads
+	/// the library, running any pending migrations. The replaced database and config are kept as `{id}.db.bak`
+	/// and `{id}.sdlibrary.bak`, and put back if the library can't be installed.
+	/// Callers must hold the `libraries` write lock, with the library closed.
+	async fn install_unpacked(
+		&self,
+		manifest: &BackupManifest,
+		staging_dir: &Path,
+	) -> Result<Library, LibraryManagerError> {
+		let id = manifest.library_id;
+
+		let mut set_aside = vec![];
+		let result = self
+			.swap_in_unpacked(manifest, staging_dir, &mut set_aside)
+			.await;
+
+		if let Err(e) = &result {
+			warn!("Failed to install library {id}, rolling back: {e:#?}");
+
+			for path in self.library_files(id) {
+				if let Err(e) = fs::remove_file(&path) {
+					if e.kind() != io::ErrorKind::NotFound {
+						error!("Failed to remove '{}': {e:#?}", path.display());
+					}
+				}
+			}
+			for (path, backup_path) in set_aside.into_iter().rev() {
+				if let Err(e) = fs::rename(&backup_path, &path) {
+					error!(
+						"Failed to put back '{}' from '{}': {e:#?}",
+						path.display(),
+						backup_path.display()
+					);
+				}
+			}
+		}
+
+		result
+	}
+
+	/// swap_in_unpacked does the work of [`LibraryManager::install_unpacked`], recording the files it set aside
+	/// into `set_aside`. Every file is moved with a rename, so none is ever left half written.
+	async fn swap_in_unpacked(
+		&self,
+		manifest: &BackupManifest,
+		staging_dir: &Path,
+		set_aside: &mut Vec<(PathBuf, PathBuf)>,
+	) -> Result<Library, LibraryManagerError> {
+		let id = manifest.library_id;
+
+		// `{id}.db-wal` becomes `{id}.db.bak-wal`, so it still belongs to the kept database
+		for (path, backup_path) in self.library_files(id).into_iter().zip(
+			["db.bak", "db.bak-wal", "db.bak-shm", "db.bak-journal", "sdlibrary.bak"]
+				.map(|extension| self.libraries_dir.join(format!("{id}.{extension}"))),
+		) {
+			if path.try_exists()? {
+				fs::rename(&path, &backup_path)?;
+				set_aside.push((path, backup_path));
+			}
+		}
+
+		let db_path = self.libraries_dir.join(format!("{id}.db"));
+		fs::rename(staging_dir.join(BACKUP_DB_NAME), &db_path)?;
+
+		let config_path = self.libraries_dir.join(format!("{id}.sdlibrary"));
+		fs::rename(staging_dir.join(BACKUP_CONFIG_NAME), &config_path)?;
+
+		if manifest.includes_thumbnails {
+			let thumbnails_dir = self
+				.node_context
+				.config
+				.data_directory()
+				.join(THUMBNAIL_CACHE_DIR_NAME);
+			fs::create_dir_all(&thumbnails_dir)?;
+
+			let unpacked_thumbnails_dir = staging_dir.join(THUMBNAIL_CACHE_DIR_NAME);
+			if unpacked_thumbnails_dir.is_dir() {
+				for entry in fs::read_dir(unpacked_thumbnails_dir)?.filter_map(|entry| entry.ok()) {
+					let thumbnail_path = thumbnails_dir.join(entry.file_name());
+					if !thumbnail_path.try_exists()? {
+						fs::rename(entry.path(), thumbnail_path)?;
+					}
+				}
+			}
+		}
+
+		let config = LibraryConfig::read(config_path).await?;
+
+		Self::load(id, &db_path, config, self.node_context.clone()).await
+	}
+
+	/// library_files returns the paths of the database, its SQLite sidecar files and the config of a library.
+	fn library_files(&self, id: Uuid) -> Vec<PathBuf> {
+		["db", "db-wal", "db-shm", "db-journal", "sdlibrary"]
+			.into_iter()
+			.map(|extension| self.libraries_dir.join(format!("{id}.{extension}")))
+			.collect()
+	}
+
 	// get_ctx will return the library context for the given library id.
 	pub(crate) async fn get_ctx(&self, library_id: Uuid) -> Option<Library> {
 		self.libraries
@@ -299,19 +868,20 @@ impl LibraryManager {
 		node_context: NodeContext,
 	) -> Result<Library, LibraryManagerError> {
 		let db_path = db_path.as_ref();
+		let node_config = node_context.config.get().await;
 		let db = Arc::new(
-			load_and_migrate(&format!(
-				"file:{}",
-				db_path.as_os_str().to_str().ok_or_else(|| {
-					LibraryManagerError::InvalidDatabasePath(db_path.to_path_buf())
-				})?
-			))
-			.await
-			.unwrap(),
+			load_and_migrate(
+				&format!(
+					"file:{}",
+					db_path.as_os_str().to_str().ok_or_else(|| {
+						LibraryManagerError::InvalidDatabasePath(db_path.to_path_buf())
+					})?
+				),
+				&node_config.database,
+			)
+			.await?,
 		);
 
-		let node_config = node_context.config.get().await;
-
 		let platform = match env::consts::OS {
 			"windows" => Platform::Windows,
 			"macos" => Platform::MacOS,
@@ -352,3 +922,19 @@ impl LibraryManager {
 		})
 	}
 }
+
+/// library_cas_ids returns the cas_id of every identified file in the library.
+pub(crate) async fn library_cas_ids(
+	library: &Library,
+) -> Result<HashSet<String>, prisma_client_rust::QueryError> {
+	Ok(library
+		.db
+		.file_path()
+		.find_many(vec![file_path::cas_id::not(None)])
+		.select(file_path::select!({ cas_id }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|file_path| file_path.cas_id)
+		.collect())
+}
@@ -0,0 +1,639 @@
+use crate::{
+	invalidate_query,
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::Library,
+	prisma::{self, file_path, location, node, object, tag, tag_on_object, PrismaClient},
+	sync,
+};
+
+use std::{
+	collections::{HashMap, VecDeque},
+	path::{Path, PathBuf},
+};
+
+use prisma_client_rust::{Direction, NewClientError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+use tokio::sync::OnceCell;
+use tracing::{error, info};
+use uuid::Uuid;
+
+pub const LIBRARY_MERGE_JOB_NAME: &str = "library_merge";
+
+const BATCH_SIZE: i64 = 1000;
+
+#[derive(Error, Debug)]
+pub enum LibraryMergeError {
+	#[error("can't merge a library into itself")]
+	SameLibrary,
+	#[error("failed to open the source library database: {0}")]
+	OpenSource(#[from] Box<NewClientError>),
+}
+
+/// `LibraryMergeJob` runs on the target library and copies the locations, objects, tags and notes
+/// of the source library into it. Objects are deduplicated by the cas_id of their file paths.
+/// The source library is left untouched, so users can check the result before deleting it.
+/// Its database is opened once and kept open for all the steps.
+#[derive(Default)]
+pub struct LibraryMergeJob {
+	source: OnceCell<PrismaClient>,
+}
+
+#[derive(Serialize, Deserialize, Hash)]
+pub struct LibraryMergeJobInit {
+	pub source_library_id: Uuid,
+}
+
+/// `LibraryMergeReport` summarizes what happened during a merge, including every conflict
+/// that was resolved in favour of the target library.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct LibraryMergeReport {
+	pub tags_created: usize,
+	pub tags_merged: usize,
+	pub locations_created: usize,
+	pub locations_merged: usize,
+	pub file_paths_copied: usize,
+	pub objects_created: usize,
+	pub objects_deduplicated: usize,
+	pub conflicts: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LibraryMergeJobState {
+	source_db_path: PathBuf,
+	/// source tag id -> target tag id
+	tag_ids: HashMap<i32, i32>,
+	/// source location id -> (target location id, whether it was created by this merge)
+	location_ids: HashMap<i32, (i32, bool)>,
+	/// source object id -> target object id
+	object_ids: HashMap<i32, i32>,
+	report: LibraryMergeReport,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum LibraryMergeJobStep {
+	Tags,
+	Location(i32),
+	FilePaths { location_id: i32, skip: i64 },
+}
+
+object::include!(object_with_tags { tags });
+
+async fn open_source(path: &Path) -> Result<PrismaClient, JobError> {
+	Ok(
+		prisma::new_client_with_url(&format!("file:{}", path.to_str().ok_or(JobError::Path)?))
+			.await
+			.map_err(|e| LibraryMergeError::OpenSource(Box::new(e)))?,
+	)
+}
+
+impl LibraryMergeJob {
+	/// source opens the database of the source library the first time it's needed, which is in `init`,
+	/// or in the first step of a resumed job.
+	async fn source(&self, path: &Path) -> Result<&PrismaClient, JobError> {
+		self.source.get_or_try_init(|| open_source(path)).await
+	}
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for LibraryMergeJob {
+	type Init = LibraryMergeJobInit;
+	type Data = LibraryMergeJobState;
+	type Step = LibraryMergeJobStep;
+
+	fn name(&self) -> &'static str {
+		LIBRARY_MERGE_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let library = &ctx.library;
+
+		if state.init.source_library_id == library.id {
+			return Err(LibraryMergeError::SameLibrary.into());
+		}
+
+		let source_db_path = library
+			.config()
+			.data_directory()
+			.join("libraries")
+			.join(format!("{}.db", state.init.source_library_id));
+		let source = self.source(&source_db_path).await?;
+
+		let mut steps = VecDeque::from([LibraryMergeJobStep::Tags]);
+		for location in source.location().find_many(vec![]).exec().await? {
+			steps.push_back(LibraryMergeJobStep::Location(location.id));
+
+			let file_paths_count = source
+				.file_path()
+				.count(vec![file_path::location_id::equals(location.id)])
+				.exec()
+				.await?;
+
+			steps.extend(
+				(0..file_paths_count)
+					.step_by(BATCH_SIZE as usize)
+					.map(|skip| LibraryMergeJobStep::FilePaths {
+						location_id: location.id,
+						skip,
+					}),
+			);
+		}
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(steps.len()),
+			JobReportUpdate::Message(format!(
+				"Merging library {} into '{}'",
+				state.init.source_library_id, library.config.name
+			)),
+		]);
+
+		state.data = Some(LibraryMergeJobState {
+			source_db_path,
+			tag_ids: HashMap::new(),
+			location_ids: HashMap::new(),
+			object_ids: HashMap::new(),
+			report: LibraryMergeReport::default(),
+		});
+		state.steps = steps;
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let Library { db, sync, .. } = &ctx.library;
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+		let source = self.source(&data.source_db_path).await?;
+
+		match &state.steps[0] {
+			LibraryMergeJobStep::Tags => {
+				for source_tag in source.tag().find_many(vec![]).exec().await? {
+					let existing = db
+						.tag()
+						.find_first(vec![tag::pub_id::equals(source_tag.pub_id.clone())])
+						.exec()
+						.await?;
+
+					let existing = match (existing, &source_tag.name) {
+						(Some(tag), _) => Some(tag),
+						(None, Some(name)) => {
+							db.tag()
+								.find_first(vec![tag::name::equals(Some(name.clone()))])
+								.exec()
+								.await?
+						}
+						(None, None) => None,
+					};
+
+					let target_tag_id = match existing {
+						Some(target_tag) => {
+							if target_tag.color != source_tag.color {
+								data.report.conflicts.push(format!(
+									"Tag '{}' has a different color in both libraries, kept the target one",
+									source_tag.name.clone().unwrap_or_default()
+								));
+							}
+							data.report.tags_merged += 1;
+							target_tag.id
+						}
+						None => {
+							data.report.tags_created += 1;
+							sync.write_op(
+								db,
+								sync.unique_shared_create(
+									sync::tag::SyncId {
+										pub_id: source_tag.pub_id.clone(),
+									},
+									[
+										("name", json!(source_tag.name)),
+										("color", json!(source_tag.color)),
+									],
+								),
+								db.tag().create(
+									source_tag.pub_id.clone(),
+									vec![
+										tag::name::set(source_tag.name.clone()),
+										tag::color::set(source_tag.color.clone()),
+									],
+								),
+							)
+							.await?
+							.id
+						}
+					};
+
+					data.tag_ids.insert(source_tag.id, target_tag_id);
+				}
+			}
+			LibraryMergeJobStep::Location(location_id) => {
+				let Some(source_location) = source
+					.location()
+					.find_unique(location::id::equals(*location_id))
+					.exec()
+					.await?
+				else {
+					return Err(JobError::MissingData {
+						value: format!("source location <id={location_id}>"),
+					});
+				};
+
+				let existing = db
+					.location()
+					.find_first(vec![location::path::equals(source_location.path.clone())])
+					.exec()
+					.await?;
+
+				let target_location = match existing {
+					Some(target_location) => {
+						// Both libraries indexed the same directory, so we keep the target file paths
+						// and only carry over the objects metadata
+						data.report.locations_merged += 1;
+						data.location_ids
+							.insert(source_location.id, (target_location.id, false));
+						target_location
+					}
+					None => {
+						let pub_id_taken = db
+							.location()
+							.find_unique(location::pub_id::equals(source_location.pub_id.clone()))
+							.exec()
+							.await?
+							.is_some();
+
+						let pub_id = if pub_id_taken {
+							Uuid::new_v4().as_bytes().to_vec()
+						} else {
+							source_location.pub_id.clone()
+						};
+
+						let target_location = sync
+							.write_op(
+								db,
+								sync.unique_shared_create(
+									sync::location::SyncId {
+										pub_id: pub_id.clone(),
+									},
+									[
+										("node", json!({ "pub_id": ctx.library.id.as_bytes() })),
+										("name", json!(source_location.name)),
+										("path", json!(source_location.path)),
+										("is_archived", json!(source_location.is_archived)),
+										(
+											"generate_preview_media",
+											json!(source_location.generate_preview_media),
+										),
+										(
+											"sync_preview_media",
+											json!(source_location.sync_preview_media),
+										),
+										("hidden", json!(source_location.hidden)),
+									],
+								),
+								db.location().create(
+									pub_id,
+									source_location.name.clone(),
+									source_location.path.clone(),
+									node::id::equals(ctx.library.node_local_id),
+									vec![
+										location::is_archived::set(source_location.is_archived),
+										location::generate_preview_media::set(
+											source_location.generate_preview_media,
+										),
+										location::sync_preview_media::set(
+											source_location.sync_preview_media,
+										),
+										location::hidden::set(source_location.hidden),
+									],
+								),
+							)
+							.await?;
+
+						data.report.locations_created += 1;
+						data.location_ids
+							.insert(source_location.id, (target_location.id, true));
+						target_location
+					}
+				};
+
+				info!(
+					"Merging location '{}' into location <id={}>",
+					source_location.path, target_location.id
+				);
+			}
+			LibraryMergeJobStep::FilePaths { location_id, skip } => {
+				let (target_location_id, created) = *data
+					.location_ids
+					.get(location_id)
+					.ok_or_else(|| JobError::MissingData {
+						value: format!("merged location for source location <id={location_id}>"),
+					})?;
+
+				let file_paths = source
+					.file_path()
+					.find_many(vec![file_path::location_id::equals(*location_id)])
+					.order_by(file_path::id::order(Direction::Asc))
+					.skip(*skip)
+					.take(BATCH_SIZE)
+					.exec()
+					.await?;
+
+				let source_objects = source
+					.object()
+					.find_many(vec![object::id::in_vec(
+						file_paths
+							.iter()
+							.filter_map(|file_path| file_path.object_id)
+							.collect(),
+					)])
+					.include(object_with_tags::include())
+					.exec()
+					.await?
+					.into_iter()
+					.map(|object| (object.id, object))
+					.collect::<HashMap<_, _>>();
+
+				let mut new_file_paths = Vec::with_capacity(file_paths.len());
+				for file_path in file_paths {
+					let source_object = file_path
+						.object_id
+						.and_then(|object_id| source_objects.get(&object_id));
+
+					// The file paths of a merged location aren't copied, so their objects are only merged
+					// into the objects of the target file paths with the same cas_id
+					let target_object_id = match source_object {
+						Some(source_object) => {
+							merge_object(
+								&ctx.library,
+								data,
+								source_object,
+								file_path.cas_id.as_deref(),
+								created,
+							)
+							.await?
+						}
+						None => None,
+					};
+
+					if created {
+						data.report.file_paths_copied += 1;
+						new_file_paths.push(file_path::create_unchecked(
+							file_path.id,
+							target_location_id,
+							file_path.materialized_path,
+							file_path.name,
+							file_path.extension,
+							vec![
+								file_path::is_dir::set(file_path.is_dir),
+								file_path::cas_id::set(file_path.cas_id),
+								file_path::parent_id::set(file_path.parent_id),
+								file_path::object_id::set(target_object_id),
+								file_path::date_created::set(file_path.date_created),
+								file_path::date_modified::set(file_path.date_modified),
+								file_path::date_indexed::set(file_path.date_indexed),
+							],
+						));
+					}
+				}
+
+				if !new_file_paths.is_empty() {
+					db.file_path()
+						.create_many(new_file_paths)
+						.skip_duplicates()
+						.exec()
+						.await?;
+				}
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let library = &ctx.library;
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		for (target_location_id, _) in data.location_ids.values().filter(|(_, created)| *created) {
+			if let Err(e) = library
+				.location_manager()
+				.add(*target_location_id, library.clone())
+				.await
+			{
+				error!("Failed to add location to location manager: {:#?}", e);
+			}
+		}
+
+		invalidate_query!(library, "locations.list");
+		invalidate_query!(library, "tags.list");
+
+		info!(
+			"Merged library {} into {}: {:#?}",
+			state.init.source_library_id, library.id, data.report
+		);
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
+
+/// `merge_object` finds or creates the target object for a source object, deduplicating by cas_id,
+/// and carries over its tags, note and flags. A new object is only created when `can_create` is set,
+/// as an object without any file path would be left otherwise. Returns the id of the target object, if there is one.
+async fn merge_object(
+	library: &Library,
+	data: &mut LibraryMergeJobState,
+	source_object: &object_with_tags::Data,
+	cas_id: Option<&str>,
+	can_create: bool,
+) -> Result<Option<i32>, JobError> {
+	let Library { db, sync, .. } = library;
+
+	if let Some(target_object_id) = data.object_ids.get(&source_object.id) {
+		return Ok(Some(*target_object_id));
+	}
+
+	let existing = match cas_id {
+		Some(cas_id) => {
+			db.object()
+				.find_first(vec![object::file_paths::some(vec![
+					file_path::cas_id::equals(Some(cas_id.to_string())),
+				])])
+				.exec()
+				.await?
+		}
+		None => None,
+	};
+
+	let target_object_id = match existing {
+		Some(target_object) => {
+			data.report.objects_deduplicated += 1;
+
+			let mut params = vec![];
+			match (&target_object.note, &source_object.note) {
+				(None, Some(note)) => {
+					params.push((("note", json!(note)), object::note::set(Some(note.clone()))))
+				}
+				(Some(target_note), Some(source_note)) if target_note != source_note => {
+					data.report.conflicts.push(format!(
+						"Object '{}' has a different note in both libraries, kept the target one",
+						source_object.name.clone().unwrap_or_default()
+					));
+				}
+				_ => {}
+			}
+			if source_object.favorite && !target_object.favorite {
+				params.push((("favorite", json!(true)), object::favorite::set(true)));
+			}
+			if source_object.important && !target_object.important {
+				params.push((("important", json!(true)), object::important::set(true)));
+			}
+
+			if !params.is_empty() {
+				let (sync_params, db_params): (Vec<_>, Vec<_>) = params.into_iter().unzip();
+
+				sync.write_ops(
+					db,
+					(
+						sync_params
+							.into_iter()
+							.map(|(field, value)| {
+								sync.shared_update(
+									sync::object::SyncId {
+										pub_id: target_object.pub_id.clone(),
+									},
+									field,
+									value,
+								)
+							})
+							.collect(),
+						db.object()
+							.update(object::id::equals(target_object.id), db_params),
+					),
+				)
+				.await?;
+			}
+
+			target_object.id
+		}
+		None if can_create => {
+			data.report.objects_created += 1;
+
+			let pub_id_taken = db
+				.object()
+				.find_unique(object::pub_id::equals(source_object.pub_id.clone()))
+				.exec()
+				.await?
+				.is_some();
+
+			let pub_id = if pub_id_taken {
+				Uuid::new_v4().as_bytes().to_vec()
+			} else {
+				source_object.pub_id.clone()
+			};
+
+			let sync_id = || sync::object::SyncId {
+				pub_id: pub_id.clone(),
+			};
+
+			let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+				(
+					("name", json!(source_object.name)),
+					object::name::set(source_object.name.clone()),
+				),
+				(
+					("extension", json!(source_object.extension)),
+					object::extension::set(source_object.extension.clone()),
+				),
+				(
+					("kind", json!(source_object.kind)),
+					object::kind::set(source_object.kind),
+				),
+				(
+					("size_in_bytes", json!(source_object.size_in_bytes)),
+					object::size_in_bytes::set(source_object.size_in_bytes.clone()),
+				),
+				(
+					("hidden", json!(source_object.hidden)),
+					object::hidden::set(source_object.hidden),
+				),
+				(
+					("favorite", json!(source_object.favorite)),
+					object::favorite::set(source_object.favorite),
+				),
+				(
+					("important", json!(source_object.important)),
+					object::important::set(source_object.important),
+				),
+				(
+					("has_thumbnail", json!(source_object.has_thumbnail)),
+					object::has_thumbnail::set(source_object.has_thumbnail),
+				),
+				(
+					("note", json!(source_object.note)),
+					object::note::set(source_object.note.clone()),
+				),
+				(
+					("date_created", json!(source_object.date_created)),
+					object::date_created::set(source_object.date_created),
+				),
+				(
+					("date_modified", json!(source_object.date_modified)),
+					object::date_modified::set(source_object.date_modified),
+				),
+			]
+			.into_iter()
+			.unzip();
+
+			sync.write_ops(
+				db,
+				(
+					[sync.shared_create(sync_id())]
+						.into_iter()
+						.chain(
+							sync_params
+								.into_iter()
+								.map(|(field, value)| sync.shared_update(sync_id(), field, value)),
+						)
+						.collect(),
+					db.object().create(pub_id.clone(), db_params),
+				),
+			)
+			.await?
+			.id
+		}
+		None => return Ok(None),
+	};
+
+	let tags_on_object = source_object
+		.tags
+		.iter()
+		.filter_map(|tag_on_object| data.tag_ids.get(&tag_on_object.tag_id))
+		.map(|target_tag_id| {
+			tag_on_object::create_unchecked(*target_tag_id, target_object_id, vec![])
+		})
+		.collect::<Vec<_>>();
+
+	if !tags_on_object.is_empty() {
+		db.tag_on_object()
+			.create_many(tags_on_object)
+			.skip_duplicates()
+			.exec()
+			.await?;
+	}
+
+	data.object_ids.insert(source_object.id, target_object_id);
+
+	Ok(Some(target_object_id))
+}
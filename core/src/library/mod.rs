@@ -1,8 +1,26 @@
+mod activity;
+mod backup;
 mod config;
+mod export;
 #[allow(clippy::module_inception)]
 mod library;
+mod maintenance;
 mod manager;
+mod merge;
+mod recovery;
+mod storage_budget;
+mod template;
+mod trash;
 
+pub use activity::*;
+pub use backup::*;
 pub use config::*;
+pub use export::*;
 pub use library::*;
+pub use maintenance::*;
 pub use manager::*;
+pub use merge::*;
+pub use recovery::*;
+pub use storage_budget::*;
+pub use template::*;
+pub use trash::*;
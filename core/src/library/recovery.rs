@@ -0,0 +1,265 @@
+use crate::{
+	job::{recover_interrupted_steps, JobStatus},
+	library::{Library, LibraryManager},
+	object::preview::THUMBNAIL_CACHE_DIR_NAME,
+	prisma::job,
+};
+
+use std::{
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use chrono::Utc;
+use int_enum::IntEnum;
+use tokio::{fs, io::AsyncReadExt};
+use tracing::{error, info, warn};
+
+use super::maintenance::integrity_check;
+
+/// RUNNING_MARKER_NAME is the name of the file which exists in the data directory while the node is running.
+/// Finding it when the node starts means the last run didn't shut down cleanly.
+const RUNNING_MARKER_NAME: &str = "running.lock";
+
+/// RecoveryReport is what the recovery pass of a library found and fixed.
+#[derive(Debug, Default)]
+pub struct RecoveryReport {
+	/// Jobs which were running and have been paused, to be resumed from their last saved state
+	pub paused_jobs: usize,
+	/// Jobs which were running without any saved state and have been marked as failed
+	pub failed_jobs: usize,
+	/// Journaled job steps which were interrupted, whose writes have been rolled back
+	pub rolled_back_steps: usize,
+	pub integrity_issues: Vec<String>,
+	pub resynced_locations: usize,
+}
+
+/// mark_node_running creates the running marker in the data directory.
+/// It returns when the previous run started if it didn't shut down cleanly.
+pub(crate) async fn mark_node_running(data_dir: impl AsRef<Path>) -> Option<SystemTime> {
+	let marker = data_dir.as_ref().join(RUNNING_MARKER_NAME);
+	let crashed_run_started = fs::metadata(&marker)
+		.await
+		.and_then(|metadata| metadata.modified())
+		.ok();
+
+	// Writing when this run started also updates the modification time of an existing marker
+	if let Err(e) = fs::write(&marker, Utc::now().to_rfc3339()).await {
+		warn!("Failed to create the running marker, unclean shutdowns won't be detected: {e:#?}");
+	}
+
+	crashed_run_started
+}
+
+/// mark_node_stopped removes the running marker, as the node is shutting down cleanly.
+pub(crate) async fn mark_node_stopped(data_dir: impl AsRef<Path>) {
+	if let Err(e) = fs::remove_file(data_dir.as_ref().join(RUNNING_MARKER_NAME)).await {
+		warn!("Failed to remove the running marker: {e:#?}");
+	}
+}
+
+/// recover_from_crash runs the recovery pass after an unclean shutdown, before any job is resumed.
+/// `crashed_run_started` is when the run which crashed started, to only check what it could have left behind.
+pub(crate) async fn recover_from_crash(
+	library_manager: &LibraryManager,
+	data_dir: impl AsRef<Path>,
+	crashed_run_started: SystemTime,
+) {
+	warn!("Spacedrive didn't shut down cleanly, running the recovery pass");
+
+	match remove_partial_thumbnails(
+		data_dir.as_ref().join(THUMBNAIL_CACHE_DIR_NAME),
+		crashed_run_started,
+	)
+	.await
+	{
+		Ok(removed) => info!("Removed {removed} partial thumbnails"),
+		Err(e) => error!("Failed to remove partial thumbnails: {e:#?}"),
+	}
+
+	for library in library_manager.get_all_libraries().await {
+		let report = recover_library(&library).await;
+
+		if !report.integrity_issues.is_empty() {
+			error!(
+				"Found {} integrity issues in library {} database, restoring a backup is recommended: {:#?}",
+				report.integrity_issues.len(),
+				library.id,
+				report.integrity_issues
+			);
+		}
+
+		info!("Recovered library {}: {report:?}", library.id);
+	}
+}
+
+/// recover_library reconciles the state of a library which could have been left inconsistent by a crash.
+pub async fn recover_library(library: &Library) -> RecoveryReport {
+	let mut report = RecoveryReport::default();
+	let db = &library.db;
+
+	// Jobs with an interrupted journaled step are paused with their state from before it
+	match recover_interrupted_steps(library).await {
+		Ok(steps) => report.rolled_back_steps = steps,
+		Err(e) => error!(
+			"Failed to roll back the interrupted job steps of library {}: {e:#?}",
+			library.id
+		),
+	}
+
+	// Only jobs with a saved state can resume, either from an earlier pause or from a checkpoint of their last completed step
+	let orphaned_jobs = db
+		.job()
+		.find_many(vec![job::status::equals(JobStatus::Running.int_value())])
+		.exec()
+		.await
+		.unwrap_or_else(|e| {
+			error!(
+				"Failed to fetch the running jobs of library {}: {e:#?}",
+				library.id
+			);
+			vec![]
+		});
+
+	for orphaned_job in orphaned_jobs {
+		let status = match orphaned_job.data {
+			Some(_) => {
+				report.paused_jobs += 1;
+				JobStatus::Paused
+			}
+			None => {
+				report.failed_jobs += 1;
+				JobStatus::Failed
+			}
+		};
+
+		if let Err(e) = db
+			.job()
+			.update(
+				job::id::equals(orphaned_job.id),
+				vec![
+					job::status::set(status.int_value()),
+					job::date_modified::set(Utc::now().into()),
+				],
+			)
+			.exec()
+			.await
+		{
+			error!("Failed to reconcile orphaned job: {e:#?}");
+		}
+	}
+
+	match integrity_check(db).await {
+		Ok(issues) => report.integrity_issues = issues,
+		Err(e) => error!(
+			"Failed to check the integrity of library {} database: {e:#?}",
+			library.id
+		),
+	}
+
+	match library.last_file_path_id_manager.resync(db).await {
+		Ok(locations) => report.resynced_locations = locations,
+		Err(e) => error!(
+			"Failed to resync the file path ids of library {}: {e:#?}",
+			library.id
+		),
+	}
+
+	report
+}
+
+/// remove_partial_thumbnails removes the thumbnails written since the given time which aren't complete image files,
+/// including the ones in the directories of thumbnail variants.
+async fn remove_partial_thumbnails(
+	thumbnails_dir: PathBuf,
+	since: SystemTime,
+) -> Result<usize, std::io::Error> {
+	let mut removed = 0;
+	let mut dirs = vec![thumbnails_dir];
+	while let Some(dir) = dirs.pop() {
+		let mut read_dir = match fs::read_dir(&dir).await {
+			Ok(read_dir) => read_dir,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+			Err(e) => return Err(e),
+		};
+
+		while let Some(entry) = read_dir.next_entry().await? {
+			let metadata = entry.metadata().await?;
+			if metadata.is_dir() {
+				dirs.push(entry.path());
+				continue;
+			}
+			if !metadata.is_file() || metadata.modified()? < since {
+				continue;
+			}
+
+			let path = entry.path();
+			let is_complete = match path.extension().and_then(|extension| extension.to_str()) {
+				Some("webp") => is_complete_webp(&path, metadata.len()).await,
+				Some("avif") => is_complete_avif(&path, metadata.len()).await,
+				// Not a thumbnail
+				_ => true,
+			};
+
+			if !is_complete {
+				fs::remove_file(path).await?;
+				removed += 1;
+			}
+		}
+	}
+
+	Ok(removed)
+}
+
+/// is_complete_webp checks the RIFF header of a WebP file, which holds the size the file must have.
+async fn is_complete_webp(path: &Path, len: u64) -> bool {
+	let mut header = [0; 12];
+	let Ok(mut file) = fs::File::open(path).await else {
+		return false;
+	};
+
+	if file.read_exact(&mut header).await.is_err() {
+		return false;
+	}
+
+	let riff_size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+	&header[0..4] == b"RIFF" && &header[8..12] == b"WEBP" && riff_size as u64 + 8 == len
+}
+
+/// is_complete_avif checks that the boxes of an AVIF file, which start with their size, add up to the size of the file.
+async fn is_complete_avif(path: &Path, len: u64) -> bool {
+	let Ok(bytes) = fs::read(path).await else {
+		return false;
+	};
+
+	let mut offset = 0;
+	while offset < len {
+		let start = offset as usize;
+		let Some(header) = bytes.get(start..start + 8) else {
+			return false;
+		};
+
+		let box_size = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+			// The last box can extend to the end of the file
+			0 => len - offset,
+			// The size doesn't fit in 32 bits and follows the type of the box
+			1 => match bytes.get(start + 8..start + 16) {
+				Some(large_size) => u64::from_be_bytes(
+					large_size
+						.try_into()
+						.expect("slice of 8 bytes converts to an array"),
+				),
+				None => return false,
+			},
+			size => size as u64,
+		};
+		if box_size < 8 {
+			return false;
+		}
+
+		offset = offset.saturating_add(box_size);
+	}
+
+	offset == len && bytes.get(4..8) == Some(b"ftyp".as_slice())
+}
@@ -0,0 +1,186 @@
+use crate::{
+	api::CoreEvent,
+	node::ThumbnailFormat,
+	object::preview::{thumbnail_path, THUMBNAIL_CACHE_DIR_NAME},
+	util::webhook::send_webhook,
+};
+
+use std::{collections::HashMap, path::Path, sync::Arc, time::Duration};
+
+use rspc::Type;
+use serde::Serialize;
+use serde_with::{serde_as, DisplayFromStr};
+use tokio::{fs, time::interval};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use super::{
+	default_backups_dir, manager::library_cas_ids, Library, LibraryManager, LibraryManagerError,
+	BACKUP_EXTENSION,
+};
+
+/// Percentages of the storage budget which trigger an alert when crossed
+pub const STORAGE_BUDGET_THRESHOLDS: [u8; 2] = [80, 100];
+
+/// How often the monitor checks the storage used by every library with a budget
+const STORAGE_MONITOR_TICK: Duration = Duration::from_secs(60 * 10);
+
+/// LibraryStorageUsage is the disk space used by a library, split by what uses it.
+#[serde_as]
+#[derive(Debug, Clone, Default, Serialize, Type)]
+pub struct LibraryStorageUsage {
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub database_bytes: u64,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub thumbnails_bytes: u64,
+	/// managed_bytes is the space used by files Spacedrive creates for the library, like backups.
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub managed_bytes: u64,
+}
+
+impl LibraryStorageUsage {
+	pub fn total(&self) -> u64 {
+		self.database_bytes + self.thumbnails_bytes + self.managed_bytes
+	}
+}
+
+/// StorageBudgetAlert is emitted when a library crosses one of the [`STORAGE_BUDGET_THRESHOLDS`] of its budget.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct StorageBudgetAlert {
+	pub library_id: Uuid,
+	pub library_name: String,
+	/// threshold is the percentage of the budget which was crossed.
+	pub threshold: u8,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub used_bytes: u64,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub max_bytes: u64,
+}
+
+async fn file_size(path: impl AsRef<Path>) -> u64 {
+	fs::metadata(path)
+		.await
+		.map(|metadata| metadata.len())
+		.unwrap_or(0)
+}
+
+/// library_storage_usage measures the disk space used by the database, thumbnails and backups of a library.
+pub async fn library_storage_usage(
+	library: &Library,
+) -> Result<LibraryStorageUsage, LibraryManagerError> {
+	let data_dir = library.config().data_directory();
+	let mut usage = LibraryStorageUsage::default();
+
+	let db_path = data_dir
+		.join("libraries")
+		.join(format!("{}.db", library.id));
+	usage.database_bytes = file_size(&db_path).await;
+	for suffix in ["-wal", "-shm"] {
+		usage.database_bytes += file_size(format!("{}{suffix}", db_path.display())).await;
+	}
+
+	let thumbnails_dir = data_dir.join(THUMBNAIL_CACHE_DIR_NAME);
+	for cas_id in library_cas_ids(library).await? {
+		for format in ThumbnailFormat::ALL {
+			usage.thumbnails_bytes +=
+				file_size(thumbnail_path(&thumbnails_dir, None, &cas_id, format)).await;
+		}
+	}
+
+	let backups_dir = library
+		.config
+		.backup
+		.as_ref()
+		.and_then(|schedule| schedule.output_dir.clone())
+		.unwrap_or_else(|| default_backups_dir(library));
+	if let Ok(mut read_dir) = fs::read_dir(backups_dir).await {
+		let prefix = format!("{}-", library.id);
+		while let Ok(Some(entry)) = read_dir.next_entry().await {
+			let is_backup = entry
+				.file_name()
+				.to_str()
+				.map(|name| name.starts_with(&prefix) && name.ends_with(BACKUP_EXTENSION))
+				.unwrap_or(false);
+
+			if is_backup {
+				usage.managed_bytes += file_size(entry.path()).await;
+			}
+		}
+	}
+
+	Ok(usage)
+}
+
+/// spawn_storage_budget_monitor periodically measures every library with a storage budget, and emits a
+/// [`CoreEvent::StorageBudgetAlert`] and calls the budget webhook whenever a new threshold is crossed.
+pub fn spawn_storage_budget_monitor(library_manager: Arc<LibraryManager>) {
+	tokio::spawn(async move {
+		let mut interval = interval(STORAGE_MONITOR_TICK);
+		// Highest threshold already reported for each library, so we only alert when crossing a new one
+		let mut reported_thresholds = HashMap::<Uuid, u8>::new();
+
+		loop {
+			interval.tick().await;
+
+			for library in library_manager.get_all_libraries().await {
+				let Some(budget) = library.config.storage_budget.clone() else {
+					reported_thresholds.remove(&library.id);
+					continue;
+				};
+
+				let usage = match library_storage_usage(&library).await {
+					Ok(usage) => usage,
+					Err(e) => {
+						warn!(
+							"Failed to measure storage used by library {}: {e:#?}",
+							library.id
+						);
+						continue;
+					}
+				};
+
+				let used_bytes = usage.total();
+				let crossed = STORAGE_BUDGET_THRESHOLDS
+					.into_iter()
+					.filter(|threshold| {
+						used_bytes as u128 * 100 >= budget.max_bytes as u128 * *threshold as u128
+					})
+					.max()
+					.unwrap_or(0);
+
+				let reported = reported_thresholds.entry(library.id).or_default();
+				if crossed <= *reported {
+					// Usage went down, so we can alert again if it grows back
+					*reported = crossed;
+					continue;
+				}
+				*reported = crossed;
+
+				let alert = StorageBudgetAlert {
+					library_id: library.id,
+					library_name: library.config.name.clone(),
+					threshold: crossed,
+					used_bytes,
+					max_bytes: budget.max_bytes,
+				};
+
+				info!(
+					"Library {} crossed {}% of its storage budget ({used_bytes}/{} bytes)",
+					library.id, crossed, budget.max_bytes
+				);
+
+				library.emit(CoreEvent::StorageBudgetAlert(alert.clone()));
+
+				if let Some(url) = &budget.webhook_url {
+					send_webhook(url, &alert).await;
+				}
+			}
+		}
+	});
+}
@@ -0,0 +1,228 @@
+use crate::{
+	node::NodeConfigManager,
+	prisma::{tag, PrismaClient},
+};
+
+use std::path::{Path, PathBuf};
+
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::warn;
+use uuid::Uuid;
+
+use super::{Library, LibraryBackupSchedule, LibraryConfig, LibraryManagerError};
+
+/// TEMPLATES_DIR_NAME is the name of the directory inside the node data directory where library templates are stored.
+pub const TEMPLATES_DIR_NAME: &str = "library_templates";
+pub const TEMPLATE_EXTENSION: &str = "sdtemplate";
+
+/// LibraryTemplate holds the setup of a library which can be reused when creating new libraries.
+/// This is stored as a '{uuid}.sdtemplate' file in the templates directory.
+#[derive(Debug, Serialize, Deserialize, Clone, Type)]
+pub struct LibraryTemplate {
+	pub id: Uuid,
+	pub name: String,
+	pub description: String,
+	/// indexer_rules replace the default indexer rules of libraries created from this template.
+	pub indexer_rules: Vec<LibraryTemplateIndexerRule>,
+	pub tags: Vec<LibraryTemplateTag>,
+	pub backup: Option<LibraryBackupSchedule>,
+	pub maintenance_interval_secs: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Type)]
+pub struct LibraryTemplateIndexerRule {
+	pub kind: i32,
+	pub name: String,
+	/// parameters are stored exactly as they are in the database, see [`crate::location::indexer::rules::ParametersPerKind`].
+	pub parameters: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Type)]
+pub struct LibraryTemplateTag {
+	pub name: Option<String>,
+	pub color: Option<String>,
+}
+
+impl LibraryTemplate {
+	/// from_library captures the indexer rules, tags and settings of an existing library.
+	pub async fn from_library(
+		library: &Library,
+		name: String,
+		description: String,
+	) -> Result<Self, LibraryManagerError> {
+		let indexer_rules = library
+			.db
+			.indexer_rule()
+			.find_many(vec![])
+			.exec()
+			.await?
+			.into_iter()
+			.map(|rule| LibraryTemplateIndexerRule {
+				kind: rule.kind,
+				name: rule.name,
+				parameters: rule.parameters,
+			})
+			.collect();
+
+		let tags = library
+			.db
+			.tag()
+			.find_many(vec![])
+			.exec()
+			.await?
+			.into_iter()
+			.map(|tag| LibraryTemplateTag {
+				name: tag.name,
+				color: tag.color,
+			})
+			.collect();
+
+		Ok(Self {
+			id: Uuid::new_v4(),
+			name,
+			description,
+			indexer_rules,
+			tags,
+			backup: library.config.backup.clone(),
+			maintenance_interval_secs: library.config.maintenance_interval_secs,
+		})
+	}
+
+	/// apply_config copies the settings of this template into the config of a new library.
+	pub(super) fn apply_config(&self, config: &mut LibraryConfig) {
+		config.backup = self.backup.clone();
+		config.maintenance_interval_secs = self.maintenance_interval_secs;
+	}
+
+	/// apply_db creates the indexer rules and tags of this template in the database of a new library.
+	pub(super) async fn apply_db(&self, db: &PrismaClient) -> Result<(), LibraryManagerError> {
+		db._batch(
+			self.indexer_rules
+				.iter()
+				.map(|rule| {
+					db.indexer_rule().create(
+						rule.kind,
+						rule.name.clone(),
+						rule.parameters.clone(),
+						vec![],
+					)
+				})
+				.collect::<Vec<_>>(),
+		)
+		.await?;
+
+		db._batch(
+			self.tags
+				.iter()
+				.map(|template_tag| {
+					db.tag().create(
+						Uuid::new_v4().as_bytes().to_vec(),
+						vec![
+							tag::name::set(template_tag.name.clone()),
+							tag::color::set(template_tag.color.clone()),
+						],
+					)
+				})
+				.collect::<Vec<_>>(),
+		)
+		.await?;
+
+		Ok(())
+	}
+
+	/// has_indexer_rules returns whether libraries created from this template should skip the default indexer rules.
+	pub(super) fn has_indexer_rules(&self) -> bool {
+		!self.indexer_rules.is_empty()
+	}
+}
+
+/// templates_dir returns the directory where library templates are stored.
+pub fn templates_dir(config: &NodeConfigManager) -> PathBuf {
+	config.data_directory().join(TEMPLATES_DIR_NAME)
+}
+
+fn template_path(dir: impl AsRef<Path>, id: Uuid) -> PathBuf {
+	dir.as_ref().join(format!("{id}.{TEMPLATE_EXTENSION}"))
+}
+
+/// list_templates returns every template in the templates directory, skipping the ones which can't be read.
+pub async fn list_templates(
+	config: &NodeConfigManager,
+) -> Result<Vec<LibraryTemplate>, LibraryManagerError> {
+	let dir = templates_dir(config);
+	let mut read_dir = match fs::read_dir(&dir).await {
+		Ok(read_dir) => read_dir,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+		Err(e) => return Err(e.into()),
+	};
+
+	let mut templates = vec![];
+	while let Some(entry) = read_dir.next_entry().await? {
+		let path = entry.path();
+		if path.extension().and_then(|ext| ext.to_str()) != Some(TEMPLATE_EXTENSION) {
+			continue;
+		}
+
+		match fs::read(&path)
+			.await
+			.map_err(LibraryManagerError::from)
+			.and_then(|bytes| Ok(serde_json::from_slice::<LibraryTemplate>(&bytes)?))
+		{
+			Ok(template) => templates.push(template),
+			Err(e) => warn!(
+				"Failed to read library template at '{}', skipping: {e:#?}",
+				path.display()
+			),
+		}
+	}
+
+	templates.sort_by(|a, b| a.name.cmp(&b.name));
+
+	Ok(templates)
+}
+
+/// read_template returns the template with the given id.
+pub async fn read_template(
+	config: &NodeConfigManager,
+	id: Uuid,
+) -> Result<LibraryTemplate, LibraryManagerError> {
+	match fs::read(template_path(templates_dir(config), id)).await {
+		Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+			Err(LibraryManagerError::TemplateNotFound(id))
+		}
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// save_template writes the template to the templates directory, replacing any template with the same id.
+pub async fn save_template(
+	config: &NodeConfigManager,
+	template: &LibraryTemplate,
+) -> Result<(), LibraryManagerError> {
+	let dir = templates_dir(config);
+	fs::create_dir_all(&dir).await?;
+	fs::write(
+		template_path(dir, template.id),
+		serde_json::to_vec(template)?,
+	)
+	.await?;
+
+	Ok(())
+}
+
+/// delete_template removes the template with the given id.
+pub async fn delete_template(
+	config: &NodeConfigManager,
+	id: Uuid,
+) -> Result<(), LibraryManagerError> {
+	match fs::remove_file(template_path(templates_dir(config), id)).await {
+		Ok(()) => Ok(()),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+			Err(LibraryManagerError::TemplateNotFound(id))
+		}
+		Err(e) => Err(e.into()),
+	}
+}
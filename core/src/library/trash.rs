@@ -0,0 +1,380 @@
+use crate::{
+	api::utils::get_size,
+	invalidate_query,
+	location::{
+		delete_directory,
+		file_path_helper::{get_existing_file_path_id, MaterializedPath},
+		find_location, light_scan_location, location_with_indexer_rules, LocationError,
+	},
+	prisma::{location, trashed_file},
+};
+
+use std::{
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use chrono::Utc;
+use prisma_client_rust::{Direction, QueryError};
+use rspc::ErrorCode;
+use thiserror::Error;
+use tokio::{fs, io, time::interval};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use super::{ActivityKind, Library, LibraryManager};
+
+/// TRASH_DIR_NAME is the directory at the root of every location where its trashed files are kept.
+/// It's never indexed nor watched.
+pub const TRASH_DIR_NAME: &str = ".sdtrash";
+
+/// Used for libraries which don't set a trash retention
+pub const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+
+/// How often the purger removes the trashed files past the retention of their library
+const TRASH_PURGE_TICK: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Error, Debug)]
+pub enum TrashError {
+	#[error("trashed file not found (id: {0})")]
+	NotFound(i32),
+	#[error("location not found (id: {0})")]
+	LocationNotFound(i32),
+	#[error("can't restore over an existing file (path: {0:?})")]
+	RestoreConflict(PathBuf),
+	#[error("can't restore into a directory which isn't indexed, restore it first (path: {0:?})")]
+	ParentNotIndexed(PathBuf),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("filesystem error: {0}")]
+	IO(#[from] io::Error),
+	#[error("location error: {0}")]
+	Location(#[from] LocationError),
+}
+
+impl From<TrashError> for rspc::Error {
+	fn from(error: TrashError) -> Self {
+		let code = match error {
+			TrashError::NotFound(_) | TrashError::LocationNotFound(_) => ErrorCode::NotFound,
+			TrashError::RestoreConflict(_) => ErrorCode::Conflict,
+			TrashError::ParentNotIndexed(_) => ErrorCode::BadRequest,
+			_ => ErrorCode::InternalServerError,
+		};
+
+		rspc::Error::with_cause(code, error.to_string(), error)
+	}
+}
+
+/// trashed_path is where a trashed file is kept, named after its `pub_id` so names never collide.
+fn trashed_path(location_path: impl AsRef<Path>, pub_id: &[u8]) -> PathBuf {
+	location_path
+		.as_ref()
+		.join(TRASH_DIR_NAME)
+		.join(Uuid::from_slice(pub_id).unwrap_or_default().to_string())
+}
+
+async fn location_path(library: &Library, location_id: i32) -> Result<PathBuf, TrashError> {
+	Ok(find_location(library, location_id)
+		.select(location::select!({ path }))
+		.exec()
+		.await?
+		.ok_or(TrashError::LocationNotFound(location_id))?
+		.path
+		.into())
+}
+
+/// move_to_trash moves a file or directory of a location to the trash of the location,
+/// where it's kept until it's restored or the trash retention of the library passes.
+/// The watcher ignores the trash, so the file paths under it are removed from the library here.
+pub async fn move_to_trash(
+	library: &Library,
+	location_id: i32,
+	file_path_id: i32,
+	materialized_path: &str,
+	is_dir: bool,
+) -> Result<trashed_file::Data, TrashError> {
+	let location_path = location_path(library, location_id).await?;
+	let full_path = location_path.join(materialized_path);
+
+	let size = get_size(&full_path).await?;
+
+	let pub_id = Uuid::new_v4().as_bytes().to_vec();
+	let trashed_path = trashed_path(&location_path, &pub_id);
+	fs::create_dir_all(location_path.join(TRASH_DIR_NAME)).await?;
+	// The trash is in the location, so this is a rename on the same filesystem
+	fs::rename(&full_path, &trashed_path).await?;
+
+	let name = full_path
+		.file_name()
+		.and_then(|name| name.to_str())
+		.unwrap_or_default()
+		.to_string();
+
+	// The file is moved back if it can't be recorded, so it isn't left in the trash without being listed
+	let trashed = match library
+		.db
+		.trashed_file()
+		.create(
+			pub_id,
+			location_id,
+			materialized_path.to_string(),
+			name,
+			is_dir,
+			size.to_string(),
+			vec![],
+		)
+		.exec()
+		.await
+	{
+		Ok(trashed) => trashed,
+		Err(e) => {
+			fs::rename(&trashed_path, &full_path).await?;
+			return Err(e.into());
+		}
+	};
+
+	// The subtree of a file is only the file itself, so this works for both
+	if let Err(e) = delete_directory(library, location_id, Some(file_path_id)).await {
+		// Undoing the move, so the file isn't listed in the trash while still being indexed where it was
+		fs::rename(&trashed_path, &full_path).await?;
+		library
+			.db
+			.trashed_file()
+			.delete(trashed_file::id::equals(trashed.id))
+			.exec()
+			.await?;
+		return Err(e.into());
+	}
+
+	invalidate_query!(library, "files.listTrash");
+
+	Ok(trashed)
+}
+
+/// restore_from_trash moves a trashed file back to where it was in its location, then rescans its directory.
+pub async fn restore_from_trash(library: &Library, id: i32) -> Result<(), TrashError> {
+	let trashed = library
+		.db
+		.trashed_file()
+		.find_unique(trashed_file::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(TrashError::NotFound(id))?;
+
+	let location = find_location(library, trashed.location_id)
+		.include(location_with_indexer_rules::include())
+		.exec()
+		.await?
+		.ok_or(TrashError::LocationNotFound(trashed.location_id))?;
+
+	let target = Path::new(&location.path).join(&trashed.materialized_path);
+	if fs::metadata(&target).await.is_ok() {
+		return Err(TrashError::RestoreConflict(target));
+	}
+
+	// The directory the file was restored to is scanned shallowly, which needs it to be indexed
+	let sub_path = Path::new(&trashed.materialized_path)
+		.parent()
+		.map(Path::to_path_buf)
+		.unwrap_or_default();
+	if sub_path != Path::new("")
+		&& get_existing_file_path_id(
+			MaterializedPath::new(
+				location.id,
+				&location.path,
+				Path::new(&location.path).join(&sub_path),
+				true,
+			)
+			.map_err(LocationError::from)?,
+			&library.db,
+		)
+		.await
+		.map_err(LocationError::from)?
+		.is_none()
+	{
+		return Err(TrashError::ParentNotIndexed(sub_path));
+	}
+
+	let trashed_path = trashed_path(&location.path, &trashed.pub_id);
+	fs::rename(&trashed_path, &target).await?;
+
+	// The file is put back in the trash if it can't be taken out of it in the database
+	if let Err(e) = library
+		.db
+		.trashed_file()
+		.delete(trashed_file::id::equals(id))
+		.exec()
+		.await
+	{
+		fs::rename(&target, &trashed_path).await?;
+		return Err(e.into());
+	}
+
+	library
+		.record_activity(
+			ActivityKind::FilesRestored,
+			format!("Restored '{}' from the trash", target.display()),
+			Some(serde_json::json!({
+				"location_id": trashed.location_id,
+				"path": target,
+			})),
+		)
+		.await;
+
+	invalidate_query!(library, "files.listTrash");
+
+	// Indexing the directory the file was restored to, so it shows up again without waiting for the watcher
+	light_scan_location(library, location, sub_path).await?;
+
+	Ok(())
+}
+
+/// remove_trashed removes a trashed file from the disk and the database.
+async fn remove_trashed(
+	library: &Library,
+	trashed: &trashed_file::Data,
+	location_path: impl AsRef<Path>,
+) -> Result<(), TrashError> {
+	let path = trashed_path(location_path, &trashed.pub_id);
+
+	let result = if trashed.is_dir {
+		fs::remove_dir_all(&path).await
+	} else {
+		fs::remove_file(&path).await
+	};
+
+	match result {
+		Ok(()) => {}
+		// It was removed by hand, so we only have to forget about it
+		Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+		Err(e) => return Err(e.into()),
+	}
+
+	library
+		.db
+		.trashed_file()
+		.delete(trashed_file::id::equals(trashed.id))
+		.exec()
+		.await?;
+
+	Ok(())
+}
+
+/// delete_from_trash permanently deletes a trashed file.
+pub async fn delete_from_trash(library: &Library, id: i32) -> Result<(), TrashError> {
+	let trashed = library
+		.db
+		.trashed_file()
+		.find_unique(trashed_file::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(TrashError::NotFound(id))?;
+
+	let location_path = location_path(library, trashed.location_id).await?;
+	remove_trashed(library, &trashed, location_path).await?;
+
+	invalidate_query!(library, "files.listTrash");
+
+	Ok(())
+}
+
+/// purge_trash permanently deletes the trashed files matching `params`, returning how many were deleted.
+/// Files which fail to be deleted are logged and kept in the trash.
+async fn purge_trash(
+	library: &Library,
+	params: Vec<trashed_file::WhereParam>,
+) -> Result<usize, TrashError> {
+	let trashed_files = library
+		.db
+		.trashed_file()
+		.find_many(params)
+		.order_by(trashed_file::date_trashed::order(Direction::Asc))
+		.exec()
+		.await?;
+
+	let mut purged = 0;
+	for trashed in trashed_files {
+		let result = match location_path(library, trashed.location_id).await {
+			Ok(location_path) => remove_trashed(library, &trashed, location_path).await,
+			// The location was removed, so its trash can't be reached anymore
+			Err(TrashError::LocationNotFound(_)) => library
+				.db
+				.trashed_file()
+				.delete(trashed_file::id::equals(trashed.id))
+				.exec()
+				.await
+				.map(|_| ())
+				.map_err(Into::into),
+			Err(e) => Err(e),
+		};
+
+		match result {
+			Ok(()) => purged += 1,
+			Err(e) => error!(
+				"Failed to purge trashed file <id={}> of library {}: {e:#?}",
+				trashed.id, library.id
+			),
+		}
+	}
+
+	if purged > 0 {
+		invalidate_query!(library, "files.listTrash");
+	}
+
+	Ok(purged)
+}
+
+/// empty_trash permanently deletes every trashed file of the library.
+pub async fn empty_trash(library: &Library) -> Result<usize, TrashError> {
+	purge_trash(library, vec![]).await
+}
+
+/// trash_bytes is the disk space used by the trashed files of the library.
+pub async fn trash_bytes(library: &Library) -> Result<u64, QueryError> {
+	Ok(library
+		.db
+		.trashed_file()
+		.find_many(vec![])
+		.select(trashed_file::select!({ size_in_bytes }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|trashed| trashed.size_in_bytes.parse::<u64>().unwrap_or(0))
+		.sum())
+}
+
+/// spawn_trash_purger periodically deletes the trashed files of every library which are past its trash retention.
+pub fn spawn_trash_purger(library_manager: Arc<LibraryManager>) {
+	tokio::spawn(async move {
+		let mut interval = interval(TRASH_PURGE_TICK);
+
+		loop {
+			interval.tick().await;
+
+			for library in library_manager.get_all_libraries().await {
+				let Some(retention_days) = library.config.trash_retention_days else {
+					continue;
+				};
+
+				let expired_before = Utc::now() - chrono::Duration::days(retention_days as i64);
+				match purge_trash(
+					&library,
+					vec![trashed_file::date_trashed::lt(expired_before.into())],
+				)
+				.await
+				{
+					Ok(0) => {}
+					Ok(purged) => info!(
+						"Purged {purged} trashed files older than {retention_days} days from library {}",
+						library.id
+					),
+					Err(e) => error!(
+						"Failed to purge the trash of library {}: {e:#?}",
+						library.id
+					),
+				}
+			}
+		}
+	});
+}
@@ -0,0 +1,369 @@
+use crate::{
+	invalidate_query,
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::Library,
+	location::{
+		file_path_helper::{
+			file_path_in_subtree, file_path_just_id_materialized_path, get_existing_file_path_id,
+			MaterializedPath,
+		},
+		LocationError,
+	},
+	prisma::{file_path, location},
+	sync,
+	util::db::{refresh_count_estimates, CountedTable},
+};
+
+use std::{
+	collections::HashMap,
+	hash::{Hash, Hasher},
+	mem,
+	path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::task::spawn_blocking;
+use tracing::{info, warn};
+
+use super::{list_archive, ArchiveEntry, ArchiveError, ArchiveKind};
+
+pub const ARCHIVE_INDEXER_JOB_NAME: &str = "archive_indexer";
+
+/// How many entries of an archive are written to the database at once
+const ENTRIES_PER_WRITE: usize = 1000;
+
+/// ArchiveIndexerJob lists the contents of the zip, tar and 7z archives of a location into `file_path` rows
+/// flagged with `in_archive`, under the `file_path` of their archive. It lets the archives be browsed and their
+/// contents be searched without extracting them. Archives indexed before have their contents replaced.
+pub struct ArchiveIndexerJob {}
+
+#[derive(Serialize, Deserialize)]
+pub struct ArchiveIndexerJobInit {
+	pub location: location::Data,
+	pub sub_path: Option<PathBuf>,
+}
+
+impl Hash for ArchiveIndexerJobInit {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.location.id.hash(state);
+		if let Some(ref sub_path) = self.sub_path {
+			sub_path.hash(state);
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ArchiveIndexerJobState {
+	indexed_archives: usize,
+	indexed_entries: usize,
+	/// Archives which couldn't be read, like corrupted ones
+	failed_archives: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArchiveIndexerJobStep {
+	file_path_id: i32,
+	materialized_path: String,
+	kind: ArchiveKind,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ArchiveIndexerJob {
+	type Init = ArchiveIndexerJobInit;
+	type Data = ArchiveIndexerJobState;
+	type Step = ArchiveIndexerJobStep;
+
+	fn name(&self) -> &'static str {
+		ARCHIVE_INDEXER_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let Library { db, .. } = &ctx.library;
+		let location = &state.init.location;
+
+		let mut params = vec![
+			file_path::location_id::equals(location.id),
+			file_path::is_dir::equals(false),
+			file_path::is_symlink::equals(false),
+			file_path::in_archive::equals(false),
+		];
+		if let Some(ref sub_path) = state.init.sub_path {
+			let full_path = Path::new(&location.path).join(sub_path);
+			let sub_path_id = get_existing_file_path_id(
+				MaterializedPath::new(location.id, &location.path, &full_path, true)
+					.map_err(ArchiveError::from)?,
+				db,
+			)
+			.await
+			.map_err(ArchiveError::from)?
+			.ok_or_else(|| {
+				LocationError::DirectoryNotFound(sub_path.to_string_lossy().to_string())
+			})?;
+
+			params.push(file_path_in_subtree(location.id, sub_path_id));
+		}
+
+		state.steps = db
+			.file_path()
+			.find_many(params)
+			.select(file_path_just_id_materialized_path::select())
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|file_path| {
+				ArchiveKind::from_path(&file_path.materialized_path).map(|kind| {
+					ArchiveIndexerJobStep {
+						file_path_id: file_path.id,
+						materialized_path: file_path.materialized_path,
+						kind,
+					}
+				})
+			})
+			.collect();
+
+		state.data = Some(ArchiveIndexerJobState::default());
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+		let data = state.data.as_mut().expect("fatal: missing job state");
+		let location = &state.init.location;
+		let archive_path = Path::new(&location.path).join(&step.materialized_path);
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Indexing archive {}",
+			step.materialized_path
+		))]);
+
+		let kind = step.kind;
+		let entries = match spawn_blocking({
+			let archive_path = archive_path.clone();
+			move || list_archive(archive_path, kind)
+		})
+		.await?
+		{
+			Ok(entries) => entries,
+			Err(e) => {
+				warn!("Failed to list archive {}: {e:#?}", archive_path.display());
+				data.failed_archives += 1;
+				return Ok(());
+			}
+		};
+
+		data.indexed_entries +=
+			index_archive_entries(&ctx.library, location, step, &archive_path, entries).await?;
+		data.indexed_archives += 1;
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!(
+			"Finished indexing archives: {} archives with {} entries indexed, {} failed",
+			data.indexed_archives, data.indexed_entries, data.failed_archives
+		);
+
+		if data.indexed_entries > 0 {
+			refresh_count_estimates(ctx.library.db.clone(), &[CountedTable::FilePath]);
+			invalidate_query!(ctx.library, "locations.getExplorerData");
+		}
+
+		Ok(Some(serde_json::to_value(data)?))
+	}
+}
+
+/// index_archive_entries replaces the contents of an archive in the database with its `entries`,
+/// returning how many were written.
+async fn index_archive_entries(
+	library: &Library,
+	location: &location::Data,
+	step: &ArchiveIndexerJobStep,
+	archive_path: &Path,
+	entries: Vec<ArchiveEntry>,
+) -> Result<usize, JobError> {
+	let Library {
+		db,
+		sync,
+		last_file_path_id_manager,
+		..
+	} = library;
+
+	// The archive could have changed since it was indexed, so its old contents are dropped along with the
+	// first write of the new ones
+	let old_entries_params = vec![
+		file_path::location_id::equals(location.id),
+		file_path_in_subtree(location.id, step.file_path_id),
+		file_path::in_archive::equals(true),
+	];
+	let mut old_entries_deletes = db
+		.file_path()
+		.find_many(old_entries_params.clone())
+		.select(file_path_just_id_materialized_path::select())
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| {
+			sync.shared_delete(sync::file_path::SyncId {
+				id: file_path.id,
+				location: sync::location::SyncId {
+					pub_id: location.pub_id.clone(),
+				},
+			})
+		})
+		.collect::<Vec<_>>();
+
+	let first_file_id = last_file_path_id_manager
+		.get_max_file_path_id(location.id, db)
+		.await
+		.map_err(ArchiveError::from)?
+		+ 1;
+	let last_file_id = first_file_id + entries.len() as i32;
+	last_file_path_id_manager
+		.set_max_file_path_id(location.id, last_file_id - 1)
+		.await;
+
+	// The entries are sorted by their paths, so their directories always have an id before them
+	let mut dirs_ids = HashMap::from([(PathBuf::new(), step.file_path_id)]);
+	let mut rows = Vec::with_capacity(entries.len());
+	for (entry, file_id) in entries.into_iter().zip(first_file_id..last_file_id) {
+		let materialized_path = MaterializedPath::new(
+			location.id,
+			&location.path,
+			archive_path.join(&entry.path),
+			entry.is_dir,
+		)
+		.map_err(ArchiveError::from)?;
+
+		let parent_id = entry
+			.path
+			.parent()
+			.and_then(|parent| dirs_ids.get(parent).copied());
+		if entry.is_dir {
+			dirs_ids.insert(entry.path.clone(), file_id);
+		}
+
+		let hidden = entry
+			.path
+			.file_name()
+			.map_or(false, |name| name.to_string_lossy().starts_with('.'));
+
+		rows.push((
+			file_id,
+			materialized_path,
+			parent_id,
+			entry.modified_at.unwrap_or_else(Utc::now),
+			hidden,
+		));
+	}
+
+	let mut count = 0;
+	for chunk in rows.into_iter().chunks(ENTRIES_PER_WRITE).into_iter() {
+		let (sync_stuff, paths): (Vec<_>, Vec<_>) = chunk
+			.map(
+				|(file_id, materialized_path, parent_id, created_at, hidden)| {
+					let MaterializedPath {
+						materialized_path,
+						is_dir,
+						name,
+						extension,
+						..
+					} = materialized_path;
+
+					use file_path::*;
+
+					(
+						sync.unique_shared_create(
+							sync::file_path::SyncId {
+								id: file_id,
+								location: sync::location::SyncId {
+									pub_id: location.pub_id.clone(),
+								},
+							},
+							[
+								("materialized_path", json!(materialized_path.clone())),
+								("name", json!(name.clone())),
+								("is_dir", json!(is_dir)),
+								("extension", json!(extension.clone())),
+								("parent_id", json!(parent_id)),
+								("date_created", json!(created_at)),
+								("hidden", json!(hidden)),
+								("in_archive", json!(true)),
+							],
+						),
+						file_path::create_unchecked(
+							file_id,
+							location.id,
+							materialized_path,
+							name,
+							extension,
+							vec![
+								is_dir::set(is_dir),
+								parent_id::set(parent_id),
+								date_created::set(created_at.into()),
+								date_modified::set(created_at.into()),
+								hidden::set(hidden),
+								in_archive::set(true),
+							],
+						),
+					)
+				},
+			)
+			.unzip();
+
+		let create_many = db.file_path().create_many(paths).skip_duplicates();
+
+		count += if old_entries_deletes.is_empty() {
+			sync.write_ops(db, (sync_stuff, create_many)).await?
+		} else {
+			let mut ops = mem::take(&mut old_entries_deletes);
+			ops.extend(sync_stuff);
+
+			sync.write_ops(
+				db,
+				(
+					ops,
+					(
+						db.file_path().delete_many(old_entries_params.clone()),
+						create_many,
+					),
+				),
+			)
+			.await?
+			.1
+		} as usize;
+	}
+
+	// An archive that became empty still needs its old contents dropped
+	if !old_entries_deletes.is_empty() {
+		sync.write_ops(
+			db,
+			(
+				old_entries_deletes,
+				db.file_path().delete_many(old_entries_params),
+			),
+		)
+		.await?;
+	}
+
+	Ok(count)
+}
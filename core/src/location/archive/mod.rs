@@ -0,0 +1,301 @@
+use crate::location::file_path_helper::FilePathError;
+
+use std::{
+	collections::BTreeMap,
+	ffi::OsStr,
+	fs::File,
+	io::{self, BufReader, Read},
+	path::{Component, Path, PathBuf},
+};
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub mod archive_job;
+
+/// The kinds of archives whose contents can be indexed without extracting them
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+	Zip,
+	Tar,
+	TarGz,
+	SevenZip,
+}
+
+impl ArchiveKind {
+	/// from_path tells the kind of archive from the file name of `path`, as the double extension of
+	/// compressed tarballs isn't kept in the `extension` column.
+	pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+		let name = path.as_ref().file_name()?.to_str()?.to_lowercase();
+
+		if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+			return Some(Self::TarGz);
+		}
+
+		match Path::new(&name).extension().and_then(OsStr::to_str)? {
+			"zip" => Some(Self::Zip),
+			"tar" => Some(Self::Tar),
+			"7z" => Some(Self::SevenZip),
+			_ => None,
+		}
+	}
+}
+
+/// An entry of an archive, with its path relative to the root of the archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveEntry {
+	pub path: PathBuf,
+	pub is_dir: bool,
+	pub modified_at: Option<DateTime<Utc>>,
+}
+
+/// Error type for the archive module
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+	#[error("Unsupported archive format (path: {0:?})")]
+	UnsupportedFormat(PathBuf),
+	#[error("File already exists (path: {0:?})")]
+	AlreadyExists(PathBuf),
+	#[error("Zip archive error: {0}")]
+	Zip(#[from] zip::result::ZipError),
+	#[error("7z archive error: {0}")]
+	SevenZip(#[from] sevenz_rust::Error),
+
+	// Internal Errors
+	#[error("Database error: {0}")]
+	DatabaseError(#[from] prisma_client_rust::QueryError),
+	#[error("I/O error: {0}")]
+	IOError(#[from] io::Error),
+	#[error("File path related error (error: {0})")]
+	FilePathError(#[from] FilePathError),
+}
+
+/// ArchiveListing keeps the entries of an archive by their paths, so the directories are sorted before their contents
+#[derive(Default)]
+struct ArchiveListing(BTreeMap<PathBuf, ArchiveEntry>);
+
+impl ArchiveListing {
+	fn add(&mut self, path: &Path, is_dir: bool, modified_at: Option<DateTime<Utc>>) {
+		let Some(path) = enclosed_path(path) else {
+			return;
+		};
+
+		// Archives don't always have entries for the directories of their files
+		for ancestor in path.ancestors().skip(1) {
+			if ancestor.as_os_str().is_empty() {
+				break;
+			}
+			self.0
+				.entry(ancestor.to_path_buf())
+				.or_insert_with(|| ArchiveEntry {
+					path: ancestor.to_path_buf(),
+					is_dir: true,
+					modified_at: None,
+				});
+		}
+
+		self.0.insert(
+			path.clone(),
+			ArchiveEntry {
+				path,
+				is_dir,
+				modified_at,
+			},
+		);
+	}
+
+	fn add_tar(&mut self, mut archive: tar::Archive<impl Read>) -> io::Result<()> {
+		for entry in archive.entries()? {
+			let entry = entry?;
+			let header = entry.header();
+			let entry_type = header.entry_type();
+
+			// Links and special files inside of the archive aren't indexed
+			if !entry_type.is_dir() && !entry_type.is_file() {
+				continue;
+			}
+
+			let modified_at = header
+				.mtime()
+				.ok()
+				.and_then(|mtime| Utc.timestamp_opt(mtime as i64, 0).single());
+
+			self.add(&entry.path()?, entry_type.is_dir(), modified_at);
+		}
+
+		Ok(())
+	}
+}
+
+/// enclosed_path returns the path of an archive entry without its `.` components, or `None` for paths escaping
+/// the archive, like absolute ones or with `..` components.
+fn enclosed_path(path: &Path) -> Option<PathBuf> {
+	let mut enclosed = PathBuf::new();
+	for component in path.components() {
+		match component {
+			Component::Normal(part) => enclosed.push(part),
+			Component::CurDir => {}
+			_ => return None,
+		}
+	}
+
+	(!enclosed.as_os_str().is_empty()).then_some(enclosed)
+}
+
+/// zip_modified_at converts the MS-DOS date of a zip entry, which doesn't have a timezone, taking it as UTC
+fn zip_modified_at(date: zip::DateTime) -> Option<DateTime<Utc>> {
+	NaiveDate::from_ymd_opt(date.year() as i32, date.month() as u32, date.day() as u32)?
+		.and_hms_opt(
+			date.hour() as u32,
+			date.minute() as u32,
+			date.second() as u32,
+		)
+		.map(|naive| Utc.from_utc_datetime(&naive))
+}
+
+/// list_archive reads the entries of the archive at `path` without extracting them, sorted by their paths.
+/// It reads from the disk, so it must be called in a blocking task.
+pub fn list_archive(
+	path: impl AsRef<Path>,
+	kind: ArchiveKind,
+) -> Result<Vec<ArchiveEntry>, ArchiveError> {
+	let path = path.as_ref();
+	let mut listing = ArchiveListing::default();
+
+	match kind {
+		ArchiveKind::Zip => {
+			let mut archive = zip::ZipArchive::new(BufReader::new(File::open(path)?))?;
+			for i in 0..archive.len() {
+				// The raw entries are read, so encrypted ones are listed as well
+				let entry = archive.by_index_raw(i)?;
+				let Some(entry_path) = entry.enclosed_name().map(Path::to_path_buf) else {
+					continue;
+				};
+
+				listing.add(
+					&entry_path,
+					entry.is_dir(),
+					zip_modified_at(entry.last_modified()),
+				);
+			}
+		}
+		ArchiveKind::Tar => {
+			listing.add_tar(tar::Archive::new(BufReader::new(File::open(path)?)))?
+		}
+		ArchiveKind::TarGz => listing.add_tar(tar::Archive::new(GzDecoder::new(
+			BufReader::new(File::open(path)?),
+		)))?,
+		ArchiveKind::SevenZip => {
+			let reader = sevenz_rust::SevenZReader::open(path, sevenz_rust::Password::empty())?;
+			for entry in &reader.archive().files {
+				// 7z archives made on Windows can have backslashes as path separators
+				listing.add(
+					Path::new(&entry.name.replace('\\', "/")),
+					entry.is_directory,
+					None,
+				);
+			}
+		}
+	}
+
+	Ok(listing.0.into_values().collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Write;
+	use tempfile::tempdir;
+
+	fn entry(path: &str, is_dir: bool) -> (PathBuf, bool) {
+		(PathBuf::from(path), is_dir)
+	}
+
+	fn paths(entries: Vec<ArchiveEntry>) -> Vec<(PathBuf, bool)> {
+		entries
+			.into_iter()
+			.map(|entry| (entry.path, entry.is_dir))
+			.collect()
+	}
+
+	#[test]
+	fn test_archive_kind_from_path() {
+		assert_eq!(ArchiveKind::from_path("a/b.zip"), Some(ArchiveKind::Zip));
+		assert_eq!(ArchiveKind::from_path("b.TAR"), Some(ArchiveKind::Tar));
+		assert_eq!(ArchiveKind::from_path("b.tar.gz"), Some(ArchiveKind::TarGz));
+		assert_eq!(ArchiveKind::from_path("b.tgz"), Some(ArchiveKind::TarGz));
+		assert_eq!(ArchiveKind::from_path("b.7z"), Some(ArchiveKind::SevenZip));
+		assert_eq!(ArchiveKind::from_path("b.gz"), None);
+		assert_eq!(ArchiveKind::from_path("zip"), None);
+	}
+
+	#[test]
+	fn test_list_zip() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("test.zip");
+
+		let mut writer = zip::ZipWriter::new(File::create(&path).unwrap());
+		let options =
+			zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+		writer.start_file("photos/2023/photo.jpg", options).unwrap();
+		writer.write_all(b"photo").unwrap();
+		writer.add_directory("empty/", options).unwrap();
+		writer.start_file("readme.txt", options).unwrap();
+		writer.write_all(b"readme").unwrap();
+		writer.start_file("../escaped.txt", options).unwrap();
+		writer.write_all(b"escaped").unwrap();
+		writer.finish().unwrap();
+
+		assert_eq!(
+			paths(list_archive(&path, ArchiveKind::Zip).unwrap()),
+			vec![
+				entry("empty", true),
+				entry("photos", true),
+				entry("photos/2023", true),
+				entry("photos/2023/photo.jpg", false),
+				entry("readme.txt", false),
+			]
+		);
+	}
+
+	#[test]
+	fn test_list_tar() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("test.tar");
+
+		let mut builder = tar::Builder::new(File::create(&path).unwrap());
+		for (name, contents) in [("docs/a.txt", "a"), ("docs/b/c.txt", "c"), ("d.txt", "d")] {
+			let mut header = tar::Header::new_gnu();
+			header.set_size(contents.len() as u64);
+			header.set_mtime(1_678_000_000);
+			header.set_mode(0o644);
+			header.set_cksum();
+			builder
+				.append_data(&mut header, name, contents.as_bytes())
+				.unwrap();
+		}
+		builder.finish().unwrap();
+
+		let entries = list_archive(&path, ArchiveKind::Tar).unwrap();
+		assert_eq!(
+			entries
+				.iter()
+				.find(|entry| entry.path == Path::new("d.txt"))
+				.and_then(|entry| entry.modified_at)
+				.map(|modified_at| modified_at.timestamp()),
+			Some(1_678_000_000)
+		);
+		assert_eq!(
+			paths(entries),
+			vec![
+				entry("d.txt", false),
+				entry("docs", true),
+				entry("docs/a.txt", false),
+				entry("docs/b", true),
+				entry("docs/b/c.txt", false),
+			]
+		);
+	}
+}
@@ -19,6 +19,8 @@ pub enum LocationError {
 	UuidNotFound(Uuid),
 	#[error("Location not found (id: {0})")]
 	IdNotFound(i32),
+	#[error("Volume not found (uuid: {0})")]
+	VolumeNotFound(String),
 
 	// User errors
 	#[error("Location not a directory (path: {0:?})")]
@@ -68,7 +70,8 @@ impl From<LocationError> for rspc::Error {
 			// Not found errors
 			LocationError::PathNotFound(_)
 			| LocationError::UuidNotFound(_)
-			| LocationError::IdNotFound(_) => {
+			| LocationError::IdNotFound(_)
+			| LocationError::VolumeNotFound(_) => {
 				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
 			}
 
@@ -1,6 +1,9 @@
-use crate::prisma::{
-	file_path::{self, FindMany},
-	location, PrismaClient,
+use crate::{
+	prisma::{
+		file_path::{self, FindMany},
+		file_path_closure, location, PrismaClient,
+	},
+	sync::{self, SyncManager},
 };
 
 use std::{
@@ -10,8 +13,10 @@ use std::{
 
 use dashmap::{mapref::entry::Entry, DashMap};
 use futures::future::try_join_all;
-use prisma_client_rust::{Direction, QueryError};
+use prisma_client_rust::{raw, Direction, PrismaValue, QueryError};
+use sd_sync::CRDTOperation;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use thiserror::Error;
 use tokio::{fs, io};
 use tracing::error;
@@ -27,6 +32,16 @@ file_path::select!(file_path_for_file_identifier {
 	id
 	materialized_path
 	date_created
+	inode
+	device
+});
+file_path::select!(file_path_for_hard_links {
+	materialized_path
+	inode
+	device
+	cas_id
+	cas_id_algorithm
+	object: select { pub_id }
 });
 file_path::select!(file_path_just_object_id { object_id });
 file_path::select!(file_path_for_object_validator {
@@ -38,10 +53,53 @@ file_path::select!(file_path_for_object_validator {
 		pub_id
 	}
 });
+file_path::select!(file_path_for_object_verifier {
+	id
+	location_id
+	materialized_path
+	cas_id
+	cas_id_algorithm
+	date_modified
+	object: select {
+		id
+		pub_id
+		integrity_checksum
+		size_in_bytes
+	}
+});
+file_path::select!(file_path_for_media_data {
+	materialized_path
+	object: select {
+		id
+		kind
+		media_data: select { id }
+	}
+});
+file_path::select!(file_path_for_content_indexer {
+	materialized_path
+	name
+	extension
+	location: select { path }
+	object: select { id kind }
+});
+file_path::select!(file_path_for_extension {
+	id
+	materialized_path
+	name
+	extension
+});
 file_path::select!(file_path_just_materialized_path_cas_id {
 	materialized_path
 	cas_id
 });
+file_path::select!(file_path_for_move_detection {
+	id
+	materialized_path
+	is_dir
+	inode
+	device
+	date_created
+});
 
 // File Path includes!
 file_path::include!(file_path_with_object { object });
@@ -108,6 +166,14 @@ impl MaterializedPath {
 		self.location_id
 	}
 
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn extension(&self) -> &str {
+		&self.extension
+	}
+
 	fn prepare_name(path: &Path) -> String {
 		// Not using `impl AsRef<Path>` here because it's an private method
 		path.file_name()
@@ -122,17 +188,8 @@ impl MaterializedPath {
 			.parent()
 			.unwrap_or_else(|| Path::new("/"));
 
-		let mut parent_path_str = parent_path
-			.to_str()
-			.unwrap() // SAFETY: This unwrap is ok because this path was a valid UTF-8 String before
-			.to_string();
-
-		if !parent_path_str.ends_with('/') {
-			parent_path_str += "/";
-		}
-
 		Self {
-			materialized_path: parent_path_str,
+			materialized_path: parent_materialized_path(&self.materialized_path),
 			is_dir: true,
 			location_id: self.location_id,
 			// NOTE: This way we don't use the same name for "/" `file_path`, that uses the location
@@ -143,6 +200,22 @@ impl MaterializedPath {
 	}
 }
 
+/// parent_materialized_path returns the materialized path of the directory holding `materialized_path`, ending with a `/`.
+pub fn parent_materialized_path(materialized_path: &str) -> String {
+	let mut parent_path_str = Path::new(materialized_path)
+		.parent()
+		.unwrap_or_else(|| Path::new("/"))
+		.to_str()
+		.unwrap() // SAFETY: This unwrap is ok because this path was a valid UTF-8 String before
+		.to_string();
+
+	if !parent_path_str.ends_with('/') {
+		parent_path_str += "/";
+	}
+
+	parent_path_str
+}
+
 impl From<MaterializedPath> for String {
 	fn from(path: MaterializedPath) -> Self {
 		path.materialized_path
@@ -234,6 +307,41 @@ impl LastFilePathIdManager {
 		self.last_id_by_location.insert(location_id, id);
 	}
 
+	/// reserve_file_path_ids makes sure the max file_path id of a location is at least `id`,
+	/// so ids assigned before a restart aren't handed out again while they're being inserted.
+	pub async fn reserve_file_path_ids(
+		&self,
+		location_id: LocationId,
+		id: i32,
+		db: &PrismaClient,
+	) -> Result<(), FilePathError> {
+		let max_id = self.get_max_file_path_id(location_id, db).await?;
+		if id > max_id {
+			self.set_max_file_path_id(location_id, id).await;
+		}
+
+		Ok(())
+	}
+
+	/// resync replaces the max file_path id of every location with the one in the database,
+	/// as the cached ones can be behind the table after a crash. Returns how many locations were synced.
+	pub async fn resync(&self, db: &PrismaClient) -> Result<usize, FilePathError> {
+		let locations = db
+			.location()
+			.find_many(vec![])
+			.select(location::select!({ id }))
+			.exec()
+			.await?;
+
+		self.last_id_by_location.clear();
+		for location in &locations {
+			let id = Self::fetch_max_file_path_id(location.id, db).await?;
+			self.last_id_by_location.insert(location.id, id);
+		}
+
+		Ok(locations.len())
+	}
+
 	async fn fetch_max_file_path_id(
 		location_id: LocationId,
 		db: &PrismaClient,
@@ -261,6 +369,7 @@ impl LastFilePathIdManager {
 			extension,
 		}: MaterializedPath,
 		parent_id: Option<i32>,
+		hidden: bool,
 	) -> Result<file_path::Data, FilePathError> {
 		// Keeping a reference in that map for the entire duration of the function, so we keep it locked
 		let mut last_id_ref = match self.last_id_by_location.entry(location_id) {
@@ -284,6 +393,7 @@ impl LastFilePathIdManager {
 				vec![
 					file_path::parent_id::set(parent_id),
 					file_path::is_dir::set(is_dir),
+					file_path::hidden::set(hidden),
 				],
 			)
 			.exec()
@@ -314,6 +424,29 @@ pub fn subtract_location_path(
 	}
 }
 
+/// sub_path_materialized_path returns the materialized path of a directory of a location from the sub path of a job,
+/// or `None` if the job runs on the whole location.
+pub fn sub_path_materialized_path(
+	location_id: LocationId,
+	location_path: impl AsRef<Path>,
+	sub_path: Option<&Path>,
+) -> Option<String> {
+	let location_path = location_path.as_ref();
+
+	sub_path
+		.filter(|sub_path| *sub_path != Path::new(""))
+		.and_then(|sub_path| {
+			MaterializedPath::new(
+				location_id,
+				location_path,
+				location_path.join(sub_path),
+				true,
+			)
+			.ok()
+		})
+		.map(String::from)
+}
+
 pub fn extract_materialized_path(
 	location_id: LocationId,
 	location_path: impl AsRef<Path>,
@@ -351,6 +484,90 @@ pub async fn find_many_file_paths_by_full_path<'db>(
 	]))
 }
 
+/// file_path_in_subtree matches the file paths under a directory, the directory included. It goes through the
+/// `file_path_closure` hierarchy, which is an index lookup unlike a prefix scan over materialized paths.
+pub fn file_path_in_subtree(location_id: LocationId, directory_id: i32) -> file_path::WhereParam {
+	file_path::ancestors::some(vec![
+		file_path_closure::location_id::equals(location_id),
+		file_path_closure::ancestor_id::equals(directory_id),
+	])
+}
+
+/// move_file_path_descendants moves the contents of a directory whose materialized path changed from
+/// `old_materialized_path` to `new_materialized_path`, replacing only the start of their materialized paths.
+pub async fn move_file_path_descendants(
+	location_id: LocationId,
+	directory_id: i32,
+	old_materialized_path: &str,
+	new_materialized_path: &str,
+	db: &PrismaClient,
+) -> Result<(), FilePathError> {
+	db._execute_raw(raw!(
+		"UPDATE file_path SET materialized_path = {} || SUBSTR(materialized_path, {}) \
+		WHERE location_id = {} AND id != {} AND SUBSTR(materialized_path, 1, {}) = {}",
+		PrismaValue::String(new_materialized_path.to_string()),
+		PrismaValue::Int(old_materialized_path.chars().count() as i64 + 1),
+		PrismaValue::Int(location_id as i64),
+		PrismaValue::Int(directory_id as i64),
+		PrismaValue::Int(old_materialized_path.chars().count() as i64),
+		PrismaValue::String(old_materialized_path.to_string())
+	))
+	.exec()
+	.await?;
+
+	Ok(())
+}
+
+/// file_path_descendant_moves returns the synced updates moving the contents of a directory whose materialized
+/// path changed from `old_materialized_path` to `new_materialized_path`, so they're written in the same batch
+/// as the update of the directory itself.
+pub async fn file_path_descendant_moves<'db>(
+	location: &location::Data,
+	directory_id: i32,
+	old_materialized_path: &str,
+	new_materialized_path: &str,
+	sync: &SyncManager,
+	db: &'db PrismaClient,
+) -> Result<(Vec<CRDTOperation>, Vec<file_path::Update<'db>>), FilePathError> {
+	let descendants = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(location.id),
+			file_path_in_subtree(location.id, directory_id),
+			file_path::id::not(directory_id),
+		])
+		.select(file_path_just_id_materialized_path::select())
+		.exec()
+		.await?;
+
+	Ok(descendants
+		.into_iter()
+		.map(|file_path| {
+			let materialized_path = format!(
+				"{new_materialized_path}{}",
+				&file_path.materialized_path[old_materialized_path.len()..]
+			);
+
+			(
+				sync.shared_update(
+					sync::file_path::SyncId {
+						id: file_path.id,
+						location: sync::location::SyncId {
+							pub_id: location.pub_id.clone(),
+						},
+					},
+					"materialized_path",
+					json!(&materialized_path),
+				),
+				db.file_path().update(
+					file_path::location_id_id(location.id, file_path.id),
+					vec![file_path::materialized_path::set(materialized_path)],
+				),
+			)
+		})
+		.unzip())
+}
+
 pub async fn get_existing_file_path_id(
 	materialized_path: MaterializedPath,
 	db: &PrismaClient,
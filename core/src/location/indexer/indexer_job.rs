@@ -1,36 +1,34 @@
 use crate::{
-	job::{JobError, JobResult, JobState, StatefulJob, WorkerContext},
-	library::Library,
-	location::file_path_helper::{
-		ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
-		file_path_just_id_materialized_path, find_many_file_paths_by_full_path,
-		get_existing_file_path_id, MaterializedPath,
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	location::{
+		file_path_helper::{
+			ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
+			get_existing_file_path_id, MaterializedPath,
+		},
+		SymlinkPolicy,
 	},
-	prisma::location,
+	volume::{volume_kind_of, VolumeKind},
 };
 
 use std::{collections::HashMap, path::Path};
 
 use chrono::Utc;
-use itertools::Itertools;
-use tokio::time::Instant;
-use tracing::error;
+use int_enum::IntEnum;
+use tokio::{sync::mpsc, time::Instant, try_join};
+use tracing::warn;
 
 use super::{
-	execute_indexer_step, finalize_indexer,
+	execute_indexer_step, finalize_indexer, prepare_indexer_step,
 	rules::{IndexerRule, RuleKind},
-	walk::walk,
-	IndexerError, IndexerJobData, IndexerJobInit, IndexerJobStep, IndexerJobStepEntry,
-	ScanProgress,
+	walk::{walk, WalkLimits},
+	IndexerError, IndexerJobData, IndexerJobInit, IndexerJobStep, ScanProgress,
 };
 
-/// BATCH_SIZE is the number of files to index at each step, writing the chunk of files metadata in the database.
-const BATCH_SIZE: usize = 1000;
 pub const INDEXER_JOB_NAME: &str = "indexer";
 
 /// A `IndexerJob` is a stateful job that walks a directory and indexes all files.
-/// First it walks the directory and generates a list of files to index, chunked into
-/// batches of [`BATCH_SIZE`]. Then for each chunk it write the file metadata to the database.
+/// First it walks the directory, turning the found files into steps as they're found in
+/// batches of [`BATCH_SIZE`](super::BATCH_SIZE). Then for each step it write the file metadata to the database.
 pub struct IndexerJob;
 
 #[async_trait::async_trait]
@@ -43,24 +41,18 @@ impl StatefulJob for IndexerJob {
 		INDEXER_JOB_NAME
 	}
 
-	/// Creates a vector of valid path buffers from a directory, chunked into batches of `BATCH_SIZE`.
+	fn journals(&self) -> bool {
+		// The file paths of a chunk interrupted midway are deleted, and the chunk is written again in full on resume
+		true
+	}
+
+	/// Walks the directory, preparing a step for each batch of `BATCH_SIZE` found paths which aren't indexed yet.
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
-		let Library {
-			last_file_path_id_manager,
-			db,
-			..
-		} = &ctx.library;
+		let db = &ctx.library.db;
 
 		let location_id = state.init.location.id;
 		let location_path = Path::new(&state.init.location.path);
 
-		// grab the next id so we can increment in memory for batch inserting
-		let first_file_id = last_file_path_id_manager
-			.get_max_file_path_id(location_id, db)
-			.await
-			.map_err(IndexerError::from)?
-			+ 1;
-
 		let mut indexer_rules_by_kind: HashMap<RuleKind, Vec<IndexerRule>> =
 			HashMap::with_capacity(state.init.location.indexer_rules.len());
 		for location_rule in &state.init.location.indexer_rules {
@@ -99,106 +91,72 @@ impl StatefulJob for IndexerJob {
 			location_path.to_path_buf()
 		};
 
+		if volume_kind_of(location_path) == VolumeKind::Network {
+			warn!(
+				"Location {} is on a network volume, indexing may be slow",
+				location_path.display()
+			);
+			IndexerJobData::on_scan_progress(
+				&ctx,
+				vec![ScanProgress::Message(format!(
+					"{} is on a network volume, indexing may be slow",
+					location_path.display()
+				))],
+			);
+		}
+
 		let scan_start = Instant::now();
 
-		let found_paths = walk(
+		// The walked entries are indexed in batches while the walk goes on, instead of collecting all of them first
+		let (batches_tx, mut batches_rx) = mpsc::channel(1);
+		let mut total_paths = 0;
+
+		let walking = walk(
+			location_path,
 			to_walk_path,
 			&indexer_rules_by_kind,
-			|path, total_entries| {
+			|path, _| {
 				IndexerJobData::on_scan_progress(
 					&ctx,
-					vec![
-						ScanProgress::Message(format!("Scanning {}", path.display())),
-						ScanProgress::ChunkCount(total_entries / BATCH_SIZE),
-					],
+					vec![ScanProgress::Message(format!(
+						"Scanning {}",
+						path.display()
+					))],
 				);
 			},
 			// if we're not using a sub_path, then its a full indexing and we must include root dir
 			state.init.sub_path.is_none(),
-		)
-		.await?;
-
-		dirs_ids.extend(
-			find_many_file_paths_by_full_path(
-				&location::Data::from(&state.init.location),
-				&found_paths
-					.iter()
-					.map(|entry| &entry.path)
-					.collect::<Vec<_>>(),
-				db,
-			)
-			.await
-			.map_err(IndexerError::from)?
-			.select(file_path_just_id_materialized_path::select())
-			.exec()
-			.await?
-			.into_iter()
-			.map(|file_path| {
-				(
-					location_path.join(file_path.materialized_path),
-					file_path.id,
-				)
-			}),
+			WalkLimits {
+				max_depth: state.init.max_depth,
+				max_entries: state.init.max_entries,
+			},
+			SymlinkPolicy::from_int(state.init.location.symlink_policy)
+				.map_err(IndexerError::from)?,
+			batches_tx,
 		);
 
-		let mut new_paths = found_paths
-			.into_iter()
-			.filter_map(|entry| {
-				MaterializedPath::new(
-					location_id,
-					&state.init.location.path,
-					&entry.path,
-					entry.is_dir,
-				)
-				.map_or_else(
-					|e| {
-						error!("Failed to create materialized path: {e}");
-						None
-					},
-					|materialized_path| {
-						(!dirs_ids.contains_key(&entry.path)).then(|| {
-							IndexerJobStepEntry {
-								materialized_path,
-								created_at: entry.created_at,
-								file_id: 0, // To be set later
-								parent_id: entry.path.parent().and_then(|parent_dir| {
-									/***************************************************************
-									 * If we're dealing with a new path which its parent already   *
-									 * exist, we fetch its parent id from our `dirs_ids` map       *
-									 **************************************************************/
-									dirs_ids.get(parent_dir).copied()
-								}),
-								full_path: entry.path,
-							}
-						})
-					},
-				)
-			})
-			.collect::<Vec<_>>();
-
-		let total_paths = new_paths.len();
-		let last_file_id = first_file_id + total_paths as i32;
-
-		// Setting our global state for `file_path` ids
-		last_file_path_id_manager
-			.set_max_file_path_id(location_id, last_file_id)
-			.await;
-
-		new_paths
-			.iter_mut()
-			.zip(first_file_id..last_file_id)
-			.for_each(|(entry, file_id)| {
-				// If the `parent_id` is still none here, is because the parent of this entry is also
-				// a new one in the DB
-				if entry.parent_id.is_none() {
-					entry.parent_id = entry
-						.full_path
-						.parent()
-						.and_then(|parent_dir| dirs_ids.get(parent_dir).copied());
+		let preparing_steps = async {
+			while let Some(batch) = batches_rx.recv().await {
+				let step =
+					prepare_indexer_step(&state.init.location, batch, &mut dirs_ids, &ctx.library)
+						.await?;
+				if step.is_empty() {
+					continue;
 				}
-				entry.file_id = file_id;
-				dirs_ids.insert(entry.full_path.clone(), file_id);
-			});
+
+				total_paths += step.len();
+				state.steps.push_back(step);
+
+				IndexerJobData::on_scan_progress(
+					&ctx,
+					vec![ScanProgress::ChunkCount(state.steps.len())],
+				);
+			}
+
+			Ok::<_, IndexerError>(())
+		};
+
+		try_join!(walking, preparing_steps)?;
 
 		state.data = Some(IndexerJobData {
 			db_write_start: Utc::now(),
@@ -207,28 +165,6 @@ impl StatefulJob for IndexerJob {
 			indexed_paths: 0,
 		});
 
-		state.steps = new_paths
-			.into_iter()
-			.chunks(BATCH_SIZE)
-			.into_iter()
-			.enumerate()
-			.map(|(i, chunk)| {
-				let chunk_steps = chunk.collect::<Vec<_>>();
-				IndexerJobData::on_scan_progress(
-					&ctx,
-					vec![
-						ScanProgress::SavedChunks(i),
-						ScanProgress::Message(format!(
-							"Writing {} of {} to db",
-							i * chunk_steps.len(),
-							total_paths,
-						)),
-					],
-				);
-				chunk_steps
-			})
-			.collect();
-
 		Ok(())
 	}
 
@@ -238,19 +174,39 @@ impl StatefulJob for IndexerJob {
 		ctx: WorkerContext,
 		state: &mut JobState<Self>,
 	) -> Result<(), JobError> {
-		execute_indexer_step(&state.init.location, &state.steps[0], ctx)
-			.await
-			.map(|indexed_paths| {
-				state
-					.data
-					.as_mut()
-					.expect("critical error: missing data on job state")
-					.indexed_paths = indexed_paths;
-			})
+		let indexed_paths =
+			execute_indexer_step(&state.init.location, &state.steps[0], ctx.clone()).await?;
+
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+		data.indexed_paths += indexed_paths;
+		ctx.progress(vec![JobReportUpdate::ItemsProcessed(
+			indexed_paths as usize,
+		)]);
+
+		IndexerJobData::on_scan_progress(
+			&ctx,
+			vec![
+				ScanProgress::SavedChunks(state.step_number + 1),
+				ScanProgress::Message(format!(
+					"Written {} of {} to db",
+					data.indexed_paths, data.total_paths
+				)),
+			],
+		);
+
+		Ok(())
 	}
 
 	/// Logs some metadata about the indexer job
 	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
-		finalize_indexer(&state.init.location.path, state, ctx)
+		finalize_indexer(
+			&state.init.location,
+			state.init.sub_path.as_deref(),
+			state,
+			ctx,
+		)
 	}
 }
@@ -1,45 +1,84 @@
 use crate::{
-	invalidate_query,
+	api::locations::invalidate_explorer_path,
 	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
 	library::Library,
-	prisma::file_path,
+	prisma::{file_path, location},
 	sync,
+	util::db::{refresh_count_estimates, CountedTable},
 };
 
 use std::{
+	collections::HashMap,
 	hash::{Hash, Hasher},
 	path::{Path, PathBuf},
+	thread::available_parallelism,
 	time::Duration,
 };
 
 use chrono::{DateTime, Utc};
 use int_enum::IntEnumError;
+use itertools::Itertools;
+use once_cell::sync::Lazy;
 use rmp_serde::{decode, encode};
 use rspc::ErrorCode;
 use rules::RuleKind;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
+use sysinfo::{RefreshKind, System, SystemExt};
 use thiserror::Error;
-use tokio::io;
-use tracing::info;
+use tokio::{fs, io};
+use tracing::{error, info, trace};
 
 use super::{
-	file_path_helper::{FilePathError, MaterializedPath},
-	location_with_indexer_rules,
+	file_path_helper::{
+		file_path_for_move_detection, file_path_just_id_materialized_path,
+		find_many_file_paths_by_full_path, get_existing_file_path_id, move_file_path_descendants,
+		sub_path_materialized_path, FilePathError, MaterializedPath,
+	},
+	location_with_indexer_rules, SymlinkPolicy,
 };
+use walk::{FileInode, WalkEntry};
+
+pub(crate) use walk::is_hidden;
 
 pub mod indexer_job;
 pub mod rules;
 pub mod shallow_indexer_job;
 mod walk;
 
+/// Files indexed at each step for every available CPU thread
+const BATCH_SIZE_PER_THREAD: usize = 250;
+const MIN_BATCH_SIZE: usize = 500;
+const MAX_BATCH_SIZE: usize = 5000;
+/// Devices with less memory than this get smaller batches, as every step is held in memory while it's written
+const LOW_MEMORY_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+/// BATCH_SIZE is the number of files to index at each step, writing the chunk of files metadata in the database.
+/// It's sized to the hardware, as larger batches are written faster but need more memory.
+static BATCH_SIZE: Lazy<usize> = Lazy::new(|| {
+	let threads = available_parallelism().map_or(1, Into::into);
+	let total_memory = System::new_with_specifics(RefreshKind::new().with_memory()).total_memory();
+
+	let batch_size = (threads * BATCH_SIZE_PER_THREAD).clamp(MIN_BATCH_SIZE, MAX_BATCH_SIZE);
+	if total_memory < LOW_MEMORY_BYTES {
+		(batch_size / 2).max(MIN_BATCH_SIZE)
+	} else {
+		batch_size
+	}
+});
+
 /// `IndexerJobInit` receives a `location::Data` object to be indexed
 /// and possibly a `sub_path` to be indexed. The `sub_path` is used when
-/// we want do index just a part of a location.
+/// we want do index just a part of a location. `max_depth` and `max_entries` bound the walk,
+/// for locations too deep or too big to be entirely indexed, like mounted network shares.
 #[derive(Serialize, Deserialize)]
 pub struct IndexerJobInit {
 	pub location: location_with_indexer_rules::Data,
 	pub sub_path: Option<PathBuf>,
+	#[serde(default)]
+	pub max_depth: Option<usize>,
+	#[serde(default)]
+	pub max_entries: Option<usize>,
 }
 
 impl Hash for IndexerJobInit {
@@ -62,7 +101,7 @@ pub struct IndexerJobData {
 }
 
 /// `IndexerJobStep` is a type alias, specifying that each step of the [`IndexerJob`] is a vector of
-/// `IndexerJobStepEntry`. The size of this vector is given by the [`BATCH_SIZE`] constant.
+/// `IndexerJobStepEntry`. The size of this vector is given by [`BATCH_SIZE`], which depends on the hardware.
 pub type IndexerJobStep = Vec<IndexerJobStepEntry>;
 
 /// `IndexerJobStepEntry` represents a single file to be indexed, given its metadata to be written
@@ -74,6 +113,12 @@ pub struct IndexerJobStepEntry {
 	created_at: DateTime<Utc>,
 	file_id: i32,
 	parent_id: Option<i32>,
+	#[serde(default)]
+	inode: Option<FileInode>,
+	#[serde(default)]
+	symlink_target: Option<PathBuf>,
+	#[serde(default)]
+	hidden: bool,
 }
 
 impl IndexerJobData {
@@ -108,8 +153,12 @@ pub enum IndexerError {
 	// User errors
 	#[error("Invalid indexer rule kind integer: {0}")]
 	InvalidRuleKindInt(#[from] IntEnumError<RuleKind>),
+	#[error("Invalid symlink policy integer: {0}")]
+	InvalidSymlinkPolicyInt(#[from] IntEnumError<SymlinkPolicy>),
 	#[error("Glob builder error: {0}")]
 	GlobBuilderError(#[from] globset::Error),
+	#[error("Invalid regex: {0}")]
+	InvalidRegex(#[from] regex::Error),
 
 	// Internal Errors
 	#[error("Database error: {0}")]
@@ -133,7 +182,9 @@ impl From<IndexerError> for rspc::Error {
 				rspc::Error::with_cause(ErrorCode::NotFound, err.to_string(), err)
 			}
 
-			IndexerError::InvalidRuleKindInt(_) | IndexerError::GlobBuilderError(_) => {
+			IndexerError::InvalidRuleKindInt(_)
+			| IndexerError::GlobBuilderError(_)
+			| IndexerError::InvalidRegex(_) => {
 				rspc::Error::with_cause(ErrorCode::BadRequest, err.to_string(), err)
 			}
 
@@ -142,12 +193,121 @@ impl From<IndexerError> for rspc::Error {
 	}
 }
 
+/// prepare_indexer_step turns a batch of walked entries into a step, skipping the already indexed ones
+/// and assigning ids to the new ones. `dirs_ids` keeps the ids of the directories walked so far, which
+/// are always walked before their contents, to find the parent ids of the entries of the next batches.
+async fn prepare_indexer_step(
+	location: &location_with_indexer_rules::Data,
+	batch: Vec<WalkEntry>,
+	dirs_ids: &mut HashMap<PathBuf, i32>,
+	library: &Library,
+) -> Result<IndexerJobStep, IndexerError> {
+	let Library {
+		db,
+		last_file_path_id_manager,
+		..
+	} = library;
+	let location_path = Path::new(&location.path);
+
+	// Moved file paths are updated first, so they're found in their new place below and not indexed again
+	update_moved_file_paths(location, &batch, library).await?;
+
+	let existing_ids = find_many_file_paths_by_full_path(
+		&location::Data::from(location),
+		&batch.iter().map(|entry| &entry.path).collect::<Vec<_>>(),
+		db,
+	)
+	.await?
+	.select(file_path_just_id_materialized_path::select())
+	.exec()
+	.await?
+	.into_iter()
+	.map(|file_path| {
+		(
+			location_path.join(file_path.materialized_path),
+			file_path.id,
+		)
+	})
+	.collect::<HashMap<_, _>>();
+
+	// grab the next id so we can increment in memory for batch inserting
+	let mut next_file_id = last_file_path_id_manager
+		.get_max_file_path_id(location.id, db)
+		.await?
+		+ 1;
+
+	let mut step = Vec::with_capacity(batch.len());
+	for entry in batch {
+		if let Some(&id) = existing_ids.get(&entry.path) {
+			if entry.is_dir {
+				dirs_ids.insert(entry.path, id);
+			}
+			continue;
+		}
+
+		let materialized_path =
+			match MaterializedPath::new(location.id, location_path, &entry.path, entry.is_dir) {
+				Ok(materialized_path) => materialized_path,
+				Err(e) => {
+					error!("Failed to create materialized path: {e}");
+					continue;
+				}
+			};
+
+		let file_id = next_file_id;
+		next_file_id += 1;
+
+		if entry.is_dir {
+			dirs_ids.insert(entry.path.clone(), file_id);
+		}
+
+		step.push(IndexerJobStepEntry {
+			parent_id: entry
+				.path
+				.parent()
+				.and_then(|parent_dir| dirs_ids.get(parent_dir).copied()),
+			full_path: entry.path,
+			materialized_path,
+			created_at: entry.created_at,
+			file_id,
+			inode: entry.inode,
+			symlink_target: entry.symlink_target,
+			hidden: entry.hidden,
+		});
+	}
+
+	if !step.is_empty() {
+		// Setting our global state for `file_path` ids
+		last_file_path_id_manager
+			.set_max_file_path_id(location.id, next_file_id - 1)
+			.await;
+	}
+
+	Ok(step)
+}
+
+/// execute_indexer_step writes a chunk of file paths and their sync operations in a single transaction,
+/// so a chunk is either fully indexed or not at all.
 async fn execute_indexer_step(
 	location: &location_with_indexer_rules::Data,
 	step: &[IndexerJobStepEntry],
 	ctx: WorkerContext,
 ) -> Result<i64, JobError> {
-	let Library { sync, db, .. } = &ctx.library;
+	let Library {
+		sync,
+		db,
+		last_file_path_id_manager,
+		..
+	} = &ctx.library;
+
+	// The ids of the chunk were assigned when the job started, which could be before a restart
+	// reset the max ids to the ones in the database
+	if let Some(max_file_id) = step.iter().map(|entry| entry.file_id).max() {
+		last_file_path_id_manager
+			.reserve_file_path_ids(location.id, max_file_id, db)
+			.await
+			.map_err(IndexerError::from)?;
+	}
 
 	let (sync_stuff, paths): (Vec<_>, Vec<_>) = step
 		.iter()
@@ -162,6 +322,12 @@ async fn execute_indexer_step(
 
 			use file_path::*;
 
+			let (inode, device) = inode_params(entry.inode);
+			let symlink_target = entry
+				.symlink_target
+				.as_ref()
+				.map(|target| target.to_string_lossy().to_string());
+
 			(
 				sync.unique_shared_create(
 					sync::file_path::SyncId {
@@ -177,6 +343,9 @@ async fn execute_indexer_step(
 						("extension", json!(extension.clone())),
 						("parent_id", json!(entry.parent_id)),
 						("date_created", json!(entry.created_at)),
+						("is_symlink", json!(symlink_target.is_some())),
+						("symlink_target", json!(symlink_target)),
+						("hidden", json!(entry.hidden)),
 					],
 				),
 				file_path::create_unchecked(
@@ -189,12 +358,22 @@ async fn execute_indexer_step(
 						is_dir::set(is_dir),
 						parent_id::set(entry.parent_id),
 						date_created::set(entry.created_at.into()),
+						// The inode only means something on this node, so it isn't synced
+						inode::set(inode),
+						device::set(device),
+						is_symlink::set(symlink_target.is_some()),
+						symlink_target::set(symlink_target),
+						hidden::set(entry.hidden),
 					],
 				),
 			)
 		})
 		.unzip();
 
+	ctx.journal_file_paths(location.id, step.iter().map(|entry| entry.file_id))
+		.await?;
+
+	// Every query of `write_ops` is run in the same batch, which is a single transaction
 	let count = sync
 		.write_ops(
 			db,
@@ -210,8 +389,222 @@ async fn execute_indexer_step(
 	Ok(count)
 }
 
+/// inode_params returns the `inode` and `device` columns of a file path.
+fn inode_params(inode: Option<FileInode>) -> (Option<Vec<u8>>, Option<Vec<u8>>) {
+	inode
+		.map(|(inode, device)| (inode.to_le_bytes().to_vec(), device.to_le_bytes().to_vec()))
+		.unzip()
+}
+
+/// update_moved_file_paths finds the file paths of the location which were moved or renamed since it was indexed,
+/// by the inode of the found paths which aren't indexed yet, and updates them in place.
+/// Moved file paths keep their object, and so its tags and notes, instead of being indexed again as new files.
+/// It also fills the inode of the found paths indexed before it was tracked. Returns how many file paths were moved.
+async fn update_moved_file_paths(
+	location: &location_with_indexer_rules::Data,
+	found_paths: &[WalkEntry],
+	library: &Library,
+) -> Result<usize, IndexerError> {
+	let Library { db, sync, .. } = library;
+	let location_path = Path::new(&location.path);
+
+	let found_paths = found_paths
+		.iter()
+		.filter_map(|entry| {
+			MaterializedPath::new(location.id, location_path, &entry.path, entry.is_dir)
+				.map_err(|e| error!("Failed to create materialized path: {e}"))
+				.ok()
+				.map(|materialized_path| (entry, materialized_path))
+		})
+		.collect::<Vec<_>>();
+
+	let indexed_paths = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(location.id),
+			file_path::materialized_path::in_vec(
+				found_paths
+					.iter()
+					.map(|(_, materialized_path)| materialized_path.as_ref().to_string())
+					.collect(),
+			),
+		])
+		.select(file_path_for_move_detection::select())
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| (file_path.materialized_path.clone(), file_path))
+		.collect::<HashMap<_, _>>();
+
+	// Filling the inodes of file paths indexed before they were tracked, so their next moves are detected
+	let missing_inodes = found_paths
+		.iter()
+		.filter_map(|(entry, materialized_path)| {
+			let file_path = indexed_paths.get(materialized_path.as_ref())?;
+			let (inode, device) = inode_params(entry.inode);
+
+			(file_path.inode.is_none() && inode.is_some()).then(|| {
+				db.file_path().update(
+					file_path::location_id_id(location.id, file_path.id),
+					vec![file_path::inode::set(inode), file_path::device::set(device)],
+				)
+			})
+		})
+		.collect::<Vec<_>>();
+	for chunk in missing_inodes.into_iter().chunks(*BATCH_SIZE).into_iter() {
+		db._batch(chunk.collect::<Vec<_>>()).await?;
+	}
+
+	let mut candidates = found_paths
+		.iter()
+		.filter(|(entry, materialized_path)| {
+			entry.inode.is_some() && !indexed_paths.contains_key(materialized_path.as_ref())
+		})
+		.collect::<Vec<_>>();
+	if candidates.is_empty() {
+		return Ok(0);
+	}
+	// Directories are moved before their contents, which are moved along with them
+	candidates.sort_by_key(|(entry, _)| entry.path.components().count());
+
+	let moved_file_paths = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(location.id),
+			file_path::inode::in_vec(
+				candidates
+					.iter()
+					.filter_map(|(entry, _)| inode_params(entry.inode).0)
+					.collect(),
+			),
+		])
+		.select(file_path_for_move_detection::select())
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|file_path| {
+			Some((
+				(file_path.inode.clone()?, file_path.device.clone()?),
+				file_path,
+			))
+		})
+		.collect::<HashMap<_, _>>();
+
+	// The old and new materialized paths of the moved directories
+	let mut moved_dirs: Vec<(String, String)> = vec![];
+	let mut moved_count = 0;
+
+	for (entry, materialized_path) in candidates {
+		let (Some(inode), Some(device)) = inode_params(entry.inode) else {
+			continue;
+		};
+		let Some(file_path) = moved_file_paths.get(&(inode, device)) else {
+			continue;
+		};
+
+		// An inode can be reused by a new file once the old one is deleted, but not with the same creation date
+		if file_path.is_dir != entry.is_dir
+			|| file_path.date_created.timestamp() != entry.created_at.timestamp()
+		{
+			continue;
+		}
+
+		// The materialized path of the file path once its moved ancestors were, in the order they were moved
+		let old_materialized_path =
+			moved_dirs
+				.iter()
+				.fold(
+					file_path.materialized_path.clone(),
+					|path, (old, new)| match path.strip_prefix(old.as_str()) {
+						Some(rest) => format!("{new}{rest}"),
+						None => path,
+					},
+				);
+
+		let new_materialized_path = materialized_path.as_ref().to_string();
+		if old_materialized_path == new_materialized_path {
+			// Already moved along with its directory
+			continue;
+		}
+
+		// If the old path still exists, the found path is a hard link to it and not a move
+		if fs::metadata(location_path.join(&old_materialized_path))
+			.await
+			.is_ok()
+		{
+			continue;
+		}
+
+		// Paths moved into new directories are indexed as new files, as their parent doesn't have an id yet
+		let Some(parent_id) = get_existing_file_path_id(materialized_path.parent(), db).await?
+		else {
+			continue;
+		};
+
+		let MaterializedPath {
+			name, extension, ..
+		} = materialized_path.clone();
+
+		let sync_id = || sync::file_path::SyncId {
+			id: file_path.id,
+			location: sync::location::SyncId {
+				pub_id: location.pub_id.clone(),
+			},
+		};
+
+		sync.write_ops(
+			db,
+			(
+				vec![
+					sync.shared_update(
+						sync_id(),
+						"materialized_path",
+						json!(&new_materialized_path),
+					),
+					sync.shared_update(sync_id(), "name", json!(&name)),
+					sync.shared_update(sync_id(), "extension", json!(&extension)),
+					sync.shared_update(sync_id(), "parent_id", json!(parent_id)),
+				],
+				db.file_path().update(
+					file_path::location_id_id(location.id, file_path.id),
+					vec![
+						file_path::materialized_path::set(new_materialized_path.clone()),
+						file_path::name::set(name),
+						file_path::extension::set(extension),
+						file_path::parent_id::set(Some(parent_id)),
+					],
+				),
+			),
+		)
+		.await?;
+
+		if file_path.is_dir {
+			move_file_path_descendants(
+				location.id,
+				file_path.id,
+				&old_materialized_path,
+				&new_materialized_path,
+				db,
+			)
+			.await?;
+
+			moved_dirs.push((old_materialized_path.clone(), new_materialized_path.clone()));
+		}
+
+		trace!("Moved file path {old_materialized_path} to {new_materialized_path}");
+		moved_count += 1;
+	}
+
+	if moved_count > 0 {
+		info!("Found {moved_count} moved file paths");
+	}
+
+	Ok(moved_count)
+}
+
 fn finalize_indexer<SJob, Init>(
-	location_path: impl AsRef<Path>,
+	location: &location_with_indexer_rules::Data,
+	sub_path: Option<&Path>,
 	state: &JobState<SJob>,
 	ctx: WorkerContext,
 ) -> JobResult
@@ -227,7 +620,7 @@ where
 	tracing::info!(
 		"scan of {} completed in {:?}. {} new files found, \
 			indexed {} files in db. db write completed in {:?}",
-		location_path.as_ref().display(),
+		location.path,
 		data.scan_read_time,
 		data.total_paths,
 		data.indexed_paths,
@@ -237,7 +630,13 @@ where
 	);
 
 	if data.indexed_paths > 0 {
-		invalidate_query!(ctx.library, "locations.getExplorerData");
+		refresh_count_estimates(ctx.library.db.clone(), &[CountedTable::FilePath]);
+		// Only the listings of the indexed directory and of its subdirectories changed
+		invalidate_explorer_path(
+			&ctx.library,
+			location.id,
+			sub_path_materialized_path(location.id, &location.path, sub_path),
+		);
 	}
 
 	Ok(Some(serde_json::to_value(state)?))
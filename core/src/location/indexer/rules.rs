@@ -1,17 +1,27 @@
 use crate::{
 	library::Library,
-	location::indexer::IndexerError,
+	location::{indexer::IndexerError, metadata::SPACEDRIVE_LOCATION_METADATA_FILE},
 	prisma::{indexer_rule, PrismaClient},
 };
 
 use chrono::{DateTime, Utc};
 use globset::Glob;
+use ignore::{
+	gitignore::{Gitignore, GitignoreBuilder},
+	Match,
+};
 use int_enum::IntEnum;
+use regex::Regex;
 use rmp_serde;
 use rspc::Type;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashSet, path::Path};
+use std::{
+	collections::{HashMap, HashSet},
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+};
 use tokio::fs;
+use tracing::warn;
 
 /// `IndexerRuleCreateArgs` is the argument received from the client using rspc to create a new indexer rule.
 /// Note that `parameters` field **MUST** be a JSON object serialized to bytes.
@@ -19,8 +29,20 @@ use tokio::fs;
 /// In case of  `RuleKind::AcceptFilesByGlob` or `RuleKind::RejectFilesByGlob`, it will be a
 /// single string containing a glob pattern.
 ///
+/// In case of `RuleKind::AcceptFilesByRegex` or `RuleKind::RejectFilesByRegex`, it will be a
+/// single string containing a regex matched against the full path.
+///
 /// In case of `RuleKind::AcceptIfChildrenDirectoriesArePresent` or `RuleKind::RejectIfChildrenDirectoriesArePresent` the
 /// `parameters` field must be a vector of strings containing the names of the directories.
+///
+/// In case of `RuleKind::IgnoreFile` the `parameters` field must be a vector of strings containing the names of
+/// the ignore files, like `.gitignore`.
+///
+/// In case of `RuleKind::RejectFilesLargerThan` or `RuleKind::RejectFilesSmallerThan`, it will be a
+/// single number with the size in bytes.
+///
+/// In case of `RuleKind::RejectFilesModifiedBefore` or `RuleKind::RejectFilesModifiedAfter`, it will be a
+/// single RFC 3339 date string.
 #[derive(Type, Deserialize)]
 pub struct IndexerRuleCreateArgs {
 	pub kind: RuleKind,
@@ -29,17 +51,50 @@ pub struct IndexerRuleCreateArgs {
 }
 
 impl IndexerRuleCreateArgs {
-	pub async fn create(self, library: &Library) -> Result<indexer_rule::Data, IndexerError> {
-		let parameters = match self.kind {
-			RuleKind::AcceptFilesByGlob | RuleKind::RejectFilesByGlob => rmp_serde::to_vec(
-				&Glob::new(&serde_json::from_slice::<String>(&self.parameters)?)?,
-			)?,
-
-			RuleKind::AcceptIfChildrenDirectoriesArePresent
-			| RuleKind::RejectIfChildrenDirectoriesArePresent => {
-				rmp_serde::to_vec(&serde_json::from_slice::<Vec<String>>(&self.parameters)?)?
+	/// parameters parses the JSON parameters of the rule, failing if they're invalid for its kind.
+	pub fn parameters(&self) -> Result<ParametersPerKind, IndexerError> {
+		let pattern = || serde_json::from_slice::<String>(&self.parameters);
+		let names = || serde_json::from_slice::<Vec<String>>(&self.parameters);
+		let size = || serde_json::from_slice::<u64>(&self.parameters);
+		let date = || serde_json::from_slice::<DateTime<Utc>>(&self.parameters);
+
+		Ok(match self.kind {
+			RuleKind::AcceptFilesByGlob => {
+				ParametersPerKind::AcceptFilesByGlob(Glob::new(&pattern()?)?)
 			}
-		};
+			RuleKind::RejectFilesByGlob => {
+				ParametersPerKind::RejectFilesByGlob(Glob::new(&pattern()?)?)
+			}
+			RuleKind::AcceptFilesByRegex => {
+				ParametersPerKind::AcceptFilesByRegex(Regex::new(&pattern()?)?)
+			}
+			RuleKind::RejectFilesByRegex => {
+				ParametersPerKind::RejectFilesByRegex(Regex::new(&pattern()?)?)
+			}
+			RuleKind::AcceptIfChildrenDirectoriesArePresent => {
+				ParametersPerKind::AcceptIfChildrenDirectoriesArePresent(
+					names()?.into_iter().collect(),
+				)
+			}
+			RuleKind::RejectIfChildrenDirectoriesArePresent => {
+				ParametersPerKind::RejectIfChildrenDirectoriesArePresent(
+					names()?.into_iter().collect(),
+				)
+			}
+			RuleKind::IgnoreFile => ParametersPerKind::IgnoreFile(IgnoreFiles::new(names()?)),
+			RuleKind::RejectFilesLargerThan => ParametersPerKind::RejectFilesLargerThan(size()?),
+			RuleKind::RejectFilesSmallerThan => ParametersPerKind::RejectFilesSmallerThan(size()?),
+			RuleKind::RejectFilesModifiedBefore => {
+				ParametersPerKind::RejectFilesModifiedBefore(date()?)
+			}
+			RuleKind::RejectFilesModifiedAfter => {
+				ParametersPerKind::RejectFilesModifiedAfter(date()?)
+			}
+		})
+	}
+
+	pub async fn create(self, library: &Library) -> Result<indexer_rule::Data, IndexerError> {
+		let parameters = self.parameters()?.serialize()?;
 
 		library
 			.db
@@ -59,6 +114,13 @@ pub enum RuleKind {
 	RejectFilesByGlob = 1,
 	AcceptIfChildrenDirectoriesArePresent = 2,
 	RejectIfChildrenDirectoriesArePresent = 3,
+	IgnoreFile = 4,
+	AcceptFilesByRegex = 5,
+	RejectFilesByRegex = 6,
+	RejectFilesLargerThan = 7,
+	RejectFilesSmallerThan = 8,
+	RejectFilesModifiedBefore = 9,
+	RejectFilesModifiedAfter = 10,
 }
 
 /// `ParametersPerKind` is a mapping from `RuleKind` to the parameters required for each kind of rule.
@@ -68,12 +130,23 @@ pub enum RuleKind {
 ///
 /// In case of `ParametersPerKind::AcceptIfChildrenDirectoriesArePresent` or `ParametersPerKind::RejectIfChildrenDirectoriesArePresent`
 /// first we change the data structure to a vector, then we serialize it.
+///
+/// In case of `ParametersPerKind::IgnoreFile` the names of the ignore files are serialized as a vector.
+///
+/// The size and date rules only apply to files, using the metadata read while walking the location.
 #[derive(Debug)]
 pub enum ParametersPerKind {
 	AcceptFilesByGlob(Glob),
 	RejectFilesByGlob(Glob),
 	AcceptIfChildrenDirectoriesArePresent(HashSet<String>),
 	RejectIfChildrenDirectoriesArePresent(HashSet<String>),
+	IgnoreFile(IgnoreFiles),
+	AcceptFilesByRegex(Regex),
+	RejectFilesByRegex(Regex),
+	RejectFilesLargerThan(u64),
+	RejectFilesSmallerThan(u64),
+	RejectFilesModifiedBefore(DateTime<Utc>),
+	RejectFilesModifiedAfter(DateTime<Utc>),
 }
 
 impl ParametersPerKind {
@@ -88,9 +161,39 @@ impl ParametersPerKind {
 
 			ParametersPerKind::AcceptFilesByGlob(glob) => accept_by_glob(source, glob),
 			ParametersPerKind::RejectFilesByGlob(glob) => reject_by_glob(source, glob),
+			ParametersPerKind::IgnoreFile(ignore_files) => {
+				ignore_files.is_not_ignored(source.as_ref()).await
+			}
+			ParametersPerKind::AcceptFilesByRegex(regex) => Ok(matches_regex(source, regex)),
+			ParametersPerKind::RejectFilesByRegex(regex) => Ok(!matches_regex(source, regex)),
+			ParametersPerKind::RejectFilesLargerThan(_)
+			| ParametersPerKind::RejectFilesSmallerThan(_)
+			| ParametersPerKind::RejectFilesModifiedBefore(_)
+			| ParametersPerKind::RejectFilesModifiedAfter(_) => {
+				self.apply_to_metadata(&fs::metadata(source).await?)
+			}
 		}
 	}
 
+	/// apply_to_metadata applies the size and date rules to already read metadata, accepting anything for other rules.
+	fn apply_to_metadata(&self, metadata: &std::fs::Metadata) -> Result<bool, IndexerError> {
+		if metadata.is_dir() {
+			return Ok(true);
+		}
+
+		Ok(match self {
+			ParametersPerKind::RejectFilesLargerThan(size) => metadata.len() <= *size,
+			ParametersPerKind::RejectFilesSmallerThan(size) => metadata.len() >= *size,
+			ParametersPerKind::RejectFilesModifiedBefore(date) => {
+				DateTime::<Utc>::from(metadata.modified()?) >= *date
+			}
+			ParametersPerKind::RejectFilesModifiedAfter(date) => {
+				DateTime::<Utc>::from(metadata.modified()?) <= *date
+			}
+			_ => true,
+		})
+	}
+
 	fn serialize(self) -> Result<Vec<u8>, IndexerError> {
 		match self {
 			Self::AcceptFilesByGlob(glob) | Self::RejectFilesByGlob(glob) => {
@@ -100,10 +203,132 @@ impl ParametersPerKind {
 			| Self::RejectIfChildrenDirectoriesArePresent(children) => {
 				rmp_serde::to_vec(&children.into_iter().collect::<Vec<_>>()).map_err(Into::into)
 			}
+			Self::IgnoreFile(ignore_files) => {
+				rmp_serde::to_vec(&ignore_files.names).map_err(Into::into)
+			}
+			Self::AcceptFilesByRegex(regex) | Self::RejectFilesByRegex(regex) => {
+				rmp_serde::to_vec(regex.as_str()).map_err(Into::into)
+			}
+			Self::RejectFilesLargerThan(size) | Self::RejectFilesSmallerThan(size) => {
+				rmp_serde::to_vec(&size).map_err(Into::into)
+			}
+			Self::RejectFilesModifiedBefore(date) | Self::RejectFilesModifiedAfter(date) => {
+				rmp_serde::to_vec(&date).map_err(Into::into)
+			}
 		}
 	}
 }
 
+/// `IgnoreFiles` are the names of the ignore files honored by a `RuleKind::IgnoreFile` rule, like `.gitignore`.
+/// They use the gitignore syntax, and apply to the directory they're in and its descendants up to the location root,
+/// with the nearest ones taking precedence. Each directory is only read once for as long as the rule is used.
+#[derive(Debug, Default)]
+pub struct IgnoreFiles {
+	names: Vec<String>,
+	directories: Mutex<HashMap<PathBuf, Arc<IgnoreDirectory>>>,
+}
+
+#[derive(Debug)]
+struct IgnoreDirectory {
+	/// The patterns of the ignore files in the directory, if it has any
+	matcher: Option<Gitignore>,
+	/// Ignore files above the root directory of a location don't apply to it
+	is_location_root: bool,
+}
+
+impl IgnoreFiles {
+	pub fn new(names: impl IntoIterator<Item = impl Into<String>>) -> Self {
+		Self {
+			names: names.into_iter().map(Into::into).collect(),
+			directories: Default::default(),
+		}
+	}
+
+	async fn directory(&self, path: &Path) -> Arc<IgnoreDirectory> {
+		if let Some(directory) = self
+			.directories
+			.lock()
+			.expect("ignore files lock poisoned")
+			.get(path)
+		{
+			return Arc::clone(directory);
+		}
+
+		let mut builder = GitignoreBuilder::new(path);
+		let mut has_ignore_files = false;
+		for name in &self.names {
+			let ignore_file = path.join(name);
+			let Ok(contents) = fs::read_to_string(&ignore_file).await else {
+				continue;
+			};
+
+			has_ignore_files = true;
+			for line in contents.lines() {
+				if let Err(e) = builder.add_line(Some(ignore_file.clone()), line) {
+					warn!("Invalid pattern in {}: {e}", ignore_file.display());
+				}
+			}
+		}
+
+		let matcher = if has_ignore_files {
+			builder
+				.build()
+				.map_err(|e| warn!("Failed to read the ignore files of {}: {e}", path.display()))
+				.ok()
+		} else {
+			None
+		};
+
+		let directory = Arc::new(IgnoreDirectory {
+			matcher,
+			is_location_root: fs::metadata(path.join(SPACEDRIVE_LOCATION_METADATA_FILE))
+				.await
+				.is_ok(),
+		});
+
+		self.directories
+			.lock()
+			.expect("ignore files lock poisoned")
+			.insert(path.to_path_buf(), Arc::clone(&directory));
+
+		directory
+	}
+
+	async fn is_not_ignored(&self, source: &Path) -> Result<bool, IndexerError> {
+		let is_dir = fs::metadata(source).await?.is_dir();
+
+		let mut directories = vec![];
+		let mut in_location = false;
+		for ancestor in source.ancestors().skip(1) {
+			let directory = self.directory(ancestor).await;
+			let is_location_root = directory.is_location_root;
+			directories.push(directory);
+
+			if is_location_root {
+				in_location = true;
+				break;
+			}
+		}
+
+		// Ignore files only apply inside of a location
+		if !in_location {
+			return Ok(true);
+		}
+
+		for directory in directories {
+			if let Some(matcher) = &directory.matcher {
+				match matcher.matched_path_or_any_parents(source, is_dir) {
+					Match::Ignore(_) => return Ok(false),
+					Match::Whitelist(_) => return Ok(true),
+					Match::None => {}
+				}
+			}
+		}
+
+		Ok(true)
+	}
+}
+
 #[derive(Debug)]
 pub struct IndexerRule {
 	pub id: Option<i32>,
@@ -130,6 +355,10 @@ impl IndexerRule {
 		self.parameters.apply(source).await
 	}
 
+	pub fn apply_to_metadata(&self, metadata: &std::fs::Metadata) -> Result<bool, IndexerError> {
+		self.parameters.apply_to_metadata(metadata)
+	}
+
 	pub async fn save(self, client: &PrismaClient) -> Result<(), IndexerError> {
 		if let Some(id) = self.id {
 			client
@@ -193,6 +422,34 @@ impl TryFrom<&indexer_rule::Data> for IndexerRule {
 						ParametersPerKind::RejectIfChildrenDirectoriesArePresent(childrens)
 					}
 				}
+				RuleKind::IgnoreFile => {
+					let names = rmp_serde::from_slice::<Vec<String>>(&data.parameters)?;
+					ParametersPerKind::IgnoreFile(IgnoreFiles::new(names))
+				}
+				RuleKind::AcceptFilesByRegex | RuleKind::RejectFilesByRegex => {
+					let regex = Regex::new(&rmp_serde::from_slice::<String>(&data.parameters)?)?;
+					if matches!(kind, RuleKind::AcceptFilesByRegex) {
+						ParametersPerKind::AcceptFilesByRegex(regex)
+					} else {
+						ParametersPerKind::RejectFilesByRegex(regex)
+					}
+				}
+				RuleKind::RejectFilesLargerThan | RuleKind::RejectFilesSmallerThan => {
+					let size = rmp_serde::from_slice(&data.parameters)?;
+					if matches!(kind, RuleKind::RejectFilesLargerThan) {
+						ParametersPerKind::RejectFilesLargerThan(size)
+					} else {
+						ParametersPerKind::RejectFilesSmallerThan(size)
+					}
+				}
+				RuleKind::RejectFilesModifiedBefore | RuleKind::RejectFilesModifiedAfter => {
+					let date = rmp_serde::from_slice(&data.parameters)?;
+					if matches!(kind, RuleKind::RejectFilesModifiedBefore) {
+						ParametersPerKind::RejectFilesModifiedBefore(date)
+					} else {
+						ParametersPerKind::RejectFilesModifiedAfter(date)
+					}
+				}
 			},
 			date_created: data.date_created.into(),
 			date_modified: data.date_modified.into(),
@@ -216,6 +473,10 @@ fn reject_by_glob(source: impl AsRef<Path>, reject_glob: &Glob) -> Result<bool,
 	Ok(!reject_glob.compile_matcher().is_match(source.as_ref()))
 }
 
+fn matches_regex(source: impl AsRef<Path>, regex: &Regex) -> bool {
+	regex.is_match(&source.as_ref().to_string_lossy())
+}
+
 async fn accept_dir_for_its_children(
 	source: impl AsRef<Path>,
 	children: &HashSet<String>,
@@ -323,6 +584,41 @@ mod tests {
 		assert!(rule.apply(many_inner_dirs_png).await.unwrap());
 	}
 
+	#[tokio::test]
+	async fn test_reject_by_regex() {
+		let log = Path::new("/test/app.log");
+		let rotated_log = Path::new("/test/app.log.3");
+		let text = Path::new("/test/logs/file.txt");
+		let rule = IndexerRule::new(
+			RuleKind::RejectFilesByRegex,
+			"no logs".to_string(),
+			ParametersPerKind::RejectFilesByRegex(Regex::new(r"\.log(\.\d+)?$").unwrap()),
+		);
+		assert!(!rule.apply(log).await.unwrap());
+		assert!(!rule.apply(rotated_log).await.unwrap());
+		assert!(rule.apply(text).await.unwrap());
+	}
+
+	#[tokio::test]
+	async fn test_reject_by_size() {
+		let root = tempdir().unwrap();
+
+		let small = root.path().join("small.txt");
+		let big = root.path().join("big.img");
+		fs::write(&small, [0; 16]).await.unwrap();
+		fs::write(&big, [0; 4096]).await.unwrap();
+
+		let rule = IndexerRule::new(
+			RuleKind::RejectFilesLargerThan,
+			"no big files".to_string(),
+			ParametersPerKind::RejectFilesLargerThan(1024),
+		);
+		assert!(rule.apply(&small).await.unwrap());
+		assert!(!rule.apply(&big).await.unwrap());
+		// Directories are never rejected by their size
+		assert!(rule.apply(root.path()).await.unwrap());
+	}
+
 	#[tokio::test]
 	async fn test_directory_has_children() {
 		let root = tempdir().unwrap();
@@ -380,4 +676,43 @@ mod tests {
 		assert!(!rule.apply(project2).await.unwrap());
 		assert!(rule.apply(not_project).await.unwrap());
 	}
+
+	#[tokio::test]
+	async fn test_ignore_files() {
+		let root = tempdir().unwrap();
+
+		// Ignore files only apply inside of a location
+		fs::write(root.path().join(".spacedrive"), "{}")
+			.await
+			.unwrap();
+		fs::write(root.path().join(".gitignore"), "node_modules/\n*.log\n")
+			.await
+			.unwrap();
+
+		let project = root.path().join("project");
+		let node_modules = project.join("node_modules");
+		let debug_log = project.join("debug.log");
+		let important_log = project.join("important.log");
+		let main_rs = project.join("main.rs");
+
+		fs::create_dir(&project).await.unwrap();
+		fs::create_dir(&node_modules).await.unwrap();
+		fs::write(project.join(".sdignore"), "!important.log\n")
+			.await
+			.unwrap();
+		for file in [&debug_log, &important_log, &main_rs] {
+			fs::write(file, "").await.unwrap();
+		}
+
+		let rule = IndexerRule::new(
+			RuleKind::IgnoreFile,
+			"ignore files".to_string(),
+			ParametersPerKind::IgnoreFile(IgnoreFiles::new([".gitignore", ".sdignore"])),
+		);
+
+		assert!(!rule.apply(&node_modules).await.unwrap());
+		assert!(!rule.apply(&debug_log).await.unwrap());
+		assert!(rule.apply(&important_log).await.unwrap());
+		assert!(rule.apply(&main_rs).await.unwrap());
+	}
 }
@@ -1,10 +1,13 @@
 use crate::{
-	job::{JobError, JobResult, JobState, StatefulJob, WorkerContext},
+	job::{JobError, JobPriority, JobResult, JobState, StatefulJob, WorkerContext},
 	library::Library,
-	location::file_path_helper::{
-		ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
-		file_path_just_id_materialized_path, find_many_file_paths_by_full_path,
-		get_existing_file_path_id, MaterializedPath,
+	location::{
+		file_path_helper::{
+			ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
+			file_path_just_id_materialized_path, find_many_file_paths_by_full_path,
+			get_existing_file_path_id, MaterializedPath,
+		},
+		LocationError, SymlinkPolicy,
 	},
 	prisma::location,
 };
@@ -16,6 +19,7 @@ use std::{
 };
 
 use chrono::Utc;
+use int_enum::IntEnum;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use tokio::time::Instant;
@@ -24,21 +28,25 @@ use tracing::error;
 use super::{
 	execute_indexer_step, finalize_indexer, location_with_indexer_rules,
 	rules::{IndexerRule, RuleKind},
-	walk::walk_single_dir,
-	IndexerError, IndexerJobData, IndexerJobStep, IndexerJobStepEntry, ScanProgress,
+	update_moved_file_paths,
+	walk::{walk_single_dir, WalkLimits},
+	IndexerError, IndexerJobData, IndexerJobStep, IndexerJobStepEntry, ScanProgress, BATCH_SIZE,
 };
 
-/// BATCH_SIZE is the number of files to index at each step, writing the chunk of files metadata in the database.
-const BATCH_SIZE: usize = 1000;
 pub const SHALLOW_INDEXER_JOB_NAME: &str = "shallow_indexer";
 
 /// `ShallowIndexerJobInit` receives a `location::Data` object to be indexed
 /// and possibly a `sub_path` to be indexed. The `sub_path` is used when
-/// we want do index just a part of a location.
+/// we want do index just a part of a location. `max_depth` and `max_entries` bound the walk,
+/// as in [`IndexerJobInit`](super::IndexerJobInit).
 #[derive(Serialize, Deserialize)]
 pub struct ShallowIndexerJobInit {
 	pub location: location_with_indexer_rules::Data,
 	pub sub_path: PathBuf,
+	#[serde(default)]
+	pub max_depth: Option<usize>,
+	#[serde(default)]
+	pub max_entries: Option<usize>,
 }
 
 impl Hash for ShallowIndexerJobInit {
@@ -63,6 +71,16 @@ impl StatefulJob for ShallowIndexerJob {
 		SHALLOW_INDEXER_JOB_NAME
 	}
 
+	fn journals(&self) -> bool {
+		// All of its chunks are prepared in `init`, so an interrupted job is rebuilt from its journal
+		true
+	}
+
+	fn priority(&self, _init: &Self::Init) -> JobPriority {
+		// The user is looking at the directory being scanned
+		JobPriority::Interactive
+	}
+
 	/// Creates a vector of valid path buffers from a directory, chunked into batches of `BATCH_SIZE`.
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
 		let Library {
@@ -109,7 +127,12 @@ impl StatefulJob for ShallowIndexerJob {
 				)
 				.await
 				.map_err(IndexerError::from)?
-				.expect("Sub path should already exist in the database"),
+				// The directory is scanned shallowly, so it must have been indexed before
+				.ok_or_else(|| {
+					LocationError::DirectoryNotFound(
+						state.init.sub_path.to_string_lossy().to_string(),
+					)
+				})?,
 			)
 		} else {
 			(
@@ -121,12 +144,15 @@ impl StatefulJob for ShallowIndexerJob {
 				)
 				.await
 				.map_err(IndexerError::from)?
-				.expect("Location root path should already exist in the database"),
+				.ok_or_else(|| {
+					LocationError::DirectoryNotFound(location_path.to_string_lossy().to_string())
+				})?,
 			)
 		};
 
 		let scan_start = Instant::now();
 		let found_paths = walk_single_dir(
+			location_path,
 			to_walk_path,
 			&indexer_rules_by_kind,
 			|path, total_entries| {
@@ -134,13 +160,22 @@ impl StatefulJob for ShallowIndexerJob {
 					&ctx,
 					vec![
 						ScanProgress::Message(format!("Scanning {}", path.display())),
-						ScanProgress::ChunkCount(total_entries / BATCH_SIZE),
+						ScanProgress::ChunkCount(total_entries / *BATCH_SIZE),
 					],
 				);
 			},
+			WalkLimits {
+				max_depth: state.init.max_depth,
+				max_entries: state.init.max_entries,
+			},
+			SymlinkPolicy::from_int(state.init.location.symlink_policy)
+				.map_err(IndexerError::from)?,
 		)
 		.await?;
 
+		// Moved file paths are updated first, so they're found in their new place below and not indexed again
+		update_moved_file_paths(&state.init.location, &found_paths, &ctx.library).await?;
+
 		let already_existing_file_paths = find_many_file_paths_by_full_path(
 			&location::Data::from(&state.init.location),
 			&found_paths
@@ -177,6 +212,9 @@ impl StatefulJob for ShallowIndexerJob {
 								created_at: entry.created_at,
 								file_id: 0, // To be set later
 								parent_id: Some(parent_id),
+								inode: entry.inode,
+								symlink_target: entry.symlink_target,
+								hidden: entry.hidden,
 							})
 						},
 					)
@@ -211,7 +249,7 @@ impl StatefulJob for ShallowIndexerJob {
 
 		state.steps = new_paths
 			.into_iter()
-			.chunks(BATCH_SIZE)
+			.chunks(*BATCH_SIZE)
 			.into_iter()
 			.enumerate()
 			.map(|(i, chunk)| {
@@ -247,12 +285,17 @@ impl StatefulJob for ShallowIndexerJob {
 					.data
 					.as_mut()
 					.expect("critical error: missing data on job state")
-					.indexed_paths = indexed_paths;
+					.indexed_paths += indexed_paths;
 			})
 	}
 
 	/// Logs some metadata about the indexer job
 	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
-		finalize_indexer(&state.init.location.path, state, ctx)
+		finalize_indexer(
+			&state.init.location,
+			Some(state.init.sub_path.as_path()),
+			state,
+			ctx,
+		)
 	}
 }
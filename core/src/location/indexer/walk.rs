@@ -1,16 +1,19 @@
+use crate::{library::TRASH_DIR_NAME, location::SymlinkPolicy};
+
 use chrono::{DateTime, Utc};
 use std::{
 	cmp::Ordering,
-	collections::{HashMap, VecDeque},
+	collections::{HashMap, HashSet, VecDeque},
 	hash::{Hash, Hasher},
+	mem,
 	path::{Path, PathBuf},
 };
-use tokio::fs;
-use tracing::{error, trace};
+use tokio::{fs, sync::mpsc};
+use tracing::{error, trace, warn};
 
 use super::{
 	rules::{IndexerRule, RuleKind},
-	IndexerError,
+	IndexerError, BATCH_SIZE,
 };
 
 /// `WalkEntry` represents a single path in the filesystem, for any comparison purposes, we only
@@ -20,6 +23,10 @@ pub(super) struct WalkEntry {
 	pub(super) path: PathBuf,
 	pub(super) is_dir: bool,
 	pub(super) created_at: DateTime<Utc>,
+	pub(super) inode: Option<FileInode>,
+	/// Where the path points to, if it's a symlink indexed as a link
+	pub(super) symlink_target: Option<PathBuf>,
+	pub(super) hidden: bool,
 }
 
 impl PartialEq for WalkEntry {
@@ -48,24 +55,192 @@ impl Ord for WalkEntry {
 	}
 }
 
+/// FileInode identifies a file on its device as `(inode, device)`, which doesn't change when it's moved or renamed.
+pub(super) type FileInode = (u64, u64);
+
+/// file_inode returns the inode and device of a file. They're only known on unix, so moves aren't detected on other platforms.
+pub(super) fn file_inode(metadata: &std::fs::Metadata) -> Option<FileInode> {
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+
+		Some((metadata.ino(), metadata.dev()))
+	}
+	#[cfg(not(unix))]
+	{
+		let _ = metadata;
+		None
+	}
+}
+
+/// is_hidden tells if a path is hidden from file managers, which are dotfiles on unix,
+/// and files with the hidden or system attributes on windows.
+pub(crate) fn is_hidden(path: &Path, metadata: &std::fs::Metadata) -> bool {
+	#[cfg(windows)]
+	{
+		use std::os::windows::fs::MetadataExt;
+
+		const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+		const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+
+		let _ = path;
+		metadata.file_attributes() & (FILE_ATTRIBUTE_HIDDEN | FILE_ATTRIBUTE_SYSTEM) != 0
+	}
+	#[cfg(not(windows))]
+	{
+		let _ = metadata;
+		path.file_name()
+			.map_or(false, |name| name.to_string_lossy().starts_with('.'))
+	}
+}
+
 type ToWalkEntry = (PathBuf, Option<bool>);
 
-/// This function walks through the filesystem, applying the rules to each entry and then returning
-/// a list of accepted entries. There are some useful comments in the implementation of this function
-/// in case of doubts.
+/// `WalkLimits` bounds how much of the filesystem is walked, for trees too deep or too big to be entirely indexed,
+/// like the ones on mounted network shares.
+#[derive(Clone, Copy, Debug, Default)]
+pub(super) struct WalkLimits {
+	/// Directories this deep below the walked one aren't walked into, so `Some(1)` only indexes its direct children
+	pub(super) max_depth: Option<usize>,
+	/// The walk stops once this many paths were accepted
+	pub(super) max_entries: Option<usize>,
+}
+
+impl WalkLimits {
+	fn walks_into(&self, root: &Path, path: &Path) -> bool {
+		let depth = path
+			.strip_prefix(root)
+			.map_or(0, |relative| relative.components().count());
+
+		self.max_depth.map_or(true, |max_depth| depth < max_depth)
+	}
+
+	fn entries_reached(&self, entries: usize) -> bool {
+		self.max_entries
+			.map_or(false, |max_entries| entries >= max_entries)
+	}
+}
+
+/// rules_of_kinds returns the rules of any of `kinds`, which are applied the same way.
+fn rules_of_kinds<'rules>(
+	rules_per_kind: &'rules HashMap<RuleKind, Vec<IndexerRule>>,
+	kinds: impl IntoIterator<Item = RuleKind> + 'rules,
+) -> impl Iterator<Item = &'rules IndexerRule> {
+	kinds
+		.into_iter()
+		.filter_map(|kind| rules_per_kind.get(&kind))
+		.flatten()
+}
+
+/// `WalkedPaths` gathers the accepted entries of a walk. When there's a channel for them, they're sent in
+/// batches while the walk goes on, so the entries of a whole location are never held at once.
+/// Only the accepted directories are kept, to send the ancestors of accepted entries just once.
+struct WalkedPaths {
+	dirs: HashSet<PathBuf>,
+	/// The directories walked into, so a followed symlink reaching one of them again isn't walked twice
+	visited_dirs: HashSet<FileInode>,
+	batch: Vec<WalkEntry>,
+	count: usize,
+	batches_tx: Option<mpsc::Sender<Vec<WalkEntry>>>,
+}
+
+impl WalkedPaths {
+	fn new(batches_tx: Option<mpsc::Sender<Vec<WalkEntry>>>) -> Self {
+		Self {
+			dirs: HashSet::new(),
+			visited_dirs: HashSet::new(),
+			batch: vec![],
+			count: 0,
+			batches_tx,
+		}
+	}
+
+	fn contains_dir(&self, path: &Path) -> bool {
+		self.dirs.contains(path)
+	}
+
+	/// visit_dir records a directory walked into, returning false if it was already visited.
+	/// Directories without an inode can't be told apart, so they're always visited.
+	fn visit_dir(&mut self, metadata: &std::fs::Metadata) -> bool {
+		file_inode(metadata).map_or(true, |inode| self.visited_dirs.insert(inode))
+	}
+
+	async fn push(&mut self, entry: WalkEntry) {
+		if entry.is_dir {
+			self.dirs.insert(entry.path.clone());
+		}
+		self.count += 1;
+		self.batch.push(entry);
+
+		if self.batch.len() >= *BATCH_SIZE {
+			self.send_batch().await;
+		}
+	}
+
+	async fn send_batch(&mut self) {
+		let Some(batches_tx) = &self.batches_tx else {
+			return;
+		};
+
+		if !self.batch.is_empty() && batches_tx.send(mem::take(&mut self.batch)).await.is_err() {
+			// The receiver is only dropped when indexing failed, which it reports itself
+			trace!("Dropping walked entries as they're no longer received");
+		}
+	}
+}
+
+/// This function walks through the filesystem, applying the rules to each entry and then sending
+/// the accepted entries in batches of [`BATCH_SIZE`], with the directories always before their contents.
+/// Returns how many entries were accepted. There are some useful comments in the implementation of
+/// this function in case of doubts.
 pub(super) async fn walk(
+	location_path: impl AsRef<Path>,
 	root: impl AsRef<Path>,
 	rules_per_kind: &HashMap<RuleKind, Vec<IndexerRule>>,
 	update_notifier: impl Fn(&Path, usize),
 	include_root: bool,
-) -> Result<Vec<WalkEntry>, IndexerError> {
+	limits: WalkLimits,
+	symlink_policy: SymlinkPolicy,
+	batches_tx: mpsc::Sender<Vec<WalkEntry>>,
+) -> Result<usize, IndexerError> {
+	let location_path = location_path.as_ref();
 	let root = root.as_ref().to_path_buf();
 
 	let mut to_walk = VecDeque::with_capacity(1);
 	to_walk.push_back((root.clone(), None));
-	let mut indexed_paths = HashMap::new();
+	let mut walked = WalkedPaths::new(Some(batches_tx));
+
+	let root_metadata = fs::metadata(&root).await?;
+	walked.visit_dir(&root_metadata);
+
+	if include_root {
+		// Also adding the root location path, first so its id is known by its children
+		walked
+			.push(WalkEntry {
+				path: root.clone(),
+				is_dir: true,
+				created_at: root_metadata.created()?.into(),
+				inode: file_inode(&root_metadata),
+				symlink_target: None,
+				hidden: is_hidden(&root, &root_metadata),
+			})
+			.await;
+	}
 
 	while let Some((current_path, parent_dir_accepted_by_its_children)) = to_walk.pop_front() {
+		if limits.entries_reached(walked.count) {
+			break;
+		}
+
+		if !limits.walks_into(&root, &current_path) {
+			trace!(
+				"Not walking into {} as it's deeper than {:?}",
+				current_path.display(),
+				limits.max_depth
+			);
+			continue;
+		}
+
 		let mut read_dir = match fs::read_dir(&current_path).await {
 			Ok(read_dir) => read_dir,
 			Err(e) => {
@@ -79,33 +254,50 @@ pub(super) async fn walk(
 		};
 
 		inner_walk_single_dir(
+			location_path,
 			&root,
 			(current_path, parent_dir_accepted_by_its_children),
 			&mut read_dir,
 			rules_per_kind,
 			&update_notifier,
-			&mut indexed_paths,
+			&mut walked,
 			Some(&mut to_walk),
+			limits,
+			symlink_policy,
 		)
 		.await?;
 	}
 
-	prepared_indexed_paths(root, indexed_paths, include_root).await
+	walked.send_batch().await;
+
+	Ok(walked.count)
 }
 
 async fn inner_walk_single_dir(
+	location_path: &Path,
 	root: impl AsRef<Path>,
 	(current_path, parent_dir_accepted_by_its_children): ToWalkEntry,
 	read_dir: &mut fs::ReadDir,
 	rules_per_kind: &HashMap<RuleKind, Vec<IndexerRule>>,
 	update_notifier: &impl Fn(&Path, usize),
-	indexed_paths: &mut HashMap<PathBuf, WalkEntry>,
+	walked: &mut WalkedPaths,
 	mut maybe_to_walk: Option<&mut VecDeque<(PathBuf, Option<bool>)>>,
+	limits: WalkLimits,
+	symlink_policy: SymlinkPolicy,
 ) -> Result<(), IndexerError> {
 	let root = root.as_ref();
 
 	// Marking with a loop label here in case of rejection or erros, to continue with next entry
 	'entries: loop {
+		if limits.entries_reached(walked.count) {
+			warn!(
+				"Stopped walking {} as the limit of entries was reached with {} of them",
+				root.display(),
+				walked.count
+			);
+			break;
+		}
+
 		let entry = match read_dir.next_entry().await {
 			Ok(Some(entry)) => entry,
 			Ok(None) => break,
@@ -128,32 +320,117 @@ async fn inner_walk_single_dir(
 
 		let current_path = entry.path();
 
-		update_notifier(&current_path, indexed_paths.len());
+		// The trash of the location is managed by the library, so it's never indexed. Only the one at the root
+		// of the location is the trash, directories with the same name anywhere else are indexed as usual
+		if entry.file_name() == TRASH_DIR_NAME && current_path.parent() == Some(location_path) {
+			continue 'entries;
+		}
+
+		update_notifier(&current_path, walked.count);
 
 		trace!(
 			"Current filesystem path: {}, accept_by_children_dir: {:#?}",
 			current_path.display(),
 			accept_by_children_dir
 		);
-		if let Some(reject_rules) = rules_per_kind.get(&RuleKind::RejectFilesByGlob) {
-			for reject_rule in reject_rules {
-				// It's ok to unwrap here, reject rules are infallible
-				if !reject_rule.apply(&current_path).await.unwrap() {
-					trace!(
-						"Path {} rejected by rule {}",
+		for reject_rule in rules_of_kinds(
+			rules_per_kind,
+			[RuleKind::RejectFilesByGlob, RuleKind::RejectFilesByRegex],
+		) {
+			// It's ok to unwrap here, reject rules are infallible
+			if !reject_rule.apply(&current_path).await.unwrap() {
+				trace!(
+					"Path {} rejected by rule {}",
+					current_path.display(),
+					reject_rule.name
+				);
+				continue 'entries;
+			}
+		}
+
+		if let Some(ignore_file_rules) = rules_per_kind.get(&RuleKind::IgnoreFile) {
+			for ignore_file_rule in ignore_file_rules {
+				match ignore_file_rule.apply(&current_path).await {
+					Ok(true) => {}
+					Ok(false) => {
+						trace!(
+							"Path {} ignored by rule {}",
+							current_path.display(),
+							ignore_file_rule.name
+						);
+						continue 'entries;
+					}
+					// A path which can't be checked is kept, as ignoring it would hide it from the library
+					Err(e) => error!(
+						"Error applying rule {} to path {}: {:#?}",
+						ignore_file_rule.name,
 						current_path.display(),
-						reject_rule.name
-					);
-					continue 'entries;
+						e
+					),
 				}
 			}
 		}
 
-		let metadata = entry.metadata().await?;
+		let mut metadata = entry.metadata().await?;
+		let mut symlink_target = None;
 
-		// TODO: Hard ignoring symlinks for now, but this should be configurable
 		if metadata.is_symlink() {
-			continue 'entries;
+			match symlink_policy {
+				SymlinkPolicy::Skip => continue 'entries,
+				// The link itself is indexed and never walked into, so its target isn't counted again
+				SymlinkPolicy::IndexAsLink => match fs::read_link(&current_path).await {
+					Ok(target) => symlink_target = Some(target),
+					Err(e) => {
+						error!("Error reading symlink {}: {:#?}", current_path.display(), e);
+						continue 'entries;
+					}
+				},
+				SymlinkPolicy::Follow => match followed_symlink(&current_path).await {
+					// Symlinks can reach a directory from several places, or form cycles through other symlinks
+					Some(target_metadata)
+						if target_metadata.is_dir() && !walked.visit_dir(&target_metadata) =>
+					{
+						warn!(
+							"Skipping symlink {} as its target was already walked",
+							current_path.display()
+						);
+						continue 'entries;
+					}
+					Some(target_metadata) => metadata = target_metadata,
+					None => continue 'entries,
+				},
+			}
+		} else if metadata.is_dir() {
+			walked.visit_dir(&metadata);
+		}
+
+		for metadata_rule in rules_of_kinds(
+			rules_per_kind,
+			[
+				RuleKind::RejectFilesLargerThan,
+				RuleKind::RejectFilesSmallerThan,
+				RuleKind::RejectFilesModifiedBefore,
+				RuleKind::RejectFilesModifiedAfter,
+			],
+		) {
+			match metadata_rule.apply_to_metadata(&metadata) {
+				Ok(true) => {}
+				Ok(false) => {
+					trace!(
+						"Path {} rejected by rule {}",
+						current_path.display(),
+						metadata_rule.name
+					);
+					continue 'entries;
+				}
+				// Only happens when the platform doesn't keep modification dates, so the file is kept
+				Err(e) => error!(
+					"Error applying rule {} to path {}: {:#?}",
+					metadata_rule.name,
+					current_path.display(),
+					e
+				),
+			}
 		}
 
 		let is_dir = metadata.is_dir();
@@ -227,7 +504,12 @@ async fn inner_walk_single_dir(
 		}
 
 		let mut accept_by_glob = false;
-		if let Some(accept_rules) = rules_per_kind.get(&RuleKind::AcceptFilesByGlob) {
+		let accept_rules = rules_of_kinds(
+			rules_per_kind,
+			[RuleKind::AcceptFilesByGlob, RuleKind::AcceptFilesByRegex],
+		)
+		.collect::<Vec<_>>();
+		if !accept_rules.is_empty() {
 			for accept_rule in accept_rules {
 				// It's ok to unwrap here, accept rules are infallible
 				if accept_rule.apply(&current_path).await.unwrap() {
@@ -242,7 +524,7 @@ async fn inner_walk_single_dir(
 			}
 			if !accept_by_glob {
 				trace!(
-					"Path {} reject because it didn't passed in any AcceptFilesByGlob rules",
+					"Path {} reject because it didn't passed in any AcceptFilesByGlob or AcceptFilesByRegex rules",
 					current_path.display()
 				);
 				continue 'entries;
@@ -253,88 +535,119 @@ async fn inner_walk_single_dir(
 		}
 
 		if accept_by_glob && (accept_by_children_dir.is_none() || accept_by_children_dir.unwrap()) {
-			indexed_paths.insert(
-				current_path.clone(),
-				WalkEntry {
-					path: current_path.clone(),
-					is_dir,
-					created_at: metadata.created()?.into(),
-				},
-			);
-
-			// If the ancestors directories wasn't indexed before, now we do
+			// If the ancestors directories wasn't indexed before, now we do, before the current path
+			let mut missing_ancestors = vec![];
 			for ancestor in current_path
 				.ancestors()
-				.skip(1) // Skip the current directory as it was already indexed
+				.skip(1) // Skip the current directory as it's indexed below
 				.take_while(|&ancestor| ancestor != root)
 			{
-				trace!("Indexing ancestor {}", ancestor.display());
-				if !indexed_paths.contains_key(ancestor) {
-					indexed_paths.insert(
-						ancestor.to_path_buf(),
-						WalkEntry {
-							path: ancestor.to_path_buf(),
-							is_dir: true,
-							created_at: fs::metadata(ancestor).await?.created()?.into(),
-						},
-					);
-				} else {
-					// If indexed_paths contains the current ancestors, then it will contain
-					// also all if its ancestors too, so we can stop here
+				if walked.contains_dir(ancestor) {
+					// If the current ancestor was indexed, then all of its ancestors
+					// were too, so we can stop here
 					break;
 				}
+				missing_ancestors.push(ancestor);
 			}
+
+			for ancestor in missing_ancestors.into_iter().rev() {
+				trace!("Indexing ancestor {}", ancestor.display());
+				let metadata = fs::metadata(ancestor).await?;
+				walked
+					.push(WalkEntry {
+						path: ancestor.to_path_buf(),
+						is_dir: true,
+						created_at: metadata.created()?.into(),
+						inode: file_inode(&metadata),
+						symlink_target: None,
+						hidden: is_hidden(ancestor, &metadata),
+					})
+					.await;
+			}
+
+			walked
+				.push(WalkEntry {
+					path: current_path.clone(),
+					is_dir,
+					created_at: metadata.created()?.into(),
+					inode: file_inode(&metadata),
+					symlink_target,
+					hidden: is_hidden(&current_path, &metadata),
+				})
+				.await;
 		}
 	}
 
 	Ok(())
 }
 
-async fn prepared_indexed_paths(
-	root: PathBuf,
-	indexed_paths: HashMap<PathBuf, WalkEntry>,
-	include_root: bool,
-) -> Result<Vec<WalkEntry>, IndexerError> {
-	let mut indexed_paths = indexed_paths.into_values().collect::<Vec<_>>();
-
-	if include_root {
-		// Also adding the root location path
-		let root_created_at = fs::metadata(&root).await?.created()?.into();
-		indexed_paths.push(WalkEntry {
-			path: root,
-			is_dir: true,
-			created_at: root_created_at,
-		});
+/// followed_symlink returns the metadata of the target of a symlink, or none if it's broken or
+/// it's a directory holding the symlink, which would be walked into forever.
+async fn followed_symlink(path: &Path) -> Option<std::fs::Metadata> {
+	let target = match fs::canonicalize(path).await {
+		Ok(target) => target,
+		Err(e) => {
+			trace!("Skipping broken symlink {}: {e}", path.display());
+			return None;
+		}
+	};
+
+	let metadata = fs::metadata(&target).await.ok()?;
+
+	if metadata.is_dir() {
+		if let Some(parent) = path.parent() {
+			if let Ok(real_parent) = fs::canonicalize(parent).await {
+				if real_parent.starts_with(&target) {
+					warn!(
+						"Skipping symlink {} as it loops back to {}",
+						path.display(),
+						target.display()
+					);
+					return None;
+				}
+			}
+		}
 	}
 
-	// Sorting so we can give each path a crescent id given the filesystem hierarchy
-	indexed_paths.sort();
-
-	Ok(indexed_paths)
+	Some(metadata)
 }
 
 pub(super) async fn walk_single_dir(
+	location_path: impl AsRef<Path>,
 	root: impl AsRef<Path>,
 	rules_per_kind: &HashMap<RuleKind, Vec<IndexerRule>>,
 	update_notifier: impl Fn(&Path, usize),
+	limits: WalkLimits,
+	symlink_policy: SymlinkPolicy,
 ) -> Result<Vec<WalkEntry>, IndexerError> {
 	let root = root.as_ref().to_path_buf();
 
+	// A single directory is small enough to be kept whole, so its entries aren't sent anywhere
+	let mut walked = WalkedPaths::new(None);
+	if !limits.walks_into(&root, &root) {
+		return Ok(walked.batch);
+	}
+
 	let mut read_dir = fs::read_dir(&root).await?;
-	let mut indexed_paths = HashMap::new();
 
 	inner_walk_single_dir(
+		location_path.as_ref(),
 		&root,
 		(root.clone(), None),
 		&mut read_dir,
 		rules_per_kind,
 		&update_notifier,
-		&mut indexed_paths,
+		&mut walked,
 		None,
+		limits,
+		symlink_policy,
 	)
 	.await?;
 
-	prepared_indexed_paths(root, indexed_paths, false).await
+	// Sorting so we can give each path a crescent id given the filesystem hierarchy
+	walked.batch.sort();
+
+	Ok(walked.batch)
 }
 
 #[cfg(test)]
@@ -344,12 +657,92 @@ mod tests {
 	use chrono::Utc;
 	use globset::Glob;
 	use std::collections::BTreeSet;
-	use tempfile::{tempdir, TempDir};
+	use tempfile::{tempdir, Builder, TempDir};
 	use tokio::fs;
 	use tracing_test::traced_test;
 
+	/// walk_all receives every batch sent by the walk while it goes on, returning all their entries.
+	async fn walk_all(
+		root: &Path,
+		rules_per_kind: &HashMap<RuleKind, Vec<IndexerRule>>,
+		include_root: bool,
+		limits: WalkLimits,
+	) -> Vec<WalkEntry> {
+		walk_all_with_policy(
+			root,
+			rules_per_kind,
+			include_root,
+			limits,
+			SymlinkPolicy::Skip,
+		)
+		.await
+	}
+
+	async fn walk_all_with_policy(
+		root: &Path,
+		rules_per_kind: &HashMap<RuleKind, Vec<IndexerRule>>,
+		include_root: bool,
+		limits: WalkLimits,
+		symlink_policy: SymlinkPolicy,
+	) -> Vec<WalkEntry> {
+		let (batches_tx, mut batches_rx) = mpsc::channel(1);
+
+		let (count, entries) = tokio::join!(
+			walk(
+				root,
+				root,
+				rules_per_kind,
+				|_, _| {},
+				include_root,
+				limits,
+				symlink_policy,
+				batches_tx
+			),
+			async {
+				let mut entries = vec![];
+				while let Some(batch) = batches_rx.recv().await {
+					entries.extend(batch);
+				}
+				entries
+			}
+		);
+
+		assert_eq!(count.unwrap(), entries.len());
+
+		// Directories are always sent before their contents
+		let mut sent = HashSet::new();
+		for entry in &entries {
+			if let Some(parent) = entry.path.parent().filter(|&parent| parent != root) {
+				assert!(
+					sent.contains(parent),
+					"{} sent before its parent",
+					entry.path.display()
+				);
+			}
+			sent.insert(entry.path.as_path());
+		}
+
+		entries
+	}
+
+	/// assert_same_metadata checks the fields of the entries which aren't compared by their equality.
+	fn assert_same_metadata(actual: &BTreeSet<WalkEntry>, expected: &BTreeSet<WalkEntry>) {
+		for (actual, expected) in actual.iter().zip(expected) {
+			let path = actual.path.display();
+			assert_eq!(actual.is_dir, expected.is_dir, "is_dir of {path}");
+			assert_eq!(actual.hidden, expected.hidden, "hidden of {path}");
+			assert_eq!(
+				actual.symlink_target, expected.symlink_target,
+				"symlink_target of {path}"
+			);
+			// Inodes are only known on unix
+			assert_eq!(actual.inode.is_some(), cfg!(unix), "inode of {path}");
+		}
+	}
+
 	async fn prepare_location() -> TempDir {
-		let root = tempdir().unwrap();
+		// Temporary directories are dotfiles by default, which would make the location root hidden
+		let root = Builder::new().prefix("location").tempdir().unwrap();
 		let root_path = root.path();
 		let rust_project = root_path.join("rust_project");
 		let inner_project = root_path.join("inner");
@@ -414,40 +807,40 @@ mod tests {
 
 		#[rustfmt::skip]
 		let expected = [
-			WalkEntry { path: root_path.to_path_buf(), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/.git"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/Cargo.toml"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/src"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/src/main.rs"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/target"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/target/debug"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/target/debug/main"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/.git"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/package.json"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/src"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/src/App.tsx"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/node_modules"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/node_modules/react"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/node_modules/react/package.json"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("photos"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("photos/photo1.png"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("photos/photo2.jpg"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("photos/photo3.jpeg"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("photos/text.txt"), is_dir: false, created_at: any_datetime },
+			WalkEntry { path: root_path.to_path_buf(), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/.git"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: true },
+			WalkEntry { path: root_path.join("rust_project/Cargo.toml"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/src"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/src/main.rs"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/target"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/target/debug"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/target/debug/main"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/.git"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: true },
+			WalkEntry { path: root_path.join("inner/node_project/package.json"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/src"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/src/App.tsx"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/node_modules"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/node_modules/react"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/node_modules/react/package.json"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("photos"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("photos/photo1.png"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("photos/photo2.jpg"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("photos/photo3.jpeg"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("photos/text.txt"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
 		]
 		.into_iter()
 		.collect::<BTreeSet<_>>();
 
-		let actual = walk(root_path.to_path_buf(), &HashMap::new(), |_, _| {}, true)
+		let actual = walk_all(root_path, &HashMap::new(), true, WalkLimits::default())
 			.await
-			.unwrap()
 			.into_iter()
 			.collect::<BTreeSet<_>>();
 
 		assert_eq!(actual, expected);
+		assert_same_metadata(&actual, &expected);
 	}
 
 	#[tokio::test]
@@ -460,11 +853,11 @@ mod tests {
 
 		#[rustfmt::skip]
 		let expected = [
-			WalkEntry { path: root_path.to_path_buf(), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("photos"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("photos/photo1.png"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("photos/photo2.jpg"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("photos/photo3.jpeg"), is_dir: false, created_at: any_datetime },
+			WalkEntry { path: root_path.to_path_buf(), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("photos"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("photos/photo1.png"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("photos/photo2.jpg"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("photos/photo3.jpeg"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
 		]
 		.into_iter()
 		.collect::<BTreeSet<_>>();
@@ -480,13 +873,13 @@ mod tests {
 		.into_iter()
 		.collect::<HashMap<_, _>>();
 
-		let actual = walk(root_path.to_path_buf(), &only_photos_rule, |_, _| {}, true)
+		let actual = walk_all(root_path, &only_photos_rule, true, WalkLimits::default())
 			.await
-			.unwrap()
 			.into_iter()
 			.collect::<BTreeSet<_>>();
 
 		assert_eq!(actual, expected);
+		assert_same_metadata(&actual, &expected);
 	}
 
 	#[tokio::test]
@@ -499,24 +892,24 @@ mod tests {
 
 		#[rustfmt::skip]
 		let expected = [
-			WalkEntry { path: root_path.to_path_buf(), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/.git"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/Cargo.toml"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/src"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/src/main.rs"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/target"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/target/debug"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/target/debug/main"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/.git"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/package.json"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/src"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/src/App.tsx"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/node_modules"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/node_modules/react"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/node_modules/react/package.json"), is_dir: false, created_at: any_datetime },
+			WalkEntry { path: root_path.to_path_buf(), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/.git"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: true },
+			WalkEntry { path: root_path.join("rust_project/Cargo.toml"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/src"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/src/main.rs"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/target"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/target/debug"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/target/debug/main"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/.git"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: true },
+			WalkEntry { path: root_path.join("inner/node_project/package.json"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/src"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/src/App.tsx"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/node_modules"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/node_modules/react"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/node_modules/react/package.json"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
 		]
 		.into_iter()
 		.collect::<BTreeSet<_>>();
@@ -534,13 +927,13 @@ mod tests {
 		.into_iter()
 		.collect::<HashMap<_, _>>();
 
-		let actual = walk(root_path.to_path_buf(), &git_repos, |_, _| {}, true)
+		let actual = walk_all(root_path, &git_repos, true, WalkLimits::default())
 			.await
-			.unwrap()
 			.into_iter()
 			.collect::<BTreeSet<_>>();
 
 		assert_eq!(actual, expected);
+		assert_same_metadata(&actual, &expected);
 	}
 
 	#[tokio::test]
@@ -553,18 +946,18 @@ mod tests {
 
 		#[rustfmt::skip]
 		let expected = [
-			WalkEntry { path: root_path.to_path_buf(), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/.git"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/Cargo.toml"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/src"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("rust_project/src/main.rs"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/.git"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/package.json"), is_dir: false, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/src"), is_dir: true, created_at: any_datetime },
-			WalkEntry { path: root_path.join("inner/node_project/src/App.tsx"), is_dir: false, created_at: any_datetime },
+			WalkEntry { path: root_path.to_path_buf(), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/.git"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: true },
+			WalkEntry { path: root_path.join("rust_project/Cargo.toml"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/src"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project/src/main.rs"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/.git"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: true },
+			WalkEntry { path: root_path.join("inner/node_project/package.json"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/src"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner/node_project/src/App.tsx"), is_dir: false, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
 		]
 		.into_iter()
 		.collect::<BTreeSet<_>>();
@@ -603,14 +996,178 @@ mod tests {
 		.into_iter()
 		.collect::<HashMap<_, _>>();
 
-		let actual = walk(
-			root_path.to_path_buf(),
+		let actual = walk_all(
+			root_path,
 			&git_repos_no_deps_no_build_dirs,
-			|_, _| {},
 			true,
+			WalkLimits::default(),
 		)
 		.await
-		.unwrap()
+		.into_iter()
+		.collect::<BTreeSet<_>>();
+
+		assert_eq!(actual, expected);
+		assert_same_metadata(&actual, &expected);
+	}
+
+	#[tokio::test]
+	#[traced_test]
+	async fn test_walk_with_limits() {
+		let root = prepare_location().await;
+		let root_path = root.path();
+
+		let any_datetime = Utc::now();
+
+		#[rustfmt::skip]
+		let expected = [
+			WalkEntry { path: root_path.to_path_buf(), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("rust_project"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("inner"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+			WalkEntry { path: root_path.join("photos"), is_dir: true, created_at: any_datetime, inode: None, symlink_target: None, hidden: false },
+		]
+		.into_iter()
+		.collect::<BTreeSet<_>>();
+
+		let shallow = WalkLimits {
+			max_depth: Some(1),
+			max_entries: None,
+		};
+
+		let actual = walk_all(root_path, &HashMap::new(), true, shallow)
+			.await
+			.into_iter()
+			.collect::<BTreeSet<_>>();
+
+		assert_eq!(actual, expected);
+		assert_same_metadata(&actual, &expected);
+
+		let budget = WalkLimits {
+			max_depth: None,
+			max_entries: Some(5),
+		};
+
+		let actual = walk_all(root_path, &HashMap::new(), false, budget).await;
+
+		assert_eq!(actual.len(), 5);
+	}
+
+	#[cfg(unix)]
+	#[tokio::test]
+	async fn test_symlink_policies() {
+		let root = tempdir().unwrap();
+		let root_path = root.path();
+
+		let data = tempdir().unwrap();
+		fs::File::create(data.path().join("file.txt"))
+			.await
+			.unwrap();
+		fs::symlink(data.path(), root_path.join("linked_dir"))
+			.await
+			.unwrap();
+		fs::symlink(root_path, root_path.join("loop"))
+			.await
+			.unwrap();
+		fs::symlink(root_path.join("missing"), root_path.join("broken"))
+			.await
+			.unwrap();
+
+		let no_rules = HashMap::new();
+		let walk_with = |symlink_policy| {
+			walk_single_dir(
+				root_path,
+				root_path,
+				&no_rules,
+				|_, _| {},
+				WalkLimits::default(),
+				symlink_policy,
+			)
+		};
+
+		assert!(walk_with(SymlinkPolicy::Skip).await.unwrap().is_empty());
+
+		let followed = walk_with(SymlinkPolicy::Follow).await.unwrap();
+		assert_eq!(followed.len(), 1);
+		assert_eq!(followed[0].path, root_path.join("linked_dir"));
+		assert!(followed[0].is_dir);
+		assert_eq!(followed[0].symlink_target, None);
+
+		let links = walk_with(SymlinkPolicy::IndexAsLink).await.unwrap();
+		assert_eq!(links.len(), 3);
+		assert!(links.iter().all(|entry| !entry.is_dir));
+		assert_eq!(links[1].path, root_path.join("linked_dir"));
+		assert_eq!(links[1].symlink_target.as_deref(), Some(data.path()));
+	}
+
+	#[cfg(unix)]
+	#[tokio::test]
+	async fn test_followed_symlinks_walked_once() {
+		let root = tempdir().unwrap();
+		let root_path = root.path();
+
+		// Each directory links to the other, which loops without going through an ancestor of the links
+		fs::create_dir(root_path.join("a")).await.unwrap();
+		fs::create_dir(root_path.join("b")).await.unwrap();
+		fs::File::create(root_path.join("b/file.txt"))
+			.await
+			.unwrap();
+		fs::symlink(root_path.join("b"), root_path.join("a/to_b"))
+			.await
+			.unwrap();
+		fs::symlink(root_path.join("a"), root_path.join("b/to_a"))
+			.await
+			.unwrap();
+
+		let actual = walk_all_with_policy(
+			root_path,
+			&HashMap::new(),
+			false,
+			WalkLimits::default(),
+			SymlinkPolicy::Follow,
+		)
+		.await
+		.into_iter()
+		.map(|entry| entry.path)
+		.collect::<BTreeSet<_>>();
+
+		let expected = [
+			root_path.join("a"),
+			root_path.join("b"),
+			root_path.join("b/file.txt"),
+		]
+		.into_iter()
+		.collect::<BTreeSet<_>>();
+
+		assert_eq!(actual, expected);
+	}
+
+	#[tokio::test]
+	async fn test_only_location_trash_skipped() {
+		let root = tempdir().unwrap();
+		let root_path = root.path();
+
+		fs::create_dir(root_path.join(TRASH_DIR_NAME))
+			.await
+			.unwrap();
+		fs::File::create(root_path.join(TRASH_DIR_NAME).join("trashed.txt"))
+			.await
+			.unwrap();
+		let nested_trash = root_path.join("docs").join(TRASH_DIR_NAME);
+		fs::create_dir_all(&nested_trash).await.unwrap();
+		fs::File::create(nested_trash.join("kept.txt"))
+			.await
+			.unwrap();
+
+		let actual = walk_all(root_path, &HashMap::new(), false, WalkLimits::default())
+			.await
+			.into_iter()
+			.map(|entry| entry.path)
+			.collect::<BTreeSet<_>>();
+
+		let expected = [
+			root_path.join("docs"),
+			nested_trash.clone(),
+			nested_trash.join("kept.txt"),
+		]
 		.into_iter()
 		.collect::<BTreeSet<_>>();
 
@@ -1,4 +1,4 @@
-use crate::{library::Library, prisma::location};
+use crate::{library::Library, location::follow_volume, prisma::location};
 
 use std::{
 	collections::{HashMap, HashSet},
@@ -102,7 +102,7 @@ pub(super) fn drop_location(
 }
 
 pub(super) async fn get_location(location_id: i32, library: &Library) -> Option<location::Data> {
-	library
+	let location = library
 		.db
 		.location()
 		.find_unique(location::id::equals(location_id))
@@ -111,7 +111,16 @@ pub(super) async fn get_location(location_id: i32, library: &Library) -> Option<
 		.unwrap_or_else(|err| {
 			error!("Failed to get location data from location_id: {:#?}", err);
 			None
-		})
+		})?;
+
+	// Locations bound to a volume follow it when it's mounted somewhere else
+	match follow_volume(library, location.clone()).await {
+		Ok(location) => Some(location),
+		Err(e) => {
+			error!("Failed to follow volume of location {location_id}: {e:#?}");
+			Some(location)
+		}
+	}
 }
 
 pub(super) async fn handle_remove_location_request(
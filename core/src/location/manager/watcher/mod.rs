@@ -2,6 +2,7 @@ use crate::{
 	library::Library,
 	location::{find_location, location_with_indexer_rules, LocationId},
 	prisma::location,
+	util::metrics::METRICS,
 };
 
 use std::{
@@ -27,7 +28,7 @@ mod windows;
 
 mod utils;
 
-use utils::check_event;
+use utils::{check_event, is_in_trash, reindex_directory};
 
 #[cfg(target_os = "linux")]
 type Handler = linux::LinuxEventHandler;
@@ -165,6 +166,8 @@ impl LocationWatcher {
 		library: &Library,
 		ignore_paths: &HashSet<PathBuf>,
 	) -> Result<(), LocationManagerError> {
+		METRICS.record_watcher_event();
+
 		if !check_event(&event, ignore_paths) {
 			return Ok(());
 		}
@@ -175,14 +178,30 @@ impl LocationWatcher {
 			.await?
 		else {
 			warn!("Tried to handle event for unknown location: <id='{location_id}'>");
-            return Ok(());
-        };
+			return Ok(());
+		};
+
+		// The trash of the location is managed by the library, so changes inside it are ignored
+		if event
+			.paths
+			.iter()
+			.any(|path| is_in_trash(&location.path, path))
+		{
+			return Ok(());
+		}
 
 		if !library.location_manager().is_online(&location.pub_id).await {
 			warn!("Tried to handle event for offline location: <id='{location_id}'>");
 			return Ok(());
 		}
 
+		// Events were dropped by the OS, so the changes they were about can only be found by indexing again
+		if event.need_rescan() {
+			warn!("Location watcher missed events, reindexing location: <id='{location_id}'>");
+			reindex_directory(&location, &location.path, library).await;
+			return Ok(());
+		}
+
 		event_handler.handle_event(location, library, event).await
 	}
 
@@ -1,22 +1,38 @@
 use crate::{
+	api::locations::invalidate_explorer_directory,
 	invalidate_query,
-	library::Library,
+	job::Job,
+	library::{Library, TRASH_DIR_NAME},
 	location::{
+		archive::{
+			archive_job::{ArchiveIndexerJob, ArchiveIndexerJobInit},
+			ArchiveKind,
+		},
 		delete_directory,
 		file_path_helper::{
 			extract_materialized_path, file_path_with_object, get_existing_file_or_directory,
-			get_existing_file_path_with_object, get_parent_dir, MaterializedPath,
+			get_existing_file_path_with_object, get_parent_dir, parent_materialized_path,
+			MaterializedPath,
 		},
+		find_location,
+		indexer::is_hidden,
 		location_with_indexer_rules,
 		manager::LocationManagerError,
 	},
 	object::{
 		file_identifier::FileMetadata,
+		fs::scan_target_tree,
 		object_just_id_has_thumbnail,
 		preview::{
-			can_generate_thumbnail_for_image, generate_image_thumbnail, THUMBNAIL_CACHE_DIR_NAME,
+			can_generate_thumbnail_for_audio, can_generate_thumbnail_for_document,
+			can_generate_thumbnail_for_font, can_generate_thumbnail_for_image,
+			create_thumbnail_dirs, generate_audio_thumbnail, generate_document_thumbnail,
+			generate_font_thumbnail, generate_image_thumbnail, thumbnail_path, thumbnail_specs,
+			THUMBNAIL_CACHE_DIR_NAME,
 		},
+		search::{forget_deleted_objects, reindex_object_content},
 		validation::hash::file_checksum,
+		version::{is_versioned, snapshot_file_version},
 	},
 	prisma::{file_path, object},
 };
@@ -31,9 +47,12 @@ use chrono::{DateTime, FixedOffset, Local, Utc};
 use int_enum::IntEnum;
 use notify::{event::RemoveKind, Event};
 use prisma_client_rust::{raw, PrismaValue};
-use sd_file_ext::extensions::ImageExtension;
+use sd_file_ext::{
+	extensions::{AudioExtension, DocumentExtension, FontExtension, ImageExtension},
+	kind::ObjectKind,
+};
 use tokio::{fs, io::ErrorKind};
-use tracing::{error, info, trace, warn};
+use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
 pub(super) fn check_event(event: &Event, ignore_paths: &HashSet<PathBuf>) -> bool {
@@ -47,6 +66,16 @@ pub(super) fn check_event(event: &Event, ignore_paths: &HashSet<PathBuf>) -> boo
 	})
 }
 
+/// is_in_trash checks if a path is inside the trash at the root of its location.
+/// Directories with the same name anywhere else in the location aren't the trash.
+pub(super) fn is_in_trash(location_path: impl AsRef<Path>, path: impl AsRef<Path>) -> bool {
+	path.as_ref()
+		.strip_prefix(location_path)
+		.ok()
+		.and_then(|relative| relative.components().next())
+		.map_or(false, |component| component.as_os_str() == TRASH_DIR_NAME)
+}
+
 pub(super) async fn create_dir(
 	location: &location_with_indexer_rules::Data,
 	event: &Event,
@@ -74,14 +103,27 @@ pub(super) async fn create_dir(
         return Ok(())
 	};
 
+	let hidden = is_hidden(
+		&event.paths[0],
+		&fs::symlink_metadata(&event.paths[0]).await?,
+	);
+
 	let created_path = library
 		.last_file_path_id_manager
-		.create_file_path(&library.db, materialized_path, Some(parent_directory.id))
+		.create_file_path(
+			&library.db,
+			materialized_path,
+			Some(parent_directory.id),
+			hidden,
+		)
 		.await?;
 
 	info!("Created path: {}", created_path.materialized_path);
 
-	invalidate_query!(library, "locations.getExplorerData");
+	invalidate_explorer_directory(library, location.id, &parent_directory.materialized_path);
+
+	// A directory moved or copied into the location already has contents, which don't trigger events
+	reindex_directory(location, &event.paths[0], library).await;
 
 	Ok(())
 }
@@ -114,9 +156,16 @@ pub(super) async fn create_file(
         return Ok(())
     };
 
+	let hidden = is_hidden(full_path, &fs::symlink_metadata(full_path).await?);
+
 	let created_file = library
 		.last_file_path_id_manager
-		.create_file_path(&library.db, materialized_path, Some(parent_directory.id))
+		.create_file_path(
+			&library.db,
+			materialized_path,
+			Some(parent_directory.id),
+			hidden,
+		)
 		.await?;
 
 	info!("Created path: {}", created_file.materialized_path);
@@ -126,7 +175,15 @@ pub(super) async fn create_file(
 		cas_id,
 		kind,
 		fs_metadata,
-	} = FileMetadata::new(&location.path, &created_file.materialized_path).await?;
+		perceptual_hash,
+		..
+	} = FileMetadata::new(
+		&location.path,
+		&created_file.materialized_path,
+		library.config.cas_id_algorithm,
+		library.config.perceptual_hashing,
+	)
+	.await?;
 
 	let existing_object = db
 		.object()
@@ -162,6 +219,7 @@ pub(super) async fn create_file(
 					),
 					object::kind::set(kind.int_value()),
 					object::size_in_bytes::set(size_str.clone()),
+					object::perceptual_hash::set(perceptual_hash),
 				],
 			)
 			.select(object_just_id_has_thumbnail::select())
@@ -172,7 +230,11 @@ pub(super) async fn create_file(
 	db.file_path()
 		.update(
 			file_path::location_id_id(location.id, created_file.id),
-			vec![file_path::object_id::set(Some(object.id))],
+			vec![
+				file_path::object_id::set(Some(object.id)),
+				file_path::cas_id::set(Some(cas_id.clone())),
+				file_path::cas_id_algorithm::set(Some(library.config.cas_id_algorithm.int_value())),
+			],
 		)
 		.exec()
 		.await?;
@@ -182,7 +244,9 @@ pub(super) async fn create_file(
 		generate_thumbnail(&created_file.extension, &cas_id, &event.paths[0], library).await;
 	}
 
-	invalidate_query!(library, "locations.getExplorerData");
+	snapshot_if_versioned(location, object.id, &event.paths[0], library).await;
+
+	invalidate_explorer_directory(library, location.id, &parent_directory.materialized_path);
 
 	Ok(())
 }
@@ -218,7 +282,11 @@ pub(super) async fn update_file(
 		.await?
 		{
 			let ret = inner_update_file(location, file_path, event, library).await;
-			invalidate_query!(library, "locations.getExplorerData");
+			invalidate_explorer_directory(
+				library,
+				location.id,
+				&parent_materialized_path(&file_path.materialized_path),
+			);
 			ret
 		} else {
 			Err(LocationManagerError::UpdateNonExistingFile(
@@ -246,7 +314,13 @@ async fn inner_update_file(
 		cas_id,
 		fs_metadata,
 		..
-	} = FileMetadata::new(&location.path, &file_path.materialized_path).await?;
+	} = FileMetadata::new(
+		&location.path,
+		&file_path.materialized_path,
+		library.config.cas_id_algorithm,
+		false,
+	)
+	.await?;
 
 	if let Some(old_cas_id) = &file_path.cas_id {
 		if old_cas_id != &cas_id {
@@ -257,7 +331,10 @@ async fn inner_update_file(
 				.update(
 					file_path::location_id_id(location.id, file_path.id),
 					vec![
-						file_path::cas_id::set(Some(old_cas_id.clone())),
+						file_path::cas_id::set(Some(cas_id.clone())),
+						file_path::cas_id_algorithm::set(Some(
+							library.config.cas_id_algorithm.int_value(),
+						)),
 						// file_path::size_in_bytes::set(fs_metadata.len().to_string()),
 						// file_path::kind::set(kind.int_value()),
 						file_path::date_modified::set(
@@ -288,10 +365,32 @@ async fn inner_update_file(
 						.await;
 				}
 			}
+
+			if let Some(object_id) = file_path.object_id {
+				snapshot_if_versioned(location, object_id, &event.paths[0], library).await;
+			}
+
+			reindex_archive_if_indexed(location, file_path, &event.paths[0], library).await;
+
+			if let Some(object) = &file_path.object {
+				reindex_object_content(
+					library,
+					object.id,
+					ObjectKind::from_int(object.kind).unwrap_or(ObjectKind::Unknown),
+					&event.paths[0],
+					&file_path.name,
+					&file_path.extension,
+				)
+				.await;
+			}
 		}
 	}
 
-	invalidate_query!(library, "locations.getExplorerData");
+	invalidate_explorer_directory(
+		library,
+		location.id,
+		&parent_materialized_path(&file_path.materialized_path),
+	);
 
 	Ok(())
 }
@@ -325,6 +424,12 @@ pub(super) async fn rename(
 
 	if let Some(file_path) = get_existing_file_or_directory(location, old_path, &library.db).await?
 	{
+		// A rename can hide or unhide the path, like adding or removing the leading dot of its name
+		let hidden = is_hidden(
+			new_path.as_ref(),
+			&fs::symlink_metadata(new_path.as_ref()).await?,
+		);
+
 		// If the renamed path is a directory, we have to update every successor
 		if file_path.is_dir {
 			if !old_path_materialized.ends_with('/') {
@@ -355,7 +460,7 @@ pub(super) async fn rename(
 			.update(
 				file_path::location_id_id(file_path.location_id, file_path.id),
 				vec![
-					file_path::materialized_path::set(new_path_materialized_str),
+					file_path::materialized_path::set(new_path_materialized_str.clone()),
 					file_path::name::set(
 						new_path_materialized
 							.file_stem()
@@ -374,16 +479,71 @@ pub(super) async fn rename(
 							})
 							.unwrap_or_default(),
 					),
+					file_path::hidden::set(hidden),
 				],
 			)
 			.exec()
 			.await?;
-		invalidate_query!(library, "locations.getExplorerData");
+
+		// Renaming a directory changes the listings of every directory inside of it
+		if file_path.is_dir {
+			invalidate_query!(library, "locations.getExplorerData");
+		} else {
+			invalidate_explorer_directory(
+				library,
+				location.id,
+				&parent_materialized_path(&file_path.materialized_path),
+			);
+			invalidate_explorer_directory(
+				library,
+				location.id,
+				&parent_materialized_path(&new_path_materialized_str),
+			);
+		}
+	} else if let Some(parent_path) = new_path.as_ref().parent() {
+		// The path was moved in from outside of the location, so it's indexed with its new siblings
+		reindex_directory(location, parent_path, library).await;
 	}
 
 	Ok(())
 }
 
+/// reindex_directory dispatches an `IndexerJob` for a directory the watcher can't apply the changes of
+/// by itself, followed by the file identifier and thumbnailer jobs. The whole tree under the directory is
+/// indexed, as directories created or moved in come with their contents.
+/// Jobs already running for the same directory aren't dispatched again, so bursts of events index it once.
+pub(super) async fn reindex_directory(
+	location: &location_with_indexer_rules::Data,
+	path: impl AsRef<Path>,
+	library: &Library,
+) {
+	let path = path.as_ref();
+	if location.node_id != library.node_local_id {
+		return;
+	}
+
+	debug!(
+		"Location: <root_path ='{}'> reindexing directory: {}",
+		location.path,
+		path.display()
+	);
+
+	let sub_path = match path.strip_prefix(&location.path) {
+		Ok(sub_path) => sub_path,
+		Err(_) => {
+			error!(
+				"Failed to reindex directory {} outside of its location",
+				path.display()
+			);
+			return;
+		}
+	};
+
+	if let Err(e) = scan_target_tree(library, location.id, sub_path).await {
+		error!("Failed to reindex directory {}: {e:#?}", path.display());
+	}
+}
+
 pub(super) async fn remove_event(
 	location: &location_with_indexer_rules::Data,
 	event: &Event,
@@ -403,9 +563,11 @@ pub(super) async fn remove_event(
 			}
 			Err(e) if e.kind() == ErrorKind::NotFound => {
 				// if is doesn't, we can remove it safely from our db
-				if file_path.is_dir {
-					delete_directory(library, location.id, Some(file_path.materialized_path))
-						.await?;
+				// along with the indexed contents of archives, which are under them like in a directory
+				if file_path.is_dir
+					|| ArchiveKind::from_path(&file_path.materialized_path).is_some()
+				{
+					delete_directory(library, location.id, Some(file_path.id)).await?;
 				} else {
 					library
 						.db
@@ -425,18 +587,111 @@ pub(super) async fn remove_event(
 							])
 							.exec()
 							.await?;
+
+						forget_deleted_objects(library, vec![object_id]).await;
 					}
 				}
 			}
 			Err(e) => return Err(e.into()),
 		}
 
-		invalidate_query!(library, "locations.getExplorerData");
+		// The listings of the directories inside of a removed directory are gone as well
+		if file_path.is_dir {
+			invalidate_query!(library, "locations.getExplorerData");
+		} else {
+			invalidate_explorer_directory(
+				library,
+				location.id,
+				&parent_materialized_path(&file_path.materialized_path),
+			);
+		}
 	}
 
 	Ok(())
 }
 
+/// snapshot_if_versioned keeps the new contents of a file of a versioned location, or with a versioned tag.
+/// Failing to snapshot is logged, as it must not stop the watcher from handling the change.
+async fn snapshot_if_versioned(
+	location: &location_with_indexer_rules::Data,
+	object_id: i32,
+	path: impl AsRef<Path>,
+	library: &Library,
+) {
+	match is_versioned(library, location.file_versioning, object_id).await {
+		Ok(true) => {
+			if let Err(e) = snapshot_file_version(library, object_id, path).await {
+				error!("Failed to snapshot a version of object <id={object_id}>: {e:#?}");
+			}
+		}
+		Ok(false) => {}
+		Err(e) => error!("Failed to check if object <id={object_id}> is versioned: {e:#?}"),
+	}
+}
+
+/// reindex_archive_if_indexed replaces the indexed contents of a changed archive with its new ones,
+/// if its contents were indexed before. Failing to do so is logged, like for the snapshots.
+async fn reindex_archive_if_indexed(
+	location: &location_with_indexer_rules::Data,
+	file_path: &file_path_with_object::Data,
+	path: &Path,
+	library: &Library,
+) {
+	if ArchiveKind::from_path(&file_path.materialized_path).is_none() {
+		return;
+	}
+
+	let indexed = match library
+		.db
+		.file_path()
+		.count(vec![
+			file_path::location_id::equals(location.id),
+			file_path::parent_id::equals(Some(file_path.id)),
+			file_path::in_archive::equals(true),
+		])
+		.exec()
+		.await
+	{
+		Ok(count) => count > 0,
+		Err(e) => {
+			error!(
+				"Failed to check if archive {} is indexed: {e:#?}",
+				path.display()
+			);
+			return;
+		}
+	};
+	if !indexed {
+		return;
+	}
+
+	let location_data = match find_location(library, location.id).exec().await {
+		Ok(Some(location_data)) => location_data,
+		Ok(None) => return,
+		Err(e) => {
+			error!("Failed to fetch location <id={}>: {e:#?}", location.id);
+			return;
+		}
+	};
+
+	// The job indexes the archives of a directory, so the archive's one is given
+	let sub_path = path
+		.parent()
+		.and_then(|parent| parent.strip_prefix(&location.path).ok())
+		.filter(|sub_path| !sub_path.as_os_str().is_empty())
+		.map(Path::to_path_buf);
+
+	library
+		.spawn_job(Job::new(
+			ArchiveIndexerJobInit {
+				location: location_data,
+				sub_path,
+			},
+			ArchiveIndexerJob {},
+		))
+		.await;
+}
+
 async fn generate_thumbnail(
 	extension: &str,
 	cas_id: &str,
@@ -444,30 +699,78 @@ async fn generate_thumbnail(
 	library: &Library,
 ) {
 	let file_path = file_path.as_ref();
-	let output_path = library
+	let thumbnail_dir = library
 		.config()
 		.data_directory()
-		.join(THUMBNAIL_CACHE_DIR_NAME)
-		.join(cas_id)
-		.with_extension("webp");
-
-	if let Ok(extension) = ImageExtension::from_str(extension) {
-		if can_generate_thumbnail_for_image(&extension) {
-			if let Err(e) = generate_image_thumbnail(file_path, &output_path).await {
-				error!("Failed to image thumbnail on location manager: {e:#?}");
+		.join(THUMBNAIL_CACHE_DIR_NAME);
+	let config = library.config().get().await.thumbnail;
+
+	if let Err(e) = create_thumbnail_dirs(&thumbnail_dir, &config).await {
+		error!("Failed to create thumbnail directories on location manager: {e:#?}");
+		return;
+	}
+
+	for (variant, spec) in thumbnail_specs(&config) {
+		let output_path = thumbnail_path(&thumbnail_dir, variant, cas_id, config.format);
+
+		if let Ok(extension) = ImageExtension::from_str(extension) {
+			if can_generate_thumbnail_for_image(&extension) {
+				if let Err(e) =
+					generate_image_thumbnail(file_path, &output_path, &config, spec).await
+				{
+					error!("Failed to image thumbnail on location manager: {e:#?}");
+				}
 			}
 		}
-	}
 
-	#[cfg(feature = "ffmpeg")]
-	{
-		use crate::object::preview::{can_generate_thumbnail_for_video, generate_video_thumbnail};
-		use sd_file_ext::extensions::VideoExtension;
+		if let Ok(extension) = AudioExtension::from_str(extension) {
+			if can_generate_thumbnail_for_audio(&extension) {
+				if let Err(e) =
+					generate_audio_thumbnail(file_path, &output_path, &config, spec).await
+				{
+					// Without a cover there's no thumbnail of any size
+					debug!("No audio thumbnail generated on location manager: {e:#?}");
+					return;
+				}
+			}
+		}
+
+		if let Ok(extension) = DocumentExtension::from_str(extension) {
+			if can_generate_thumbnail_for_document(&extension) {
+				if let Err(e) =
+					generate_document_thumbnail(file_path, &output_path, &config, spec).await
+				{
+					// Without a cover or a readable first page there's no thumbnail of any size
+					debug!("No document thumbnail generated on location manager: {e:#?}");
+					return;
+				}
+			}
+		}
 
-		if let Ok(extension) = VideoExtension::from_str(extension) {
-			if can_generate_thumbnail_for_video(&extension) {
-				if let Err(e) = generate_video_thumbnail(file_path, &output_path).await {
-					error!("Failed to video thumbnail on location manager: {e:#?}");
+		if let Ok(extension) = FontExtension::from_str(extension) {
+			if can_generate_thumbnail_for_font(&extension) {
+				if let Err(e) =
+					generate_font_thumbnail(file_path, &output_path, &config, spec).await
+				{
+					error!("Failed to font thumbnail on location manager: {e:#?}");
+				}
+			}
+		}
+
+		#[cfg(feature = "ffmpeg")]
+		{
+			use crate::object::preview::{
+				can_generate_thumbnail_for_video, generate_video_thumbnail,
+			};
+			use sd_file_ext::extensions::VideoExtension;
+
+			if let Ok(extension) = VideoExtension::from_str(extension) {
+				if can_generate_thumbnail_for_video(&extension) {
+					if let Err(e) =
+						generate_video_thumbnail(file_path, &output_path, &config, spec).await
+					{
+						error!("Failed to video thumbnail on location manager: {e:#?}");
+					}
 				}
 			}
 		}
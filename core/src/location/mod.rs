@@ -1,35 +1,43 @@
 use crate::{
 	invalidate_query,
 	job::Job,
-	library::Library,
+	library::{ActivityKind, Library},
 	object::{
 		file_identifier::{
 			file_identifier_job::{FileIdentifierJob, FileIdentifierJobInit},
 			shallow_file_identifier_job::{ShallowFileIdentifierJob, ShallowFileIdentifierJobInit},
 		},
 		preview::{
+			media_data_job::{MediaDataJob, MediaDataJobInit},
 			shallow_thumbnailer_job::{ShallowThumbnailerJob, ShallowThumbnailerJobInit},
 			thumbnailer_job::{ThumbnailerJob, ThumbnailerJobInit},
 		},
+		search::forget_deleted_objects,
 	},
 	prisma::{file_path, indexer_rules_in_location, location, node, object},
 	sync,
+	volume::find_volume_by_uuid,
 };
 
+#[cfg(feature = "content-search")]
+use crate::object::search::content_indexer_job::{ContentIndexerJob, ContentIndexerJobInit};
+
 use std::{
 	collections::HashSet,
 	ffi::OsStr,
-	path::{Path, PathBuf},
+	path::{Component, Path, PathBuf},
 };
 
+use int_enum::IntEnum;
 use prisma_client_rust::QueryError;
 use rspc::Type;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tokio::{fs, io};
 use tracing::{debug, info};
 use uuid::Uuid;
 
+pub mod archive;
 mod error;
 pub mod file_path_helper;
 pub mod indexer;
@@ -37,7 +45,7 @@ mod manager;
 mod metadata;
 
 pub use error::LocationError;
-use file_path_helper::file_path_just_object_id;
+use file_path_helper::{file_path_in_subtree, file_path_just_object_id};
 use indexer::{
 	indexer_job::IndexerJob,
 	shallow_indexer_job::{ShallowIndexerJob, ShallowIndexerJobInit},
@@ -170,6 +178,114 @@ impl LocationCreateArgs {
 	}
 }
 
+/// `VolumeLocationCreateArgs` is the argument received from the client using `rspc` to create a new location
+/// bound to a volume. The location path is resolved from the volume's current mount point, and
+/// followed when the volume is mounted somewhere else, like under a new drive letter.
+#[derive(Type, Deserialize)]
+pub struct VolumeLocationCreateArgs {
+	/// volume_id is the file system UUID of the volume, see [`crate::volume::Volume::uuid`].
+	pub volume_id: String,
+	/// subpath is the path of the location relative to the root of the volume.
+	pub subpath: PathBuf,
+	pub indexer_rules_ids: Vec<i32>,
+}
+
+impl VolumeLocationCreateArgs {
+	pub async fn create(
+		self,
+		library: &Library,
+	) -> Result<location_with_indexer_rules::Data, LocationError> {
+		let volume = find_volume_by_uuid(&self.volume_id)
+			.map_err(|e| LocationError::VolumeReadError(e.to_string()))?
+			.ok_or_else(|| LocationError::VolumeNotFound(self.volume_id.clone()))?;
+
+		// Only keep plain components, so the subpath can't escape the volume
+		let subpath = self
+			.subpath
+			.components()
+			.filter(|component| matches!(component, Component::Normal(_)))
+			.collect::<PathBuf>();
+
+		let location = LocationCreateArgs {
+			path: Path::new(&volume.mount_point).join(&subpath),
+			indexer_rules_ids: self.indexer_rules_ids,
+		}
+		.create(library)
+		.await?;
+
+		// The volume binding is specific to this node, so it isn't synced
+		library
+			.db
+			.location()
+			.update(
+				location::id::equals(location.id),
+				vec![
+					location::volume_uuid::set(Some(self.volume_id)),
+					location::volume_subpath::set(Some(subpath.to_string_lossy().to_string())),
+				],
+			)
+			.exec()
+			.await?;
+
+		Ok(location)
+	}
+}
+
+/// `follow_volume` relinks a location bound to a volume when its path is gone but the volume is mounted somewhere else.
+/// It returns the location with its current path.
+pub(crate) async fn follow_volume(
+	library: &Library,
+	location: location::Data,
+) -> Result<location::Data, LocationError> {
+	let (Some(volume_uuid), Some(volume_subpath)) =
+		(&location.volume_uuid, &location.volume_subpath)
+	else {
+		return Ok(location);
+	};
+
+	if location.node_id != library.node_local_id || fs::metadata(&location.path).await.is_ok() {
+		return Ok(location);
+	}
+
+	let Some(volume) = find_volume_by_uuid(volume_uuid)
+		.map_err(|e| LocationError::VolumeReadError(e.to_string()))?
+	else {
+		// The volume isn't mounted, so the location is just offline
+		return Ok(location);
+	};
+
+	let new_path = Path::new(&volume.mount_point).join(volume_subpath);
+	if new_path == Path::new(&location.path) || fs::metadata(&new_path).await.is_err() {
+		return Ok(location);
+	}
+
+	info!(
+		"Location {} followed its volume from '{}' to '{}'",
+		location.id,
+		location.path,
+		new_path.display()
+	);
+
+	relink_location(library, &new_path).await?;
+
+	find_location(library, location.id)
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location.id))
+}
+
+/// `SymlinkPolicy` is how the indexer handles the symlinks of a location, stored in its `symlink_policy` column.
+/// Following them indexes their targets as if they were in the location, while indexing them as links keeps
+/// where they point to without reading their targets, so backup-style locations don't count the same data twice.
+#[repr(i32)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, Type, Eq, PartialEq, IntEnum)]
+pub enum SymlinkPolicy {
+	#[default]
+	Skip = 0,
+	Follow = 1,
+	IndexAsLink = 2,
+}
+
 /// `LocationUpdateArgs` is the argument received from the client using `rspc` to update a location.
 /// It contains the id of the location to be updated, possible a name to change the current location's name
 /// and a vector of indexer rules ids to add or remove from the location.
@@ -183,6 +299,8 @@ pub struct LocationUpdateArgs {
 	pub generate_preview_media: Option<bool>,
 	pub sync_preview_media: Option<bool>,
 	pub hidden: Option<bool>,
+	pub file_versioning: Option<bool>,
+	pub symlink_policy: Option<SymlinkPolicy>,
 	pub indexer_rules_ids: Vec<i32>,
 }
 
@@ -215,6 +333,18 @@ impl LocationUpdateArgs {
 			}),
 			self.hidden
 				.map(|v| (("hidden", json!(v)), location::hidden::set(v))),
+			self.file_versioning.map(|v| {
+				(
+					("file_versioning", json!(v)),
+					location::file_versioning::set(v),
+				)
+			}),
+			self.symlink_policy.map(|v| {
+				(
+					("symlink_policy", json!(v as i32)),
+					location::symlink_policy::set(v as i32),
+				)
+			}),
 		]
 		.into_iter()
 		.flatten()
@@ -325,41 +455,73 @@ pub async fn scan_location(
 		return Ok(());
 	}
 
+	let indexer = Job::new(
+		IndexerJobInit {
+			location: location.clone(),
+			sub_path: None,
+			max_depth: None,
+			max_entries: None,
+		},
+		IndexerJob {},
+	);
+	let identifier = Job::new(
+		FileIdentifierJobInit {
+			location: location::Data::from(&location),
+			sub_path: None,
+		},
+		FileIdentifierJob {},
+	)
+	.run_after(&indexer);
+
+	// Everything else works on the objects of the identified files
 	library
-		.queue_job(Job::new(
-			FileIdentifierJobInit {
-				location: location::Data::from(&location),
-				sub_path: None,
-			},
-			FileIdentifierJob {},
-		))
+		.queue_job(
+			Job::new(
+				ThumbnailerJobInit {
+					location: location::Data::from(&location),
+					sub_path: None,
+					background: true,
+					regenerate: false,
+				},
+				ThumbnailerJob {},
+			)
+			.run_after(&identifier),
+		)
 		.await;
 
 	library
-		.queue_job(Job::new(
-			ThumbnailerJobInit {
-				location: location::Data::from(&location),
-				sub_path: None,
-				background: true,
-			},
-			ThumbnailerJob {},
-		))
+		.queue_job(
+			Job::new(
+				MediaDataJobInit {
+					location_id: location.id,
+					regenerate: false,
+				},
+				MediaDataJob {},
+			)
+			.run_after(&identifier),
+		)
 		.await;
 
+	#[cfg(feature = "content-search")]
 	library
-		.spawn_job(Job::new(
-			IndexerJobInit {
-				location,
-				sub_path: None,
-			},
-			IndexerJob {},
-		))
+		.queue_job(
+			Job::new(
+				ContentIndexerJobInit {
+					location_id: Some(location.id),
+					rebuild: false,
+				},
+				ContentIndexerJob {},
+			)
+			.run_after(&identifier),
+		)
 		.await;
 
+	library.queue_job(identifier).await;
+	library.spawn_job(indexer).await;
+
 	Ok(())
 }
 
-#[allow(dead_code)]
 pub async fn scan_location_sub_path(
 	library: &Library,
 	location: location_with_indexer_rules::Data,
@@ -370,36 +532,41 @@ pub async fn scan_location_sub_path(
 		return Ok(());
 	}
 
-	library
-		.queue_job(Job::new(
-			FileIdentifierJobInit {
-				location: location::Data::from(&location),
-				sub_path: Some(sub_path.clone()),
-			},
-			FileIdentifierJob {},
-		))
-		.await;
+	let indexer = Job::new(
+		IndexerJobInit {
+			location: location.clone(),
+			sub_path: Some(sub_path.clone()),
+			max_depth: None,
+			max_entries: None,
+		},
+		IndexerJob {},
+	);
+	let identifier = Job::new(
+		FileIdentifierJobInit {
+			location: location::Data::from(&location),
+			sub_path: Some(sub_path.clone()),
+		},
+		FileIdentifierJob {},
+	)
+	.run_after(&indexer);
 
 	library
-		.queue_job(Job::new(
-			ThumbnailerJobInit {
-				location: location::Data::from(&location),
-				sub_path: Some(sub_path.clone()),
-				background: true,
-			},
-			ThumbnailerJob {},
-		))
+		.queue_job(
+			Job::new(
+				ThumbnailerJobInit {
+					location: location::Data::from(&location),
+					sub_path: Some(sub_path),
+					background: true,
+					regenerate: false,
+				},
+				ThumbnailerJob {},
+			)
+			.run_after(&identifier),
+		)
 		.await;
 
-	library
-		.spawn_job(Job::new(
-			IndexerJobInit {
-				location,
-				sub_path: Some(sub_path),
-			},
-			IndexerJob {},
-		))
-		.await;
+	library.queue_job(identifier).await;
+	library.spawn_job(indexer).await;
 
 	Ok(())
 }
@@ -414,32 +581,39 @@ pub async fn light_scan_location(
 		return Ok(());
 	}
 
-	library
-		.queue_job(Job::new(
-			ShallowFileIdentifierJobInit {
-				location: location::Data::from(&location),
-				sub_path: sub_path.clone(),
-			},
-			ShallowFileIdentifierJob {},
-		))
-		.await;
+	let indexer = Job::new(
+		ShallowIndexerJobInit {
+			location: location.clone(),
+			sub_path: sub_path.clone(),
+			max_depth: None,
+			max_entries: None,
+		},
+		ShallowIndexerJob {},
+	);
+	let identifier = Job::new(
+		ShallowFileIdentifierJobInit {
+			location: location::Data::from(&location),
+			sub_path: sub_path.clone(),
+		},
+		ShallowFileIdentifierJob {},
+	)
+	.run_after(&indexer);
 
 	library
-		.queue_job(Job::new(
-			ShallowThumbnailerJobInit {
-				location: location::Data::from(&location),
-				sub_path: sub_path.clone(),
-			},
-			ShallowThumbnailerJob {},
-		))
+		.queue_job(
+			Job::new(
+				ShallowThumbnailerJobInit {
+					location: location::Data::from(&location),
+					sub_path,
+				},
+				ShallowThumbnailerJob {},
+			)
+			.run_after(&identifier),
+		)
 		.await;
 
-	library
-		.spawn_job(Job::new(
-			ShallowIndexerJobInit { location, sub_path },
-			ShallowIndexerJob {},
-		))
-		.await;
+	library.queue_job(identifier).await;
+	library.spawn_job(indexer).await;
 
 	Ok(())
 }
@@ -530,6 +704,14 @@ async fn create_location(
 
 	debug!("created in db");
 
+	library
+		.record_activity(
+			ActivityKind::LocationAdded,
+			format!("Added location '{}'", location.path),
+			Some(json!({ "location_id": location.id, "path": location.path })),
+		)
+		.await;
+
 	if !indexer_rules_ids.is_empty() {
 		link_location_and_indexer_rules(library, location.id, indexer_rules_ids).await?;
 	}
@@ -578,6 +760,13 @@ pub async fn delete_location(library: &Library, location_id: i32) -> Result<(),
 	}
 
 	info!("Location {} deleted", location_id);
+	library
+		.record_activity(
+			ActivityKind::LocationRemoved,
+			format!("Removed location '{}'", location.path),
+			Some(json!({ "location_id": location_id, "path": location.path })),
+		)
+		.await;
 	invalidate_query!(library, "locations.list");
 
 	Ok(())
@@ -588,12 +777,12 @@ pub async fn delete_location(library: &Library, location_id: i32) -> Result<(),
 pub async fn delete_directory(
 	library: &Library,
 	location_id: i32,
-	parent_materialized_path: Option<String>,
+	parent_directory_id: Option<i32>,
 ) -> Result<(), QueryError> {
-	let children_params = if let Some(parent_materialized_path) = parent_materialized_path {
+	let children_params = if let Some(parent_directory_id) = parent_directory_id {
 		vec![
 			file_path::location_id::equals(location_id),
-			file_path::materialized_path::starts_with(parent_materialized_path),
+			file_path_in_subtree(location_id, parent_directory_id),
 		]
 	} else {
 		vec![file_path::location_id::equals(location_id)]
@@ -609,7 +798,7 @@ pub async fn delete_directory(
 		.await?
 		.into_iter()
 		.filter_map(|file_path| file_path.object_id)
-		.collect();
+		.collect::<Vec<_>>();
 
 	// WARNING: file_paths must be deleted before objects, as they reference objects through object_id
 	// delete all children file_paths
@@ -625,13 +814,15 @@ pub async fn delete_directory(
 		.db
 		.object()
 		.delete_many(vec![
-			object::id::in_vec(object_ids),
+			object::id::in_vec(object_ids.clone()),
 			// https://www.prisma.io/docs/reference/api-reference/prisma-client-reference#none
 			object::file_paths::none(vec![]),
 		])
 		.exec()
 		.await?;
 
+	forget_deleted_objects(library, object_ids).await;
+
 	invalidate_query!(library, "locations.getExplorerData");
 
 	Ok(())
@@ -651,7 +842,11 @@ impl From<location_with_indexer_rules::Data> for location::Data {
 			generate_preview_media: data.generate_preview_media,
 			sync_preview_media: data.sync_preview_media,
 			hidden: data.hidden,
+			file_versioning: data.file_versioning,
+			symlink_policy: data.symlink_policy,
 			date_created: data.date_created,
+			volume_uuid: data.volume_uuid,
+			volume_subpath: data.volume_subpath,
 			node: None,
 			file_paths: None,
 			indexer_rules: None,
@@ -673,7 +868,11 @@ impl From<&location_with_indexer_rules::Data> for location::Data {
 			generate_preview_media: data.generate_preview_media,
 			sync_preview_media: data.sync_preview_media,
 			hidden: data.hidden,
+			file_versioning: data.file_versioning,
+			symlink_policy: data.symlink_policy,
 			date_created: data.date_created,
+			volume_uuid: data.volume_uuid.clone(),
+			volume_subpath: data.volume_subpath.clone(),
 			node: None,
 			file_paths: None,
 			indexer_rules: None,
@@ -0,0 +1,240 @@
+use crate::{
+	job::JobError,
+	library::LibraryManager,
+	util::db::{estimated_count, CountedTable},
+};
+
+use std::{collections::BTreeMap, path::Path, sync::Arc, time::Duration};
+
+use once_cell::sync::Lazy;
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, sync::Mutex, time::interval};
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+use super::{NodeConfigError, NodeConfigManager};
+
+/// TELEMETRY_PENDING_NAME is the name of the file in the data directory which batches what will be sent in the next report.
+pub const TELEMETRY_PENDING_NAME: &str = "telemetry_pending.json";
+/// How often the pending report is sent
+const TELEMETRY_REPORT_INTERVAL: Duration = Duration::from_secs(60 * 60 * 24);
+const TELEMETRY_REPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Serializes the updates of the pending file
+static PENDING_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// TelemetryConfig controls the anonymous telemetry of the node, which is off by default.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, Type)]
+pub struct TelemetryConfig {
+	pub enabled: bool,
+	/// endpoint receives the reports as a JSON `POST`. Reports stay pending on the node until it is set.
+	pub endpoint: Option<String>,
+}
+
+/// PendingTelemetry is what has been collected since the last report was sent.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct PendingTelemetry {
+	anonymous_id: Uuid,
+	job_failures: BTreeMap<String, u32>,
+}
+
+impl PendingTelemetry {
+	fn new() -> Self {
+		Self {
+			anonymous_id: Uuid::new_v4(),
+			job_failures: BTreeMap::new(),
+		}
+	}
+}
+
+/// TelemetryReport is everything sent about a node, which never includes names, paths or identifiers of the node or its libraries.
+#[derive(Serialize, Deserialize, Debug, Clone, Type)]
+pub struct TelemetryReport {
+	/// anonymous_id is random, and is replaced every time telemetry is enabled again.
+	pub anonymous_id: Uuid,
+	pub version: String,
+	pub platform: String,
+	pub arch: String,
+	/// library_sizes are the number of objects of every library, as a bucket like "1k-10k".
+	pub library_sizes: Vec<String>,
+	/// job_failures counts the failed jobs by job name and error category, like "indexer:IOError".
+	pub job_failures: BTreeMap<String, u32>,
+}
+
+fn library_size_bucket(object_count: i64) -> &'static str {
+	match object_count {
+		..=999 => "<1k",
+		1_000..=9_999 => "1k-10k",
+		10_000..=99_999 => "10k-100k",
+		100_000..=999_999 => "100k-1M",
+		_ => ">1M",
+	}
+}
+
+/// error_category is the name of the error variant, which leaves out its details as they can contain paths.
+fn error_category(error: &JobError) -> String {
+	format!("{error:?}")
+		.split(|c: char| !c.is_alphanumeric())
+		.next()
+		.unwrap_or_default()
+		.to_string()
+}
+
+async fn read_pending(data_dir: &Path) -> PendingTelemetry {
+	match fs::read(data_dir.join(TELEMETRY_PENDING_NAME)).await {
+		Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+			warn!("Discarding invalid pending telemetry: {e:#?}");
+			PendingTelemetry::new()
+		}),
+		Err(_) => PendingTelemetry::new(),
+	}
+}
+
+async fn write_pending(data_dir: &Path, pending: &PendingTelemetry) {
+	let result = match serde_json::to_vec(pending) {
+		Ok(bytes) => fs::write(data_dir.join(TELEMETRY_PENDING_NAME), bytes)
+			.await
+			.map_err(|e| e.to_string()),
+		Err(e) => Err(e.to_string()),
+	};
+
+	if let Err(e) = result {
+		error!("Failed to save pending telemetry: {e}");
+	}
+}
+
+/// record_job_failure adds a failed job to the pending report, if telemetry is enabled.
+pub(crate) async fn record_job_failure(
+	config: &NodeConfigManager,
+	job_name: &str,
+	error: &JobError,
+) {
+	if !config.get().await.telemetry.enabled {
+		return;
+	}
+
+	let data_dir = config.data_directory();
+	let _guard = PENDING_LOCK.lock().await;
+	let mut pending = read_pending(&data_dir).await;
+	*pending
+		.job_failures
+		.entry(format!("{job_name}:{}", error_category(error)))
+		.or_default() += 1;
+	write_pending(&data_dir, &pending).await;
+}
+
+/// pending_report returns the report which would be sent now, so users can see exactly what is shared.
+pub async fn pending_report(
+	library_manager: &LibraryManager,
+	config: &NodeConfigManager,
+) -> TelemetryReport {
+	let pending = {
+		let _guard = PENDING_LOCK.lock().await;
+		read_pending(&config.data_directory()).await
+	};
+
+	let mut library_sizes = vec![];
+	for library in library_manager.get_all_libraries().await {
+		match estimated_count(&library.db, CountedTable::Object).await {
+			Ok(count) => library_sizes.push(library_size_bucket(count).to_string()),
+			Err(e) => error!("Failed to count the objects of a library: {e:#?}"),
+		}
+	}
+
+	TelemetryReport {
+		anonymous_id: pending.anonymous_id,
+		version: env!("CARGO_PKG_VERSION").to_string(),
+		platform: std::env::consts::OS.to_string(),
+		arch: std::env::consts::ARCH.to_string(),
+		library_sizes,
+		job_failures: pending.job_failures,
+	}
+}
+
+/// set_telemetry changes the telemetry config. Disabling it discards everything pending,
+/// and enabling it again starts over with a new anonymous id.
+pub async fn set_telemetry(
+	config: &NodeConfigManager,
+	telemetry: TelemetryConfig,
+) -> Result<(), NodeConfigError> {
+	let was_enabled = config.get().await.telemetry.enabled;
+
+	config
+		.write(|mut config| config.telemetry = telemetry.clone())
+		.await?;
+
+	if was_enabled != telemetry.enabled {
+		clear_pending(config).await;
+	}
+
+	Ok(())
+}
+
+/// clear_pending discards everything collected since the last report.
+pub async fn clear_pending(config: &NodeConfigManager) {
+	let _guard = PENDING_LOCK.lock().await;
+	if let Err(e) = fs::remove_file(config.data_directory().join(TELEMETRY_PENDING_NAME)).await {
+		if e.kind() != std::io::ErrorKind::NotFound {
+			error!("Failed to discard pending telemetry: {e:#?}");
+		}
+	}
+}
+
+/// send_report sends the pending report to the configured endpoint, then starts a new batch.
+pub async fn send_report(library_manager: &LibraryManager, config: &NodeConfigManager) {
+	let TelemetryConfig {
+		enabled: true,
+		endpoint: Some(endpoint),
+	} = config.get().await.telemetry
+	else {
+		return;
+	};
+
+	let report = pending_report(library_manager, config).await;
+
+	let result = reqwest::Client::new()
+		.post(&endpoint)
+		.timeout(TELEMETRY_REPORT_TIMEOUT)
+		.json(&report)
+		.send()
+		.await
+		.and_then(|response| response.error_for_status());
+
+	match result {
+		Ok(_) => {
+			debug!("Sent telemetry report");
+
+			// Failures recorded while sending are kept for the next report
+			let _guard = PENDING_LOCK.lock().await;
+			let data_dir = config.data_directory();
+			let mut pending = read_pending(&data_dir).await;
+			for (key, count) in report.job_failures {
+				if let Some(current) = pending.job_failures.get_mut(&key) {
+					*current = current.saturating_sub(count);
+				}
+			}
+			pending.job_failures.retain(|_, count| *count > 0);
+			write_pending(&data_dir, &pending).await;
+		}
+		// Reports are kept locally until they can be sent
+		Err(e) => warn!("Failed to send telemetry report: {e:#?}"),
+	}
+}
+
+/// spawn_telemetry_reporter periodically sends the pending report, when telemetry is enabled.
+pub fn spawn_telemetry_reporter(
+	library_manager: Arc<LibraryManager>,
+	config: Arc<NodeConfigManager>,
+) {
+	tokio::spawn(async move {
+		let mut interval = interval(TELEMETRY_REPORT_INTERVAL);
+		// The first tick completes immediately, and we don't want to report on every start
+		interval.tick().await;
+
+		loop {
+			interval.tick().await;
+			send_report(&library_manager, &config).await;
+		}
+	});
+}
@@ -1,16 +1,28 @@
 use rspc::Type;
 use sd_p2p::Keypair;
 use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
 use std::{
+	collections::HashMap,
 	fs::File,
 	io::{self, BufReader, Seek, Write},
 	path::{Path, PathBuf},
 	sync::Arc,
+	time::SystemTime,
 };
 use thiserror::Error;
-use tokio::sync::{RwLock, RwLockWriteGuard};
+use tokio::{
+	fs,
+	sync::{broadcast, RwLock, RwLockWriteGuard},
+	time::{interval, Duration},
+};
+use tracing::{info, warn};
 use uuid::Uuid;
 
+use crate::{job::JobPriority, object::preview::PREVIEW_STRIP_VARIANT};
+
+use super::{OtlpConfig, TelemetryConfig, UpdateConfig};
+
 /// NODE_STATE_CONFIG_NAME is the name of the file which stores the NodeState
 pub const NODE_STATE_CONFIG_NAME: &str = "node_state.sdconfig";
 
@@ -31,6 +43,7 @@ impl Default for ConfigMetadata {
 }
 
 /// NodeConfig is the configuration for a node. This is shared between all libraries and is stored in a JSON file on disk.
+#[serde_as]
 #[derive(Debug, Serialize, Deserialize, Clone, Type)]
 pub struct NodeConfig {
 	#[serde(flatten)]
@@ -48,6 +61,271 @@ pub struct NodeConfig {
 	// TODO: These will probs be replaced by your Spacedrive account in the near future.
 	pub p2p_email: Option<String>,
 	pub p2p_img_url: Option<String>,
+	/// otlp enables exporting tracing spans to an OpenTelemetry collector, for profiling the node end to end.
+	/// It needs the node to be built with the `otel` feature.
+	pub otlp: Option<OtlpConfig>,
+	/// log_level is added to the default log filter, like "debug" or "sd_core::location=trace". It is applied without a restart.
+	pub log_level: Option<String>,
+	/// max_running_jobs is how many jobs of the normal class, like full scans and the jobs started by the user,
+	/// can run at the same time. It is applied without a restart.
+	pub max_running_jobs: Option<u32>,
+	/// max_interactive_jobs is how many interactive jobs, like the shallow scan of the directory being browsed,
+	/// can run at the same time. It is applied without a restart.
+	#[serde(default)]
+	pub max_interactive_jobs: Option<u32>,
+	/// max_background_jobs is how many housekeeping jobs, like backups and cache cleanups,
+	/// can run at the same time. It is applied without a restart.
+	#[serde(default)]
+	pub max_background_jobs: Option<u32>,
+	/// thumbnail configures how thumbnails are generated. It is applied without a restart.
+	#[serde(default)]
+	pub thumbnail: ThumbnailConfig,
+	/// resources limits what jobs can use, so the node behaves on low-end devices as well as on big servers. It is applied on restart.
+	#[serde(default)]
+	pub resources: ResourceLimits,
+	/// throttling overrides how jobs are slowed down on battery and under thermal pressure. It is applied without a restart.
+	#[serde(default)]
+	pub throttling: ThrottlingConfig,
+	/// job_read_limits is how many bytes per second the jobs of a type, like "file_identifier" or "file_copier", can read
+	/// from disk, all together, so they don't saturate slow drives. Jobs of types without a limit, or with a limit of 0, read as fast as they can.
+	/// It is applied when jobs start.
+	#[specta(type = HashMap<String, String>)]
+	#[serde_as(as = "HashMap<_, DisplayFromStr>")]
+	#[serde(default)]
+	pub job_read_limits: HashMap<String, u64>,
+	/// job_webhooks receive the jobs which finish or fail, for nodes running without a UI. It is applied without a restart.
+	#[serde(default)]
+	pub job_webhooks: JobWebhookConfig,
+	/// telemetry configures the anonymous telemetry, which is off unless the user opts in.
+	#[serde(default)]
+	pub telemetry: TelemetryConfig,
+	/// updates configures the channel and endpoint used to check for new releases.
+	#[serde(default)]
+	pub updates: UpdateConfig,
+	/// database overrides the SQLite settings of library databases. It is applied when libraries are opened.
+	#[serde(default)]
+	pub database: DatabaseConfig,
+}
+
+/// ThumbnailConfig configures how thumbnails are generated.
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Type)]
+pub struct ThumbnailConfig {
+	/// size_factor is the size of image thumbnails relative to the original image.
+	pub size_factor: f32,
+	/// quality is the quality of thumbnails, from 0 to 100.
+	pub quality: f32,
+	/// format is the image format thumbnails are written with. Existing thumbnails are converted on the next thumbnailer run.
+	#[serde(default)]
+	pub format: ThumbnailFormat,
+	/// workers is how many images and videos are decoded at the same time, on threads dedicated to thumbnails.
+	/// Defaults to the worker threads of the resource limits. Unlike the rest of the config, it is applied on restart.
+	#[serde(default)]
+	pub workers: Option<u32>,
+	/// worker_memory_bytes is the most memory a worker can allocate to decode an image, bigger images get no thumbnail.
+	/// Defaults to 512MiB.
+	#[specta(type = Option<String>)]
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	#[serde(default)]
+	pub worker_memory_bytes: Option<u64>,
+	/// variants are extra sizes of thumbnails, like a bigger one for quick previews, generated with the default ones.
+	/// Changing the sizes or qualities of thumbnails regenerates them on the next thumbnailer run.
+	#[serde(default)]
+	pub variants: Vec<ThumbnailVariant>,
+	/// video_preview_strips generates the preview strips of videos, used to scrub through them from the explorer,
+	/// along with their thumbnails. Otherwise strips are generated the first time they're requested.
+	#[serde(default)]
+	pub video_preview_strips: bool,
+	/// animated_thumbnails makes the thumbnails of GIFs and short videos short looping animations, when they aren't too heavy.
+	/// Animations are only written as WebP, so AVIF thumbnails stay still.
+	#[serde(default)]
+	pub animated_thumbnails: bool,
+	/// cache_limit_bytes is the most disk space thumbnails can use. Past it, the least recently used ones are removed
+	/// by the thumbnail cleanup, which runs every few hours. Unlimited by default.
+	#[specta(type = Option<String>)]
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	#[serde(default)]
+	pub cache_limit_bytes: Option<u64>,
+	/// concurrency is how many files each thumbnailer job processes at the same time. Defaults to the number of workers.
+	#[serde(default)]
+	pub concurrency: Option<u32>,
+	/// battery_throttle lets thumbnailer jobs be throttled while the device runs on battery, processing fewer files at a time
+	/// and pausing between them. They are throttled under thermal pressure regardless. The thumbnails of the files shown in the explorer are still generated right away. Enabled by default.
+	#[serde(default = "default_battery_throttle")]
+	pub battery_throttle: bool,
+}
+
+/// ThumbnailVariant is an extra size of thumbnails, written to a directory of its own in the thumbnails directory.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Type)]
+pub struct ThumbnailVariant {
+	/// name is the directory of the variant, like `preview`. It can only hold ASCII letters, digits, `-` and `_`.
+	pub name: String,
+	/// size is the length in pixels of the longest edge of thumbnails. Images smaller than it aren't upscaled.
+	pub size: u32,
+	/// quality is the quality of thumbnails, from 0 to 100. Defaults to the quality of the default thumbnails.
+	#[serde(default)]
+	pub quality: Option<f32>,
+}
+
+impl ThumbnailVariant {
+	/// has_valid_name tells if the name of the variant can be used as a directory name and in thumbnail URLs.
+	/// The directory of the preview strips of videos can't be reused.
+	pub fn has_valid_name(&self) -> bool {
+		is_valid_variant_name(&self.name) && self.name != PREVIEW_STRIP_VARIANT
+	}
+}
+
+/// is_valid_variant_name tells if `name` can name a thumbnail variant.
+pub fn is_valid_variant_name(name: &str) -> bool {
+	!name.is_empty()
+		&& name
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// ThumbnailFormat is the image format of thumbnails.
+/// AVIF makes smaller files than WebP but is slower to encode.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Type)]
+pub enum ThumbnailFormat {
+	#[default]
+	Webp,
+	Avif,
+}
+
+impl ThumbnailFormat {
+	pub const ALL: [ThumbnailFormat; 2] = [ThumbnailFormat::Webp, ThumbnailFormat::Avif];
+
+	pub fn extension(&self) -> &'static str {
+		match self {
+			ThumbnailFormat::Webp => "webp",
+			ThumbnailFormat::Avif => "avif",
+		}
+	}
+
+	pub fn mime_type(&self) -> &'static str {
+		match self {
+			ThumbnailFormat::Webp => "image/webp",
+			ThumbnailFormat::Avif => "image/avif",
+		}
+	}
+}
+
+/// ResourceLimits caps the resources used by jobs. Every limit defaults to a value fit for the current device when unset.
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, Type)]
+pub struct ResourceLimits {
+	/// worker_threads is how many threads jobs can keep busy at the same time with CPU heavy work, like generating thumbnails.
+	/// Defaults to the number of CPU cores.
+	pub worker_threads: Option<u32>,
+	/// max_open_files is how many files jobs can have open at the same time.
+	pub max_open_files: Option<u32>,
+	/// cache_memory_bytes is how much memory in-memory caches can use.
+	#[specta(type = Option<String>)]
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	#[serde(default)]
+	pub cache_memory_bytes: Option<u64>,
+}
+
+/// ThrottlingMode tells when jobs are throttled.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Type)]
+pub enum ThrottlingMode {
+	/// Throttles jobs while the device runs on battery or is under thermal pressure
+	#[default]
+	Auto,
+	/// Always throttles jobs, like on battery
+	Always,
+	/// Never throttles jobs
+	Never,
+}
+
+/// ThrottlingConfig overrides how CPU and disk heavy jobs, like the identifier and the thumbnailer, are slowed down
+/// to spare the battery and the temperature of the device. Every setting defaults to a value fit for laptops when unset.
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Type)]
+pub struct ThrottlingConfig {
+	#[serde(default)]
+	pub mode: ThrottlingMode,
+	/// ignore_battery keeps jobs running at full speed on battery, they are still throttled under thermal pressure.
+	#[serde(default)]
+	pub ignore_battery: bool,
+	/// thermal_threshold_celsius is the temperature of a sensor from which jobs are throttled. Defaults to 85°C,
+	/// sensors reporting a lower critical temperature throttle jobs 5°C below it.
+	pub thermal_threshold_celsius: Option<f32>,
+	/// parallelism_factor is the share of their usual parallelism jobs keep while throttled, from 0 to 1.
+	/// Defaults to 0.25. Under thermal pressure jobs process one file at a time whatever the factor.
+	pub parallelism_factor: Option<f32>,
+	/// max_read_bytes_per_second is how many bytes throttled jobs can read per second, all together. Defaults to 32MiB, 0 doesn't limit them.
+	#[specta(type = Option<String>)]
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	#[serde(default)]
+	pub max_read_bytes_per_second: Option<u64>,
+}
+
+/// JobWebhookConfig sends the jobs of every library which finish or fail to webhooks.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, Type)]
+pub struct JobWebhookConfig {
+	/// urls will receive a JSON `POST` of each job which fails, or completes after running for `min_seconds`.
+	#[serde(default)]
+	pub urls: Vec<String>,
+	/// min_seconds is how long a job must have run for its completion to be sent, failed jobs are always sent.
+	/// Defaults to 60 seconds, so the many short jobs of a scan aren't sent.
+	pub min_seconds: Option<u32>,
+}
+
+/// JournalMode is the SQLite journal mode of library databases.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Type)]
+pub enum JournalMode {
+	Delete,
+	Truncate,
+	Persist,
+	Wal,
+}
+
+/// Synchronous is how often SQLite waits for writes to reach the disk.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Type)]
+pub enum Synchronous {
+	Off,
+	Normal,
+	Full,
+}
+
+/// DatabaseConfig overrides the `PRAGMA` settings of library databases. Every setting has a default tuned for indexing while the UI reads.
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize, Clone, Default, Type)]
+pub struct DatabaseConfig {
+	/// journal_mode defaults to `Wal`, so reads don't wait for writes.
+	pub journal_mode: Option<JournalMode>,
+	/// synchronous defaults to `Normal`, which is safe with the WAL journal.
+	pub synchronous: Option<Synchronous>,
+	/// mmap_size is how many bytes of the database are memory mapped. Defaults to 256MiB.
+	#[specta(type = Option<String>)]
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	#[serde(default)]
+	pub mmap_size: Option<u64>,
+	/// cache_size_kib is how many KiB of pages SQLite keeps in memory for every connection to the library. Defaults to 64MiB.
+	pub cache_size_kib: Option<u32>,
+}
+
+impl Default for ThumbnailConfig {
+	fn default() -> Self {
+		Self {
+			size_factor: 0.2,
+			quality: 30.0,
+			format: ThumbnailFormat::default(),
+			workers: None,
+			worker_memory_bytes: None,
+			variants: vec![],
+			video_preview_strips: false,
+			animated_thumbnails: false,
+			cache_limit_bytes: None,
+			concurrency: None,
+			battery_throttle: default_battery_throttle(),
+		}
+	}
+}
+
+fn default_battery_throttle() -> bool {
+	true
 }
 
 // TODO: Probs remove this in future. It's just to prevent breaking changes.
@@ -84,21 +362,113 @@ impl NodeConfig {
 			keypair: Keypair::generate(),
 			p2p_email: None,
 			p2p_img_url: None,
+			otlp: None,
+			log_level: None,
+			max_running_jobs: None,
+			max_interactive_jobs: None,
+			max_background_jobs: None,
+			thumbnail: ThumbnailConfig::default(),
+			resources: ResourceLimits::default(),
+			throttling: ThrottlingConfig::default(),
+			job_read_limits: HashMap::new(),
+			job_webhooks: JobWebhookConfig::default(),
+			telemetry: TelemetryConfig::default(),
+			updates: UpdateConfig::default(),
+			database: DatabaseConfig::default(),
 		}
 	}
+
+	/// max_jobs returns how many jobs of the `priority` class can run at the same time, `None` being the default.
+	pub fn max_jobs(&self, priority: JobPriority) -> Option<usize> {
+		match priority {
+			JobPriority::Interactive => self.max_interactive_jobs,
+			JobPriority::Normal => self.max_running_jobs,
+			JobPriority::Background => self.max_background_jobs,
+		}
+		.map(|max| max as usize)
+	}
 }
 
-pub struct NodeConfigManager(RwLock<NodeConfig>, PathBuf);
+/// How often the config file is checked for changes made outside of Spacedrive
+const CONFIG_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct NodeConfigManager(RwLock<NodeConfig>, PathBuf, broadcast::Sender<NodeConfig>);
 
 impl NodeConfigManager {
 	/// new will create a new NodeConfigManager with the given path to the config file.
 	pub(crate) async fn new(data_path: PathBuf) -> Result<Arc<Self>, NodeConfigError> {
+		let (changes_tx, _) = broadcast::channel(16);
+
 		Ok(Arc::new(Self(
 			RwLock::new(Self::read(&data_path).await?),
 			data_path,
+			changes_tx,
 		)))
 	}
 
+	/// subscribe returns a receiver of the config every time its hot-reloadable fields are changed on disk.
+	pub(crate) fn subscribe(&self) -> broadcast::Receiver<NodeConfig> {
+		self.2.subscribe()
+	}
+
+	/// watch spawns a task which reloads the config when its file is edited.
+	/// Only the fields which are safe to change at runtime (log level, job concurrency, throttling and thumbnail settings) are applied, the others require a restart.
+	pub(crate) fn watch(self: &Arc<Self>) {
+		let this = Arc::clone(self);
+		tokio::spawn(async move {
+			let path = this.1.join(NODE_STATE_CONFIG_NAME);
+			let mut last_modified = modified_at(&path).await;
+			let mut interval = interval(CONFIG_WATCH_INTERVAL);
+
+			loop {
+				interval.tick().await;
+
+				let modified = modified_at(&path).await;
+				if modified == last_modified {
+					continue;
+				}
+				last_modified = modified;
+
+				if let Err(e) = this.reload().await {
+					warn!("Failed to reload the node config: {e:#?}");
+				}
+			}
+		});
+	}
+
+	/// reload reads the config file and applies the fields which are safe to change at runtime.
+	async fn reload(&self) -> Result<(), NodeConfigError> {
+		let path = self.1.join(NODE_STATE_CONFIG_NAME);
+		let new_config: NodeConfig = serde_json::from_slice(&fs::read(&path).await?)?;
+
+		let config = {
+			let mut config = self.0.write().await;
+
+			if config.log_level == new_config.log_level
+				&& config.max_running_jobs == new_config.max_running_jobs
+				&& config.max_interactive_jobs == new_config.max_interactive_jobs
+				&& config.max_background_jobs == new_config.max_background_jobs
+				&& config.thumbnail == new_config.thumbnail
+			{
+				// Nothing to apply, this is most likely our own write
+				return Ok(());
+			}
+
+			config.log_level = new_config.log_level;
+			config.max_running_jobs = new_config.max_running_jobs;
+			config.max_interactive_jobs = new_config.max_interactive_jobs;
+			config.max_background_jobs = new_config.max_background_jobs;
+			config.thumbnail = new_config.thumbnail;
+			config.clone()
+		};
+
+		info!("Reloaded the node config");
+		// No one listening isn't an error here
+		self.2.send(config).ok();
+
+		Ok(())
+	}
+
 	/// get will return the current NodeConfig in a read only state.
 	pub(crate) async fn get(&self) -> NodeConfig {
 		self.0.read().await.clone()
@@ -110,7 +480,6 @@ impl NodeConfigManager {
 	}
 
 	/// write allows the user to update the configuration. This is done in a closure while a Mutex lock is held so that the user can't cause a race condition if the config were to be updated in multiple parts of the app at the same time.
-	#[allow(unused)]
 	pub(crate) async fn write<F: FnOnce(RwLockWriteGuard<NodeConfig>)>(
 		&self,
 		mutation_fn: F,
@@ -164,3 +533,7 @@ impl NodeConfigManager {
 		}
 	}
 }
+
+async fn modified_at(path: &Path) -> Option<SystemTime> {
+	fs::metadata(path).await.and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,161 @@
+use std::{
+	path::{Path, PathBuf},
+	time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Utc};
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::{warn, Level};
+use tracing_appender::{
+	non_blocking::{NonBlocking, WorkerGuard},
+	rolling,
+};
+
+/// LOGS_DIR_NAME is the name of the directory inside the node data directory where logs are written.
+pub const LOGS_DIR_NAME: &str = "logs";
+const LOG_FILE_PREFIX: &str = "sd.log";
+/// How long rotated log files are kept
+const LOG_RETENTION: Duration = Duration::from_secs(60 * 60 * 24 * 7);
+/// Maximum number of entries returned by [`read_logs`]
+const MAX_LOG_ENTRIES: usize = 5000;
+
+/// log_writer returns a writer to a log file in the data directory, rotated daily.
+/// The returned guard must be kept alive for logs to be flushed.
+pub(crate) fn log_writer(data_dir: impl AsRef<Path>) -> (NonBlocking, WorkerGuard) {
+	tracing_appender::non_blocking(rolling::daily(
+		data_dir.as_ref().join(LOGS_DIR_NAME),
+		LOG_FILE_PREFIX,
+	))
+}
+
+/// remove_old_logs deletes the log files which are older than the retention period.
+pub(crate) async fn remove_old_logs(data_dir: impl AsRef<Path>) {
+	let Ok(log_files) = log_files(data_dir).await else {
+		return;
+	};
+
+	for (path, modified) in log_files {
+		let is_expired = SystemTime::now()
+			.duration_since(modified)
+			.map(|age| age > LOG_RETENTION)
+			.unwrap_or(false);
+
+		if is_expired {
+			if let Err(e) = fs::remove_file(&path).await {
+				warn!("Failed to remove old log file {}: {e:#?}", path.display());
+			}
+		}
+	}
+}
+
+/// log_files returns every log file in the logs directory, newest first.
+async fn log_files(
+	data_dir: impl AsRef<Path>,
+) -> Result<Vec<(PathBuf, SystemTime)>, std::io::Error> {
+	let mut read_dir = fs::read_dir(data_dir.as_ref().join(LOGS_DIR_NAME)).await?;
+
+	let mut files = vec![];
+	while let Some(entry) = read_dir.next_entry().await? {
+		let is_log = entry
+			.file_name()
+			.to_str()
+			.map(|name| name.starts_with(LOG_FILE_PREFIX))
+			.unwrap_or(false);
+
+		if is_log {
+			files.push((entry.path(), entry.metadata().await?.modified()?));
+		}
+	}
+
+	files.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+	Ok(files)
+}
+
+/// LogEntry is a single line of the structured logs.
+#[derive(Serialize, Deserialize, Debug, Clone, Type)]
+pub struct LogEntry {
+	pub timestamp: DateTime<Utc>,
+	pub level: String,
+	pub target: String,
+	/// fields holds the message and every other field of the event.
+	pub fields: serde_json::Value,
+}
+
+/// LogQuery filters the logs returned by [`read_logs`].
+#[derive(Deserialize, Debug, Default, Type)]
+pub struct LogQuery {
+	/// level is the least severe level to include, like "warn" to only get warnings and errors.
+	pub level: Option<String>,
+	/// target only keeps the entries whose target starts with it, like "sd_core::location".
+	pub target: Option<String>,
+	pub since: Option<DateTime<Utc>>,
+	pub until: Option<DateTime<Utc>>,
+	/// limit is how many of the most recent matching entries to return.
+	pub limit: Option<u32>,
+}
+
+impl LogQuery {
+	fn matches(&self, entry: &LogEntry, min_level: Option<Level>) -> bool {
+		// `Level` orders the most verbose levels as the greatest
+		min_level.map_or(true, |min_level| {
+			entry
+				.level
+				.parse::<Level>()
+				.map_or(false, |level| level <= min_level)
+		}) && self
+			.target
+			.as_ref()
+			.map_or(true, |target| entry.target.starts_with(target))
+			&& self.since.map_or(true, |since| entry.timestamp >= since)
+			&& self.until.map_or(true, |until| entry.timestamp <= until)
+	}
+}
+
+/// read_logs returns the most recent log entries matching the query, oldest first.
+pub async fn read_logs(
+	data_dir: impl AsRef<Path>,
+	query: LogQuery,
+) -> Result<Vec<LogEntry>, std::io::Error> {
+	let limit = query
+		.limit
+		.map_or(MAX_LOG_ENTRIES, |limit| limit as usize)
+		.min(MAX_LOG_ENTRIES);
+	let min_level = query.level.as_ref().and_then(|level| level.parse().ok());
+
+	let log_files = match log_files(&data_dir).await {
+		Ok(log_files) => log_files,
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+		Err(e) => return Err(e),
+	};
+
+	let mut entries = vec![];
+	for (path, modified) in log_files {
+		// Files are read newest first, so we can stop once they are all before the requested range
+		if let Some(since) = query.since {
+			if DateTime::<Utc>::from(modified) < since {
+				break;
+			}
+		}
+
+		let contents = fs::read_to_string(&path).await?;
+		let mut file_entries = contents
+			.lines()
+			.rev()
+			.filter_map(|line| serde_json::from_str::<LogEntry>(line).ok())
+			.filter(|entry| query.matches(entry, min_level))
+			.take(limit - entries.len())
+			.collect::<Vec<_>>();
+		entries.append(&mut file_entries);
+
+		if entries.len() >= limit {
+			break;
+		}
+	}
+
+	entries.reverse();
+
+	Ok(entries)
+}
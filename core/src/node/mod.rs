@@ -6,9 +6,19 @@ use rspc::Type;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+mod anonymous_telemetry;
 mod config;
+mod logs;
+mod telemetry;
+mod updates;
+mod users;
 
+pub use anonymous_telemetry::*;
 pub use config::*;
+pub use logs::*;
+pub use telemetry::*;
+pub use updates::*;
+pub use users::*;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
 pub struct LibraryNode {
@@ -0,0 +1,75 @@
+#[cfg(feature = "otel")]
+use opentelemetry::{
+	sdk::{trace, Resource},
+	trace::TraceError,
+	KeyValue,
+};
+#[cfg(feature = "otel")]
+use opentelemetry_otlp::WithExportConfig;
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "otel")]
+use tracing::Subscriber;
+#[cfg(feature = "otel")]
+use tracing_opentelemetry::OpenTelemetryLayer;
+#[cfg(feature = "otel")]
+use tracing_subscriber::registry::LookupSpan;
+
+#[cfg(feature = "otel")]
+const DEFAULT_SERVICE_NAME: &str = "spacedrive";
+
+/// OtlpConfig configures the export of tracing spans to an OpenTelemetry collector.
+/// Changes are applied when the node is restarted.
+#[derive(Debug, Serialize, Deserialize, Clone, Type)]
+pub struct OtlpConfig {
+	/// endpoint is the gRPC address of the collector, like "http://localhost:4317".
+	pub endpoint: String,
+	/// service_name identifies this node in the collected traces. Defaults to "spacedrive".
+	pub service_name: Option<String>,
+}
+
+/// otlp_layer returns a tracing layer which exports every span to the configured OTLP collector.
+#[cfg(feature = "otel")]
+pub(crate) fn otlp_layer<S>(
+	config: &OtlpConfig,
+) -> Result<OpenTelemetryLayer<S, trace::Tracer>, TraceError>
+where
+	S: Subscriber + for<'span> LookupSpan<'span>,
+{
+	let tracer = opentelemetry_otlp::new_pipeline()
+		.tracing()
+		.with_exporter(
+			opentelemetry_otlp::new_exporter()
+				.tonic()
+				.with_endpoint(&config.endpoint),
+		)
+		.with_trace_config(
+			trace::config().with_resource(Resource::new(vec![KeyValue::new(
+				"service.name",
+				config
+					.service_name
+					.clone()
+					.unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_string()),
+			)])),
+		)
+		.install_batch(opentelemetry::runtime::Tokio)?;
+
+	Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
+
+/// otlp_layer fails when the node is built without the `otel` feature, as spans can't be exported then.
+#[cfg(not(feature = "otel"))]
+pub(crate) fn otlp_layer(
+	_config: &OtlpConfig,
+) -> Result<tracing_subscriber::layer::Identity, &'static str> {
+	Err("the node was built without the `otel` feature")
+}
+
+/// shutdown_otlp flushes the spans which haven't been exported yet.
+#[cfg(feature = "otel")]
+pub(crate) fn shutdown_otlp() {
+	opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(not(feature = "otel"))]
+pub(crate) fn shutdown_otlp() {}
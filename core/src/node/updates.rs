@@ -0,0 +1,188 @@
+use std::{cmp::Ordering, time::Duration};
+
+use chrono::{DateTime, Utc};
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::NodeConfigManager;
+
+const UPDATE_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// UpdateChannel is the release channel the node is updated from.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Type)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateChannel {
+	#[default]
+	Stable,
+	Beta,
+}
+
+impl UpdateChannel {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Self::Stable => "stable",
+			Self::Beta => "beta",
+		}
+	}
+}
+
+/// UpdateConfig configures where the node checks for updates.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, Type)]
+pub struct UpdateConfig {
+	pub channel: UpdateChannel,
+	/// endpoint returns the latest [`ReleaseMetadata`] of a channel, which is requested as `<endpoint>?channel=<channel>&version=<current version>`.
+	pub endpoint: Option<String>,
+}
+
+/// ReleaseMetadata describes a release of Spacedrive, as returned by the update endpoint.
+#[derive(Debug, Serialize, Deserialize, Clone, Type)]
+pub struct ReleaseMetadata {
+	pub version: String,
+	pub channel: UpdateChannel,
+	/// url is where the release can be downloaded from.
+	pub url: String,
+	pub notes: Option<String>,
+	pub published_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Error, Debug)]
+pub enum UpdateError {
+	#[error("no update endpoint is set in the node config")]
+	NoEndpoint,
+	#[error("failed to fetch the latest release: {0}")]
+	Request(#[from] reqwest::Error),
+	#[error("the latest release has an invalid version: '{0}'")]
+	InvalidVersion(String),
+}
+
+impl From<UpdateError> for rspc::Error {
+	fn from(error: UpdateError) -> Self {
+		let code = match error {
+			UpdateError::NoEndpoint => rspc::ErrorCode::BadRequest,
+			_ => rspc::ErrorCode::InternalServerError,
+		};
+
+		rspc::Error::with_cause(code, error.to_string(), error)
+	}
+}
+
+/// parse_version splits a version like "0.2.0-beta.1+build.5" into its major, minor and patch numbers and its pre-release identifiers.
+/// The build metadata is dropped as it doesn't take part in the precedence.
+fn parse_version(version: &str) -> Option<([u64; 3], Vec<&str>)> {
+	let version = version.trim_start_matches('v');
+	let version = version
+		.split_once('+')
+		.map_or(version, |(version, _)| version);
+	let (numbers, pre_release) = match version.split_once('-') {
+		Some((numbers, pre_release)) => (numbers, pre_release.split('.').collect::<Vec<_>>()),
+		None => (version, vec![]),
+	};
+
+	if pre_release.iter().any(|identifier| identifier.is_empty()) {
+		return None;
+	}
+
+	let mut numbers = numbers.split('.').map(|number| number.parse().ok());
+	let parsed = [numbers.next()??, numbers.next()??, numbers.next()??];
+
+	numbers.next().is_none().then_some((parsed, pre_release))
+}
+
+/// compare_pre_release_identifiers orders two pre-release identifiers, numeric ones being compared numerically and being lower than alphanumeric ones.
+fn compare_pre_release_identifiers(a: &str, b: &str) -> Ordering {
+	match (a.parse::<u64>(), b.parse::<u64>()) {
+		(Ok(a), Ok(b)) => a.cmp(&b),
+		(Ok(_), Err(_)) => Ordering::Less,
+		(Err(_), Ok(_)) => Ordering::Greater,
+		(Err(_), Err(_)) => a.cmp(b),
+	}
+}
+
+/// compare_versions orders two versions following the semver precedence, a release being newer than any of its pre-releases.
+fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+	let (a_numbers, a_pre_release) = parse_version(a)?;
+	let (b_numbers, b_pre_release) = parse_version(b)?;
+
+	Some(a_numbers.cmp(&b_numbers).then_with(|| {
+		match (a_pre_release.is_empty(), b_pre_release.is_empty()) {
+			(true, true) => Ordering::Equal,
+			(true, false) => Ordering::Greater,
+			(false, true) => Ordering::Less,
+			(false, false) => a_pre_release
+				.iter()
+				.zip(&b_pre_release)
+				.map(|(a, b)| compare_pre_release_identifiers(a, b))
+				.find(|ordering| ordering.is_ne())
+				// When all the shared identifiers are equal, the larger set of identifiers is newer
+				.unwrap_or_else(|| a_pre_release.len().cmp(&b_pre_release.len())),
+		}
+	}))
+}
+
+/// check_for_updates fetches the latest release of the configured channel.
+/// It returns the release only when it is newer than the running version.
+pub async fn check_for_updates(
+	config: &NodeConfigManager,
+) -> Result<Option<ReleaseMetadata>, UpdateError> {
+	let UpdateConfig { channel, endpoint } = config.get().await.updates;
+	let endpoint = endpoint.ok_or(UpdateError::NoEndpoint)?;
+	let current_version = env!("CARGO_PKG_VERSION");
+
+	let release = reqwest::Client::new()
+		.get(&endpoint)
+		.query(&[("channel", channel.as_str()), ("version", current_version)])
+		.timeout(UPDATE_CHECK_TIMEOUT)
+		.send()
+		.await?
+		.error_for_status()?
+		.json::<ReleaseMetadata>()
+		.await?;
+
+	match compare_versions(&release.version, current_version) {
+		Some(Ordering::Greater) => Ok(Some(release)),
+		Some(_) => Ok(None),
+		None => Err(UpdateError::InvalidVersion(release.version)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn versions_follow_semver_precedence() {
+		let ordered = [
+			"1.0.0-alpha",
+			"1.0.0-alpha.1",
+			"1.0.0-alpha.beta",
+			"1.0.0-beta",
+			"1.0.0-beta.2",
+			"1.0.0-beta.11",
+			"1.0.0-rc.1",
+			"1.0.0",
+			"1.0.1",
+			"1.10.0",
+		];
+
+		for pair in ordered.windows(2) {
+			assert_eq!(
+				compare_versions(pair[0], pair[1]),
+				Some(Ordering::Less),
+				"{pair:?}"
+			);
+			assert_eq!(
+				compare_versions(pair[1], pair[0]),
+				Some(Ordering::Greater),
+				"{pair:?}"
+			);
+		}
+
+		assert_eq!(
+			compare_versions("v1.0.0+build.1", "1.0.0+build.2"),
+			Some(Ordering::Equal)
+		);
+		assert_eq!(compare_versions("1.0", "1.0.0"), None);
+		assert_eq!(compare_versions("1.0.0-", "1.0.0"), None);
+	}
+}
@@ -0,0 +1,240 @@
+use std::{
+	collections::HashMap,
+	fs::{self, File},
+	io::{self, BufReader, Write},
+	path::PathBuf,
+	sync::{Arc, RwLock},
+};
+
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// USERS_CONFIG_NAME is the name of the file which stores the users of the node.
+pub const USERS_CONFIG_NAME: &str = "users.sdconfig";
+
+/// LibraryRole is what a user is allowed to do in a library. Roles are ordered from the least to the most privileged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Type)]
+pub enum LibraryRole {
+	ReadOnly,
+	ReadWrite,
+	Admin,
+}
+
+/// NodeUser is an account which can access the node through a token, used when a node is shared between multiple people.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct NodeUser {
+	pub id: Uuid,
+	pub name: String,
+	/// node_admin users can manage the users and libraries of the node, and are admins of every library.
+	pub node_admin: bool,
+	/// libraries holds the role of this user in each library it can access.
+	pub libraries: HashMap<Uuid, LibraryRole>,
+}
+
+impl NodeUser {
+	/// role returns the role of this user in the given library, if it can access it.
+	pub fn role(&self, library_id: Uuid) -> Option<LibraryRole> {
+		if self.node_admin {
+			Some(LibraryRole::Admin)
+		} else {
+			self.libraries.get(&library_id).copied()
+		}
+	}
+}
+
+/// StoredUser is a user as it's stored on disk, which is the only place its token hash is kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredUser {
+	#[serde(flatten)]
+	user: NodeUser,
+	token_hash: String,
+}
+
+#[derive(Error, Debug)]
+pub enum UserError {
+	#[error("error saving or loading the users from the filesystem")]
+	IO(#[from] io::Error),
+	#[error("error serializing or deserializing the JSON in the users file")]
+	Json(#[from] serde_json::Error),
+	#[error("user '{0}' not found")]
+	NotFound(Uuid),
+}
+
+impl From<UserError> for rspc::Error {
+	fn from(error: UserError) -> Self {
+		let code = match error {
+			UserError::NotFound(_) => rspc::ErrorCode::NotFound,
+			_ => rspc::ErrorCode::InternalServerError,
+		};
+
+		rspc::Error::with_cause(code, error.to_string(), error)
+	}
+}
+
+fn hash_token(token: &str) -> String {
+	blake3::hash(token.as_bytes()).to_hex().to_string()
+}
+
+fn generate_token() -> String {
+	format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// UserManager holds the users of the node. When there are none, the node is single-user and every client has full access.
+pub struct UserManager(RwLock<Vec<StoredUser>>, PathBuf);
+
+impl UserManager {
+	/// new will load the users stored in the given data directory.
+	pub(crate) fn new(data_path: PathBuf) -> Result<Arc<Self>, UserError> {
+		let path = data_path.join(USERS_CONFIG_NAME);
+		let users = match File::open(path) {
+			Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+			Err(e) => return Err(e.into()),
+		};
+
+		Ok(Arc::new(Self(RwLock::new(users), data_path)))
+	}
+
+	/// is_enabled returns whether the node has any users, in which case every client must authenticate as one of them.
+	pub fn is_enabled(&self) -> bool {
+		!self.0.read().unwrap_or_else(|e| e.into_inner()).is_empty()
+	}
+
+	/// authenticate returns the user owning the given token.
+	pub fn authenticate(&self, token: &str) -> Option<NodeUser> {
+		let token_hash = hash_token(token);
+
+		self.0
+			.read()
+			.unwrap_or_else(|e| e.into_inner())
+			.iter()
+			.find(|stored| stored.token_hash == token_hash)
+			.map(|stored| stored.user.clone())
+	}
+
+	pub fn list(&self) -> Vec<NodeUser> {
+		self.0
+			.read()
+			.unwrap_or_else(|e| e.into_inner())
+			.iter()
+			.map(|stored| stored.user.clone())
+			.collect()
+	}
+
+	/// create adds a new user and returns it with its token, which can't be retrieved afterwards.
+	pub fn create(&self, name: String, node_admin: bool) -> Result<(NodeUser, String), UserError> {
+		let token = generate_token();
+		let user = NodeUser {
+			id: Uuid::new_v4(),
+			name,
+			node_admin,
+			libraries: HashMap::new(),
+		};
+
+		self.write(|users| {
+			users.push(StoredUser {
+				user: user.clone(),
+				token_hash: hash_token(&token),
+			});
+			Ok(())
+		})?;
+
+		Ok((user, token))
+	}
+
+	pub fn delete(&self, id: Uuid) -> Result<(), UserError> {
+		self.write(|users| {
+			let len = users.len();
+			users.retain(|stored| stored.user.id != id);
+
+			if users.len() == len {
+				Err(UserError::NotFound(id))
+			} else {
+				Ok(())
+			}
+		})
+	}
+
+	/// set_library_role changes the role of a user in a library, `None` revokes its access.
+	pub fn set_library_role(
+		&self,
+		id: Uuid,
+		library_id: Uuid,
+		role: Option<LibraryRole>,
+	) -> Result<NodeUser, UserError> {
+		self.update(id, |user| {
+			match role {
+				Some(role) => user.libraries.insert(library_id, role),
+				None => user.libraries.remove(&library_id),
+			};
+		})
+	}
+
+	/// remove_library revokes the access of every user to a library, used when the library is deleted.
+	pub fn remove_library(&self, library_id: Uuid) -> Result<(), UserError> {
+		self.write(|users| {
+			for stored in users.iter_mut() {
+				stored.user.libraries.remove(&library_id);
+			}
+			Ok(())
+		})
+	}
+
+	/// regenerate_token replaces the token of a user, returning the new one.
+	pub fn regenerate_token(&self, id: Uuid) -> Result<String, UserError> {
+		let token = generate_token();
+		let token_hash = hash_token(&token);
+
+		self.write(|users| {
+			let stored = users
+				.iter_mut()
+				.find(|stored| stored.user.id == id)
+				.ok_or(UserError::NotFound(id))?;
+			stored.token_hash = token_hash;
+			Ok(())
+		})?;
+
+		Ok(token)
+	}
+
+	fn update(
+		&self,
+		id: Uuid,
+		update_fn: impl FnOnce(&mut NodeUser),
+	) -> Result<NodeUser, UserError> {
+		let mut updated = None;
+
+		self.write(|users| {
+			let stored = users
+				.iter_mut()
+				.find(|stored| stored.user.id == id)
+				.ok_or(UserError::NotFound(id))?;
+			update_fn(&mut stored.user);
+			updated = Some(stored.user.clone());
+			Ok(())
+		})?;
+
+		updated.ok_or(UserError::NotFound(id))
+	}
+
+	/// write applies the given mutation while holding the lock and saves the users back to disk.
+	fn write(
+		&self,
+		mutation_fn: impl FnOnce(&mut Vec<StoredUser>) -> Result<(), UserError>,
+	) -> Result<(), UserError> {
+		let mut users = self.0.write().unwrap_or_else(|e| e.into_inner());
+		mutation_fn(&mut users)?;
+
+		// Writing to a temporary file and renaming it over the old one means a crash mid-write can't leave a truncated file behind
+		let path = self.1.join(USERS_CONFIG_NAME);
+		let temp_path = path.with_extension("sdconfig.tmp");
+		let mut file = File::create(&temp_path)?;
+		file.write_all(serde_json::to_string(&*users)?.as_bytes())?;
+		file.sync_all()?;
+		fs::rename(&temp_path, &path)?;
+
+		Ok(())
+	}
+}
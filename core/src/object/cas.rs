@@ -1,49 +1,144 @@
+use crate::{
+	object::validation::hash::{file_checksum, file_sha256},
+	util::{io::read_ranges, rate_limit::limit_read},
+};
+
 use blake3::Hasher;
-use std::path::Path;
-use tokio::{
+use int_enum::IntEnum;
+use memmap2::Mmap;
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use std::{
 	fs::File,
-	io::{self, AsyncReadExt, AsyncSeekExt, SeekFrom},
+	path::{Path, PathBuf},
+	time::Duration,
 };
+use tokio::{io, task::spawn_blocking};
 
 static SAMPLE_COUNT: u64 = 4;
 static SAMPLE_SIZE: u64 = 10000;
+/// Files of at least this size are hashed through a memory map instead of buffered reads
+pub const MMAP_HASHING_THRESHOLD: u64 = 64 * 1024 * 1024;
+/// Files modified this recently may still be written, so they aren't hashed through a memory map
+const MMAP_QUIET_PERIOD: Duration = Duration::from_secs(30);
+
+/// CasIdAlgorithm is how the cas_id of a file is generated. It's stored alongside every cas_id, so the values
+/// must never change. The full file digests match the output of `b3sum` and `sha256sum` respectively.
+#[repr(i32)]
+#[derive(
+	Debug, Clone, Copy, Default, Serialize, Deserialize, Type, Eq, PartialEq, Hash, IntEnum,
+)]
+pub enum CasIdAlgorithm {
+	/// Blake3 of the size and a few samples of the file, fast but only identifies the file within Spacedrive
+	#[default]
+	Sampled = 0,
+	Blake3 = 1,
+	Sha256 = 2,
+}
+
+/// mmap_hash_ranges hashes the ranges of a file through a memory map, asking the kernel to prefetch
+/// every range before they're hashed so the page faults of the samples overlap.
+/// Returns `None` when the file looks like it's being written, as reading a mapped page the file was truncated
+/// from kills the process with SIGBUS, so it must be read with buffered reads instead.
+fn mmap_hash_ranges(
+	path: PathBuf,
+	size: u64,
+	ranges: Vec<(u64, u64)>,
+	mut hasher: Hasher,
+) -> Result<Option<Hasher>, io::Error> {
+	let file = File::open(path)?;
+	if is_being_written(&file, size)? {
+		return Ok(None);
+	}
+
+	// SAFETY: The file wasn't modified for a while and the size of the file is checked again before every range
+	// is read, so only a truncation racing with the read of a range can make it fail
+	let mmap = unsafe { Mmap::map(&file)? };
+
+	#[cfg(unix)]
+	for &(offset, length) in &ranges {
+		mmap.advise_range(memmap2::Advice::WillNeed, offset as usize, length as usize)?;
+	}
+
+	for (offset, length) in ranges {
+		if file.metadata()?.len() < offset + length {
+			return Err(io::ErrorKind::UnexpectedEof.into());
+		}
+
+		let (start, end) = (offset as usize, (offset + length) as usize);
+		hasher.update(mmap.get(start..end).ok_or(io::ErrorKind::UnexpectedEof)?);
+	}
 
-async fn read_at(file: &mut File, offset: u64, size: u64) -> Result<Vec<u8>, io::Error> {
-	let mut buf = vec![0u8; size as usize];
+	Ok(Some(hasher))
+}
 
-	file.seek(SeekFrom::Start(offset)).await?;
-	file.read_exact(&mut buf).await?;
+/// is_being_written tells if a file changed size since it was indexed, or was modified in the last
+/// [`MMAP_QUIET_PERIOD`], which is how files still being downloaded or copied look like.
+fn is_being_written(file: &File, size: u64) -> Result<bool, io::Error> {
+	let metadata = file.metadata()?;
 
-	Ok(buf)
+	Ok(metadata.len() != size
+		|| metadata
+			.modified()?
+			.elapsed()
+			.map_or(true, |elapsed| elapsed < MMAP_QUIET_PERIOD))
 }
 
-pub async fn generate_cas_id(path: impl AsRef<Path>, size: u64) -> Result<String, io::Error> {
-	// open file reference
-	let mut file = File::open(path).await?;
+pub async fn generate_cas_id(
+	path: impl AsRef<Path>,
+	size: u64,
+	algorithm: CasIdAlgorithm,
+) -> Result<String, io::Error> {
+	match algorithm {
+		CasIdAlgorithm::Sampled => sampled_cas_id(path, size).await,
+		CasIdAlgorithm::Blake3 => file_checksum(path).await,
+		CasIdAlgorithm::Sha256 => file_sha256(path).await,
+	}
+}
 
+async fn sampled_cas_id(path: impl AsRef<Path>, size: u64) -> Result<String, io::Error> {
 	let mut hasher = Hasher::new();
 
 	// include the file size in the checksum
 	hasher.update(&size.to_le_bytes());
 
 	// if size is small enough, just read the whole thing
-
-	if SAMPLE_COUNT * SAMPLE_SIZE > size {
-		let buf = read_at(&mut file, 0, size).await?;
-		hasher.update(&buf);
+	let ranges = if SAMPLE_COUNT * SAMPLE_SIZE > size {
+		vec![(0, size)]
 	} else {
-		// loop over samples
-		for i in 0..SAMPLE_COUNT {
-			let buf = read_at(&mut file, (size / SAMPLE_COUNT) * i, SAMPLE_SIZE).await?;
-			hasher.update(&buf);
+		// loop over samples, then sample end of file
+		(0..SAMPLE_COUNT)
+			.map(|i| ((size / SAMPLE_COUNT) * i, SAMPLE_SIZE))
+			.chain([(size - SAMPLE_SIZE, SAMPLE_SIZE)])
+			.collect()
+	};
+
+	if size >= MMAP_HASHING_THRESHOLD {
+		let path_buf = path.as_ref().to_path_buf();
+		let mmap_hasher = hasher.clone();
+		let mmap_ranges = ranges.clone();
+		if let Some(mmap_hasher) =
+			spawn_blocking(move || mmap_hash_ranges(path_buf, size, mmap_ranges, mmap_hasher))
+				.await??
+		{
+			// Reads through the memory map bypass the limited reads, so they're accounted for here
+			limit_read(ranges.iter().map(|(_, length)| length).sum()).await;
+
+			return Ok(cas_id_from(mmap_hasher));
 		}
-		// sample end of file
-		let buf = read_at(&mut file, size - SAMPLE_SIZE, SAMPLE_SIZE).await?;
+	}
+
+	// all the samples are read at once, so they can be submitted together
+	for buf in read_ranges(path, ranges).await? {
 		hasher.update(&buf);
 	}
 
+	Ok(cas_id_from(hasher))
+}
+
+fn cas_id_from(hasher: Hasher) -> String {
 	let hex = hasher.finalize().to_hex();
 	let mut id = hex.to_string();
 	id.truncate(16);
-	Ok(id)
+	id
 }
@@ -0,0 +1,156 @@
+use crate::{
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	object::fs::{context_menu_fs_info, copy::renamed_target, scan_target_directory, FsInfo},
+};
+
+use std::{
+	collections::BTreeSet,
+	hash::Hash,
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::fs;
+use tracing::{info, warn};
+
+use super::{find_converter, ConvertError, ConvertFormat, DEFAULT_QUALITY};
+
+pub const CONVERT_JOB_NAME: &str = "file_converter";
+
+/// FileConverterJob writes a converted copy of every file next to it, with the extension of the new format.
+pub struct FileConverterJob {}
+
+#[derive(Serialize, Deserialize, Hash, Type)]
+pub struct FileConverterJobInit {
+	pub location_id: i32,
+	pub path_ids: Vec<i32>,
+	pub format: ConvertFormat,
+	/// quality goes from 1 to 100, and is only used by lossy formats
+	pub quality: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct FileConverterJobState {
+	converted: usize,
+	failed: usize,
+	/// The directories converted files were written to, relative to the location, to index them once done
+	directories: BTreeSet<PathBuf>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for FileConverterJob {
+	type Init = FileConverterJobInit;
+	type Data = FileConverterJobState;
+	type Step = FsInfo;
+
+	fn name(&self) -> &'static str {
+		CONVERT_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		for &path_id in &state.init.path_ids {
+			let fs_info =
+				context_menu_fs_info(&ctx.library.db, state.init.location_id, path_id).await?;
+
+			if fs_info.path_data.is_dir
+				|| find_converter(&fs_info.fs_path, state.init.format).is_none()
+			{
+				return Err(ConvertError::Unsupported(fs_info.fs_path, state.init.format).into());
+			}
+
+			state.steps.push_back(fs_info);
+		}
+
+		state.data = Some(FileConverterJobState::default());
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let info = &state.steps[0];
+		let format = state.init.format;
+		let data = state.data.as_mut().ok_or(JobError::MissingData {
+			value: String::from("job state"),
+		})?;
+
+		let converter = find_converter(&info.fs_path, format)
+			.ok_or_else(|| ConvertError::Unsupported(info.fs_path.clone(), format))?;
+
+		let mut target = info.fs_path.with_extension(format.extension());
+		if fs::symlink_metadata(&target).await.is_ok() {
+			target = renamed_target(&target, false).await?;
+		}
+
+		let name = info.fs_path.display().to_string();
+		ctx.progress(vec![JobReportUpdate::Message(format!("Converting {name}"))]);
+
+		let progress = |done: f32| {
+			ctx.progress_debounced(vec![JobReportUpdate::Message(format!(
+				"Converting {name}: {:.0}%",
+				done * 100.0
+			))]);
+		};
+
+		match converter
+			.convert(
+				&info.fs_path,
+				&target,
+				format,
+				state.init.quality.unwrap_or(DEFAULT_QUALITY).clamp(1, 100),
+				&progress,
+			)
+			.await
+		{
+			Ok(()) => {
+				data.converted += 1;
+				data.directories.insert(
+					Path::new(&info.path_data.materialized_path)
+						.parent()
+						.map(Path::to_path_buf)
+						.unwrap_or_default(),
+				);
+			}
+			Err(e) => {
+				// One file failing doesn't stop the others from being converted
+				warn!("Failed to convert {name} to {format:?}: {e:#?}");
+				if let Err(e) = fs::remove_file(&target).await {
+					if e.kind() != std::io::ErrorKind::NotFound {
+						warn!("Failed to remove the incomplete conversion {target:?}: {e:#?}");
+					}
+				}
+				data.failed += 1;
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!(
+			"Converted {} files to {:?}, {} failed",
+			data.converted, state.init.format, data.failed
+		);
+
+		for directory in &data.directories {
+			scan_target_directory(&ctx.library, state.init.location_id, directory).await?;
+		}
+
+		Ok(Some(serde_json::to_value(data)?))
+	}
+}
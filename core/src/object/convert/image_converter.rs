@@ -0,0 +1,102 @@
+use std::{
+	fs::File,
+	io::BufWriter,
+	ops::Deref,
+	path::{Path, PathBuf},
+};
+
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, ImageFormat};
+use tokio::{fs, task::spawn_blocking};
+use webp::Encoder;
+
+use super::{ConvertError, ConvertFormat, Converter};
+
+/// ImageConverter converts between the image formats the `image` crate can read and write,
+/// and to lossy WebP with the `webp` crate, as `image` only writes lossless WebP.
+pub struct ImageConverter;
+
+impl ImageConverter {
+	fn output_format(format: ConvertFormat) -> Option<ImageFormat> {
+		match format {
+			ConvertFormat::Png => Some(ImageFormat::Png),
+			ConvertFormat::Jpeg => Some(ImageFormat::Jpeg),
+			ConvertFormat::Webp => Some(ImageFormat::WebP),
+			ConvertFormat::Gif => Some(ImageFormat::Gif),
+			ConvertFormat::Bmp => Some(ImageFormat::Bmp),
+			ConvertFormat::Tiff => Some(ImageFormat::Tiff),
+			ConvertFormat::Mp4 | ConvertFormat::Webm | ConvertFormat::Mkv => None,
+		}
+	}
+}
+
+/// encode writes `img` to `target` as `format`, except for lossy WebP images whose bytes are returned instead.
+/// It's blocking, so it must be called in a blocking task.
+fn encode(
+	img: DynamicImage,
+	target: PathBuf,
+	format: ImageFormat,
+	quality: u8,
+) -> Result<Option<Vec<u8>>, ConvertError> {
+	match format {
+		// JPEG doesn't have an alpha channel
+		ImageFormat::Jpeg => {
+			let mut writer = BufWriter::new(File::create(target)?);
+			JpegEncoder::new_with_quality(&mut writer, quality).encode_image(&img.to_rgb8())?;
+		}
+		ImageFormat::WebP => {
+			let img = DynamicImage::ImageRgba8(img.to_rgba8());
+			let encoder =
+				Encoder::from_image(&img).map_err(|e| ConvertError::WebpEncoder(e.to_string()))?;
+
+			// WebPMemory is !Send, so its bytes are copied out to be written from the async side
+			return Ok(Some(encoder.encode(quality as f32).deref().to_owned()));
+		}
+		format => img.save_with_format(target, format)?,
+	}
+
+	Ok(None)
+}
+
+#[async_trait::async_trait]
+impl Converter for ImageConverter {
+	fn can_convert(&self, source: &Path, format: ConvertFormat) -> bool {
+		matches!(
+			ImageFormat::from_path(source),
+			Ok(ImageFormat::Png
+				| ImageFormat::Jpeg
+				| ImageFormat::Gif
+				| ImageFormat::WebP
+				| ImageFormat::Bmp
+				| ImageFormat::Tiff
+				| ImageFormat::Ico
+				| ImageFormat::Tga)
+		) && Self::output_format(format).is_some()
+	}
+
+	async fn convert(
+		&self,
+		source: &Path,
+		target: &Path,
+		format: ConvertFormat,
+		quality: u8,
+		progress: &(dyn Fn(f32) + Send + Sync),
+	) -> Result<(), ConvertError> {
+		let output_format = Self::output_format(format)
+			.ok_or_else(|| ConvertError::Unsupported(source.to_path_buf(), format))?;
+
+		let source = source.to_path_buf();
+		let target_path = target.to_path_buf();
+		let webp = spawn_blocking(move || {
+			encode(image::open(source)?, target_path, output_format, quality)
+		})
+		.await??;
+
+		if let Some(webp) = webp {
+			fs::write(target, webp).await?;
+		}
+
+		progress(1.0);
+
+		Ok(())
+	}
+}
@@ -0,0 +1,102 @@
+//! Conversion of files into other formats, by converters which each handle a kind of file.
+//! Images are converted with the `image` crate, and videos with the `ffmpeg` binary when the `ffmpeg` feature is enabled
+//! and the binary is found.
+
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use thiserror::Error;
+use tokio::{io, task::JoinError};
+
+pub mod convert_job;
+mod image_converter;
+#[cfg(feature = "ffmpeg")]
+mod video_converter;
+
+/// Used for the lossy formats when no quality is requested
+pub const DEFAULT_QUALITY: u8 = 80;
+
+/// The formats files can be converted to
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConvertFormat {
+	Png,
+	Jpeg,
+	Webp,
+	Gif,
+	Bmp,
+	Tiff,
+	Mp4,
+	Webm,
+	Mkv,
+}
+
+impl ConvertFormat {
+	pub const fn extension(&self) -> &'static str {
+		match self {
+			Self::Png => "png",
+			Self::Jpeg => "jpg",
+			Self::Webp => "webp",
+			Self::Gif => "gif",
+			Self::Bmp => "bmp",
+			Self::Tiff => "tiff",
+			Self::Mp4 => "mp4",
+			Self::Webm => "webm",
+			Self::Mkv => "mkv",
+		}
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum ConvertError {
+	#[error("can't convert {0:?} to {1:?}")]
+	Unsupported(PathBuf, ConvertFormat),
+	#[error("image error: {0}")]
+	Image(#[from] image::ImageError),
+	#[error("WebP encoding error: {0}")]
+	WebpEncoder(String),
+	#[cfg(feature = "ffmpeg")]
+	#[error("failed to probe the source: {0}")]
+	Probe(#[from] ffmpeg_next::Error),
+	#[error("ffmpeg failed: {0}")]
+	EncoderFailed(std::process::ExitStatus),
+	#[error("io error: {0}")]
+	IO(#[from] io::Error),
+	#[error("failed to join the conversion task: {0}")]
+	Join(#[from] JoinError),
+}
+
+/// Converter writes files of the kinds it can read into some of the formats of [`ConvertFormat`].
+#[async_trait::async_trait]
+pub trait Converter: Send + Sync {
+	/// can_convert tells if the converter can read `source`, by its extension, and write it as `format`
+	fn can_convert(&self, source: &Path, format: ConvertFormat) -> bool;
+
+	/// convert writes `source` as `format` to `target`, calling `progress` with the fraction done so far.
+	/// `quality` goes from 1 to 100, and is only used by lossy formats.
+	async fn convert(
+		&self,
+		source: &Path,
+		target: &Path,
+		format: ConvertFormat,
+		quality: u8,
+		progress: &(dyn Fn(f32) + Send + Sync),
+	) -> Result<(), ConvertError>;
+}
+
+static CONVERTERS: Lazy<Vec<Box<dyn Converter>>> = Lazy::new(|| {
+	vec![
+		Box::new(image_converter::ImageConverter),
+		#[cfg(feature = "ffmpeg")]
+		Box::new(video_converter::VideoConverter),
+	]
+});
+
+/// find_converter returns the converter which can write `source` as `format`, if there's one.
+pub fn find_converter(source: &Path, format: ConvertFormat) -> Option<&'static dyn Converter> {
+	CONVERTERS
+		.iter()
+		.find(|converter| converter.can_convert(source, format))
+		.map(Box::as_ref)
+}
@@ -0,0 +1,129 @@
+use crate::util::ffmpeg::{ffmpeg_available, ffmpeg_command};
+
+use std::{path::Path, process::Stdio};
+
+use sd_file_ext::extensions::VideoExtension;
+use tokio::{
+	io::{AsyncBufReadExt, BufReader},
+	task::spawn_blocking,
+};
+
+use super::{ConvertError, ConvertFormat, Converter};
+
+/// Durations in ffmpeg are in microseconds
+const AV_TIME_BASE: f64 = 1_000_000.0;
+
+/// VideoConverter transcodes videos with the `ffmpeg` binary, to H.264 and AAC for MP4 and MKV, and to VP9 and Opus for WebM.
+/// It can't convert anything when the binary isn't found.
+pub struct VideoConverter;
+
+/// crf maps a quality from 1 to 100 to the constant rate factor of the encoders, lower being better
+fn crf(quality: u8) -> u32 {
+	40 - (quality.min(100) as u32 * 22 / 100)
+}
+
+fn duration_secs(source: &Path) -> Result<f64, ConvertError> {
+	ffmpeg_next::init()?;
+
+	Ok(ffmpeg_next::format::input(&source)?.duration().max(0) as f64 / AV_TIME_BASE)
+}
+
+#[async_trait::async_trait]
+impl Converter for VideoConverter {
+	fn can_convert(&self, source: &Path, format: ConvertFormat) -> bool {
+		let is_video = source
+			.extension()
+			.and_then(|extension| extension.to_str())
+			.and_then(|extension| extension.to_lowercase().parse::<VideoExtension>().ok())
+			.is_some();
+
+		is_video
+			&& ffmpeg_available()
+			&& matches!(
+				format,
+				ConvertFormat::Mp4 | ConvertFormat::Webm | ConvertFormat::Mkv
+			)
+	}
+
+	async fn convert(
+		&self,
+		source: &Path,
+		target: &Path,
+		format: ConvertFormat,
+		quality: u8,
+		progress: &(dyn Fn(f32) + Send + Sync),
+	) -> Result<(), ConvertError> {
+		let duration_secs = spawn_blocking({
+			let source = source.to_path_buf();
+			move || duration_secs(&source)
+		})
+		.await??;
+
+		let crf = crf(quality).to_string();
+
+		let mut command = ffmpeg_command()?;
+		command
+			.args(["-hide_banner", "-loglevel", "error", "-nostdin", "-y"])
+			.arg("-i")
+			.arg(source)
+			.args(["-map", "0:v:0?", "-map", "0:a:0?"]);
+
+		match format {
+			ConvertFormat::Webm => command.args([
+				"-c:v",
+				"libvpx-vp9",
+				"-b:v",
+				"0",
+				"-crf",
+				&crf,
+				"-c:a",
+				"libopus",
+			]),
+			ConvertFormat::Mp4 => command.args([
+				"-c:v",
+				"libx264",
+				"-crf",
+				&crf,
+				"-c:a",
+				"aac",
+				"-movflags",
+				"+faststart",
+			]),
+			_ => command.args(["-c:v", "libx264", "-crf", &crf, "-c:a", "aac"]),
+		};
+
+		// ffmpeg writes how far it got to stdout, as `key=value` lines
+		let mut child = command
+			.args(["-progress", "pipe:1"])
+			.arg(target)
+			.stdout(Stdio::piped())
+			.stderr(Stdio::null())
+			.kill_on_drop(true)
+			.spawn()?;
+
+		if let Some(stdout) = child.stdout.take() {
+			let mut lines = BufReader::new(stdout).lines();
+			while let Some(line) = lines.next_line().await? {
+				let Some(out_time_us) = line
+					.strip_prefix("out_time_us=")
+					.and_then(|out_time_us| out_time_us.parse::<f64>().ok())
+				else {
+					continue;
+				};
+
+				if duration_secs > 0.0 {
+					progress(((out_time_us / AV_TIME_BASE) / duration_secs).clamp(0.0, 1.0) as f32);
+				}
+			}
+		}
+
+		let status = child.wait().await?;
+		if !status.success() {
+			return Err(ConvertError::EncoderFailed(status));
+		}
+
+		progress(1.0);
+
+		Ok(())
+	}
+}
@@ -0,0 +1,525 @@
+use crate::{
+	invalidate_query,
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	prisma::{disk_usage, location, PrismaClient},
+	util::resources::resource_limiter,
+	volume::get_volumes,
+};
+
+use std::{
+	collections::{BTreeMap, HashMap, HashSet, VecDeque},
+	ffi::OsStr,
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::Direction;
+use rspc::Type;
+use sd_file_ext::{
+	extensions::{Extension, ExtensionPossibility},
+	kind::ObjectKind,
+};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use tokio::fs;
+use tracing::{info, warn};
+
+pub const DISK_USAGE_JOB_NAME: &str = "disk_usage";
+
+/// How many directories are written to the cache in a single batch
+const CACHE_BATCH_SIZE: usize = 500;
+
+/// How many of the heaviest directories are returned with a [`DiskUsageBreakdown`]
+pub const DISK_USAGE_TOP_DIRECTORIES: i64 = 20;
+
+/// DiskUsageTarget is what a [`DiskUsageJob`] scans.
+#[derive(Serialize, Deserialize, Debug, Clone, Hash, Type)]
+pub enum DiskUsageTarget {
+	Location(i32),
+	/// Volume holds the mount point of the volume.
+	Volume(String),
+}
+
+impl DiskUsageTarget {
+	/// cache_key is how the directories of this target are identified in the `disk_usage` table.
+	pub fn cache_key(&self) -> String {
+		match self {
+			Self::Location(id) => format!("location:{id}"),
+			Self::Volume(mount_point) => format!("volume:{mount_point}"),
+		}
+	}
+}
+
+/// DiskUsageDirectory is the cached size of a directory, as shown in the storage cleanup view.
+#[serde_as]
+#[derive(Serialize, Debug, Type)]
+pub struct DiskUsageDirectory {
+	pub path: String,
+	pub parent_path: Option<String>,
+	/// own_bytes is the size of the files directly inside this directory.
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub own_bytes: u64,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub own_files: u64,
+	/// total_bytes is the size of the whole subtree of this directory.
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub total_bytes: u64,
+	/// kinds is the size of the whole subtree of this directory, split by [`ObjectKind`].
+	pub kinds: Vec<DiskUsageKind>,
+}
+
+#[serde_as]
+#[derive(Serialize, Debug, Type)]
+pub struct DiskUsageKind {
+	pub kind: i32,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub bytes: u64,
+}
+
+impl From<disk_usage::Data> for DiskUsageDirectory {
+	fn from(data: disk_usage::Data) -> Self {
+		let kinds = serde_json::from_slice::<BTreeMap<i32, u64>>(&data.kinds)
+			.unwrap_or_default()
+			.into_iter()
+			.map(|(kind, bytes)| DiskUsageKind { kind, bytes })
+			.collect();
+
+		Self {
+			path: data.path,
+			parent_path: data.parent_path,
+			own_bytes: data.own_bytes as u64,
+			own_files: data.own_files as u64,
+			total_bytes: data.total_bytes as u64,
+			kinds,
+		}
+	}
+}
+
+/// DiskUsageBreakdown is a directory with its direct subdirectories, ready to be drawn as a treemap,
+/// alongside the directories holding the most data directly inside them across the whole target.
+#[derive(Serialize, Debug, Type)]
+pub struct DiskUsageBreakdown {
+	pub directory: Option<DiskUsageDirectory>,
+	pub children: Vec<DiskUsageDirectory>,
+	pub heaviest: Vec<DiskUsageDirectory>,
+}
+
+/// disk_usage_breakdown returns the cached breakdown of a directory of the target, or of its root if no path is given.
+pub async fn disk_usage_breakdown(
+	db: &PrismaClient,
+	target: &DiskUsageTarget,
+	path: Option<String>,
+) -> Result<DiskUsageBreakdown, prisma_client_rust::QueryError> {
+	let cache_key = target.cache_key();
+
+	let directory = db
+		.disk_usage()
+		.find_first(vec![
+			disk_usage::root::equals(cache_key.clone()),
+			match path {
+				Some(path) => disk_usage::path::equals(path),
+				None => disk_usage::depth::equals(0),
+			},
+		])
+		.exec()
+		.await?;
+
+	let children = match &directory {
+		Some(directory) => {
+			db.disk_usage()
+				.find_many(vec![
+					disk_usage::root::equals(cache_key.clone()),
+					disk_usage::parent_path::equals(Some(directory.path.clone())),
+				])
+				.order_by(disk_usage::total_bytes::order(Direction::Desc))
+				.exec()
+				.await?
+		}
+		None => vec![],
+	};
+
+	let heaviest = db
+		.disk_usage()
+		.find_many(vec![disk_usage::root::equals(cache_key)])
+		.order_by(disk_usage::own_bytes::order(Direction::Desc))
+		.take(DISK_USAGE_TOP_DIRECTORIES)
+		.exec()
+		.await?;
+
+	Ok(DiskUsageBreakdown {
+		directory: directory.map(Into::into),
+		children: children.into_iter().map(Into::into).collect(),
+		heaviest: heaviest.into_iter().map(Into::into).collect(),
+	})
+}
+
+/// `DiskUsageJob` walks a location or a volume and records the size of every directory, split by object kind.
+/// Directories whose modification time didn't change since the last scan reuse their cached sizes,
+/// unless a full rescan is requested.
+pub struct DiskUsageJob {}
+
+#[derive(Serialize, Deserialize, Hash)]
+pub struct DiskUsageJobInit {
+	pub target: DiskUsageTarget,
+	/// full_rescan ignores the cache, picking up files which changed size without changing their directory.
+	pub full_rescan: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DiskUsageJobState {
+	cache_key: String,
+	/// Mount points of other volumes inside the scanned tree, which are skipped
+	excluded_paths: Vec<PathBuf>,
+	directories: HashMap<PathBuf, DirectoryUsage>,
+	reused_directories: usize,
+	/// Files with many hard links found so far, as `(inode, device)`, so their contents are only counted once
+	#[serde(default)]
+	counted_hard_links: HashSet<(u64, u64)>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DiskUsageJobStep {
+	path: PathBuf,
+	parent: Option<PathBuf>,
+	depth: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct DirectoryUsage {
+	parent: Option<PathBuf>,
+	depth: i32,
+	own_bytes: u64,
+	own_files: u64,
+	own_kinds: BTreeMap<i32, u64>,
+	modified: Option<DateTime<Utc>>,
+}
+
+/// hard_link_id identifies the contents of a file with more than one hard link, as `(inode, device)`.
+/// Hard links are only known on unix, so they're counted as many times as they're found on other platforms.
+fn hard_link_id(metadata: &std::fs::Metadata) -> Option<(u64, u64)> {
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+
+		(metadata.nlink() > 1).then(|| (metadata.ino(), metadata.dev()))
+	}
+	#[cfg(not(unix))]
+	{
+		let _ = metadata;
+		None
+	}
+}
+
+fn object_kind(path: &Path) -> ObjectKind {
+	// Only the extension is used, as reading magic bytes of every file would make the scan far slower
+	match path
+		.extension()
+		.and_then(OsStr::to_str)
+		.and_then(Extension::from_str)
+	{
+		Some(ExtensionPossibility::Known(extension)) => extension.into(),
+		_ => ObjectKind::Unknown,
+	}
+}
+
+fn encode_kinds(kinds: &BTreeMap<i32, u64>) -> Vec<u8> {
+	serde_json::to_vec(kinds).unwrap_or_default()
+}
+
+/// cached_directory returns the cached usage and subdirectories of a directory, if it didn't change since it was scanned.
+async fn cached_directory(
+	db: &PrismaClient,
+	cache_key: &str,
+	path: &str,
+	modified: DateTime<Utc>,
+) -> Result<Option<(disk_usage::Data, Vec<String>)>, JobError> {
+	let Some(cached) = db
+		.disk_usage()
+		.find_unique(disk_usage::root_path(
+			cache_key.to_string(),
+			path.to_string(),
+		))
+		.exec()
+		.await?
+	else {
+		return Ok(None);
+	};
+
+	// The database only keeps millisecond precision
+	if cached.dir_modified.timestamp_millis() != modified.timestamp_millis() {
+		return Ok(None);
+	}
+
+	let children = db
+		.disk_usage()
+		.find_many(vec![
+			disk_usage::root::equals(cache_key.to_string()),
+			disk_usage::parent_path::equals(Some(path.to_string())),
+		])
+		.select(disk_usage::select!({ path }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|child| child.path)
+		.collect();
+
+	Ok(Some((cached, children)))
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for DiskUsageJob {
+	type Init = DiskUsageJobInit;
+	type Data = DiskUsageJobState;
+	type Step = DiskUsageJobStep;
+
+	fn name(&self) -> &'static str {
+		DISK_USAGE_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let root_path = match &state.init.target {
+			DiskUsageTarget::Location(location_id) => ctx
+				.library
+				.db
+				.location()
+				.find_unique(location::id::equals(*location_id))
+				.exec()
+				.await?
+				.ok_or(JobError::MissingData {
+					value: String::from("location which matches location_id"),
+				})?
+				.path
+				.into(),
+			DiskUsageTarget::Volume(mount_point) => PathBuf::from(mount_point),
+		};
+
+		let excluded_paths = get_volumes()
+			.map(|volumes| {
+				volumes
+					.into_iter()
+					.map(|volume| PathBuf::from(volume.mount_point))
+					.filter(|mount_point| {
+						mount_point != &root_path && mount_point.starts_with(&root_path)
+					})
+					.collect()
+			})
+			.unwrap_or_default();
+
+		state.steps = VecDeque::from([DiskUsageJobStep {
+			path: root_path.clone(),
+			parent: None,
+			depth: 0,
+		}]);
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(1),
+			JobReportUpdate::Message(format!("Measuring disk usage of {}", root_path.display())),
+		]);
+
+		state.data = Some(DiskUsageJobState {
+			cache_key: state.init.target.cache_key(),
+			excluded_paths,
+			directories: HashMap::new(),
+			reused_directories: 0,
+			counted_hard_links: HashSet::new(),
+		});
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+		let step = &state.steps[0];
+
+		let modified = match fs::metadata(&step.path).await {
+			Ok(metadata) => metadata.modified().ok().map(DateTime::<Utc>::from),
+			Err(e) => {
+				// The directory may have been removed or be unreadable, which shouldn't fail the whole scan
+				warn!("Failed to read directory {}: {e:#?}", step.path.display());
+				return Ok(());
+			}
+		};
+
+		let mut usage = DirectoryUsage {
+			parent: step.parent.clone(),
+			depth: step.depth,
+			modified,
+			..Default::default()
+		};
+		let mut subdirectories = vec![];
+
+		let path_str = step.path.to_string_lossy().to_string();
+		let cached = match (state.init.full_rescan, modified) {
+			(false, Some(modified)) => {
+				cached_directory(&ctx.library.db, &data.cache_key, &path_str, modified).await?
+			}
+			_ => None,
+		};
+
+		if let Some((cached, children)) = cached {
+			usage.own_bytes = cached.own_bytes as u64;
+			usage.own_files = cached.own_files as u64;
+			usage.own_kinds = serde_json::from_slice(&cached.own_kinds).unwrap_or_default();
+			subdirectories = children.into_iter().map(PathBuf::from).collect();
+			data.reused_directories += 1;
+		} else {
+			// The directory handle is held until every entry is read
+			let _permit = resource_limiter().open_file().await;
+			let mut read_dir = match fs::read_dir(&step.path).await {
+				Ok(read_dir) => read_dir,
+				Err(e) => {
+					warn!("Failed to read directory {}: {e:#?}", step.path.display());
+					return Ok(());
+				}
+			};
+
+			while let Some(entry) = read_dir.next_entry().await? {
+				// `DirEntry::metadata` doesn't follow symlinks, so linked files aren't counted twice
+				let Ok(metadata) = entry.metadata().await else {
+					continue;
+				};
+
+				if metadata.is_dir() {
+					subdirectories.push(entry.path());
+				} else if metadata.is_file() {
+					if let Some(hard_link) = hard_link_id(&metadata) {
+						if !data.counted_hard_links.insert(hard_link) {
+							continue;
+						}
+					}
+
+					let kind = object_kind(&entry.path());
+					usage.own_bytes += metadata.len();
+					usage.own_files += 1;
+					*usage.own_kinds.entry(kind as i32).or_default() += metadata.len();
+				}
+			}
+		}
+
+		let (path, depth) = (step.path.clone(), step.depth);
+		state.steps.extend(
+			subdirectories
+				.into_iter()
+				.filter(|subdirectory| !data.excluded_paths.contains(subdirectory))
+				.map(|subdirectory| DiskUsageJobStep {
+					path: subdirectory,
+					parent: Some(path.clone()),
+					depth: depth + 1,
+				}),
+		);
+		data.directories.insert(path, usage);
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(state.step_number + state.steps.len()),
+			JobReportUpdate::CompletedTaskCount(state.step_number + 1),
+		]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+		let db = &ctx.library.db;
+
+		// Deepest directories first, so every subtree is complete before it's added to its parent
+		let mut paths = data.directories.keys().collect::<Vec<_>>();
+		paths.sort_by_key(|path| std::cmp::Reverse(data.directories[*path].depth));
+
+		let mut totals = HashMap::<&PathBuf, (u64, BTreeMap<i32, u64>)>::new();
+		for path in &paths {
+			let usage = &data.directories[*path];
+			let (mut total_bytes, mut kinds) = totals.remove(path).unwrap_or_default();
+			total_bytes += usage.own_bytes;
+			for (kind, bytes) in &usage.own_kinds {
+				*kinds.entry(*kind).or_default() += bytes;
+			}
+
+			if let Some(parent) = usage.parent.as_ref() {
+				let (parent_bytes, parent_kinds) = totals.entry(parent).or_default();
+				*parent_bytes += total_bytes;
+				for (kind, bytes) in &kinds {
+					*parent_kinds.entry(*kind).or_default() += bytes;
+				}
+			}
+
+			totals.insert(*path, (total_bytes, kinds));
+		}
+
+		// Directories which no longer exist are dropped with the rest of the previous scan
+		db.disk_usage()
+			.delete_many(vec![disk_usage::root::equals(data.cache_key.clone())])
+			.exec()
+			.await?;
+
+		for chunk in paths.chunks(CACHE_BATCH_SIZE) {
+			db._batch(
+				chunk
+					.iter()
+					.map(|path| {
+						let usage = &data.directories[*path];
+						let (total_bytes, kinds) = &totals[path];
+
+						db.disk_usage().create(
+							data.cache_key.clone(),
+							path.to_string_lossy().to_string(),
+							usage.depth,
+							usage.own_bytes as i64,
+							usage.own_files as i64,
+							*total_bytes as i64,
+							encode_kinds(&usage.own_kinds),
+							encode_kinds(kinds),
+							usage
+								.modified
+								.unwrap_or_else(|| SystemTime::UNIX_EPOCH.into())
+								.into(),
+							vec![disk_usage::parent_path::set(
+								usage
+									.parent
+									.as_ref()
+									.map(|parent| parent.to_string_lossy().to_string()),
+							)],
+						)
+					})
+					.collect::<Vec<_>>(),
+			)
+			.await?;
+		}
+
+		let total_bytes = paths
+			.last()
+			.and_then(|root| totals.get(root))
+			.map(|(bytes, _)| *bytes)
+			.unwrap_or(0);
+
+		invalidate_query!(ctx.library, "volumes.diskUsage");
+
+		info!(
+			"Measured disk usage of {}: {} directories ({} unchanged), {total_bytes} bytes",
+			data.cache_key,
+			paths.len(),
+			data.reused_directories
+		);
+
+		Ok(Some(serde_json::json!({
+			"target": data.cache_key,
+			"directories": paths.len(),
+			"reused_directories": data.reused_directories,
+			"total_bytes": total_bytes,
+		})))
+	}
+}
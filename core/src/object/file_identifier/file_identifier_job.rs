@@ -3,7 +3,8 @@ use crate::{
 	library::Library,
 	location::file_path_helper::{
 		ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
-		file_path_for_file_identifier, MaterializedPath,
+		file_path_for_file_identifier, file_path_in_subtree, get_existing_file_path_id,
+		MaterializedPath,
 	},
 	prisma::{file_path, location, PrismaClient},
 };
@@ -50,7 +51,10 @@ impl Hash for FileIdentifierJobInit {
 pub struct FileIdentifierJobState {
 	cursor: FilePathIdAndLocationIdCursor,
 	report: FileIdentifierReport,
-	maybe_sub_materialized_path: Option<MaterializedPath>,
+	maybe_sub_path_id: Option<i32>,
+	/// Never saved, so a job restored from a saved state knows it's resuming on its first step
+	#[serde(skip)]
+	running: bool,
 }
 
 #[async_trait::async_trait]
@@ -63,6 +67,11 @@ impl StatefulJob for FileIdentifierJob {
 		FILE_IDENTIFIER_JOB_NAME
 	}
 
+	fn checkpoints(&self) -> bool {
+		// Large locations take long enough that a crash shouldn't make every file be hashed again
+		true
+	}
+
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
 		let Library { db, .. } = &ctx.library;
 
@@ -71,7 +80,7 @@ impl StatefulJob for FileIdentifierJob {
 		let location_id = state.init.location.id;
 		let location_path = Path::new(&state.init.location.path);
 
-		let maybe_sub_materialized_path = if let Some(ref sub_path) = state.init.sub_path {
+		let maybe_sub_path_id = if let Some(ref sub_path) = state.init.sub_path {
 			let full_path = ensure_sub_path_is_in_location(location_path, sub_path)
 				.await
 				.map_err(FileIdentifierJobError::from)?;
@@ -79,16 +88,21 @@ impl StatefulJob for FileIdentifierJob {
 				.await
 				.map_err(FileIdentifierJobError::from)?;
 
-			Some(
+			let sub_path_id = get_existing_file_path_id(
 				MaterializedPath::new(location_id, location_path, &full_path, true)
 					.map_err(FileIdentifierJobError::from)?,
+				db,
 			)
+			.await
+			.map_err(FileIdentifierJobError::from)?
+			.ok_or_else(|| FileIdentifierJobError::SubPathNotIndexed(sub_path.clone()))?;
+
+			Some(sub_path_id)
 		} else {
 			None
 		};
 
-		let orphan_count =
-			count_orphan_file_paths(db, location_id, &maybe_sub_materialized_path).await?;
+		let orphan_count = count_orphan_file_paths(db, location_id, maybe_sub_path_id).await?;
 
 		// Initializing `state.data` here because we need a complete state in case of early finish
 		state.data = Some(FileIdentifierJobState {
@@ -101,7 +115,8 @@ impl StatefulJob for FileIdentifierJob {
 				file_path_id: -1,
 				location_id,
 			},
-			maybe_sub_materialized_path,
+			maybe_sub_path_id,
+			running: true,
 		});
 
 		let data = state.data.as_mut().unwrap(); // SAFETY: We just initialized it
@@ -129,7 +144,7 @@ impl StatefulJob for FileIdentifierJob {
 			.find_first(orphan_path_filters(
 				location_id,
 				None,
-				&data.maybe_sub_materialized_path,
+				data.maybe_sub_path_id,
 			))
 			.order_by(file_path::id::order(Direction::Asc))
 			.select(file_path::select!({ id }))
@@ -153,7 +168,8 @@ impl StatefulJob for FileIdentifierJob {
 		let FileIdentifierJobState {
 			ref mut cursor,
 			ref mut report,
-			ref maybe_sub_materialized_path,
+			maybe_sub_path_id,
+			ref mut running,
 		} = state
 			.data
 			.as_mut()
@@ -161,9 +177,24 @@ impl StatefulJob for FileIdentifierJob {
 
 		let location = &state.init.location;
 
+		if !*running {
+			*running = true;
+			report.resumed_from_file_path_id = Some(cursor.file_path_id);
+
+			info!(
+				"Resuming identifier job at step {} from file path <id={}>",
+				state.step_number, cursor.file_path_id
+			);
+			ctx.progress(vec![JobReportUpdate::Message(format!(
+				"Resuming from file path {} after {} of {} orphan Paths",
+				cursor.file_path_id,
+				state.step_number * CHUNK_SIZE,
+				report.total_orphan_paths
+			))]);
+		}
+
 		// get chunk of orphans to process
-		let file_paths =
-			get_orphan_file_paths(&ctx.library.db, cursor, maybe_sub_materialized_path).await?;
+		let file_paths = get_orphan_file_paths(&ctx.library.db, cursor, *maybe_sub_path_id).await?;
 
 		process_identifier_file_paths(
 			self.name(),
@@ -184,6 +215,8 @@ impl StatefulJob for FileIdentifierJob {
 				.as_ref()
 				.expect("critical error: missing data on job state")
 				.report,
+			state.init.location.id,
+			state.init.sub_path.as_deref(),
 			ctx,
 		)
 	}
@@ -192,11 +225,15 @@ impl StatefulJob for FileIdentifierJob {
 fn orphan_path_filters(
 	location_id: i32,
 	file_path_id: Option<i32>,
-	maybe_sub_materialized_path: &Option<MaterializedPath>,
+	maybe_sub_path_id: Option<i32>,
 ) -> Vec<file_path::WhereParam> {
 	let mut params = vec![
 		file_path::object_id::equals(None),
 		file_path::is_dir::equals(false),
+		// Symlinks indexed as links don't have contents of their own to be identified
+		file_path::is_symlink::equals(false),
+		// The entries of archives can't be read without extracting them
+		file_path::in_archive::equals(false),
 		file_path::location_id::equals(location_id),
 	];
 	// this is a workaround for the cursor not working properly
@@ -204,10 +241,8 @@ fn orphan_path_filters(
 		params.push(file_path::id::gte(file_path_id));
 	}
 
-	if let Some(ref sub_materealized_path) = maybe_sub_materialized_path {
-		params.push(file_path::materialized_path::starts_with(
-			sub_materealized_path.into(),
-		));
+	if let Some(sub_path_id) = maybe_sub_path_id {
+		params.push(file_path_in_subtree(location_id, sub_path_id));
 	}
 
 	params
@@ -216,14 +251,10 @@ fn orphan_path_filters(
 async fn count_orphan_file_paths(
 	db: &PrismaClient,
 	location_id: i32,
-	maybe_sub_materialized_path: &Option<MaterializedPath>,
+	maybe_sub_path_id: Option<i32>,
 ) -> Result<usize, prisma_client_rust::QueryError> {
 	db.file_path()
-		.count(orphan_path_filters(
-			location_id,
-			None,
-			maybe_sub_materialized_path,
-		))
+		.count(orphan_path_filters(location_id, None, maybe_sub_path_id))
 		.exec()
 		.await
 		.map(|c| c as usize)
@@ -232,7 +263,7 @@ async fn count_orphan_file_paths(
 async fn get_orphan_file_paths(
 	db: &PrismaClient,
 	cursor: &FilePathIdAndLocationIdCursor,
-	maybe_sub_materialized_path: &Option<MaterializedPath>,
+	maybe_sub_path_id: Option<i32>,
 ) -> Result<Vec<file_path_for_file_identifier::Data>, prisma_client_rust::QueryError> {
 	info!(
 		"Querying {} orphan Paths at cursor: {:?}",
@@ -242,7 +273,7 @@ async fn get_orphan_file_paths(
 		.find_many(orphan_path_filters(
 			cursor.location_id,
 			Some(cursor.file_path_id),
-			maybe_sub_materialized_path,
+			maybe_sub_path_id,
 		))
 		.order_by(file_path::id::order(Direction::Asc))
 		// .cursor(cursor.into())
@@ -1,24 +1,38 @@
 use crate::{
-	invalidate_query,
+	api::locations::invalidate_explorer_path,
 	job::{JobError, JobReportUpdate, JobResult, WorkerContext},
 	library::Library,
-	location::file_path_helper::{file_path_for_file_identifier, FilePathError},
-	object::{cas::generate_cas_id, object_for_file_identifier},
+	location::file_path_helper::{
+		file_path_for_file_identifier, file_path_for_hard_links, sub_path_materialized_path,
+		FilePathError,
+	},
+	object::{
+		cas::{generate_cas_id, CasIdAlgorithm, MMAP_HASHING_THRESHOLD},
+		object_for_file_identifier,
+		perceptual_hash::perceptual_hash,
+	},
 	prisma::{file_path, location, object, PrismaClient},
 	sync,
 	sync::SyncManager,
+	util::{
+		db::{refresh_count_estimates, CountedTable},
+		governor::governor,
+		resources::resource_limiter,
+	},
+	volume::job_concurrency,
 };
 
 use sd_file_ext::{extensions::Extension, kind::ObjectKind};
 use sd_sync::CRDTOperation;
 
-use futures::future::join_all;
+use futures::{stream, StreamExt};
 use int_enum::IntEnum;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::{
 	collections::{HashMap, HashSet},
 	path::{Path, PathBuf},
+	time::{Duration, Instant},
 };
 use thiserror::Error;
 use tokio::{fs, io};
@@ -30,11 +44,15 @@ pub mod shallow_file_identifier_job;
 
 // we break these jobs into chunks of 100 to improve performance
 const CHUNK_SIZE: usize = 100;
+// how many of the slowest files to hash are kept in the job report
+const SLOWEST_HASHED_FILES: usize = 10;
 
 #[derive(Error, Debug)]
 pub enum FileIdentifierJobError {
 	#[error("File path related error (error: {0})")]
 	FilePathError(#[from] FilePathError),
+	#[error("Sub path isn't indexed (path: {0:?})")]
+	SubPathNotIndexed(PathBuf),
 }
 
 #[derive(Debug, Clone)]
@@ -42,6 +60,10 @@ pub struct FileMetadata {
 	pub cas_id: String,
 	pub kind: ObjectKind,
 	pub fs_metadata: std::fs::Metadata,
+	/// hashing_time is how long generating the cas_id took
+	pub hashing_time: Duration,
+	/// perceptual_hash is only computed for images, when requested
+	pub perceptual_hash: Option<String>,
 }
 
 impl FileMetadata {
@@ -49,6 +71,8 @@ impl FileMetadata {
 	pub async fn new(
 		location_path: impl AsRef<Path>,
 		materialized_path: impl AsRef<Path>, // TODO: use dedicated CreateUnchecked type
+		cas_id_algorithm: CasIdAlgorithm,
+		perceptual_hashing: bool,
 	) -> Result<FileMetadata, io::Error> {
 		let path = location_path.as_ref().join(materialized_path.as_ref());
 
@@ -65,14 +89,27 @@ impl FileMetadata {
 			.map(Into::into)
 			.unwrap_or(ObjectKind::Unknown);
 
-		let cas_id = generate_cas_id(&path, fs_metadata.len()).await?;
+		let start = Instant::now();
+		let cas_id = generate_cas_id(&path, fs_metadata.len(), cas_id_algorithm).await?;
+		let hashing_time = start.elapsed();
 
-		info!("Analyzed file: {:?} {:?} {:?}", path, cas_id, kind);
+		let perceptual_hash = if perceptual_hashing && kind == ObjectKind::Image {
+			perceptual_hash(&path).await
+		} else {
+			None
+		};
+
+		info!(
+			"Analyzed file: {:?} {:?} {:?} in {:?}",
+			path, cas_id, kind, hashing_time
+		);
 
 		Ok(FileMetadata {
 			cas_id,
 			kind,
 			fs_metadata,
+			hashing_time,
+			perceptual_hash,
 		})
 	}
 }
@@ -89,6 +126,61 @@ impl From<&FilePathIdAndLocationIdCursor> for file_path::UniqueWhereParam {
 	}
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SlowHashedFile {
+	materialized_path: String,
+	size: u64,
+	hashing_time: Duration,
+}
+
+/// HashingStats measures the cas_id generation of a job, to tune the hashing backends.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct HashingStats {
+	hashed_files: usize,
+	hashed_bytes: u64,
+	/// mmap_hashed_files are the files hashed through a memory map, for being over [`MMAP_HASHING_THRESHOLD`]
+	mmap_hashed_files: usize,
+	total_hashing_time: Duration,
+	max_hashing_time: Duration,
+	slowest_files: Vec<SlowHashedFile>,
+}
+
+impl HashingStats {
+	fn record(&mut self, materialized_path: &str, size: u64, hashing_time: Duration) {
+		self.hashed_files += 1;
+		self.hashed_bytes += size;
+		if size >= MMAP_HASHING_THRESHOLD {
+			self.mmap_hashed_files += 1;
+		}
+		self.total_hashing_time += hashing_time;
+		self.max_hashing_time = self.max_hashing_time.max(hashing_time);
+
+		self.slowest_files.push(SlowHashedFile {
+			materialized_path: materialized_path.to_string(),
+			size,
+			hashing_time,
+		});
+		self.keep_slowest();
+	}
+
+	fn merge(&mut self, other: HashingStats) {
+		self.hashed_files += other.hashed_files;
+		self.hashed_bytes += other.hashed_bytes;
+		self.mmap_hashed_files += other.mmap_hashed_files;
+		self.total_hashing_time += other.total_hashing_time;
+		self.max_hashing_time = self.max_hashing_time.max(other.max_hashing_time);
+
+		self.slowest_files.extend(other.slowest_files);
+		self.keep_slowest();
+	}
+
+	fn keep_slowest(&mut self) {
+		self.slowest_files
+			.sort_by(|a, b| b.hashing_time.cmp(&a.hashing_time));
+		self.slowest_files.truncate(SLOWEST_HASHED_FILES);
+	}
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct FileIdentifierReport {
 	location_path: PathBuf,
@@ -96,18 +188,58 @@ pub struct FileIdentifierReport {
 	total_objects_created: usize,
 	total_objects_linked: usize,
 	total_objects_ignored: usize,
+	#[serde(default)]
+	hashing: HashingStats,
+	/// The cursor the job resumed from after being paused or interrupted, the orphans before it were already identified
+	#[serde(default)]
+	resumed_from_file_path_id: Option<i32>,
 }
 
 async fn identifier_job_step(
-	Library { db, sync, .. }: &Library,
+	library: &Library,
 	location: &location::Data,
 	file_paths: &[file_path_for_file_identifier::Data],
-) -> Result<(usize, usize), JobError> {
-	let file_path_metas = join_all(file_paths.iter().map(|file_path| async move {
-		FileMetadata::new(&location.path, &file_path.materialized_path)
-			.await
-			.map(|params| (file_path.id, (params, file_path)))
+) -> Result<(usize, usize, HashingStats), JobError> {
+	let Library { db, sync, .. } = library;
+
+	let cas_id_algorithm = library.config.cas_id_algorithm;
+	let perceptual_hashing = library.config.perceptual_hashing;
+
+	// Slow volumes like network shares get fewer files read at the same time
+	let concurrency = job_concurrency(library, &location.path)
+		.await
+		.min(resource_limiter().max_open_files());
+	let concurrency = governor()
+		.throttle()
+		.map_or(concurrency, |throttle| throttle.parallelism(concurrency));
+
+	// Hard links share their contents, so the ones of an identified file path are linked to its object
+	// without being hashed, and only one of the hard links of this step is hashed for the others to follow
+	let mut linked =
+		link_hard_links(library, location, &file_paths.iter().collect::<Vec<_>>()).await?;
+	let mut hashed_inodes = HashSet::new();
+	let (to_hash, hard_links): (Vec<_>, Vec<_>) = file_paths
+		.iter()
+		.filter(|file_path| !linked.contains(&file_path.id))
+		.partition(|file_path| match (&file_path.inode, &file_path.device) {
+			(Some(inode), Some(device)) => hashed_inodes.insert((inode, device)),
+			_ => true,
+		});
+
+	let file_path_metas = stream::iter(to_hash.into_iter().map(|file_path| async move {
+		let _permit = resource_limiter().open_file().await;
+
+		FileMetadata::new(
+			&location.path,
+			&file_path.materialized_path,
+			cas_id_algorithm,
+			perceptual_hashing,
+		)
+		.await
+		.map(|params| (file_path.id, (params, file_path)))
 	}))
+	.buffer_unordered(concurrency)
+	.collect::<Vec<_>>()
 	.await
 	.into_iter()
 	.flat_map(|data| {
@@ -119,32 +251,48 @@ async fn identifier_job_step(
 	})
 	.collect::<HashMap<i32, _>>();
 
-	// Assign cas_id to each file path
-	sync.write_ops(
-		db,
-		file_path_metas
-			.iter()
-			.map(|(id, (meta, _))| {
-				(
+	let mut hashing = HashingStats::default();
+	for (meta, file_path) in file_path_metas.values() {
+		hashing.record(
+			&file_path.materialized_path,
+			meta.fs_metadata.len(),
+			meta.hashing_time,
+		);
+	}
+
+	// Assign cas_id to each file path, alongside the algorithm that generated it so other nodes can verify it
+	let (crdt_ops, db_ops): (Vec<_>, Vec<_>) = file_path_metas
+		.iter()
+		.map(|(id, (meta, _))| {
+			let sync_id = || sync::file_path::SyncId {
+				id: *id,
+				location: sync::location::SyncId {
+					pub_id: location.pub_id.clone(),
+				},
+			};
+
+			(
+				[
+					sync.shared_update(sync_id(), "cas_id", json!(&meta.cas_id)),
 					sync.shared_update(
-						sync::file_path::SyncId {
-							id: *id,
-							location: sync::location::SyncId {
-								pub_id: location.pub_id.clone(),
-							},
-						},
-						"cas_id",
-						json!(&meta.cas_id),
-					),
-					db.file_path().update(
-						file_path::location_id_id(location.id, *id),
-						vec![file_path::cas_id::set(Some(meta.cas_id.clone()))],
+						sync_id(),
+						"cas_id_algorithm",
+						json!(cas_id_algorithm.int_value()),
 					),
-				)
-			})
-			.unzip::<_, _, _, Vec<_>>(),
-	)
-	.await?;
+				],
+				db.file_path().update(
+					file_path::location_id_id(location.id, *id),
+					vec![
+						file_path::cas_id::set(Some(meta.cas_id.clone())),
+						file_path::cas_id_algorithm::set(Some(cas_id_algorithm.int_value())),
+					],
+				),
+			)
+		})
+		.unzip();
+
+	sync.write_ops(db, (crdt_ops.into_iter().flatten().collect(), db_ops))
+		.await?;
 
 	let unique_cas_ids = file_path_metas
 		.values()
@@ -246,6 +394,7 @@ async fn identifier_job_step(
 									("date_created", json!(fp.date_created)),
 									("kind", json!(kind)),
 									("size_in_bytes", json!(size)),
+									("perceptual_hash", json!(meta.perceptual_hash)),
 								]
 								.into_iter()
 								.map(|(f, v)| sync.shared_update(sync_id(), f, v)),
@@ -257,6 +406,7 @@ async fn identifier_job_step(
 								object::date_created::set(fp.date_created),
 								object::kind::set(kind),
 								object::size_in_bytes::set(size),
+								object::perceptual_hash::set(meta.perceptual_hash.clone()),
 							],
 						),
 					);
@@ -299,7 +449,151 @@ async fn identifier_job_step(
 		0
 	};
 
-	Ok((total_created, updated_file_paths.len()))
+	linked.extend(link_hard_links(library, location, &hard_links).await?);
+
+	Ok((
+		total_created,
+		updated_file_paths.len() + linked.len(),
+		hashing,
+	))
+}
+
+/// link_hard_links links the file paths which are hard links of an identified file path of the location
+/// to its object, copying its cas_id instead of hashing them again. Returns the ids of the linked file paths.
+/// Only the files still sharing the metadata of the identified one on disk are linked.
+async fn link_hard_links(
+	library: &Library,
+	location: &location::Data,
+	file_paths: &[&file_path_for_file_identifier::Data],
+) -> Result<HashSet<i32>, JobError> {
+	let Library { db, sync, .. } = library;
+
+	let inodes = file_paths
+		.iter()
+		.filter_map(|file_path| file_path.inode.clone())
+		.collect::<Vec<_>>();
+	if inodes.is_empty() {
+		return Ok(HashSet::new());
+	}
+
+	let identified = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(location.id),
+			file_path::inode::in_vec(inodes),
+			file_path::object_id::not(None),
+		])
+		.select(file_path_for_hard_links::select())
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|file_path| {
+			Some((
+				(file_path.inode.clone()?, file_path.device.clone()?),
+				file_path,
+			))
+		})
+		.collect::<HashMap<_, _>>();
+
+	let location_path = Path::new(&location.path);
+
+	let mut hard_links = vec![];
+	for file_path in file_paths {
+		let Some(identified) = file_path
+			.inode
+			.clone()
+			.zip(file_path.device.clone())
+			.and_then(|key| identified.get(&key))
+		else {
+			continue;
+		};
+		let Some(object) = identified.object.as_ref() else {
+			continue;
+		};
+
+		// Inodes are reused once their files are removed, so the indexed ones may not be hard links anymore
+		if is_hard_link_of(
+			location_path.join(&file_path.materialized_path),
+			location_path.join(&identified.materialized_path),
+		)
+		.await
+		{
+			hard_links.push((file_path.id, identified, object));
+		}
+	}
+
+	let mut linked = HashSet::new();
+	let (crdt_ops, db_ops): (Vec<_>, Vec<_>) = hard_links
+		.into_iter()
+		.map(|(id, identified, object)| {
+			linked.insert(id);
+
+			let sync_id = || sync::file_path::SyncId {
+				id,
+				location: sync::location::SyncId {
+					pub_id: location.pub_id.clone(),
+				},
+			};
+
+			(
+				[
+					sync.shared_update(sync_id(), "cas_id", json!(&identified.cas_id)),
+					sync.shared_update(
+						sync_id(),
+						"cas_id_algorithm",
+						json!(identified.cas_id_algorithm),
+					),
+					sync.shared_update(
+						sync_id(),
+						"object",
+						json!({ "pub_id": Uuid::from_slice(&object.pub_id).unwrap() }),
+					),
+				],
+				db.file_path().update(
+					file_path::location_id_id(location.id, id),
+					vec![
+						file_path::cas_id::set(identified.cas_id.clone()),
+						file_path::cas_id_algorithm::set(identified.cas_id_algorithm),
+						file_path::object::connect(object::pub_id::equals(object.pub_id.clone())),
+					],
+				),
+			)
+		})
+		.unzip();
+
+	if !linked.is_empty() {
+		info!("Linking {} hard links to their Objects", linked.len());
+
+		sync.write_ops(db, (crdt_ops.into_iter().flatten().collect(), db_ops))
+			.await?;
+	}
+
+	Ok(linked)
+}
+
+/// is_hard_link_of tells if the file at `path` is a hard link of the file at `identified_path`, which it is if it has several
+/// links and both have the same size and modification date, as hard links share their metadata.
+async fn is_hard_link_of(path: impl AsRef<Path>, identified_path: impl AsRef<Path>) -> bool {
+	let (Ok(metadata), Ok(identified_metadata)) =
+		(fs::metadata(path).await, fs::metadata(identified_path).await)
+	else {
+		return false;
+	};
+
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+
+		if metadata.nlink() < 2 {
+			return false;
+		}
+	}
+
+	metadata.len() == identified_metadata.len()
+		&& matches!(
+			(metadata.modified(), identified_metadata.modified()),
+			(Ok(modified), Ok(identified_modified)) if modified == identified_modified
+		)
 }
 
 fn file_path_object_connect_ops<'db>(
@@ -357,11 +651,13 @@ async fn process_identifier_file_paths(
 		report.total_orphan_paths
 	);
 
-	let (total_objects_created, total_objects_linked) =
+	let (total_objects_created, total_objects_linked, hashing) =
 		identifier_job_step(&ctx.library, location, file_paths).await?;
 
 	report.total_objects_created += total_objects_created;
 	report.total_objects_linked += total_objects_linked;
+	let hashed_bytes = hashing.hashed_bytes;
+	report.hashing.merge(hashing);
 
 	// set the step data cursor to the last row of this chunk
 	if let Some(last_row) = file_paths.last() {
@@ -370,6 +666,8 @@ async fn process_identifier_file_paths(
 
 	ctx.progress(vec![
 		JobReportUpdate::CompletedTaskCount(step_number),
+		JobReportUpdate::ItemsProcessed(file_paths.len()),
+		JobReportUpdate::CompletedBytes(hashed_bytes),
 		JobReportUpdate::Message(format!(
 			"Processed {} of {} orphan Paths",
 			step_number * CHUNK_SIZE,
@@ -380,11 +678,25 @@ async fn process_identifier_file_paths(
 	Ok(())
 }
 
-fn finalize_file_identifier(report: &FileIdentifierReport, ctx: WorkerContext) -> JobResult {
+fn finalize_file_identifier(
+	report: &FileIdentifierReport,
+	location_id: i32,
+	sub_path: Option<&Path>,
+	ctx: WorkerContext,
+) -> JobResult {
 	info!("Finalizing identifier job: {report:?}");
 
+	if report.total_objects_created > 0 {
+		refresh_count_estimates(ctx.library.db.clone(), &[CountedTable::Object]);
+	}
+
+	// Only the listings of the identified directory and of its subdirectories changed
 	if report.total_orphan_paths > 0 {
-		invalidate_query!(ctx.library, "locations.getExplorerData");
+		invalidate_explorer_path(
+			&ctx.library,
+			location_id,
+			sub_path_materialized_path(location_id, &report.location_path, sub_path),
+		);
 	}
 
 	Ok(Some(serde_json::to_value(report)?))
@@ -1,5 +1,7 @@
 use crate::{
-	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	job::{
+		JobError, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
 	library::Library,
 	location::file_path_helper::{
 		ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
@@ -60,6 +62,10 @@ impl StatefulJob for ShallowFileIdentifierJob {
 		SHALLOW_FILE_IDENTIFIER_JOB_NAME
 	}
 
+	fn priority(&self, _init: &Self::Init) -> JobPriority {
+		JobPriority::Interactive
+	}
+
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
 		let Library { db, .. } = &ctx.library;
 
@@ -185,6 +191,8 @@ impl StatefulJob for ShallowFileIdentifierJob {
 				.as_ref()
 				.expect("critical error: missing data on job state")
 				.report,
+			state.init.location.id,
+			Some(state.init.sub_path.as_path()),
 			ctx,
 		)
 	}
@@ -198,6 +206,10 @@ fn orphan_path_filters(
 	let mut params = vec![
 		file_path::object_id::equals(None),
 		file_path::is_dir::equals(false),
+		// Symlinks indexed as links don't have contents of their own to be identified
+		file_path::is_symlink::equals(false),
+		// The entries of archives can't be read without extracting them
+		file_path::in_archive::equals(false),
 		file_path::location_id::equals(location_id),
 		file_path::parent_id::equals(Some(sub_path_id)),
 	];
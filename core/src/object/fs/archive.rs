@@ -0,0 +1,592 @@
+use crate::{
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	location::archive::ArchiveError,
+};
+
+use std::{
+	ffi::OsString,
+	fs::{self, File},
+	io::{self, BufReader},
+	path::{Component, Path, PathBuf},
+	sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::task::spawn_blocking;
+use tracing::{info, warn};
+
+use super::{
+	context_menu_fs_info, get_path_from_location_id, osstr_to_string, scan_target_directory,
+	scan_target_tree,
+};
+
+pub const COMPRESS_JOB_NAME: &str = "file_compressor";
+pub const EXTRACT_JOB_NAME: &str = "file_extractor";
+
+/// How many entries are written to or read from an archive at each step
+const ENTRIES_PER_STEP: usize = 100;
+
+/// How many entries of a tar.zst archive are extracted at each step. Every step reads the archive from its start,
+/// so they're bigger than the steps of zip archives.
+const TAR_ENTRIES_PER_STEP: usize = 1000;
+
+/// The archive formats files can be compressed to and extracted from
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArchiveFormat {
+	Zip,
+	TarZst,
+}
+
+impl ArchiveFormat {
+	pub fn extension(&self) -> &'static str {
+		match self {
+			Self::Zip => "zip",
+			Self::TarZst => "tar.zst",
+		}
+	}
+
+	pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+		let name = path.as_ref().file_name()?.to_str()?.to_lowercase();
+
+		[Self::Zip, Self::TarZst]
+			.into_iter()
+			.find(|format| name.ends_with(&format!(".{}", format.extension())))
+	}
+}
+
+/// A file or directory to be added to an archive, with its path inside of the archive
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompressEntry {
+	path: PathBuf,
+	name: PathBuf,
+	is_dir: bool,
+}
+
+/// archive_entry_name joins the components of a path inside of an archive with `/`, whatever the platform.
+fn archive_entry_name(name: &Path) -> String {
+	name.components()
+		.map(|component| component.as_os_str().to_string_lossy())
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+enum ArchiveWriter {
+	Zip(zip::ZipWriter<File>),
+	TarZst(tar::Builder<zstd::Encoder<'static, File>>),
+}
+
+impl ArchiveWriter {
+	fn create(path: &Path, format: ArchiveFormat) -> io::Result<Self> {
+		let file = File::create(path)?;
+
+		Ok(match format {
+			ArchiveFormat::Zip => Self::Zip(zip::ZipWriter::new(file)),
+			ArchiveFormat::TarZst => Self::TarZst(tar::Builder::new(zstd::Encoder::new(
+				file,
+				zstd::DEFAULT_COMPRESSION_LEVEL,
+			)?)),
+		})
+	}
+
+	fn add(&mut self, entry: &CompressEntry) -> Result<(), ArchiveError> {
+		match self {
+			Self::Zip(writer) => {
+				let options = zip::write::FileOptions::default()
+					.compression_method(zip::CompressionMethod::Deflated)
+					.large_file(true);
+				let name = archive_entry_name(&entry.name);
+
+				if entry.is_dir {
+					writer.add_directory(name, options)?;
+				} else {
+					writer.start_file(name, options)?;
+					io::copy(&mut BufReader::new(File::open(&entry.path)?), writer)?;
+				}
+			}
+			Self::TarZst(builder) => builder.append_path_with_name(&entry.path, &entry.name)?,
+		}
+
+		Ok(())
+	}
+
+	fn finish(self) -> Result<(), ArchiveError> {
+		match self {
+			Self::Zip(mut writer) => {
+				writer.finish()?;
+			}
+			Self::TarZst(builder) => {
+				builder.into_inner()?.finish()?;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+/// FileCompressorJob compresses a file or a directory of a location into a zip or tar.zst archive in one of its directories.
+/// The archive is kept open between the steps, so a resumed job writes it again from the start.
+#[derive(Default)]
+pub struct FileCompressorJob {
+	writer: Mutex<Option<ArchiveWriter>>,
+}
+
+#[derive(Serialize, Deserialize, Hash, Type)]
+pub struct FileCompressorJobInit {
+	pub location_id: i32,
+	pub path_id: i32,
+	/// target_path is the directory of the location the archive is written to
+	pub target_path: PathBuf,
+	pub format: ArchiveFormat,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileCompressorJobState {
+	archive_path: PathBuf,
+	entries: Vec<CompressEntry>,
+}
+
+/// FileCompressorJobStep is a range of the entries of the job state to be added to the archive
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileCompressorJobStep {
+	start: usize,
+	end: usize,
+}
+
+/// compress_entries lists `path` and everything under it, with their paths inside of the archive starting at `name`.
+/// Symlinks and special files are left out.
+async fn compress_entries(path: PathBuf, name: PathBuf) -> io::Result<Vec<CompressEntry>> {
+	let mut entries = vec![];
+	let mut dirs = vec![];
+
+	let metadata = tokio::fs::symlink_metadata(&path).await?;
+	if metadata.is_dir() {
+		dirs.push((path.clone(), name.clone()));
+	}
+	entries.push(CompressEntry {
+		path,
+		name,
+		is_dir: metadata.is_dir(),
+	});
+
+	while let Some((dir, dir_name)) = dirs.pop() {
+		let mut read_dir = tokio::fs::read_dir(&dir).await?;
+		while let Some(entry) = read_dir.next_entry().await? {
+			let file_type = entry.file_type().await?;
+			if !file_type.is_dir() && !file_type.is_file() {
+				continue;
+			}
+
+			let entry = CompressEntry {
+				name: dir_name.join(entry.file_name()),
+				path: entry.path(),
+				is_dir: file_type.is_dir(),
+			};
+			if entry.is_dir {
+				dirs.push((entry.path.clone(), entry.name.clone()));
+			}
+			entries.push(entry);
+		}
+	}
+
+	Ok(entries)
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for FileCompressorJob {
+	type Init = FileCompressorJobInit;
+	type Data = FileCompressorJobState;
+	type Step = FileCompressorJobStep;
+
+	fn name(&self) -> &'static str {
+		COMPRESS_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let source_fs_info =
+			context_menu_fs_info(&ctx.library.db, state.init.location_id, state.init.path_id)
+				.await?;
+
+		let file_name = osstr_to_string(source_fs_info.fs_path.file_name())?;
+		let archive_path = get_path_from_location_id(&ctx.library.db, state.init.location_id)
+			.await?
+			.join(&state.init.target_path)
+			.join(format!("{file_name}.{}", state.init.format.extension()));
+
+		// Existing files are never overwritten
+		if tokio::fs::metadata(&archive_path).await.is_ok() {
+			return Err(ArchiveError::AlreadyExists(archive_path).into());
+		}
+
+		let entries = compress_entries(source_fs_info.fs_path, PathBuf::from(file_name)).await?;
+
+		state.steps = (0..entries.len())
+			.step_by(ENTRIES_PER_STEP)
+			.map(|start| FileCompressorJobStep {
+				start,
+				end: (start + ENTRIES_PER_STEP).min(entries.len()),
+			})
+			.collect();
+
+		state.data = Some(FileCompressorJobState {
+			archive_path,
+			entries,
+		});
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+		let data = state.data.as_ref().expect("fatal: missing job state");
+
+		let writer = self
+			.writer
+			.lock()
+			.expect("critical error: poisoned archive writer")
+			.take();
+
+		// Without a writer, it's the first step or the job was resumed, so the entries of the previous steps are written again
+		let (writer, start) = match writer {
+			Some(writer) => (writer, step.start),
+			None => (
+				ArchiveWriter::create(&data.archive_path, state.init.format)?,
+				0,
+			),
+		};
+
+		let entries = data.entries[start..step.end].to_vec();
+		let writer = spawn_blocking(move || {
+			let mut writer = writer;
+			for entry in &entries {
+				writer.add(entry)?;
+			}
+			Ok::<_, ArchiveError>(writer)
+		})
+		.await??;
+
+		*self
+			.writer
+			.lock()
+			.expect("critical error: poisoned archive writer") = Some(writer);
+
+		ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(state.step_number + 1),
+			JobReportUpdate::Message(format!(
+				"Compressed {} of {} files",
+				step.end,
+				data.entries.len()
+			)),
+		]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		let writer = self
+			.writer
+			.get_mut()
+			.expect("critical error: poisoned archive writer")
+			.take();
+		if let Some(writer) = writer {
+			spawn_blocking(move || writer.finish()).await??;
+		}
+
+		info!(
+			"Compressed {} files into {}",
+			data.entries.len(),
+			data.archive_path.display()
+		);
+
+		scan_target_directory(
+			&ctx.library,
+			state.init.location_id,
+			&state.init.target_path,
+		)
+		.await?;
+
+		Ok(Some(serde_json::to_value(&state.init)?))
+	}
+}
+
+/// FileExtractorJob extracts a zip or tar.zst archive of a location into one of its directories.
+/// Files which already exist aren't overwritten.
+pub struct FileExtractorJob {}
+
+#[derive(Serialize, Deserialize, Hash, Type)]
+pub struct FileExtractorJobInit {
+	pub location_id: i32,
+	pub path_id: i32,
+	/// target_path is the directory of the location the archive is extracted into
+	pub target_path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileExtractorJobState {
+	archive_path: PathBuf,
+	target_dir: PathBuf,
+	extracted: usize,
+	/// Files left out as they already exist
+	skipped: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum FileExtractorJobStep {
+	/// A range of the entries of a zip archive, which can be read in any order
+	ZipEntries { start: usize, end: usize },
+	/// A range of the entries of a tar.zst archive, which is read through from the start to get to them
+	TarZstEntries { start: usize, end: usize },
+}
+
+/// ExtractedEntry tells what happened to an entry of the archive
+enum ExtractedEntry {
+	Extracted,
+	Skipped,
+}
+
+/// extract_zip_entry extracts the entry at `index` of a zip archive into `target_dir`,
+/// leaving out the entries which would be out of it.
+fn extract_zip_entry(
+	archive: &mut zip::ZipArchive<BufReader<File>>,
+	index: usize,
+	target_dir: &Path,
+) -> Result<ExtractedEntry, ArchiveError> {
+	let mut entry = archive.by_index(index)?;
+	let Some(name) = entry.enclosed_name().map(Path::to_path_buf) else {
+		warn!("Skipping zip entry out of the archive: {}", entry.name());
+		return Ok(ExtractedEntry::Skipped);
+	};
+	let path = target_dir.join(name);
+
+	if entry.is_dir() {
+		fs::create_dir_all(&path)?;
+		return Ok(ExtractedEntry::Extracted);
+	}
+
+	if path.symlink_metadata().is_ok() {
+		return Ok(ExtractedEntry::Skipped);
+	}
+	if let Some(parent) = path.parent() {
+		fs::create_dir_all(parent)?;
+	}
+
+	let extracting_path = extracting_path(&path);
+	io::copy(&mut entry, &mut File::create(&extracting_path)?)?;
+	fs::rename(&extracting_path, &path)?;
+
+	Ok(ExtractedEntry::Extracted)
+}
+
+/// extracting_path is where a file of an archive is written before it's renamed to `path`, so an interrupted
+/// extraction doesn't leave a truncated file, which would be skipped as already existing when the job is resumed.
+fn extracting_path(path: &Path) -> PathBuf {
+	let mut name = OsString::from(".");
+	name.push(path.file_name().unwrap_or_default());
+	name.push(".sdextract");
+
+	path.with_file_name(name)
+}
+
+/// extract_tar_zst extracts the entries from `start` to `end` of a tar.zst archive into `target_dir`,
+/// returning how many were extracted and skipped.
+fn extract_tar_zst(
+	archive_path: &Path,
+	target_dir: &Path,
+	start: usize,
+	end: usize,
+) -> Result<(usize, usize), ArchiveError> {
+	let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(archive_path)?)?);
+	let (mut extracted, mut skipped) = (0, 0);
+
+	for entry in archive.entries()?.skip(start).take(end - start) {
+		let mut entry = entry?;
+		let entry_path = entry.path()?.to_path_buf();
+
+		let is_enclosed = entry_path
+			.components()
+			.all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+		let path = target_dir.join(&entry_path);
+
+		if is_enclosed && entry.header().entry_type().is_file() {
+			if path.symlink_metadata().is_ok() {
+				skipped += 1;
+				continue;
+			}
+			if let Some(parent) = path.parent() {
+				fs::create_dir_all(parent)?;
+			}
+
+			let extracting_path = extracting_path(&path);
+			entry.unpack(&extracting_path)?;
+			fs::rename(&extracting_path, &path)?;
+			extracted += 1;
+			continue;
+		}
+
+		let exists = is_enclosed && !entry.header().entry_type().is_dir() && path.exists();
+
+		// `unpack_in` leaves out the entries which would be out of the target directory
+		if exists || !entry.unpack_in(target_dir)? {
+			skipped += 1;
+		} else {
+			extracted += 1;
+		}
+	}
+
+	Ok((extracted, skipped))
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for FileExtractorJob {
+	type Init = FileExtractorJobInit;
+	type Data = FileExtractorJobState;
+	type Step = FileExtractorJobStep;
+
+	fn name(&self) -> &'static str {
+		EXTRACT_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let archive_fs_info =
+			context_menu_fs_info(&ctx.library.db, state.init.location_id, state.init.path_id)
+				.await?;
+		let archive_path = archive_fs_info.fs_path;
+
+		let target_dir = get_path_from_location_id(&ctx.library.db, state.init.location_id)
+			.await?
+			.join(&state.init.target_path);
+
+		state.steps = match ArchiveFormat::from_path(&archive_path) {
+			Some(ArchiveFormat::Zip) => {
+				let entries_count = spawn_blocking({
+					let archive_path = archive_path.clone();
+					move || {
+						Ok::<_, ArchiveError>(
+							zip::ZipArchive::new(BufReader::new(File::open(archive_path)?))?.len(),
+						)
+					}
+				})
+				.await??;
+
+				(0..entries_count)
+					.step_by(ENTRIES_PER_STEP)
+					.map(|start| FileExtractorJobStep::ZipEntries {
+						start,
+						end: (start + ENTRIES_PER_STEP).min(entries_count),
+					})
+					.collect()
+			}
+			Some(ArchiveFormat::TarZst) => {
+				let entries_count = spawn_blocking({
+					let archive_path = archive_path.clone();
+					move || {
+						Ok::<_, ArchiveError>(
+							tar::Archive::new(zstd::Decoder::new(File::open(archive_path)?)?)
+								.entries()?
+								.count(),
+						)
+					}
+				})
+				.await??;
+
+				(0..entries_count)
+					.step_by(TAR_ENTRIES_PER_STEP)
+					.map(|start| FileExtractorJobStep::TarZstEntries {
+						start,
+						end: (start + TAR_ENTRIES_PER_STEP).min(entries_count),
+					})
+					.collect()
+			}
+			None => return Err(ArchiveError::UnsupportedFormat(archive_path).into()),
+		};
+
+		state.data = Some(FileExtractorJobState {
+			archive_path,
+			target_dir,
+			extracted: 0,
+			skipped: 0,
+		});
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let data = state.data.as_mut().expect("fatal: missing job state");
+		let archive_path = data.archive_path.clone();
+		let target_dir = data.target_dir.clone();
+
+		let (extracted, skipped) = match state.steps[0] {
+			FileExtractorJobStep::ZipEntries { start, end } => {
+				spawn_blocking(move || {
+					let mut archive =
+						zip::ZipArchive::new(BufReader::new(File::open(archive_path)?))?;
+					let (mut extracted, mut skipped) = (0, 0);
+					for index in start..end {
+						match extract_zip_entry(&mut archive, index, &target_dir)? {
+							ExtractedEntry::Extracted => extracted += 1,
+							ExtractedEntry::Skipped => skipped += 1,
+						}
+					}
+					Ok::<_, ArchiveError>((extracted, skipped))
+				})
+				.await??
+			}
+			FileExtractorJobStep::TarZstEntries { start, end } => {
+				spawn_blocking(move || extract_tar_zst(&archive_path, &target_dir, start, end))
+					.await??
+			}
+		};
+
+		data.extracted += extracted;
+		data.skipped += skipped;
+
+		ctx.progress(vec![
+			JobReportUpdate::CompletedTaskCount(state.step_number + 1),
+			JobReportUpdate::Message(format!("Extracted {} files", data.extracted)),
+		]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!(
+			"Extracted {} files of {} into {}, {} already existing skipped",
+			data.extracted,
+			data.archive_path.display(),
+			data.target_dir.display(),
+			data.skipped
+		);
+
+		// The archive can hold whole directory trees
+		scan_target_tree(
+			&ctx.library,
+			state.init.location_id,
+			&state.init.target_path,
+		)
+		.await?;
+
+		Ok(Some(serde_json::to_value(data)?))
+	}
+}
@@ -1,54 +1,191 @@
-use crate::job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext};
+use crate::{
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	util::rate_limit::{limit_read, read_limited},
+};
 
-use std::{hash::Hash, path::PathBuf};
+use std::{
+	hash::Hash,
+	path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
-use tokio::sync::oneshot;
-use tracing::{error, trace};
-
-use super::{context_menu_fs_info, get_path_from_location_id, osstr_to_string, FsInfo};
+use tokio::{
+	fs::{self, File},
+	io::{self, AsyncReadExt, AsyncWriteExt},
+	sync::oneshot,
+};
+use tracing::{error, info, trace};
+
+use super::{
+	context_menu_fs_info, get_path_from_location_id, osstr_to_string, scan_target_directory,
+	scan_target_tree,
+};
 
 pub struct FileCopierJob {
 	pub done_tx: Option<oneshot::Sender<()>>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct FileCopierJobState {
-	pub target_path: PathBuf, // target dir prefix too
-	pub source_fs_info: FsInfo,
+	copied: usize,
+	/// Files and directories left out by the [`ConflictPolicy::Skip`] policy
+	skipped: usize,
+	copied_bytes: u64,
+	/// Bytes of all the files found to copy so far, as the files of copied directories are only found while copying
+	#[serde(default)]
+	total_bytes: u64,
+	/// Whether a directory was copied, whose whole tree is indexed once the job is done
+	#[serde(default)]
+	copied_directories: bool,
+}
+
+/// ConflictPolicy tells what to do with a copied file or directory whose name is already taken in the target directory
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, Default, Hash, PartialEq, Eq)]
+pub enum ConflictPolicy {
+	/// Leaves the existing file as it is, without copying the new one
+	Skip,
+	/// Copies the new file with a number added to its name, like `file (1).txt`
+	#[default]
+	Rename,
+	/// Replaces the existing file, and merges the contents of directories
+	Overwrite,
 }
 
 #[derive(Serialize, Deserialize, Hash, Type)]
 pub struct FileCopierJobInit {
 	pub source_location_id: i32,
-	pub source_path_id: i32,
+	pub source_path_ids: Vec<i32>,
 	pub target_location_id: i32,
 	pub target_path: PathBuf,
 	pub target_file_name_suffix: Option<String>,
+	#[serde(default)]
+	pub conflict_policy: ConflictPolicy,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum FileCopierJobStep {
-	Directory { path: PathBuf },
-	File { path: PathBuf },
+	Directory { source: PathBuf, target: PathBuf },
+	File { source: PathBuf, target: PathBuf },
+	/// Symlinks are copied as links, so a link to a directory can't make a copy walk into itself
+	Symlink { source: PathBuf, target: PathBuf },
 }
 
-impl From<FsInfo> for FileCopierJobStep {
-	fn from(value: FsInfo) -> Self {
-		if value.path_data.is_dir {
-			Self::Directory {
-				path: value.fs_path,
-			}
+pub const COPY_JOB_NAME: &str = "file_copier";
+
+/// How much of a file is read at once when copies are read limited
+const LIMITED_COPY_BLOCK_LEN: usize = 1024 * 1024;
+
+/// copy_file copies a file like [`fs::copy`], returning how many bytes were copied. While reads are limited,
+/// it's copied block by block so each block waits for the limit, instead of in a single call.
+async fn copy_file(source: &Path, target: &Path) -> Result<u64, io::Error> {
+	if !read_limited() {
+		return fs::copy(source, target).await;
+	}
+
+	let mut reader = File::open(source).await?;
+	let mut writer = File::create(target).await?;
+	let mut buffer = vec![0; LIMITED_COPY_BLOCK_LEN].into_boxed_slice();
+	let mut copied = 0;
+	loop {
+		let read_count = reader.read(&mut buffer).await?;
+		if read_count == 0 {
+			break;
+		}
+		limit_read(read_count as u64).await;
+		writer.write_all(&buffer[..read_count]).await?;
+		copied += read_count as u64;
+	}
+	writer.flush().await?;
+
+	fs::set_permissions(target, reader.metadata().await?.permissions()).await?;
+
+	Ok(copied)
+}
+
+/// copy_symlink creates a symlink at `target` pointing where the `source` symlink points to,
+/// replacing the file the conflict policy let it overwrite.
+async fn copy_symlink(source: &Path, target: &Path) -> Result<(), io::Error> {
+	let link_target = fs::read_link(source).await?;
+
+	if fs::symlink_metadata(target).await.is_ok() {
+		fs::remove_file(target).await?;
+	}
+
+	#[cfg(unix)]
+	{
+		fs::symlink(link_target, target).await
+	}
+	#[cfg(windows)]
+	{
+		if fs::metadata(source).await.map_or(false, |metadata| metadata.is_dir()) {
+			fs::symlink_dir(link_target, target).await
 		} else {
-			Self::File {
-				path: value.fs_path,
-			}
+			fs::symlink_file(link_target, target).await
 		}
 	}
 }
 
-pub const COPY_JOB_NAME: &str = "file_copier";
+/// renamed_target finds the first free name for `target`, adding ` (1)`, ` (2)` and so on before its extension.
+pub(crate) async fn renamed_target(target: &Path, is_dir: bool) -> Result<PathBuf, JobError> {
+	let (stem, extension) = if is_dir {
+		(osstr_to_string(target.file_name())?, String::new())
+	} else {
+		(
+			osstr_to_string(target.file_stem())?,
+			target.extension().map_or_else(
+				|| Ok(String::new()),
+				|ext| {
+					ext.to_str()
+						.map(|ext| format!(".{ext}"))
+						.ok_or(JobError::OsStr)
+				},
+			)?,
+		)
+	};
+
+	let mut number = 1;
+	loop {
+		let renamed = target.with_file_name(format!("{stem} ({number}){extension}"));
+		if fs::symlink_metadata(&renamed).await.is_err() {
+			return Ok(renamed);
+		}
+		number += 1;
+	}
+}
+
+/// resolve_conflict returns where a file or directory must be copied to by the conflict policy,
+/// or `None` if it must be skipped.
+async fn resolve_conflict(
+	source: &Path,
+	target: PathBuf,
+	is_dir: bool,
+	policy: ConflictPolicy,
+) -> Result<Option<PathBuf>, JobError> {
+	let Ok(metadata) = fs::symlink_metadata(&target).await else {
+		return Ok(Some(target));
+	};
+
+	// Overwriting a file with itself would empty it, so it's copied next to itself instead
+	let is_source = fs::canonicalize(&target).await? == fs::canonicalize(source).await?;
+
+	Ok(match policy {
+		ConflictPolicy::Skip => None,
+		ConflictPolicy::Overwrite if !is_source => {
+			if metadata.is_dir() != is_dir {
+				if metadata.is_dir() {
+					fs::remove_dir_all(&target).await?;
+				} else {
+					fs::remove_file(&target).await?;
+				}
+			}
+			Some(target)
+		}
+		ConflictPolicy::Rename | ConflictPolicy::Overwrite => {
+			Some(renamed_target(&target, is_dir).await?)
+		}
+	})
+}
 
 #[async_trait::async_trait]
 impl StatefulJob for FileCopierJob {
@@ -61,50 +198,79 @@ impl StatefulJob for FileCopierJob {
 	}
 
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
-		let source_fs_info = context_menu_fs_info(
-			&ctx.library.db,
-			state.init.source_location_id,
-			state.init.source_path_id,
-		)
-		.await?;
-
 		let mut full_target_path =
 			get_path_from_location_id(&ctx.library.db, state.init.target_location_id).await?;
 
 		// add the currently viewed subdirectory to the location root
 		full_target_path.push(&state.init.target_path);
 
-		// extension wizardry for cloning and such
-		// if no suffix has been selected, just use the file name
-		// if a suffix is provided and it's a directory, use the directory name + suffix
-		// if a suffix is provided and it's a file, use the (file name + suffix).extension
-		let file_name = osstr_to_string(source_fs_info.fs_path.file_name())?;
-
-		let target_file_name = state.init.target_file_name_suffix.as_ref().map_or_else(
-			|| Ok::<_, JobError>(file_name.clone()),
-			|suffix| {
-				Ok(if source_fs_info.path_data.is_dir {
-					format!("{file_name}{suffix}")
-				} else {
-					osstr_to_string(source_fs_info.fs_path.file_stem())?
-						+ suffix + &source_fs_info.fs_path.extension().map_or_else(
-						|| Ok(String::new()),
-						|ext| ext.to_str().map(|e| format!(".{e}")).ok_or(JobError::OsStr),
-					)?
-				})
-			},
-		)?;
+		let mut total_bytes = 0;
 
-		full_target_path.push(target_file_name);
+		for &source_path_id in &state.init.source_path_ids {
+			let source_fs_info = context_menu_fs_info(
+				&ctx.library.db,
+				state.init.source_location_id,
+				source_path_id,
+			)
+			.await?;
+
+			if source_fs_info.path_data.is_dir
+				&& full_target_path.starts_with(&source_fs_info.fs_path)
+			{
+				return Err(JobError::CopyIntoItself(source_fs_info.fs_path));
+			}
+
+			// extension wizardry for cloning and such
+			// if no suffix has been selected, just use the file name
+			// if a suffix is provided and it's a directory, use the directory name + suffix
+			// if a suffix is provided and it's a file, use the (file name + suffix).extension
+			let file_name = osstr_to_string(source_fs_info.fs_path.file_name())?;
+
+			let target_file_name = state.init.target_file_name_suffix.as_ref().map_or_else(
+				|| Ok::<_, JobError>(file_name.clone()),
+				|suffix| {
+					Ok(if source_fs_info.path_data.is_dir {
+						format!("{file_name}{suffix}")
+					} else {
+						osstr_to_string(source_fs_info.fs_path.file_stem())?
+							+ suffix + &source_fs_info.fs_path.extension().map_or_else(
+							|| Ok(String::new()),
+							|ext| ext.to_str().map(|e| format!(".{e}")).ok_or(JobError::OsStr),
+						)?
+					})
+				},
+			)?;
+
+			let target = full_target_path.join(target_file_name);
+			let metadata = fs::symlink_metadata(&source_fs_info.fs_path).await?;
+			state.steps.push_back(if metadata.is_symlink() {
+				FileCopierJobStep::Symlink {
+					source: source_fs_info.fs_path,
+					target,
+				}
+			} else if source_fs_info.path_data.is_dir {
+				FileCopierJobStep::Directory {
+					source: source_fs_info.fs_path,
+					target,
+				}
+			} else {
+				total_bytes += metadata.len();
+				FileCopierJobStep::File {
+					source: source_fs_info.fs_path,
+					target,
+				}
+			});
+		}
 
 		state.data = Some(FileCopierJobState {
-			target_path: full_target_path,
-			source_fs_info: source_fs_info.clone(),
+			total_bytes,
+			..Default::default()
 		});
 
-		state.steps = [source_fs_info.into()].into_iter().collect();
-
-		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(state.steps.len()),
+			JobReportUpdate::TotalBytes(total_bytes),
+		]);
 
 		Ok(())
 	}
@@ -114,61 +280,92 @@ impl StatefulJob for FileCopierJob {
 		ctx: WorkerContext,
 		state: &mut JobState<Self>,
 	) -> Result<(), JobError> {
-		let step = &state.steps[0];
+		let step = state.steps[0].clone();
+		let policy = state.init.conflict_policy;
 
-		let job_state = state.data.as_ref().ok_or(JobError::MissingData {
+		let job_state = state.data.as_mut().ok_or(JobError::MissingData {
 			value: String::from("job state"),
 		})?;
 
 		match step {
-			FileCopierJobStep::File { path } => {
-				let mut target_path = job_state.target_path.clone();
-
-				if job_state.source_fs_info.path_data.is_dir {
-					// if root type is a dir, we need to preserve structure by making paths relative
-					target_path.push(
-						path.strip_prefix(&job_state.source_fs_info.fs_path)
-							.map_err(|_| JobError::Path)?,
-					);
+			FileCopierJobStep::File { source, target } => {
+				ctx.progress(vec![JobReportUpdate::Message(format!(
+					"Copying {}",
+					source.display()
+				))]);
+
+				match resolve_conflict(&source, target, false, policy).await? {
+					Some(target) => {
+						trace!("Copying from {:?} to {:?}", source, target);
+
+						let copied_bytes = copy_file(&source, &target).await?;
+						job_state.copied_bytes += copied_bytes;
+						job_state.copied += 1;
+
+						ctx.progress(vec![
+							JobReportUpdate::CompletedBytes(copied_bytes),
+							JobReportUpdate::BytesRead(copied_bytes),
+						]);
+					}
+					None => {
+						job_state.skipped += 1;
+
+						// Skipped files are done with as well, or the remaining time would never reach zero
+						ctx.progress(vec![JobReportUpdate::CompletedBytes(
+							fs::symlink_metadata(&source).await?.len(),
+						)]);
+					}
 				}
-
-				trace!("Copying from {:?} to {:?}", path, target_path);
-
-				tokio::fs::copy(&path, &target_path).await?;
 			}
-			FileCopierJobStep::Directory { path } => {
-				// if this is the very first path, create the target dir
-				// fixes copying dirs with no child directories
-				if job_state.source_fs_info.path_data.is_dir
-					&& &job_state.source_fs_info.fs_path == path
-				{
-					tokio::fs::create_dir_all(&job_state.target_path).await?;
+			FileCopierJobStep::Directory { source, target } => {
+				match resolve_conflict(&source, target, true, policy).await? {
+					Some(target) => {
+						fs::create_dir_all(&target).await?;
+
+						let mut dir = fs::read_dir(&source).await?;
+						while let Some(entry) = dir.next_entry().await? {
+							let target = target.join(entry.file_name());
+							let metadata = fs::symlink_metadata(entry.path()).await?;
+
+							state.steps.push_back(if metadata.is_symlink() {
+								FileCopierJobStep::Symlink {
+									source: entry.path(),
+									target,
+								}
+							} else if metadata.is_dir() {
+								FileCopierJobStep::Directory {
+									source: entry.path(),
+									target,
+								}
+							} else {
+								job_state.total_bytes += metadata.len();
+								FileCopierJobStep::File {
+									source: entry.path(),
+									target,
+								}
+							});
+
+							ctx.progress(vec![
+								JobReportUpdate::TaskCount(state.steps.len()),
+								JobReportUpdate::TotalBytes(job_state.total_bytes),
+							]);
+						}
+
+						job_state.copied += 1;
+						job_state.copied_directories = true;
+					}
+					None => job_state.skipped += 1,
 				}
-
-				let mut dir = tokio::fs::read_dir(&path).await?;
-
-				while let Some(entry) = dir.next_entry().await? {
-					if entry.metadata().await?.is_dir() {
-						state
-							.steps
-							.push_back(FileCopierJobStep::Directory { path: entry.path() });
-
-						tokio::fs::create_dir_all(
-							job_state.target_path.join(
-								entry
-									.path()
-									.strip_prefix(&job_state.source_fs_info.fs_path)
-									.map_err(|_| JobError::Path)?,
-							),
-						)
-						.await?;
-					} else {
-						state
-							.steps
-							.push_back(FileCopierJobStep::File { path: entry.path() });
-					};
-
-					ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+			}
+			FileCopierJobStep::Symlink { source, target } => {
+				match resolve_conflict(&source, target, false, policy).await? {
+					Some(target) => {
+						trace!("Copying symlink from {:?} to {:?}", source, target);
+
+						copy_symlink(&source, &target).await?;
+						job_state.copied += 1;
+					}
+					None => job_state.skipped += 1,
 				}
 			}
 		};
@@ -179,13 +376,40 @@ impl StatefulJob for FileCopierJob {
 		Ok(())
 	}
 
-	async fn finalize(&mut self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!(
+			"Copied {} files and directories ({} bytes), {} skipped",
+			data.copied, data.copied_bytes, data.skipped
+		);
+
+		// The contents of copied directories are indexed as well, which a shallow scan of the target wouldn't do
+		if data.copied_directories {
+			scan_target_tree(
+				&ctx.library,
+				state.init.target_location_id,
+				&state.init.target_path,
+			)
+			.await?;
+		} else {
+			scan_target_directory(
+				&ctx.library,
+				state.init.target_location_id,
+				&state.init.target_path,
+			)
+			.await?;
+		}
+
 		if let Some(done_tx) = self.done_tx.take() {
 			if done_tx.send(()).is_err() {
 				error!("Failed to send done signal on FileCopierJob");
 			}
 		}
 
-		Ok(Some(serde_json::to_value(&state.init)?))
+		Ok(Some(serde_json::to_value(data)?))
 	}
 }
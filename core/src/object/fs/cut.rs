@@ -1,24 +1,68 @@
-use crate::job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext};
+use crate::{
+	invalidate_query,
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::Library,
+	location::{
+		delete_directory,
+		file_path_helper::{
+			file_path_in_subtree, file_path_with_object, get_existing_file_path_id,
+			move_file_path_descendants, MaterializedPath,
+		},
+		find_location, LocationError,
+	},
+	object::validation::hash::file_checksum,
+	prisma::{file_path, location},
+	sync,
+};
 
-use std::{hash::Hash, path::PathBuf};
+use std::{
+	collections::HashMap,
+	hash::Hash,
+	path::{Path, PathBuf},
+};
 
+use prisma_client_rust::Direction;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use specta::Type;
-use tracing::trace;
+use tokio::{fs, io};
+use tracing::{info, trace, warn};
+use uuid::Uuid;
 
-use super::{context_menu_fs_info, get_path_from_location_id, FsInfo};
+use super::{
+	context_menu_fs_info, copy::renamed_target, copy::ConflictPolicy, get_path_from_location_id,
+	scan_target_directory, FsInfo, PlannedChange,
+};
 
+/// FileCutterJob moves files and directories with `rename` when they stay in the same volume, and copies them,
+/// checks the copies and deletes the originals when they don't. Their `file_path`s are moved along with them,
+/// keeping their objects, and so their tags and notes.
 pub struct FileCutterJob {}
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct FileCutterJobState {}
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct FileCutterJobState {
+	moved: usize,
+	/// Files and directories copied to another volume, and deleted from the source one
+	moved_across_devices: usize,
+	/// Files and directories left out by the [`ConflictPolicy::Skip`] policy
+	skipped: usize,
+	dry_run: bool,
+	/// The changes a dry run would have made
+	changes: Vec<PlannedChange>,
+}
 
 #[derive(Serialize, Deserialize, Hash, Type)]
 pub struct FileCutterJobInit {
 	pub source_location_id: i32,
-	pub source_path_id: i32,
+	pub source_path_ids: Vec<i32>,
 	pub target_location_id: i32,
 	pub target_path: PathBuf,
+	#[serde(default)]
+	pub conflict_policy: ConflictPolicy,
+	/// dry_run only lists the moves, overwrites and skips which would be made in the job report, without moving anything
+	#[serde(default)]
+	pub dry_run: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -40,23 +84,39 @@ impl StatefulJob for FileCutterJob {
 	}
 
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
-		let source_fs_info = context_menu_fs_info(
-			&ctx.library.db,
-			state.init.source_location_id,
-			state.init.source_path_id,
-		)
-		.await?;
-
 		let mut full_target_path =
 			get_path_from_location_id(&ctx.library.db, state.init.target_location_id).await?;
 		full_target_path.push(&state.init.target_path);
 
-		state.steps = [FileCutterJobStep {
-			source_fs_info,
-			target_directory: full_target_path,
-		}]
-		.into_iter()
-		.collect();
+		for &source_path_id in &state.init.source_path_ids {
+			let source_fs_info = context_menu_fs_info(
+				&ctx.library.db,
+				state.init.source_location_id,
+				source_path_id,
+			)
+			.await?;
+
+			if source_fs_info.path_data.is_dir
+				&& full_target_path.starts_with(&source_fs_info.fs_path)
+			{
+				return Err(JobError::CopyIntoItself(source_fs_info.fs_path));
+			}
+
+			// Moving a file into the directory it already is in doesn't do anything
+			if source_fs_info.fs_path.parent() == Some(full_target_path.as_path()) {
+				continue;
+			}
+
+			state.steps.push_back(FileCutterJobStep {
+				source_fs_info,
+				target_directory: full_target_path.clone(),
+			});
+		}
+
+		state.data = Some(FileCutterJobState {
+			dry_run: state.init.dry_run,
+			..Default::default()
+		});
 
 		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
 
@@ -70,14 +130,125 @@ impl StatefulJob for FileCutterJob {
 	) -> Result<(), JobError> {
 		let step = &state.steps[0];
 		let source_info = &step.source_fs_info;
+		let is_dir = source_info.path_data.is_dir;
+		let library = &ctx.library;
+
+		let data = state.data.as_mut().ok_or(JobError::MissingData {
+			value: String::from("job state"),
+		})?;
+
+		ctx.progress(vec![JobReportUpdate::Message(format!(
+			"Moving {}",
+			source_info.fs_path.display()
+		))]);
 
-		let full_output = step
+		let source_location = find_location(library, state.init.source_location_id)
+			.exec()
+			.await?
+			.ok_or(LocationError::IdNotFound(state.init.source_location_id))?;
+		let target_location = find_location(library, state.init.target_location_id)
+			.exec()
+			.await?
+			.ok_or(LocationError::IdNotFound(state.init.target_location_id))?;
+
+		let target = step
 			.target_directory
 			.join(source_info.fs_path.file_name().ok_or(JobError::OsStr)?);
 
-		trace!("Cutting {:?} to {:?}", source_info.fs_path, full_output);
+		let target = match fs::symlink_metadata(&target).await {
+			Err(_) => target,
+			Ok(metadata) => match state.init.conflict_policy {
+				ConflictPolicy::Skip => {
+					data.skipped += 1;
+					if state.init.dry_run {
+						data.changes.push(PlannedChange::Skip {
+							path: source_info.fs_path.clone(),
+						});
+					}
+					ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+						state.step_number + 1,
+					)]);
+					return Ok(());
+				}
+				ConflictPolicy::Rename => renamed_target(&target, is_dir).await?,
+				ConflictPolicy::Overwrite if state.init.dry_run => {
+					data.changes.push(PlannedChange::Overwrite {
+						path: target.clone(),
+					});
+					target
+				}
+				// Directories are merged, so only the conflicting files in them are replaced
+				ConflictPolicy::Overwrite if is_dir && metadata.is_dir() => target,
+				ConflictPolicy::Overwrite => {
+					remove_overwritten(library, &target_location, &target, metadata.is_dir())
+						.await?;
+					target
+				}
+			},
+		};
+
+		if state.init.dry_run {
+			data.changes.push(PlannedChange::Move {
+				from: source_info.fs_path.clone(),
+				to: target,
+			});
+			data.moved += 1;
+
+			ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+				state.step_number + 1,
+			)]);
+			return Ok(());
+		}
+
+		// The watchers would index the moved files as new ones, while their file paths are moved below
+		let _source_guard = library
+			.location_manager()
+			.temporary_stop(source_location.id, library.clone())
+			.await?;
+		let _target_guard = if target_location.id != source_location.id {
+			Some(
+				library
+					.location_manager()
+					.temporary_stop(target_location.id, library.clone())
+					.await?,
+			)
+		} else {
+			None
+		};
+
+		trace!("Moving {:?} to {:?}", source_info.fs_path, target);
+
+		// Only an existing directory is left at the target when it's to be merged
+		let moved_across_devices = if is_dir && fs::symlink_metadata(&target).await.is_ok() {
+			merge_directory(
+				library,
+				&source_info.path_data,
+				&source_location,
+				&target_location,
+				&source_info.fs_path,
+				&target,
+			)
+			.await?
+		} else {
+			let moved_across_devices = move_path(&source_info.fs_path, &target).await?;
 
-		tokio::fs::rename(&source_info.fs_path, &full_output).await?;
+			move_file_paths(
+				library,
+				&source_info.path_data,
+				&source_location,
+				&target_location,
+				&target,
+			)
+			.await?;
+
+			moved_across_devices
+		};
+
+		if moved_across_devices {
+			data.moved_across_devices += 1;
+		} else {
+			data.moved += 1;
+		}
 
 		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
 			state.step_number + 1,
@@ -85,7 +256,500 @@ impl StatefulJob for FileCutterJob {
 		Ok(())
 	}
 
-	async fn finalize(&mut self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
-		Ok(Some(serde_json::to_value(&state.init)?))
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		if data.dry_run {
+			info!(
+				"Dry run would move {} files and directories, {} skipped",
+				data.moved, data.skipped
+			);
+
+			return Ok(Some(serde_json::to_value(data)?));
+		}
+
+		info!(
+			"Moved {} files and directories ({} across devices), {} skipped",
+			data.moved + data.moved_across_devices,
+			data.moved_across_devices,
+			data.skipped
+		);
+
+		scan_target_directory(
+			&ctx.library,
+			state.init.target_location_id,
+			&state.init.target_path,
+		)
+		.await?;
+
+		invalidate_query!(ctx.library, "locations.getExplorerData");
+
+		Ok(Some(serde_json::to_value(data)?))
+	}
+}
+
+/// is_cross_device tells if `rename` failed because the source and the target are on different volumes
+fn is_cross_device(e: &io::Error) -> bool {
+	#[cfg(unix)]
+	const CROSS_DEVICE_ERROR: i32 = 18; // EXDEV
+	#[cfg(windows)]
+	const CROSS_DEVICE_ERROR: i32 = 17; // ERROR_NOT_SAME_DEVICE
+
+	e.raw_os_error() == Some(CROSS_DEVICE_ERROR)
+}
+
+/// move_path moves `source` to `target` with `rename`, or by copying it and deleting the original when they're
+/// on different volumes. Returns whether it was moved across volumes.
+async fn move_path(source: &Path, target: &Path) -> Result<bool, JobError> {
+	match fs::rename(source, target).await {
+		Ok(()) => Ok(false),
+		Err(e) if is_cross_device(&e) => {
+			if let Err(e) = copy_verified(source, target).await {
+				// The source is kept, so the copy made so far is dropped
+				if let Err(e) = remove_path(target).await {
+					warn!("Failed to remove the incomplete copy {target:?}: {e:#?}");
+				}
+				return Err(e);
+			}
+			remove_path(source).await?;
+			Ok(true)
+		}
+		Err(e) => Err(e.into()),
+	}
+}
+
+/// merge_directory moves the contents of the `source` directory into the existing `target` one for the
+/// [`ConflictPolicy::Overwrite`] policy. Directories found in both are merged in turn, and the other entries of
+/// `target` taking the name of a moved one are replaced. The emptied source directories are deleted afterwards.
+/// Returns whether anything was moved across volumes.
+async fn merge_directory(
+	library: &Library,
+	source: &file_path_with_object::Data,
+	source_location: &location::Data,
+	target_location: &location::Data,
+	source_path: &Path,
+	target_path: &Path,
+) -> Result<bool, JobError> {
+	let mut moved_across_devices = false;
+	let mut merged = vec![];
+	let mut pending = vec![(source_path.to_path_buf(), target_path.to_path_buf())];
+
+	while let Some((source_dir, target_dir)) = pending.pop() {
+		let mut entries = vec![];
+		let mut dir = fs::read_dir(&source_dir).await?;
+		while let Some(entry) = dir.next_entry().await? {
+			entries.push((entry.path(), entry.file_type().await?.is_dir()));
+		}
+
+		for (source, is_dir) in entries {
+			let target = target_dir.join(source.file_name().ok_or(JobError::OsStr)?);
+
+			match fs::symlink_metadata(&target).await {
+				Ok(metadata) if is_dir && metadata.is_dir() => {
+					pending.push((source, target));
+					continue;
+				}
+				Ok(metadata) => {
+					remove_overwritten(library, target_location, &target, metadata.is_dir())
+						.await?;
+				}
+				Err(_) => {}
+			}
+
+			let file_path = library
+				.db
+				.file_path()
+				.find_first(vec![
+					file_path::location_id::equals(source_location.id),
+					file_path::materialized_path::equals(
+						MaterializedPath::new(
+							source_location.id,
+							&source_location.path,
+							&source,
+							is_dir,
+						)
+						.map_err(LocationError::from)?
+						.into(),
+					),
+				])
+				.include(file_path_with_object::include())
+				.exec()
+				.await?;
+
+			moved_across_devices |= move_path(&source, &target).await?;
+
+			// Entries which weren't indexed yet are left for the scan of the target directory
+			if let Some(file_path) = file_path {
+				move_file_paths(library, &file_path, source_location, target_location, &target)
+					.await?;
+			}
+		}
+
+		merged.push(source_dir);
+	}
+
+	// Deepest directories first, as they're emptied
+	for dir in merged.iter().rev() {
+		fs::remove_dir(dir).await?;
+	}
+
+	// Only the merged directories are left in the subtree of the source one
+	let merged_ids = library
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(source_location.id),
+			file_path_in_subtree(source_location.id, source.id),
+		])
+		.select(file_path::select!({ id }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| file_path.id)
+		.collect();
+
+	delete_file_paths(library, source_location, merged_ids).await?;
+
+	Ok(moved_across_devices)
+}
+
+/// delete_file_paths deletes file paths of a location, syncing the deletes.
+async fn delete_file_paths(
+	library: &Library,
+	location: &location::Data,
+	ids: Vec<i32>,
+) -> Result<(), JobError> {
+	let Library { db, sync, .. } = library;
+
+	sync.write_ops(
+		db,
+		(
+			ids.iter()
+				.map(|&id| {
+					sync.shared_delete(sync::file_path::SyncId {
+						id,
+						location: sync::location::SyncId {
+							pub_id: location.pub_id.clone(),
+						},
+					})
+				})
+				.collect(),
+			db.file_path().delete_many(vec![
+				file_path::location_id::equals(location.id),
+				file_path::id::in_vec(ids),
+			]),
+		),
+	)
+	.await?;
+
+	Ok(())
+}
+
+async fn remove_path(path: &Path) -> io::Result<()> {
+	if fs::symlink_metadata(path).await?.is_dir() {
+		fs::remove_dir_all(path).await
+	} else {
+		fs::remove_file(path).await
 	}
 }
+
+/// copy_verified copies `source` to `target` recursively, checking every copied file against its source,
+/// as the source is deleted afterwards.
+async fn copy_verified(source: &Path, target: &Path) -> Result<(), JobError> {
+	let mut pending = vec![(source.to_path_buf(), target.to_path_buf())];
+
+	while let Some((source, target)) = pending.pop() {
+		let metadata = fs::symlink_metadata(&source).await?;
+
+		if metadata.is_dir() {
+			fs::create_dir_all(&target).await?;
+
+			let mut dir = fs::read_dir(&source).await?;
+			while let Some(entry) = dir.next_entry().await? {
+				pending.push((entry.path(), target.join(entry.file_name())));
+			}
+		} else if metadata.is_symlink() && cfg!(unix) {
+			// Links are moved as links, instead of as copies of what they point to
+			#[cfg(unix)]
+			fs::symlink(fs::read_link(&source).await?, &target).await?;
+		} else {
+			fs::copy(&source, &target).await?;
+
+			if file_checksum(&source).await? != file_checksum(&target).await? {
+				return Err(JobError::MoveVerification(source));
+			}
+		}
+	}
+
+	Ok(())
+}
+
+/// remove_overwritten deletes a file or directory replaced by the [`ConflictPolicy::Overwrite`] policy,
+/// along with its file paths.
+async fn remove_overwritten(
+	library: &Library,
+	location: &location::Data,
+	path: &Path,
+	is_dir: bool,
+) -> Result<(), JobError> {
+	if let Some(id) = get_existing_file_path_id(
+		MaterializedPath::new(location.id, &location.path, path, is_dir)
+			.map_err(LocationError::from)?,
+		&library.db,
+	)
+	.await
+	.map_err(LocationError::from)?
+	{
+		delete_directory(library, location.id, Some(id)).await?;
+	}
+
+	remove_path(path).await?;
+
+	Ok(())
+}
+
+/// move_file_paths moves the file path of a moved file or directory, and the ones of its contents, to `target`.
+/// They're updated in place within a location, and recreated with the same objects in another one.
+/// When the directory they were moved to isn't indexed, they're deleted and left for its next scan.
+async fn move_file_paths(
+	library: &Library,
+	source: &file_path_with_object::Data,
+	source_location: &location::Data,
+	target_location: &location::Data,
+	target: &Path,
+) -> Result<(), JobError> {
+	let Library {
+		db,
+		sync,
+		last_file_path_id_manager,
+		..
+	} = library;
+
+	let materialized_path = MaterializedPath::new(
+		target_location.id,
+		&target_location.path,
+		target,
+		source.is_dir,
+	)
+	.map_err(LocationError::from)?;
+
+	// Sorted by materialized path, so directories come before their contents
+	let file_paths = db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(source_location.id),
+			file_path_in_subtree(source_location.id, source.id),
+		])
+		.include(file_path_with_object::include())
+		.order_by(file_path::materialized_path::order(Direction::Asc))
+		.exec()
+		.await?;
+
+	let Some(parent_id) = get_existing_file_path_id(materialized_path.parent(), db)
+		.await
+		.map_err(LocationError::from)?
+	else {
+		warn!(
+			"The directory {target:?} was moved to isn't indexed, \
+			its file paths are left for the next scan of its location"
+		);
+		return delete_file_paths(
+			library,
+			source_location,
+			file_paths.iter().map(|file_path| file_path.id).collect(),
+		)
+		.await;
+	};
+
+	let new_materialized_path = materialized_path.as_ref().to_string();
+	let name = materialized_path.name().to_string();
+	let extension = materialized_path.extension().to_string();
+
+	if source_location.id == target_location.id {
+		let sync_id = || sync::file_path::SyncId {
+			id: source.id,
+			location: sync::location::SyncId {
+				pub_id: source_location.pub_id.clone(),
+			},
+		};
+
+		let (mut sync_stuff, mut updates) = if source.is_dir {
+			file_path_descendant_moves(
+				source_location,
+				source.id,
+				&source.materialized_path,
+				&new_materialized_path,
+				sync,
+				db,
+			)
+			.await
+			.map_err(LocationError::from)?
+		} else {
+			Default::default()
+		};
+
+		sync_stuff.extend([
+			sync.shared_update(
+				sync_id(),
+				"materialized_path",
+				json!(&new_materialized_path),
+			),
+			sync.shared_update(sync_id(), "name", json!(&name)),
+			sync.shared_update(sync_id(), "extension", json!(&extension)),
+			sync.shared_update(sync_id(), "parent_id", json!(parent_id)),
+		]);
+		updates.push(db.file_path().update(
+			file_path::location_id_id(source_location.id, source.id),
+			vec![
+				file_path::materialized_path::set(new_materialized_path),
+				file_path::name::set(name),
+				file_path::extension::set(extension),
+				file_path::parent_id::set(Some(parent_id)),
+			],
+		));
+
+		sync.write_ops(db, (sync_stuff, updates)).await?;
+
+		return Ok(());
+	}
+
+	let first_file_id = last_file_path_id_manager
+		.get_max_file_path_id(target_location.id, db)
+		.await
+		.map_err(LocationError::from)?
+		+ 1;
+	let last_file_id = first_file_id + file_paths.len() as i32;
+	last_file_path_id_manager
+		.set_max_file_path_id(target_location.id, last_file_id - 1)
+		.await;
+
+	let old_ids = file_paths
+		.iter()
+		.map(|file_path| file_path.id)
+		.collect::<Vec<_>>();
+
+	// Deleting the old file paths first, as their integrity checksums are unique
+	let mut sync_stuff = old_ids
+		.iter()
+		.map(|&id| {
+			sync.shared_delete(sync::file_path::SyncId {
+				id,
+				location: sync::location::SyncId {
+					pub_id: source_location.pub_id.clone(),
+				},
+			})
+		})
+		.collect::<Vec<_>>();
+
+	let mut new_ids = HashMap::new();
+	let paths = file_paths
+		.into_iter()
+		.zip(first_file_id..last_file_id)
+		.map(|(file_path, file_id)| {
+			new_ids.insert(file_path.id, file_id);
+
+			let (materialized_path, name, extension, parent_id) = if file_path.id == source.id {
+				(
+					new_materialized_path.clone(),
+					name.clone(),
+					extension.clone(),
+					Some(parent_id),
+				)
+			} else {
+				(
+					format!(
+						"{new_materialized_path}{}",
+						&file_path.materialized_path[source.materialized_path.len()..]
+					),
+					file_path.name,
+					file_path.extension,
+					file_path
+						.parent_id
+						.and_then(|parent_id| new_ids.get(&parent_id).copied()),
+				)
+			};
+
+			let object_pub_id = file_path
+				.object
+				.as_ref()
+				.and_then(|object| Uuid::from_slice(&object.pub_id).ok());
+
+			use file_path::*;
+
+			sync_stuff.push(sync.unique_shared_create(
+				sync::file_path::SyncId {
+					id: file_id,
+					location: sync::location::SyncId {
+						pub_id: target_location.pub_id.clone(),
+					},
+				},
+				[
+					("materialized_path", json!(materialized_path.clone())),
+					("name", json!(name.clone())),
+					("is_dir", json!(file_path.is_dir)),
+					("extension", json!(extension.clone())),
+					("parent_id", json!(parent_id)),
+					("cas_id", json!(file_path.cas_id)),
+					("cas_id_algorithm", json!(file_path.cas_id_algorithm)),
+					("integrity_checksum", json!(file_path.integrity_checksum)),
+					("is_symlink", json!(file_path.is_symlink)),
+					("symlink_target", json!(file_path.symlink_target)),
+					("hidden", json!(file_path.hidden)),
+					("in_archive", json!(file_path.in_archive)),
+					("date_created", json!(file_path.date_created)),
+					(
+						"object",
+						json!(object_pub_id.map(|pub_id| json!({ "pub_id": pub_id }))),
+					),
+				],
+			));
+
+			file_path::create_unchecked(
+				file_id,
+				target_location.id,
+				materialized_path,
+				name,
+				extension,
+				vec![
+					is_dir::set(file_path.is_dir),
+					parent_id::set(parent_id),
+					object_id::set(file_path.object_id),
+					cas_id::set(file_path.cas_id),
+					cas_id_algorithm::set(file_path.cas_id_algorithm),
+					integrity_checksum::set(file_path.integrity_checksum),
+					key_id::set(file_path.key_id),
+					// The inode only means something on this node, so it isn't synced
+					inode::set(file_path.inode),
+					device::set(file_path.device),
+					is_symlink::set(file_path.is_symlink),
+					symlink_target::set(file_path.symlink_target),
+					hidden::set(file_path.hidden),
+					in_archive::set(file_path.in_archive),
+					date_created::set(file_path.date_created),
+					date_modified::set(file_path.date_modified),
+				],
+			)
+		})
+		.collect::<Vec<_>>();
+
+	// Both are run in the same batch, which is a single transaction
+	sync.write_ops(
+		db,
+		(
+			sync_stuff,
+			(
+				db.file_path().delete_many(vec![
+					file_path::location_id::equals(source_location.id),
+					file_path::id::in_vec(old_ids),
+				]),
+				db.file_path().create_many(paths),
+			),
+		),
+	)
+	.await?;
+
+	Ok(())
+}
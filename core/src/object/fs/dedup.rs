@@ -0,0 +1,315 @@
+use crate::{
+	invalidate_query,
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::{move_to_trash, ActivityKind, Library},
+	object::validation::hash::file_checksum,
+	prisma::{file_path, location},
+};
+
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+};
+
+use prisma_client_rust::{Direction, QueryError};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::{fs, io};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+pub const DUPLICATE_ELIMINATOR_JOB_NAME: &str = "duplicate_eliminator";
+
+/// DuplicateFile is a file of a duplicate group, with its full path on this node.
+#[derive(Serialize, Deserialize, Debug, Clone, Type)]
+pub struct DuplicateFile {
+	pub location_id: i32,
+	pub file_path_id: i32,
+	pub materialized_path: String,
+	pub path: PathBuf,
+}
+
+/// DuplicateGroup are the files of this node which point at the same object, oldest first.
+#[derive(Serialize, Deserialize, Debug, Clone, Type)]
+pub struct DuplicateGroup {
+	pub object_id: i32,
+	pub files: Vec<DuplicateFile>,
+}
+
+/// find_duplicates groups the files of the locations of this node by object, keeping only the objects with more than one file.
+pub async fn find_duplicates(
+	library: &Library,
+	location_id: Option<i32>,
+	object_ids: Option<Vec<i32>>,
+) -> Result<Vec<DuplicateGroup>, QueryError> {
+	let mut params = vec![
+		file_path::is_dir::equals(false),
+		file_path::object_id::not(None),
+		file_path::location::is(vec![location::node_id::equals(library.node_local_id)]),
+	];
+	if let Some(location_id) = location_id {
+		params.push(file_path::location_id::equals(location_id));
+	}
+	if let Some(object_ids) = object_ids {
+		params.push(file_path::object_id::in_vec(object_ids));
+	}
+
+	let file_paths = library
+		.db
+		.file_path()
+		.find_many(params)
+		.order_by(file_path::date_created::order(Direction::Asc))
+		.select(file_path::select!({
+			id
+			location_id
+			materialized_path
+			object_id
+			location: select { path }
+		}))
+		.exec()
+		.await?;
+
+	let mut groups = BTreeMap::<i32, Vec<DuplicateFile>>::new();
+	for file_path in file_paths {
+		let Some(object_id) = file_path.object_id else {
+			continue;
+		};
+
+		groups.entry(object_id).or_default().push(DuplicateFile {
+			location_id: file_path.location_id,
+			file_path_id: file_path.id,
+			path: PathBuf::from(&file_path.location.path).join(&file_path.materialized_path),
+			materialized_path: file_path.materialized_path,
+		});
+	}
+
+	Ok(groups
+		.into_iter()
+		.filter(|(_, files)| files.len() > 1)
+		.map(|(object_id, files)| DuplicateGroup { object_id, files })
+		.collect())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, Type)]
+pub enum DuplicateAction {
+	/// Replaces the redundant copies with hardlinks to the kept one, which only works within the same filesystem
+	Hardlink,
+	/// Moves the redundant copies to the trash of their location
+	Delete,
+}
+
+pub struct DuplicateEliminatorJob {}
+
+/// `DuplicateEliminatorJobInit` keeps the oldest file of every duplicate group, and hardlinks or deletes the others.
+/// Files only share an object by their cas_id, so every copy is compared byte for byte with the kept one before being touched.
+#[derive(Serialize, Deserialize, Hash, Type)]
+pub struct DuplicateEliminatorJobInit {
+	pub location_id: Option<i32>,
+	/// object_ids restricts the job to these duplicate groups, otherwise every group is processed
+	pub object_ids: Option<Vec<i32>>,
+	pub action: DuplicateAction,
+	/// dry_run only reports what would be done, without changing any file
+	#[serde(default)]
+	pub dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct DuplicateEliminatorReport {
+	dry_run: bool,
+	groups: usize,
+	eliminated_files: Vec<PathBuf>,
+	/// Files which were left as is, with the reason
+	skipped_files: Vec<(PathBuf, String)>,
+	reclaimed_bytes: u64,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for DuplicateEliminatorJob {
+	type Init = DuplicateEliminatorJobInit;
+	type Data = DuplicateEliminatorReport;
+	type Step = DuplicateGroup;
+
+	fn name(&self) -> &'static str {
+		DUPLICATE_ELIMINATOR_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		state.steps = find_duplicates(
+			&ctx.library,
+			state.init.location_id,
+			state.init.object_ids.clone(),
+		)
+		.await?
+		.into_iter()
+		.collect();
+
+		state.data = Some(DuplicateEliminatorReport {
+			dry_run: state.init.dry_run,
+			groups: state.steps.len(),
+			..Default::default()
+		});
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let group = &state.steps[0];
+		let report = state.data.as_mut().expect("fatal: missing job state");
+
+		// SAFETY: groups always have more than one file
+		let (kept, redundant) = group.files.split_first().unwrap();
+
+		// A kept file which can't be read, like one removed since the duplicates were found, only skips its group
+		let kept_checksum = match file_checksum(&kept.path).await {
+			Ok(checksum) => checksum,
+			Err(e) => {
+				warn!(
+					"Skipping duplicates of {}, failed to hash it: {e:#?}",
+					kept.path.display()
+				);
+				let reason = format!("failed to hash the kept file {}: {e}", kept.path.display());
+				report.skipped_files.extend(
+					redundant
+						.iter()
+						.map(|file| (file.path.clone(), reason.clone())),
+				);
+
+				ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+					state.step_number + 1,
+				)]);
+
+				return Ok(());
+			}
+		};
+
+		for file in redundant {
+			let size = match check_duplicate(kept, &kept_checksum, file).await {
+				Ok(size) => size,
+				Err(reason) => {
+					info!("Skipping duplicate {}: {reason}", file.path.display());
+					report.skipped_files.push((file.path.clone(), reason));
+					continue;
+				}
+			};
+
+			if !state.init.dry_run {
+				let result = match state.init.action {
+					DuplicateAction::Hardlink => hardlink_over(&kept.path, &file.path).await,
+					DuplicateAction::Delete => move_to_trash(
+						&ctx.library,
+						file.location_id,
+						file.file_path_id,
+						&file.materialized_path,
+						false,
+					)
+					.await
+					.map(|_| ())
+					.map_err(|e| e.to_string()),
+				};
+
+				if let Err(reason) = result {
+					warn!(
+						"Failed to eliminate duplicate {}: {reason}",
+						file.path.display()
+					);
+					report.skipped_files.push((file.path.clone(), reason));
+					continue;
+				}
+			}
+
+			report.eliminated_files.push(file.path.clone());
+			report.reclaimed_bytes += size;
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let report = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		if !report.dry_run && !report.eliminated_files.is_empty() {
+			let kind = match state.init.action {
+				DuplicateAction::Hardlink => ActivityKind::DuplicatesHardlinked,
+				DuplicateAction::Delete => ActivityKind::FilesTrashed,
+			};
+
+			ctx.library
+				.record_activity(
+					kind,
+					format!(
+						"Eliminated {} duplicate files, reclaiming {} bytes",
+						report.eliminated_files.len(),
+						report.reclaimed_bytes
+					),
+					Some(serde_json::json!({ "paths": report.eliminated_files })),
+				)
+				.await;
+
+			invalidate_query!(ctx.library, "files.duplicates");
+			invalidate_query!(ctx.library, "locations.getExplorerData");
+		}
+
+		Ok(Some(serde_json::to_value(report)?))
+	}
+}
+
+/// check_duplicate makes sure `file` can be eliminated in favor of `kept`, returning the space it would reclaim.
+async fn check_duplicate(
+	kept: &DuplicateFile,
+	kept_checksum: &str,
+	file: &DuplicateFile,
+) -> Result<u64, String> {
+	let metadata = fs::metadata(&file.path).await.map_err(|e| e.to_string())?;
+
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+
+		let kept_metadata = fs::metadata(&kept.path).await.map_err(|e| e.to_string())?;
+		if kept_metadata.dev() == metadata.dev() && kept_metadata.ino() == metadata.ino() {
+			return Err("already a hardlink of the kept file".to_string());
+		}
+	}
+
+	if file_checksum(&file.path).await.map_err(|e| e.to_string())? != kept_checksum {
+		return Err(format!(
+			"contents differ from {}, despite sharing its object",
+			kept.path.display()
+		));
+	}
+
+	Ok(metadata.len())
+}
+
+/// hardlink_over replaces `path` with a hardlink to `original`. The link is created next to `path` and renamed over it,
+/// so `path` is never missing if creating the link fails, as it does across filesystems.
+async fn hardlink_over(original: &Path, path: &Path) -> Result<(), String> {
+	let temp_path = path.with_file_name(format!(".{}.sdlink", Uuid::new_v4()));
+
+	fs::hard_link(original, &temp_path)
+		.await
+		.map_err(|e| match e.kind() {
+			io::ErrorKind::Unsupported => "hardlinks aren't supported here".to_string(),
+			_ => e.to_string(),
+		})?;
+
+	if let Err(e) = fs::rename(&temp_path, path).await {
+		fs::remove_file(&temp_path).await.ok();
+		return Err(e.to_string());
+	}
+
+	Ok(())
+}
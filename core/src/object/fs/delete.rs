@@ -1,21 +1,34 @@
-use crate::job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext};
+use crate::{
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::{move_to_trash, ActivityKind},
+};
 
 use std::hash::Hash;
 
 use serde::{Deserialize, Serialize};
 use specta::Type;
 
-use super::{context_menu_fs_info, FsInfo};
+use super::{context_menu_fs_info, FsInfo, PlannedChange};
 
 pub struct FileDeleterJob {}
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct FileDeleterJobState {}
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct FileDeleterJobState {
+	/// The deletions a dry run would have made
+	changes: Vec<PlannedChange>,
+}
 
 #[derive(Serialize, Deserialize, Hash, Type)]
 pub struct FileDeleterJobInit {
 	pub location_id: i32,
 	pub path_id: i32,
+	/// permanent deletes the file right away instead of moving it to the trash of its location.
+	#[serde(default)]
+	pub permanent: bool,
+	/// dry_run only lists the file which would be deleted in the job report, without deleting it
+	#[serde(default)]
+	pub dry_run: bool,
 }
 
 pub const DELETE_JOB_NAME: &str = "file_deleter";
@@ -36,6 +49,7 @@ impl StatefulJob for FileDeleterJob {
 				.await?;
 
 		state.steps = [fs_info].into_iter().collect();
+		state.data = Some(FileDeleterJobState::default());
 
 		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
 
@@ -52,11 +66,61 @@ impl StatefulJob for FileDeleterJob {
 		// need to handle stuff such as querying prisma for all paths of a file, and deleting all of those if requested (with a checkbox in the ui)
 		// maybe a files.countOccurances/and or files.getPath(location_id, path_id) to show how many of these files would be deleted (and where?)
 
-		if info.path_data.is_dir {
-			tokio::fs::remove_dir_all(info.fs_path.clone()).await
+		if state.init.dry_run {
+			let path = info.fs_path.clone();
+			state
+				.data
+				.get_or_insert_with(Default::default)
+				.changes
+				.push(if state.init.permanent {
+					PlannedChange::Delete { path }
+				} else {
+					PlannedChange::Trash { path }
+				});
+
+			ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+				state.step_number + 1,
+			)]);
+			return Ok(());
+		}
+
+		let (kind, message) = if state.init.permanent {
+			if info.path_data.is_dir {
+				tokio::fs::remove_dir_all(info.fs_path.clone()).await
+			} else {
+				tokio::fs::remove_file(info.fs_path.clone()).await
+			}?;
+
+			(
+				ActivityKind::FilesDeleted,
+				format!("Deleted '{}'", info.fs_path.display()),
+			)
 		} else {
-			tokio::fs::remove_file(info.fs_path.clone()).await
-		}?;
+			move_to_trash(
+				&ctx.library,
+				state.init.location_id,
+				info.path_data.id,
+				&info.path_data.materialized_path,
+				info.path_data.is_dir,
+			)
+			.await?;
+
+			(
+				ActivityKind::FilesTrashed,
+				format!("Moved '{}' to the trash", info.fs_path.display()),
+			)
+		};
+
+		ctx.library
+			.record_activity(
+				kind,
+				message,
+				Some(serde_json::json!({
+					"location_id": state.init.location_id,
+					"path": info.fs_path,
+				})),
+			)
+			.await;
 
 		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
 			state.step_number + 1,
@@ -65,6 +129,13 @@ impl StatefulJob for FileDeleterJob {
 	}
 
 	async fn finalize(&mut self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		if state.init.dry_run {
+			return Ok(Some(serde_json::json!({
+				"dry_run": true,
+				"changes": state.data.as_ref().map(|data| &data.changes),
+			})));
+		}
+
 		Ok(Some(serde_json::to_value(&state.init)?))
 	}
 }
@@ -1,15 +1,25 @@
-use crate::job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext};
+use crate::{
+	invalidate_query,
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::ActivityKind,
+	location::delete_directory,
+};
 
 use std::{hash::Hash, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 use specta::Type;
-use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+use tokio::{
+	fs::{self, OpenOptions},
+	io::AsyncWriteExt,
+};
 use tracing::{trace, warn};
 
 use super::{context_menu_fs_info, FsInfo};
 
+/// FileEraserJob overwrites the contents of a file, or of every file in a directory, with random data
+/// as many times as requested before deleting them, so they can't be recovered from the disk.
 pub struct FileEraserJob {}
 
 #[serde_as]
@@ -80,6 +90,21 @@ impl StatefulJob for FileEraserJob {
 
 		match step {
 			FileEraserJobStep::File { path } => {
+				ctx.progress(vec![JobReportUpdate::Message(format!(
+					"Erasing {}",
+					path.display()
+				))]);
+
+				// Links are only removed, overwriting them would erase the file they point to
+				if fs::symlink_metadata(&path).await?.is_symlink() {
+					fs::remove_file(&path).await?;
+
+					ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+						state.step_number + 1,
+					)]);
+					return Ok(());
+				}
+
 				let mut file = OpenOptions::new()
 					.read(true)
 					.write(true)
@@ -95,10 +120,10 @@ impl StatefulJob for FileEraserJob {
 
 				trace!("Erasing file: {:?}", path);
 
-				tokio::fs::remove_file(&path).await?;
+				fs::remove_file(&path).await?;
 			}
 			FileEraserJobStep::Directory { path } => {
-				let mut dir = tokio::fs::read_dir(&path).await?;
+				let mut dir = fs::read_dir(&path).await?;
 
 				while let Some(entry) = dir.next_entry().await? {
 					state.steps.push_back(if entry.metadata().await?.is_dir() {
@@ -118,11 +143,32 @@ impl StatefulJob for FileEraserJob {
 		Ok(())
 	}
 
-	async fn finalize(&mut self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
 		if let Some(ref info) = state.data {
 			if info.path_data.is_dir {
-				tokio::fs::remove_dir_all(&info.fs_path).await?;
+				fs::remove_dir_all(&info.fs_path).await?;
 			}
+
+			// The erased files are gone for good, so they're removed from the library right away
+			delete_directory(
+				&ctx.library,
+				state.init.location_id,
+				Some(info.path_data.id),
+			)
+			.await?;
+			invalidate_query!(ctx.library, "locations.getExplorerData");
+
+			ctx.library
+				.record_activity(
+					ActivityKind::FilesErased,
+					format!("Securely erased '{}'", info.fs_path.display()),
+					Some(serde_json::json!({
+						"location_id": state.init.location_id,
+						"path": info.fs_path,
+						"passes": state.init.passes,
+					})),
+				)
+				.await;
 		} else {
 			warn!("missing job state, unable to fully finalise erase job");
 		}
@@ -1,18 +1,29 @@
 use crate::{
 	job::JobError,
-	location::file_path_helper::file_path_with_object,
+	library::Library,
+	location::{
+		file_path_helper::{file_path_with_object, get_existing_file_path_id, MaterializedPath},
+		find_location, light_scan_location, location_with_indexer_rules, scan_location,
+		scan_location_sub_path, LocationError,
+	},
 	prisma::{file_path, location, PrismaClient},
 };
 
-use std::{ffi::OsStr, path::PathBuf};
+use std::{
+	ffi::OsStr,
+	path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 
+pub mod archive;
 pub mod create;
 
 pub mod copy;
 pub mod cut;
 
+pub mod dedup;
+
 pub mod decrypt;
 pub mod delete;
 pub mod encrypt;
@@ -21,6 +32,8 @@ pub mod error;
 
 pub mod erase;
 
+pub mod rename;
+
 pub const BYTES_EXT: &str = ".bytes";
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
@@ -29,6 +42,31 @@ pub enum ObjectType {
 	Directory,
 }
 
+/// PlannedChange is a change a destructive job would make to the filesystem. Dry runs of these jobs
+/// list their planned changes in the metadata of their report instead of making them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum PlannedChange {
+	Delete {
+		path: PathBuf,
+	},
+	Trash {
+		path: PathBuf,
+	},
+	Move {
+		from: PathBuf,
+		to: PathBuf,
+	},
+	/// The file or directory at `path` would be replaced by a moved one
+	Overwrite {
+		path: PathBuf,
+	},
+	/// The file or directory at `path` would be left out, as its name is taken at the target
+	Skip {
+		path: PathBuf,
+	},
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FsInfo {
 	pub path_data: file_path_with_object::Data,
@@ -80,3 +118,65 @@ pub async fn context_menu_fs_info(
 		path_data,
 	})
 }
+
+/// scan_target_directory indexes the directory of a location files were written to, so they show up
+/// without waiting for the location watcher.
+pub async fn scan_target_directory(
+	library: &Library,
+	location_id: i32,
+	sub_path: &Path,
+) -> Result<(), JobError> {
+	let location = find_location(library, location_id)
+		.include(location_with_indexer_rules::include())
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	light_scan_location(library, location, sub_path).await?;
+
+	Ok(())
+}
+
+/// scan_target_tree indexes the directory of a location whole trees were written to, and everything under it,
+/// as [`scan_target_directory`] would only index its direct contents. The deep indexer starts from an indexed
+/// directory, so an unindexed one is indexed from its closest indexed ancestor, the location root at worst.
+pub async fn scan_target_tree(
+	library: &Library,
+	location_id: i32,
+	sub_path: &Path,
+) -> Result<(), JobError> {
+	let location = find_location(library, location_id)
+		.include(location_with_indexer_rules::include())
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(location_id))?;
+
+	for ancestor in sub_path.ancestors() {
+		if ancestor == Path::new("") {
+			break;
+		}
+
+		let is_indexed = get_existing_file_path_id(
+			MaterializedPath::new(
+				location.id,
+				&location.path,
+				Path::new(&location.path).join(ancestor),
+				true,
+			)
+			.map_err(LocationError::from)?,
+			&library.db,
+		)
+		.await
+		.map_err(LocationError::from)?
+		.is_some();
+
+		if is_indexed {
+			scan_location_sub_path(library, location, ancestor).await?;
+			return Ok(());
+		}
+	}
+
+	scan_location(library, location).await?;
+
+	Ok(())
+}
@@ -0,0 +1,532 @@
+use crate::{
+	invalidate_query,
+	library::Library,
+	location::{
+		file_path_helper::{file_path_descendant_moves, FilePathError, MaterializedPath},
+		find_location, LocationError, LocationId, LocationManagerError,
+	},
+	prisma::{file_path, location},
+	sync,
+};
+
+use std::{
+	collections::{HashMap, HashSet},
+	ffi::OsStr,
+	path::{Path, PathBuf},
+};
+
+use chrono::{format::StrftimeItems, DateTime, FixedOffset};
+use once_cell::sync::Lazy;
+use prisma_client_rust::QueryError;
+use regex::{Captures, Regex};
+use rspc::{ErrorCode, Type};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use thiserror::Error;
+use tokio::{fs, io};
+use tracing::error;
+use uuid::Uuid;
+
+/// Matches the `{name}`, `{n}`, `{n:3}`, `{date}` and `{date:%Y}` tokens of a rename template
+static TEMPLATE_TOKEN: Lazy<Regex> =
+	Lazy::new(|| Regex::new(r"\{(name|n|date)(?::([^}]*))?\}").unwrap());
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// The widest a `{n:WIDTH}` counter is padded to, as a huge width would make a huge name
+const MAX_COUNTER_WIDTH: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum BulkRenameError {
+	#[error("file paths not found in the location (ids: {0:?})")]
+	NotFound(Vec<i32>),
+	#[error("invalid find pattern: {0}")]
+	InvalidPattern(#[from] regex::Error),
+	#[error("invalid date format in the template: {0}")]
+	InvalidDateFormat(String),
+	#[error("invalid file name: '{0}'")]
+	InvalidName(String),
+	#[error("more than one file would be named '{0}'")]
+	DuplicateName(String),
+	#[error("a file already exists with the new name (path: {0:?})")]
+	NameTaken(PathBuf),
+	#[error("location error: {0}")]
+	Location(#[from] LocationError),
+	#[error("location manager error: {0}")]
+	LocationManager(#[from] LocationManagerError),
+	#[error("file path error: {0}")]
+	FilePath(#[from] FilePathError),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("filesystem error: {0}")]
+	IO(#[from] io::Error),
+}
+
+impl From<BulkRenameError> for rspc::Error {
+	fn from(error: BulkRenameError) -> Self {
+		let code = match error {
+			BulkRenameError::NotFound(_) => ErrorCode::NotFound,
+			BulkRenameError::NameTaken(_) | BulkRenameError::DuplicateName(_) => {
+				ErrorCode::Conflict
+			}
+			BulkRenameError::InvalidPattern(_)
+			| BulkRenameError::InvalidDateFormat(_)
+			| BulkRenameError::InvalidName(_) => ErrorCode::BadRequest,
+			_ => ErrorCode::InternalServerError,
+		};
+
+		rspc::Error::with_cause(code, error.to_string(), error)
+	}
+}
+
+/// BulkRenameArgs renames the `file_path_ids` of a location by a template, in the order they're given.
+/// The template makes the new names without their extensions, which are kept, out of these tokens:
+/// - `{name}`: the current name, after replacing the `find` pattern with `replace` in it
+/// - `{n}`: a counter starting at `counter_start`, or `{n:3}` padded with zeros to 3 digits
+/// - `{date}`: the date the file was created, or `{date:%Y%m%d}` with a custom format
+#[derive(Type, Deserialize, Debug)]
+pub struct BulkRenameArgs {
+	pub location_id: LocationId,
+	pub file_path_ids: Vec<i32>,
+	pub template: String,
+	pub find: Option<String>,
+	#[serde(default)]
+	pub replace: String,
+	pub counter_start: Option<u32>,
+	/// dry_run only returns the new names, like [`preview_bulk_rename`], without renaming anything
+	#[serde(default)]
+	pub dry_run: bool,
+}
+
+#[derive(Type, Serialize, Debug, Clone)]
+pub struct BulkRenameEntry {
+	pub id: i32,
+	pub name: String,
+	pub new_name: String,
+}
+
+file_path::select!(file_path_for_rename {
+	id
+	materialized_path
+	is_dir
+	date_created
+});
+
+/// expand_template makes the name of a file out of a rename template, see [`BulkRenameArgs`].
+fn expand_template(
+	template: &str,
+	name: &str,
+	counter: u32,
+	date_created: DateTime<FixedOffset>,
+) -> Result<String, BulkRenameError> {
+	let mut invalid_format = None;
+
+	let expanded = TEMPLATE_TOKEN.replace_all(template, |captures: &Captures| {
+		let param = captures.get(2).map(|param| param.as_str());
+
+		match &captures[1] {
+			"name" => name.to_string(),
+			"n" => {
+				let width = param
+					.and_then(|width| width.parse().ok())
+					.unwrap_or(0)
+					.min(MAX_COUNTER_WIDTH);
+				format!("{counter:0width$}")
+			}
+			_ => {
+				let format = param.unwrap_or(DEFAULT_DATE_FORMAT);
+				// Formatting with an invalid format panics, so it's checked first
+				if StrftimeItems::new(format).any(|item| item == chrono::format::Item::Error) {
+					invalid_format = Some(format.to_string());
+					return String::new();
+				}
+				date_created.format(format).to_string()
+			}
+		}
+	});
+
+	match invalid_format {
+		Some(format) => Err(BulkRenameError::InvalidDateFormat(format)),
+		None => Ok(expanded.into_owned()),
+	}
+}
+
+/// split_name splits the name of a file path into its name without extension and its extension with the dot,
+/// which is empty for directories.
+fn split_name(materialized_path: &str, is_dir: bool) -> (String, String) {
+	let path = Path::new(materialized_path);
+	let to_string =
+		|name: Option<&OsStr>| name.and_then(OsStr::to_str).unwrap_or_default().to_string();
+
+	if is_dir {
+		(to_string(path.file_name()), String::new())
+	} else {
+		(
+			to_string(path.file_stem()),
+			path.extension()
+				.and_then(OsStr::to_str)
+				.map(|extension| format!(".{extension}"))
+				.unwrap_or_default(),
+		)
+	}
+}
+
+async fn find_file_paths(
+	library: &Library,
+	args: &BulkRenameArgs,
+) -> Result<(location::Data, Vec<file_path_for_rename::Data>), BulkRenameError> {
+	let location = find_location(library, args.location_id)
+		.exec()
+		.await?
+		.ok_or(LocationError::IdNotFound(args.location_id))?;
+
+	let mut file_paths = library
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(args.location_id),
+			file_path::id::in_vec(args.file_path_ids.clone()),
+			file_path::in_archive::equals(false),
+		])
+		.select(file_path_for_rename::select())
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| (file_path.id, file_path))
+		.collect::<HashMap<_, _>>();
+
+	let missing = args
+		.file_path_ids
+		.iter()
+		.filter(|id| !file_paths.contains_key(id))
+		.copied()
+		.collect::<Vec<_>>();
+	if !missing.is_empty() {
+		return Err(BulkRenameError::NotFound(missing));
+	}
+
+	// The counter follows the order the file paths were given in
+	let file_paths = args
+		.file_path_ids
+		.iter()
+		.filter_map(|id| file_paths.remove(id))
+		.collect();
+
+	Ok((location, file_paths))
+}
+
+/// preview_bulk_rename returns the new names the file paths would have, without renaming them.
+/// It fails for names which are invalid or would collide with each other or with other files.
+pub async fn preview_bulk_rename(
+	library: &Library,
+	args: &BulkRenameArgs,
+) -> Result<Vec<BulkRenameEntry>, BulkRenameError> {
+	let (location, file_paths) = find_file_paths(library, args).await?;
+	let find = args.find.as_deref().map(Regex::new).transpose()?;
+
+	let entries = file_paths
+		.iter()
+		.zip(args.counter_start.unwrap_or(1)..)
+		.map(|(file_path, counter)| {
+			let (stem, extension) = split_name(&file_path.materialized_path, file_path.is_dir);
+			let name = find.as_ref().map_or_else(
+				|| stem.clone(),
+				|find| find.replace_all(&stem, &args.replace).into_owned(),
+			);
+
+			let new_name = expand_template(&args.template, &name, counter, file_path.date_created)?;
+			if new_name.is_empty()
+				|| new_name == "."
+				|| new_name == ".."
+				|| new_name.contains(['/', '\\'])
+			{
+				return Err(BulkRenameError::InvalidName(new_name));
+			}
+
+			Ok(BulkRenameEntry {
+				id: file_path.id,
+				name: format!("{stem}{extension}"),
+				new_name: format!("{new_name}{extension}"),
+			})
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let location_path = Path::new(&location.path);
+	let old_paths = file_paths
+		.iter()
+		.map(|file_path| location_path.join(&file_path.materialized_path))
+		.collect::<HashSet<_>>();
+
+	let mut new_paths = HashSet::with_capacity(entries.len());
+	for (entry, file_path) in entries.iter().zip(&file_paths) {
+		let old_path = location_path.join(&file_path.materialized_path);
+		let new_path = old_path.with_file_name(&entry.new_name);
+
+		if !new_paths.insert(new_path.clone()) {
+			return Err(BulkRenameError::DuplicateName(entry.new_name.clone()));
+		}
+
+		// Names freed by the other renamed files can be taken, so names can be swapped. A file whose name only
+		// changes case is found under its new name on case-insensitive filesystems, which doesn't take it.
+		if !old_paths.contains(&new_path)
+			&& fs::symlink_metadata(&new_path).await.is_ok()
+			&& !is_same_file(&old_path, &new_path).await
+		{
+			return Err(BulkRenameError::NameTaken(new_path));
+		}
+	}
+
+	Ok(entries)
+}
+
+/// bulk_rename renames the file paths by the template of `args`, on disk and in the database.
+/// Either every file is renamed or none is, as the renamed ones are renamed back when one of them fails.
+pub async fn bulk_rename(
+	library: &Library,
+	args: BulkRenameArgs,
+) -> Result<Vec<BulkRenameEntry>, BulkRenameError> {
+	let entries = preview_bulk_rename(library, &args).await?;
+	if args.dry_run {
+		return Ok(entries);
+	}
+
+	let (location, file_paths) = find_file_paths(library, &args).await?;
+	let location_path = Path::new(&location.path);
+
+	let renames = file_paths
+		.iter()
+		.zip(&entries)
+		.filter(|(_, entry)| entry.name != entry.new_name)
+		.map(|(file_path, entry)| {
+			// Going through a temporary name first, so files can swap their names
+			let temp_name = format!(".{}.sdrename", Uuid::new_v4().simple());
+
+			(file_path, temp_name, &entry.new_name)
+		})
+		.collect::<Vec<_>>();
+
+	if renames.is_empty() {
+		return Ok(entries);
+	}
+
+	// The watcher would see the renames as moves, while the file paths are updated below
+	let _guard = library
+		.location_manager()
+		.temporary_stop(location.id, library.clone())
+		.await?;
+
+	// Where every renamed file is now, as renaming a directory moves the renamed files in it
+	let mut current_paths = renames
+		.iter()
+		.map(|(file_path, _, _)| location_path.join(&file_path.materialized_path))
+		.collect::<Vec<_>>();
+	// The (from, to) renames done so far, to undo them if one fails
+	let mut done = Vec::with_capacity(renames.len() * 2);
+
+	let phases = [
+		renames
+			.iter()
+			.map(|(_, temp_name, _)| temp_name.as_str())
+			.collect::<Vec<_>>(),
+		renames
+			.iter()
+			.map(|(_, _, new_name)| new_name.as_str())
+			.collect(),
+	];
+	for names in phases {
+		for (i, name) in names.into_iter().enumerate() {
+			let from = current_paths[i].clone();
+			let to = from.with_file_name(name);
+
+			if let Err(e) = fs::rename(&from, &to).await {
+				undo_renames(done).await;
+				return Err(e.into());
+			}
+
+			current_paths[i] = to.clone();
+			for path in &mut current_paths {
+				if let Ok(rest) = path.strip_prefix(&from) {
+					*path = to.join(rest);
+				}
+			}
+			done.push((from, to));
+		}
+	}
+
+	if let Err(e) = update_renamed_file_paths(library, &location, &renames, &current_paths).await {
+		undo_renames(done).await;
+		return Err(e);
+	}
+
+	invalidate_query!(library, "locations.getExplorerData");
+
+	Ok(entries)
+}
+
+/// is_same_file tells if both paths lead to the same file, like a name and the same name in another case
+/// on a case-insensitive filesystem.
+async fn is_same_file(path: &Path, other: &Path) -> bool {
+	let (Ok(metadata), Ok(other_metadata)) =
+		(fs::symlink_metadata(path).await, fs::symlink_metadata(other).await)
+	else {
+		return false;
+	};
+
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+
+		metadata.dev() == other_metadata.dev() && metadata.ino() == other_metadata.ino()
+	}
+	#[cfg(not(unix))]
+	{
+		// Windows filesystems are case-insensitive, and file ids aren't stable in std
+		let _ = (metadata, other_metadata);
+		path.to_string_lossy().to_lowercase() == other.to_string_lossy().to_lowercase()
+	}
+}
+
+/// undo_renames renames back the (from, to) renames done, latest first.
+async fn undo_renames(done: Vec<(PathBuf, PathBuf)>) {
+	for (from, to) in done.into_iter().rev() {
+		if let Err(e) = fs::rename(&to, &from).await {
+			error!("Failed to undo the rename of {from:?} to {to:?}: {e:#?}");
+		}
+	}
+}
+
+/// update_renamed_file_paths updates the file paths renamed to `new_paths`, along with the contents of the renamed
+/// directories, in a single synced batch.
+async fn update_renamed_file_paths(
+	library: &Library,
+	location: &location::Data,
+	renames: &[(&file_path_for_rename::Data, String, &String)],
+	new_paths: &[PathBuf],
+) -> Result<(), BulkRenameError> {
+	let Library { db, sync, .. } = library;
+	let location_path = Path::new(&location.path);
+
+	let mut renamed = renames
+		.iter()
+		.zip(new_paths)
+		.map(|((file_path, _, _), new_path)| {
+			MaterializedPath::new(location.id, location_path, new_path, file_path.is_dir)
+				.map(|materialized_path| (*file_path, materialized_path))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	// The contents of a directory are moved before the directories in it, which are renamed themselves, so
+	// each file path ends up with the path made by its deepest renamed directory
+	renamed.sort_by_key(|(file_path, _)| file_path.materialized_path.matches('/').count());
+
+	let mut sync_stuff = vec![];
+	let mut updates = vec![];
+	for (file_path, materialized_path) in renamed.iter().filter(|(file_path, _)| file_path.is_dir) {
+		let (descendants_sync_stuff, descendants_updates) = file_path_descendant_moves(
+			location,
+			file_path.id,
+			&file_path.materialized_path,
+			materialized_path.as_ref(),
+			sync,
+			db,
+		)
+		.await?;
+
+		sync_stuff.extend(descendants_sync_stuff);
+		updates.extend(descendants_updates);
+	}
+
+	for (file_path, materialized_path) in renamed {
+		let new_materialized_path = materialized_path.as_ref().to_string();
+		let name = materialized_path.name().to_string();
+		let extension = materialized_path.extension().to_string();
+
+		let sync_id = || sync::file_path::SyncId {
+			id: file_path.id,
+			location: sync::location::SyncId {
+				pub_id: location.pub_id.clone(),
+			},
+		};
+
+		sync_stuff.extend([
+			sync.shared_update(
+				sync_id(),
+				"materialized_path",
+				json!(&new_materialized_path),
+			),
+			sync.shared_update(sync_id(), "name", json!(&name)),
+			sync.shared_update(sync_id(), "extension", json!(&extension)),
+		]);
+		updates.push(db.file_path().update(
+			file_path::location_id_id(location.id, file_path.id),
+			vec![
+				file_path::materialized_path::set(new_materialized_path),
+				file_path::name::set(name),
+				file_path::extension::set(extension),
+			],
+		));
+	}
+
+	// Every query of `write_ops` is run in the same batch, which is a single transaction
+	sync.write_ops(db, (sync_stuff, updates)).await?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use chrono::TimeZone;
+
+	fn date() -> DateTime<FixedOffset> {
+		FixedOffset::east_opt(0)
+			.unwrap()
+			.with_ymd_and_hms(2023, 3, 18, 12, 30, 0)
+			.unwrap()
+	}
+
+	#[test]
+	fn test_expand_template() {
+		assert_eq!(
+			expand_template("{name}", "photo", 1, date()).unwrap(),
+			"photo"
+		);
+		assert_eq!(
+			expand_template("trip_{n:3}", "photo", 7, date()).unwrap(),
+			"trip_007"
+		);
+		assert_eq!(
+			expand_template("{date} {name} {n}", "photo", 12, date()).unwrap(),
+			"2023-03-18 photo 12"
+		);
+		assert_eq!(
+			expand_template("{date:%Y%m%d_%H%M}", "photo", 1, date()).unwrap(),
+			"20230318_1230"
+		);
+		assert_eq!(
+			expand_template("{unknown}_{n}", "photo", 2, date()).unwrap(),
+			"{unknown}_2"
+		);
+		assert_eq!(
+			expand_template("{n:1000000}", "photo", 1, date()).unwrap(),
+			format!("{:0>32}", 1)
+		);
+		assert!(matches!(
+			expand_template("{date:%Q}", "photo", 1, date()),
+			Err(BulkRenameError::InvalidDateFormat(_))
+		));
+	}
+
+	#[test]
+	fn test_split_name() {
+		let split = |name: &str, extension: &str| (name.to_string(), extension.to_string());
+
+		assert_eq!(split_name("photos/trip.jpg", false), split("trip", ".jpg"));
+		assert_eq!(
+			split_name("photos/archive.tar.gz", false),
+			split("archive.tar", ".gz")
+		);
+		assert_eq!(split_name("photos/README", false), split("README", ""));
+		assert_eq!(split_name("photos/2023.03/", true), split("2023.03", ""));
+	}
+}
@@ -4,11 +4,16 @@ use rspc::Type;
 use serde::{Deserialize, Serialize};
 
 pub mod cas;
+pub mod convert;
+pub mod disk_usage;
 pub mod file_identifier;
 pub mod fs;
+pub mod perceptual_hash;
 pub mod preview;
+pub mod search;
 pub mod tag;
 pub mod validation;
+pub mod version;
 
 // Objects are primarily created by the identifier from Paths
 // Some Objects are purely virtual, unless they have one or more associated Paths, which refer to a file found in a Location
@@ -0,0 +1,106 @@
+//! Perceptual hashes identify images which look the same even when their bytes differ, like re-encodes and resizes.
+//! They're a difference hash (dHash): the image is shrunk to 9x8 grayscale pixels, and each bit tells whether a pixel
+//! is brighter than its right neighbor. Similar images have hashes with a small hamming distance.
+
+use crate::{
+	prisma::{object, PrismaClient},
+	util::resources::resource_limiter,
+};
+
+use std::path::{Path, PathBuf};
+
+use image::{imageops::FilterType, io::Reader, ImageError};
+use prisma_client_rust::QueryError;
+use tokio::task::spawn_blocking;
+use tracing::debug;
+
+/// The hamming distance under which images are considered similar when the caller doesn't set one
+pub const DEFAULT_SIMILARITY_DISTANCE: u32 = 10;
+
+fn dhash(path: &Path) -> Result<u64, ImageError> {
+	let image = Reader::open(path)?
+		.with_guessed_format()?
+		.decode()?
+		.resize_exact(9, 8, FilterType::Triangle)
+		.into_luma8();
+
+	let mut hash = 0u64;
+	for y in 0..8 {
+		for x in 0..8 {
+			hash <<= 1;
+			if image.get_pixel(x, y)[0] > image.get_pixel(x + 1, y)[0] {
+				hash |= 1;
+			}
+		}
+	}
+
+	Ok(hash)
+}
+
+/// perceptual_hash returns the hex encoded perceptual hash of an image, or `None` if it can't be decoded.
+pub async fn perceptual_hash(path: impl AsRef<Path>) -> Option<String> {
+	let path = PathBuf::from(path.as_ref());
+
+	// Decoding is CPU heavy, so it shares the worker thread limit of the node
+	let _permit = resource_limiter().worker_thread().await;
+	match spawn_blocking(move || dhash(&path).map_err(|e| (path, e))).await {
+		Ok(Ok(hash)) => Some(format!("{hash:016x}")),
+		Ok(Err((path, e))) => {
+			debug!(
+				"Failed to compute the perceptual hash of {}: {e}",
+				path.display()
+			);
+			None
+		}
+		Err(e) => {
+			debug!("Perceptual hash task failed: {e:#?}");
+			None
+		}
+	}
+}
+
+/// hamming_distance is how many bits differ between two hex encoded perceptual hashes.
+pub fn hamming_distance(a: &str, b: &str) -> Option<u32> {
+	let a = u64::from_str_radix(a, 16).ok()?;
+	let b = u64::from_str_radix(b, 16).ok()?;
+
+	Some((a ^ b).count_ones())
+}
+
+/// find_similar_images returns the ids of the objects which look like `object_id`, with their distance, closest first.
+pub async fn find_similar_images(
+	db: &PrismaClient,
+	object_id: i32,
+	max_distance: u32,
+) -> Result<Vec<(i32, u32)>, QueryError> {
+	let Some(hash) = db
+		.object()
+		.find_unique(object::id::equals(object_id))
+		.select(object::select!({ perceptual_hash }))
+		.exec()
+		.await?
+		.and_then(|object| object.perceptual_hash)
+	else {
+		return Ok(vec![]);
+	};
+
+	let mut similar = db
+		.object()
+		.find_many(vec![
+			object::perceptual_hash::not(None),
+			object::id::not(object_id),
+		])
+		.select(object::select!({ id perceptual_hash }))
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|object| {
+			let distance = hamming_distance(&hash, object.perceptual_hash.as_deref()?)?;
+			(distance <= max_distance).then_some((object.id, distance))
+		})
+		.collect::<Vec<_>>();
+
+	similar.sort_by_key(|(_, distance)| *distance);
+
+	Ok(similar)
+}
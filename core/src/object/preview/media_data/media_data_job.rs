@@ -0,0 +1,198 @@
+use crate::{
+	invalidate_query,
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::Library,
+	location::{file_path_helper::file_path_for_media_data, find_location, LocationError},
+	prisma::{file_path, location, media_data, object},
+	util::resources::resource_limiter,
+};
+
+use sd_file_ext::kind::ObjectKind;
+
+use std::{collections::VecDeque, path::PathBuf};
+
+use chrono::Utc;
+use int_enum::IntEnum;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::{info, warn};
+
+use super::{extract_audio_metadata, extract_image_metadata};
+
+pub const MEDIA_DATA_JOB_NAME: &str = "media_metadata";
+
+/// MediaDataJob extracts the EXIF, IPTC, audio tags and container metadata of the images, audio files and videos of a location.
+/// Videos are only handled when the core is built with FFmpeg.
+pub struct MediaDataJob {}
+
+#[derive(Serialize, Deserialize, Hash, Type)]
+pub struct MediaDataJobInit {
+	pub location_id: i32,
+	/// regenerate extracts the metadata of files which already have it again
+	#[serde(default)]
+	pub regenerate: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct MediaDataJobState {
+	root_path: PathBuf,
+	extracted: usize,
+	failed: usize,
+}
+
+/// The kinds of objects media data can be extracted from, with this build of the core
+fn media_kinds() -> Vec<i32> {
+	let mut kinds = vec![ObjectKind::Image.int_value(), ObjectKind::Audio.int_value()];
+	if cfg!(feature = "ffmpeg") {
+		kinds.push(ObjectKind::Video.int_value());
+	}
+	kinds
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for MediaDataJob {
+	type Init = MediaDataJobInit;
+	type Data = MediaDataJobState;
+	type Step = file_path_for_media_data::Data;
+
+	fn name(&self) -> &'static str {
+		MEDIA_DATA_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let location_id = state.init.location_id;
+
+		let location = find_location(&ctx.library, location_id)
+			.select(location::select!({ path }))
+			.exec()
+			.await?
+			.ok_or(LocationError::IdNotFound(location_id))?;
+
+		state.steps = ctx
+			.library
+			.db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(location_id),
+				file_path::is_dir::equals(false),
+				file_path::object::is(vec![object::kind::in_vec(media_kinds())]),
+			])
+			.select(file_path_for_media_data::select())
+			.exec()
+			.await?
+			.into_iter()
+			.filter(|file_path| {
+				state.init.regenerate
+					|| file_path
+						.object
+						.as_ref()
+						.map_or(false, |object| object.media_data.is_none())
+			})
+			.collect::<VecDeque<_>>();
+
+		state.data = Some(MediaDataJobState {
+			root_path: location.path.into(),
+			..Default::default()
+		});
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let file_path = &state.steps[0];
+		let data = state.data.as_mut().expect("fatal: missing job state");
+
+		let Some(object) = &file_path.object else {
+			return Ok(());
+		};
+
+		let path = data.root_path.join(&file_path.materialized_path);
+
+		let extracted = {
+			let _permit = resource_limiter().open_file().await;
+
+			match ObjectKind::from_int(object.kind) {
+				Ok(ObjectKind::Audio) => extract_audio_metadata(&path).await,
+				#[cfg(feature = "ffmpeg")]
+				Ok(ObjectKind::Video) => super::extract_video_metadata(&path).await,
+				_ => extract_image_metadata(&path).await,
+			}
+		};
+
+		match extracted {
+			Ok(metadata) => {
+				save_media_data(&ctx.library, object.id, metadata.into_params()).await?;
+				data.extracted += 1;
+			}
+			Err(e) => {
+				warn!(
+					"Failed to extract the media data of {}: {e:#?}",
+					path.display()
+				);
+				data.failed += 1;
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!(
+			"Finished extracting media data at {}: {} extracted, {} failed",
+			data.root_path.display(),
+			data.extracted,
+			data.failed
+		);
+
+		if data.extracted > 0 {
+			invalidate_query!(ctx.library, "files.mediaData");
+		}
+
+		Ok(Some(serde_json::to_value(&state.init)?))
+	}
+}
+
+/// save_media_data replaces the media data of an object.
+async fn save_media_data(
+	library: &Library,
+	object_id: i32,
+	mut params: Vec<media_data::SetParam>,
+) -> Result<(), JobError> {
+	let db = &library.db;
+
+	let exists =
+		db.media_data()
+			.count(vec![media_data::id::equals(object_id)])
+			.exec()
+			.await? > 0;
+
+	if !exists {
+		db.media_data()
+			.create_unchecked(object_id, vec![])
+			.exec()
+			.await?;
+	}
+
+	params.push(media_data::date_extracted::set(Utc::now().into()));
+	db.media_data()
+		.update(media_data::id::equals(object_id), params)
+		.exec()
+		.await?;
+
+	Ok(())
+}
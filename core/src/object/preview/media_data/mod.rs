@@ -0,0 +1,497 @@
+use crate::prisma::media_data;
+
+use std::{
+	collections::BTreeMap,
+	fs::File,
+	io::{BufReader, Read},
+	path::Path,
+};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone};
+use exif::{In, Tag, Value};
+use symphonia::core::{
+	formats::{FormatOptions, FormatReader},
+	io::MediaSourceStream,
+	meta::{
+		MetadataOptions, MetadataRevision, StandardTagKey, StandardVisualKey, Value as TagValue,
+	},
+	probe::{Hint, ProbeResult},
+};
+use thiserror::Error;
+use tokio::{io, task::spawn_blocking};
+
+pub mod media_data_job;
+
+#[derive(Error, Debug)]
+pub enum MediaDataError {
+	#[error("filesystem error: {0}")]
+	IO(#[from] io::Error),
+	#[error("failed to join extraction task: {0}")]
+	Join(#[from] tokio::task::JoinError),
+	#[error("audio tags error: {0}")]
+	Symphonia(#[from] symphonia::core::errors::Error),
+	#[cfg(feature = "ffmpeg")]
+	#[error("ffmpeg error: {0}")]
+	FFmpeg(#[from] ffmpeg_next::Error),
+}
+
+/// MediaMetadata is what could be extracted from an image, a video or an audio file, every field is optional as files carry
+/// whatever their camera or encoder wrote.
+#[derive(Debug, Default)]
+pub struct MediaMetadata {
+	pub pixel_width: Option<i32>,
+	pub pixel_height: Option<i32>,
+	pub longitude: Option<f64>,
+	pub latitude: Option<f64>,
+	pub fps: Option<i32>,
+	pub capture_device_make: Option<String>,
+	pub capture_device_model: Option<String>,
+	pub capture_device_software: Option<String>,
+	pub capture_date: Option<DateTime<FixedOffset>>,
+	pub duration_seconds: Option<i32>,
+	pub codecs: Option<String>,
+	pub streams: Option<i32>,
+	/// Every EXIF, IPTC, audio or container tag found, by name
+	pub tags: BTreeMap<String, String>,
+}
+
+impl MediaMetadata {
+	pub fn into_params(self) -> Vec<media_data::SetParam> {
+		vec![
+			media_data::pixel_width::set(self.pixel_width),
+			media_data::pixel_height::set(self.pixel_height),
+			media_data::longitude::set(self.longitude),
+			media_data::latitude::set(self.latitude),
+			media_data::fps::set(self.fps),
+			media_data::capture_device_make::set(self.capture_device_make),
+			media_data::capture_device_model::set(self.capture_device_model),
+			media_data::capture_device_software::set(self.capture_device_software),
+			media_data::capture_date::set(self.capture_date),
+			media_data::duration_seconds::set(self.duration_seconds),
+			media_data::codecs::set(self.codecs),
+			media_data::streams::set(self.streams),
+			media_data::tags::set(if self.tags.is_empty() {
+				None
+			} else {
+				serde_json::to_string(&self.tags).ok()
+			}),
+		]
+	}
+}
+
+/// extract_image_metadata reads the dimensions, EXIF and IPTC metadata of an image.
+pub async fn extract_image_metadata(
+	path: impl AsRef<Path>,
+) -> Result<MediaMetadata, MediaDataError> {
+	let path = path.as_ref().to_path_buf();
+
+	Ok(spawn_blocking(move || {
+		let mut metadata = MediaMetadata::default();
+
+		// Only the header is read, so it's cheap even for large images
+		if let Ok((width, height)) = image::image_dimensions(&path) {
+			metadata.pixel_width = Some(width as i32);
+			metadata.pixel_height = Some(height as i32);
+		}
+
+		read_exif(&path, &mut metadata)?;
+		read_iptc(&path, &mut metadata)?;
+
+		Ok::<_, MediaDataError>(metadata)
+	})
+	.await??)
+}
+
+fn ascii_value(value: &Value) -> Option<String> {
+	match value {
+		Value::Ascii(values) => values
+			.first()
+			.map(|bytes| String::from_utf8_lossy(bytes).trim().to_string())
+			.filter(|value| !value.is_empty()),
+		_ => None,
+	}
+}
+
+/// gps_coordinate converts an EXIF degrees, minutes and seconds coordinate to decimal degrees.
+fn gps_coordinate(exif: &exif::Exif, tag: Tag, ref_tag: Tag, negative_ref: &str) -> Option<f64> {
+	let Value::Rational(parts) = &exif.get_field(tag, In::PRIMARY)?.value else {
+		return None;
+	};
+
+	let coordinate = parts
+		.iter()
+		.zip([1.0, 60.0, 3600.0])
+		.map(|(part, divisor)| part.to_f64() / divisor)
+		.sum::<f64>();
+
+	let reference = exif
+		.get_field(ref_tag, In::PRIMARY)
+		.and_then(|field| ascii_value(&field.value));
+
+	Some(match reference.as_deref() {
+		Some(reference) if reference == negative_ref => -coordinate,
+		_ => coordinate,
+	})
+}
+
+fn read_exif(path: &Path, metadata: &mut MediaMetadata) -> Result<(), MediaDataError> {
+	let exif = match exif::Reader::new().read_from_container(&mut BufReader::new(File::open(path)?))
+	{
+		Ok(exif) => exif,
+		// Most images simply don't have any EXIF data
+		Err(exif::Error::Io(e)) => return Err(e.into()),
+		Err(_) => return Ok(()),
+	};
+
+	for field in exif.fields() {
+		metadata.tags.insert(
+			field.tag.to_string(),
+			field.display_value().with_unit(&exif).to_string(),
+		);
+	}
+
+	let ascii = |tag| {
+		exif.get_field(tag, In::PRIMARY)
+			.and_then(|field| ascii_value(&field.value))
+	};
+
+	metadata.capture_device_make = ascii(Tag::Make);
+	metadata.capture_device_model = ascii(Tag::Model);
+	metadata.capture_device_software = ascii(Tag::Software);
+
+	metadata.capture_date = exif
+		.get_field(Tag::DateTimeOriginal, In::PRIMARY)
+		.and_then(|field| match &field.value {
+			Value::Ascii(values) => exif::DateTime::from_ascii(values.first()?).ok(),
+			_ => None,
+		})
+		.and_then(|date| {
+			let naive =
+				NaiveDate::from_ymd_opt(date.year as i32, date.month as u32, date.day as u32)?
+					.and_hms_opt(date.hour as u32, date.minute as u32, date.second as u32)?;
+
+			// Cameras rarely write their timezone, in which case the local time of the capture is kept as is
+			let offset = ascii(Tag::OffsetTimeOriginal)
+				.and_then(|offset| {
+					DateTime::parse_from_str(
+						&format!("2000-01-01 00:00:00 {offset}"),
+						"%Y-%m-%d %H:%M:%S %:z",
+					)
+					.ok()
+				})
+				.map(|date| *date.offset())
+				.unwrap_or_else(|| FixedOffset::east_opt(0).expect("0 is a valid offset"));
+
+			offset.from_local_datetime(&naive).single()
+		});
+
+	metadata.latitude = gps_coordinate(&exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S");
+	metadata.longitude = gps_coordinate(&exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W");
+
+	// EXIF dimensions are only a fallback, as they're often left untouched by editors which resize the image
+	let dimension = |tag| {
+		exif.get_field(tag, In::PRIMARY)
+			.and_then(|field| field.value.get_uint(0))
+			.map(|value| value as i32)
+	};
+	metadata.pixel_width = metadata
+		.pixel_width
+		.or_else(|| dimension(Tag::PixelXDimension));
+	metadata.pixel_height = metadata
+		.pixel_height
+		.or_else(|| dimension(Tag::PixelYDimension));
+
+	Ok(())
+}
+
+/// The IPTC datasets of the application record which are kept, with the name they're stored under
+const IPTC_DATASETS: &[(u8, &str)] = &[
+	(5, "ObjectName"),
+	(25, "Keywords"),
+	(80, "By-line"),
+	(90, "City"),
+	(101, "Country"),
+	(105, "Headline"),
+	(110, "Credit"),
+	(116, "CopyrightNotice"),
+	(120, "Caption-Abstract"),
+];
+
+/// read_iptc reads the IPTC metadata of a JPEG, which is stored in the Photoshop resources of its APP13 segment.
+fn read_iptc(path: &Path, metadata: &mut MediaMetadata) -> Result<(), MediaDataError> {
+	const APP13_HEADER: &[u8] = b"Photoshop 3.0\0";
+	const IPTC_RESOURCE_ID: u16 = 0x0404;
+
+	let mut reader = BufReader::new(File::open(path)?);
+
+	let mut marker = [0; 2];
+	if reader.read_exact(&mut marker).is_err() || marker != [0xFF, 0xD8] {
+		// Not a JPEG
+		return Ok(());
+	}
+
+	// Walking the segments until the image data starts, IPTC is always in the header
+	let segment = loop {
+		let mut header = [0; 4];
+		if reader.read_exact(&mut header).is_err() || header[0] != 0xFF {
+			return Ok(());
+		}
+
+		let length = u16::from_be_bytes([header[2], header[3]]).saturating_sub(2) as usize;
+		let mut segment = vec![0; length];
+		reader.read_exact(&mut segment)?;
+
+		match header[1] {
+			0xED if segment.starts_with(APP13_HEADER) => break segment,
+			// Start of scan, there's no IPTC
+			0xDA => return Ok(()),
+			_ => continue,
+		}
+	};
+
+	// Photoshop image resources: "8BIM", id, padded pascal name, size, padded data
+	let mut resources = &segment[APP13_HEADER.len()..];
+	while resources.len() >= 12 && resources.starts_with(b"8BIM") {
+		let id = u16::from_be_bytes([resources[4], resources[5]]);
+		let name_length = resources[6] as usize;
+		let name_end = 7 + name_length + (name_length + 1) % 2;
+		let Some(size_bytes) = resources.get(name_end..name_end + 4) else {
+			return Ok(());
+		};
+		let size = u32::from_be_bytes([size_bytes[0], size_bytes[1], size_bytes[2], size_bytes[3]])
+			as usize;
+		let data_start = name_end + 4;
+		let Some(data) = resources.get(data_start..data_start + size) else {
+			return Ok(());
+		};
+
+		if id == IPTC_RESOURCE_ID {
+			read_iptc_datasets(data, metadata);
+			return Ok(());
+		}
+
+		resources = resources
+			.get(data_start + size + size % 2..)
+			.unwrap_or_default();
+	}
+
+	Ok(())
+}
+
+/// read_iptc_datasets reads the IPTC-IIM datasets of the application record, repeated datasets like keywords are joined.
+fn read_iptc_datasets(mut data: &[u8], metadata: &mut MediaMetadata) {
+	while data.len() >= 5 && data[0] == 0x1C {
+		let (record, dataset) = (data[1], data[2]);
+		let length = u16::from_be_bytes([data[3], data[4]]) as usize;
+		let Some(value) = data.get(5..5 + length) else {
+			return;
+		};
+
+		if record == 2 {
+			if let Some((_, name)) = IPTC_DATASETS.iter().find(|(id, _)| *id == dataset) {
+				let value = String::from_utf8_lossy(value).trim().to_string();
+				metadata
+					.tags
+					.entry(format!("Iptc.{name}"))
+					.and_modify(|values| {
+						values.push_str(", ");
+						values.push_str(&value);
+					})
+					.or_insert(value);
+			}
+		}
+
+		data = &data[5 + length..];
+	}
+}
+
+/// probe_audio opens the container of an audio file, returning it with the tags found in and before it.
+fn probe_audio(
+	path: &Path,
+) -> Result<(Box<dyn FormatReader>, Vec<MetadataRevision>), MediaDataError> {
+	let mut hint = Hint::new();
+	if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+		hint.with_extension(extension);
+	}
+
+	let ProbeResult {
+		mut format,
+		mut metadata,
+	} = symphonia::default::get_probe().format(
+		&hint,
+		MediaSourceStream::new(Box::new(File::open(path)?), Default::default()),
+		&FormatOptions::default(),
+		&MetadataOptions::default(),
+	)?;
+
+	// Tags can be part of the container, like Vorbis comments, or come before it, like the ID3v2 tags of MP3s
+	let revisions = format
+		.metadata()
+		.current()
+		.cloned()
+		.into_iter()
+		.chain(
+			metadata
+				.get()
+				.and_then(|metadata| metadata.current().cloned()),
+		)
+		.collect();
+
+	Ok((format, revisions))
+}
+
+/// extract_audio_metadata reads the duration, codec and ID3, Vorbis or MP4 tags of an audio file.
+pub async fn extract_audio_metadata(
+	path: impl AsRef<Path>,
+) -> Result<MediaMetadata, MediaDataError> {
+	let path = path.as_ref().to_path_buf();
+
+	Ok(spawn_blocking(move || {
+		let (format, revisions) = probe_audio(&path)?;
+
+		let mut metadata = MediaMetadata {
+			streams: Some(format.tracks().len() as i32),
+			..Default::default()
+		};
+
+		if let Some(track) = format.default_track() {
+			let params = &track.codec_params;
+
+			metadata.codecs = symphonia::default::get_codecs()
+				.get_codec(params.codec)
+				.map(|codec| codec.short_name.to_string());
+
+			if let (Some(time_base), Some(frames)) = (params.time_base, params.n_frames) {
+				metadata.duration_seconds = Some(time_base.calc_time(frames).seconds as i32);
+			}
+			if let Some(sample_rate) = params.sample_rate {
+				metadata
+					.tags
+					.insert("SampleRate".to_string(), format!("{sample_rate} Hz"));
+			}
+			if let Some(channels) = params.channels {
+				metadata
+					.tags
+					.insert("Channels".to_string(), channels.count().to_string());
+			}
+		}
+
+		// Files can carry several tag formats, like Vorbis comments and ID3v2, the ones of the container win
+		for tag in revisions.iter().flat_map(MetadataRevision::tags) {
+			let TagValue::String(value) = &tag.value else {
+				continue;
+			};
+
+			if tag.std_key == Some(StandardTagKey::Encoder) {
+				metadata
+					.capture_device_software
+					.get_or_insert_with(|| value.clone());
+			}
+
+			let key = match tag.std_key {
+				Some(key) => format!("{key:?}"),
+				None => tag.key.clone(),
+			};
+			metadata.tags.entry(key).or_insert_with(|| value.clone());
+		}
+
+		Ok::<_, MediaDataError>(metadata)
+	})
+	.await??)
+}
+
+/// extract_cover_art returns the embedded front cover of an audio file, or its first picture if none is marked as the front cover.
+pub async fn extract_cover_art(path: impl AsRef<Path>) -> Result<Option<Vec<u8>>, MediaDataError> {
+	let path = path.as_ref().to_path_buf();
+
+	Ok(spawn_blocking(move || {
+		let (_, revisions) = probe_audio(&path)?;
+		let pictures = revisions
+			.iter()
+			.flat_map(MetadataRevision::visuals)
+			.collect::<Vec<_>>();
+
+		Ok::<_, MediaDataError>(
+			pictures
+				.iter()
+				.find(|picture| picture.usage == Some(StandardVisualKey::FrontCover))
+				.or_else(|| pictures.first())
+				.map(|picture| picture.data.to_vec()),
+		)
+	})
+	.await??)
+}
+
+/// extract_video_metadata reads the dimensions, duration, codecs and container tags of a video.
+#[cfg(feature = "ffmpeg")]
+pub async fn extract_video_metadata(
+	path: impl AsRef<Path>,
+) -> Result<MediaMetadata, MediaDataError> {
+	use ffmpeg_next::{codec, ffi::AV_TIME_BASE, media::Type};
+
+	let path = path.as_ref().to_path_buf();
+
+	Ok(spawn_blocking(move || {
+		ffmpeg_next::init()?;
+
+		let input = ffmpeg_next::format::input(&path)?;
+		let mut metadata = MediaMetadata::default();
+
+		for (key, value) in input.metadata().iter() {
+			metadata.tags.insert(key.to_string(), value.to_string());
+		}
+
+		// Apple devices write their own tags, which are more accurate than the generic ones
+		let tag = |keys: &[&str]| keys.iter().find_map(|key| metadata.tags.get(*key)).cloned();
+		metadata.capture_device_make = tag(&["com.apple.quicktime.make", "make"]);
+		metadata.capture_device_model = tag(&["com.apple.quicktime.model", "model"]);
+		metadata.capture_device_software = tag(&["com.apple.quicktime.software", "encoder"]);
+		metadata.capture_date = tag(&["com.apple.quicktime.creationdate", "creation_time"])
+			.and_then(|date| DateTime::parse_from_rfc3339(&date).ok());
+		if let Some((latitude, longitude)) =
+			tag(&["com.apple.quicktime.location.ISO6709", "location"])
+				.as_deref()
+				.and_then(parse_iso6709)
+		{
+			metadata.latitude = Some(latitude);
+			metadata.longitude = Some(longitude);
+		}
+
+		metadata.duration_seconds = Some((input.duration().max(0) / AV_TIME_BASE as i64) as i32);
+		metadata.streams = Some(input.streams().count() as i32);
+
+		let mut codecs = vec![];
+		for stream in input.streams() {
+			let parameters = stream.parameters();
+			codecs.push(parameters.id().name().to_string());
+
+			if parameters.medium() == Type::Video && metadata.pixel_width.is_none() {
+				if let Ok(video) = codec::context::Context::from_parameters(parameters)
+					.and_then(|context| context.decoder().video())
+				{
+					metadata.pixel_width = Some(video.width() as i32);
+					metadata.pixel_height = Some(video.height() as i32);
+				}
+				metadata.fps = Some(f64::from(stream.avg_frame_rate()).round() as i32);
+			}
+		}
+		metadata.codecs = Some(codecs.join(","));
+
+		Ok::<_, MediaDataError>(metadata)
+	})
+	.await??)
+}
+
+/// parse_iso6709 parses the `+DD.DDDD+DDD.DDDD` prefix of an ISO 6709 location, which is how videos store their GPS position.
+#[cfg(feature = "ffmpeg")]
+fn parse_iso6709(location: &str) -> Option<(f64, f64)> {
+	let longitude_start = location[1..].find(['+', '-'])? + 1;
+	let longitude_end = location[longitude_start + 1..]
+		.find(['+', '-', '/'])
+		.map_or(location.len(), |end| end + longitude_start + 1);
+
+	Some((
+		location[..longitude_start].parse().ok()?,
+		location[longitude_start..longitude_end].parse().ok()?,
+	))
+}
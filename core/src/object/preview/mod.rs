@@ -1,5 +1,7 @@
 mod media_data;
 mod thumbnail;
+#[cfg(feature = "ffmpeg")]
+pub mod transcode;
 
 pub use media_data::*;
 pub use thumbnail::*;
@@ -0,0 +1,198 @@
+use crate::{
+	node::{ThumbnailConfig, ThumbnailFormat},
+	util::{io::read_file, resources::resource_limiter},
+};
+
+use std::{io::Cursor, ops::Deref, path::Path};
+
+use image::{
+	codecs::gif::GifDecoder, imageops, AnimationDecoder, ImageDecoder, ImageResult, RgbaImage,
+};
+use sd_file_ext::extensions::ImageExtension;
+use webp::{AnimEncoder, AnimFrame, WebPConfig};
+
+use super::{
+	pool::{thumbnailer_pool, worker_memory_bytes},
+	ThumbnailSpec, ThumbnailerError, ThumbnailerJobStepKind,
+};
+
+/// The most an animated thumbnail can weigh, heavier ones are replaced by still thumbnails
+pub(super) const ANIMATED_THUMBNAIL_MAX_BYTES: usize = 1024 * 1024;
+
+/// The most frames of a GIF its animated thumbnail keeps
+const MAX_GIF_FRAMES: u64 = 60;
+
+/// GIF frames shorter than this are shown for 100ms instead, like browsers do
+const MIN_GIF_FRAME_DELAY_MS: u32 = 20;
+
+/// How many frames of a video its animated thumbnail is made of, and how far apart they are
+#[cfg(feature = "ffmpeg")]
+const VIDEO_ANIMATION_FRAMES: u32 = 24;
+#[cfg(feature = "ffmpeg")]
+const VIDEO_ANIMATION_FRAME_INTERVAL: std::time::Duration = std::time::Duration::from_millis(125);
+
+/// Videos longer than this get still thumbnails, as their first seconds tell little about them
+#[cfg(feature = "ffmpeg")]
+const MAX_ANIMATED_VIDEO_SECONDS: i64 = 60;
+
+/// generate_animated_thumbnail encodes a looping animation as the thumbnail of a GIF or a short video,
+/// returning `None` for the files which are thumbnailed with a still image. Animations are always WebP,
+/// so thumbnails written in other formats stay still.
+pub(super) async fn generate_animated_thumbnail(
+	kind: ThumbnailerJobStepKind,
+	file_path: impl AsRef<Path>,
+	config: &ThumbnailConfig,
+	spec: ThumbnailSpec,
+) -> Result<Option<Vec<u8>>, ThumbnailerError> {
+	if config.format != ThumbnailFormat::Webp {
+		return Ok(None);
+	}
+
+	let file_path = file_path.as_ref();
+
+	match kind {
+		ThumbnailerJobStepKind::Image if is_gif(file_path) => {
+			let bytes = {
+				let _file_permit = resource_limiter().open_file().await;
+				read_file(file_path).await?
+			};
+			let max_alloc = worker_memory_bytes(config);
+
+			thumbnailer_pool(config)
+				.run(move |_| animate_gif(bytes, spec, max_alloc))
+				.await?
+				.map(Some)
+		}
+		#[cfg(feature = "ffmpeg")]
+		ThumbnailerJobStepKind::Video => animate_video(file_path, config, spec).await,
+		_ => Ok(None),
+	}
+}
+
+fn is_gif(file_path: &Path) -> bool {
+	file_path
+		.extension()
+		.and_then(|extension| extension.to_str())
+		.and_then(|extension| extension.to_lowercase().parse::<ImageExtension>().ok())
+		== Some(ImageExtension::Gif)
+}
+
+/// animate_gif scales the frames of a GIF down to the size of its thumbnail, keeping their timing.
+/// GIFs made of a single frame aren't animated.
+fn animate_gif(
+	bytes: Vec<u8>,
+	spec: ThumbnailSpec,
+	max_alloc: u64,
+) -> Result<Vec<u8>, ThumbnailerError> {
+	let decoder = GifDecoder::new(Cursor::new(bytes))?;
+	let (width, height) = decoder.dimensions();
+
+	// Every frame is decoded to its full size before being scaled down
+	let max_frames = (max_alloc / (width as u64 * height as u64 * 4).max(1)).min(MAX_GIF_FRAMES);
+	if max_frames == 0 {
+		return Err(ThumbnailerError::TooLarge);
+	}
+
+	let frames = decoder
+		.into_frames()
+		.take(max_frames as usize)
+		.collect::<ImageResult<Vec<_>>>()?;
+	if frames.len() < 2 {
+		return Err(ThumbnailerError::NotAnimated);
+	}
+
+	let (thumbnail_width, thumbnail_height) = spec.dimensions(width, height);
+	let (thumbnail_width, thumbnail_height) = (thumbnail_width.max(1), thumbnail_height.max(1));
+
+	let mut timestamp = 0;
+	let frames = frames
+		.into_iter()
+		.map(|frame| {
+			let frame_timestamp = timestamp;
+			let (numerator, denominator) = frame.delay().numer_denom_ms();
+			let delay = numerator / denominator.max(1);
+			timestamp += if delay < MIN_GIF_FRAME_DELAY_MS {
+				100
+			} else {
+				delay
+			};
+
+			(
+				imageops::resize(
+					frame.buffer(),
+					thumbnail_width,
+					thumbnail_height,
+					imageops::FilterType::Triangle,
+				),
+				frame_timestamp as i32,
+			)
+		})
+		.collect::<Vec<_>>();
+
+	encode_animation(&frames, thumbnail_width, thumbnail_height, spec.quality)
+}
+
+/// encode_animation encodes frames and the time they're shown at, in milliseconds, to a WebP animation looping forever.
+fn encode_animation(
+	frames: &[(RgbaImage, i32)],
+	width: u32,
+	height: u32,
+	quality: f32,
+) -> Result<Vec<u8>, ThumbnailerError> {
+	let mut config = WebPConfig::new().map_err(|_| {
+		ThumbnailerError::EncoderError("Failed to configure animation encoder".to_string())
+	})?;
+	config.quality = quality;
+
+	let mut encoder = AnimEncoder::new(width, height, &config);
+	encoder.set_loop_count(0);
+	for (frame, timestamp) in frames {
+		encoder.add_frame(AnimFrame::from_rgba(frame, width, height, *timestamp));
+	}
+
+	Ok(encoder.encode().deref().to_vec())
+}
+
+/// animate_video makes an animation of the first seconds of a video, if the video is short.
+#[cfg(feature = "ffmpeg")]
+async fn animate_video(
+	file_path: &Path,
+	config: &ThumbnailConfig,
+	spec: ThumbnailSpec,
+) -> Result<Option<Vec<u8>>, ThumbnailerError> {
+	use ffmpeg_next::ffi::AV_TIME_BASE;
+	use sd_ffmpeg::ThumbnailerBuilder;
+
+	let _file_permit = resource_limiter().open_file().await;
+
+	let file_path = file_path.to_path_buf();
+	let size = spec.rendered_size();
+	let quality = spec.quality;
+
+	thumbnailer_pool(config)
+		.run(move |handle| -> Result<Option<Vec<u8>>, ThumbnailerError> {
+			ffmpeg_next::init().map_err(|_| ThumbnailerError::NotAnimated)?;
+			// Durations in ffmpeg are in microseconds
+			let duration = ffmpeg_next::format::input(&file_path)
+				.map_err(|_| ThumbnailerError::NotAnimated)?
+				.duration();
+			if duration > MAX_ANIMATED_VIDEO_SECONDS * AV_TIME_BASE as i64 {
+				return Ok(None);
+			}
+
+			Ok(Some(
+				handle.block_on(
+					ThumbnailerBuilder::new()
+						.size(size)
+						.quality(quality)?
+						.build()
+						.process_to_animated_webp_bytes(
+							file_path,
+							VIDEO_ANIMATION_FRAMES,
+							VIDEO_ANIMATION_FRAME_INTERVAL,
+						),
+				)?,
+			))
+		})
+		.await?
+}
@@ -0,0 +1,289 @@
+use std::io::{Cursor, Read};
+
+#[cfg(feature = "pdfium")]
+use image::DynamicImage;
+
+use super::ThumbnailerError;
+
+/// extract_epub_cover returns the cover image of an EPUB, found through the package document of the book.
+pub(super) fn extract_epub_cover(bytes: Vec<u8>) -> Result<Option<Vec<u8>>, ThumbnailerError> {
+	let mut archive = zip::ZipArchive::new(Cursor::new(bytes))?;
+
+	let mut container = String::new();
+	archive
+		.by_name("META-INF/container.xml")?
+		.read_to_string(&mut container)?;
+	let Some(package_path) = find_package_path(&container) else {
+		return Ok(None);
+	};
+
+	let mut package = String::new();
+	archive
+		.by_name(&package_path)?
+		.read_to_string(&mut package)?;
+	let Some(cover_href) = find_cover_href(&package) else {
+		return Ok(None);
+	};
+
+	let mut cover = vec![];
+	match archive.by_name(&resolve_href(&package_path, &cover_href)) {
+		Ok(mut entry) => entry.read_to_end(&mut cover)?,
+		Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+		Err(e) => return Err(e.into()),
+	};
+
+	Ok(Some(cover))
+}
+
+/// find_package_path returns the path of the package document, the `.opf` file, from the container of an EPUB.
+fn find_package_path(container: &str) -> Option<String> {
+	tags(container)
+		.find(|(name, _)| *name == "rootfile")
+		.and_then(|(_, attributes)| attribute(&attributes, "full-path"))
+		.map(unescape)
+}
+
+/// find_cover_href returns the location of the cover image from the manifest of a package document.
+/// EPUB 3 marks the cover with a property, while EPUB 2 points to it from a `cover` meta element.
+fn find_cover_href(package: &str) -> Option<String> {
+	let items = tags(package)
+		.filter(|(name, _)| *name == "item")
+		.map(|(_, attributes)| attributes)
+		.collect::<Vec<_>>();
+
+	let cover_id = tags(package)
+		.find(|(name, attributes)| {
+			*name == "meta" && attribute(attributes, "name") == Some("cover")
+		})
+		.and_then(|(_, attributes)| attribute(&attributes, "content"));
+
+	let is_image = |attributes: &[(&str, &str)]| {
+		attribute(attributes, "media-type")
+			.map_or(false, |media_type| media_type.starts_with("image/"))
+	};
+
+	items
+		.iter()
+		.find(|attributes| {
+			attribute(attributes, "properties").map_or(false, |properties| {
+				properties
+					.split_whitespace()
+					.any(|property| property == "cover-image")
+			})
+		})
+		.or_else(|| {
+			cover_id.and_then(|cover_id| {
+				items
+					.iter()
+					.find(|attributes| attribute(attributes, "id") == Some(cover_id))
+			})
+		})
+		// Books without any cover metadata usually still name their cover image after it
+		.or_else(|| {
+			items.iter().find(|attributes| {
+				is_image(attributes)
+					&& ["id", "href"].into_iter().any(|key| {
+						attribute(attributes, key)
+							.map_or(false, |value| value.to_lowercase().contains("cover"))
+					})
+			})
+		})
+		.and_then(|attributes| attribute(attributes, "href"))
+		.map(unescape)
+}
+
+/// resolve_href resolves the location of a manifest item, relative to the package document, to an entry of the archive.
+fn resolve_href(package_path: &str, href: &str) -> String {
+	let mut segments = package_path.split('/').collect::<Vec<_>>();
+	// The package document itself isn't part of the directory
+	segments.pop();
+
+	let href = percent_decode(href.split('#').next().unwrap_or_default());
+	for segment in href.split('/') {
+		match segment {
+			"" | "." => {}
+			".." => {
+				segments.pop();
+			}
+			segment => segments.push(segment),
+		}
+	}
+
+	segments.join("/")
+}
+
+/// tags iterates over the opening and self-closing tags of an XML document, with their local name and attributes.
+fn tags(xml: &str) -> impl Iterator<Item = (&str, Vec<(&str, &str)>)> {
+	let mut rest = xml;
+
+	std::iter::from_fn(move || loop {
+		let tag_start = rest.find('<')?;
+		let tag_end = rest[tag_start..].find('>')? + tag_start;
+		let tag = &rest[tag_start + 1..tag_end];
+		rest = &rest[tag_end + 1..];
+
+		// Closing tags, comments, declarations and processing instructions
+		if tag.starts_with(['/', '!', '?']) {
+			continue;
+		}
+
+		let tag = tag.trim_end_matches('/');
+		let (name, mut attributes_source) =
+			tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+		let local_name = name.rsplit(':').next().unwrap_or(name);
+
+		let mut attributes = vec![];
+		while let Some((key, value_source)) = attributes_source.split_once('=') {
+			let value_source = value_source.trim_start();
+			let Some(quote) = value_source
+				.chars()
+				.next()
+				.filter(|c| *c == '"' || *c == '\'')
+			else {
+				break;
+			};
+			let Some((value, remaining)) = value_source[1..].split_once(quote) else {
+				break;
+			};
+
+			let key = key.trim();
+			attributes.push((key.rsplit(':').next().unwrap_or(key), value));
+			attributes_source = remaining;
+		}
+
+		return Some((local_name, attributes));
+	})
+}
+
+fn attribute<'a>(attributes: &[(&str, &'a str)], key: &str) -> Option<&'a str> {
+	attributes
+		.iter()
+		.find(|(attribute_key, _)| *attribute_key == key)
+		.map(|(_, value)| *value)
+}
+
+/// unescape replaces the predefined entities of XML in an attribute value.
+fn unescape(value: &str) -> String {
+	value
+		.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&apos;", "'")
+		.replace("&amp;", "&")
+}
+
+/// percent_decode decodes the escaped characters of a URL path, like the spaces in `My%20Cover.jpg`.
+fn percent_decode(path: &str) -> String {
+	let bytes = path.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'%' {
+			if let Some(byte) = path
+				.get(i + 1..i + 3)
+				.and_then(|hex| u8::from_str_radix(hex, 16).ok())
+			{
+				decoded.push(byte);
+				i += 3;
+				continue;
+			}
+		}
+
+		decoded.push(bytes[i]);
+		i += 1;
+	}
+
+	String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// render_pdf_first_page renders the first page of a PDF to fit in a square of `size` pixels, with the pdfium library
+/// shipped next to the executable or installed on the system.
+#[cfg(feature = "pdfium")]
+pub(super) fn render_pdf_first_page(
+	bytes: Vec<u8>,
+	size: u32,
+) -> Result<DynamicImage, ThumbnailerError> {
+	use pdfium_render::prelude::*;
+
+	let bindings = Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path("./"))
+		.or_else(|_| Pdfium::bind_to_system_library())?;
+	let pdfium = Pdfium::new(bindings);
+
+	let document = pdfium.load_pdf_from_byte_vec(bytes, None)?;
+	let page = document.pages().first()?;
+	let image = page
+		.render_with_config(
+			&PdfRenderConfig::new()
+				.set_target_width(size as i32)
+				.set_maximum_height(size as i32),
+		)?
+		.as_image();
+
+	Ok(DynamicImage::ImageRgba8(image.into_rgba8()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_find_package_path() {
+		let container = r#"<?xml version="1.0"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+	<rootfiles>
+		<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+	</rootfiles>
+</container>"#;
+
+		assert_eq!(
+			find_package_path(container),
+			Some("OEBPS/content.opf".to_string())
+		);
+	}
+
+	#[test]
+	fn test_find_cover_href() {
+		let epub3 = r#"<package xmlns="http://www.idpf.org/2007/opf" version="3.0">
+	<manifest>
+		<item id="chapter" href="text/chapter.xhtml" media-type="application/xhtml+xml"/>
+		<item id="img" href="images/front.jpg" media-type="image/jpeg" properties="cover-image"/>
+	</manifest>
+</package>"#;
+		assert_eq!(find_cover_href(epub3), Some("images/front.jpg".to_string()));
+
+		let epub2 = r#"<package xmlns="http://www.idpf.org/2007/opf" version="2.0">
+	<metadata><meta name="cover" content="front-image" /></metadata>
+	<manifest>
+		<item id='front-image' href='images/front&amp;back.png' media-type='image/png'/>
+	</manifest>
+</package>"#;
+		assert_eq!(
+			find_cover_href(epub2),
+			Some("images/front&back.png".to_string())
+		);
+
+		let unmarked = r#"<opf:package><opf:manifest>
+	<opf:item id="i1" href="Images/Cover.jpeg" media-type="image/jpeg"/>
+</opf:manifest></opf:package>"#;
+		assert_eq!(
+			find_cover_href(unmarked),
+			Some("Images/Cover.jpeg".to_string())
+		);
+
+		assert_eq!(find_cover_href("<package><manifest/></package>"), None);
+	}
+
+	#[test]
+	fn test_resolve_href() {
+		assert_eq!(
+			resolve_href("OEBPS/content.opf", "images/My%20Cover.jpg"),
+			"OEBPS/images/My Cover.jpg"
+		);
+		assert_eq!(
+			resolve_href("OEBPS/content.opf", "../cover.png"),
+			"cover.png"
+		);
+		assert_eq!(resolve_href("content.opf", "./cover.png"), "cover.png");
+	}
+}
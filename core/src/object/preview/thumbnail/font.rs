@@ -0,0 +1,249 @@
+use std::io::Read;
+
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use flate2::read::ZlibDecoder;
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use super::ThumbnailerError;
+
+/// The big sample of a font, showing the shapes of its letters
+const SAMPLE_HEADLINE: &str = "Aa";
+
+/// The small sample of a font under its headline, showing how it reads
+const SAMPLE_LINE: &str = "The quick brown fox";
+
+/// The most bytes the tables of a WOFF font can inflate to
+const MAX_WOFF_SFNT_BYTES: u64 = 64 * 1024 * 1024;
+
+const WOFF_SIGNATURE: &[u8] = b"wOFF";
+const WOFF_HEADER_LEN: usize = 44;
+const WOFF_TABLE_ENTRY_LEN: usize = 20;
+
+const BACKGROUND: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const INK: [u8; 3] = [28, 28, 30];
+
+/// render_font_sample draws sample text in a font, on a square of `size` pixels.
+/// TrueType and OpenType fonts are read as they are, WOFF fonts are unpacked first.
+pub(super) fn render_font_sample(
+	bytes: Vec<u8>,
+	size: u32,
+) -> Result<DynamicImage, ThumbnailerError> {
+	let bytes = if bytes.starts_with(WOFF_SIGNATURE) {
+		woff_to_sfnt(&bytes)?
+	} else {
+		bytes
+	};
+	let font = FontVec::try_from_vec(bytes).map_err(|_| ThumbnailerError::InvalidFont)?;
+
+	let mut canvas = RgbaImage::from_pixel(size, size, BACKGROUND);
+	let size = size as f32;
+	draw_line(&mut canvas, &font, SAMPLE_HEADLINE, size * 0.5, size * 0.6);
+	draw_line(&mut canvas, &font, SAMPLE_LINE, size * 0.11, size * 0.85);
+
+	Ok(DynamicImage::ImageRgba8(canvas))
+}
+
+/// draw_line draws a line of text centered horizontally on its baseline, shrinking it to fit the width of the canvas.
+fn draw_line(canvas: &mut RgbaImage, font: &FontVec, text: &str, px: f32, baseline: f32) {
+	let max_width = canvas.width() as f32 * 0.9;
+
+	let line_width = |scale: PxScale| {
+		let font = font.as_scaled(scale);
+		let mut previous = None;
+		text.chars().fold(0.0, |width, c| {
+			let glyph_id = font.glyph_id(c);
+			let kerning = previous.map_or(0.0, |previous| font.kern(previous, glyph_id));
+			previous = Some(glyph_id);
+			width + kerning + font.h_advance(glyph_id)
+		})
+	};
+
+	let mut scale = PxScale::from(px);
+	let width = line_width(scale);
+	if width > max_width {
+		scale = PxScale::from(px * max_width / width);
+	}
+
+	let scaled_font = font.as_scaled(scale);
+	let mut x = (canvas.width() as f32 - line_width(scale)) / 2.0;
+	let mut previous = None;
+
+	for c in text.chars() {
+		let glyph_id = scaled_font.glyph_id(c);
+		if let Some(previous) = previous {
+			x += scaled_font.kern(previous, glyph_id);
+		}
+		previous = Some(glyph_id);
+
+		let glyph = glyph_id.with_scale_and_position(scale, point(x, baseline));
+		x += scaled_font.h_advance(glyph_id);
+
+		let Some(outline) = font.outline_glyph(glyph) else {
+			continue;
+		};
+		let bounds = outline.px_bounds();
+		outline.draw(|glyph_x, glyph_y, coverage| {
+			let (pixel_x, pixel_y) = (
+				bounds.min.x as i32 + glyph_x as i32,
+				bounds.min.y as i32 + glyph_y as i32,
+			);
+			if pixel_x < 0
+				|| pixel_y < 0
+				|| pixel_x >= canvas.width() as i32
+				|| pixel_y >= canvas.height() as i32
+			{
+				return;
+			}
+
+			let pixel = canvas.get_pixel_mut(pixel_x as u32, pixel_y as u32);
+			let coverage = coverage.clamp(0.0, 1.0);
+			for (channel, ink) in pixel.0.iter_mut().zip(INK) {
+				*channel = (*channel as f32 * (1.0 - coverage) + ink as f32 * coverage) as u8;
+			}
+		});
+	}
+}
+
+/// woff_to_sfnt unpacks a WOFF font to the TrueType or OpenType font it wraps, inflating its tables.
+fn woff_to_sfnt(woff: &[u8]) -> Result<Vec<u8>, ThumbnailerError> {
+	let u16_at = |offset: usize| {
+		woff.get(offset..offset + 2)
+			.map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+			.ok_or(ThumbnailerError::InvalidFont)
+	};
+	let u32_at = |offset: usize| {
+		woff.get(offset..offset + 4)
+			.map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+			.ok_or(ThumbnailerError::InvalidFont)
+	};
+
+	let flavor = u32_at(4)?;
+	let num_tables = u16_at(12)?;
+
+	// The tables follow the offset table and the table records of the font
+	let mut table_offset = 12 + 16 * num_tables as usize;
+	let mut records = Vec::with_capacity(16 * num_tables as usize);
+	let mut tables = Vec::new();
+	let mut total_len = 0;
+
+	for index in 0..num_tables as usize {
+		let entry = WOFF_HEADER_LEN + index * WOFF_TABLE_ENTRY_LEN;
+		let (tag, offset, compressed_len, original_len, checksum) = (
+			u32_at(entry)?,
+			u32_at(entry + 4)? as usize,
+			u32_at(entry + 8)? as usize,
+			u32_at(entry + 12)?,
+			u32_at(entry + 16)?,
+		);
+
+		total_len += original_len as u64;
+		if total_len > MAX_WOFF_SFNT_BYTES {
+			return Err(ThumbnailerError::InvalidFont);
+		}
+
+		let data = woff
+			.get(offset..offset + compressed_len)
+			.ok_or(ThumbnailerError::InvalidFont)?;
+		// Tables are only compressed when it makes them smaller
+		let mut table = Vec::with_capacity(original_len as usize);
+		if compressed_len < original_len as usize {
+			ZlibDecoder::new(data)
+				.take(original_len as u64)
+				.read_to_end(&mut table)
+				.map_err(|_| ThumbnailerError::InvalidFont)?;
+		} else {
+			table.extend_from_slice(data);
+		}
+		if table.len() != original_len as usize {
+			return Err(ThumbnailerError::InvalidFont);
+		}
+
+		records.extend_from_slice(&tag.to_be_bytes());
+		records.extend_from_slice(&checksum.to_be_bytes());
+		records.extend_from_slice(&(table_offset as u32).to_be_bytes());
+		records.extend_from_slice(&original_len.to_be_bytes());
+
+		// Tables are aligned to 4 bytes
+		table.resize((table.len() + 3) & !3, 0);
+		table_offset += table.len();
+		tables.push(table);
+	}
+
+	let entry_selector = if num_tables == 0 {
+		0
+	} else {
+		15 - num_tables.leading_zeros() as u16
+	};
+	let search_range = 16u16.saturating_mul(1 << entry_selector);
+
+	let mut sfnt = Vec::with_capacity(table_offset);
+	sfnt.extend_from_slice(&flavor.to_be_bytes());
+	sfnt.extend_from_slice(&num_tables.to_be_bytes());
+	sfnt.extend_from_slice(&search_range.to_be_bytes());
+	sfnt.extend_from_slice(&entry_selector.to_be_bytes());
+	sfnt.extend_from_slice(
+		&num_tables
+			.saturating_mul(16)
+			.saturating_sub(search_range)
+			.to_be_bytes(),
+	);
+	sfnt.extend_from_slice(&records);
+	for table in tables {
+		sfnt.extend_from_slice(&table);
+	}
+
+	Ok(sfnt)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn woff_with_table(tag: &[u8; 4], table: &[u8]) -> Vec<u8> {
+		let mut woff = Vec::new();
+		woff.extend_from_slice(WOFF_SIGNATURE);
+		woff.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+		woff.extend_from_slice(&0u32.to_be_bytes());
+		woff.extend_from_slice(&1u16.to_be_bytes());
+		woff.resize(WOFF_HEADER_LEN, 0);
+
+		woff.extend_from_slice(tag);
+		woff.extend_from_slice(&((WOFF_HEADER_LEN + WOFF_TABLE_ENTRY_LEN) as u32).to_be_bytes());
+		woff.extend_from_slice(&(table.len() as u32).to_be_bytes());
+		woff.extend_from_slice(&(table.len() as u32).to_be_bytes());
+		woff.extend_from_slice(&0xCAFE_F00Du32.to_be_bytes());
+		woff.extend_from_slice(table);
+
+		woff
+	}
+
+	#[test]
+	fn test_woff_to_sfnt() {
+		let sfnt = woff_to_sfnt(&woff_with_table(b"name", &[1, 2, 3, 4, 5])).unwrap();
+
+		// Offset table
+		assert_eq!(&sfnt[..4], &[0, 1, 0, 0]);
+		assert_eq!(&sfnt[4..12], &[0, 1, 0, 16, 0, 0, 0, 0]);
+		// Table record, pointing right after itself
+		assert_eq!(&sfnt[12..16], b"name");
+		assert_eq!(&sfnt[16..20], &0xCAFE_F00Du32.to_be_bytes());
+		assert_eq!(&sfnt[20..24], &28u32.to_be_bytes());
+		assert_eq!(&sfnt[24..28], &5u32.to_be_bytes());
+		// Table, padded to 4 bytes
+		assert_eq!(&sfnt[28..], &[1, 2, 3, 4, 5, 0, 0, 0]);
+	}
+
+	#[test]
+	fn test_woff_to_sfnt_truncated() {
+		let woff = woff_with_table(b"name", &[1, 2, 3, 4, 5]);
+
+		assert!(matches!(
+			woff_to_sfnt(&woff[..woff.len() - 1]),
+			Err(ThumbnailerError::InvalidFont)
+		));
+		assert!(matches!(
+			woff_to_sfnt(&woff[..20]),
+			Err(ThumbnailerError::InvalidFont)
+		));
+	}
+}
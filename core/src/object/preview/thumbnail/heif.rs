@@ -0,0 +1,42 @@
+use image::{DynamicImage, RgbaImage};
+use libheif_rs::{ColorSpace, HeifContext, RgbChroma};
+use sd_file_ext::extensions::ImageExtension;
+
+use super::ThumbnailerError;
+
+pub(super) const fn is_heif_image(image_extension: &ImageExtension) -> bool {
+	use ImageExtension::*;
+	matches!(image_extension, Heic | Heif)
+}
+
+/// decode_heif decodes the primary image of a HEIF file, like the HEIC photos taken by iPhones.
+/// Like other images, it fails when decoding would need more than `max_alloc` bytes.
+pub(super) fn decode_heif(bytes: &[u8], max_alloc: u64) -> Result<DynamicImage, ThumbnailerError> {
+	let context = HeifContext::read_from_bytes(bytes)?;
+	let handle = context.primary_image_handle()?;
+
+	let (width, height) = (handle.width(), handle.height());
+	if width as u64 * height as u64 * 4 > max_alloc {
+		return Err(ThumbnailerError::TooLarge);
+	}
+
+	// The rotation and mirroring of the photo are applied by libheif
+	let image = handle.decode(ColorSpace::Rgb(RgbChroma::Rgba), None)?;
+	let Some(plane) = image.planes().interleaved else {
+		return Err(ThumbnailerError::NoHeifPixels);
+	};
+
+	// Rows of the plane can be padded, so only the pixels of each row are kept
+	let row_len = plane.width as usize * 4;
+	let pixels = plane
+		.data
+		.chunks(plane.stride)
+		.take(plane.height as usize)
+		.flat_map(|row| row.get(..row_len).unwrap_or(row))
+		.copied()
+		.collect::<Vec<_>>();
+
+	RgbaImage::from_raw(plane.width, plane.height, pixels)
+		.map(DynamicImage::ImageRgba8)
+		.ok_or(ThumbnailerError::NoHeifPixels)
+}
@@ -1,39 +1,114 @@
 use crate::{
-	api::CoreEvent,
-	invalidate_query,
+	api::{locations::invalidate_explorer_path, CoreEvent},
+	invalidate_query_debounced,
 	job::{JobError, JobReportUpdate, JobResult, WorkerContext},
+	library::Library,
 	location::{
 		file_path_helper::{file_path_just_materialized_path_cas_id, FilePathError},
 		LocationId,
 	},
+	node::{ThumbnailConfig, ThumbnailFormat, ThumbnailVariant},
+	object::preview::extract_cover_art,
+	prisma::{thumbnail, PrismaClient},
+	util::{
+		governor::{governor, ThrottleReason},
+		io::read_file,
+		resources::resource_limiter,
+	},
 };
 
 use std::{
+	collections::{HashMap, HashSet, VecDeque},
 	error::Error,
+	io::Cursor,
 	ops::Deref,
 	path::{Path, PathBuf},
+	time::{Duration, SystemTime},
 };
 
-use sd_file_ext::extensions::{Extension, ImageExtension};
+use sd_file_ext::extensions::{
+	AudioExtension, DocumentExtension, Extension, FontExtension, ImageExtension,
+};
 
 #[cfg(feature = "ffmpeg")]
 use sd_file_ext::extensions::VideoExtension;
 
-use image::{self, imageops, DynamicImage, GenericImageView};
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use image::{
+	self,
+	codecs::avif::AvifEncoder,
+	imageops,
+	io::{Limits, Reader},
+	DynamicImage, GenericImageView, ImageEncoder, ImageError, ImageFormat,
+};
 use once_cell::sync::Lazy;
+use rspc::ErrorCode;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tokio::{fs, io, task::block_in_place};
-use tracing::{error, info, trace, warn};
+use tokio::{fs, io, task::spawn_blocking, time::sleep};
+use tracing::{debug, error, info, trace, warn};
 use webp::Encoder;
 
+mod animated;
+mod document;
+mod font;
+#[cfg(feature = "heif")]
+mod heif;
+mod pool;
+mod preview_strip;
+mod raw;
+mod requested;
 pub mod shallow_thumbnailer_job;
+#[cfg(feature = "svg-thumbnails")]
+mod svg;
+pub mod thumbnail_gc_job;
 pub mod thumbnailer_job;
 
-static THUMBNAIL_SIZE_FACTOR: f32 = 0.2;
-static THUMBNAIL_QUALITY: f32 = 30.0;
+use animated::{generate_animated_thumbnail, ANIMATED_THUMBNAIL_MAX_BYTES};
+use document::extract_epub_cover;
+#[cfg(feature = "pdfium")]
+use document::render_pdf_first_page;
+use font::render_font_sample;
+use pool::{queued_thumbnails, thumbnailer_pool, worker_memory_bytes};
+#[cfg(feature = "ffmpeg")]
+use preview_strip::generate_preview_strip;
+pub use preview_strip::{
+	get_preview_strip, preview_strip_path, VideoPreviewStrip, PREVIEW_STRIP_FRAMES,
+	PREVIEW_STRIP_VARIANT,
+};
+use raw::{decode_raw, is_raw_image};
+pub use requested::request_thumbnails;
+
+#[cfg(feature = "heif")]
+use heif::{decode_heif, is_heif_image};
+#[cfg(feature = "svg-thumbnails")]
+use svg::{rasterize_svg, MAX_SVG_BYTES};
+#[cfg(feature = "svg-thumbnails")]
+use tokio::time::timeout;
+
 pub static THUMBNAIL_CACHE_DIR_NAME: &str = "thumbnails";
 
+/// How many cas_ids are looked up at once in the records of generated thumbnails
+const THUMBNAIL_RECORDS_CHUNK_SIZE: usize = 500;
+
+/// Recorded for the files which can't have a thumbnail, like audio files without a cover, so they aren't tried again.
+/// Variant names can't have '<', so it doesn't clash with them.
+const NO_THUMBNAIL_VARIANT: &str = "<none>";
+
+/// How long thumbnailer jobs pause after each batch of files while they are throttled
+const THROTTLE_PAUSE: Duration = Duration::from_millis(500);
+
+/// How long an SVG can take to render before it's given up on
+#[cfg(feature = "svg-thumbnails")]
+const SVG_RENDER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The speed of the AVIF encoder, from 1 to 10. Thumbnails are encoded by the thousands, so it leans towards speed
+const AVIF_ENCODER_SPEED: u8 = 8;
+
+/// The file in the thumbnails directory recording the settings thumbnails were last generated with
+const THUMBNAIL_SETTINGS_FILE_NAME: &str = "settings.json";
+
 #[cfg(feature = "ffmpeg")]
 static FILTERED_VIDEO_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
 	sd_file_ext::extensions::ALL_VIDEO_EXTENSIONS
@@ -53,11 +128,44 @@ static FILTERED_IMAGE_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
 		.collect()
 });
 
+static FILTERED_AUDIO_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	sd_file_ext::extensions::ALL_AUDIO_EXTENSIONS
+		.iter()
+		.map(Clone::clone)
+		.filter(can_generate_thumbnail_for_audio)
+		.map(Extension::Audio)
+		.collect()
+});
+
+static FILTERED_DOCUMENT_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	sd_file_ext::extensions::ALL_DOCUMENT_EXTENSIONS
+		.iter()
+		.map(Clone::clone)
+		.filter(can_generate_thumbnail_for_document)
+		.map(Extension::Document)
+		.collect()
+});
+
+static FILTERED_FONT_EXTENSIONS: Lazy<Vec<Extension>> = Lazy::new(|| {
+	sd_file_ext::extensions::ALL_FONT_EXTENSIONS
+		.iter()
+		.map(Clone::clone)
+		.filter(can_generate_thumbnail_for_font)
+		.map(Extension::Font)
+		.collect()
+});
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ThumbnailerJobState {
 	thumbnail_dir: PathBuf,
 	location_path: PathBuf,
 	report: ThumbnailerJobReport,
+	/// Thumbnails written before their settings last changed are generated again
+	#[serde(default)]
+	thumbnails_outdated_before: Option<ThumbnailsOutdatedBefore>,
+	/// How many files were processed, as a step can process several of them at once
+	#[serde(default)]
+	processed_files: usize,
 }
 
 #[derive(Error, Debug)]
@@ -66,6 +174,60 @@ pub enum ThumbnailerError {
 	FilePathError(#[from] FilePathError),
 	#[error("IO error (error: {0})")]
 	IOError(#[from] io::Error),
+	#[error("Image error (error: {0})")]
+	ImageError(#[from] ImageError),
+	#[error("WebP encoding error (error: {0})")]
+	EncoderError(String),
+	#[error("Thumbnailer worker failed")]
+	WorkerFailed,
+	#[error("File has no embedded cover art")]
+	NoCoverArt,
+	#[error("Document archive error (error: {0})")]
+	ZipError(#[from] zip::result::ZipError),
+	#[cfg(feature = "pdfium")]
+	#[error("PDF rendering error (error: {0})")]
+	PdfiumError(#[from] pdfium_render::prelude::PdfiumError),
+	#[error("Thumbnails can't be generated for this document")]
+	UnsupportedDocument,
+	#[cfg(feature = "heif")]
+	#[error("HEIF decoding error (error: {0})")]
+	HeifError(#[from] libheif_rs::HeifError),
+	#[cfg(feature = "heif")]
+	#[error("Decoded HEIF image has no pixels")]
+	NoHeifPixels,
+	#[error("Image needs more memory to decode than a thumbnailer worker has")]
+	TooLarge,
+	#[cfg(feature = "svg-thumbnails")]
+	#[error("SVG error (error: {0})")]
+	SvgError(#[from] resvg::usvg::Error),
+	#[cfg(feature = "svg-thumbnails")]
+	#[error("SVG has nothing to render")]
+	EmptySvg,
+	#[cfg(feature = "svg-thumbnails")]
+	#[error("SVG is too big to render")]
+	SvgTooLarge,
+	#[cfg(feature = "svg-thumbnails")]
+	#[error("SVG took too long to render")]
+	SvgTimedOut,
+	#[error("Raw image has no embedded preview")]
+	NoEmbeddedPreview,
+	#[error("Font can't be read")]
+	InvalidFont,
+	#[error("File can't be animated")]
+	NotAnimated,
+	#[error("Thumbnail settings error (error: {0})")]
+	SettingsError(#[from] serde_json::Error),
+	#[error("Database error (error: {0:?})")]
+	DatabaseError(#[from] prisma_client_rust::QueryError),
+	#[cfg(feature = "ffmpeg")]
+	#[error("Video decoding error (error: {0})")]
+	VideoError(#[from] sd_ffmpeg::ThumbnailerError),
+}
+
+impl From<ThumbnailerError> for rspc::Error {
+	fn from(error: ThumbnailerError) -> Self {
+		rspc::Error::with_cause(ErrorCode::InternalServerError, error.to_string(), error)
+	}
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -78,6 +240,9 @@ pub struct ThumbnailerJobReport {
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 enum ThumbnailerJobStepKind {
 	Image,
+	Audio,
+	Document,
+	Font,
 	#[cfg(feature = "ffmpeg")]
 	Video,
 }
@@ -88,47 +253,692 @@ pub struct ThumbnailerJobStep {
 	kind: ThumbnailerJobStepKind,
 }
 
+/// ThumbnailSize is how big a thumbnail is, relative to its original or by the length of its longest edge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThumbnailSize {
+	Factor(f32),
+	MaxEdge(u32),
+}
+
+/// ThumbnailSpec is the size and WebP quality a thumbnail is generated with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThumbnailSpec {
+	pub size: ThumbnailSize,
+	pub quality: f32,
+}
+
+impl ThumbnailSpec {
+	/// The spec of the default thumbnails, the ones shown by the explorer
+	pub fn default_of(config: &ThumbnailConfig) -> Self {
+		Self {
+			size: ThumbnailSize::Factor(config.size_factor),
+			quality: config.quality,
+		}
+	}
+
+	pub fn of_variant(variant: &ThumbnailVariant, config: &ThumbnailConfig) -> Self {
+		Self {
+			size: ThumbnailSize::MaxEdge(variant.size),
+			quality: variant.quality.unwrap_or(config.quality),
+		}
+	}
+
+	fn dimensions(&self, width: u32, height: u32) -> (u32, u32) {
+		match self.size {
+			// FIXME : Think of a better heuristic to get the thumbnail size
+			ThumbnailSize::Factor(factor) => (
+				(width as f32 * factor) as u32,
+				(height as f32 * factor) as u32,
+			),
+			ThumbnailSize::MaxEdge(max_edge) => {
+				let longest_edge = width.max(height);
+				if longest_edge <= max_edge {
+					return (width, height);
+				}
+
+				let scale = max_edge as f32 / longest_edge as f32;
+				(
+					((width as f32 * scale) as u32).max(1),
+					((height as f32 * scale) as u32).max(1),
+				)
+			}
+		}
+	}
+
+	/// Videos, PDFs, SVGs and fonts are rendered at the length of their longest edge, as they have no size of their own to scale
+	fn rendered_size(&self) -> u32 {
+		match self.size {
+			ThumbnailSize::Factor(_) => 256,
+			ThumbnailSize::MaxEdge(max_edge) => max_edge,
+		}
+	}
+}
+
+/// thumbnail_specs lists the default thumbnail and every variant with a valid name, with the name of their variant.
+pub fn thumbnail_specs(
+	config: &ThumbnailConfig,
+) -> impl Iterator<Item = (Option<&str>, ThumbnailSpec)> {
+	std::iter::once((None, ThumbnailSpec::default_of(config))).chain(
+		config
+			.variants
+			.iter()
+			.filter(|variant| variant.has_valid_name())
+			.map(|variant| {
+				(
+					Some(variant.name.as_str()),
+					ThumbnailSpec::of_variant(variant, config),
+				)
+			}),
+	)
+}
+
+/// thumbnail_path returns where the thumbnail of `cas_id` is written, in the directory of its variant if it has one.
+pub fn thumbnail_path(
+	thumbnail_dir: impl AsRef<Path>,
+	variant: Option<&str>,
+	cas_id: &str,
+	format: ThumbnailFormat,
+) -> PathBuf {
+	let thumbnail_dir = thumbnail_dir.as_ref();
+	match variant {
+		Some(variant) => thumbnail_dir.join(variant),
+		None => thumbnail_dir.to_path_buf(),
+	}
+	.join(cas_id)
+	.with_extension(format.extension())
+}
+
+/// remove_thumbnails removes every thumbnail of `cas_id`, in each variant and format, along with its preview strip.
+/// Returns how many files were removed.
+async fn remove_thumbnails(
+	thumbnail_dir: impl AsRef<Path>,
+	cas_id: &str,
+	config: &ThumbnailConfig,
+) -> io::Result<usize> {
+	let thumbnail_dir = thumbnail_dir.as_ref();
+
+	let paths = thumbnail_specs(config)
+		.flat_map(|(variant, _)| {
+			ThumbnailFormat::ALL
+				.into_iter()
+				.map(move |format| thumbnail_path(thumbnail_dir, variant, cas_id, format))
+		})
+		.chain(std::iter::once(preview_strip_path(thumbnail_dir, cas_id)))
+		.collect::<Vec<_>>();
+
+	let mut removed = 0;
+	for path in paths {
+		match fs::remove_file(path).await {
+			Ok(()) => removed += 1,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+			Err(e) => return Err(e),
+		}
+	}
+
+	Ok(removed)
+}
+
+/// remove_stale_thumbnails removes the thumbnails of `cas_id` written in other formats than `format`.
+async fn remove_stale_thumbnails(
+	thumbnail_dir: impl AsRef<Path>,
+	variant: Option<&str>,
+	cas_id: &str,
+	format: ThumbnailFormat,
+) -> io::Result<()> {
+	for stale_format in ThumbnailFormat::ALL.into_iter().filter(|f| *f != format) {
+		match fs::remove_file(thumbnail_path(
+			&thumbnail_dir,
+			variant,
+			cas_id,
+			stale_format,
+		))
+		.await
+		{
+			Ok(()) => {}
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+			Err(e) => return Err(e),
+		}
+	}
+
+	Ok(())
+}
+
+/// create_thumbnail_dirs creates the thumbnails directory and the directories of its variants.
+pub async fn create_thumbnail_dirs(
+	thumbnail_dir: impl AsRef<Path>,
+	config: &ThumbnailConfig,
+) -> io::Result<()> {
+	let thumbnail_dir = thumbnail_dir.as_ref();
+	fs::create_dir_all(thumbnail_dir).await?;
+
+	for variant in &config.variants {
+		if variant.has_valid_name() {
+			fs::create_dir_all(thumbnail_dir.join(&variant.name)).await?;
+		} else {
+			warn!(
+				"Skipping thumbnail variant with invalid name {:?}, only ASCII letters, digits, '-' and '_' are allowed, and {PREVIEW_STRIP_VARIANT:?} is reserved",
+				variant.name
+			);
+		}
+	}
+
+	Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct ThumbnailSettings {
+	size_factor: f32,
+	quality: f32,
+	variants: Vec<ThumbnailVariant>,
+	#[serde(default)]
+	animated: bool,
+	#[serde(default)]
+	format: ThumbnailFormat,
+	/// changed_at is when the settings of the default thumbnails last changed
+	changed_at: DateTime<Utc>,
+	/// variants_changed_at is when the settings of each variant last changed
+	#[serde(default)]
+	variants_changed_at: HashMap<String, DateTime<Utc>>,
+	#[serde(default)]
+	format_changed_at: Option<DateTime<Utc>>,
+	#[serde(default)]
+	preview_strips_changed_at: Option<DateTime<Utc>>,
+}
+
+/// ThumbnailsOutdatedBefore tells when the settings of the default thumbnails, of each variant and of preview strips
+/// last changed. Thumbnails written before that are outdated, the others are kept.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ThumbnailsOutdatedBefore {
+	default: DateTime<Utc>,
+	variants: HashMap<String, DateTime<Utc>>,
+	preview_strips: DateTime<Utc>,
+}
+
+impl ThumbnailsOutdatedBefore {
+	/// of returns when the thumbnails of `variant` were outdated, the default thumbnails for `None`.
+	fn of(&self, variant: Option<&str>) -> DateTime<Utc> {
+		match variant {
+			None | Some("") | Some(NO_THUMBNAIL_VARIANT) => self.default,
+			Some(PREVIEW_STRIP_VARIANT) => self.preview_strips,
+			Some(variant) => self.variants.get(variant).copied().unwrap_or(self.default),
+		}
+	}
+}
+
+/// refresh_thumbnail_settings records the settings thumbnails are generated with in the thumbnails directory,
+/// returning when they last changed. Thumbnails written before that are outdated.
+/// Each variant has its own time, so changing or adding a variant only regenerates its thumbnails,
+/// while changing the format outdates the thumbnails of every variant, but not the preview strips which are always WebP.
+/// When no settings were recorded yet, the existing thumbnails are kept.
+async fn refresh_thumbnail_settings(
+	thumbnail_dir: impl AsRef<Path>,
+	config: &ThumbnailConfig,
+) -> Result<ThumbnailsOutdatedBefore, ThumbnailerError> {
+	let settings_path = thumbnail_dir.as_ref().join(THUMBNAIL_SETTINGS_FILE_NAME);
+	let now = Utc::now();
+
+	let old_settings = match fs::read(&settings_path).await {
+		Ok(bytes) => match serde_json::from_slice::<ThumbnailSettings>(&bytes) {
+			Ok(settings) => Some(settings),
+			Err(e) => {
+				warn!("Failed to read thumbnail settings, regenerating thumbnails: {e:#?}");
+				None
+			}
+		},
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Some(ThumbnailSettings {
+			size_factor: config.size_factor,
+			quality: config.quality,
+			variants: config.variants.clone(),
+			animated: config.animated_thumbnails,
+			format: config.format,
+			changed_at: DateTime::<Utc>::from(SystemTime::UNIX_EPOCH),
+			variants_changed_at: HashMap::new(),
+			format_changed_at: None,
+			preview_strips_changed_at: None,
+		}),
+		Err(e) => return Err(e.into()),
+	};
+
+	let settings = match &old_settings {
+		Some(old) => ThumbnailSettings {
+			size_factor: config.size_factor,
+			quality: config.quality,
+			variants: config.variants.clone(),
+			animated: config.animated_thumbnails,
+			format: config.format,
+			changed_at: if old.size_factor == config.size_factor
+				&& old.quality == config.quality
+				&& old.animated == config.animated_thumbnails
+			{
+				old.changed_at
+			} else {
+				now
+			},
+			variants_changed_at: config
+				.variants
+				.iter()
+				.map(|variant| {
+					let unchanged = old.variants.contains(variant)
+						// Variants without a quality of their own use the quality of the default thumbnails
+						&& (variant.quality.is_some() || old.quality == config.quality);
+
+					let changed_at = if unchanged {
+						old.variants_changed_at
+							.get(&variant.name)
+							.copied()
+							.unwrap_or(old.changed_at)
+					} else {
+						now
+					};
+
+					(variant.name.clone(), changed_at)
+				})
+				.collect(),
+			// Settings recorded with a single time for everything keep it for the format and the preview strips
+			format_changed_at: if old.format == config.format {
+				old.format_changed_at.or(Some(old.changed_at))
+			} else {
+				Some(now)
+			},
+			preview_strips_changed_at: if old.quality == config.quality {
+				old.preview_strips_changed_at.or(Some(old.changed_at))
+			} else {
+				Some(now)
+			},
+		},
+		None => ThumbnailSettings {
+			size_factor: config.size_factor,
+			quality: config.quality,
+			variants: config.variants.clone(),
+			animated: config.animated_thumbnails,
+			format: config.format,
+			changed_at: now,
+			variants_changed_at: config
+				.variants
+				.iter()
+				.map(|variant| (variant.name.clone(), now))
+				.collect(),
+			format_changed_at: Some(now),
+			preview_strips_changed_at: Some(now),
+		},
+	};
+
+	if old_settings.as_ref() != Some(&settings) {
+		info!("Thumbnail settings changed, outdated thumbnails will be regenerated");
+		fs::write(&settings_path, serde_json::to_vec(&settings)?).await?;
+	}
+
+	let format_changed_at = settings.format_changed_at.unwrap_or(settings.changed_at);
+	Ok(ThumbnailsOutdatedBefore {
+		default: settings.changed_at.max(format_changed_at),
+		variants: settings
+			.variants_changed_at
+			.into_iter()
+			.map(|(variant, changed_at)| (variant, changed_at.max(format_changed_at)))
+			.collect(),
+		preview_strips: settings
+			.preview_strips_changed_at
+			.unwrap_or(settings.changed_at),
+	})
+}
+
+/// prepare_thumbnail_dir creates the directories of thumbnails and returns when the thumbnails of each variant were outdated.
+async fn prepare_thumbnail_dir(
+	thumbnail_dir: impl AsRef<Path>,
+	config: &ThumbnailConfig,
+) -> Result<ThumbnailsOutdatedBefore, ThumbnailerError> {
+	create_thumbnail_dirs(&thumbnail_dir, config).await?;
+	refresh_thumbnail_settings(thumbnail_dir, config).await
+}
+
+/// is_thumbnail_outdated tells if the thumbnail at `output_path` is missing or was written before `outdated_before`.
+async fn is_thumbnail_outdated(
+	output_path: impl AsRef<Path>,
+	outdated_before: Option<DateTime<Utc>>,
+) -> io::Result<bool> {
+	match fs::metadata(output_path).await {
+		Ok(metadata) => Ok(match outdated_before {
+			Some(outdated_before) => DateTime::<Utc>::from(metadata.modified()?) < outdated_before,
+			None => false,
+		}),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(true),
+		Err(e) => Err(e),
+	}
+}
+
 pub async fn generate_image_thumbnail<P: AsRef<Path>>(
 	file_path: P,
 	output_path: P,
+	config: &ThumbnailConfig,
+	spec: ThumbnailSpec,
 ) -> Result<(), Box<dyn Error>> {
-	// Webp creation has blocking code
-	let webp = block_in_place(|| -> Result<Vec<u8>, Box<dyn Error>> {
-		// Using `image` crate, open the included .jpg file
-		let img = image::open(file_path)?;
-		let (w, h) = img.dimensions();
-		// Optionally, resize the existing photo and convert back into DynamicImage
-		let img = DynamicImage::ImageRgba8(imageops::resize(
-			&img,
-			// FIXME : Think of a better heuristic to get the thumbnail size
-			(w as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
-			(h as f32 * THUMBNAIL_SIZE_FACTOR) as u32,
-			imageops::FilterType::Triangle,
-		));
-		// Create the WebP encoder for the above image
-		let encoder = Encoder::from_image(&img)?;
+	let extension = file_path
+		.as_ref()
+		.extension()
+		.and_then(|extension| extension.to_str())
+		.and_then(|extension| extension.to_lowercase().parse::<ImageExtension>().ok());
+
+	#[cfg(feature = "svg-thumbnails")]
+	if extension == Some(ImageExtension::Svg) {
+		return generate_svg_thumbnail(file_path, output_path, config, spec).await;
+	}
+
+	let bytes = {
+		let _file_permit = resource_limiter().open_file().await;
+		read_file(&file_path).await?
+	};
+	let max_alloc = worker_memory_bytes(config);
+
+	if extension.map_or(false, |extension| is_raw_image(&extension)) {
+		return generate_decoded_thumbnail(
+			move || decode_raw(&bytes, max_alloc),
+			output_path,
+			config,
+			spec,
+		)
+		.await;
+	}
+
+	#[cfg(feature = "heif")]
+	if extension.map_or(false, |extension| is_heif_image(&extension)) {
+		return generate_decoded_thumbnail(
+			move || decode_heif(&bytes, max_alloc),
+			output_path,
+			config,
+			spec,
+		)
+		.await;
+	}
+
+	encode_thumbnail(
+		bytes,
+		ImageFormat::from_path(&file_path).ok(),
+		output_path,
+		config,
+		spec,
+	)
+	.await
+}
+
+/// generate_svg_thumbnail rasterizes an SVG to write its thumbnail, skipping the ones which are too big or take
+/// too long to render.
+#[cfg(feature = "svg-thumbnails")]
+async fn generate_svg_thumbnail(
+	file_path: impl AsRef<Path>,
+	output_path: impl AsRef<Path>,
+	config: &ThumbnailConfig,
+	spec: ThumbnailSpec,
+) -> Result<(), Box<dyn Error>> {
+	let bytes = {
+		let _file_permit = resource_limiter().open_file().await;
+		if fs::metadata(&file_path).await?.len() > MAX_SVG_BYTES {
+			return Err(ThumbnailerError::SvgTooLarge.into());
+		}
+		read_file(&file_path).await?
+	};
+
+	let size = spec.rendered_size();
+	let output_format = config.format;
+
+	// Rendering can't be interrupted, so it runs on a blocking thread instead of the thumbnailer workers,
+	// which keeps an SVG that never finishes from holding one of them
+	let Ok(rendered) = timeout(
+		SVG_RENDER_TIMEOUT,
+		spawn_blocking(move || {
+			encode_image(&rasterize_svg(&bytes, size)?, output_format, spec.quality)
+		}),
+	)
+	.await
+	else {
+		return Err(ThumbnailerError::SvgTimedOut.into());
+	};
+	let thumbnail = rendered??;
+
+	fs::write(output_path, &thumbnail).await.map_err(Into::into)
+}
+
+/// generate_decoded_thumbnail writes the thumbnail of an image the `image` crate can't decode by itself,
+/// like camera raw and HEIF files, decoding it with `decode` on the thumbnailer workers.
+async fn generate_decoded_thumbnail(
+	decode: impl FnOnce() -> Result<DynamicImage, ThumbnailerError> + Send + 'static,
+	output_path: impl AsRef<Path>,
+	config: &ThumbnailConfig,
+	spec: ThumbnailSpec,
+) -> Result<(), Box<dyn Error>> {
+	let output_format = config.format;
+
+	let thumbnail = thumbnailer_pool(config)
+		.run(move |_| -> Result<Vec<u8>, ThumbnailerError> {
+			encode_image(&resize_image(&decode()?, spec), output_format, spec.quality)
+		})
+		.await??;
+
+	fs::write(output_path, &thumbnail).await.map_err(Into::into)
+}
+
+/// generate_audio_thumbnail makes the thumbnail of an audio file out of its embedded cover art.
+pub async fn generate_audio_thumbnail<P: AsRef<Path>>(
+	file_path: P,
+	output_path: P,
+	config: &ThumbnailConfig,
+	spec: ThumbnailSpec,
+) -> Result<(), Box<dyn Error>> {
+	let cover = {
+		let _file_permit = resource_limiter().open_file().await;
+		extract_cover_art(&file_path).await?
+	};
+
+	let Some(cover) = cover else {
+		return Err(ThumbnailerError::NoCoverArt.into());
+	};
+
+	// The format of covers is guessed from their contents, as their declared mime type is often wrong
+	encode_thumbnail(cover, None, output_path, config, spec).await
+}
+
+/// generate_document_thumbnail makes the thumbnail of an EPUB out of its cover, or of a PDF out of its first page.
+pub async fn generate_document_thumbnail<P: AsRef<Path>>(
+	file_path: P,
+	output_path: P,
+	config: &ThumbnailConfig,
+	spec: ThumbnailSpec,
+) -> Result<(), Box<dyn Error>> {
+	let extension = file_path
+		.as_ref()
+		.extension()
+		.and_then(|extension| extension.to_str())
+		.map(str::to_lowercase)
+		.and_then(|extension| extension.parse::<DocumentExtension>().ok());
+
+	let bytes = {
+		let _file_permit = resource_limiter().open_file().await;
+		read_file(&file_path).await?
+	};
+
+	match extension {
+		Some(DocumentExtension::Epub) => {
+			let Some(cover) = spawn_blocking(move || extract_epub_cover(bytes)).await?? else {
+				return Err(ThumbnailerError::NoCoverArt.into());
+			};
+
+			encode_thumbnail(cover, None, output_path, config, spec).await
+		}
+		#[cfg(feature = "pdfium")]
+		Some(DocumentExtension::Pdf) => {
+			let output_format = config.format;
+			let size = spec.rendered_size();
+			let quality = spec.quality;
+
+			// Rendering is blocking and uses a lot of memory, so it runs on the thumbnailer workers
+			let thumbnail = thumbnailer_pool(config)
+				.run(move |_| -> Result<Vec<u8>, ThumbnailerError> {
+					encode_image(&render_pdf_first_page(bytes, size)?, output_format, quality)
+				})
+				.await??;
+
+			fs::write(output_path, &thumbnail).await.map_err(Into::into)
+		}
+		_ => Err(ThumbnailerError::UnsupportedDocument.into()),
+	}
+}
 
-		// Encode the image at a specified quality 0-100
+/// generate_font_thumbnail makes the thumbnail of a font out of sample text drawn with it.
+pub async fn generate_font_thumbnail<P: AsRef<Path>>(
+	file_path: P,
+	output_path: P,
+	config: &ThumbnailConfig,
+	spec: ThumbnailSpec,
+) -> Result<(), Box<dyn Error>> {
+	let bytes = {
+		let _file_permit = resource_limiter().open_file().await;
+		read_file(&file_path).await?
+	};
 
-		// Type WebPMemory is !Send, which makes the Future in this function !Send,
-		// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
-		// which implies on a unwanted clone...
-		Ok(encoder.encode(THUMBNAIL_QUALITY).deref().to_owned())
-	})?;
+	let output_format = config.format;
+	let size = spec.rendered_size();
+	let quality = spec.quality;
 
-	fs::write(output_path, &webp).await.map_err(Into::into)
+	let thumbnail = thumbnailer_pool(config)
+		.run(move |_| -> Result<Vec<u8>, ThumbnailerError> {
+			encode_image(&render_font_sample(bytes, size)?, output_format, quality)
+		})
+		.await??;
+
+	fs::write(output_path, &thumbnail).await.map_err(Into::into)
+}
+
+/// encode_thumbnail decodes an image, guessing its format if it isn't given, and writes its thumbnail.
+async fn encode_thumbnail(
+	bytes: Vec<u8>,
+	format: Option<ImageFormat>,
+	output_path: impl AsRef<Path>,
+	config: &ThumbnailConfig,
+	spec: ThumbnailSpec,
+) -> Result<(), Box<dyn Error>> {
+	let max_alloc = worker_memory_bytes(config);
+	let output_format = config.format;
+
+	// Decoding and encoding are blocking and use a lot of memory, so they run on the thumbnailer workers
+	let thumbnail = thumbnailer_pool(config)
+		.run(move |_| -> Result<Vec<u8>, ThumbnailerError> {
+			// Using `image` crate, decode the file read above, with the format given by its extension
+			let mut reader = match format {
+				Some(format) => Reader::with_format(Cursor::new(bytes), format),
+				None => Reader::new(Cursor::new(bytes)).with_guessed_format()?,
+			};
+			// Images which would need more memory than a worker has fail to decode, instead of spiking the memory of the node
+			let mut limits = Limits::default();
+			limits.max_alloc = Some(max_alloc);
+			reader.limits(limits);
+
+			let img = reader.decode()?;
+
+			encode_image(&resize_image(&img, spec), output_format, spec.quality)
+		})
+		.await??;
+
+	fs::write(output_path, &thumbnail).await.map_err(Into::into)
+}
+
+/// resize_image scales an image to the size of a thumbnail spec, as an RGBA image.
+fn resize_image(img: &DynamicImage, spec: ThumbnailSpec) -> DynamicImage {
+	let (w, h) = img.dimensions();
+	let (width, height) = spec.dimensions(w, h);
+	// Optionally, resize the existing photo and convert back into DynamicImage
+	DynamicImage::ImageRgba8(imageops::resize(
+		img,
+		width,
+		height,
+		imageops::FilterType::Triangle,
+	))
+}
+
+/// encode_image encodes an RGBA image in the given format, at a quality from 0 to 100.
+fn encode_image(
+	img: &DynamicImage,
+	format: ThumbnailFormat,
+	quality: f32,
+) -> Result<Vec<u8>, ThumbnailerError> {
+	match format {
+		ThumbnailFormat::Webp => {
+			// Create the WebP encoder for the above image
+			let encoder = Encoder::from_image(img)
+				.map_err(|e| ThumbnailerError::EncoderError(e.to_string()))?;
+
+			// Type WebPMemory is !Send, which makes the Future in this function !Send,
+			// this make us `deref` to have a `&[u8]` and then `to_owned` to make a Vec<u8>
+			// which implies on a unwanted clone...
+			Ok(encoder.encode(quality).deref().to_owned())
+		}
+		ThumbnailFormat::Avif => {
+			let mut avif = Vec::new();
+			AvifEncoder::new_with_speed_quality(
+				&mut avif,
+				AVIF_ENCODER_SPEED,
+				quality.clamp(0.0, 100.0) as u8,
+			)
+			.write_image(img.as_bytes(), img.width(), img.height(), img.color())?;
+
+			Ok(avif)
+		}
+	}
 }
 
 #[cfg(feature = "ffmpeg")]
 pub async fn generate_video_thumbnail<P: AsRef<Path>>(
 	file_path: P,
 	output_path: P,
+	config: &ThumbnailConfig,
+	spec: ThumbnailSpec,
 ) -> Result<(), Box<dyn Error>> {
-	use sd_ffmpeg::to_thumbnail;
+	use sd_ffmpeg::{to_thumbnail, ThumbnailerBuilder};
 
-	to_thumbnail(file_path, output_path, 256, THUMBNAIL_QUALITY).await?;
+	let _file_permit = resource_limiter().open_file().await;
 
-	Ok(())
+	let file_path = file_path.as_ref().to_path_buf();
+	let output_path = output_path.as_ref().to_path_buf();
+	let size = spec.rendered_size();
+	let quality = spec.quality;
+
+	// The memory limit of the workers doesn't apply to ffmpeg, but videos are still decoded on the workers
+	match config.format {
+		ThumbnailFormat::Webp => {
+			thumbnailer_pool(config)
+				.run(move |handle| {
+					handle.block_on(to_thumbnail(file_path, output_path, size, quality))
+				})
+				.await??;
+
+			Ok(())
+		}
+		ThumbnailFormat::Avif => {
+			// ffmpeg only writes WebP frames, so one at full quality is encoded again in the configured format
+			let frame = thumbnailer_pool(config)
+				.run(move |handle| {
+					handle.block_on(
+						ThumbnailerBuilder::new()
+							.with_film_strip(false)
+							.size(size)
+							.quality(100.0)?
+							.build()
+							.process_to_webp_bytes(file_path),
+					)
+				})
+				.await??;
+
+			encode_thumbnail(
+				frame,
+				Some(ImageFormat::WebP),
+				output_path,
+				config,
+				ThumbnailSpec {
+					size: ThumbnailSize::Factor(1.0),
+					quality,
+				},
+			)
+			.await
+		}
+	}
 }
 
 #[cfg(feature = "ffmpeg")]
@@ -138,9 +948,179 @@ pub const fn can_generate_thumbnail_for_video(video_extension: &VideoExtension)
 	!matches!(video_extension, Mpg | Swf | M2v | Hevc)
 }
 
+pub const fn can_generate_thumbnail_for_audio(audio_extension: &AudioExtension) -> bool {
+	use AudioExtension::*;
+	// Formats whose tags can carry pictures
+	matches!(
+		audio_extension,
+		Mp3 | M4a | Flac | Ogg | Oga | Opus | Wav | Aiff | Aif | Wv | Aac
+	)
+}
+
+pub const fn can_generate_thumbnail_for_document(document_extension: &DocumentExtension) -> bool {
+	use DocumentExtension::*;
+	// PDFs are only rendered when pdfium is built in, EPUBs only need their cover to be extracted
+	#[cfg(feature = "pdfium")]
+	{
+		matches!(document_extension, Pdf | Epub)
+	}
+	#[cfg(not(feature = "pdfium"))]
+	{
+		matches!(document_extension, Epub)
+	}
+}
+
+pub const fn can_generate_thumbnail_for_font(font_extension: &FontExtension) -> bool {
+	use FontExtension::*;
+	// WOFF2 fonts are compressed with Brotli after their tables are transformed, which can't be undone yet
+	matches!(font_extension, Ttf | Otf | Woff)
+}
+
 pub const fn can_generate_thumbnail_for_image(image_extension: &ImageExtension) -> bool {
 	use ImageExtension::*;
-	matches!(image_extension, Jpg | Jpeg | Png | Webp | Gif)
+	// Camera raw files get the preview embedded in them, while SVGs are only rasterized when resvg is built in and
+	// HEIF files only decoded when libheif is
+	matches!(
+		image_extension,
+		Jpg | Jpeg | Png | Webp | Gif | Cr2 | Nef | Arw | Dng
+	) || (cfg!(feature = "svg-thumbnails") && matches!(image_extension, Svg))
+		|| (cfg!(feature = "heif") && matches!(image_extension, Heic | Heif))
+}
+
+/// record_thumbnail records that the thumbnail of `cas_id` in `variant` is up to date, so thumbnailer jobs skip it.
+/// Failing to record it only means it's checked again by the next thumbnailer run.
+async fn record_thumbnail(db: &PrismaClient, cas_id: &str, variant: Option<&str>) {
+	let variant = variant.unwrap_or_default().to_string();
+
+	if let Err(e) = db
+		.thumbnail()
+		.upsert(
+			thumbnail::cas_id_variant(cas_id.to_string(), variant.clone()),
+			(cas_id.to_string(), variant, vec![]),
+			vec![thumbnail::generated_at::set(Utc::now().into())],
+		)
+		.exec()
+		.await
+	{
+		warn!("Failed to record thumbnail of {cas_id}: {e:#?}");
+	}
+}
+
+/// skip_generated_thumbnails removes the steps whose files have all their thumbnails recorded as generated after
+/// the settings of their variant last changed, in the default size and every variant, and the preview strips of videos
+/// when they're generated with thumbnails.
+async fn skip_generated_thumbnails(
+	db: &PrismaClient,
+	steps: VecDeque<ThumbnailerJobStep>,
+	config: &ThumbnailConfig,
+	outdated_before: &ThumbnailsOutdatedBefore,
+) -> Result<VecDeque<ThumbnailerJobStep>, ThumbnailerError> {
+	let cas_ids = steps
+		.iter()
+		.filter_map(|step| step.file_path.cas_id.clone())
+		.collect::<HashSet<_>>()
+		.into_iter()
+		.collect::<Vec<_>>();
+
+	let mut generated = HashMap::<String, HashSet<String>>::new();
+	for chunk in cas_ids.chunks(THUMBNAIL_RECORDS_CHUNK_SIZE) {
+		for record in db
+			.thumbnail()
+			.find_many(vec![thumbnail::cas_id::in_vec(chunk.to_vec())])
+			.exec()
+			.await?
+			.into_iter()
+			.filter(|record| {
+				DateTime::<Utc>::from(record.generated_at)
+					>= outdated_before.of(Some(&record.variant))
+			}) {
+			generated
+				.entry(record.cas_id)
+				.or_default()
+				.insert(record.variant);
+		}
+	}
+
+	let variants = thumbnail_specs(config)
+		.map(|(variant, _)| variant.unwrap_or_default().to_string())
+		.collect::<Vec<_>>();
+
+	Ok(steps
+		.into_iter()
+		.filter(|step| {
+			let Some(generated) = step
+				.file_path
+				.cas_id
+				.as_ref()
+				.and_then(|cas_id| generated.get(cas_id))
+			else {
+				return true;
+			};
+
+			if generated.contains(NO_THUMBNAIL_VARIANT) {
+				return false;
+			}
+
+			#[cfg(feature = "ffmpeg")]
+			if matches!(step.kind, ThumbnailerJobStepKind::Video)
+				&& config.video_preview_strips
+				&& !generated.contains(PREVIEW_STRIP_VARIANT)
+			{
+				return true;
+			}
+
+			!variants.iter().all(|variant| generated.contains(variant))
+		})
+		.collect())
+}
+
+/// forget_thumbnails removes the records of the thumbnails of `cas_ids`, so thumbnailer jobs generate them again.
+async fn forget_thumbnails(
+	db: &PrismaClient,
+	cas_ids: Vec<String>,
+) -> Result<(), ThumbnailerError> {
+	for chunk in cas_ids.chunks(THUMBNAIL_RECORDS_CHUNK_SIZE) {
+		db.thumbnail()
+			.delete_many(vec![thumbnail::cas_id::in_vec(chunk.to_vec())])
+			.exec()
+			.await?;
+	}
+
+	Ok(())
+}
+
+/// thumbnail_kind tells how the thumbnail of a file with `extension` is generated, if it can have one.
+fn thumbnail_kind(extension: &str) -> Option<ThumbnailerJobStepKind> {
+	let extension = extension.to_lowercase();
+
+	if let Ok(extension) = extension.parse::<ImageExtension>() {
+		if can_generate_thumbnail_for_image(&extension) {
+			return Some(ThumbnailerJobStepKind::Image);
+		}
+	}
+	#[cfg(feature = "ffmpeg")]
+	if let Ok(extension) = extension.parse::<VideoExtension>() {
+		if can_generate_thumbnail_for_video(&extension) {
+			return Some(ThumbnailerJobStepKind::Video);
+		}
+	}
+	if let Ok(extension) = extension.parse::<AudioExtension>() {
+		if can_generate_thumbnail_for_audio(&extension) {
+			return Some(ThumbnailerJobStepKind::Audio);
+		}
+	}
+	if let Ok(extension) = extension.parse::<DocumentExtension>() {
+		if can_generate_thumbnail_for_document(&extension) {
+			return Some(ThumbnailerJobStepKind::Document);
+		}
+	}
+	if let Ok(extension) = extension.parse::<FontExtension>() {
+		if can_generate_thumbnail_for_font(&extension) {
+			return Some(ThumbnailerJobStepKind::Font);
+		}
+	}
+
+	None
 }
 
 fn finalize_thumbnailer(data: &ThumbnailerJobState, ctx: WorkerContext) -> JobResult {
@@ -152,38 +1132,89 @@ fn finalize_thumbnailer(data: &ThumbnailerJobState, ctx: WorkerContext) -> JobRe
 			.display()
 	);
 
+	// Only the listings of the directory the thumbnails were generated for and of its subdirectories changed
 	if data.report.thumbnails_created > 0 {
-		invalidate_query!(ctx.library, "locations.getExplorerData");
+		invalidate_explorer_path(
+			&ctx.library,
+			data.report.location_id,
+			(data.report.materialized_path != "/").then(|| data.report.materialized_path.clone()),
+		);
 	}
 
 	Ok(Some(serde_json::to_value(&data.report)?))
 }
 
+/// process_step processes the first of the remaining `steps`, along with the next ones up to the concurrency of the config.
+/// The first step is removed by the job once it's done, the others are removed from `steps` here once they're done,
+/// so a job paused in the middle of a step processes them again when it's resumed.
+/// While the device runs on battery, a single file is processed at a time with a pause after it.
 async fn process_step(
 	is_background: bool,
-	step_number: usize,
-	step: &ThumbnailerJobStep,
+	steps: &mut VecDeque<ThumbnailerJobStep>,
 	data: &mut ThumbnailerJobState,
 	ctx: WorkerContext,
 ) -> Result<(), JobError> {
+	let config = ctx.library.config().get().await.thumbnail;
+	let throttle = governor()
+		.throttle()
+		.filter(|throttle| config.battery_throttle || throttle.reason != ThrottleReason::Battery);
+	let concurrency = config.concurrency.map_or_else(
+		|| thumbnailer_pool(&config).workers(),
+		|concurrency| concurrency as usize,
+	);
+	let concurrency = throttle.map_or(concurrency, |throttle| throttle.parallelism(concurrency));
+
+	let batch = steps
+		.range(..concurrency.clamp(1, steps.len()))
+		.collect::<Vec<_>>();
+	let batch_len = batch.len();
+
 	ctx.progress(vec![JobReportUpdate::Message(format!(
-		"Processing {}",
-		step.file_path.materialized_path
+		"Processing {}{} ({} thumbnails queued)",
+		batch[0].file_path.materialized_path,
+		if batch.len() > 1 {
+			format!(" and {} other files", batch.len() - 1)
+		} else {
+			String::new()
+		},
+		queued_thumbnails()
 	))]);
 
-	let step_result = inner_process_step(is_background, step, data, &ctx).await;
+	let results = {
+		let data = &*data;
+		join_all(
+			batch
+				.iter()
+				.map(|step| inner_process_step(is_background, step, data, &ctx)),
+		)
+		.await
+	};
+
+	steps.drain(1..batch_len);
 
-	ctx.progress(vec![JobReportUpdate::CompletedTaskCount(step_number + 1)]);
+	data.processed_files += batch_len;
+	ctx.progress(vec![
+		JobReportUpdate::CompletedTaskCount(data.processed_files),
+		JobReportUpdate::ItemsProcessed(batch_len),
+	]);
 
-	step_result
+	if throttle.is_some() {
+		sleep(THROTTLE_PAUSE).await;
+	}
+
+	for created in results {
+		data.report.thumbnails_created += created?;
+	}
+
+	Ok(())
 }
 
 async fn inner_process_step(
 	is_background: bool,
 	step: &ThumbnailerJobStep,
-	data: &mut ThumbnailerJobState,
+	data: &ThumbnailerJobState,
 	ctx: &WorkerContext,
-) -> Result<(), JobError> {
+) -> Result<usize, JobError> {
 	// assemble the file path
 	let path = data.location_path.join(&step.file_path.materialized_path);
 	trace!("image_file {:?}", step);
@@ -195,45 +1226,163 @@ async fn inner_process_step(
 			step.file_path.materialized_path
 		);
 
-		return Ok(());
+		return Ok(0);
 	};
 
-	// Define and write the WebP-encoded file to a given path
-	let output_path = data.thumbnail_dir.join(format!("{cas_id}.webp"));
+	Ok(generate_thumbnails(
+		&ctx.library,
+		step.kind,
+		&path,
+		cas_id,
+		&data.thumbnail_dir,
+		data.thumbnails_outdated_before.as_ref(),
+		!is_background,
+	)
+	.await?)
+}
+
+/// generate_thumbnails writes the thumbnails of a file which are missing or outdated, in the default size and every variant,
+/// along with the preview strip of videos when strips are generated with thumbnails. Returns how many thumbnails were written.
+/// With `notify`, the explorer is told about each new thumbnail to show it right away.
+async fn generate_thumbnails(
+	library: &Library,
+	kind: ThumbnailerJobStepKind,
+	path: &Path,
+	cas_id: &str,
+	thumbnail_dir: &Path,
+	outdated_before: Option<&ThumbnailsOutdatedBefore>,
+	notify: bool,
+) -> Result<usize, ThumbnailerError> {
+	let mut created = 0;
 
-	match fs::metadata(&output_path).await {
-		Ok(_) => {
+	let config = library.config().get().await.thumbnail;
+
+	for (variant, spec) in thumbnail_specs(&config) {
+		// Define and write the WebP-encoded file to a given path
+		let output_path = thumbnail_path(thumbnail_dir, variant, cas_id, config.format);
+
+		let variant_outdated_before =
+			outdated_before.map(|outdated_before| outdated_before.of(variant));
+		if !is_thumbnail_outdated(&output_path, variant_outdated_before).await? {
 			info!("Thumb exists, skipping... {}", output_path.display());
+			// Thumbnails written before they were recorded are skipped by the next thumbnailer runs too
+			record_thumbnail(&library.db, cas_id, variant).await;
+			continue;
 		}
-		Err(e) if e.kind() == io::ErrorKind::NotFound => {
-			info!("Writing {:?} to {:?}", path, output_path);
 
-			match step.kind {
+		info!("Writing {:?} to {:?}", path, output_path);
+
+		// The default thumbnails of GIFs and short videos can be animations, as long as they stay light
+		let animated = if variant.is_none() && config.animated_thumbnails {
+			match generate_animated_thumbnail(kind, path, &config, spec).await {
+				Ok(Some(animation)) if animation.len() <= ANIMATED_THUMBNAIL_MAX_BYTES => {
+					fs::write(&output_path, animation).await?;
+					true
+				}
+				Ok(Some(animation)) => {
+					debug!(
+						"Animated thumbnail of {:?} is too heavy ({} bytes), writing a still one",
+						path,
+						animation.len()
+					);
+					false
+				}
+				Ok(None) => false,
+				Err(e) => {
+					debug!("No animated thumbnail generated for {:?}: {:#?}", path, e);
+					false
+				}
+			}
+		} else {
+			false
+		};
+
+		if !animated {
+			match kind {
 				ThumbnailerJobStepKind::Image => {
-					if let Err(e) = generate_image_thumbnail(&path, &output_path).await {
+					if let Err(e) =
+						generate_image_thumbnail(path, &output_path, &config, spec).await
+					{
 						error!("Error generating thumb for image {:#?}", e);
+						continue;
+					}
+				}
+				ThumbnailerJobStepKind::Audio => {
+					if let Err(e) =
+						generate_audio_thumbnail(path, &output_path, &config, spec).await
+					{
+						// Most audio files simply have no cover, so there's no thumbnail of any size to announce
+						debug!("No thumb generated for audio: {:?} {:#?}", path, e);
+						record_thumbnail(&library.db, cas_id, Some(NO_THUMBNAIL_VARIANT)).await;
+						return Ok(created);
+					}
+				}
+				ThumbnailerJobStepKind::Document => {
+					if let Err(e) =
+						generate_document_thumbnail(path, &output_path, &config, spec).await
+					{
+						// Books without a cover and unreadable PDFs get no thumbnail of any size either
+						debug!("No thumb generated for document: {:?} {:#?}", path, e);
+						record_thumbnail(&library.db, cas_id, Some(NO_THUMBNAIL_VARIANT)).await;
+						return Ok(created);
+					}
+				}
+				ThumbnailerJobStepKind::Font => {
+					if let Err(e) = generate_font_thumbnail(path, &output_path, &config, spec).await
+					{
+						error!("Error generating thumb for font: {:?} {:#?}", path, e);
+						continue;
 					}
 				}
 				#[cfg(feature = "ffmpeg")]
 				ThumbnailerJobStepKind::Video => {
-					if let Err(e) = generate_video_thumbnail(&path, &output_path).await {
-						error!("Error generating thumb for video: {:?} {:#?}", &path, e);
+					if let Err(e) =
+						generate_video_thumbnail(path, &output_path, &config, spec).await
+					{
+						error!("Error generating thumb for video: {:?} {:#?}", path, e);
+						continue;
 					}
 				}
 			}
+		}
 
-			if !is_background {
-				ctx.library.emit(CoreEvent::NewThumbnail {
-					cas_id: cas_id.clone(),
-				});
-				// With this invalidate query, we update the user interface to show each new thumbnail
-				invalidate_query!(ctx.library, "locations.getExplorerData");
-			};
+		// Thumbnails written before the format was changed aren't served anymore
+		if let Err(e) = remove_stale_thumbnails(thumbnail_dir, variant, cas_id, config.format).await
+		{
+			warn!("Failed to remove stale thumbnails of {cas_id}: {e:#?}");
+		}
+
+		if notify {
+			library.emit(CoreEvent::NewThumbnail {
+				cas_id: cas_id.to_string(),
+				variant: variant.map(ToString::to_string),
+			});
+			// With this invalidate query, we update the user interface to show each new thumbnail
+			invalidate_query_debounced!(library, "locations.getExplorerData");
+		};
+
+		record_thumbnail(&library.db, cas_id, variant).await;
+		created += 1;
+	}
 
-			data.report.thumbnails_created += 1;
+	#[cfg(feature = "ffmpeg")]
+	if matches!(kind, ThumbnailerJobStepKind::Video) && config.video_preview_strips {
+		let output_path = preview_strip_path(thumbnail_dir, cas_id);
+
+		let strip_outdated_before =
+			outdated_before.map(|outdated_before| outdated_before.of(Some(PREVIEW_STRIP_VARIANT)));
+		if is_thumbnail_outdated(&output_path, strip_outdated_before).await? {
+			match generate_preview_strip(path, &output_path, &config).await {
+				Ok(()) => record_thumbnail(&library.db, cas_id, Some(PREVIEW_STRIP_VARIANT)).await,
+				Err(e) => error!(
+					"Error generating preview strip for video: {:?} {:#?}",
+					path, e
+				),
+			}
+		} else {
+			record_thumbnail(&library.db, cas_id, Some(PREVIEW_STRIP_VARIANT)).await;
 		}
-		Err(e) => return Err(ThumbnailerError::from(e).into()),
 	}
 
-	Ok(())
+	Ok(created)
 }
@@ -0,0 +1,201 @@
+use crate::{node::ThumbnailConfig, util::resources::resource_limiter};
+
+use std::{
+	collections::VecDeque,
+	future::Future,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Condvar, Mutex,
+	},
+	thread,
+};
+
+use once_cell::sync::OnceCell;
+use tokio::{runtime::Handle, sync::oneshot, task_local};
+use tracing::error;
+
+use super::ThumbnailerError;
+
+/// Used when the memory of a worker isn't set, which fits a 100 megapixel RGBA image
+const DEFAULT_WORKER_MEMORY_BYTES: u64 = 512 * 1024 * 1024;
+
+type Task = Box<dyn FnOnce(&Handle) + Send>;
+
+static POOL: OnceCell<ThumbnailerPool> = OnceCell::new();
+
+task_local! {
+	static PRIORITY: ThumbnailPriority;
+}
+
+/// ThumbnailPriority is how soon the pool runs the thumbnails of a task, set with [`with_priority`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ThumbnailPriority {
+	/// Thumbnails generated by jobs, which is the default
+	Background,
+	/// Thumbnails of files the user is looking at, which are run before any background one
+	Visible,
+}
+
+/// TaskQueue holds the tasks waiting for a worker, the visible ones being taken first.
+#[derive(Default)]
+struct TaskQueue {
+	visible: VecDeque<Task>,
+	background: VecDeque<Task>,
+}
+
+impl TaskQueue {
+	fn push(&mut self, task: Task, priority: ThumbnailPriority) {
+		match priority {
+			ThumbnailPriority::Visible => self.visible.push_back(task),
+			ThumbnailPriority::Background => self.background.push_back(task),
+		}
+	}
+
+	fn pop(&mut self) -> Option<(Task, ThumbnailPriority)> {
+		self.visible
+			.pop_front()
+			.map(|task| (task, ThumbnailPriority::Visible))
+			.or_else(|| {
+				self.background
+					.pop_front()
+					.map(|task| (task, ThumbnailPriority::Background))
+			})
+	}
+}
+
+/// ThumbnailerPool decodes images and videos on threads dedicated to thumbnails,
+/// so the number of decodes in flight, and the memory they use, doesn't grow with the number of thumbnailer jobs.
+pub(super) struct ThumbnailerPool {
+	tasks: Arc<(Mutex<TaskQueue>, Condvar)>,
+	queued: Arc<AtomicUsize>,
+	workers: usize,
+}
+
+impl ThumbnailerPool {
+	fn new(workers: usize, handle: Handle) -> Self {
+		let tasks = Arc::new((Mutex::new(TaskQueue::default()), Condvar::new()));
+		let queued = Arc::new(AtomicUsize::new(0));
+		let mut spawned_workers = 0;
+
+		for i in 0..workers {
+			let tasks = Arc::clone(&tasks);
+			let queued = Arc::clone(&queued);
+			let handle = handle.clone();
+
+			let spawned = thread::Builder::new()
+				.name(format!("sd-thumbnailer-{i}"))
+				.spawn(move || loop {
+					// The lock is released as soon as a task is taken, so other workers can wait for the next one
+					let (task, priority) = {
+						let (queue, available) = &*tasks;
+						let Ok(mut queue) = queue.lock() else {
+							return;
+						};
+						loop {
+							if let Some(task) = queue.pop() {
+								break task;
+							}
+							queue = match available.wait(queue) {
+								Ok(queue) => queue,
+								Err(_) => return,
+							};
+						}
+					};
+
+					queued.fetch_sub(1, Ordering::Relaxed);
+					// Background thumbnails share the CPU heavy work limit of the node with other jobs,
+					// the visible ones don't wait for other jobs to release it
+					let _thread_permit = (priority == ThumbnailPriority::Background)
+						.then(|| handle.block_on(resource_limiter().worker_thread()));
+					task(&handle);
+				});
+
+			match spawned {
+				Ok(_) => spawned_workers += 1,
+				Err(e) => error!("Failed to spawn thumbnailer worker: {e:#?}"),
+			}
+		}
+
+		Self {
+			tasks,
+			queued,
+			workers: spawned_workers,
+		}
+	}
+
+	/// run sends a task to the workers, and waits until one of them runs it.
+	/// The task receives a handle of the main runtime, to run async code like the video decoder.
+	pub(super) async fn run<T: Send + 'static>(
+		&self,
+		task: impl FnOnce(&Handle) -> T + Send + 'static,
+	) -> Result<T, ThumbnailerError> {
+		if self.workers == 0 {
+			return Err(ThumbnailerError::WorkerFailed);
+		}
+
+		let (tx, rx) = oneshot::channel();
+		let priority = PRIORITY
+			.try_with(|priority| *priority)
+			.unwrap_or(ThumbnailPriority::Background);
+
+		{
+			let (queue, available) = &*self.tasks;
+			queue
+				.lock()
+				.map_err(|_| ThumbnailerError::WorkerFailed)?
+				.push(
+					Box::new(move |handle: &Handle| {
+						tx.send(task(handle)).ok();
+					}),
+					priority,
+				);
+			self.queued.fetch_add(1, Ordering::Relaxed);
+			available.notify_one();
+		}
+
+		// The sender is dropped without a result if the task panics
+		rx.await.map_err(|_| ThumbnailerError::WorkerFailed)
+	}
+
+	/// queued is how many thumbnails are waiting for a worker, across every thumbnailer job.
+	pub(super) fn queued(&self) -> usize {
+		self.queued.load(Ordering::Relaxed)
+	}
+
+	/// workers is how many workers the pool runs thumbnails on.
+	pub(super) fn workers(&self) -> usize {
+		self.workers
+	}
+}
+
+/// thumbnailer_pool returns the pool of the node, which is started with the number of workers of the config the first time it's used.
+pub(super) fn thumbnailer_pool(config: &ThumbnailConfig) -> &'static ThumbnailerPool {
+	POOL.get_or_init(|| {
+		let workers = config
+			.workers
+			.map_or_else(
+				|| resource_limiter().worker_threads(),
+				|workers| workers as usize,
+			)
+			.max(1);
+
+		ThumbnailerPool::new(workers, Handle::current())
+	})
+}
+
+/// queued_thumbnails is how many thumbnails are waiting for a worker, or 0 if the pool hasn't started yet.
+pub(super) fn queued_thumbnails() -> usize {
+	POOL.get().map_or(0, ThumbnailerPool::queued)
+}
+
+/// with_priority runs `future` with every thumbnail it sends to the pool queued with `priority`.
+pub(super) async fn with_priority<F: Future>(priority: ThumbnailPriority, future: F) -> F::Output {
+	PRIORITY.scope(priority, future).await
+}
+
+/// worker_memory_bytes is the most memory a worker can allocate to decode an image.
+pub(super) fn worker_memory_bytes(config: &ThumbnailConfig) -> u64 {
+	config
+		.worker_memory_bytes
+		.unwrap_or(DEFAULT_WORKER_MEMORY_BYTES)
+}
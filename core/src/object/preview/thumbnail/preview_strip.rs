@@ -0,0 +1,160 @@
+use crate::{library::Library, location::LocationId, node::ThumbnailFormat};
+
+use std::path::{Path, PathBuf};
+
+use rspc::Type;
+use serde::Serialize;
+
+use super::{thumbnail_path, ThumbnailerError};
+
+#[cfg(feature = "ffmpeg")]
+use crate::{node::ThumbnailConfig, prisma::file_path, util::resources::resource_limiter};
+
+#[cfg(feature = "ffmpeg")]
+use std::io::Cursor;
+
+#[cfg(feature = "ffmpeg")]
+use image::io::Reader;
+#[cfg(feature = "ffmpeg")]
+use sd_file_ext::extensions::VideoExtension;
+#[cfg(feature = "ffmpeg")]
+use tokio::fs;
+
+#[cfg(feature = "ffmpeg")]
+use super::{
+	can_generate_thumbnail_for_video, is_thumbnail_outdated, thumbnailer_pool,
+	THUMBNAIL_CACHE_DIR_NAME,
+};
+
+/// The directory in the thumbnails directory holding the preview strips of videos, served like a thumbnail variant
+pub const PREVIEW_STRIP_VARIANT: &str = "preview-strip";
+
+/// How many frames of a video are laid side by side in its preview strip
+pub const PREVIEW_STRIP_FRAMES: u32 = 10;
+
+/// The length of the longest edge of the frames of preview strips
+#[cfg(feature = "ffmpeg")]
+const PREVIEW_STRIP_FRAME_SIZE: u32 = 160;
+
+/// VideoPreviewStrip describes the preview strip of a video, served by `thumbnail/preview-strip/<cas_id>`.
+/// Its frames are taken at regular intervals of the video and laid side by side, the first one on the left.
+#[derive(Serialize, Type)]
+pub struct VideoPreviewStrip {
+	pub cas_id: String,
+	pub frames: u32,
+	pub frame_width: u32,
+	pub frame_height: u32,
+}
+
+/// preview_strip_path returns where the preview strip of the video with `cas_id` is written. Strips are always WebP.
+pub fn preview_strip_path(thumbnail_dir: impl AsRef<Path>, cas_id: &str) -> PathBuf {
+	thumbnail_path(
+		thumbnail_dir,
+		Some(PREVIEW_STRIP_VARIANT),
+		cas_id,
+		ThumbnailFormat::Webp,
+	)
+}
+
+#[cfg(feature = "ffmpeg")]
+pub(super) async fn generate_preview_strip(
+	file_path: impl AsRef<Path>,
+	output_path: impl AsRef<Path>,
+	config: &ThumbnailConfig,
+) -> Result<(), ThumbnailerError> {
+	use sd_ffmpeg::ThumbnailerBuilder;
+
+	let _file_permit = resource_limiter().open_file().await;
+
+	let file_path = file_path.as_ref().to_path_buf();
+	let quality = config.quality;
+
+	let strip = thumbnailer_pool(config)
+		.run(move |handle| {
+			handle.block_on(
+				ThumbnailerBuilder::new()
+					.size(PREVIEW_STRIP_FRAME_SIZE)
+					.quality(quality)?
+					.build()
+					.process_sprite_sheet_to_webp_bytes(file_path, PREVIEW_STRIP_FRAMES),
+			)
+		})
+		.await??;
+
+	let output_path = output_path.as_ref();
+	if let Some(parent) = output_path.parent() {
+		fs::create_dir_all(parent).await?;
+	}
+	fs::write(output_path, strip).await?;
+
+	Ok(())
+}
+
+/// get_preview_strip returns the preview strip of a video, generating it if it wasn't yet.
+/// Files which aren't videos, or aren't identified yet, have no strip.
+#[cfg(feature = "ffmpeg")]
+pub async fn get_preview_strip(
+	library: &Library,
+	location_id: LocationId,
+	file_path_id: i32,
+) -> Result<Option<VideoPreviewStrip>, ThumbnailerError> {
+	let Some(file_path) = library
+		.db
+		.file_path()
+		.find_unique(file_path::location_id_id(location_id, file_path_id))
+		.include(file_path::include!({ location }))
+		.exec()
+		.await?
+	else {
+		return Ok(None);
+	};
+
+	let is_video = file_path
+		.extension
+		.to_lowercase()
+		.parse::<VideoExtension>()
+		.map_or(false, |extension| {
+			can_generate_thumbnail_for_video(&extension)
+		});
+	let Some(cas_id) = file_path.cas_id.filter(|_| is_video) else {
+		return Ok(None);
+	};
+
+	let output_path = preview_strip_path(
+		library
+			.config()
+			.data_directory()
+			.join(THUMBNAIL_CACHE_DIR_NAME),
+		&cas_id,
+	);
+
+	if is_thumbnail_outdated(&output_path, None).await? {
+		generate_preview_strip(
+			Path::new(&file_path.location.path).join(&file_path.materialized_path),
+			&output_path,
+			&library.config().get().await.thumbnail,
+		)
+		.await?;
+	}
+
+	let (width, height) = Reader::new(Cursor::new(fs::read(&output_path).await?))
+		.with_guessed_format()?
+		.into_dimensions()?;
+
+	Ok(Some(VideoPreviewStrip {
+		cas_id,
+		frames: PREVIEW_STRIP_FRAMES,
+		frame_width: width / PREVIEW_STRIP_FRAMES,
+		frame_height: height,
+	}))
+}
+
+/// get_preview_strip returns no strip without ffmpeg, as videos can't be decoded.
+#[cfg(not(feature = "ffmpeg"))]
+pub async fn get_preview_strip(
+	_library: &Library,
+	_location_id: LocationId,
+	_file_path_id: i32,
+) -> Result<Option<VideoPreviewStrip>, ThumbnailerError> {
+	Ok(None)
+}
@@ -0,0 +1,319 @@
+use std::{collections::HashSet, io::Cursor};
+
+use image::{
+	io::{Limits, Reader},
+	DynamicImage, ImageFormat,
+};
+use sd_file_ext::extensions::ImageExtension;
+
+use super::ThumbnailerError;
+
+const TAG_COMPRESSION: u16 = 0x0103;
+const TAG_STRIP_OFFSETS: u16 = 0x0111;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+const TAG_SUB_IFDS: u16 = 0x014A;
+const TAG_JPEG_INTERCHANGE_FORMAT: u16 = 0x0201;
+const TAG_JPEG_INTERCHANGE_FORMAT_LENGTH: u16 = 0x0202;
+
+/// Old-style and new-style JPEG compressions of TIFF strips
+const JPEG_COMPRESSIONS: [u16; 2] = [6, 7];
+
+/// Raw files hold a handful of images, more IFDs than this can only come from a corrupted file
+const MAX_IFDS: usize = 32;
+
+pub(super) const fn is_raw_image(image_extension: &ImageExtension) -> bool {
+	use ImageExtension::*;
+	matches!(image_extension, Cr2 | Nef | Arw | Dng)
+}
+
+/// decode_raw decodes the picture of a camera raw file from the biggest JPEG preview embedded by the camera, which is
+/// much faster than developing the raw sensor data.
+pub(super) fn decode_raw(bytes: &[u8], max_alloc: u64) -> Result<DynamicImage, ThumbnailerError> {
+	let previews = EmbeddedPreviews::find(bytes);
+
+	for preview in &previews.jpegs {
+		let mut reader = Reader::with_format(Cursor::new(*preview), ImageFormat::Jpeg);
+		let mut limits = Limits::default();
+		limits.max_alloc = Some(max_alloc);
+		reader.limits(limits);
+
+		// Raw sensor data can be stored as lossless JPEG, which doesn't decode and is skipped
+		if let Ok(img) = reader.decode() {
+			return Ok(apply_orientation(img, previews.orientation));
+		}
+	}
+
+	Err(ThumbnailerError::NoEmbeddedPreview)
+}
+
+/// apply_orientation turns an image upright, from the value of its EXIF orientation tag.
+fn apply_orientation(img: DynamicImage, orientation: Option<u16>) -> DynamicImage {
+	match orientation {
+		Some(2) => img.fliph(),
+		Some(3) => img.rotate180(),
+		Some(4) => img.flipv(),
+		Some(5) => img.rotate90().fliph(),
+		Some(6) => img.rotate90(),
+		Some(7) => img.rotate270().fliph(),
+		Some(8) => img.rotate270(),
+		_ => img,
+	}
+}
+
+/// EmbeddedPreviews are the JPEG images a camera embedded in a raw file, biggest first, with the orientation of the photo.
+#[derive(Debug, Default)]
+struct EmbeddedPreviews<'a> {
+	jpegs: Vec<&'a [u8]>,
+	orientation: Option<u16>,
+}
+
+impl<'a> EmbeddedPreviews<'a> {
+	/// find walks the IFDs of a TIFF based raw file, CR2, NEF, ARW and DNG all being one, looking for JPEG images.
+	fn find(bytes: &'a [u8]) -> Self {
+		let mut previews = Self::default();
+		let Some(tiff) = Tiff::new(bytes) else {
+			return previews;
+		};
+
+		let mut visited = HashSet::new();
+		let mut pending = tiff.u32_at(4).into_iter().collect::<Vec<_>>();
+		while let Some(ifd_offset) = pending.pop() {
+			if ifd_offset == 0 || !visited.insert(ifd_offset) || visited.len() > MAX_IFDS {
+				continue;
+			}
+
+			let Some(entries) = tiff.entries(ifd_offset as usize) else {
+				continue;
+			};
+			let value = |tag| entries.iter().find(|entry| entry.tag == tag);
+
+			// The orientation of the photo is only held by the first IFD
+			if visited.len() == 1 {
+				previews.orientation =
+					value(TAG_ORIENTATION).and_then(|entry| tiff.short_value(entry));
+			}
+
+			if let (Some(offset), Some(length)) = (
+				value(TAG_JPEG_INTERCHANGE_FORMAT).and_then(|entry| tiff.long_value(entry)),
+				value(TAG_JPEG_INTERCHANGE_FORMAT_LENGTH).and_then(|entry| tiff.long_value(entry)),
+			) {
+				previews.push(bytes, offset, length);
+			}
+
+			let is_jpeg = value(TAG_COMPRESSION)
+				.and_then(|entry| tiff.short_value(entry))
+				.map_or(false, |compression| {
+					JPEG_COMPRESSIONS.contains(&compression)
+				});
+			if is_jpeg {
+				// Only images held by a single strip are whole JPEG files
+				if let (Some(offset), Some(length)) = (
+					value(TAG_STRIP_OFFSETS)
+						.filter(|entry| entry.count == 1)
+						.and_then(|entry| tiff.long_value(entry)),
+					value(TAG_STRIP_BYTE_COUNTS)
+						.filter(|entry| entry.count == 1)
+						.and_then(|entry| tiff.long_value(entry)),
+				) {
+					previews.push(bytes, offset, length);
+				}
+			}
+
+			if let Some(sub_ifds) = value(TAG_SUB_IFDS) {
+				pending.extend(tiff.long_values(sub_ifds));
+			}
+			if let Some(next_ifd) = tiff.u32_at(ifd_offset as usize + 2 + entries.len() * 12) {
+				pending.push(next_ifd);
+			}
+		}
+
+		previews
+			.jpegs
+			.sort_by_key(|jpeg| std::cmp::Reverse(jpeg.len()));
+		previews.jpegs.dedup();
+
+		previews
+	}
+
+	fn push(&mut self, bytes: &'a [u8], offset: u32, length: u32) {
+		let start = offset as usize;
+		if let Some(jpeg) = bytes
+			.get(start..start.saturating_add(length as usize))
+			.filter(|jpeg| jpeg.starts_with(&[0xFF, 0xD8]))
+		{
+			self.jpegs.push(jpeg);
+		}
+	}
+}
+
+#[derive(Debug)]
+struct IfdEntry {
+	tag: u16,
+	field_type: u16,
+	count: u32,
+	/// Where the value of the entry is, which holds the value itself when it fits in 4 bytes
+	value_offset: usize,
+}
+
+struct Tiff<'a> {
+	bytes: &'a [u8],
+	little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+	const SHORT: u16 = 3;
+	const LONG: u16 = 4;
+
+	fn new(bytes: &'a [u8]) -> Option<Self> {
+		let little_endian = match bytes.get(0..4)? {
+			[0x49, 0x49, 0x2A, 0x00] => true,
+			[0x4D, 0x4D, 0x00, 0x2A] => false,
+			_ => return None,
+		};
+
+		Some(Self {
+			bytes,
+			little_endian,
+		})
+	}
+
+	fn u16_at(&self, offset: usize) -> Option<u16> {
+		let bytes = self.bytes.get(offset..offset + 2)?.try_into().ok()?;
+		Some(if self.little_endian {
+			u16::from_le_bytes(bytes)
+		} else {
+			u16::from_be_bytes(bytes)
+		})
+	}
+
+	fn u32_at(&self, offset: usize) -> Option<u32> {
+		let bytes = self.bytes.get(offset..offset + 4)?.try_into().ok()?;
+		Some(if self.little_endian {
+			u32::from_le_bytes(bytes)
+		} else {
+			u32::from_be_bytes(bytes)
+		})
+	}
+
+	fn entries(&self, ifd_offset: usize) -> Option<Vec<IfdEntry>> {
+		let count = self.u16_at(ifd_offset)? as usize;
+
+		(0..count)
+			.map(|i| {
+				let entry_offset = ifd_offset + 2 + i * 12;
+				Some(IfdEntry {
+					tag: self.u16_at(entry_offset)?,
+					field_type: self.u16_at(entry_offset + 2)?,
+					count: self.u32_at(entry_offset + 4)?,
+					value_offset: entry_offset + 8,
+				})
+			})
+			.collect()
+	}
+
+	fn short_value(&self, entry: &IfdEntry) -> Option<u16> {
+		if entry.field_type != Self::SHORT {
+			return None;
+		}
+
+		self.u16_at(entry.value_offset)
+	}
+
+	/// long_value reads the first value of an entry, which tags holding offsets can store as a short or a long
+	fn long_value(&self, entry: &IfdEntry) -> Option<u32> {
+		match entry.field_type {
+			Self::SHORT => self.u16_at(entry.value_offset).map(u32::from),
+			Self::LONG => self.u32_at(entry.value_offset),
+			_ => None,
+		}
+	}
+
+	fn long_values(&self, entry: &IfdEntry) -> Vec<u32> {
+		if entry.field_type != Self::LONG {
+			return vec![];
+		}
+
+		// Values which don't fit in the entry are stored at the offset it holds
+		let values_offset = if entry.count > 1 {
+			match self.u32_at(entry.value_offset) {
+				Some(offset) => offset as usize,
+				None => return vec![],
+			}
+		} else {
+			entry.value_offset
+		};
+
+		(0..entry.count.min(MAX_IFDS as u32) as usize)
+			.filter_map(|i| self.u32_at(values_offset + i * 4))
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn entry(tag: u16, field_type: u16, value: u32) -> Vec<u8> {
+		let mut entry = vec![];
+		entry.extend(tag.to_le_bytes());
+		entry.extend(field_type.to_le_bytes());
+		entry.extend(1u32.to_le_bytes());
+		entry.extend(value.to_le_bytes());
+		entry
+	}
+
+	#[test]
+	fn test_find_embedded_previews() {
+		let small_jpeg = [0xFF, 0xD8, 0xFF, 0xD9];
+		let big_jpeg = [0xFF, 0xD8, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xD9];
+
+		// A header, then an IFD with the orientation and a small preview, linked to an IFD with a big JPEG strip
+		let first_ifd = 8;
+		let second_ifd = first_ifd + 2 + 3 * 12 + 4;
+		let small_offset = second_ifd + 2 + 3 * 12 + 4;
+		let big_offset = small_offset + small_jpeg.len() as u32;
+
+		let mut bytes = vec![0x49, 0x49, 0x2A, 0x00];
+		bytes.extend(first_ifd.to_le_bytes());
+
+		bytes.extend(3u16.to_le_bytes());
+		bytes.extend(entry(TAG_ORIENTATION, Tiff::SHORT, 6));
+		bytes.extend(entry(TAG_JPEG_INTERCHANGE_FORMAT, Tiff::LONG, small_offset));
+		bytes.extend(entry(
+			TAG_JPEG_INTERCHANGE_FORMAT_LENGTH,
+			Tiff::LONG,
+			small_jpeg.len() as u32,
+		));
+		bytes.extend(second_ifd.to_le_bytes());
+
+		bytes.extend(3u16.to_le_bytes());
+		bytes.extend(entry(TAG_COMPRESSION, Tiff::SHORT, 6));
+		bytes.extend(entry(TAG_STRIP_OFFSETS, Tiff::LONG, big_offset));
+		bytes.extend(entry(
+			TAG_STRIP_BYTE_COUNTS,
+			Tiff::LONG,
+			big_jpeg.len() as u32,
+		));
+		bytes.extend(0u32.to_le_bytes());
+
+		bytes.extend(small_jpeg);
+		bytes.extend(big_jpeg);
+
+		let previews = EmbeddedPreviews::find(&bytes);
+		assert_eq!(previews.orientation, Some(6));
+		assert_eq!(previews.jpegs, vec![&big_jpeg[..], &small_jpeg[..]]);
+	}
+
+	#[test]
+	fn test_find_embedded_previews_in_invalid_files() {
+		assert!(EmbeddedPreviews::find(&[]).jpegs.is_empty());
+		assert!(EmbeddedPreviews::find(b"not a raw file").jpegs.is_empty());
+
+		// An IFD linking to itself
+		let mut bytes = vec![0x49, 0x49, 0x2A, 0x00, 8, 0, 0, 0];
+		bytes.extend(0u16.to_le_bytes());
+		bytes.extend(8u32.to_le_bytes());
+		assert!(EmbeddedPreviews::find(&bytes).jpegs.is_empty());
+	}
+}
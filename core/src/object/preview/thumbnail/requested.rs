@@ -0,0 +1,88 @@
+use crate::{library::Library, prisma::file_path};
+
+use std::{collections::HashSet, path::Path};
+
+use futures::future::join_all;
+use tracing::error;
+
+use super::{
+	generate_thumbnails,
+	pool::{with_priority, ThumbnailPriority},
+	prepare_thumbnail_dir, thumbnail_kind, ThumbnailerError, THUMBNAIL_CACHE_DIR_NAME,
+};
+
+/// The most thumbnails which can be requested at once, a few screens of the explorer grid
+const MAX_REQUESTED_THUMBNAILS: usize = 200;
+
+/// request_thumbnails generates the thumbnails of the files with `cas_ids`, which the user is looking at,
+/// ahead of the ones queued by thumbnailer jobs. It returns once they're queued, and the explorer is told
+/// about each thumbnail as it's written. Thumbnails which are up to date are left as they are.
+pub async fn request_thumbnails(
+	library: &Library,
+	mut cas_ids: Vec<String>,
+) -> Result<(), ThumbnailerError> {
+	cas_ids.truncate(MAX_REQUESTED_THUMBNAILS);
+
+	let file_paths = library
+		.db
+		.file_path()
+		.find_many(vec![file_path::cas_id::in_vec(cas_ids)])
+		.include(file_path::include!({ location }))
+		.exec()
+		.await?;
+
+	// Files with the same contents share their thumbnails, so any of them will do
+	let mut requested = HashSet::new();
+	let files = file_paths
+		.into_iter()
+		.filter_map(|file_path| {
+			let kind = thumbnail_kind(&file_path.extension)?;
+			let cas_id = file_path.cas_id?;
+
+			requested.insert(cas_id.clone()).then(|| {
+				(
+					kind,
+					Path::new(&file_path.location.path).join(&file_path.materialized_path),
+					cas_id,
+				)
+			})
+		})
+		.collect::<Vec<_>>();
+
+	if files.is_empty() {
+		return Ok(());
+	}
+
+	let thumbnail_dir = library
+		.config()
+		.data_directory()
+		.join(THUMBNAIL_CACHE_DIR_NAME);
+	let outdated_before =
+		prepare_thumbnail_dir(&thumbnail_dir, &library.config().get().await.thumbnail).await?;
+
+	let library = library.clone();
+	tokio::spawn(with_priority(ThumbnailPriority::Visible, async move {
+		join_all(files.iter().map(|(kind, path, cas_id)| {
+			let (library, thumbnail_dir, outdated_before) =
+				(&library, &thumbnail_dir, &outdated_before);
+			async move {
+				if let Err(e) = generate_thumbnails(
+					library,
+					*kind,
+					path,
+					cas_id,
+					thumbnail_dir,
+					Some(outdated_before),
+					true,
+				)
+				.await
+				{
+					error!("Failed to generate requested thumbnails of {path:?}: {e:#?}");
+				}
+			}
+		}))
+		.await;
+	}));
+
+	Ok(())
+}
@@ -1,5 +1,8 @@
 use crate::{
-	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	job::{
+		JobError, JobPriority, JobReportUpdate, JobResult, JobState, RetryPolicy, StatefulJob,
+		WorkerContext,
+	},
 	library::Library,
 	location::{
 		file_path_helper::{
@@ -20,13 +23,13 @@ use std::{
 use sd_file_ext::extensions::Extension;
 
 use serde::{Deserialize, Serialize};
-use tokio::fs;
 use tracing::info;
 
 use super::{
-	finalize_thumbnailer, process_step, ThumbnailerError, ThumbnailerJobReport,
-	ThumbnailerJobState, ThumbnailerJobStep, ThumbnailerJobStepKind, FILTERED_IMAGE_EXTENSIONS,
-	THUMBNAIL_CACHE_DIR_NAME,
+	finalize_thumbnailer, prepare_thumbnail_dir, process_step, skip_generated_thumbnails,
+	ThumbnailerError, ThumbnailerJobReport, ThumbnailerJobState, ThumbnailerJobStep,
+	ThumbnailerJobStepKind, FILTERED_AUDIO_EXTENSIONS, FILTERED_DOCUMENT_EXTENSIONS,
+	FILTERED_FONT_EXTENSIONS, FILTERED_IMAGE_EXTENSIONS, THUMBNAIL_CACHE_DIR_NAME,
 };
 
 #[cfg(feature = "ffmpeg")]
@@ -59,6 +62,16 @@ impl StatefulJob for ShallowThumbnailerJob {
 		SHALLOW_THUMBNAILER_JOB_NAME
 	}
 
+	fn retry_policy(&self) -> RetryPolicy {
+		// Steps are only removed once their thumbnails are written, and existing thumbnails are skipped
+		RetryPolicy::default()
+	}
+
+	fn priority(&self, _init: &Self::Init) -> JobPriority {
+		// Thumbnails of the directory being browsed shouldn't wait for the full scan of the location
+		JobPriority::Interactive
+	}
+
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
 		let Library { db, .. } = &ctx.library;
 
@@ -100,8 +113,9 @@ impl StatefulJob for ShallowThumbnailerJob {
 
 		info!("Searching for images in location {location_id} at parent directory with id {sub_path_id}");
 
-		// create all necessary directories if they don't exist
-		fs::create_dir_all(&thumbnail_dir).await?;
+		// create all necessary directories if they don't exist, and find the thumbnails made with outdated settings
+		let config = ctx.library.config().get().await.thumbnail;
+		let outdated_before = prepare_thumbnail_dir(&thumbnail_dir, &config).await?;
 
 		// query database for all image files in this location that need thumbnails
 		let image_files = get_files_by_extensions(
@@ -114,6 +128,39 @@ impl StatefulJob for ShallowThumbnailerJob {
 		.await?;
 		info!("Found {:?} image files", image_files.len());
 
+		// audio files get their embedded cover art as thumbnail
+		let audio_files = get_files_by_extensions(
+			db,
+			location_id,
+			sub_path_id,
+			&FILTERED_AUDIO_EXTENSIONS,
+			ThumbnailerJobStepKind::Audio,
+		)
+		.await?;
+		info!("Found {:?} audio files", audio_files.len());
+
+		// EPUBs get their cover as thumbnail, and PDFs their first page
+		let document_files = get_files_by_extensions(
+			db,
+			location_id,
+			sub_path_id,
+			&FILTERED_DOCUMENT_EXTENSIONS,
+			ThumbnailerJobStepKind::Document,
+		)
+		.await?;
+		info!("Found {:?} document files", document_files.len());
+
+		// fonts get sample text drawn with them as thumbnail
+		let font_files = get_files_by_extensions(
+			db,
+			location_id,
+			sub_path_id,
+			&FILTERED_FONT_EXTENSIONS,
+			ThumbnailerJobStepKind::Font,
+		)
+		.await?;
+		info!("Found {:?} font files", font_files.len());
+
 		#[cfg(feature = "ffmpeg")]
 		let all_files = {
 			// query database for all video files in this location that need thumbnails
@@ -130,10 +177,27 @@ impl StatefulJob for ShallowThumbnailerJob {
 			image_files
 				.into_iter()
 				.chain(video_files.into_iter())
+				.chain(audio_files.into_iter())
+				.chain(document_files.into_iter())
+				.chain(font_files.into_iter())
 				.collect::<VecDeque<_>>()
 		};
 		#[cfg(not(feature = "ffmpeg"))]
-		let all_files = { image_files.into_iter().collect::<VecDeque<_>>() };
+		let all_files = {
+			image_files
+				.into_iter()
+				.chain(audio_files.into_iter())
+				.chain(document_files.into_iter())
+				.chain(font_files.into_iter())
+				.collect::<VecDeque<_>>()
+		};
+
+		let found = all_files.len();
+		let all_files = skip_generated_thumbnails(db, all_files, &config, &outdated_before).await?;
+		info!(
+			"Skipping {} files whose thumbnails are up to date",
+			found - all_files.len()
+		);
 
 		ctx.progress(vec![
 			JobReportUpdate::TaskCount(all_files.len()),
@@ -143,6 +207,7 @@ impl StatefulJob for ShallowThumbnailerJob {
 		state.data = Some(ThumbnailerJobState {
 			thumbnail_dir,
 			location_path,
+			thumbnails_outdated_before: Some(outdated_before),
 			report: ThumbnailerJobReport {
 				location_id,
 				materialized_path: if state.init.sub_path != Path::new("") {
@@ -153,6 +218,7 @@ impl StatefulJob for ShallowThumbnailerJob {
 				},
 				thumbnails_created: 0,
 			},
+			processed_files: 0,
 		});
 		state.steps = all_files;
 
@@ -166,8 +232,7 @@ impl StatefulJob for ShallowThumbnailerJob {
 	) -> Result<(), JobError> {
 		process_step(
 			false, // On shallow thumbnailer, we want to show thumbnails ASAP
-			state.step_number,
-			&state.steps[0],
+			&mut state.steps,
 			state
 				.data
 				.as_mut()
@@ -200,6 +265,7 @@ async fn get_files_by_extensions(
 		.find_many(vec![
 			file_path::location_id::equals(location_id),
 			file_path::extension::in_vec(extensions.iter().map(ToString::to_string).collect()),
+			file_path::in_archive::equals(false),
 			file_path::parent_id::equals(Some(parent_id)),
 		])
 		.select(file_path_just_materialized_path_cas_id::select())
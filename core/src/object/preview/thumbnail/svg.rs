@@ -0,0 +1,49 @@
+use image::{DynamicImage, RgbaImage};
+use resvg::{
+	tiny_skia,
+	usvg::{self, TreeParsing, TreePostProc},
+};
+
+use super::ThumbnailerError;
+
+/// SVGs bigger than this are most likely generated data, like plots with millions of points, and get no thumbnail
+pub(super) const MAX_SVG_BYTES: u64 = 8 * 1024 * 1024;
+
+/// The longest edge SVGs are rasterized at, whatever size their thumbnails are
+const MAX_SVG_EDGE: u32 = 2048;
+
+/// rasterize_svg renders an SVG to fit in a square of `size` pixels, vectors being scaled up as well as down.
+/// Text is only rendered when it was converted to paths, as no fonts are loaded.
+pub(super) fn rasterize_svg(bytes: &[u8], size: u32) -> Result<DynamicImage, ThumbnailerError> {
+	let mut tree = usvg::Tree::from_data(bytes, &usvg::Options::default())?;
+	tree.postprocess(usvg::PostProcessingSteps::default());
+
+	let svg_size = tree.size;
+	let scale = size.min(MAX_SVG_EDGE) as f32 / svg_size.width().max(svg_size.height());
+	let width = ((svg_size.width() * scale).round() as u32).max(1);
+	let height = ((svg_size.height() * scale).round() as u32).max(1);
+
+	let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or(ThumbnailerError::EmptySvg)?;
+	resvg::render(
+		&tree,
+		tiny_skia::Transform::from_scale(
+			width as f32 / svg_size.width(),
+			height as f32 / svg_size.height(),
+		),
+		&mut pixmap.as_mut(),
+	);
+
+	// Pixmaps are premultiplied by their alpha, unlike images
+	let pixels = pixmap
+		.pixels()
+		.iter()
+		.flat_map(|pixel| {
+			let color = pixel.demultiply();
+			[color.red(), color.green(), color.blue(), color.alpha()]
+		})
+		.collect::<Vec<_>>();
+
+	RgbaImage::from_raw(width, height, pixels)
+		.map(DynamicImage::ImageRgba8)
+		.ok_or(ThumbnailerError::EmptySvg)
+}
@@ -0,0 +1,339 @@
+use crate::{
+	job::{
+		Job, JobError, JobPriority, JobReportUpdate, JobResult, JobState, StatefulJob,
+		WorkerContext,
+	},
+	library::{library_cas_ids, LibraryManager},
+	node::{ThumbnailConfig, ThumbnailFormat},
+};
+
+use std::{
+	collections::{HashMap, HashSet, VecDeque},
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::{Duration, SystemTime},
+};
+
+use rspc::Type;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use tokio::{fs, io, time::interval};
+use tracing::info;
+
+use super::{forget_thumbnails, THUMBNAIL_CACHE_DIR_NAME};
+
+pub const THUMBNAIL_GC_JOB_NAME: &str = "thumbnail_gc";
+
+/// How often the thumbnail cache is cleaned up
+const THUMBNAIL_GC_TICK: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// How many thumbnail files are removed by each step of the job
+const THUMBNAIL_GC_BATCH_SIZE: usize = 500;
+
+/// `ThumbnailGcJob` cleans up the thumbnail cache, which is shared by every library of the node.
+/// It removes the thumbnails of cas_ids no file has anymore, then the least recently used thumbnails
+/// until the cache fits in the limit of the node config, if it has one.
+pub struct ThumbnailGcJob {}
+
+#[derive(Serialize, Deserialize, Hash)]
+pub struct ThumbnailGcJobInit {}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThumbnailGcJobState {
+	orphans_removed: usize,
+	evicted: usize,
+	freed_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum ThumbnailGcReason {
+	Orphan,
+	Eviction,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThumbnailGcJobStep {
+	reason: ThumbnailGcReason,
+	paths: Vec<PathBuf>,
+}
+
+/// CachedThumbnails are the thumbnail files of a cas_id, in every variant and format, along with its preview strip.
+struct CachedThumbnails {
+	paths: Vec<PathBuf>,
+	size: u64,
+	last_accessed: SystemTime,
+}
+
+/// ThumbnailCacheInfo describes the thumbnail cache of the node.
+#[serde_as]
+#[derive(Serialize, Type)]
+pub struct ThumbnailCacheInfo {
+	/// thumbnails is how many files have thumbnails, counting the variants of a thumbnail once.
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub thumbnails: u64,
+	#[specta(type = String)]
+	#[serde_as(as = "DisplayFromStr")]
+	pub size_bytes: u64,
+	#[specta(type = Option<String>)]
+	#[serde_as(as = "Option<DisplayFromStr>")]
+	pub limit_bytes: Option<u64>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ThumbnailGcJob {
+	type Init = ThumbnailGcJobInit;
+	type Data = ThumbnailGcJobState;
+	type Step = ThumbnailGcJobStep;
+
+	fn name(&self) -> &'static str {
+		THUMBNAIL_GC_JOB_NAME
+	}
+
+	fn priority(&self, _init: &Self::Init) -> JobPriority {
+		JobPriority::Background
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let library = &ctx.library;
+
+		let thumbnail_dir = library
+			.config()
+			.data_directory()
+			.join(THUMBNAIL_CACHE_DIR_NAME);
+		let limit_bytes = library.config().get().await.thumbnail.cache_limit_bytes;
+
+		// The thumbnails of the files of the other libraries aren't orphans, even if this one has no file with them.
+		// They're gathered as the job starts, so files added to them since it was spawned are accounted for.
+		// Without them, the thumbnails of the other libraries would be taken for orphans
+		let libraries = library
+			.library_manager()
+			.ok_or(JobError::MissingData {
+				value: String::from("library manager"),
+			})?
+			.get_all_libraries()
+			.await;
+		let mut live_cas_ids = HashSet::new();
+		for library in &libraries {
+			live_cas_ids.extend(library_cas_ids(library).await?);
+		}
+
+		let (orphans, mut live) = scan_thumbnail_cache(&thumbnail_dir)
+			.await?
+			.into_iter()
+			.partition::<Vec<_>, _>(|(cas_id, _)| !live_cas_ids.contains(cas_id));
+		// Records of orphans are forgotten as well, in case a file with the same contents shows up again
+		let (orphan_cas_ids, orphans) = orphans.into_iter().unzip::<_, _, Vec<_>, Vec<_>>();
+		forget_thumbnails(&library.db, orphan_cas_ids).await?;
+
+		let mut evicted = vec![];
+		let mut evicted_cas_ids = vec![];
+		if let Some(limit_bytes) = limit_bytes {
+			let mut size = live
+				.iter()
+				.map(|(_, thumbnails)| thumbnails.size)
+				.sum::<u64>();
+
+			// The least recently used thumbnails go first
+			live.sort_by_key(|(_, thumbnails)| std::cmp::Reverse(thumbnails.last_accessed));
+			while size > limit_bytes {
+				let Some((cas_id, thumbnails)) = live.pop() else {
+					break;
+				};
+				size -= thumbnails.size;
+				evicted.push(thumbnails);
+				evicted_cas_ids.push(cas_id);
+			}
+		}
+
+		// Evicted thumbnails are generated again when their files are, by the thumbnailer of any library
+		for library in &libraries {
+			forget_thumbnails(&library.db, evicted_cas_ids.clone()).await?;
+		}
+
+		state.steps = [
+			(ThumbnailGcReason::Orphan, orphans),
+			(ThumbnailGcReason::Eviction, evicted),
+		]
+		.into_iter()
+		.flat_map(|(reason, groups)| {
+			groups
+				.into_iter()
+				.flat_map(|thumbnails| thumbnails.paths)
+				.collect::<Vec<_>>()
+				.chunks(THUMBNAIL_GC_BATCH_SIZE)
+				.map(|paths| ThumbnailGcJobStep {
+					reason,
+					paths: paths.to_vec(),
+				})
+				.collect::<Vec<_>>()
+		})
+		.collect::<VecDeque<_>>();
+
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(state.steps.len()),
+			JobReportUpdate::Message("Cleaning up thumbnails".to_string()),
+		]);
+
+		state.data = Some(ThumbnailGcJobState {
+			orphans_removed: 0,
+			evicted: 0,
+			freed_bytes: 0,
+		});
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let data = state
+			.data
+			.as_mut()
+			.expect("critical error: missing data on job state");
+		let step = &state.steps[0];
+
+		for path in &step.paths {
+			// The thumbnail could have been removed since the cache was scanned
+			let size = match fs::metadata(path).await {
+				Ok(metadata) => metadata.len(),
+				Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+				Err(e) => return Err(e.into()),
+			};
+			match fs::remove_file(path).await {
+				Ok(()) => {}
+				Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+				Err(e) => return Err(e.into()),
+			}
+
+			data.freed_bytes += size;
+			match step.reason {
+				ThumbnailGcReason::Orphan => data.orphans_removed += 1,
+				ThumbnailGcReason::Eviction => data.evicted += 1,
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, _ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!(
+			"Finished thumbnail cleanup: removed {} orphaned and {} evicted thumbnails, freeing {} bytes",
+			data.orphans_removed, data.evicted, data.freed_bytes
+		);
+
+		Ok(Some(serde_json::to_value(data)?))
+	}
+}
+
+/// scan_thumbnail_cache groups the files of the thumbnail cache by the cas_id they belong to,
+/// looking through the thumbnails directory and the directories of its variants and of the preview strips.
+async fn scan_thumbnail_cache(
+	thumbnail_dir: impl AsRef<Path>,
+) -> io::Result<HashMap<String, CachedThumbnails>> {
+	let thumbnail_dir = thumbnail_dir.as_ref();
+	let mut cache = HashMap::new();
+
+	let mut dirs = vec![thumbnail_dir.to_path_buf()];
+	while let Some(dir) = dirs.pop() {
+		let mut entries = match fs::read_dir(&dir).await {
+			Ok(entries) => entries,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+			Err(e) => return Err(e),
+		};
+
+		while let Some(entry) = entries.next_entry().await? {
+			let path = entry.path();
+			let metadata = entry.metadata().await?;
+
+			// Variants and preview strips are only one level deep
+			if metadata.is_dir() {
+				if dir == thumbnail_dir {
+					dirs.push(path);
+				}
+				continue;
+			}
+
+			let is_thumbnail = path
+				.extension()
+				.and_then(|extension| extension.to_str())
+				.map_or(false, |extension| {
+					ThumbnailFormat::ALL
+						.iter()
+						.any(|format| format.extension() == extension)
+				});
+			let Some(cas_id) = path
+				.file_stem()
+				.and_then(|stem| stem.to_str())
+				.filter(|_| is_thumbnail)
+			else {
+				continue;
+			};
+
+			let last_accessed = metadata
+				.accessed()
+				.or_else(|_| metadata.modified())
+				.unwrap_or(SystemTime::UNIX_EPOCH);
+
+			let thumbnails = cache
+				.entry(cas_id.to_string())
+				.or_insert_with(|| CachedThumbnails {
+					paths: vec![],
+					size: 0,
+					last_accessed,
+				});
+			thumbnails.size += metadata.len();
+			thumbnails.last_accessed = thumbnails.last_accessed.max(last_accessed);
+			thumbnails.paths.push(path);
+		}
+	}
+
+	Ok(cache)
+}
+
+/// thumbnail_cache_info returns how many thumbnails the cache in `data_directory` holds and how much space they use.
+pub async fn thumbnail_cache_info(
+	data_directory: impl AsRef<Path>,
+	config: &ThumbnailConfig,
+) -> io::Result<ThumbnailCacheInfo> {
+	let cache =
+		scan_thumbnail_cache(data_directory.as_ref().join(THUMBNAIL_CACHE_DIR_NAME)).await?;
+
+	Ok(ThumbnailCacheInfo {
+		thumbnails: cache.len() as u64,
+		size_bytes: cache.values().map(|thumbnails| thumbnails.size).sum(),
+		limit_bytes: config.cache_limit_bytes,
+	})
+}
+
+/// spawn_thumbnail_gc_scheduler periodically spawns a [`ThumbnailGcJob`] on one of the loaded libraries.
+/// As the thumbnail cache is shared by the libraries of the node, the job goes through the files of every one of them.
+pub fn spawn_thumbnail_gc_scheduler(library_manager: Arc<LibraryManager>) {
+	tokio::spawn(async move {
+		let mut interval = interval(THUMBNAIL_GC_TICK);
+
+		loop {
+			interval.tick().await;
+
+			let libraries = library_manager.get_all_libraries().await;
+			let Some(library) = libraries.first() else {
+				continue;
+			};
+
+			library
+				.spawn_job(Job::new(ThumbnailGcJobInit {}, ThumbnailGcJob {}))
+				.await;
+		}
+	});
+}
@@ -1,24 +1,34 @@
 use crate::{
-	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	job::{
+		JobError, JobReportUpdate, JobResult, JobState, RetryPolicy, StatefulJob, WorkerContext,
+	},
 	library::Library,
-	location::file_path_helper::{
-		ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
-		file_path_just_materialized_path_cas_id, MaterializedPath,
+	location::{
+		file_path_helper::{
+			ensure_sub_path_is_directory, ensure_sub_path_is_in_location, file_path_in_subtree,
+			file_path_just_materialized_path_cas_id, get_existing_file_path_id, MaterializedPath,
+		},
+		LocationId,
 	},
 	prisma::{file_path, location, PrismaClient},
 };
 
-use std::{collections::VecDeque, hash::Hash, path::PathBuf};
+use std::{
+	collections::{HashSet, VecDeque},
+	hash::Hash,
+	path::PathBuf,
+};
 
 use sd_file_ext::extensions::Extension;
 
 use serde::{Deserialize, Serialize};
-use tokio::fs;
 use tracing::info;
 
 use super::{
-	finalize_thumbnailer, process_step, ThumbnailerError, ThumbnailerJobReport,
-	ThumbnailerJobState, ThumbnailerJobStep, ThumbnailerJobStepKind, FILTERED_IMAGE_EXTENSIONS,
+	finalize_thumbnailer, forget_thumbnails, prepare_thumbnail_dir, process_step,
+	remove_thumbnails, skip_generated_thumbnails, ThumbnailerError, ThumbnailerJobReport,
+	ThumbnailerJobState, ThumbnailerJobStep, ThumbnailerJobStepKind, FILTERED_AUDIO_EXTENSIONS,
+	FILTERED_DOCUMENT_EXTENSIONS, FILTERED_FONT_EXTENSIONS, FILTERED_IMAGE_EXTENSIONS,
 	THUMBNAIL_CACHE_DIR_NAME,
 };
 
@@ -34,6 +44,10 @@ pub struct ThumbnailerJobInit {
 	pub location: location::Data,
 	pub sub_path: Option<PathBuf>,
 	pub background: bool,
+	/// regenerate removes the existing thumbnails of the files before generating them again,
+	/// even the ones which are up to date.
+	#[serde(default)]
+	pub regenerate: bool,
 }
 
 impl Hash for ThumbnailerJobInit {
@@ -42,6 +56,7 @@ impl Hash for ThumbnailerJobInit {
 		if let Some(ref sub_path) = self.sub_path {
 			sub_path.hash(state);
 		}
+		self.regenerate.hash(state);
 	}
 }
 
@@ -55,6 +70,11 @@ impl StatefulJob for ThumbnailerJob {
 		THUMBNAILER_JOB_NAME
 	}
 
+	fn retry_policy(&self) -> RetryPolicy {
+		// Steps are only removed once their thumbnails are written, and existing thumbnails are skipped
+		RetryPolicy::default()
+	}
+
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
 		let Library { db, .. } = &ctx.library;
 
@@ -84,25 +104,65 @@ impl StatefulJob for ThumbnailerJob {
 
 		info!("Searching for images in location {location_id} at directory {materialized_path}");
 
-		// create all necessary directories if they don't exist
-		fs::create_dir_all(&thumbnail_dir).await?;
+		// create all necessary directories if they don't exist, and find the thumbnails made with outdated settings
+		let config = ctx.library.config().get().await.thumbnail;
+		let outdated_before = prepare_thumbnail_dir(&thumbnail_dir, &config).await?;
+
+		let directory_id = get_existing_file_path_id(materialized_path.clone(), db)
+			.await
+			.map_err(ThumbnailerError::from)?;
 
 		// query database for all image files in this location that need thumbnails
 		let image_files = get_files_by_extensions(
 			db,
-			&materialized_path,
+			location_id,
+			directory_id,
 			&FILTERED_IMAGE_EXTENSIONS,
 			ThumbnailerJobStepKind::Image,
 		)
 		.await?;
 		info!("Found {:?} image files", image_files.len());
 
+		// audio files get their embedded cover art as thumbnail
+		let audio_files = get_files_by_extensions(
+			db,
+			location_id,
+			directory_id,
+			&FILTERED_AUDIO_EXTENSIONS,
+			ThumbnailerJobStepKind::Audio,
+		)
+		.await?;
+		info!("Found {:?} audio files", audio_files.len());
+
+		// EPUBs get their cover as thumbnail, and PDFs their first page
+		let document_files = get_files_by_extensions(
+			db,
+			location_id,
+			directory_id,
+			&FILTERED_DOCUMENT_EXTENSIONS,
+			ThumbnailerJobStepKind::Document,
+		)
+		.await?;
+		info!("Found {:?} document files", document_files.len());
+
+		// fonts get sample text drawn with them as thumbnail
+		let font_files = get_files_by_extensions(
+			db,
+			location_id,
+			directory_id,
+			&FILTERED_FONT_EXTENSIONS,
+			ThumbnailerJobStepKind::Font,
+		)
+		.await?;
+		info!("Found {:?} font files", font_files.len());
+
 		#[cfg(feature = "ffmpeg")]
 		let all_files = {
 			// query database for all video files in this location that need thumbnails
 			let video_files = get_files_by_extensions(
 				db,
-				&materialized_path,
+				location_id,
+				directory_id,
 				&FILTERED_VIDEO_EXTENSIONS,
 				ThumbnailerJobStepKind::Video,
 			)
@@ -112,10 +172,46 @@ impl StatefulJob for ThumbnailerJob {
 			image_files
 				.into_iter()
 				.chain(video_files.into_iter())
+				.chain(audio_files.into_iter())
+				.chain(document_files.into_iter())
+				.chain(font_files.into_iter())
 				.collect::<VecDeque<_>>()
 		};
 		#[cfg(not(feature = "ffmpeg"))]
-		let all_files = { image_files.into_iter().collect::<VecDeque<_>>() };
+		let all_files = {
+			image_files
+				.into_iter()
+				.chain(audio_files.into_iter())
+				.chain(document_files.into_iter())
+				.chain(font_files.into_iter())
+				.collect::<VecDeque<_>>()
+		};
+
+		let all_files = if state.init.regenerate {
+			let cas_ids = all_files
+				.iter()
+				.filter_map(|step| step.file_path.cas_id.clone())
+				.collect::<HashSet<_>>();
+
+			let mut removed = 0;
+			for cas_id in &cas_ids {
+				removed += remove_thumbnails(&thumbnail_dir, cas_id, &config).await?;
+			}
+			forget_thumbnails(db, cas_ids.into_iter().collect()).await?;
+			info!("Removed {removed} thumbnails to regenerate them");
+
+			all_files
+		} else {
+			let found = all_files.len();
+			let all_files =
+				skip_generated_thumbnails(db, all_files, &config, &outdated_before).await?;
+			info!(
+				"Skipping {} files whose thumbnails are up to date",
+				found - all_files.len()
+			);
+
+			all_files
+		};
 
 		ctx.progress(vec![
 			JobReportUpdate::TaskCount(all_files.len()),
@@ -125,11 +221,13 @@ impl StatefulJob for ThumbnailerJob {
 		state.data = Some(ThumbnailerJobState {
 			thumbnail_dir,
 			location_path,
+			thumbnails_outdated_before: Some(outdated_before),
 			report: ThumbnailerJobReport {
 				location_id,
 				materialized_path: materialized_path.into(),
 				thumbnails_created: 0,
 			},
+			processed_files: 0,
 		});
 		state.steps = all_files;
 
@@ -143,8 +241,7 @@ impl StatefulJob for ThumbnailerJob {
 	) -> Result<(), JobError> {
 		process_step(
 			state.init.background,
-			state.step_number,
-			&state.steps[0],
+			&mut state.steps,
 			state
 				.data
 				.as_mut()
@@ -165,18 +262,25 @@ impl StatefulJob for ThumbnailerJob {
 	}
 }
 
+/// get_files_by_extensions returns the files under a directory, which has no files when it isn't indexed and `directory_id` is `None`.
 async fn get_files_by_extensions(
 	db: &PrismaClient,
-	materialized_path: &MaterializedPath,
+	location_id: LocationId,
+	directory_id: Option<i32>,
 	extensions: &[Extension],
 	kind: ThumbnailerJobStepKind,
 ) -> Result<Vec<ThumbnailerJobStep>, JobError> {
+	let Some(directory_id) = directory_id else {
+		return Ok(vec![]);
+	};
+
 	Ok(db
 		.file_path()
 		.find_many(vec![
-			file_path::location_id::equals(materialized_path.location_id()),
+			file_path::location_id::equals(location_id),
 			file_path::extension::in_vec(extensions.iter().map(ToString::to_string).collect()),
-			file_path::materialized_path::starts_with(materialized_path.into()),
+			file_path::in_archive::equals(false),
+			file_path_in_subtree(location_id, directory_id),
 		])
 		.select(file_path_just_materialized_path_cas_id::select())
 		.exec()
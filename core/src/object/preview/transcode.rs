@@ -0,0 +1,448 @@
+//! Transcoding of videos and audio into HLS with fMP4 segments, for clients which can't play the codecs of the source.
+//!
+//! A session runs the `ffmpeg` binary for a source and the codecs supported by the client. The playlist lists every
+//! segment of the source upfront, so the client can seek anywhere: requesting a segment far from what ffmpeg is
+//! producing restarts it at that segment. Streams in a supported codec are copied instead of transcoded, and as
+//! copied video can only be cut on its own keyframes, sessions copying video can't restart and seeks wait for ffmpeg.
+//! Sessions which aren't requested for a while are killed and their segments removed.
+
+use crate::util::ffmpeg::{ffmpeg_available, ffmpeg_command};
+
+use std::{
+	collections::{BTreeSet, HashMap},
+	path::{Path, PathBuf},
+	process::{ExitStatus, Stdio},
+	sync::{Arc, Mutex as StdMutex},
+	time::{Duration, Instant},
+};
+
+use ffmpeg_next::{codec, media::Type};
+use once_cell::sync::Lazy;
+use thiserror::Error;
+use tokio::{
+	fs, io,
+	process::Child,
+	sync::{Mutex, OnceCell},
+	time::{interval, sleep},
+};
+use tracing::{debug, error};
+use uuid::Uuid;
+
+/// TRANSCODES_DIR_NAME is the directory in the node data directory where the segments of the sessions are written.
+pub const TRANSCODES_DIR_NAME: &str = "transcodes";
+
+/// Length of every segment but the last one
+const SEGMENT_SECS: u32 = 6;
+/// Seeking more than this many segments ahead of ffmpeg restarts it instead of waiting
+const SEEK_RESTART_DISTANCE: u32 = 2;
+const SEGMENT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const SEGMENT_WAIT_TIMEOUT: Duration = Duration::from_secs(30);
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const SESSION_REAPER_TICK: Duration = Duration::from_secs(30);
+/// Durations in ffmpeg are in microseconds
+const AV_TIME_BASE: f64 = 1_000_000.0;
+/// The playlist written by ffmpeg, which lists the segments it has finished
+const ENCODER_PLAYLIST_NAME: &str = "encoder.m3u8";
+const INIT_SEGMENT_NAME: &str = "init.mp4";
+
+static SESSIONS: Lazy<Mutex<HashMap<Uuid, Arc<TranscodeSession>>>> = Lazy::new(Default::default);
+static REAPER: OnceCell<()> = OnceCell::const_new();
+
+#[derive(Error, Debug)]
+pub enum TranscodeError {
+	#[error("io error: {0}")]
+	IO(#[from] io::Error),
+	#[error("failed to probe the source: {0}")]
+	Probe(#[from] ffmpeg_next::Error),
+	#[error("failed to join the probe task: {0}")]
+	Join(#[from] tokio::task::JoinError),
+	#[error("the source has no video nor audio stream")]
+	NoMediaStreams,
+	#[error("segment {0} doesn't exist")]
+	SegmentNotFound(u32),
+	#[error("timed out waiting for segment {0}")]
+	Timeout(u32),
+	#[error("ffmpeg failed: {0}")]
+	EncoderFailed(ExitStatus),
+	#[error("the ffmpeg binary wasn't found")]
+	FfmpegNotFound,
+}
+
+/// SupportedCodecs are the codecs the client can play, named like ffmpeg names them (e.g. `h264`, `hevc`, `aac`, `opus`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SupportedCodecs {
+	pub video: Vec<String>,
+	pub audio: Vec<String>,
+}
+
+impl Default for SupportedCodecs {
+	/// The codecs every HLS client can play
+	fn default() -> Self {
+		Self {
+			video: vec!["h264".to_string()],
+			audio: vec!["aac".to_string()],
+		}
+	}
+}
+
+/// TranscodePlan is what ffmpeg does with each stream of the source, `None` when the source doesn't have the stream.
+#[derive(Debug, Clone, Copy)]
+struct TranscodePlan {
+	copy_video: Option<bool>,
+	copy_audio: Option<bool>,
+}
+
+impl TranscodePlan {
+	fn restartable(&self) -> bool {
+		self.copy_video != Some(true)
+	}
+}
+
+#[derive(Default)]
+struct EncoderState {
+	child: Option<Child>,
+	start_segment: u32,
+	/// Segments ffmpeg has finished, across every restart
+	ready: BTreeSet<u32>,
+}
+
+pub struct TranscodeSession {
+	pub id: Uuid,
+	source: PathBuf,
+	codecs: SupportedCodecs,
+	dir: PathBuf,
+	duration_secs: f64,
+	plan: TranscodePlan,
+	state: Mutex<EncoderState>,
+	last_access: StdMutex<Instant>,
+}
+
+impl TranscodeSession {
+	fn touch(&self) {
+		if let Ok(mut last_access) = self.last_access.lock() {
+			*last_access = Instant::now();
+		}
+	}
+
+	fn idle_for(&self) -> Duration {
+		self.last_access
+			.lock()
+			.map(|last_access| last_access.elapsed())
+			.unwrap_or_default()
+	}
+
+	fn segment_count(&self) -> u32 {
+		((self.duration_secs / SEGMENT_SECS as f64).ceil() as u32).max(1)
+	}
+
+	/// playlist is the HLS media playlist of the session, with every segment of the source.
+	/// Segments are relative to the playlist, served at `stream/<library_id>/<location_id>/<file_path_id>/index.m3u8`,
+	/// so they resolve against whatever origin it was requested from. With a `token`, segments are requested with it.
+	pub fn playlist(&self, token: Option<&str>) -> String {
+		self.touch();
+
+		let base = format!("../../../session/{}", self.id);
+		let query = token.map_or_else(String::new, |token| format!("?token={token}"));
+
+		let mut playlist = format!(
+			"#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-TARGETDURATION:{SEGMENT_SECS}\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-MAP:URI=\"{base}/{INIT_SEGMENT_NAME}{query}\"\n"
+		);
+
+		let count = self.segment_count();
+		for index in 0..count {
+			let duration = if index + 1 == count {
+				self.duration_secs - (index * SEGMENT_SECS) as f64
+			} else {
+				SEGMENT_SECS as f64
+			};
+
+			playlist.push_str(&format!(
+				"#EXTINF:{duration:.6},\n{base}/{index}.m4s{query}\n"
+			));
+		}
+
+		playlist.push_str("#EXT-X-ENDLIST\n");
+
+		playlist
+	}
+
+	fn start_encoder(&self, state: &mut EncoderState, start_segment: u32) -> Result<(), io::Error> {
+		let start_secs = start_segment * SEGMENT_SECS;
+		debug!(
+			"Starting transcode session {} at {start_secs}s of {}",
+			self.id,
+			self.source.display()
+		);
+
+		let mut command = ffmpeg_command()?;
+		command
+			.args(["-hide_banner", "-loglevel", "error", "-nostdin"])
+			.args(["-ss", &start_secs.to_string()])
+			.arg("-i")
+			.arg(&self.source)
+			.args(["-map", "0:v:0?", "-map", "0:a:0?"]);
+
+		match self.plan.copy_video {
+			Some(true) => {
+				command.args(["-c:v", "copy"]);
+			}
+			Some(false) => {
+				// Keyframes on segment boundaries, so segments from different runs line up
+				command.args([
+					"-c:v",
+					"libx264",
+					"-preset",
+					"veryfast",
+					"-pix_fmt",
+					"yuv420p",
+					"-force_key_frames",
+					&format!("expr:gte(t,n_forced*{SEGMENT_SECS})"),
+				]);
+			}
+			None => {}
+		}
+
+		match self.plan.copy_audio {
+			Some(true) => {
+				command.args(["-c:a", "copy"]);
+			}
+			Some(false) => {
+				command.args(["-c:a", "aac", "-ac", "2", "-b:a", "160k"]);
+			}
+			None => {}
+		}
+
+		command
+			.args(["-output_ts_offset", &start_secs.to_string()])
+			.args(["-f", "hls", "-hls_time", &SEGMENT_SECS.to_string()])
+			.args(["-hls_playlist_type", "event", "-hls_segment_type", "fmp4"])
+			.args(["-hls_fmp4_init_filename", INIT_SEGMENT_NAME])
+			.args(["-hls_flags", "temp_file"])
+			.args(["-start_number", &start_segment.to_string()])
+			.arg("-hls_segment_filename")
+			.arg(self.dir.join("%d.m4s"))
+			.arg(self.dir.join(ENCODER_PLAYLIST_NAME))
+			.stdin(Stdio::null())
+			.stdout(Stdio::null())
+			.kill_on_drop(true);
+
+		// The previous run is killed when its child is dropped
+		state.child = Some(command.spawn()?);
+		state.start_segment = start_segment;
+
+		Ok(())
+	}
+
+	/// refresh_ready reads the segments ffmpeg has finished from its playlist.
+	async fn refresh_ready(&self, state: &mut EncoderState) -> Result<(), io::Error> {
+		let playlist = match fs::read_to_string(self.dir.join(ENCODER_PLAYLIST_NAME)).await {
+			Ok(playlist) => playlist,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+			Err(e) => return Err(e),
+		};
+
+		state.ready.extend(
+			playlist
+				.lines()
+				.filter_map(|line| line.strip_suffix(".m4s"))
+				.filter_map(|index| index.parse::<u32>().ok()),
+		);
+
+		Ok(())
+	}
+
+	/// wait_for_segment waits until ffmpeg finished a segment, restarting it if the segment is out of its way.
+	async fn wait_for_segment(&self, index: u32) -> Result<(), TranscodeError> {
+		let deadline = Instant::now() + SEGMENT_WAIT_TIMEOUT;
+
+		loop {
+			{
+				let mut state = self.state.lock().await;
+				self.refresh_ready(&mut state).await?;
+
+				if state.ready.contains(&index) {
+					return Ok(());
+				}
+
+				let next_segment = state
+					.ready
+					.range(state.start_segment..)
+					.next_back()
+					.map_or(state.start_segment, |last| last + 1);
+
+				if self.plan.restartable()
+					&& (index < state.start_segment || index > next_segment + SEEK_RESTART_DISTANCE)
+				{
+					self.start_encoder(&mut state, index)?;
+				} else if let Some(child) = state.child.as_mut() {
+					match child.try_wait()? {
+						Some(status) if status.success() => {
+							return Err(TranscodeError::SegmentNotFound(index))
+						}
+						Some(status) => return Err(TranscodeError::EncoderFailed(status)),
+						None => {}
+					}
+				}
+			}
+
+			if Instant::now() > deadline {
+				return Err(TranscodeError::Timeout(index));
+			}
+
+			sleep(SEGMENT_POLL_INTERVAL).await;
+		}
+	}
+
+	/// segment returns a media segment, once ffmpeg has finished it.
+	pub async fn segment(&self, index: u32) -> Result<Vec<u8>, TranscodeError> {
+		self.touch();
+
+		if index >= self.segment_count() {
+			return Err(TranscodeError::SegmentNotFound(index));
+		}
+
+		self.wait_for_segment(index).await?;
+
+		Ok(fs::read(self.dir.join(format!("{index}.m4s"))).await?)
+	}
+
+	/// init_segment returns the initialization segment, which ffmpeg writes along with the first media segment.
+	pub async fn init_segment(&self) -> Result<Vec<u8>, TranscodeError> {
+		self.touch();
+
+		let first_segment = self.state.lock().await.start_segment;
+		self.wait_for_segment(first_segment).await?;
+
+		Ok(fs::read(self.dir.join(INIT_SEGMENT_NAME)).await?)
+	}
+}
+
+fn probe(source: &Path, codecs: &SupportedCodecs) -> Result<(f64, TranscodePlan), TranscodeError> {
+	ffmpeg_next::init()?;
+
+	let input = ffmpeg_next::format::input(&source)?;
+	let codec_of = |kind| {
+		input
+			.streams()
+			.best(kind)
+			.map(|stream| stream.parameters().id())
+	};
+	let is_supported =
+		|id: codec::Id, supported: &[String]| supported.iter().any(|name| name == id.name());
+
+	let plan = TranscodePlan {
+		copy_video: codec_of(Type::Video).map(|id| is_supported(id, &codecs.video)),
+		copy_audio: codec_of(Type::Audio).map(|id| is_supported(id, &codecs.audio)),
+	};
+
+	if plan.copy_video.is_none() && plan.copy_audio.is_none() {
+		return Err(TranscodeError::NoMediaStreams);
+	}
+
+	Ok((input.duration().max(0) as f64 / AV_TIME_BASE, plan))
+}
+
+/// open_session returns the session streaming `source` with `codecs`, starting one if there isn't one yet.
+pub async fn open_session(
+	data_dir: impl AsRef<Path>,
+	source: PathBuf,
+	codecs: SupportedCodecs,
+) -> Result<Arc<TranscodeSession>, TranscodeError> {
+	if !ffmpeg_available() {
+		return Err(TranscodeError::FfmpegNotFound);
+	}
+
+	let transcodes_dir = data_dir.as_ref().join(TRANSCODES_DIR_NAME);
+
+	REAPER
+		.get_or_init(|| async {
+			// Segments left behind by a previous run of the node
+			if let Err(e) = fs::remove_dir_all(&transcodes_dir).await {
+				if e.kind() != io::ErrorKind::NotFound {
+					error!("Failed to remove old transcode sessions: {e:#?}");
+				}
+			}
+
+			tokio::spawn(reap_idle_sessions());
+		})
+		.await;
+
+	let mut sessions = SESSIONS.lock().await;
+
+	if let Some(session) = sessions
+		.values()
+		.find(|session| session.source == source && session.codecs == codecs)
+	{
+		session.touch();
+		return Ok(Arc::clone(session));
+	}
+
+	let (duration_secs, plan) = {
+		let source = source.clone();
+		let codecs = codecs.clone();
+		tokio::task::spawn_blocking(move || probe(&source, &codecs)).await??
+	};
+
+	let id = Uuid::new_v4();
+	let dir = transcodes_dir.join(id.to_string());
+	fs::create_dir_all(&dir).await?;
+
+	let session = Arc::new(TranscodeSession {
+		id,
+		source,
+		codecs,
+		dir,
+		duration_secs,
+		plan,
+		state: Mutex::new(EncoderState::default()),
+		last_access: StdMutex::new(Instant::now()),
+	});
+
+	session.start_encoder(&mut *session.state.lock().await, 0)?;
+
+	sessions.insert(id, Arc::clone(&session));
+
+	Ok(session)
+}
+
+pub async fn get_session(id: Uuid) -> Option<Arc<TranscodeSession>> {
+	SESSIONS.lock().await.get(&id).cloned()
+}
+
+/// reap_idle_sessions kills the sessions which haven't been requested for a while and removes their segments.
+async fn reap_idle_sessions() {
+	let mut interval = interval(SESSION_REAPER_TICK);
+
+	loop {
+		interval.tick().await;
+
+		let idle = {
+			let mut sessions = SESSIONS.lock().await;
+			let idle_ids = sessions
+				.iter()
+				.filter(|(_, session)| session.idle_for() > SESSION_IDLE_TIMEOUT)
+				.map(|(id, _)| *id)
+				.collect::<Vec<_>>();
+
+			idle_ids
+				.into_iter()
+				.filter_map(|id| sessions.remove(&id))
+				.collect::<Vec<_>>()
+		};
+
+		for session in idle {
+			debug!("Closing idle transcode session {}", session.id);
+
+			if let Some(mut child) = session.state.lock().await.child.take() {
+				child.kill().await.ok();
+			}
+
+			if let Err(e) = fs::remove_dir_all(&session.dir).await {
+				error!(
+					"Failed to remove the segments of transcode session {}: {e:#?}",
+					session.id
+				);
+			}
+		}
+	}
+}
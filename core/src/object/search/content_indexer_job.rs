@@ -0,0 +1,205 @@
+use crate::{
+	invalidate_query,
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	location::file_path_helper::file_path_for_content_indexer,
+	prisma::{file_path, location, object},
+	util::resources::resource_limiter,
+};
+
+use sd_file_ext::kind::ObjectKind;
+
+use std::{
+	collections::{HashSet, VecDeque},
+	path::PathBuf,
+	sync::Arc,
+};
+
+use int_enum::IntEnum;
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+use tracing::{info, warn};
+
+use super::{can_extract_text, content_index, extract_text, ContentIndex, SearchError};
+
+pub const CONTENT_INDEXER_JOB_NAME: &str = "content_indexer";
+
+/// How many objects are indexed between commits, so a stopped job only loses the last ones
+const COMMIT_EVERY: usize = 100;
+
+/// ContentIndexerJob extracts the text of the documents, plain text and code files of this node into the search index of the library.
+pub struct ContentIndexerJob {}
+
+#[derive(Serialize, Deserialize, Hash)]
+pub struct ContentIndexerJobInit {
+	/// location_id restricts the job to a location, otherwise every location of this node is indexed
+	pub location_id: Option<i32>,
+	/// rebuild indexes objects which already are indexed again. Without a location, the index is cleared first,
+	/// which also drops the objects which don't exist anymore.
+	#[serde(default)]
+	pub rebuild: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ContentIndexerJobState {
+	indexed: usize,
+	/// Files which have no text, like empty or binary files
+	skipped: usize,
+	failed: usize,
+	uncommitted: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ContentIndexerJobStep {
+	object_id: i32,
+	kind: i32,
+	name: String,
+	extension: String,
+	path: PathBuf,
+}
+
+async fn commit(index: Arc<ContentIndex>) -> Result<(), SearchError> {
+	spawn_blocking(move || index.commit()).await?
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ContentIndexerJob {
+	type Init = ContentIndexerJobInit;
+	type Data = ContentIndexerJobState;
+	type Step = ContentIndexerJobStep;
+
+	fn name(&self) -> &'static str {
+		CONTENT_INDEXER_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let index = content_index(&ctx.library).await?;
+
+		if state.init.rebuild && state.init.location_id.is_none() {
+			index.clear()?;
+			commit(Arc::clone(&index)).await?;
+		}
+
+		let mut params = vec![
+			file_path::is_dir::equals(false),
+			file_path::location::is(vec![location::node_id::equals(ctx.library.node_local_id)]),
+			file_path::object::is(vec![object::kind::in_vec(vec![
+				ObjectKind::Document.int_value(),
+				ObjectKind::Text.int_value(),
+				ObjectKind::Code.int_value(),
+			])]),
+		];
+		if let Some(location_id) = state.init.location_id {
+			params.push(file_path::location_id::equals(location_id));
+		}
+
+		let file_paths = ctx
+			.library
+			.db
+			.file_path()
+			.find_many(params)
+			.select(file_path_for_content_indexer::select())
+			.exec()
+			.await?;
+
+		// Objects are indexed once, from whichever of their file paths comes first
+		let mut seen_objects = HashSet::new();
+		for file_path in file_paths {
+			let Some(object) = file_path.object else {
+				continue;
+			};
+
+			let kind = ObjectKind::from_int(object.kind).unwrap_or(ObjectKind::Unknown);
+			if !can_extract_text(kind, &file_path.extension)
+				|| !seen_objects.insert(object.id)
+				|| (!state.init.rebuild && index.contains(object.id)?)
+			{
+				continue;
+			}
+
+			state.steps.push_back(ContentIndexerJobStep {
+				object_id: object.id,
+				kind: object.kind,
+				path: PathBuf::from(file_path.location.path).join(&file_path.materialized_path),
+				name: file_path.name,
+				extension: file_path.extension,
+			});
+		}
+
+		state.data = Some(ContentIndexerJobState::default());
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let step = &state.steps[0];
+		let data = state.data.as_mut().expect("fatal: missing job state");
+		let index = content_index(&ctx.library).await?;
+
+		let text = {
+			let _permit = resource_limiter().open_file().await;
+			extract_text(
+				&step.path,
+				ObjectKind::from_int(step.kind).unwrap_or(ObjectKind::Unknown),
+				&step.extension,
+			)
+			.await
+		};
+
+		match text {
+			Ok(Some(text)) => {
+				let name = if step.extension.is_empty() {
+					step.name.clone()
+				} else {
+					format!("{}.{}", step.name, step.extension)
+				};
+
+				index.add(step.object_id, &name, &text)?;
+				data.indexed += 1;
+				data.uncommitted += 1;
+			}
+			Ok(None) => data.skipped += 1,
+			Err(e) => {
+				warn!(
+					"Failed to extract the text of {}: {e:#?}",
+					step.path.display()
+				);
+				data.failed += 1;
+			}
+		}
+
+		if data.uncommitted >= COMMIT_EVERY {
+			commit(index).await?;
+			data.uncommitted = 0;
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		commit(content_index(&ctx.library).await?).await?;
+
+		info!(
+			"Finished indexing contents: {} indexed, {} without text, {} failed",
+			data.indexed, data.skipped, data.failed
+		);
+
+		invalidate_query!(ctx.library, "search.content");
+
+		Ok(Some(serde_json::to_value(data)?))
+	}
+}
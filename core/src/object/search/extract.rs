@@ -0,0 +1,174 @@
+use std::{
+	fs::File,
+	io::{BufReader, Read},
+	path::Path,
+};
+
+use sd_file_ext::kind::ObjectKind;
+
+use tokio::{fs, task::spawn_blocking};
+
+use super::SearchError;
+
+/// Files bigger than this aren't read, as the text they hold would be too big to index anyway
+const MAX_EXTRACTED_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Only the start of very long texts is indexed, which is enough to find them
+const MAX_INDEXED_TEXT_LENGTH: usize = 1024 * 1024;
+
+/// The entries of zipped office documents holding their text, by extension
+const OFFICE_DOCUMENT_ENTRIES: &[(&str, &str)] = &[
+	("docx", "word/document.xml"),
+	("pptx", "ppt/slides/slide"),
+	("xlsx", "xl/sharedStrings.xml"),
+	("odt", "content.xml"),
+	("odp", "content.xml"),
+	("ods", "content.xml"),
+];
+
+/// can_extract_text tells if the text of a file with this kind and extension can be extracted.
+pub fn can_extract_text(kind: ObjectKind, extension: &str) -> bool {
+	matches!(kind, ObjectKind::Text | ObjectKind::Code)
+		|| extension.eq_ignore_ascii_case("pdf")
+		|| OFFICE_DOCUMENT_ENTRIES
+			.iter()
+			.any(|(office_extension, _)| extension.eq_ignore_ascii_case(office_extension))
+}
+
+/// extract_text returns the text of a PDF, office document, plain text or code file, or `None` if it has none.
+pub async fn extract_text(
+	path: impl AsRef<Path>,
+	kind: ObjectKind,
+	extension: &str,
+) -> Result<Option<String>, SearchError> {
+	let path = path.as_ref().to_path_buf();
+	let extension = extension.to_lowercase();
+
+	if fs::metadata(&path).await?.len() > MAX_EXTRACTED_FILE_SIZE {
+		return Ok(None);
+	}
+
+	let text = spawn_blocking(move || match extension.as_str() {
+		"pdf" => pdf_extract::extract_text(&path)
+			.map(Some)
+			.map_err(|e| SearchError::Pdf(e.to_string())),
+		extension => {
+			if let Some((_, entry)) = OFFICE_DOCUMENT_ENTRIES
+				.iter()
+				.find(|(office_extension, _)| *office_extension == extension)
+			{
+				office_document_text(&path, entry).map(Some)
+			} else if matches!(kind, ObjectKind::Text | ObjectKind::Code) {
+				plain_text(&path)
+			} else {
+				Ok(None)
+			}
+		}
+	})
+	.await??;
+
+	Ok(text
+		.map(|mut text| {
+			if text.len() > MAX_INDEXED_TEXT_LENGTH {
+				let mut end = MAX_INDEXED_TEXT_LENGTH;
+				while !text.is_char_boundary(end) {
+					end -= 1;
+				}
+				text.truncate(end);
+			}
+			text
+		})
+		.filter(|text| !text.trim().is_empty()))
+}
+
+/// plain_text reads a text file, which is skipped if it isn't valid UTF-8 as it's most likely binary.
+fn plain_text(path: &Path) -> Result<Option<String>, SearchError> {
+	let mut bytes = vec![];
+	BufReader::new(File::open(path)?)
+		.take(MAX_EXTRACTED_FILE_SIZE)
+		.read_to_end(&mut bytes)?;
+
+	Ok(String::from_utf8(bytes).ok())
+}
+
+/// office_document_text reads the text of the XML entries of a zipped office document starting with `entry`,
+/// like all the slides of a presentation.
+fn office_document_text(path: &Path, entry: &str) -> Result<String, SearchError> {
+	let mut archive = zip::ZipArchive::new(BufReader::new(File::open(path)?))?;
+
+	let mut names = archive
+		.file_names()
+		.filter(|name| name.starts_with(entry) && name.ends_with(".xml"))
+		.map(ToString::to_string)
+		.collect::<Vec<_>>();
+	// Slides are numbered, so they're sorted to keep their order
+	names.sort_by_key(|name| (name.len(), name.clone()));
+
+	// The entries are compressed, so they're read up to a limit instead of trusting the size they claim
+	let mut remaining = MAX_EXTRACTED_FILE_SIZE;
+	let mut text = String::new();
+	for name in names {
+		if remaining == 0 || text.len() >= MAX_INDEXED_TEXT_LENGTH {
+			break;
+		}
+
+		let mut xml = vec![];
+		archive
+			.by_name(&name)?
+			.take(remaining)
+			.read_to_end(&mut xml)?;
+		remaining -= xml.len() as u64;
+
+		xml_text(&String::from_utf8_lossy(&xml), &mut text);
+	}
+
+	Ok(text)
+}
+
+/// xml_text appends the text nodes of an XML document to `text`, breaking lines at the end of paragraphs.
+/// Office documents split words across runs of differently formatted text, so nothing is added between other tags.
+fn xml_text(xml: &str, text: &mut String) {
+	let mut rest = xml;
+
+	while let Some(tag_start) = rest.find('<') {
+		push_unescaped(&rest[..tag_start], text);
+
+		let Some(tag_end) = rest[tag_start..].find('>') else {
+			return;
+		};
+		let tag = &rest[tag_start + 1..tag_start + tag_end];
+
+		// The local name of the paragraph, table cell and shared string elements of the OOXML and ODF formats
+		let name = tag
+			.trim_start_matches('/')
+			.split_whitespace()
+			.next()
+			.map(|name| name.trim_end_matches('/'));
+		let local_name = name.map(|name| name.rsplit(':').next().unwrap_or(name));
+		match local_name {
+			Some("p" | "h" | "si" | "tc" | "table-cell") if tag.starts_with('/') => text.push('\n'),
+			Some("tab" | "s" | "br") => text.push(' '),
+			_ => {}
+		}
+
+		rest = &rest[tag_start + tag_end + 1..];
+	}
+
+	push_unescaped(rest, text);
+}
+
+fn push_unescaped(escaped: &str, text: &mut String) {
+	if !escaped.contains('&') {
+		text.push_str(escaped);
+		return;
+	}
+
+	text.push_str(
+		&escaped
+			.replace("&lt;", "<")
+			.replace("&gt;", ">")
+			.replace("&quot;", "\"")
+			.replace("&apos;", "'")
+			.replace("&amp;", "&"),
+	);
+}
@@ -0,0 +1,308 @@
+use crate::{job::Job, library::Library, prisma::object};
+
+use sd_file_ext::kind::ObjectKind;
+
+use std::{
+	collections::{HashMap, HashSet},
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+};
+
+use once_cell::sync::Lazy;
+use tantivy::{
+	collector::{Count, TopDocs},
+	directory::MmapDirectory,
+	doc,
+	query::{QueryParser, TermQuery},
+	schema::{Field, IndexRecordOption, Schema, Value, FAST, INDEXED, STORED, TEXT},
+	Index, IndexReader, IndexWriter, ReloadPolicy, SnippetGenerator, TantivyDocument, Term,
+};
+use tokio::{fs, task::spawn_blocking};
+use tracing::error;
+use uuid::Uuid;
+
+use super::{
+	can_extract_text,
+	content_indexer_job::{ContentIndexerJob, ContentIndexerJobInit},
+	extract_text, ContentSearchHit, SearchError,
+};
+
+pub static SEARCH_INDEX_DIR_NAME: &str = "search";
+
+/// Memory the index writer of a library buffers documents in before flushing them to disk
+const WRITER_MEMORY_BYTES: usize = 15_000_000;
+
+/// ContentIndex is the search index of a library.
+pub struct ContentIndex {
+	index: Index,
+	reader: IndexReader,
+	writer: Mutex<IndexWriter>,
+	object_id: Field,
+	name: Field,
+	content: Field,
+}
+
+static CONTENT_INDEXES: Lazy<Mutex<HashMap<Uuid, Arc<ContentIndex>>>> = Lazy::new(Default::default);
+
+fn index_path(library: &Library) -> PathBuf {
+	library
+		.config()
+		.data_directory()
+		.join(SEARCH_INDEX_DIR_NAME)
+		.join(library.id.to_string())
+}
+
+/// content_index opens the search index of a library, creating it on first use.
+/// Only one writer can hold an index, so it's opened once and shared for the lifetime of the node.
+pub async fn content_index(library: &Library) -> Result<Arc<ContentIndex>, SearchError> {
+	if let Some(index) = CONTENT_INDEXES
+		.lock()
+		.expect("search indexes lock poisoned")
+		.get(&library.id)
+	{
+		return Ok(Arc::clone(index));
+	}
+
+	let path = index_path(library);
+	fs::create_dir_all(&path).await?;
+
+	let index = Arc::new(spawn_blocking(move || ContentIndex::open(path)).await??);
+
+	Ok(Arc::clone(
+		CONTENT_INDEXES
+			.lock()
+			.expect("search indexes lock poisoned")
+			.entry(library.id)
+			.or_insert(index),
+	))
+}
+
+/// forget_deleted_objects removes the objects among `object_ids` which were deleted from the search index of a
+/// library. Failing to do so is logged, as a stale hit is dropped when its object isn't found.
+pub async fn forget_deleted_objects(library: &Library, object_ids: Vec<i32>) {
+	if object_ids.is_empty() {
+		return;
+	}
+
+	let existing = match library
+		.db
+		.object()
+		.find_many(vec![object::id::in_vec(object_ids.clone())])
+		.select(object::select!({ id }))
+		.exec()
+		.await
+	{
+		Ok(objects) => objects
+			.into_iter()
+			.map(|object| object.id)
+			.collect::<HashSet<_>>(),
+		Err(e) => {
+			error!("Failed to find the deleted objects to remove from the search index: {e:#?}");
+			return;
+		}
+	};
+
+	let result: Result<(), SearchError> = async {
+		let index = content_index(library).await?;
+		for object_id in object_ids.into_iter().filter(|id| !existing.contains(id)) {
+			index.remove(object_id);
+		}
+		spawn_blocking(move || index.commit()).await?
+	}
+	.await;
+
+	if let Err(e) = result {
+		error!("Failed to remove deleted objects from the search index: {e:#?}");
+	}
+}
+
+/// reindex_object_content indexes the text of an object again after its file changed, if it was indexed before.
+/// Failing to do so is logged, as the [`content_indexer_job::ContentIndexerJob`] can rebuild the index.
+pub async fn reindex_object_content(
+	library: &Library,
+	object_id: i32,
+	kind: ObjectKind,
+	path: impl AsRef<Path>,
+	name: &str,
+	extension: &str,
+) {
+	let path = path.as_ref();
+
+	let result: Result<(), SearchError> = async {
+		let index = content_index(library).await?;
+		if !can_extract_text(kind, extension) || !index.contains(object_id)? {
+			return Ok(());
+		}
+
+		match extract_text(path, kind, extension).await? {
+			Some(text) => {
+				let name = if extension.is_empty() {
+					name.to_string()
+				} else {
+					format!("{name}.{extension}")
+				};
+				index.add(object_id, &name, &text)?;
+			}
+			// The file doesn't hold any text anymore
+			None => index.remove(object_id),
+		}
+
+		spawn_blocking(move || index.commit()).await?
+	}
+	.await;
+
+	if let Err(e) = result {
+		error!(
+			"Failed to index the contents of {} again: {e:#?}",
+			path.display()
+		);
+	}
+}
+
+/// search_content returns the objects whose name or contents match `query` in the search index of a library, best match first.
+pub async fn search_content(
+	library: &Library,
+	query: String,
+	take: usize,
+	skip: usize,
+) -> Result<Vec<ContentSearchHit>, SearchError> {
+	let index = content_index(library).await?;
+
+	spawn_blocking(move || index.search(&query, take, skip)).await?
+}
+
+/// rebuild_content_index extracts the text of the objects of a location, or of the whole library, into its search index again.
+pub async fn rebuild_content_index(
+	library: &Library,
+	location_id: Option<i32>,
+) -> Result<(), SearchError> {
+	library
+		.spawn_job(Job::new(
+			ContentIndexerJobInit {
+				location_id,
+				rebuild: true,
+			},
+			ContentIndexerJob {},
+		))
+		.await;
+
+	Ok(())
+}
+
+impl ContentIndex {
+	fn open(path: PathBuf) -> Result<Self, SearchError> {
+		let mut schema = Schema::builder();
+		let object_id = schema.add_i64_field("object_id", INDEXED | STORED | FAST);
+		let name = schema.add_text_field("name", TEXT | STORED);
+		let content = schema.add_text_field("content", TEXT | STORED);
+
+		let index = Index::open_or_create(MmapDirectory::open(path)?, schema.build())?;
+		let reader = index
+			.reader_builder()
+			.reload_policy(ReloadPolicy::OnCommitWithDelay)
+			.try_into()?;
+		let writer = index.writer_with_num_threads(1, WRITER_MEMORY_BYTES)?;
+
+		Ok(Self {
+			index,
+			reader,
+			writer: Mutex::new(writer),
+			object_id,
+			name,
+			content,
+		})
+	}
+
+	fn object_term(&self, object_id: i32) -> Term {
+		Term::from_field_i64(self.object_id, object_id as i64)
+	}
+
+	/// contains tells if the contents of an object were indexed, as of the last commit.
+	pub fn contains(&self, object_id: i32) -> Result<bool, SearchError> {
+		let query = TermQuery::new(self.object_term(object_id), IndexRecordOption::Basic);
+
+		Ok(self.reader.searcher().search(&query, &Count)? > 0)
+	}
+
+	/// add replaces the indexed contents of an object, which are searchable after the next commit.
+	pub fn add(&self, object_id: i32, name: &str, content: &str) -> Result<(), SearchError> {
+		let writer = self
+			.writer
+			.lock()
+			.expect("search index writer lock poisoned");
+
+		writer.delete_term(self.object_term(object_id));
+		writer.add_document(doc!(
+			self.object_id => object_id as i64,
+			self.name => name,
+			self.content => content,
+		))?;
+
+		Ok(())
+	}
+
+	/// remove removes an object from the index on the next commit.
+	pub fn remove(&self, object_id: i32) {
+		self.writer
+			.lock()
+			.expect("search index writer lock poisoned")
+			.delete_term(self.object_term(object_id));
+	}
+
+	/// clear removes every object from the index on the next commit.
+	pub fn clear(&self) -> Result<(), SearchError> {
+		self.writer
+			.lock()
+			.expect("search index writer lock poisoned")
+			.delete_all_documents()?;
+
+		Ok(())
+	}
+
+	/// commit persists the changes made to the index, and makes them searchable.
+	pub fn commit(&self) -> Result<(), SearchError> {
+		self.writer
+			.lock()
+			.expect("search index writer lock poisoned")
+			.commit()?;
+		self.reader.reload()?;
+
+		Ok(())
+	}
+
+	/// search returns the objects whose name or contents match `query`, best match first.
+	/// The query supports the tantivy syntax, like `"exact phrase"`, `+required -excluded` and `name:report`.
+	pub fn search(
+		&self,
+		query: &str,
+		take: usize,
+		skip: usize,
+	) -> Result<Vec<ContentSearchHit>, SearchError> {
+		let searcher = self.reader.searcher();
+		let query = QueryParser::for_index(&self.index, vec![self.name, self.content])
+			.parse_query(query)?;
+		let snippets = SnippetGenerator::create(&searcher, &*query, self.content)?;
+
+		// Collecting no documents isn't allowed
+		let take = take.max(1);
+
+		searcher
+			.search(&query, &TopDocs::with_limit(take).and_offset(skip))?
+			.into_iter()
+			.filter_map(|(score, address)| {
+				let document = match searcher.doc::<TantivyDocument>(address) {
+					Ok(document) => document,
+					Err(e) => return Some(Err(e.into())),
+				};
+
+				let snippet = snippets.snippet_from_doc(&document);
+
+				Some(Ok(ContentSearchHit {
+					object_id: document.get_first(self.object_id)?.as_i64()? as i32,
+					score,
+					snippet: (!snippet.is_empty()).then(|| snippet.to_html()),
+				}))
+			})
+			.collect()
+	}
+}
@@ -0,0 +1,111 @@
+//! Full-text search over the contents of documents, plain text and code.
+//! Every library has its own tantivy index in the data directory of the node, where the text extracted from
+//! an object is indexed once, whichever of its file paths it was read from.
+//! The content index needs the `content-search` feature, the path and saved searches are always available.
+
+use rspc::ErrorCode;
+use serde::Serialize;
+#[cfg(feature = "content-search")]
+use tantivy::{directory::error::OpenDirectoryError, query::QueryParserError, TantivyError};
+use thiserror::Error;
+use tokio::io;
+
+#[cfg(feature = "content-search")]
+pub mod content_indexer_job;
+#[cfg(feature = "content-search")]
+mod extract;
+#[cfg(feature = "content-search")]
+mod index;
+pub mod paths;
+pub mod saved;
+
+#[cfg(feature = "content-search")]
+pub use extract::*;
+#[cfg(feature = "content-search")]
+pub use index::*;
+#[cfg(not(feature = "content-search"))]
+pub use unsupported::*;
+
+#[derive(Error, Debug)]
+pub enum SearchError {
+	#[cfg(feature = "content-search")]
+	#[error("search index error: {0}")]
+	Index(#[from] TantivyError),
+	#[cfg(feature = "content-search")]
+	#[error("failed to open the search index: {0}")]
+	OpenDirectory(#[from] OpenDirectoryError),
+	#[cfg(feature = "content-search")]
+	#[error("invalid search query: {0}")]
+	InvalidQuery(#[from] QueryParserError),
+	#[error("filesystem error: {0}")]
+	IO(#[from] io::Error),
+	#[error("failed to join search task: {0}")]
+	Join(#[from] tokio::task::JoinError),
+	#[error("failed to extract text from a PDF: {0}")]
+	Pdf(String),
+	#[error("failed to read an office document: {0}")]
+	Zip(#[from] zip::result::ZipError),
+	#[cfg(not(feature = "content-search"))]
+	#[error("the node was built without the `content-search` feature")]
+	Unsupported,
+}
+
+impl From<SearchError> for rspc::Error {
+	fn from(error: SearchError) -> Self {
+		#[cfg(feature = "content-search")]
+		if let SearchError::InvalidQuery(_) = error {
+			return rspc::Error::with_cause(ErrorCode::BadRequest, error.to_string(), error);
+		}
+
+		rspc::Error::with_cause(ErrorCode::InternalServerError, error.to_string(), error)
+	}
+}
+
+/// ContentSearchHit is an object whose name or contents match a search.
+#[derive(Serialize, Debug)]
+pub struct ContentSearchHit {
+	pub object_id: i32,
+	pub score: f32,
+	/// snippet is the best matching excerpt of the contents, with the matched words in `<b>` tags
+	pub snippet: Option<String>,
+}
+
+/// Without the `content-search` feature there's no search index, so there's nothing to search or keep up to date.
+#[cfg(not(feature = "content-search"))]
+mod unsupported {
+	use crate::library::Library;
+
+	use sd_file_ext::kind::ObjectKind;
+
+	use std::path::Path;
+
+	use super::{ContentSearchHit, SearchError};
+
+	pub async fn forget_deleted_objects(_library: &Library, _object_ids: Vec<i32>) {}
+
+	pub async fn reindex_object_content(
+		_library: &Library,
+		_object_id: i32,
+		_kind: ObjectKind,
+		_path: impl AsRef<Path>,
+		_name: &str,
+		_extension: &str,
+	) {
+	}
+
+	pub async fn search_content(
+		_library: &Library,
+		_query: String,
+		_take: usize,
+		_skip: usize,
+	) -> Result<Vec<ContentSearchHit>, SearchError> {
+		Err(SearchError::Unsupported)
+	}
+
+	pub async fn rebuild_content_index(
+		_library: &Library,
+		_location_id: Option<i32>,
+	) -> Result<(), SearchError> {
+		Err(SearchError::Unsupported)
+	}
+}
@@ -0,0 +1,253 @@
+use crate::{
+	location::file_path_helper::file_path_with_object,
+	prisma::{file_path, PrismaClient},
+};
+
+use std::{
+	cmp::Ordering,
+	collections::{HashMap, HashSet},
+};
+
+use prisma_client_rust::{PrismaValue, QueryError, Raw};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+
+/// Candidates are prefiltered by the database and ranked in memory, so only this many of the ones sharing the most
+/// trigrams with the query are ranked
+const MAX_CANDIDATES: usize = 5_000;
+
+/// How many candidates are fetched with each query, so their ids stay under the variables limit of SQLite
+const CANDIDATES_CHUNK_SIZE: usize = 500;
+
+/// Longer queries only prefilter by their first trigrams, the others still count when ranking
+const MAX_PREFILTER_TRIGRAMS: usize = 16;
+
+/// Matches of the name of a file are worth more than matches of the directories it's in
+const PATH_MATCH_WEIGHT: f32 = 0.5;
+
+/// PathSearchCursor is where a page of results ends, the next page starts right after it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Type)]
+pub struct PathSearchCursor {
+	pub score: f32,
+	pub location_id: i32,
+	pub id: i32,
+}
+
+impl PathSearchCursor {
+	/// order sorts results best score first, and then by their id so pages never overlap.
+	fn order(&self, other: &Self) -> Ordering {
+		other
+			.score
+			.total_cmp(&self.score)
+			.then((self.location_id, self.id).cmp(&(other.location_id, other.id)))
+	}
+}
+
+#[derive(Serialize, Debug, Type)]
+pub struct PathSearchResult {
+	pub item: file_path_with_object::Data,
+	pub score: f32,
+}
+
+/// trigrams are the lowercased windows of three characters of a text.
+fn trigrams(text: &str) -> HashSet<String> {
+	let chars = text.to_lowercase().chars().collect::<Vec<_>>();
+
+	chars
+		.windows(3)
+		.map(|window| window.iter().collect())
+		.collect()
+}
+
+/// fuzzy_score is how well `candidate` matches the query, from 0 for no match up to 2 for an exact match.
+/// It's the share of the trigrams of the query found in the candidate, with bonuses when it holds the whole query.
+fn fuzzy_score(query: &str, query_trigrams: &HashSet<String>, candidate: &str) -> f32 {
+	let candidate = candidate.to_lowercase();
+
+	if candidate == query {
+		return 2.0;
+	}
+
+	let mut score = if query_trigrams.is_empty() {
+		0.0
+	} else {
+		let candidate_trigrams = trigrams(&candidate);
+		query_trigrams.intersection(&candidate_trigrams).count() as f32
+			/ query_trigrams.len() as f32
+	};
+
+	if candidate.starts_with(query) {
+		score += 0.75;
+	} else if candidate.contains(query) {
+		score += 0.5;
+	}
+
+	score
+}
+
+#[derive(Deserialize)]
+struct CandidateRow {
+	location_id: i32,
+	id: i32,
+}
+
+/// prefilter_candidates returns the file paths whose name, directories or object name contain any of the needles,
+/// up to [`MAX_CANDIDATES`] of them, the ones containing the most needles first and then the most recently modified.
+/// Also tells if some were left out.
+async fn prefilter_candidates(
+	db: &PrismaClient,
+	needles: &[String],
+	location_id: Option<i32>,
+) -> Result<(Vec<file_path_with_object::Data>, bool), QueryError> {
+	let matches = needles
+		.iter()
+		.map(|_| {
+			"(instr(lower(fp.name), {}) > 0 \
+			OR instr(lower(fp.materialized_path), {}) > 0 \
+			OR instr(lower(coalesce(o.name, '')), {}) > 0)"
+		})
+		.collect::<Vec<_>>()
+		.join(" + ");
+
+	let mut params = needles
+		.iter()
+		.flat_map(|needle| [needle, needle, needle])
+		.map(|needle| PrismaValue::String(needle.clone()))
+		.collect::<Vec<_>>();
+
+	let location_filter = if let Some(location_id) = location_id {
+		params.push(PrismaValue::Int(location_id as i64));
+		" WHERE fp.location_id = {}"
+	} else {
+		""
+	};
+
+	let mut rows = db
+		._query_raw::<CandidateRow>(Raw::new(
+			&format!(
+				"SELECT location_id, id FROM ( \
+					SELECT fp.location_id, fp.id, fp.date_modified, {matches} AS matches \
+					FROM file_path fp LEFT JOIN object o ON o.id = fp.object_id{location_filter} \
+				) WHERE matches > 0 \
+				ORDER BY matches DESC, date_modified DESC \
+				LIMIT {}",
+				MAX_CANDIDATES + 1
+			),
+			params,
+		))
+		.exec()
+		.await?;
+
+	let truncated = rows.len() > MAX_CANDIDATES;
+	rows.truncate(MAX_CANDIDATES);
+
+	let mut ids_by_location = HashMap::<i32, Vec<i32>>::new();
+	for row in rows {
+		ids_by_location
+			.entry(row.location_id)
+			.or_default()
+			.push(row.id);
+	}
+
+	let mut candidates = vec![];
+	for (location_id, ids) in ids_by_location {
+		for chunk in ids.chunks(CANDIDATES_CHUNK_SIZE) {
+			candidates.extend(
+				db.file_path()
+					.find_many(vec![
+						file_path::location_id::equals(location_id),
+						file_path::id::in_vec(chunk.to_vec()),
+					])
+					.include(file_path_with_object::include())
+					.exec()
+					.await?,
+			);
+		}
+	}
+
+	Ok((candidates, truncated))
+}
+
+/// search_paths returns the file paths whose name, directories or object name look like `query`, best match first.
+/// Typos are tolerated as matches are scored by the trigrams they share with the query.
+/// Also tells if only the best [`MAX_CANDIDATES`] file paths found by the database were ranked.
+pub async fn search_paths(
+	db: &PrismaClient,
+	query: &str,
+	location_id: Option<i32>,
+	take: usize,
+	cursor: Option<PathSearchCursor>,
+) -> Result<(Vec<PathSearchResult>, Option<PathSearchCursor>, bool), QueryError> {
+	let take = take.max(1);
+	let query = query.trim().to_lowercase();
+	if query.is_empty() {
+		return Ok((vec![], None, false));
+	}
+
+	let query_trigrams = trigrams(&query);
+
+	// Queries too short to have trigrams must be contained as is
+	let needles = if query_trigrams.is_empty() {
+		vec![query.clone()]
+	} else {
+		// Sorted so the same query always prefilters by the same trigrams
+		let mut needles = query_trigrams.iter().cloned().collect::<Vec<_>>();
+		needles.sort();
+		needles.truncate(MAX_PREFILTER_TRIGRAMS);
+		needles
+	};
+
+	let (candidates, truncated) = prefilter_candidates(db, &needles, location_id).await?;
+
+	let mut results = candidates
+		.into_iter()
+		.filter_map(|file_path| {
+			let name = if file_path.extension.is_empty() {
+				file_path.name.clone()
+			} else {
+				format!("{}.{}", file_path.name, file_path.extension)
+			};
+
+			let score = [
+				fuzzy_score(&query, &query_trigrams, &name),
+				fuzzy_score(&query, &query_trigrams, &file_path.materialized_path)
+					* PATH_MATCH_WEIGHT,
+				file_path
+					.object
+					.as_ref()
+					.and_then(|object| object.name.as_deref())
+					.map_or(0.0, |object_name| {
+						fuzzy_score(&query, &query_trigrams, object_name)
+					}),
+			]
+			.into_iter()
+			.fold(0.0, f32::max);
+
+			let position = PathSearchCursor {
+				score,
+				location_id: file_path.location_id,
+				id: file_path.id,
+			};
+
+			(score > 0.0 && cursor.map_or(true, |cursor| cursor.order(&position) == Ordering::Less))
+				.then_some((position, file_path))
+		})
+		.collect::<Vec<_>>();
+
+	results.sort_by(|(a, _), (b, _)| a.order(b));
+
+	let next_cursor = (results.len() > take).then(|| results[take - 1].0);
+	results.truncate(take);
+
+	Ok((
+		results
+			.into_iter()
+			.map(|(position, item)| PathSearchResult {
+				item,
+				score: position.score,
+			})
+			.collect(),
+		next_cursor,
+		truncated,
+	))
+}
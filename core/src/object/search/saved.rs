@@ -0,0 +1,241 @@
+//! Saved searches store a filter over the objects of a library, so it can be run again later.
+//! The stored filter references locations and tags by `pub_id`, so saved searches sync between nodes.
+
+use crate::{
+	api::{utils::InvalidateOperationEvent, CoreEvent},
+	library::LibraryManager,
+	prisma::{file_path, location, object, saved_search, tag, tag_on_object, PrismaClient},
+};
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::QueryError;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::sync::broadcast;
+use tracing::error;
+
+/// The invalidated queries which mean objects were created, changed or deleted
+const OBJECT_CHANGING_QUERIES: [&str; 2] = ["locations.getExplorerData", "tags.getExplorerData"];
+
+/// SearchFilter is what the objects found by a saved search must match, every field which is set must match.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Type)]
+pub struct SearchFilter {
+	/// kinds are the `ObjectKind`s the objects can be of
+	#[serde(default)]
+	pub kinds: Vec<i32>,
+	/// tag_ids are tags of which the objects must have at least one
+	#[serde(default)]
+	pub tag_ids: Vec<i32>,
+	/// location_ids are locations of which the objects must have a file in at least one
+	#[serde(default)]
+	pub location_ids: Vec<i32>,
+	pub created_after: Option<DateTime<Utc>>,
+	pub created_before: Option<DateTime<Utc>>,
+	pub modified_after: Option<DateTime<Utc>>,
+	pub modified_before: Option<DateTime<Utc>>,
+	pub min_size: Option<u64>,
+	pub max_size: Option<u64>,
+}
+
+/// StoredSearchFilter is how a `SearchFilter` is stored, with the local ids of tags and locations replaced by their `pub_id`.
+#[derive(Serialize, Deserialize, Default)]
+struct StoredSearchFilter {
+	#[serde(default)]
+	kinds: Vec<i32>,
+	#[serde(default)]
+	tag_pub_ids: Vec<Vec<u8>>,
+	#[serde(default)]
+	location_pub_ids: Vec<Vec<u8>>,
+	created_after: Option<DateTime<Utc>>,
+	created_before: Option<DateTime<Utc>>,
+	modified_after: Option<DateTime<Utc>>,
+	modified_before: Option<DateTime<Utc>>,
+	min_size: Option<u64>,
+	max_size: Option<u64>,
+}
+
+impl SearchFilter {
+	/// to_stored returns the JSON stored in the database for this filter.
+	pub async fn to_stored(&self, db: &PrismaClient) -> Result<String, QueryError> {
+		let tag_pub_ids = db
+			.tag()
+			.find_many(vec![tag::id::in_vec(self.tag_ids.clone())])
+			.select(tag::select!({ pub_id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|tag| tag.pub_id)
+			.collect();
+
+		let location_pub_ids = db
+			.location()
+			.find_many(vec![location::id::in_vec(self.location_ids.clone())])
+			.select(location::select!({ pub_id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|location| location.pub_id)
+			.collect();
+
+		Ok(serde_json::to_string(&StoredSearchFilter {
+			kinds: self.kinds.clone(),
+			tag_pub_ids,
+			location_pub_ids,
+			created_after: self.created_after,
+			created_before: self.created_before,
+			modified_after: self.modified_after,
+			modified_before: self.modified_before,
+			min_size: self.min_size,
+			max_size: self.max_size,
+		})
+		.expect("search filters are always serializable"))
+	}
+
+	/// from_stored reads a filter stored by `to_stored`. Tags and locations which don't exist on this node are left out.
+	pub async fn from_stored(stored: &str, db: &PrismaClient) -> Result<Self, QueryError> {
+		// Filters synced from a node running a newer version may not be readable, they match everything
+		let stored = serde_json::from_str::<StoredSearchFilter>(stored).unwrap_or_default();
+
+		let tag_ids = db
+			.tag()
+			.find_many(vec![tag::pub_id::in_vec(stored.tag_pub_ids)])
+			.select(tag::select!({ id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|tag| tag.id)
+			.collect();
+
+		let location_ids = db
+			.location()
+			.find_many(vec![location::pub_id::in_vec(stored.location_pub_ids)])
+			.select(location::select!({ id }))
+			.exec()
+			.await?
+			.into_iter()
+			.map(|location| location.id)
+			.collect();
+
+		Ok(Self {
+			kinds: stored.kinds,
+			tag_ids,
+			location_ids,
+			created_after: stored.created_after,
+			created_before: stored.created_before,
+			modified_after: stored.modified_after,
+			modified_before: stored.modified_before,
+			min_size: stored.min_size,
+			max_size: stored.max_size,
+		})
+	}
+
+	/// params are the conditions on objects of this filter, except for their size which must be checked with `matches_size`.
+	pub fn params(&self) -> Vec<object::WhereParam> {
+		let mut params = vec![];
+
+		if !self.kinds.is_empty() {
+			params.push(object::kind::in_vec(self.kinds.clone()));
+		}
+		if !self.tag_ids.is_empty() {
+			params.push(object::tags::some(vec![tag_on_object::tag_id::in_vec(
+				self.tag_ids.clone(),
+			)]));
+		}
+		if !self.location_ids.is_empty() {
+			params.push(object::file_paths::some(vec![
+				file_path::location_id::in_vec(self.location_ids.clone()),
+			]));
+		}
+		if let Some(date) = self.created_after {
+			params.push(object::date_created::gte(date.into()));
+		}
+		if let Some(date) = self.created_before {
+			params.push(object::date_created::lt(date.into()));
+		}
+		if let Some(date) = self.modified_after {
+			params.push(object::date_modified::gte(date.into()));
+		}
+		if let Some(date) = self.modified_before {
+			params.push(object::date_modified::lt(date.into()));
+		}
+
+		params
+	}
+
+	/// matches_size checks the size of an object, which is stored as a string so it can't be filtered by the database.
+	pub fn matches_size(&self, size_in_bytes: &str) -> bool {
+		if self.min_size.is_none() && self.max_size.is_none() {
+			return true;
+		}
+
+		let Ok(size) = size_in_bytes.parse::<u64>() else {
+			return false;
+		};
+
+		self.min_size.map_or(true, |min| size >= min)
+			&& self.max_size.map_or(true, |max| size <= max)
+	}
+}
+
+async fn has_smart_searches(library_manager: &LibraryManager) -> bool {
+	for library in library_manager.get_all_libraries().await {
+		match library
+			.db
+			.saved_search()
+			.count(vec![saved_search::smart::equals(true)])
+			.exec()
+			.await
+		{
+			Ok(0) => {}
+			Ok(_) => return true,
+			Err(e) => error!("Failed to count the smart searches of a library: {e:#?}"),
+		}
+	}
+
+	false
+}
+
+/// spawn_smart_search_invalidator tells clients to run their saved searches again whenever objects change,
+/// as long as a library has smart searches.
+pub(crate) fn spawn_smart_search_invalidator(
+	library_manager: Arc<LibraryManager>,
+	event_bus_tx: broadcast::Sender<CoreEvent>,
+) {
+	let mut event_bus_rx = event_bus_tx.subscribe();
+
+	tokio::spawn(async move {
+		loop {
+			// The searches of the library whose objects changed are run again
+			let library_id = match event_bus_rx.recv().await {
+				Ok(
+					CoreEvent::InvalidateOperation(op)
+					| CoreEvent::InvalidateOperationDebounced(op),
+				) if OBJECT_CHANGING_QUERIES.contains(&op.key()) => op.library_id(),
+				// Missed events could have changed any object
+				Err(broadcast::error::RecvError::Lagged(_)) => None,
+				Ok(_) => continue,
+				Err(broadcast::error::RecvError::Closed) => break,
+			};
+
+			if has_smart_searches(&library_manager).await {
+				let op = match library_id {
+					Some(library_id) => InvalidateOperationEvent::dangerously_create_for_library(
+						library_id,
+						"search.saved.run",
+						serde_json::Value::Null,
+					),
+					None => InvalidateOperationEvent::dangerously_create(
+						"search.saved.run",
+						serde_json::Value::Null,
+					),
+				};
+
+				event_bus_tx
+					.send(CoreEvent::InvalidateOperationDebounced(op))
+					.ok();
+			}
+		}
+	});
+}
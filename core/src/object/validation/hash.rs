@@ -1,4 +1,7 @@
+use crate::util::rate_limit::limit_read;
+
 use blake3::Hasher;
+use sha2::{Digest, Sha256};
 use std::path::Path;
 use tokio::{
 	fs::File,
@@ -12,13 +15,33 @@ pub async fn file_checksum(path: impl AsRef<Path>) -> Result<String, io::Error>
 	let mut context = Hasher::new();
 	let mut buffer = vec![0; BLOCK_LEN].into_boxed_slice();
 	loop {
+		// A read can return less than a block before the end of the file, which is only reached on 0
 		let read_count = reader.read(&mut buffer).await?;
-		context.update(&buffer[..read_count]);
-		if read_count != BLOCK_LEN {
+		if read_count == 0 {
 			break;
 		}
+		limit_read(read_count as u64).await;
+		context.update(&buffer[..read_count]);
 	}
 	let hex = context.finalize().to_hex();
 
 	Ok(hex.to_string())
 }
+
+/// file_sha256 digests the full contents of a file with SHA-256, matching the output of tools like `sha256sum`.
+pub async fn file_sha256(path: impl AsRef<Path>) -> Result<String, io::Error> {
+	let mut reader = File::open(path).await?;
+	let mut context = Sha256::new();
+	let mut buffer = vec![0; BLOCK_LEN].into_boxed_slice();
+	loop {
+		// A read can return less than a block before the end of the file, which is only reached on 0
+		let read_count = reader.read(&mut buffer).await?;
+		if read_count == 0 {
+			break;
+		}
+		limit_read(read_count as u64).await;
+		context.update(&buffer[..read_count]);
+	}
+
+	Ok(format!("{:x}", context.finalize()))
+}
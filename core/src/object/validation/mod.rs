@@ -1,2 +1,3 @@
 pub mod hash;
 pub mod validator_job;
+pub mod verifier_job;
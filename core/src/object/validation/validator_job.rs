@@ -1,15 +1,19 @@
 use crate::{
-	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	job::{
+		JobError, JobReportUpdate, JobResult, JobState, RetryPolicy, StatefulJob, WorkerContext,
+	},
 	library::Library,
 	location::file_path_helper::file_path_for_object_validator,
 	prisma::{file_path, location},
 	sync,
+	util::resources::resource_limiter,
 };
 
 use std::{collections::VecDeque, path::PathBuf};
 
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use tokio::fs;
 use tracing::info;
 
 use super::hash::file_checksum;
@@ -46,6 +50,11 @@ impl StatefulJob for ObjectValidatorJob {
 		VALIDATOR_JOB_NAME
 	}
 
+	fn retry_policy(&self) -> RetryPolicy {
+		// A step only sets the checksum of its file, which is the same when it's computed again
+		RetryPolicy::default()
+	}
+
 	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
 		let Library { db, .. } = &ctx.library;
 
@@ -94,7 +103,12 @@ impl StatefulJob for ObjectValidatorJob {
 		// we can also compare old and new checksums here
 		// This if is just to make sure, we already queried objects where integrity_checksum is null
 		if file_path.integrity_checksum.is_none() {
-			let checksum = file_checksum(data.root_path.join(&file_path.materialized_path)).await?;
+			let path = data.root_path.join(&file_path.materialized_path);
+			let checksum = {
+				let _permit = resource_limiter().open_file().await;
+				file_checksum(&path).await?
+			};
+			let size = fs::metadata(&path).await?.len();
 
 			sync.write_op(
 				db,
@@ -114,6 +128,11 @@ impl StatefulJob for ObjectValidatorJob {
 				),
 			)
 			.await?;
+
+			ctx.progress(vec![
+				JobReportUpdate::ItemsProcessed(1),
+				JobReportUpdate::BytesRead(size),
+			]);
 		}
 
 		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
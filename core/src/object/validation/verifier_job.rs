@@ -0,0 +1,289 @@
+use crate::{
+	invalidate_query,
+	job::{JobError, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext},
+	library::Library,
+	location::{file_path_helper::file_path_for_object_verifier, find_location, LocationError},
+	object::cas::{generate_cas_id, CasIdAlgorithm},
+	prisma::{file_path, location, object},
+	sync,
+	util::resources::resource_limiter,
+};
+
+use std::{
+	collections::VecDeque,
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use int_enum::IntEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{fs, io};
+use tracing::{info, warn};
+
+use super::hash::file_checksum;
+
+pub const VERIFIER_JOB_NAME: &str = "object_verifier";
+
+/// ObjectVerifierJob re-reads every identified file of a location, checking its contents still match the cas_id
+/// and the integrity checksum of its object, to detect files which were silently corrupted on disk.
+/// Files whose size or modification date changed were edited, so their checksums are updated instead.
+pub struct ObjectVerifierJob {}
+
+#[derive(Serialize, Deserialize, Debug, Hash)]
+pub struct ObjectVerifierJobInit {
+	pub location_id: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ObjectVerifierJobState {
+	root_path: PathBuf,
+	report: ObjectVerifierReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ObjectVerifierReport {
+	location_id: i32,
+	verified_files: usize,
+	/// Files whose contents don't match what was stored when they were identified or last verified
+	mismatched_files: Vec<PathBuf>,
+	/// Files which were indexed but couldn't be found on disk anymore
+	missing_files: Vec<PathBuf>,
+	/// Files which were modified since they were identified or last verified, whose checksums were updated
+	#[serde(default)]
+	updated_files: Vec<PathBuf>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ObjectVerifierJob {
+	type Init = ObjectVerifierJobInit;
+	type Data = ObjectVerifierJobState;
+	type Step = file_path_for_object_verifier::Data;
+
+	fn name(&self) -> &'static str {
+		VERIFIER_JOB_NAME
+	}
+
+	async fn init(&self, ctx: WorkerContext, state: &mut JobState<Self>) -> Result<(), JobError> {
+		let location_id = state.init.location_id;
+
+		let location = find_location(&ctx.library, location_id)
+			.select(location::select!({ path }))
+			.exec()
+			.await?
+			.ok_or(LocationError::IdNotFound(location_id))?;
+
+		state.steps = ctx
+			.library
+			.db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(location_id),
+				file_path::is_dir::equals(false),
+				file_path::cas_id::not(None),
+				file_path::object_id::not(None),
+			])
+			.select(file_path_for_object_verifier::select())
+			.exec()
+			.await?
+			.into_iter()
+			.collect::<VecDeque<_>>();
+
+		state.data = Some(ObjectVerifierJobState {
+			root_path: location.path.into(),
+			report: ObjectVerifierReport {
+				location_id,
+				..Default::default()
+			},
+		});
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let file_path = &state.steps[0];
+		let data = state.data.as_mut().expect("fatal: missing job state");
+
+		let (Some(cas_id), Some(object)) = (&file_path.cas_id, &file_path.object) else {
+			return Ok(());
+		};
+
+		let path = data.root_path.join(&file_path.materialized_path);
+
+		let checks = async {
+			let metadata = fs::metadata(&path).await?;
+			let _permit = resource_limiter().open_file().await;
+			let checks = verify_file(&path, metadata.len(), file_path.cas_id_algorithm).await?;
+			Ok::<_, io::Error>((metadata, checks))
+		}
+		.await;
+
+		let (metadata, (current_cas_id, checksum)) = match checks {
+			Ok(checks) => {
+				ctx.progress(vec![
+					JobReportUpdate::ItemsProcessed(1),
+					JobReportUpdate::BytesRead(checks.0.len()),
+				]);
+				checks
+			}
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {
+				warn!("File to verify is missing: {}", path.display());
+				data.report.missing_files.push(path);
+				return Ok(());
+			}
+			Err(e) => return Err(e.into()),
+		};
+
+		// Files are only corrupted when their contents change without them being written to
+		let modified_at = DateTime::<Utc>::from(metadata.modified()?);
+		let was_modified = modified_at > file_path.date_modified
+			|| object.size_in_bytes.parse::<u64>().ok() != Some(metadata.len());
+
+		let matches_integrity_checksum = object
+			.integrity_checksum
+			.as_ref()
+			.map_or(true, |integrity_checksum| integrity_checksum == &checksum);
+
+		if was_modified {
+			info!(
+				"File was modified since it was last verified, updating its checksums: {}",
+				path.display()
+			);
+
+			let Library { db, sync, .. } = &ctx.library;
+
+			db.file_path()
+				.update(
+					file_path::location_id_id(file_path.location_id, file_path.id),
+					vec![
+						file_path::cas_id::set(Some(current_cas_id)),
+						file_path::date_modified::set(modified_at.into()),
+					],
+				)
+				.exec()
+				.await?;
+
+			let size = metadata.len().to_string();
+			sync.write_ops(
+				db,
+				(
+					vec![
+						sync.shared_update(
+							sync::object::SyncId {
+								pub_id: object.pub_id.clone(),
+							},
+							"integrity_checksum",
+							json!(&checksum),
+						),
+						sync.shared_update(
+							sync::object::SyncId {
+								pub_id: object.pub_id.clone(),
+							},
+							"size_in_bytes",
+							json!(&size),
+						),
+					],
+					db.object().update(
+						object::id::equals(object.id),
+						vec![
+							object::integrity_checksum::set(Some(checksum)),
+							object::size_in_bytes::set(size),
+						],
+					),
+				),
+			)
+			.await?;
+
+			data.report.updated_files.push(path);
+		} else if &current_cas_id != cas_id || !matches_integrity_checksum {
+			warn!(
+				"File doesn't match its stored checksums, it may be corrupted: {}",
+				path.display()
+			);
+			data.report.mismatched_files.push(path);
+		} else {
+			let Library { db, sync, .. } = &ctx.library;
+
+			sync.write_op(
+				db,
+				sync.shared_update(
+					sync::object::SyncId {
+						pub_id: object.pub_id.clone(),
+					},
+					"integrity_checksum",
+					json!(&checksum),
+				),
+				db.object().update(
+					object::id::equals(object.id),
+					vec![object::integrity_checksum::set(Some(checksum))],
+				),
+			)
+			.await?;
+
+			// Only this node's copy was verified, so the date isn't synced
+			db.object()
+				.update(
+					object::id::equals(object.id),
+					vec![object::last_verified_at::set(Some(Utc::now().into()))],
+				)
+				.exec()
+				.await?;
+
+			data.report.verified_files += 1;
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		Ok(())
+	}
+
+	async fn finalize(&mut self, ctx: WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = state
+			.data
+			.as_ref()
+			.expect("critical error: missing data on job state");
+
+		info!(
+			"finalizing verifier job at {}: {:?}",
+			data.root_path.display(),
+			data.report
+		);
+
+		if data.report.verified_files > 0 {
+			invalidate_query!(ctx.library, "locations.getExplorerData");
+		}
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
+
+/// verify_file regenerates the cas_id of a file with the algorithm it was identified with, and its full checksum.
+async fn verify_file(
+	path: impl AsRef<Path>,
+	size: u64,
+	cas_id_algorithm: Option<i32>,
+) -> Result<(String, String), io::Error> {
+	let path = path.as_ref();
+
+	// File paths identified before the algorithm was recorded were sampled
+	let algorithm = cas_id_algorithm
+		.and_then(|algorithm| CasIdAlgorithm::from_int(algorithm).ok())
+		.unwrap_or_default();
+
+	let checksum = file_checksum(path).await?;
+	let cas_id = match algorithm {
+		// The full file blake3 is the checksum itself, so it's only read once
+		CasIdAlgorithm::Blake3 => checksum.clone(),
+		algorithm => generate_cas_id(path, size, algorithm).await?,
+	};
+
+	Ok((cas_id, checksum))
+}
@@ -0,0 +1,293 @@
+//! File versioning keeps the contents a file had every time it changed, in a content addressed store in the
+//! data directory of the node, so a file of a versioned location, or tagged with a versioned tag, can be
+//! restored to a previous state. The current contents are snapshotted as well, which is what makes the
+//! previous ones available once the file changes again.
+
+use crate::{
+	invalidate_query,
+	library::{FileVersionRetention, Library, LibraryManager},
+	object::validation::hash::file_checksum,
+	prisma::{file_path, file_version, location, object, tag, tag_on_object},
+};
+
+use std::{
+	collections::{HashMap, HashSet},
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use chrono::Utc;
+use prisma_client_rust::{Direction, QueryError};
+use rspc::ErrorCode;
+use thiserror::Error;
+use tokio::{fs, io, time::interval};
+use tracing::{error, info};
+use uuid::Uuid;
+
+/// FILE_VERSIONS_DIR_NAME is the directory in the node data directory where the versions of every library are stored.
+pub const FILE_VERSIONS_DIR_NAME: &str = "versions";
+
+/// How often the versions past the retention of their library are pruned
+const FILE_VERSION_PRUNE_TICK: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Error, Debug)]
+pub enum FileVersionError {
+	#[error("file version not found (id: {0})")]
+	NotFound(i32),
+	#[error("object has no file on this node (id: {0})")]
+	NoLocalFile(i32),
+	#[error("database error: {0}")]
+	Database(#[from] QueryError),
+	#[error("filesystem error: {0}")]
+	IO(#[from] io::Error),
+}
+
+impl From<FileVersionError> for rspc::Error {
+	fn from(error: FileVersionError) -> Self {
+		let code = match error {
+			FileVersionError::NotFound(_) | FileVersionError::NoLocalFile(_) => ErrorCode::NotFound,
+			_ => ErrorCode::InternalServerError,
+		};
+
+		rspc::Error::with_cause(code, error.to_string(), error)
+	}
+}
+
+fn version_store(library: &Library) -> PathBuf {
+	library
+		.config()
+		.data_directory()
+		.join(FILE_VERSIONS_DIR_NAME)
+		.join(library.id.to_string())
+}
+
+/// version_path is where the contents with `checksum` are stored, sharded by the first byte of the checksum.
+fn version_path(store: impl AsRef<Path>, checksum: &str) -> PathBuf {
+	store.as_ref().join(&checksum[..2]).join(checksum)
+}
+
+/// is_versioned tells if changes to an object must be snapshotted, which is the case if its location
+/// or one of its tags has versioning enabled.
+pub async fn is_versioned(
+	library: &Library,
+	location_versioning: bool,
+	object_id: i32,
+) -> Result<bool, QueryError> {
+	if location_versioning {
+		return Ok(true);
+	}
+
+	Ok(library
+		.db
+		.tag()
+		.count(vec![
+			tag::file_versioning::equals(true),
+			tag::tag_objects::some(vec![tag_on_object::object_id::equals(object_id)]),
+		])
+		.exec()
+		.await?
+		> 0)
+}
+
+/// snapshot_file_version stores the current contents of the file at `path` as a version of the object.
+/// Contents which are already stored for the object only have the date of their version bumped.
+pub async fn snapshot_file_version(
+	library: &Library,
+	object_id: i32,
+	path: impl AsRef<Path>,
+) -> Result<file_version::Data, FileVersionError> {
+	let store = version_store(library);
+	fs::create_dir_all(&store).await?;
+
+	// The copy is hashed instead of the file itself, so the checksum matches what's stored even if the file changes meanwhile
+	let temp_path = store.join(format!("{}.tmp", Uuid::new_v4()));
+	let size = fs::copy(path, &temp_path).await?;
+	let checksum = match file_checksum(&temp_path).await {
+		Ok(checksum) => checksum,
+		Err(e) => {
+			fs::remove_file(&temp_path).await.ok();
+			return Err(e.into());
+		}
+	};
+
+	let stored_path = version_path(&store, &checksum);
+	if fs::metadata(&stored_path).await.is_ok() {
+		fs::remove_file(&temp_path).await?;
+	} else {
+		if let Some(parent) = stored_path.parent() {
+			fs::create_dir_all(parent).await?;
+		}
+		fs::rename(&temp_path, &stored_path).await?;
+	}
+
+	let version = library
+		.db
+		.file_version()
+		.upsert(
+			file_version::object_id_checksum(object_id, checksum.clone()),
+			(
+				checksum,
+				size.to_string(),
+				object::id::equals(object_id),
+				vec![],
+			),
+			vec![file_version::date_created::set(Utc::now().into())],
+		)
+		.exec()
+		.await?;
+
+	prune_versions(
+		library,
+		&library.config.file_version_retention,
+		vec![file_version::object_id::equals(object_id)],
+	)
+	.await?;
+
+	invalidate_query!(library, "files.versions");
+
+	Ok(version)
+}
+
+/// restore_file_version overwrites the file of the object with the contents of one of its versions.
+/// The contents being overwritten are snapshotted first, so the restore can be undone.
+pub async fn restore_file_version(library: &Library, id: i32) -> Result<(), FileVersionError> {
+	let version = library
+		.db
+		.file_version()
+		.find_unique(file_version::id::equals(id))
+		.exec()
+		.await?
+		.ok_or(FileVersionError::NotFound(id))?;
+
+	let file_path = library
+		.db
+		.file_path()
+		.find_first(vec![
+			file_path::object_id::equals(Some(version.object_id)),
+			file_path::location::is(vec![location::node_id::equals(library.node_local_id)]),
+		])
+		.select(file_path::select!({ materialized_path location: select { path } }))
+		.exec()
+		.await?
+		.ok_or(FileVersionError::NoLocalFile(version.object_id))?;
+
+	let target = Path::new(&file_path.location.path).join(&file_path.materialized_path);
+
+	match snapshot_file_version(library, version.object_id, &target).await {
+		Ok(_) => {}
+		Err(FileVersionError::IO(e)) if e.kind() == io::ErrorKind::NotFound => {}
+		Err(e) => return Err(e),
+	}
+
+	// The watcher snapshots the restored contents again, which only bumps the date of this version
+	fs::copy(
+		version_path(version_store(library), &version.checksum),
+		&target,
+	)
+	.await?;
+
+	Ok(())
+}
+
+/// prune_versions deletes the versions matching `params` which are past the retention, always keeping the latest
+/// version of each object. Stored contents are removed once no version of the library references them anymore.
+async fn prune_versions(
+	library: &Library,
+	retention: &FileVersionRetention,
+	params: Vec<file_version::WhereParam>,
+) -> Result<usize, FileVersionError> {
+	let expired_before = retention
+		.max_age_days
+		.map(|days| Utc::now() - chrono::Duration::days(days as i64));
+
+	let versions = library
+		.db
+		.file_version()
+		.find_many(params)
+		.order_by(file_version::date_created::order(Direction::Desc))
+		.select(file_version::select!({ id object_id checksum date_created }))
+		.exec()
+		.await?;
+
+	let mut kept_per_object = HashMap::<i32, u32>::new();
+	let mut pruned_ids = vec![];
+	let mut pruned_checksums = HashSet::new();
+	for version in versions {
+		let kept = kept_per_object.entry(version.object_id).or_default();
+
+		let over_count = retention.max_versions.map_or(false, |max| *kept >= max);
+		let expired = expired_before.map_or(false, |before| {
+			version.date_created.with_timezone(&Utc) < before
+		});
+
+		if *kept > 0 && (over_count || expired) {
+			pruned_ids.push(version.id);
+			pruned_checksums.insert(version.checksum);
+		} else {
+			*kept += 1;
+		}
+	}
+
+	if pruned_ids.is_empty() {
+		return Ok(0);
+	}
+
+	library
+		.db
+		.file_version()
+		.delete_many(vec![file_version::id::in_vec(pruned_ids.clone())])
+		.exec()
+		.await?;
+
+	let store = version_store(library);
+	for checksum in pruned_checksums {
+		let still_used = library
+			.db
+			.file_version()
+			.count(vec![file_version::checksum::equals(checksum.clone())])
+			.exec()
+			.await? > 0;
+
+		if !still_used {
+			match fs::remove_file(version_path(&store, &checksum)).await {
+				Ok(()) => {}
+				Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+				Err(e) => return Err(e.into()),
+			}
+		}
+	}
+
+	Ok(pruned_ids.len())
+}
+
+/// spawn_file_version_pruner periodically prunes the versions of every library which are past its retention.
+/// Versions are also pruned whenever a new one is snapshotted, so this only catches the ones that expired.
+pub fn spawn_file_version_pruner(library_manager: Arc<LibraryManager>) {
+	tokio::spawn(async move {
+		let mut interval = interval(FILE_VERSION_PRUNE_TICK);
+
+		loop {
+			interval.tick().await;
+
+			for library in library_manager.get_all_libraries().await {
+				let retention = &library.config.file_version_retention;
+				if retention.max_age_days.is_none() {
+					continue;
+				}
+
+				match prune_versions(&library, retention, vec![]).await {
+					Ok(0) => {}
+					Ok(pruned) => info!(
+						"Pruned {pruned} expired file versions from library {}",
+						library.id
+					),
+					Err(e) => error!(
+						"Failed to prune the file versions of library {}: {e:#?}",
+						library.id
+					),
+				}
+			}
+		}
+	});
+}
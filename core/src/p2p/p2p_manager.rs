@@ -12,12 +12,13 @@ use tokio::{
 	io::{AsyncReadExt, AsyncWriteExt, BufReader},
 	sync::broadcast,
 };
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, info_span, Instrument};
 use uuid::Uuid;
 
 use crate::{
 	node::NodeConfigManager,
 	p2p::{OperatingSystem, SPACEDRIVE_APP_ID},
+	util::metrics::METRICS,
 };
 
 use super::{Header, PeerMetadata};
@@ -94,48 +95,54 @@ impl P2PManager {
 						event.dial().await;
 					}
 					Event::PeerMessage(mut event) => {
-						tokio::spawn(async move {
-							let header = Header::from_stream(&mut event.stream).await.unwrap();
-
-							match header {
-								Header::Ping => {
-									debug!("Received ping from peer '{}'", event.peer_id);
-								}
-								Header::Spacedrop(req) => {
-									info!("Received Spacedrop from peer '{}' for file '{}' with file length '{}'", event.peer_id, req.name, req.size);
-
-									// TODO: Ask the user if they wanna reject/accept it
-
-									// TODO: Deal with binary data. Deal with blocking based on `req.block_size`, etc
-									let mut s = String::new();
-									event.stream.read_to_string(&mut s).await.unwrap();
-
-									println!(
-										"Recieved file '{}' with content '{}' through Spacedrop!",
-										req.name, s
-									);
-
-									// TODO: Save to the filesystem
-								}
-								Header::Sync(library_id) => {
-									let mut len = [0; 4];
-									event.stream.read_exact(&mut len).await.unwrap();
-									let len = u32::from_be_bytes(len);
-
-									let mut buf = vec![0; len as usize]; // TODO: Designed for easily being able to be DOS the current Node
-									event.stream.read_exact(&mut buf).await.unwrap();
-
-									let mut buf: &[u8] = &buf;
-									let output: Vec<CRDTOperation> =
-										rmp_serde::from_read(&mut buf).unwrap();
-
-									// TODO: Handle this @Brendan
-									println!("Received sync events for library '{library_id}': {output:?}");
-
-									// TODO(@Oscar): Remember we can't do a response here cause it's a broadcast. Encode that into type system!
+						let span = info_span!("p2p_message", peer_id = %event.peer_id);
+						tokio::spawn(
+							async move {
+								let header = Header::from_stream(&mut event.stream).await.unwrap();
+
+								match header {
+									Header::Ping => {
+										debug!("Received ping from peer '{}'", event.peer_id);
+									}
+									Header::Spacedrop(req) => {
+										info!("Received Spacedrop from peer '{}' for file '{}' with file length '{}'", event.peer_id, req.name, req.size);
+
+										// TODO: Ask the user if they wanna reject/accept it
+
+										// TODO: Deal with binary data. Deal with blocking based on `req.block_size`, etc
+										let mut s = String::new();
+										event.stream.read_to_string(&mut s).await.unwrap();
+										METRICS.record_p2p_received(s.len() as u64);
+
+										println!(
+											"Recieved file '{}' with content '{}' through Spacedrop!",
+											req.name, s
+										);
+
+										// TODO: Save to the filesystem
+									}
+									Header::Sync(library_id) => {
+										let mut len = [0; 4];
+										event.stream.read_exact(&mut len).await.unwrap();
+										let len = u32::from_be_bytes(len);
+
+										let mut buf = vec![0; len as usize]; // TODO: Designed for easily being able to be DOS the current Node
+										event.stream.read_exact(&mut buf).await.unwrap();
+										METRICS.record_p2p_received(4 + len as u64);
+
+										let mut buf: &[u8] = &buf;
+										let output: Vec<CRDTOperation> =
+											rmp_serde::from_read(&mut buf).unwrap();
+
+										// TODO: Handle this @Brendan
+										println!("Received sync events for library '{library_id}': {output:?}");
+
+										// TODO(@Oscar): Remember we can't do a response here cause it's a broadcast. Encode that into type system!
+									}
 								}
 							}
-						});
+							.instrument(span),
+						);
 					}
 					_ => debug!("event: {:?}", event),
 				}
@@ -264,6 +271,7 @@ impl P2PManager {
 		reader.read_to_end(&mut buffer).await.unwrap();
 		println!("READ {:?}", buffer);
 		stream.write_all(&buffer).await.unwrap();
+		METRICS.record_p2p_sent(buffer.len() as u64);
 
 		debug!(
 			"Finished Spacedrop to peer '{peer_id}' after '{:?}",
@@ -296,6 +296,36 @@ impl SyncManager {
 						.await?;
 				}
 			},
+			ModelSyncData::Bookmark(id, shared_op) => match shared_op {
+				SharedOperationData::Create(SharedOperationCreateData::Unique(mut data)) => {
+					db.bookmark()
+						.create(
+							id.pub_id,
+							serde_json::from_value(data.remove("kind").unwrap()).unwrap(),
+							data.into_iter()
+								.flat_map(|(k, v)| bookmark::SetParam::deserialize(&k, v))
+								.collect(),
+						)
+						.exec()
+						.await?;
+				}
+				SharedOperationData::Update { field, value } => {
+					db.bookmark()
+						.update(
+							bookmark::pub_id::equals(id.pub_id),
+							vec![bookmark::SetParam::deserialize(&field, value).unwrap()],
+						)
+						.exec()
+						.await?;
+				}
+				SharedOperationData::Delete => {
+					db.bookmark()
+						.delete(bookmark::pub_id::equals(id.pub_id))
+						.exec()
+						.await?;
+				}
+				_ => unreachable!(),
+			},
 			_ => todo!(),
 		}
 
@@ -436,4 +466,17 @@ impl SyncManager {
 			},
 		}))
 	}
+	pub fn shared_delete<
+		TSyncId: SyncId<ModelTypes = TModel>,
+		TModel: SyncType<Marker = SharedSyncType>,
+	>(
+		&self,
+		id: TSyncId,
+	) -> CRDTOperation {
+		self.new_op(CRDTOperationType::Shared(SharedOperation {
+			model: TModel::MODEL.to_string(),
+			record_id: json!(id),
+			data: SharedOperationData::Delete,
+		}))
+	}
 }
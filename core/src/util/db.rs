@@ -1,8 +1,16 @@
 use crate::library::LibraryManagerError;
+use crate::node::{DatabaseConfig, JournalMode, Synchronous};
 use crate::prisma::{self, PrismaClient};
-use prisma_client_rust::{migrations::*, NewClientError};
+use prisma_client_rust::{migrations::*, NewClientError, QueryError, Raw};
 use sd_crypto::keys::keymanager::StoredKey;
+use serde::Deserialize;
+use std::{
+	ffi::{c_char, c_int, c_void, CString},
+	mem, ptr,
+	sync::{Arc, Once, RwLock},
+};
 use thiserror::Error;
+use tracing::error;
 
 /// MigrationError represents an error that occurring while opening a initialising and running migrations on the database.
 #[derive(Error, Debug)]
@@ -15,14 +23,212 @@ pub enum MigrationError {
 	#[cfg(not(debug_assertions))]
 	#[error("An error occurred during migration: {0}")]
 	MigrateFailed(#[from] MigrateDeployError),
+	#[error("An error occurred while applying the database settings: {0}")]
+	Pragma(#[from] QueryError),
 }
 
-/// load_and_migrate will load the database from the given path and migrate it to the latest version of the schema.
-pub async fn load_and_migrate(db_url: &str) -> Result<PrismaClient, MigrationError> {
+const DEFAULT_JOURNAL_MODE: JournalMode = JournalMode::Wal;
+const DEFAULT_SYNCHRONOUS: Synchronous = Synchronous::Normal;
+const DEFAULT_MMAP_SIZE: u64 = 256 * 1024 * 1024;
+const DEFAULT_CACHE_SIZE_KIB: u32 = 64 * 1024;
+
+/// CONNECTION_PRAGMAS are the per-connection `PRAGMA` statements run by [`run_connection_pragmas`] on every new connection.
+static CONNECTION_PRAGMAS: RwLock<Option<CString>> = RwLock::new(None);
+
+/// run_connection_pragmas is registered as an SQLite auto extension, which SQLite calls on every connection it opens,
+/// so the connections of the pool of a client all get the settings.
+unsafe extern "C" fn run_connection_pragmas(
+	db: *mut libsqlite3_sys::sqlite3,
+	_err_msg: *mut *mut c_char,
+	_api: *const c_void,
+) -> c_int {
+	match CONNECTION_PRAGMAS
+		.read()
+		.ok()
+		.as_deref()
+		.and_then(Option::as_ref)
+	{
+		Some(pragmas) => libsqlite3_sys::sqlite3_exec(
+			db,
+			pragmas.as_ptr(),
+			None,
+			ptr::null_mut(),
+			ptr::null_mut(),
+		),
+		None => libsqlite3_sys::SQLITE_OK,
+	}
+}
+
+/// set_connection_pragmas sets the per-connection `PRAGMA` settings of the connections opened from now on,
+/// using the defaults for the ones which aren't overridden.
+fn set_connection_pragmas(config: &DatabaseConfig) {
+	static REGISTER: Once = Once::new();
+
+	let synchronous = match config.synchronous.unwrap_or(DEFAULT_SYNCHRONOUS) {
+		Synchronous::Off => "OFF",
+		Synchronous::Normal => "NORMAL",
+		Synchronous::Full => "FULL",
+	};
+
+	let pragmas = format!(
+		// A negative cache size is in KiB instead of pages
+		"PRAGMA synchronous = {synchronous}; PRAGMA mmap_size = {}; PRAGMA cache_size = -{};",
+		config.mmap_size.unwrap_or(DEFAULT_MMAP_SIZE),
+		config.cache_size_kib.unwrap_or(DEFAULT_CACHE_SIZE_KIB)
+	);
+
+	if let Ok(mut connection_pragmas) = CONNECTION_PRAGMAS.write() {
+		*connection_pragmas = CString::new(pragmas).ok();
+	}
+
+	REGISTER.call_once(|| {
+		// SAFETY: SQLite calls auto extensions with the signature of `run_connection_pragmas`,
+		// the bindings only declare them as functions without arguments
+		let code = unsafe {
+			libsqlite3_sys::sqlite3_auto_extension(Some(mem::transmute(
+				run_connection_pragmas
+					as unsafe extern "C" fn(
+						*mut libsqlite3_sys::sqlite3,
+						*mut *mut c_char,
+						*const c_void,
+					) -> c_int,
+			)))
+		};
+
+		if code != libsqlite3_sys::SQLITE_OK {
+			error!("Failed to register the SQLite connection settings: error code {code}");
+		}
+	});
+}
+
+/// apply_journal_mode sets the journal mode of a database. It's stored in the database,
+/// so unlike the other settings it only has to be set once and not on every connection.
+async fn apply_journal_mode(db: &PrismaClient, config: &DatabaseConfig) -> Result<(), QueryError> {
+	let journal_mode = match config.journal_mode.unwrap_or(DEFAULT_JOURNAL_MODE) {
+		JournalMode::Delete => "DELETE",
+		JournalMode::Truncate => "TRUNCATE",
+		JournalMode::Persist => "PERSIST",
+		JournalMode::Wal => "WAL",
+	};
+
+	// Pragmas return the value they were set to, so they are run as queries
+	db._query_raw::<serde_json::Value>(Raw::new(
+		&format!("PRAGMA journal_mode = {journal_mode}"),
+		vec![],
+	))
+	.exec()
+	.await?;
+
+	Ok(())
+}
+
+/// Tables estimated to have fewer rows than this are counted exactly, as `COUNT(*)` is cheap for them
+const EXACT_COUNT_THRESHOLD: i64 = 10_000;
+
+/// CountedTable is a table which can be counted with [`estimated_count`].
+#[derive(Debug, Clone, Copy)]
+pub enum CountedTable {
+	FilePath,
+	Object,
+}
+
+impl CountedTable {
+	fn as_str(&self) -> &'static str {
+		match self {
+			Self::FilePath => "file_path",
+			Self::Object => "object",
+		}
+	}
+}
+
+#[derive(Deserialize)]
+struct CountRow {
+	count: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct StatRow {
+	stat: String,
+}
+
+/// estimated_count returns the number of rows of a table without scanning it, as `COUNT(*)` takes seconds over millions of rows.
+///
+/// It uses the statistics gathered by `ANALYZE`, which can be behind by the rows written since they were last refreshed.
+/// Tables without statistics and small tables are counted exactly with `COUNT(*)`.
+pub async fn estimated_count(db: &PrismaClient, table: CountedTable) -> Result<i64, QueryError> {
+	let table = table.as_str();
+
+	let has_statistics = !db
+		._query_raw::<CountRow>(Raw::new(
+			"SELECT 1 AS count FROM sqlite_master WHERE type = 'table' AND name = 'sqlite_stat1'",
+			vec![],
+		))
+		.exec()
+		.await?
+		.is_empty();
+
+	if has_statistics {
+		// The first number of every statistic is the number of rows in the index, the largest one being the whole table
+		let estimate = db
+			._query_raw::<StatRow>(Raw::new(
+				&format!("SELECT stat FROM sqlite_stat1 WHERE tbl = '{table}'"),
+				vec![],
+			))
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|row| row.stat.split(' ').next()?.parse::<i64>().ok())
+			.max();
+
+		if let Some(estimate) = estimate.filter(|&estimate| estimate >= EXACT_COUNT_THRESHOLD) {
+			return Ok(estimate);
+		}
+	}
+
+	Ok(db
+		._query_raw::<CountRow>(Raw::new(
+			&format!("SELECT COUNT(*) AS count FROM {table}"),
+			vec![],
+		))
+		.exec()
+		.await?
+		.pop()
+		.and_then(|row| row.count)
+		.unwrap_or(0))
+}
+
+/// refresh_count_estimates updates the statistics used by [`estimated_count`] for the tables a job wrote to.
+/// It runs in the background, as analyzing a large table takes a while.
+pub fn refresh_count_estimates(db: Arc<PrismaClient>, tables: &[CountedTable]) {
+	let tables = tables.to_vec();
+
+	tokio::spawn(async move {
+		for table in tables {
+			if let Err(e) = db
+				._execute_raw(Raw::new(&format!("ANALYZE {}", table.as_str()), vec![]))
+				.exec()
+				.await
+			{
+				error!("Failed to refresh the statistics of {table:?}: {e:#?}");
+			}
+		}
+	});
+}
+
+/// load_and_migrate will load the database from the given path, apply its settings and migrate it to the latest version of the schema.
+pub async fn load_and_migrate(
+	db_url: &str,
+	config: &DatabaseConfig,
+) -> Result<PrismaClient, MigrationError> {
+	// The settings must be in place before the client opens its connections
+	set_connection_pragmas(config);
+
 	let client = prisma::new_client_with_url(db_url)
 		.await
 		.map_err(Box::new)?;
 
+	apply_journal_mode(&client, config).await?;
+
 	#[cfg(debug_assertions)]
 	{
 		let mut builder = client._db_push();
@@ -0,0 +1,49 @@
+use std::{env, io, path::PathBuf};
+
+use once_cell::sync::Lazy;
+use tokio::process::Command;
+
+/// The linked FFmpeg libraries only probe and decode, streams and conversions are encoded by the `ffmpeg` binary
+const FFMPEG_BINARY_NAME: &str = if cfg!(windows) { "ffmpeg.exe" } else { "ffmpeg" };
+
+/// Overrides where the `ffmpeg` binary is, for builds which don't bundle it
+const FFMPEG_PATH_VAR: &str = "SD_FFMPEG_PATH";
+
+static FFMPEG_BINARY: Lazy<Option<PathBuf>> = Lazy::new(find_ffmpeg_binary);
+
+/// find_ffmpeg_binary looks for the `ffmpeg` binary at [`FFMPEG_PATH_VAR`], then next to the executable where it's bundled,
+/// and only then in the directories of `PATH`.
+fn find_ffmpeg_binary() -> Option<PathBuf> {
+	let overridden = env::var_os(FFMPEG_PATH_VAR).map(PathBuf::from);
+
+	let bundled = env::current_exe()
+		.ok()
+		.and_then(|exe| exe.parent().map(|dir| dir.join(FFMPEG_BINARY_NAME)));
+
+	let in_path = env::var_os("PATH")
+		.map(|paths| env::split_paths(&paths).collect::<Vec<_>>())
+		.unwrap_or_default()
+		.into_iter()
+		.map(|dir| dir.join(FFMPEG_BINARY_NAME));
+
+	overridden
+		.into_iter()
+		.chain(bundled)
+		.chain(in_path)
+		.find(|path| path.is_file())
+}
+
+/// ffmpeg_available tells if the `ffmpeg` binary was found, without which videos can't be streamed nor converted.
+pub fn ffmpeg_available() -> bool {
+	FFMPEG_BINARY.is_some()
+}
+
+/// ffmpeg_command returns a command running the `ffmpeg` binary, failing when it wasn't found.
+pub fn ffmpeg_command() -> io::Result<Command> {
+	FFMPEG_BINARY.as_ref().map(Command::new).ok_or_else(|| {
+		io::Error::new(
+			io::ErrorKind::NotFound,
+			format!("the ffmpeg binary wasn't found, set {FFMPEG_PATH_VAR} to its path"),
+		)
+	})
+}
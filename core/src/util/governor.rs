@@ -0,0 +1,209 @@
+use std::{
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex, PoisonError, RwLock,
+	},
+	time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use sysinfo::{ComponentExt, System, SystemExt};
+use tokio::runtime::Handle;
+use tracing::info;
+
+use crate::node::{ThrottlingConfig, ThrottlingMode};
+
+use super::{power::on_battery, rate_limit::RateLimiter};
+
+/// Used when the thermal threshold isn't set, which most CPUs only reach under sustained load
+const DEFAULT_THERMAL_THRESHOLD_CELSIUS: f32 = 85.0;
+/// Sensors reporting a critical temperature are considered under pressure this close to it, whatever the threshold
+const CRITICAL_TEMPERATURE_MARGIN_CELSIUS: f32 = 5.0;
+/// Used when the parallelism factor isn't set
+const DEFAULT_PARALLELISM_FACTOR: f32 = 0.25;
+/// Used when the read rate isn't set
+const DEFAULT_MAX_READ_BYTES_PER_SECOND: u64 = 32 * 1024 * 1024;
+/// How long temperatures are remembered, as reading every sensor is slow on some platforms
+const TEMPERATURES_TTL: Duration = Duration::from_secs(30);
+
+/// Temperatures are the current and critical temperatures of every sensor, along with when they were read
+type Temperatures = Option<(Instant, Vec<(f32, Option<f32>)>)>;
+
+static GOVERNOR: Lazy<ResourceGovernor> = Lazy::new(ResourceGovernor::default);
+
+/// ThrottleReason is why jobs are throttled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleReason {
+	/// The device runs on battery
+	Battery,
+	/// A sensor of the device reached the thermal threshold
+	ThermalPressure,
+	/// The node config asks for jobs to always be throttled
+	Forced,
+}
+
+/// Throttle tells how much jobs are slowed down while they are throttled.
+#[derive(Debug, Clone, Copy)]
+pub struct Throttle {
+	pub reason: ThrottleReason,
+	parallelism_factor: f32,
+}
+
+impl Throttle {
+	/// parallelism scales down how many files a job processes at the same time.
+	pub fn parallelism(&self, parallelism: usize) -> usize {
+		match self.reason {
+			// More work at the same time would only heat the device further
+			ThrottleReason::ThermalPressure => 1,
+			ThrottleReason::Battery | ThrottleReason::Forced => {
+				((parallelism as f32 * self.parallelism_factor).round() as usize).max(1)
+			}
+		}
+	}
+}
+
+/// ResourceGovernor throttles jobs by the state of the device, slowing down the CPU and disk heavy ones,
+/// like the identifier's hashing and the thumbnailer, on battery and under thermal pressure.
+#[derive(Default)]
+pub struct ResourceGovernor {
+	config: RwLock<ThrottlingConfig>,
+	/// temperatures are read in the background, so throttling checks never wait for the sensors
+	temperatures: Arc<Mutex<Temperatures>>,
+	/// reading_temperatures is set while temperatures are being read, so they are only read once at a time
+	reading_temperatures: Arc<AtomicBool>,
+	/// reads are the reads of throttled jobs, which all share the read rate
+	reads: RateLimiter,
+	/// last_reason is only kept to log when throttling starts and stops
+	last_reason: Mutex<Option<ThrottleReason>>,
+}
+
+impl ResourceGovernor {
+	/// set_config applies the throttling settings of the node config.
+	pub(crate) fn set_config(&self, config: ThrottlingConfig) {
+		*self.config.write().unwrap_or_else(PoisonError::into_inner) = config;
+	}
+
+	fn config(&self) -> ThrottlingConfig {
+		self.config
+			.read()
+			.unwrap_or_else(PoisonError::into_inner)
+			.clone()
+	}
+
+	/// throttle tells if jobs must be throttled right now and by how much, `None` if they can run at full speed.
+	pub fn throttle(&self) -> Option<Throttle> {
+		let config = self.config();
+
+		let reason = match config.mode {
+			ThrottlingMode::Never => None,
+			ThrottlingMode::Always => Some(ThrottleReason::Forced),
+			ThrottlingMode::Auto => {
+				if self.under_thermal_pressure(&config) {
+					Some(ThrottleReason::ThermalPressure)
+				} else if !config.ignore_battery && on_battery() {
+					Some(ThrottleReason::Battery)
+				} else {
+					None
+				}
+			}
+		};
+
+		let mut last_reason = self
+			.last_reason
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner);
+		if *last_reason != reason {
+			match reason {
+				Some(reason) => info!("Throttling jobs: {reason:?}"),
+				None => info!("Jobs are no longer throttled"),
+			}
+			*last_reason = reason;
+		}
+
+		reason.map(|reason| Throttle {
+			reason,
+			parallelism_factor: config
+				.parallelism_factor
+				.unwrap_or(DEFAULT_PARALLELISM_FACTOR)
+				.clamp(0.0, 1.0),
+		})
+	}
+
+	/// limit_read waits until `bytes` can be read without going over the read rate of throttled jobs.
+	/// Reads aren't limited while jobs aren't throttled.
+	pub async fn limit_read(&self, bytes: u64) {
+		if self.throttle().is_none() {
+			return;
+		}
+
+		let rate = self
+			.config()
+			.max_read_bytes_per_second
+			.unwrap_or(DEFAULT_MAX_READ_BYTES_PER_SECOND);
+		self.reads.acquire(bytes, rate).await;
+	}
+
+	fn under_thermal_pressure(&self, config: &ThrottlingConfig) -> bool {
+		let threshold = config
+			.thermal_threshold_celsius
+			.unwrap_or(DEFAULT_THERMAL_THRESHOLD_CELSIUS);
+
+		let temperatures = self
+			.temperatures
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner);
+		if !matches!(*temperatures, Some((read_at, _)) if read_at.elapsed() < TEMPERATURES_TTL) {
+			self.refresh_temperatures();
+		}
+
+		// Until the first temperatures are read, the device isn't considered under thermal pressure
+		temperatures.as_ref().map_or(false, |(_, temperatures)| {
+			temperatures.iter().any(|&(temperature, critical)| {
+				temperature >= threshold
+					|| critical.map_or(false, |critical| {
+						critical > 0.0
+							&& temperature >= critical - CRITICAL_TEMPERATURE_MARGIN_CELSIUS
+					})
+			})
+		})
+	}
+
+	/// refresh_temperatures reads the temperatures on a blocking thread, unless they are already being read.
+	/// Outside of a runtime they are left as they are, until a check runs on one.
+	fn refresh_temperatures(&self) {
+		let Ok(runtime) = Handle::try_current() else {
+			return;
+		};
+
+		if self.reading_temperatures.swap(true, Ordering::AcqRel) {
+			return;
+		}
+
+		let temperatures = Arc::clone(&self.temperatures);
+		let reading_temperatures = Arc::clone(&self.reading_temperatures);
+		runtime.spawn_blocking(move || {
+			let read = read_temperatures();
+			*temperatures.lock().unwrap_or_else(PoisonError::into_inner) =
+				Some((Instant::now(), read));
+			reading_temperatures.store(false, Ordering::Release);
+		});
+	}
+}
+
+/// read_temperatures reads the current and critical temperatures of every sensor.
+/// Platforms where sensors can't be read have none, so they are never considered under thermal pressure.
+fn read_temperatures() -> Vec<(f32, Option<f32>)> {
+	let mut system = System::new();
+	system.refresh_components_list();
+
+	system
+		.components()
+		.iter()
+		.map(|component| (component.temperature(), component.critical()))
+		.collect()
+}
+
+/// governor returns the resource governor of the node.
+pub fn governor() -> &'static ResourceGovernor {
+	&GOVERNOR
+}
@@ -0,0 +1,157 @@
+//! File reads used by jobs making many small reads, like the identifier's hashing and the thumbnailer.
+//!
+//! With the `io-uring` feature on Linux the reads are submitted to an io_uring running on its own thread,
+//! which saves a syscall and a thread pool hop for every read. Everywhere else, or when io_uring isn't
+//! supported by the kernel, they go through [`tokio::fs`].
+//!
+//! Reads are slowed down by the read limit of the running job and while the governor throttles jobs, see [`limit_read`].
+
+use std::path::{Path, PathBuf};
+
+use super::rate_limit::limit_read;
+
+use tokio::{
+	fs::File,
+	io::{self, AsyncReadExt, AsyncSeekExt, SeekFrom},
+};
+
+/// read_ranges reads each `(offset, length)` range of a file, failing if the file is shorter than a range.
+pub async fn read_ranges(
+	path: impl AsRef<Path>,
+	ranges: Vec<(u64, u64)>,
+) -> Result<Vec<Vec<u8>>, io::Error> {
+	let path = path.as_ref();
+
+	limit_read(ranges.iter().map(|(_, length)| length).sum()).await;
+
+	#[cfg(all(target_os = "linux", feature = "io-uring"))]
+	if let Some(result) = uring::read_ranges(path.to_path_buf(), ranges.clone()).await {
+		return result;
+	}
+
+	tokio_read_ranges(path.to_path_buf(), ranges).await
+}
+
+/// read_file reads the whole file.
+pub async fn read_file(path: impl AsRef<Path>) -> Result<Vec<u8>, io::Error> {
+	let path = path.as_ref();
+	let size = tokio::fs::metadata(path).await?.len();
+
+	Ok(read_ranges(path, vec![(0, size)])
+		.await?
+		.pop()
+		.unwrap_or_default())
+}
+
+async fn tokio_read_ranges(
+	path: PathBuf,
+	ranges: Vec<(u64, u64)>,
+) -> Result<Vec<Vec<u8>>, io::Error> {
+	let mut file = File::open(path).await?;
+
+	let mut buffers = Vec::with_capacity(ranges.len());
+	for (offset, length) in ranges {
+		let mut buf = vec![0u8; length as usize];
+
+		file.seek(SeekFrom::Start(offset)).await?;
+		file.read_exact(&mut buf).await?;
+
+		buffers.push(buf);
+	}
+
+	Ok(buffers)
+}
+
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring {
+	use std::{io, path::PathBuf, thread};
+
+	use once_cell::sync::Lazy;
+	use tokio::sync::{mpsc, oneshot};
+	use tracing::warn;
+
+	struct ReadRequest {
+		path: PathBuf,
+		ranges: Vec<(u64, u64)>,
+		tx: oneshot::Sender<Result<Vec<Vec<u8>>, io::Error>>,
+	}
+
+	/// The io_uring runtime can't share a thread with the main runtime, so it runs on its own.
+	/// When the kernel doesn't support io_uring the thread exits and the channel is closed.
+	static URING: Lazy<mpsc::UnboundedSender<ReadRequest>> = Lazy::new(|| {
+		let (tx, mut rx) = mpsc::unbounded_channel::<ReadRequest>();
+
+		let spawned = thread::Builder::new()
+			.name("sd-io-uring".to_string())
+			.spawn(move || {
+				let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+					tokio_uring::start(async move {
+						while let Some(request) = rx.recv().await {
+							tokio_uring::spawn(async move {
+								request
+									.tx
+									.send(read_ranges_uring(request.path, request.ranges).await)
+									.ok();
+							});
+						}
+					})
+				}));
+
+				if result.is_err() {
+					warn!("io_uring isn't available, falling back to the default file reads");
+				}
+			});
+
+		if let Err(e) = spawned {
+			warn!("Failed to spawn the io_uring thread: {e:#?}");
+		}
+
+		tx
+	});
+
+	/// read_ranges returns `None` when io_uring isn't available, so the caller can fall back to another backend.
+	pub(super) async fn read_ranges(
+		path: PathBuf,
+		ranges: Vec<(u64, u64)>,
+	) -> Option<Result<Vec<Vec<u8>>, io::Error>> {
+		let (tx, rx) = oneshot::channel();
+		URING.send(ReadRequest { path, ranges, tx }).ok()?;
+
+		rx.await.ok()
+	}
+
+	async fn read_ranges_uring(
+		path: PathBuf,
+		ranges: Vec<(u64, u64)>,
+	) -> Result<Vec<Vec<u8>>, io::Error> {
+		let file = tokio_uring::fs::File::open(path).await?;
+
+		let mut buffers = Vec::with_capacity(ranges.len());
+		for (offset, length) in ranges {
+			let length = length as usize;
+			let mut data = Vec::with_capacity(length);
+
+			while data.len() < length {
+				// Reads fill the spare capacity of the buffer
+				let (result, buf) = file
+					.read_at(
+						Vec::with_capacity(length - data.len()),
+						offset + data.len() as u64,
+					)
+					.await;
+
+				let read = result?;
+				if read == 0 {
+					return Err(io::ErrorKind::UnexpectedEof.into());
+				}
+				data.extend_from_slice(&buf[..read]);
+			}
+
+			buffers.push(data);
+		}
+
+		file.close().await?;
+
+		Ok(buffers)
+	}
+}
@@ -0,0 +1,145 @@
+use std::{
+	collections::BTreeMap,
+	fmt::Write,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Mutex,
+	},
+	time::Duration,
+};
+
+use once_cell::sync::Lazy;
+
+/// METRICS holds the metrics of this node, which are exported in the Prometheus text format by [`Metrics::render`].
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::default);
+
+#[derive(Default)]
+struct Summary {
+	count: u64,
+	sum: f64,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+	job_durations: Mutex<BTreeMap<String, Summary>>,
+	request_durations: Mutex<BTreeMap<String, Summary>>,
+	watcher_events: AtomicU64,
+	p2p_bytes_sent: AtomicU64,
+	p2p_bytes_received: AtomicU64,
+}
+
+fn observe(summaries: &Mutex<BTreeMap<String, Summary>>, label: &str, duration: Duration) {
+	let mut summaries = summaries.lock().unwrap_or_else(|e| e.into_inner());
+	let summary = summaries.entry(label.to_string()).or_default();
+	summary.count += 1;
+	summary.sum += duration.as_secs_f64();
+}
+
+fn write_summary(
+	out: &mut String,
+	name: &str,
+	help: &str,
+	label: &str,
+	summaries: &Mutex<BTreeMap<String, Summary>>,
+) {
+	let summaries = summaries.lock().unwrap_or_else(|e| e.into_inner());
+
+	writeln!(out, "# HELP {name} {help}").ok();
+	writeln!(out, "# TYPE {name} summary").ok();
+	for (value, summary) in summaries.iter() {
+		writeln!(out, "{name}_sum{{{label}=\"{value}\"}} {}", summary.sum).ok();
+		writeln!(out, "{name}_count{{{label}=\"{value}\"}} {}", summary.count).ok();
+	}
+}
+
+fn write_metric(
+	out: &mut String,
+	name: &str,
+	kind: &str,
+	help: &str,
+	value: impl std::fmt::Display,
+) {
+	writeln!(out, "# HELP {name} {help}").ok();
+	writeln!(out, "# TYPE {name} {kind}").ok();
+	writeln!(out, "{name} {value}").ok();
+}
+
+impl Metrics {
+	pub fn record_job_duration(&self, job: &str, duration: Duration) {
+		observe(&self.job_durations, job, duration);
+	}
+
+	/// record_request_duration records how long a library request took, which is mostly spent querying its database.
+	pub fn record_request_duration(&self, procedure: &str, duration: Duration) {
+		observe(&self.request_durations, procedure, duration);
+	}
+
+	pub fn record_watcher_event(&self) {
+		self.watcher_events.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn record_p2p_sent(&self, bytes: u64) {
+		self.p2p_bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+	}
+
+	pub fn record_p2p_received(&self, bytes: u64) {
+		self.p2p_bytes_received.fetch_add(bytes, Ordering::Relaxed);
+	}
+
+	/// render returns every metric in the Prometheus text format, alongside the given job queue gauges.
+	pub fn render(&self, queued_jobs: usize, running_jobs: usize) -> String {
+		let mut out = String::new();
+
+		write_summary(
+			&mut out,
+			"sd_job_duration_seconds",
+			"Duration of completed jobs.",
+			"job",
+			&self.job_durations,
+		);
+		write_metric(
+			&mut out,
+			"sd_jobs_queued",
+			"gauge",
+			"Jobs waiting to be run.",
+			queued_jobs,
+		);
+		write_metric(
+			&mut out,
+			"sd_jobs_running",
+			"gauge",
+			"Jobs currently running.",
+			running_jobs,
+		);
+		write_summary(
+			&mut out,
+			"sd_library_request_duration_seconds",
+			"Duration of library queries and mutations, mostly spent in the library database.",
+			"procedure",
+			&self.request_durations,
+		);
+		write_metric(
+			&mut out,
+			"sd_watcher_events_total",
+			"counter",
+			"File system events handled by location watchers.",
+			self.watcher_events.load(Ordering::Relaxed),
+		);
+		write_metric(
+			&mut out,
+			"sd_p2p_sent_bytes_total",
+			"counter",
+			"Bytes sent to other nodes.",
+			self.p2p_bytes_sent.load(Ordering::Relaxed),
+		);
+		write_metric(
+			&mut out,
+			"sd_p2p_received_bytes_total",
+			"counter",
+			"Bytes received from other nodes.",
+			self.p2p_bytes_received.load(Ordering::Relaxed),
+		);
+
+		out
+	}
+}
@@ -1,3 +1,12 @@
 pub mod db;
+#[cfg(feature = "ffmpeg")]
+pub mod ffmpeg;
+pub mod governor;
+pub mod io;
+pub mod metrics;
+pub mod power;
+pub mod rate_limit;
+pub mod resources;
 pub mod secure_temp_keystore;
 pub mod seeder;
+pub mod webhook;
@@ -0,0 +1,87 @@
+use std::{
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+
+/// How long the power source is remembered, as it's checked before every batch of work which can be throttled
+const POWER_SOURCE_TTL: Duration = Duration::from_secs(30);
+
+static ON_BATTERY: Lazy<Mutex<Option<(Instant, bool)>>> = Lazy::new(|| Mutex::new(None));
+
+/// on_battery tells if the device is running on battery, so CPU and disk heavy work can be slowed down.
+/// Devices without a battery, and platforms where it can't be read, are considered plugged in.
+pub fn on_battery() -> bool {
+	let Ok(mut cached) = ON_BATTERY.lock() else {
+		return false;
+	};
+
+	match *cached {
+		Some((checked_at, on_battery)) if checked_at.elapsed() < POWER_SOURCE_TTL => on_battery,
+		_ => {
+			let on_battery = read_on_battery();
+			*cached = Some((Instant::now(), on_battery));
+			on_battery
+		}
+	}
+}
+
+/// read_on_battery looks for a discharging battery in the power supplies listed by the kernel, as a laptop charging,
+/// or full and plugged in, isn't running on battery.
+#[cfg(target_os = "linux")]
+fn read_on_battery() -> bool {
+	use std::fs;
+
+	let Ok(power_supplies) = fs::read_dir("/sys/class/power_supply") else {
+		return false;
+	};
+
+	power_supplies.flatten().any(|power_supply| {
+		let read = |name| fs::read_to_string(power_supply.path().join(name)).unwrap_or_default();
+		read("type").trim() == "Battery" && read("status").trim() == "Discharging"
+	})
+}
+
+#[cfg(target_os = "macos")]
+fn read_on_battery() -> bool {
+	use std::process::Command;
+
+	// The first line of `pmset -g batt` names the power source, like "Now drawing from 'Battery Power'"
+	Command::new("pmset")
+		.args(["-g", "batt"])
+		.output()
+		.map_or(false, |output| {
+			String::from_utf8_lossy(&output.stdout)
+				.lines()
+				.next()
+				.map_or(false, |line| line.contains("'Battery Power'"))
+		})
+}
+
+#[cfg(target_os = "windows")]
+fn read_on_battery() -> bool {
+	use windows_sys::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+	let mut status = SYSTEM_POWER_STATUS {
+		ACLineStatus: 0,
+		BatteryFlag: 0,
+		BatteryLifePercent: 0,
+		SystemStatusFlag: 0,
+		BatteryLifeTime: 0,
+		BatteryFullLifeTime: 0,
+	};
+
+	// SAFETY: `status` is a valid SYSTEM_POWER_STATUS for the call to write to
+	if unsafe { GetSystemPowerStatus(&mut status) } == 0 {
+		return false;
+	}
+
+	// The AC line is offline when running on battery, and unknown (255) on devices which can't tell
+	status.ACLineStatus == 0
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn read_on_battery() -> bool {
+	false
+}
@@ -0,0 +1,134 @@
+use std::{
+	collections::HashMap,
+	future::Future,
+	sync::{Arc, Mutex, PoisonError},
+	time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use tokio::{task_local, time::sleep_until};
+
+use super::governor::governor;
+
+/// The limiters of every job type which has a read limit, shared by the jobs of the same type running at the same time
+static JOB_READ_LIMITERS: Lazy<Mutex<HashMap<String, Arc<RateLimiter>>>> =
+	Lazy::new(Default::default);
+
+task_local! {
+	static JOB_READ_LIMIT: JobReadLimit;
+}
+
+/// JobReadLimit is the read limit of the job running in the current task, set with [`with_job_read_limit`].
+#[derive(Clone)]
+struct JobReadLimit {
+	limiter: Arc<RateLimiter>,
+	bytes_per_second: u64,
+}
+
+/// RateLimiter spaces out the reads sharing it so they stay under a rate, in bytes per second.
+#[derive(Default)]
+pub struct RateLimiter {
+	/// next is when the next read can start
+	next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+	/// acquire waits until `bytes` can be read without going over `bytes_per_second`.
+	/// A rate of 0 doesn't limit reads.
+	pub async fn acquire(&self, bytes: u64, bytes_per_second: u64) {
+		if bytes_per_second == 0 {
+			return;
+		}
+
+		let duration = Duration::from_secs_f64(bytes as f64 / bytes_per_second as f64);
+
+		let start = {
+			let mut next = self.next.lock().unwrap_or_else(PoisonError::into_inner);
+			let now = Instant::now();
+			let start = next.map_or(now, |next| next.max(now));
+			*next = Some(start + duration);
+			start
+		};
+
+		// The reads of a cancelled job never happen, so their time is given back to the ones after them
+		let mut reservation = Reservation {
+			limiter: self,
+			duration,
+			done: false,
+		};
+		sleep_until(start.into()).await;
+		reservation.done = true;
+	}
+}
+
+/// Reservation gives back the time reserved by [`RateLimiter::acquire`] if it's dropped while waiting.
+struct Reservation<'a> {
+	limiter: &'a RateLimiter,
+	duration: Duration,
+	done: bool,
+}
+
+impl Drop for Reservation<'_> {
+	fn drop(&mut self) {
+		if self.done {
+			return;
+		}
+
+		let mut next = self
+			.limiter
+			.next
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner);
+		*next = next.and_then(|next| next.checked_sub(self.duration));
+	}
+}
+
+/// with_job_read_limit runs a job with the read limit of its type, so its reads through [`limit_read`] stay under it.
+pub async fn with_job_read_limit<F: Future>(
+	job_name: &str,
+	bytes_per_second: Option<u64>,
+	job: F,
+) -> F::Output {
+	// A limit of 0 doesn't limit the job, like not having one
+	let Some(bytes_per_second) = bytes_per_second.filter(|&rate| rate > 0) else {
+		return job.await;
+	};
+
+	let limiter = Arc::clone(
+		JOB_READ_LIMITERS
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.entry(job_name.to_string())
+			.or_default(),
+	);
+
+	JOB_READ_LIMIT
+		.scope(
+			JobReadLimit {
+				limiter,
+				bytes_per_second,
+			},
+			job,
+		)
+		.await
+}
+
+/// read_limited tells if reads are limited right now, either by the job running in the current task or by the governor,
+/// so fast paths bypassing [`limit_read`], like copying a file in a single call, can be avoided.
+pub fn read_limited() -> bool {
+	JOB_READ_LIMIT.try_with(|_| ()).is_ok() || governor().throttle().is_some()
+}
+
+/// limit_read waits until `bytes` can be read without going over the read limit of the job running in the current task,
+/// nor the read rate of the governor while it throttles jobs.
+pub async fn limit_read(bytes: u64) {
+	if let Ok(JobReadLimit {
+		limiter,
+		bytes_per_second,
+	}) = JOB_READ_LIMIT.try_with(Clone::clone)
+	{
+		limiter.acquire(bytes, bytes_per_second).await;
+	}
+
+	governor().limit_read(bytes).await;
+}
@@ -0,0 +1,91 @@
+use std::thread::available_parallelism;
+
+use once_cell::sync::OnceCell;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tracing::warn;
+
+use crate::node::ResourceLimits;
+
+/// Used when the max number of open files isn't set, which stays well below the default limit of most platforms
+const DEFAULT_MAX_OPEN_FILES: usize = 256;
+/// Used when the cache memory isn't set
+const DEFAULT_CACHE_MEMORY_BYTES: u64 = 4 * 1024 * 1024;
+
+static LIMITER: OnceCell<ResourceLimiter> = OnceCell::new();
+
+/// ResourceLimiter enforces the [`ResourceLimits`] of the node where jobs allocate resources.
+pub struct ResourceLimiter {
+	worker_threads: Semaphore,
+	max_worker_threads: usize,
+	open_files: Semaphore,
+	max_open_files: usize,
+	cache_memory_bytes: u64,
+}
+
+impl ResourceLimiter {
+	fn new(limits: &ResourceLimits) -> Self {
+		let worker_threads = limits
+			.worker_threads
+			.map(|threads| threads as usize)
+			.or_else(|| available_parallelism().ok().map(Into::into))
+			.unwrap_or(1)
+			.max(1);
+		let max_open_files = limits
+			.max_open_files
+			.map_or(DEFAULT_MAX_OPEN_FILES, |files| files as usize)
+			.max(1);
+
+		Self {
+			worker_threads: Semaphore::new(worker_threads),
+			max_worker_threads: worker_threads,
+			open_files: Semaphore::new(max_open_files),
+			max_open_files,
+			cache_memory_bytes: limits
+				.cache_memory_bytes
+				.unwrap_or(DEFAULT_CACHE_MEMORY_BYTES),
+		}
+	}
+
+	/// worker_thread waits until a thread can be kept busy with CPU heavy work, which lasts as long as the permit is held.
+	pub async fn worker_thread(&self) -> SemaphorePermit<'_> {
+		self.worker_threads
+			.acquire()
+			.await
+			.expect("the resource semaphores are never closed")
+	}
+
+	/// worker_threads is how many threads can be kept busy at the same time, to size the thread pools of jobs.
+	pub fn worker_threads(&self) -> usize {
+		self.max_worker_threads
+	}
+
+	/// open_file waits until a file can be opened, which must be closed before the permit is dropped.
+	pub async fn open_file(&self) -> SemaphorePermit<'_> {
+		self.open_files
+			.acquire()
+			.await
+			.expect("the resource semaphores are never closed")
+	}
+
+	/// max_open_files is how many files can be opened at the same time, to size the concurrency of jobs.
+	pub fn max_open_files(&self) -> usize {
+		self.max_open_files
+	}
+
+	/// cache_memory_bytes is how much memory the in-memory caches can use.
+	pub fn cache_memory_bytes(&self) -> u64 {
+		self.cache_memory_bytes
+	}
+}
+
+/// init_resource_limiter sets the limits of the node, it must be called before any job runs.
+pub(crate) fn init_resource_limiter(limits: &ResourceLimits) {
+	if LIMITER.set(ResourceLimiter::new(limits)).is_err() {
+		warn!("The resource limits were already set, restart Spacedrive to apply the new ones");
+	}
+}
+
+/// resource_limiter returns the limiter of the node, with the default limits if it wasn't initialized.
+pub(crate) fn resource_limiter() -> &'static ResourceLimiter {
+	LIMITER.get_or_init(|| ResourceLimiter::new(&ResourceLimits::default()))
+}
@@ -1,6 +1,6 @@
 use crate::{
 	location::indexer::{
-		rules::{IndexerRule, ParametersPerKind, RuleKind},
+		rules::{IgnoreFiles, IndexerRule, ParametersPerKind, RuleKind},
 		IndexerError,
 	},
 	prisma::PrismaClient,
@@ -33,6 +33,15 @@ pub async fn indexer_rules_seeder(client: &PrismaClient) -> Result<(), SeederErr
 					[".git".to_string()].into_iter().collect(),
 				),
 			),
+			IndexerRule::new(
+				RuleKind::IgnoreFile,
+				"Ignore Files".to_string(),
+				ParametersPerKind::IgnoreFile(IgnoreFiles::new([
+					".gitignore",
+					".ignore",
+					".sdignore",
+				])),
+			),
 			IndexerRule::new(
 				RuleKind::AcceptFilesByGlob,
 				"Only Images".to_string(),
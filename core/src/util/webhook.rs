@@ -0,0 +1,23 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::{debug, warn};
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// send_webhook POSTs `payload` as JSON to the user configured `url`.
+/// Webhooks are best effort, so failures are only logged.
+pub async fn send_webhook(url: &str, payload: &impl Serialize) {
+	let result = reqwest::Client::new()
+		.post(url)
+		.timeout(WEBHOOK_TIMEOUT)
+		.json(payload)
+		.send()
+		.await
+		.and_then(|response| response.error_for_status());
+
+	match result {
+		Ok(_) => debug!("Sent webhook to '{url}'"),
+		Err(e) => warn!("Failed to send webhook to '{url}': {e:#?}"),
+	}
+}
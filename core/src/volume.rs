@@ -1,16 +1,27 @@
-use crate::{library::Library, prisma::volume::*};
+use crate::{
+	library::Library,
+	prisma::{volume::*, volume_benchmark},
+};
 
+use prisma_client_rust::Direction;
 use rspc::Type;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
-use std::process::Command;
+use std::{collections::HashMap, path::Path, process::Command, time::Instant};
 use sysinfo::{DiskExt, System, SystemExt};
 use thiserror::Error;
+use tokio::{
+	fs,
+	io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, SeekFrom},
+};
+use tracing::error;
 
 #[serde_as]
 #[derive(Serialize, Deserialize, Debug, Default, Clone, Type)]
 pub struct Volume {
 	pub name: String,
+	/// uuid is the identifier of the file system, which stays the same when the volume is mounted somewhere else.
+	pub uuid: Option<String>,
 	pub mount_point: String,
 	#[specta(type = String)]
 	#[serde_as(as = "DisplayFromStr")]
@@ -22,6 +33,434 @@ pub struct Volume {
 	pub disk_type: Option<String>,
 	pub file_system: Option<String>,
 	pub is_root_filesystem: bool,
+	pub kind: VolumeKind,
+	/// smart holds the SMART health attributes of the disk backing this volume, when the platform allows reading them.
+	pub smart: Option<VolumeSmart>,
+	/// health_warning is set when the SMART attributes indicate the disk may be failing.
+	pub health_warning: bool,
+}
+
+/// VolumeKind tells where the data of a volume lives, which bounds how fast it can be read.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Type)]
+pub enum VolumeKind {
+	#[default]
+	Local,
+	Removable,
+	Network,
+}
+
+/// File systems which are backed by a remote machine
+const NETWORK_FILE_SYSTEMS: &[&str] = &[
+	"nfs",
+	"nfs4",
+	"cifs",
+	"smbfs",
+	"smb2",
+	"smb3",
+	"afpfs",
+	"webdav",
+	"davfs",
+	"fuse.sshfs",
+	"sshfs",
+	"9p",
+	"ceph",
+	"glusterfs",
+	"fuse.glusterfs",
+	"fuse.rclone",
+];
+
+impl VolumeKind {
+	fn classify(file_system: &str, is_removable: bool) -> Self {
+		if NETWORK_FILE_SYSTEMS.contains(&file_system.to_lowercase().as_str()) {
+			Self::Network
+		} else if is_removable {
+			Self::Removable
+		} else {
+			Self::Local
+		}
+	}
+
+	/// max_concurrency is how many files jobs should read at the same time from a volume of this kind.
+	pub fn max_concurrency(self) -> usize {
+		match self {
+			Self::Local => 100,
+			Self::Removable => 16,
+			// Network shares slow down a lot with many parallel requests, especially over SMB
+			Self::Network => 4,
+		}
+	}
+}
+
+/// volume_kind_of returns the kind of the volume holding the given path, defaulting to [`VolumeKind::Local`].
+/// It only reads the mounted disks list, so it's cheap enough to be called by jobs.
+pub fn volume_kind_of(path: impl AsRef<Path>) -> VolumeKind {
+	let mut system = System::new();
+	system.refresh_disks_list();
+
+	system
+		.disks()
+		.iter()
+		.filter(|disk| path.as_ref().starts_with(disk.mount_point()))
+		.max_by_key(|disk| disk.mount_point().as_os_str().len())
+		.map(|disk| {
+			VolumeKind::classify(
+				&String::from_utf8_lossy(disk.file_system()),
+				disk.is_removable(),
+			)
+		})
+		.unwrap_or_default()
+}
+
+/// VolumeThroughput is the measured sequential speed of a volume, in MB/s.
+#[derive(Serialize, Debug, Clone, Type)]
+pub struct VolumeThroughput {
+	pub write_mb_per_sec: f64,
+	/// read_mb_per_sec can be inflated by the OS page cache, so it's an upper bound.
+	pub read_mb_per_sec: f64,
+}
+
+/// Size of the file written and read back by [`probe_throughput`]
+const THROUGHPUT_PROBE_SIZE: usize = 16 * 1024 * 1024;
+const THROUGHPUT_PROBE_FILE_NAME: &str = ".sd_throughput_probe";
+
+/// probe_throughput measures the speed of the volume holding `dir` by writing a temporary file into it and reading it back.
+pub async fn probe_throughput(dir: impl AsRef<Path>) -> Result<VolumeThroughput, VolumeError> {
+	let probe_path = dir.as_ref().join(THROUGHPUT_PROBE_FILE_NAME);
+	let result = measure_throughput(&probe_path).await;
+	// Always clean up, even if the measurement failed halfway
+	fs::remove_file(&probe_path).await.ok();
+
+	result
+}
+
+/// probe_data returns non repeating bytes, so compressing file systems can't skew measurements.
+fn probe_data(size: usize) -> Vec<u8> {
+	(0..size)
+		.map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+		.collect()
+}
+
+async fn measure_throughput(probe_path: &Path) -> Result<VolumeThroughput, VolumeError> {
+	let data = probe_data(THROUGHPUT_PROBE_SIZE);
+	let megabytes = THROUGHPUT_PROBE_SIZE as f64 / (1024.0 * 1024.0);
+
+	let start = Instant::now();
+	let mut file = fs::File::create(probe_path).await?;
+	file.write_all(&data).await?;
+	file.sync_all().await?;
+	let write_mb_per_sec = megabytes / start.elapsed().as_secs_f64();
+	drop(file);
+
+	let mut buffer = Vec::with_capacity(THROUGHPUT_PROBE_SIZE);
+	let start = Instant::now();
+	fs::File::open(probe_path)
+		.await?
+		.read_to_end(&mut buffer)
+		.await?;
+	let read_mb_per_sec = megabytes / start.elapsed().as_secs_f64();
+
+	Ok(VolumeThroughput {
+		write_mb_per_sec,
+		read_mb_per_sec,
+	})
+}
+
+/// VolumeBenchmark is the result of a read benchmark of a volume.
+#[derive(Serialize, Debug, Clone, Type)]
+pub struct VolumeBenchmark {
+	pub mount_point: String,
+	pub sequential_read_mbps: f64,
+	pub random_read_iops: f64,
+	pub date_created: chrono::DateTime<chrono::FixedOffset>,
+}
+
+impl From<volume_benchmark::Data> for VolumeBenchmark {
+	fn from(data: volume_benchmark::Data) -> Self {
+		Self {
+			mount_point: data.mount_point,
+			sequential_read_mbps: data.sequential_read_mbps,
+			random_read_iops: data.random_read_iops,
+			date_created: data.date_created,
+		}
+	}
+}
+
+impl VolumeBenchmark {
+	/// max_concurrency is how many files jobs should read at the same time from the benchmarked volume.
+	pub fn max_concurrency(&self) -> usize {
+		// Each reader is assumed to need around 50 random reads per second to not starve the others
+		((self.random_read_iops / 50.0) as usize).clamp(2, 100)
+	}
+}
+
+/// Size of the file used by [`benchmark_volume`], big enough to not be served from the disk cache only
+const BENCHMARK_FILE_SIZE: usize = 64 * 1024 * 1024;
+const BENCHMARK_FILE_NAME: &str = ".sd_benchmark";
+const BENCHMARK_CHUNK_SIZE: usize = 1024 * 1024;
+const BENCHMARK_RANDOM_READ_SIZE: usize = 4 * 1024;
+/// How long random reads are issued for
+const BENCHMARK_RANDOM_DURATION: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// benchmark_volume runs a short sequential and random read test against the volume mounted at `mount_point`,
+/// and stores the result in the library so jobs can tune their concurrency.
+/// The results can be inflated by the OS page cache, so they are best used to compare volumes with each other.
+pub async fn benchmark_volume(
+	library: &Library,
+	mount_point: String,
+) -> Result<VolumeBenchmark, VolumeError> {
+	let benchmark_path = Path::new(&mount_point).join(BENCHMARK_FILE_NAME);
+	let result = measure_reads(&benchmark_path).await;
+	// Always clean up, even if the benchmark failed halfway
+	fs::remove_file(&benchmark_path).await.ok();
+	let (sequential_read_mbps, random_read_iops) = result?;
+
+	Ok(library
+		.db
+		.volume_benchmark()
+		.create(
+			library.node_local_id,
+			mount_point,
+			sequential_read_mbps,
+			random_read_iops,
+			vec![],
+		)
+		.exec()
+		.await?
+		.into())
+}
+
+async fn measure_reads(benchmark_path: &Path) -> Result<(f64, f64), VolumeError> {
+	let mut file = fs::File::create(benchmark_path).await?;
+	file.write_all(&probe_data(BENCHMARK_FILE_SIZE)).await?;
+	file.sync_all().await?;
+	drop(file);
+
+	let mut file = fs::File::open(benchmark_path).await?;
+	let mut buffer = vec![0; BENCHMARK_CHUNK_SIZE];
+	let start = Instant::now();
+	while file.read(&mut buffer).await? > 0 {}
+	let sequential_read_mbps =
+		BENCHMARK_FILE_SIZE as f64 / (1024.0 * 1024.0) / start.elapsed().as_secs_f64();
+
+	// xorshift is enough to spread the reads, we don't need a proper random generator
+	let mut offset_seed = 0x2545_f491_4f6c_dd1d_u64;
+	let blocks = (BENCHMARK_FILE_SIZE / BENCHMARK_RANDOM_READ_SIZE) as u64;
+	let mut buffer = vec![0; BENCHMARK_RANDOM_READ_SIZE];
+	let mut reads = 0u64;
+	let start = Instant::now();
+	while start.elapsed() < BENCHMARK_RANDOM_DURATION {
+		offset_seed ^= offset_seed << 13;
+		offset_seed ^= offset_seed >> 7;
+		offset_seed ^= offset_seed << 17;
+
+		file.seek(SeekFrom::Start(
+			(offset_seed % blocks) * BENCHMARK_RANDOM_READ_SIZE as u64,
+		))
+		.await?;
+		file.read_exact(&mut buffer).await?;
+		reads += 1;
+	}
+	let random_read_iops = reads as f64 / start.elapsed().as_secs_f64();
+
+	Ok((sequential_read_mbps, random_read_iops))
+}
+
+/// list_benchmarks returns the latest benchmark of every volume of this node.
+pub async fn list_benchmarks(library: &Library) -> Result<Vec<VolumeBenchmark>, VolumeError> {
+	let mut benchmarks = HashMap::<String, VolumeBenchmark>::new();
+
+	for benchmark in library
+		.db
+		.volume_benchmark()
+		.find_many(vec![volume_benchmark::node_id::equals(
+			library.node_local_id,
+		)])
+		.order_by(volume_benchmark::date_created::order(Direction::Asc))
+		.exec()
+		.await?
+	{
+		// Later benchmarks replace older ones
+		benchmarks.insert(benchmark.mount_point.clone(), benchmark.into());
+	}
+
+	Ok(benchmarks.into_values().collect())
+}
+
+/// job_concurrency returns how many files jobs should read at the same time from the volume holding `path`,
+/// based on its latest benchmark or, if it was never benchmarked, on its [`VolumeKind`].
+pub async fn job_concurrency(library: &Library, path: impl AsRef<Path>) -> usize {
+	match list_benchmarks(library).await {
+		Ok(benchmarks) => benchmarks
+			.iter()
+			.filter(|benchmark| path.as_ref().starts_with(&benchmark.mount_point))
+			.max_by_key(|benchmark| benchmark.mount_point.len())
+			.map(VolumeBenchmark::max_concurrency),
+		Err(e) => {
+			error!("Failed to fetch volume benchmarks: {e:#?}");
+			None
+		}
+	}
+	.unwrap_or_else(|| volume_kind_of(path).max_concurrency())
+}
+
+/// VolumeSmart are the SMART attributes of a disk which give an early notice of failure.
+/// They are read with `smartctl`, so any of them may be missing depending on the disk and the platform.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Type)]
+pub struct VolumeSmart {
+	/// passed is the overall SMART self-assessment of the disk.
+	pub passed: Option<bool>,
+	pub reallocated_sectors: Option<u32>,
+	/// wear_level is the percentage of the rated endurance of a SSD that has been used. It can go above 100.
+	pub wear_level: Option<u32>,
+	/// temperature is the current temperature of the disk, in Celsius.
+	pub temperature: Option<i32>,
+}
+
+/// Temperature in Celsius above which a disk is considered at risk
+const SMART_MAX_TEMPERATURE: i32 = 60;
+/// Wear level percentage above which a SSD is considered close to the end of its life
+const SMART_MAX_WEAR_LEVEL: u32 = 90;
+
+impl VolumeSmart {
+	pub fn has_warning(&self) -> bool {
+		self.passed == Some(false)
+			|| self.reallocated_sectors.map_or(false, |count| count > 0)
+			|| self
+				.wear_level
+				.map_or(false, |wear| wear >= SMART_MAX_WEAR_LEVEL)
+			|| self
+				.temperature
+				.map_or(false, |temp| temp >= SMART_MAX_TEMPERATURE)
+	}
+
+	fn from_smartctl_json(json: &serde_json::Value) -> Self {
+		// ATA disks report their attributes in a table, NVMe disks in a health log
+		let ata_attribute = |id: u64| {
+			json["ata_smart_attributes"]["table"]
+				.as_array()?
+				.iter()
+				.find(|attribute| attribute["id"].as_u64() == Some(id))
+				.and_then(|attribute| attribute["raw"]["value"].as_u64())
+		};
+		let nvme_log = &json["nvme_smart_health_information_log"];
+
+		Self {
+			passed: json["smart_status"]["passed"].as_bool(),
+			// 5: Reallocated Sectors Count
+			reallocated_sectors: ata_attribute(5)
+				.or_else(|| nvme_log["media_errors"].as_u64())
+				.map(|count| count as u32),
+			// 177: Wear Leveling Count, 233: Media Wearout Indicator
+			wear_level: nvme_log["percentage_used"]
+				.as_u64()
+				.or_else(|| {
+					json["ata_smart_attributes"]["table"]
+						.as_array()?
+						.iter()
+						.find(|attribute| matches!(attribute["id"].as_u64(), Some(177 | 233)))
+						.and_then(|attribute| attribute["value"].as_u64())
+						// ATA normalized values count down from 100
+						.map(|value| 100u64.saturating_sub(value))
+				})
+				.map(|wear| wear as u32),
+			temperature: json["temperature"]["current"]
+				.as_i64()
+				.map(|temp| temp as i32),
+		}
+	}
+}
+
+/// disk_device returns the whole disk device for a partition device name, as SMART attributes belong to disks.
+#[cfg(target_os = "linux")]
+fn disk_device(partition: &str) -> Option<String> {
+	let name = partition.strip_prefix("/dev/")?;
+	let sys_path = std::path::Path::new("/sys/class/block").join(name);
+
+	if sys_path.join("partition").exists() {
+		// The partition's sysfs entry lives inside the directory of its parent disk
+		let parent = std::fs::canonicalize(&sys_path).ok()?;
+		let disk = parent.parent()?.file_name()?.to_str()?;
+		Some(format!("/dev/{disk}"))
+	} else {
+		Some(partition.to_string())
+	}
+}
+
+#[cfg(not(target_os = "linux"))]
+fn disk_device(_partition: &str) -> Option<String> {
+	// Mapping a volume to its physical disk isn't supported on this platform yet
+	None
+}
+
+/// volume_uuid returns the file system UUID of a volume, from its device on Linux and its mount point elsewhere.
+#[cfg(target_os = "linux")]
+fn volume_uuid(device: &str, _mount_point: &str) -> Option<String> {
+	let device = std::fs::canonicalize(device).ok()?;
+
+	std::fs::read_dir("/dev/disk/by-uuid")
+		.ok()?
+		.flatten()
+		.find(|entry| {
+			std::fs::canonicalize(entry.path())
+				.map(|target| target == device)
+				.unwrap_or(false)
+		})
+		.and_then(|entry| entry.file_name().to_str().map(str::to_string))
+}
+
+#[cfg(target_os = "macos")]
+fn volume_uuid(_device: &str, mount_point: &str) -> Option<String> {
+	let output = Command::new("diskutil")
+		.args(["info", mount_point])
+		.output()
+		.ok()?;
+
+	String::from_utf8(output.stdout)
+		.ok()?
+		.lines()
+		.find_map(|line| line.trim().strip_prefix("Volume UUID:"))
+		.map(|uuid| uuid.trim().to_string())
+}
+
+#[cfg(target_os = "windows")]
+fn volume_uuid(_device: &str, mount_point: &str) -> Option<String> {
+	// Windows volumes are identified by their serial number, e.g. "Volume Serial Number is 1A2B-3C4D"
+	let output = Command::new("cmd")
+		.args(["/C", &format!("vol {}", mount_point.trim_end_matches('\\'))])
+		.output()
+		.ok()?;
+
+	String::from_utf8(output.stdout)
+		.ok()?
+		.lines()
+		.find(|line| line.contains("Serial Number"))
+		.and_then(|line| line.rsplit_once(" is "))
+		.map(|(_, serial)| serial.trim().to_string())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn volume_uuid(_device: &str, _mount_point: &str) -> Option<String> {
+	None
+}
+
+/// read_smart reads the SMART attributes of a disk with `smartctl`, if it is installed and allowed to access the disk.
+fn read_smart(device: &str) -> Option<VolumeSmart> {
+	let output = Command::new("smartctl")
+		.args(["--json", "--all", device])
+		.output()
+		.ok()?;
+
+	// smartctl uses its exit code as a bitmask, so we rely on the output instead
+	let json = serde_json::from_slice::<serde_json::Value>(&output.stdout).ok()?;
+	if json["smartctl"]["exit_status"]
+		.as_u64()
+		.map_or(true, |status| status & 0b11 != 0)
+	{
+		// Bits 0 and 1 mean the command line didn't parse or the device couldn't be opened
+		return None;
+	}
+
+	Some(VolumeSmart::from_smartctl_json(&json))
 }
 
 #[derive(Error, Debug)]
@@ -30,6 +469,8 @@ pub enum VolumeError {
 	DatabaseErr(#[from] prisma_client_rust::QueryError),
 	#[error("FromUtf8Error: {0}")]
 	FromUtf8Error(#[from] std::string::FromUtf8Error),
+	#[error("I/O error: {0}")]
+	IOError(#[from] std::io::Error),
 }
 
 impl From<VolumeError> for rspc::Error {
@@ -80,6 +521,20 @@ pub async fn save_volume(library: &Library) -> Result<(), VolumeError> {
 
 // TODO: Error handling in this function
 pub fn get_volumes() -> Result<Vec<Volume>, VolumeError> {
+	list_volumes(true)
+}
+
+/// find_volume_by_uuid returns the mounted volume with the given file system UUID, wherever it's mounted now.
+pub fn find_volume_by_uuid(uuid: &str) -> Result<Option<Volume>, VolumeError> {
+	Ok(list_volumes(false)?
+		.into_iter()
+		.find(|volume| volume.uuid.as_deref() == Some(uuid)))
+}
+
+fn list_volumes(with_smart: bool) -> Result<Vec<Volume>, VolumeError> {
+	// Many volumes can share the same disk, so we only read its SMART attributes once
+	let mut smart_per_disk = HashMap::<String, Option<VolumeSmart>>::new();
+
 	System::new_all()
 		.disks()
 		.iter()
@@ -125,15 +580,37 @@ pub fn get_volumes() -> Result<Vec<Volume>, VolumeError> {
 				}
 			}
 
-			(!mount_point.starts_with("/System")).then_some(Ok(Volume {
+			if mount_point.starts_with("/System") {
+				return None;
+			}
+
+			let smart = disk
+				.name()
+				.to_str()
+				.filter(|_| with_smart)
+				.and_then(disk_device)
+				.and_then(|device| {
+					smart_per_disk
+						.entry(device)
+						.or_insert_with_key(|device| read_smart(device))
+						.clone()
+				});
+
+			let uuid = volume_uuid(disk.name().to_str().unwrap_or_default(), &mount_point);
+
+			Some(Ok(Volume {
 				name,
+				uuid,
 				is_root_filesystem: mount_point == "/",
 				mount_point,
 				total_capacity,
 				available_capacity,
 				is_removable,
+				kind: VolumeKind::classify(&file_system, is_removable),
 				disk_type: Some(disk_type),
 				file_system: Some(file_system),
+				health_warning: smart.as_ref().map_or(false, VolumeSmart::has_warning),
+				smart,
 			}))
 		})
 		.collect::<Result<Vec<_>, _>>()
@@ -34,6 +34,10 @@ pub enum ThumbnailerError {
 	InvalidSeekPercentage(f32),
 	#[error("Received an invalid quality, expected range [0.0, 100.0], received: {0}")]
 	InvalidQuality(f32),
+	#[error("Received an invalid frame count, expected at least 1, received: {0}")]
+	InvalidFrameCount(u32),
+	#[error("Failed to encode animation")]
+	AnimationEncodeError,
 	#[error("Background task failed: {0}")]
 	BackgroundTaskFailed(#[from] JoinError),
 }
@@ -248,6 +248,17 @@ impl MovieDecoder {
 		Ok(())
 	}
 
+	/// The position in the video of the last decoded frame, relative to the start of its stream
+	pub(crate) fn get_frame_timestamp(&self) -> Duration {
+		let time_base = unsafe { (*self.video_stream).time_base };
+		if time_base.den == 0 {
+			return Duration::ZERO;
+		}
+
+		let timestamp = unsafe { (*self.frame).best_effort_timestamp }.max(0);
+		Duration::from_secs_f64(timestamp as f64 * time_base.num as f64 / time_base.den as f64)
+	}
+
 	pub(crate) fn get_video_duration(&self) -> Duration {
 		Duration::from_secs(unsafe { (*self.format_context).duration as u64 / AV_TIME_BASE as u64 })
 	}
@@ -1,8 +1,8 @@
 use crate::{film_strip_filter, MovieDecoder, ThumbnailSize, ThumbnailerError, VideoFrame};
 
-use std::{ops::Deref, path::Path};
+use std::{ops::Deref, path::Path, time::Duration};
 use tokio::{fs, task::spawn_blocking};
-use webp::Encoder;
+use webp::{AnimEncoder, AnimFrame, Encoder, WebPConfig};
 
 /// `Thumbnailer` struct holds data from a `ThumbnailerBuilder`, exposing methods
 /// to generate thumbnails from video files.
@@ -71,6 +71,158 @@ impl Thumbnailer {
 		})
 		.await?
 	}
+
+	/// Processes an video input file and returns a webp encoded sprite sheet as bytes, with `frames` frames
+	/// taken at regular intervals laid side by side
+	pub async fn process_sprite_sheet_to_webp_bytes(
+		&self,
+		video_file_path: impl AsRef<Path>,
+		frames: u32,
+	) -> Result<Vec<u8>, ThumbnailerError> {
+		if frames == 0 {
+			return Err(ThumbnailerError::InvalidFrameCount(frames));
+		}
+
+		let video_file_path = video_file_path.as_ref().to_path_buf();
+		let size = self.builder.size;
+		let maintain_aspect_ratio = self.builder.maintain_aspect_ratio;
+		let quality = self.builder.quality;
+
+		spawn_blocking(move || -> Result<Vec<u8>, ThumbnailerError> {
+			// Embedded cover art is the same picture everywhere in the video, so frames are always decoded
+			let mut decoder = MovieDecoder::new(video_file_path, false)?;
+			decoder.decode_video_frame()?;
+
+			let duration = decoder.get_video_duration().as_secs_f32();
+			let mut video_frame = VideoFrame::default();
+			let mut sheet = Vec::new();
+			let (mut width, mut height) = (0, 0);
+
+			for index in 0..frames {
+				// Frames are taken in the middle of each interval, avoiding the black first and last frames
+				let seconds = (duration * (index as f32 + 0.5) / frames as f32) as i64;
+
+				// A frame that can't be reached repeats the previous one, so the sheet keeps its layout,
+				// and the first frame falls back to the start of the video
+				if decoder.seek(seconds).is_ok() || index == 0 {
+					decoder.get_scaled_video_frame(
+						Some(size),
+						maintain_aspect_ratio,
+						&mut video_frame,
+					)?;
+				}
+
+				if index == 0 {
+					(width, height) = (video_frame.width, video_frame.height);
+					sheet = vec![0; (width * frames * height * 3) as usize];
+				}
+				copy_frame_to_sheet(&video_frame, &mut sheet, index, (width, height), frames);
+			}
+
+			Ok(Encoder::from_rgb(&sheet, width * frames, height)
+				.encode(quality)
+				.deref()
+				.to_vec())
+		})
+		.await?
+	}
+
+	/// Processes an video input file and returns a looping webp animation as bytes, made of up to `frames` frames
+	/// taken every `frame_interval` from the start of the video
+	pub async fn process_to_animated_webp_bytes(
+		&self,
+		video_file_path: impl AsRef<Path>,
+		frames: u32,
+		frame_interval: Duration,
+	) -> Result<Vec<u8>, ThumbnailerError> {
+		if frames == 0 {
+			return Err(ThumbnailerError::InvalidFrameCount(frames));
+		}
+
+		let video_file_path = video_file_path.as_ref().to_path_buf();
+		let size = self.builder.size;
+		let maintain_aspect_ratio = self.builder.maintain_aspect_ratio;
+		let quality = self.builder.quality;
+
+		spawn_blocking(move || -> Result<Vec<u8>, ThumbnailerError> {
+			let mut decoder = MovieDecoder::new(video_file_path, false)?;
+			decoder.decode_video_frame()?;
+
+			let start = decoder.get_frame_timestamp();
+			let mut next_frame_at = Duration::ZERO;
+			let mut video_frame = VideoFrame::default();
+			let mut captured = Vec::with_capacity(frames as usize);
+			let (mut width, mut height) = (0, 0);
+
+			// Frames are decoded one after the other, as seeking only lands on keyframes
+			loop {
+				if decoder.get_frame_timestamp().saturating_sub(start) >= next_frame_at {
+					decoder.get_scaled_video_frame(
+						Some(size),
+						maintain_aspect_ratio,
+						&mut video_frame,
+					)?;
+
+					if captured.is_empty() {
+						(width, height) = (video_frame.width, video_frame.height);
+					}
+					let mut frame = vec![0; (width * height * 3) as usize];
+					copy_frame_to_sheet(&video_frame, &mut frame, 0, (width, height), 1);
+					captured.push(frame);
+
+					if captured.len() == frames as usize {
+						break;
+					}
+					next_frame_at += frame_interval;
+				}
+
+				// Videos shorter than the animation end it early
+				if decoder.decode_video_frame().is_err() {
+					break;
+				}
+			}
+
+			let mut config =
+				WebPConfig::new().map_err(|_| ThumbnailerError::AnimationEncodeError)?;
+			config.quality = quality;
+
+			let mut encoder = AnimEncoder::new(width, height, &config);
+			// Animations loop forever
+			encoder.set_loop_count(0);
+			for (index, frame) in captured.iter().enumerate() {
+				encoder.add_frame(AnimFrame::from_rgb(
+					frame,
+					width,
+					height,
+					(frame_interval.as_millis() * index as u128) as i32,
+				));
+			}
+
+			Ok(encoder.encode().deref().to_vec())
+		})
+		.await?
+	}
+}
+
+/// Copies the RGB pixels of a frame to the column `index` of a sprite sheet of `frames` frames of `width` by `height`
+/// pixels. Frames of any other size are cropped or padded to fit.
+fn copy_frame_to_sheet(
+	video_frame: &VideoFrame,
+	sheet: &mut [u8],
+	index: u32,
+	(width, height): (u32, u32),
+	frames: u32,
+) {
+	let sheet_line_size = (width * frames * 3) as usize;
+	let column_offset = (index * width * 3) as usize;
+	let row_len = (width.min(video_frame.width) * 3) as usize;
+
+	for y in 0..height.min(video_frame.height) as usize {
+		let source = y * video_frame.line_size as usize;
+		let destination = y * sheet_line_size + column_offset;
+		sheet[destination..destination + row_len]
+			.copy_from_slice(&video_frame.data[source..source + row_len]);
+	}
 }
 
 /// `ThumbnailerBuilder` struct holds data to build a `Thumbnailer` struct, exposing many methods
@@ -75,6 +75,7 @@ extension_category_enum! {
 		Svg = [0x3C, 0x73, 0x76, 0x67],
 		Ico = [0x00, 0x00, 0x01, 0x00],
 		Heic = [0x00, 0x00, 0x00, 0x18, 0x66, 0x74, 0x79, 0x70, 0x68, 0x65, 0x69, 0x63],
+		Heif = [0x66, 0x74, 0x79, 0x70, 0x6D, 0x69, 0x66, 0x31] + 4,
 		Raw = [],
 		Akw = [0x41, 0x4B, 0x57, 0x42],
 		Dng = [0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00, 0x44, 0x4E, 0x47, 0x00],
@@ -82,12 +83,13 @@ extension_category_enum! {
 		Dcr = [0x49, 0x49, 0x2A, 0x00, 0x10, 0x00, 0x00, 0x00, 0x44, 0x43, 0x52, 0x00],
 		Nwr = [0x49, 0x49, 0x2A, 0x00, 0x10, 0x00, 0x00, 0x00, 0x4E, 0x57, 0x52, 0x00],
 		Nef = [0x49, 0x49, 0x2A, 0x00, 0x08, 0x00, 0x00, 0x00, 0x4E, 0x45, 0x46, 0x00],
+		Arw = [0x49, 0x49, 0x2A, 0x00],
 	}
 }
 
 // audio extensions
 extension_category_enum! {
-	AudioExtension _ALL_AUDIO_EXTENSIONS {
+	AudioExtension ALL_AUDIO_EXTENSIONS {
 		Mp3 = [0x49, 0x44, 0x33],
 		Mp2 = [0xFF, 0xFB] | [0xFF, 0xFD],
 		M4a = [0x66, 0x74, 0x79, 0x70, 0x4D, 0x34, 0x41, 0x20] + 4,
@@ -145,7 +147,7 @@ extension_category_enum! {
 
 // document extensions
 extension_category_enum! {
-	DocumentExtension _ALL_DOCUMENT_EXTENSIONS {
+	DocumentExtension ALL_DOCUMENT_EXTENSIONS {
 		Pdf = [0x25, 0x50, 0x44, 0x46, 0x2D],
 		Key = [0x50, 0x4B, 0x03, 0x04],
 		Pages = [0x50, 0x4B, 0x03, 0x04],
@@ -161,6 +163,7 @@ extension_category_enum! {
 		Odp = [0x50, 0x4B, 0x03, 0x04],
 		Ics = [0x42, 0x45, 0x47, 0x49, 0x4E, 0x3A, 0x56, 0x43, 0x41, 0x52, 0x44],
 		Hwp = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1],
+		Epub = [0x50, 0x4B, 0x03, 0x04],
 	}
 }
 
@@ -206,7 +209,7 @@ extension_category_enum! {
 
 // font extensions
 extension_category_enum! {
-	FontExtension _ALL_FONT_EXTENSIONS {
+	FontExtension ALL_FONT_EXTENSIONS {
 		Ttf = [0x00, 0x01, 0x00, 0x00, 0x00],
 		Otf = [0x4F, 0x54, 0x54, 0x4F, 0x00],
 		Woff = [0x77, 0x4F, 0x46, 0x46],
@@ -1,4 +1,4 @@
-import { Clipboard, FileX, Image, Plus, Repeat, Share, ShieldCheck } from 'phosphor-react';
+import { Archive, Clipboard, FileX, Image, Plus, Repeat, Share, ShieldCheck } from 'phosphor-react';
 import { PropsWithChildren, useMemo } from 'react';
 import { useLibraryMutation } from '@sd/client';
 import { ContextMenu as CM } from '@sd/ui';
@@ -42,9 +42,10 @@ export default (props: PropsWithChildren) => {
 
 	const generateThumbsForLocation = useLibraryMutation('jobs.generateThumbsForLocation');
 	const objectValidator = useLibraryMutation('jobs.objectValidator');
+	const indexArchives = useLibraryMutation('jobs.indexArchives');
 	const rescanLocation = useLibraryMutation('locations.fullRescan');
-	const copyFiles = useLibraryMutation('files.copyFiles');
-	const cutFiles = useLibraryMutation('files.cutFiles');
+	const copyFiles = useLibraryMutation('files.copy');
+	const moveFiles = useLibraryMutation('files.move');
 
 	return (
 		<div className="relative">
@@ -84,18 +85,20 @@ export default (props: PropsWithChildren) => {
 							store.locationId &&
 								copyFiles.mutate({
 									source_location_id: store.cutCopyState.sourceLocationId,
-									source_path_id: store.cutCopyState.sourcePathId,
+									source_path_ids: [store.cutCopyState.sourcePathId],
 									target_location_id: store.locationId,
 									target_path: params.path,
-									target_file_name_suffix: null
+									target_file_name_suffix: null,
+									conflict_policy: 'Rename'
 								});
 						} else {
 							store.locationId &&
-								cutFiles.mutate({
+								moveFiles.mutate({
 									source_location_id: store.cutCopyState.sourceLocationId,
-									source_path_id: store.cutCopyState.sourcePathId,
+									source_path_ids: [store.cutCopyState.sourcePathId],
 									target_location_id: store.locationId,
-									target_path: params.path
+									target_path: params.path,
+									conflict_policy: 'Rename'
 								});
 						}
 					}}
@@ -130,6 +133,14 @@ export default (props: PropsWithChildren) => {
 						label="Generate Checksums"
 						icon={ShieldCheck}
 					/>
+					<CM.Item
+						onClick={() =>
+							store.locationId &&
+							indexArchives.mutate({ id: store.locationId, path: params.path || null })
+						}
+						label="Index Archives"
+						icon={Archive}
+					/>
 				</CM.SubMenu>
 
 				<CM.Separator />
@@ -14,7 +14,9 @@ import {
 } from 'phosphor-react';
 import { PropsWithChildren } from 'react';
 import {
+	ConvertFormat,
 	ExplorerItem,
+	ObjectKind,
 	isObject,
 	useLibraryContext,
 	useLibraryMutation,
@@ -48,6 +50,27 @@ export default ({ data, ...props }: Props) => {
 	const hasMountedKeys = mountedKeys.data?.length ?? 0 > 0;
 
 	const copyFiles = useLibraryMutation('files.copyFiles');
+	const compressFiles = useLibraryMutation('files.compress');
+	const extractFiles = useLibraryMutation('files.extract');
+	const convertFiles = useLibraryMutation('files.convert');
+
+	const materializedPath = data.type === 'Path' ? data.item.materialized_path.toLowerCase() : '';
+	const canExtract = materializedPath.endsWith('.zip') || materializedPath.endsWith('.tar.zst');
+
+	const objectKind = ObjectKind[objectData?.kind || 0];
+	const convertFormats: [string, ConvertFormat][] =
+		objectKind === 'Image'
+			? [
+					['PNG', 'Png'],
+					['JPEG', 'Jpeg'],
+					['WebP', 'Webp']
+			  ]
+			: objectKind === 'Video'
+			? [
+					['MP4', 'Mp4'],
+					['WebM', 'Webm']
+			  ]
+			: [];
 
 	return (
 		<div className="relative">
@@ -92,10 +115,11 @@ export default ({ data, ...props }: Props) => {
 					onClick={() => {
 						copyFiles.mutate({
 							source_location_id: store.locationId!,
-							source_path_id: data.item.id,
+							source_path_ids: [data.item.id],
 							target_location_id: store.locationId!,
 							target_path: params.path,
-							target_file_name_suffix: ' copy'
+							target_file_name_suffix: ' copy',
+							conflict_policy: 'Rename'
 						});
 					}}
 				/>
@@ -203,11 +227,50 @@ export default ({ data, ...props }: Props) => {
 							}
 						}}
 					/>
-					<ContextMenu.Item label="Compress" icon={Package} keybind="⌘B" />
-					<ContextMenu.SubMenu label="Convert to" icon={ArrowBendUpRight}>
-						<ContextMenu.Item label="PNG" />
-						<ContextMenu.Item label="WebP" />
-					</ContextMenu.SubMenu>
+					<ContextMenu.Item
+						label="Compress"
+						icon={Package}
+						keybind="⌘B"
+						onClick={() => {
+							compressFiles.mutate({
+								location_id: store.locationId!,
+								path_id: data.item.id,
+								target_path: params.path,
+								format: 'Zip'
+							});
+						}}
+					/>
+					{canExtract && (
+						<ContextMenu.Item
+							label="Extract here"
+							icon={Package}
+							onClick={() => {
+								extractFiles.mutate({
+									location_id: store.locationId!,
+									path_id: data.item.id,
+									target_path: params.path
+								});
+							}}
+						/>
+					)}
+					{convertFormats.length > 0 && (
+						<ContextMenu.SubMenu label="Convert to" icon={ArrowBendUpRight}>
+							{convertFormats.map(([label, format]) => (
+								<ContextMenu.Item
+									key={format}
+									label={label}
+									onClick={() => {
+										convertFiles.mutate({
+											location_id: store.locationId!,
+											path_ids: [data.item.id],
+											format,
+											quality: null
+										});
+									}}
+								/>
+							))}
+						</ContextMenu.SubMenu>
+					)}
 					<ContextMenu.Item label="Rescan Directory" icon={Package} />
 					<ContextMenu.Item label="Regen Thumbnails" icon={Package} />
 					<ContextMenu.Item
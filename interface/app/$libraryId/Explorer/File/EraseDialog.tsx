@@ -15,7 +15,7 @@ const schema = z.object({
 export default (props: Props) => {
 	const dialog = useDialog(props);
 
-	const eraseFile = useLibraryMutation('files.eraseFiles');
+	const eraseFile = useLibraryMutation('files.secureDelete');
 
 	const form = useZodForm({
 		schema,
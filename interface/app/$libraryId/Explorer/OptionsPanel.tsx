@@ -67,6 +67,18 @@ export default () => {
 					}}
 				/>
 			</div>
+			<div className="flex w-full">
+				<RadixCheckbox
+					checked={explorerStore.showHiddenFiles}
+					label="Show hidden files"
+					name="showHiddenFiles"
+					onCheckedChange={(value) => {
+						if (typeof value === 'boolean') {
+							getExplorerStore().showHiddenFiles = value;
+						}
+					}}
+				/>
+			</div>
 		</div>
 	);
 };
@@ -2,7 +2,7 @@ import { useVirtualizer } from '@tanstack/react-virtual';
 import { memo, useCallback, useEffect, useLayoutEffect, useRef, useState } from 'react';
 import { useSearchParams } from 'react-router-dom';
 import { useKey, useOnWindowResize } from 'rooks';
-import { ExplorerContext, ExplorerItem, isPath } from '@sd/client';
+import { ExplorerContext, ExplorerItem, isArchive, isPath } from '@sd/client';
 import { ExplorerLayoutMode, getExplorerStore, useExplorerStore } from '~/hooks/useExplorerStore';
 import FileItem from './File/Item';
 import FileRow from './File/Row';
@@ -14,9 +14,10 @@ interface Props {
 	context: ExplorerContext;
 	data: ExplorerItem[];
 	onScroll?: (posY: number) => void;
+	onLoadMore?: () => void;
 }
 
-export const VirtualizedList = memo(({ data, onScroll }: Props) => {
+export const VirtualizedList = memo(({ data, onScroll, onLoadMore }: Props) => {
 	const scrollRef = useRef<HTMLDivElement>(null);
 	const innerRef = useRef<HTMLDivElement>(null);
 
@@ -67,6 +68,13 @@ export const VirtualizedList = memo(({ data, onScroll }: Props) => {
 		measureElement: () => itemSize
 	});
 
+	// load the next page of the listing once its last row is rendered
+	const virtualRows = rowVirtualizer.getVirtualItems();
+	const lastVirtualRow = virtualRows[virtualRows.length - 1];
+	useEffect(() => {
+		if (lastVirtualRow && lastVirtualRow.index >= amountOfRows - 1) onLoadMore?.();
+	}, [lastVirtualRow?.index, amountOfRows, onLoadMore]);
+
 	// recalculate virtualizer when store values change that affect the virtual measurements
 	useEffect(() => {
 		rowVirtualizer.measure();
@@ -172,7 +180,12 @@ const WrappedItem = memo(({ item, index, isSelected, kind }: WrappedItemProps) =
 	const [_, setSearchParams] = useSearchParams();
 
 	const onDoubleClick = useCallback(() => {
-		if (isPath(item) && item.item.is_dir) setSearchParams({ path: item.item.materialized_path });
+		if (!isPath(item)) return;
+
+		// The indexed contents of archives are browsed like directories
+		if (item.item.is_dir) setSearchParams({ path: item.item.materialized_path });
+		else if (isArchive(item.item.materialized_path))
+			setSearchParams({ path: `${item.item.materialized_path}/` });
 	}, [item, setSearchParams]);
 
 	const onClick = useCallback(
@@ -8,6 +8,8 @@ import { VirtualizedList } from './VirtualizedList';
 
 interface Props {
 	data?: ExplorerData;
+	/** Called when the end of the listing is reached, to load its next page */
+	onLoadMore?: () => void;
 }
 
 export default function Explorer(props: Props) {
@@ -53,6 +55,7 @@ export default function Explorer(props: Props) {
 								data={props.data.items}
 								context={props.data.context}
 								onScroll={onScroll}
+								onLoadMore={props.onLoadMore}
 							/>
 						)}
 						{expStore.showInspector && (
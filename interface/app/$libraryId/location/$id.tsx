@@ -1,7 +1,7 @@
 import { useEffect } from 'react';
 import { useParams, useSearchParams } from 'react-router-dom';
-import { useLibraryMutation, useLibraryQuery } from '@sd/client';
-import { getExplorerStore } from '~/hooks/useExplorerStore';
+import { useExplorerData, useLibraryMutation } from '@sd/client';
+import { getExplorerStore, useExplorerStore } from '~/hooks/useExplorerStore';
 import Explorer from '../Explorer';
 
 export function useExplorerParams() {
@@ -20,6 +20,7 @@ export const Component = () => {
 
 	const quickRescan = useLibraryMutation('locations.quickRescan');
 	const explorerState = getExplorerStore();
+	const { showHiddenFiles } = useExplorerStore();
 
 	useEffect(() => {
 		explorerState.locationId = location_id;
@@ -28,19 +29,24 @@ export const Component = () => {
 
 	if (location_id === null) throw new Error(`location_id is null!`);
 
-	const explorerData = useLibraryQuery([
-		'locations.getExplorerData',
-		{
-			location_id,
-			path,
-			limit,
-			cursor: null
-		}
-	]);
+	const explorerData = useExplorerData({
+		location_id,
+		// The root directory is keyed like the scoped invalidations of its listing
+		path: path || '/',
+		limit,
+		filter: null,
+		show_hidden: showHiddenFiles
+	});
 
 	return (
 		<div className="relative flex w-full flex-col">
-			<Explorer data={explorerData.data} />
+			<Explorer
+				data={explorerData.data}
+				onLoadMore={() => {
+					if (explorerData.hasNextPage && !explorerData.isFetchingNextPage)
+						explorerData.fetchNextPage();
+				}}
+			/>
 		</div>
 	);
 };
@@ -31,7 +31,7 @@ export default function DeleteLibraryDialog(props: Props) {
 
 	const form = useZodForm({ schema: z.object({}) });
 
-	const onSubmit = form.handleSubmit(() => deleteLib.mutateAsync(props.libraryUuid));
+	const onSubmit = form.handleSubmit(() => deleteLib.mutateAsync({ id: props.libraryUuid, purge: true }));
 
 	return (
 		<Dialog
@@ -1,4 +1,4 @@
-import { Navigate, Outlet, RouteObject } from 'react-router-dom';
+import { Navigate, Outlet, RouteObject, useMatch } from 'react-router-dom';
 import { currentLibraryCache, useCachedLibraries, useInvalidateQuery } from '@sd/client';
 import libraryRoutes from './$libraryId';
 import onboardingRoutes from './onboarding';
@@ -21,8 +21,11 @@ const Index = () => {
 
 
 const Wrapper = () => {
+	const libraries = useCachedLibraries();
+	const libraryId = useMatch('/:libraryId/*')?.params.libraryId;
+
 	useKeybindHandler();
-	useInvalidateQuery();
+	useInvalidateQuery(libraries.data?.find((l) => l.uuid === libraryId)?.uuid ?? null);
 
 	return <Outlet/>
 }
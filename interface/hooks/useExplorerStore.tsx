@@ -18,6 +18,7 @@ const state = {
 	listItemSize: 40,
 	selectedRowIndex: 1,
 	showBytesInGridView: true,
+	showHiddenFiles: false,
 	tagAssignMode: false,
 	showInspector: false,
 	multiSelectIndexes: [] as number[],
@@ -3,11 +3,25 @@
 
 export type Procedures = {
     queries: 
+        { key: "bookmarks.list", input: LibraryArgs<null>, result: Bookmark[] } | 
         { key: "buildInfo", input: never, result: BuildInfo } | 
+        { key: "ext.list", input: never, result: ExtensionInfo[] } | 
+        { key: "ext.query", input: ExtensionProcedureArgs, result: any } | 
+        { key: "files.duplicates", input: LibraryArgs<DuplicatesArgs>, result: DuplicateGroup[] } | 
         { key: "files.get", input: LibraryArgs<GetArgs>, result: { id: number, pub_id: number[], name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, date_modified: string, date_indexed: string, file_paths: FilePath[], media_data: MediaData | null } | null } | 
+        { key: "files.listTrash", input: LibraryArgs<null>, result: TrashedFile[] } | 
+        { key: "files.mediaData", input: LibraryArgs<number>, result: MediaData | null } | 
+        { key: "files.previewBulkRename", input: LibraryArgs<BulkRenameArgs>, result: BulkRenameEntry[] } | 
+        { key: "files.similarImages", input: LibraryArgs<SimilarImagesArgs>, result: SimilarImage[] } | 
+        { key: "files.versions", input: LibraryArgs<number>, result: FileVersion[] } | 
+        { key: "files.videoPreviewStrip", input: LibraryArgs<VideoPreviewStripArgs>, result: VideoPreviewStrip | null } | 
         { key: "jobs.getHistory", input: LibraryArgs<null>, result: JobReport[] } | 
+        { key: "jobs.getQueued", input: LibraryArgs<null>, result: JobReport[] } | 
         { key: "jobs.getRunning", input: LibraryArgs<null>, result: JobReport[] } | 
+        { key: "jobs.history", input: LibraryArgs<JobHistoryArgs>, result: JobHistoryPage } | 
         { key: "jobs.isRunning", input: LibraryArgs<null>, result: boolean } | 
+        { key: "jobs.schedules.list", input: LibraryArgs<null>, result: JobSchedule[] } | 
+        { key: "jobs.statistics", input: LibraryArgs<null>, result: JobStatistics[] } | 
         { key: "keys.getDefault", input: LibraryArgs<null>, result: string | null } | 
         { key: "keys.getKey", input: LibraryArgs<string>, result: string } | 
         { key: "keys.getSecretKey", input: LibraryArgs<null>, result: string | null } | 
@@ -15,35 +29,85 @@ export type Procedures = {
         { key: "keys.isUnlocked", input: LibraryArgs<null>, result: boolean } | 
         { key: "keys.list", input: LibraryArgs<null>, result: StoredKey[] } | 
         { key: "keys.listMounted", input: LibraryArgs<null>, result: string[] } | 
+        { key: "library.activity", input: LibraryArgs<ActivityArgs>, result: ActivityPage } | 
+        { key: "library.getOverview", input: never, result: NodeOverview } | 
         { key: "library.getStatistics", input: LibraryArgs<null>, result: Statistics } | 
         { key: "library.list", input: never, result: LibraryConfigWrapped[] } | 
+        { key: "library.storageUsage", input: LibraryArgs<null>, result: LibraryStorageUsage } | 
+        { key: "library.templates.list", input: never, result: LibraryTemplate[] } | 
         { key: "locations.getById", input: LibraryArgs<number>, result: location_with_indexer_rules | null } | 
         { key: "locations.getExplorerData", input: LibraryArgs<LocationExplorerArgs>, result: ExplorerData } | 
         { key: "locations.indexer_rules.get", input: LibraryArgs<number>, result: IndexerRule } | 
         { key: "locations.indexer_rules.list", input: LibraryArgs<null>, result: IndexerRule[] } | 
         { key: "locations.indexer_rules.listForLocation", input: LibraryArgs<number>, result: IndexerRule[] } | 
+        { key: "locations.indexer_rules.test", input: LibraryArgs<IndexerRuleTestArgs>, result: IndexerRuleTestResult[] } | 
         { key: "locations.list", input: LibraryArgs<null>, result: { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, hidden: boolean, date_created: string, node: Node }[] } | 
         { key: "nodeState", input: never, result: NodeState } | 
+        { key: "nodes.cacheInfo", input: never, result: ThumbnailCacheInfo } | 
+        { key: "nodes.logs", input: LogQuery, result: LogEntry[] } | 
+        { key: "nodes.telemetry.get", input: never, result: TelemetryConfig } | 
+        { key: "nodes.telemetry.preview", input: never, result: TelemetryReport } | 
+        { key: "search.content", input: LibraryArgs<ContentSearchArgs>, result: ContentSearchResult[] } | 
+        { key: "search.paths", input: LibraryArgs<SearchPathsArgs>, result: SearchPaths } | 
+        { key: "search.saved.list", input: LibraryArgs<null>, result: SavedSearch[] } | 
+        { key: "search.saved.run", input: LibraryArgs<number>, result: object_with_file_paths[] } | 
         { key: "tags.get", input: LibraryArgs<number>, result: Tag | null } | 
         { key: "tags.getExplorerData", input: LibraryArgs<number>, result: ExplorerData } | 
         { key: "tags.getForObject", input: LibraryArgs<number>, result: Tag[] } | 
         { key: "tags.list", input: LibraryArgs<null>, result: Tag[] } | 
-        { key: "volumes.list", input: never, result: Volume[] },
+        { key: "users.list", input: never, result: NodeUser[] } | 
+        { key: "users.me", input: never, result: NodeUser | null } | 
+        { key: "volumes.benchmarks", input: LibraryArgs<null>, result: VolumeBenchmark[] } | 
+        { key: "volumes.diskUsage", input: LibraryArgs<DiskUsageArgs>, result: DiskUsageBreakdown } | 
+        { key: "volumes.list", input: never, result: Volume[] } | 
+        { key: "volumes.listForLocations", input: LibraryArgs<null>, result: LocationVolume[] },
     mutations: 
+        { key: "bookmarks.create", input: LibraryArgs<BookmarkCreateArgs>, result: number } | 
+        { key: "bookmarks.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "bookmarks.rename", input: LibraryArgs<BookmarkRenameArgs>, result: null } | 
+        { key: "bookmarks.reorder", input: LibraryArgs<number[]>, result: null } | 
+        { key: "ext.grant", input: GrantExtensionArgs, result: null } | 
+        { key: "ext.mutation", input: ExtensionProcedureArgs, result: any } | 
+        { key: "ext.reload", input: never, result: null } | 
+        { key: "ext.runStepHandler", input: LibraryArgs<RunStepHandlerArgs>, result: null } | 
+        { key: "files.batch", input: LibraryArgs<BatchMutation[]>, result: null } | 
+        { key: "files.bulkRename", input: LibraryArgs<BulkRenameArgs>, result: BulkRenameEntry[] } | 
+        { key: "files.compress", input: LibraryArgs<FileCompressorJobInit>, result: null } | 
+        { key: "files.convert", input: LibraryArgs<FileConverterJobInit>, result: null } | 
+        { key: "files.copy", input: LibraryArgs<FileCopierJobInit>, result: null } | 
         { key: "files.copyFiles", input: LibraryArgs<FileCopierJobInit>, result: null } | 
         { key: "files.cutFiles", input: LibraryArgs<FileCutterJobInit>, result: null } | 
         { key: "files.decryptFiles", input: LibraryArgs<FileDecryptorJobInit>, result: null } | 
-        { key: "files.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "files.delete", input: LibraryArgs<DeleteArgs>, result: null } | 
         { key: "files.deleteFiles", input: LibraryArgs<FileDeleterJobInit>, result: null } | 
+        { key: "files.deleteFromTrash", input: LibraryArgs<number>, result: null } | 
         { key: "files.duplicateFiles", input: LibraryArgs<FileCopierJobInit>, result: null } | 
+        { key: "files.eliminateDuplicates", input: LibraryArgs<DuplicateEliminatorJobInit>, result: null } | 
+        { key: "files.emptyTrash", input: LibraryArgs<null>, result: null } | 
         { key: "files.encryptFiles", input: LibraryArgs<FileEncryptorJobInit>, result: null } | 
-        { key: "files.eraseFiles", input: LibraryArgs<FileEraserJobInit>, result: null } | 
+        { key: "files.extract", input: LibraryArgs<FileExtractorJobInit>, result: null } | 
+        { key: "files.move", input: LibraryArgs<FileCutterJobInit>, result: null } | 
+        { key: "files.requestThumbnails", input: LibraryArgs<string[]>, result: null } | 
+        { key: "files.restoreFromTrash", input: LibraryArgs<number>, result: null } | 
+        { key: "files.restoreVersion", input: LibraryArgs<number>, result: null } | 
+        { key: "files.secureDelete", input: LibraryArgs<FileEraserJobInit>, result: null } | 
         { key: "files.setFavorite", input: LibraryArgs<SetFavoriteArgs>, result: null } | 
         { key: "files.setNote", input: LibraryArgs<SetNoteArgs>, result: null } | 
+        { key: "jobs.cancel", input: LibraryArgs<string>, result: null } | 
         { key: "jobs.clearAll", input: LibraryArgs<null>, result: null } | 
+        { key: "jobs.extractMediaData", input: LibraryArgs<MediaDataJobInit>, result: null } | 
         { key: "jobs.generateThumbsForLocation", input: LibraryArgs<GenerateThumbsForLocationArgs>, result: null } | 
         { key: "jobs.identifyUniqueFiles", input: LibraryArgs<IdentifyUniqueFilesArgs>, result: null } | 
+        { key: "jobs.indexArchives", input: LibraryArgs<IndexArchivesArgs>, result: null } | 
+        { key: "jobs.measureDiskUsage", input: LibraryArgs<MeasureDiskUsageArgs>, result: null } | 
         { key: "jobs.objectValidator", input: LibraryArgs<ObjectValidatorArgs>, result: null } | 
+        { key: "jobs.pause", input: LibraryArgs<string>, result: null } | 
+        { key: "jobs.regenerateThumbnails", input: LibraryArgs<RegenerateThumbnailsArgs>, result: null } | 
+        { key: "jobs.resume", input: LibraryArgs<string>, result: null } | 
+        { key: "jobs.schedules.create", input: LibraryArgs<JobScheduleCreateArgs>, result: number } | 
+        { key: "jobs.schedules.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "jobs.schedules.setEnabled", input: LibraryArgs<JobScheduleSetEnabledArgs>, result: null } | 
+        { key: "jobs.verifyLocation", input: LibraryArgs<number>, result: null } | 
         { key: "keys.add", input: LibraryArgs<KeyAddArgs>, result: null } | 
         { key: "keys.backupKeystore", input: LibraryArgs<string>, result: null } | 
         { key: "keys.changeMasterPassword", input: LibraryArgs<MasterPasswordChangeArgs>, result: null } | 
@@ -57,9 +121,22 @@ export type Procedures = {
         { key: "keys.unmount", input: LibraryArgs<string>, result: null } | 
         { key: "keys.unmountAll", input: LibraryArgs<null>, result: null } | 
         { key: "keys.updateAutomountStatus", input: LibraryArgs<AutomountUpdateArgs>, result: null } | 
+        { key: "library.backup", input: LibraryArgs<LibraryBackupJobInit>, result: null } | 
         { key: "library.create", input: CreateLibraryArgs, result: LibraryConfigWrapped } | 
-        { key: "library.delete", input: string, result: null } | 
+        { key: "library.delete", input: DeleteLibraryArgs, result: null } | 
         { key: "library.edit", input: EditLibraryArgs, result: null } | 
+        { key: "library.export", input: LibraryArgs<LibraryExportJobInit>, result: null } | 
+        { key: "library.import", input: string, result: ImportLibraryResult } | 
+        { key: "library.mergeInto", input: LibraryArgs<string>, result: null } | 
+        { key: "library.optimize", input: LibraryArgs<null>, result: null } | 
+        { key: "library.restore", input: string, result: LibraryConfigWrapped } | 
+        { key: "library.setBackupSchedule", input: LibraryArgs<LibraryBackupSchedule | null>, result: null } | 
+        { key: "library.setFileVersionRetention", input: LibraryArgs<FileVersionRetention>, result: null } | 
+        { key: "library.setMaintenanceInterval", input: LibraryArgs<number | null>, result: null } | 
+        { key: "library.setStorageBudget", input: LibraryArgs<LibraryStorageBudget | null>, result: null } | 
+        { key: "library.setTrashRetention", input: LibraryArgs<number | null>, result: null } | 
+        { key: "library.templates.delete", input: string, result: null } | 
+        { key: "library.templates.saveFromLibrary", input: LibraryArgs<SaveTemplateArgs>, result: LibraryTemplate } | 
         { key: "locations.addLibrary", input: LibraryArgs<LocationCreateArgs>, result: null } | 
         { key: "locations.create", input: LibraryArgs<LocationCreateArgs>, result: null } | 
         { key: "locations.delete", input: LibraryArgs<number>, result: null } | 
@@ -69,39 +146,187 @@ export type Procedures = {
         { key: "locations.quickRescan", input: LibraryArgs<LightScanArgs>, result: null } | 
         { key: "locations.relink", input: LibraryArgs<string>, result: null } | 
         { key: "locations.update", input: LibraryArgs<LocationUpdateArgs>, result: null } | 
+        { key: "nodes.checkForUpdates", input: never, result: ReleaseMetadata | null } | 
+        { key: "nodes.setUpdateConfig", input: UpdateConfig, result: null } | 
+        { key: "nodes.telemetry.clear", input: never, result: null } | 
+        { key: "nodes.telemetry.sendNow", input: never, result: null } | 
+        { key: "nodes.telemetry.setEnabled", input: boolean, result: null } | 
+        { key: "nodes.telemetry.setEndpoint", input: string | null, result: null } | 
         { key: "nodes.tokenizeSensitiveKey", input: TokenizeKeyArgs, result: TokenizeResponse } | 
         { key: "p2p.spacedrop", input: SpacedropArgs, result: null } | 
+        { key: "search.rebuildIndex", input: LibraryArgs<RebuildIndexArgs>, result: null } | 
+        { key: "search.saved.create", input: LibraryArgs<SavedSearchCreateArgs>, result: number } | 
+        { key: "search.saved.delete", input: LibraryArgs<number>, result: null } | 
+        { key: "search.saved.update", input: LibraryArgs<SavedSearchUpdateArgs>, result: null } | 
         { key: "tags.assign", input: LibraryArgs<TagAssignArgs>, result: null } | 
         { key: "tags.create", input: LibraryArgs<TagCreateArgs>, result: Tag } | 
         { key: "tags.delete", input: LibraryArgs<number>, result: null } | 
-        { key: "tags.update", input: LibraryArgs<TagUpdateArgs>, result: null },
+        { key: "tags.update", input: LibraryArgs<TagUpdateArgs>, result: null } | 
+        { key: "users.create", input: CreateUserArgs, result: CreatedUser } | 
+        { key: "users.delete", input: string, result: null } | 
+        { key: "users.regenerateToken", input: string, result: string } | 
+        { key: "users.setLibraryRole", input: SetLibraryRoleArgs, result: NodeUser } | 
+        { key: "volumes.benchmark", input: LibraryArgs<string>, result: VolumeBenchmark } | 
+        { key: "volumes.createLocation", input: LibraryArgs<VolumeLocationCreateArgs>, result: null } | 
+        { key: "volumes.probeThroughput", input: string, result: VolumeThroughput },
     subscriptions: 
-        { key: "invalidateQuery", input: never, result: InvalidateOperationEvent } | 
-        { key: "jobs.newThumbnail", input: LibraryArgs<null>, result: string } | 
+        { key: "invalidateQuery", input: string | null, result: InvalidateOperationEvent } | 
+        { key: "jobs.completed", input: LibraryArgs<null>, result: JobCompleted } | 
+        { key: "jobs.newThumbnail", input: LibraryArgs<string | null>, result: string } | 
+        { key: "library.storageBudgetAlerts", input: LibraryArgs<null>, result: StorageBudgetAlert } | 
         { key: "locations.online", input: never, result: number[][] } | 
+        { key: "locations.streamExplorerData", input: LibraryArgs<LocationExplorerStreamArgs>, result: ExplorerDataChunk } | 
+        { key: "nodes.updateAvailable", input: never, result: ReleaseMetadata } | 
         { key: "p2p.events", input: never, result: P2PEvent }
 };
 
+export type Activity = { id: number, kind: number, message: string, data: number[] | null, node_id: number, date_created: string }
+
+export type ActivityArgs = { cursor: number | null, take: number | null }
+
+export type ActivityPage = { items: Activity[], next_cursor: number | null }
+
 /**
  *  These are all possible algorithms that can be used for encryption and decryption
  */
 export type Algorithm = "XChaCha20Poly1305" | "Aes256Gcm"
 
+/**
+ *  The archive formats files can be compressed to and extracted from
+ */
+export type ArchiveFormat = "Zip" | "TarZst"
+
 export type AuthOption = { type: "Password", value: string } | { type: "TokenizedPassword", value: string }
 
 export type AutomountUpdateArgs = { uuid: string, status: boolean }
 
+/**
+ *  BatchMutation is a mutation of many objects at once, for the multi-selections of the explorer.
+ */
+export type BatchMutation = { type: "AssignTag", tag_id: number, object_ids: number[], unassign: boolean } | { type: "SetFavorite", object_ids: number[], favorite: boolean } | { type: "SetNote", object_ids: number[], note: string | null }
+
+/**
+ *  Bookmark is a bookmark with its target resolved to the ids of this node. They are `None` when the target
+ *  isn't indexed on this node, or doesn't exist anymore.
+ */
+export type Bookmark = { id: number, kind: BookmarkKind, name: string | null, position: number, location_id: number | null, file_path_id: number | null, materialized_path: string | null, search: string | null }
+
+export type BookmarkCreateArgs = { name: string | null, target: BookmarkTarget }
+
+/**
+ *  BookmarkKind is what a bookmark points to.
+ *  The values are stored in the database, so existing variants must never change their value.
+ */
+export type BookmarkKind = "Location" | "FilePath" | "Search"
+
+export type BookmarkRenameArgs = { id: number, name: string | null }
+
+/**
+ *  BookmarkTarget is what a new bookmark points to.
+ */
+export type BookmarkTarget = { type: "Location", location_id: number } | { type: "FilePath", location_id: number, file_path_id: number } | { type: "Search", search: string }
+
 export type BuildInfo = { version: string, commit: string }
 
+/**
+ *  BulkRenameArgs renames the `file_path_ids` of a location by a template, in the order they're given.
+ *  The template makes the new names without their extensions, which are kept, out of these tokens:
+ *  - `{name}`: the current name, after replacing the `find` pattern with `replace` in it
+ *  - `{n}`: a counter starting at `counter_start`, or `{n:3}` padded with zeros to 3 digits
+ *  - `{date}`: the date the file was created, or `{date:%Y%m%d}` with a custom format
+ */
+export type BulkRenameArgs = { location_id: number, file_path_ids: number[], template: string, find: string | null, replace: string, counter_start: number | null, dry_run: boolean }
+
+export type BulkRenameEntry = { id: number, name: string, new_name: string }
+
+/**
+ *  Capability is something an extension can do on the node, which must be granted by the user before the extension runs.
+ *  Extensions run in a WASM sandbox which only exposes the host functions of the granted capabilities.
+ */
+export type Capability = "Log" | "ReadFiles" | "ReadLibrary"
+
+/**
+ *  CasIdAlgorithm is how the cas_id of a file is generated. It's stored alongside every cas_id, so the values
+ *  must never change. The full file digests match the output of `b3sum` and `sha256sum` respectively.
+ */
+export type CasIdAlgorithm = "Sampled" | "Blake3" | "Sha256"
+
 /**
  *  ConfigMetadata is a part of node configuration that is loaded before the main configuration and contains information about the schema of the config.
  *  This allows us to migrate breaking changes to the config format between Spacedrive releases.
  */
 export type ConfigMetadata = { version: string | null }
 
+/**
+ *  ConflictPolicy tells what to do with a copied file or directory whose name is already taken in the target directory
+ */
+export type ConflictPolicy = "Skip" | "Rename" | "Overwrite"
+
+export type ContentSearchArgs = { query: string, take: number | null, skip: number | null }
+
+export type ContentSearchResult = { object: object_with_file_paths, score: number, snippet: string | null }
+
+/**
+ *  The formats files can be converted to
+ */
+export type ConvertFormat = "Png" | "Jpeg" | "Webp" | "Gif" | "Bmp" | "Tiff" | "Mp4" | "Webm" | "Mkv"
+
 export type CreateLibraryArgs = { name: string, auth: AuthOption, algorithm: Algorithm, hashing_algorithm: HashingAlgorithm }
 
-export type EditLibraryArgs = { id: string, name: string | null, description: string | null }
+export type CreateUserArgs = { name: string, node_admin: boolean }
+
+export type CreatedUser = { user: NodeUser, token: string }
+
+/**
+ *  DatabaseConfig overrides the `PRAGMA` settings of library databases. Every setting has a default tuned for indexing while the UI reads.
+ */
+export type DatabaseConfig = { journal_mode: JournalMode | null, synchronous: Synchronous | null, mmap_size: string | null, cache_size_kib: number | null }
+
+export type DeleteArgs = { id: number, permanent: boolean }
+
+export type DeleteLibraryArgs = { id: string, purge: boolean }
+
+export type DiskUsageArgs = { target: DiskUsageTarget, path: string | null }
+
+/**
+ *  DiskUsageBreakdown is a directory with its direct subdirectories, ready to be drawn as a treemap,
+ *  alongside the directories holding the most data directly inside them across the whole target.
+ */
+export type DiskUsageBreakdown = { directory: DiskUsageDirectory | null, children: DiskUsageDirectory[], heaviest: DiskUsageDirectory[] }
+
+/**
+ *  DiskUsageDirectory is the cached size of a directory, as shown in the storage cleanup view.
+ */
+export type DiskUsageDirectory = { path: string, parent_path: string | null, own_bytes: string, own_files: string, total_bytes: string, kinds: DiskUsageKind[] }
+
+export type DiskUsageKind = { kind: number, bytes: string }
+
+/**
+ *  DiskUsageTarget is what a [`DiskUsageJob`] scans.
+ */
+export type DiskUsageTarget = { Location: number } | { Volume: string }
+
+export type DuplicateAction = "Hardlink" | "Delete"
+
+/**
+ *  `DuplicateEliminatorJobInit` keeps the oldest file of every duplicate group, and hardlinks or deletes the others.
+ *  Files only share an object by their cas_id, so every copy is compared byte for byte with the kept one before being touched.
+ */
+export type DuplicateEliminatorJobInit = { location_id: number | null, object_ids: number[] | null, action: DuplicateAction, dry_run: boolean }
+
+/**
+ *  DuplicateFile is a file of a duplicate group, with its full path on this node.
+ */
+export type DuplicateFile = { location_id: number, file_path_id: number, materialized_path: string, path: string }
+
+/**
+ *  DuplicateGroup are the files of this node which point at the same object, oldest first.
+ */
+export type DuplicateGroup = { object_id: number, files: DuplicateFile[] }
+
+export type DuplicatesArgs = { location_id: number | null }
+
+export type EditLibraryArgs = { id: string, name: string | null, description: string | null, cas_id_algorithm: CasIdAlgorithm | null, perceptual_hashing: boolean | null }
 
 /**
  *  This should be used for passing an encrypted key around.
@@ -112,28 +337,73 @@ export type EncryptedKey = number[]
 
 export type ExplorerContext = ({ type:  "Location" } & Location) | ({ type:  "Tag" } & Tag)
 
-export type ExplorerData = { context: ExplorerContext, items: ExplorerItem[] }
+export type ExplorerData = { context: ExplorerContext, items: ExplorerItem[], cursor: string | null, total_count: number }
+
+/**
+ *  ExplorerDataChunk is an event of `locations.streamExplorerData`, which sends the context first,
+ *  then the items in batches as they're read from the database, and ends with `Done` or `Error`.
+ */
+export type ExplorerDataChunk = { type: "Context", context: ExplorerContext } | { type: "Items", items: ExplorerItem[] } | { type: "Done" } | { type: "Error", message: string }
+
+/**
+ *  ExplorerFilter narrows down the items of a directory listing, every field which is set must match.
+ *  Conditions on objects exclude the file paths which have none, like directories.
+ */
+export type ExplorerFilter = { kinds: number[], extensions: string[], min_size: number | null, max_size: number | null, created_after: string | null, created_before: string | null, modified_after: string | null, modified_before: string | null, tag_ids: number[], favorite: boolean | null, hidden: boolean | null }
 
 export type ExplorerItem = { type: "Path", has_thumbnail: boolean, item: file_path_with_object } | { type: "Object", has_thumbnail: boolean, item: object_with_file_paths }
 
-export type FileCopierJobInit = { source_location_id: number, source_path_id: number, target_location_id: number, target_path: string, target_file_name_suffix: string | null }
+/**
+ *  ExtensionInfo is an extension loaded by the node and the capabilities it has been granted.
+ */
+export type ExtensionInfo = { manifest: ExtensionManifest, granted: Capability[], enabled: boolean }
+
+/**
+ *  ExtensionManifest describes an extension. It's read from the `manifest.json` file next to the WASM module of the extension.
+ */
+export type ExtensionManifest = { name: string, version: string, description: string | null, capabilities: Capability[], step_handlers: StepHandler[], procedures: ExtensionProcedure[] }
+
+/**
+ *  ExtensionProcedure is an rspc procedure registered by an extension, which is called through `ext.query` or `ext.mutation`.
+ */
+export type ExtensionProcedure = { key: string, kind: ProcedureKind }
+
+export type ExtensionProcedureArgs = { extension: string, key: string, arg: any }
+
+export type FileCompressorJobInit = { location_id: number, path_id: number, target_path: string, format: ArchiveFormat }
 
-export type FileCutterJobInit = { source_location_id: number, source_path_id: number, target_location_id: number, target_path: string }
+export type FileConverterJobInit = { location_id: number, path_ids: number[], format: ConvertFormat, quality: number | null }
+
+export type FileCopierJobInit = { source_location_id: number, source_path_ids: number[], target_location_id: number, target_path: string, target_file_name_suffix: string | null, conflict_policy: ConflictPolicy }
+
+export type FileCutterJobInit = { source_location_id: number, source_path_ids: number[], target_location_id: number, target_path: string, conflict_policy: ConflictPolicy, dry_run: boolean }
 
 export type FileDecryptorJobInit = { location_id: number, path_id: number, mount_associated_key: boolean, output_path: string | null, password: string | null, save_to_library: boolean | null }
 
-export type FileDeleterJobInit = { location_id: number, path_id: number }
+export type FileDeleterJobInit = { location_id: number, path_id: number, permanent: boolean, dry_run: boolean }
 
 export type FileEncryptorJobInit = { location_id: number, path_id: number, key_uuid: string, algorithm: Algorithm, metadata: boolean, preview_media: boolean, output_path: string | null }
 
 export type FileEraserJobInit = { location_id: number, path_id: number, passes: string }
 
-export type FilePath = { id: number, is_dir: boolean, cas_id: string | null, integrity_checksum: string | null, location_id: number, materialized_path: string, name: string, extension: string, object_id: number | null, parent_id: number | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string }
+export type FileExtractorJobInit = { location_id: number, path_id: number, target_path: string }
+
+export type FilePath = { id: number, is_dir: boolean, cas_id: string | null, cas_id_algorithm: number | null, integrity_checksum: string | null, location_id: number, materialized_path: string, name: string, extension: string, object_id: number | null, parent_id: number | null, key_id: number | null, inode: number[] | null, device: number[] | null, is_symlink: boolean, symlink_target: string | null, hidden: boolean, in_archive: boolean, date_created: string, date_modified: string, date_indexed: string }
+
+export type FileVersion = { id: number, object_id: number, checksum: string, size_in_bytes: string, date_created: string }
+
+/**
+ *  FileVersionRetention is how long the versions of a file are kept, the oldest ones being pruned first.
+ *  The latest version of a file is never pruned.
+ */
+export type FileVersionRetention = { max_versions: number | null, max_age_days: number | null }
 
 export type GenerateThumbsForLocationArgs = { id: number, path: string }
 
 export type GetArgs = { id: number }
 
+export type GrantExtensionArgs = { extension: string, capabilities: Capability[] }
+
 /**
  *  This defines all available password hashing algorithms.
  */
@@ -141,6 +411,10 @@ export type HashingAlgorithm = { name: "Argon2id", params: Params } | { name: "B
 
 export type IdentifyUniqueFilesArgs = { id: number, path: string }
 
+export type ImportLibraryResult = { library: LibraryConfigWrapped, report: LibraryImportReport }
+
+export type IndexArchivesArgs = { id: number, path: string | null }
+
 export type IndexerRule = { id: number, kind: number, name: string, parameters: number[], date_created: string, date_modified: string }
 
 /**
@@ -150,17 +424,80 @@ export type IndexerRule = { id: number, kind: number, name: string, parameters:
  *  In case of  `RuleKind::AcceptFilesByGlob` or `RuleKind::RejectFilesByGlob`, it will be a
  *  single string containing a glob pattern.
  * 
+ *  In case of `RuleKind::AcceptFilesByRegex` or `RuleKind::RejectFilesByRegex`, it will be a
+ *  single string containing a regex matched against the full path.
+ * 
  *  In case of `RuleKind::AcceptIfChildrenDirectoriesArePresent` or `RuleKind::RejectIfChildrenDirectoriesArePresent` the
  *  `parameters` field must be a vector of strings containing the names of the directories.
+ * 
+ *  In case of `RuleKind::IgnoreFile` the `parameters` field must be a vector of strings containing the names of
+ *  the ignore files, like `.gitignore`.
+ * 
+ *  In case of `RuleKind::RejectFilesLargerThan` or `RuleKind::RejectFilesSmallerThan`, it will be a
+ *  single number with the size in bytes.
+ * 
+ *  In case of `RuleKind::RejectFilesModifiedBefore` or `RuleKind::RejectFilesModifiedAfter`, it will be a
+ *  single RFC 3339 date string.
  */
 export type IndexerRuleCreateArgs = { kind: RuleKind, name: string, parameters: number[] }
 
-export type InvalidateOperationEvent = { key: string, arg: any }
+export type IndexerRuleTestArgs = { rule: IndexerRuleCreateArgs, paths: string[] }
 
-export type JobReport = { id: string, name: string, data: number[] | null, metadata: any | null, date_created: string, date_modified: string, status: JobStatus, task_count: number, completed_task_count: number, message: string, seconds_elapsed: number }
+export type IndexerRuleTestResult = { path: string, accepted: boolean, error: string | null }
+
+export type InvalidateOperationEvent = { key: string, arg: any, 
+/**
+ * library_id is the library the invalidation comes from, `None` for the ones of the node.
+ */
+library_id: string | null, 
+/**
+ * scope is the part of a location the invalidation is about, `None` if it isn't about the directories of a location.
+ */
+scope: PathScope | null, 
+/**
+ * all tells to invalidate every query, as some invalidations were missed.
+ */
+all: boolean }
+
+/**
+ *  JobCompleted tells that a job of a library is done running, whether it completed, failed or was canceled.
+ */
+export type JobCompleted = { library_id: string, id: string, name: string, status: JobStatus, seconds_elapsed: number, error: string | null }
+
+export type JobHistoryArgs = { name: string | null, status: JobStatus | null, cursor: JobHistoryCursor | null, take: number | null }
+
+export type JobHistoryCursor = { date_created: string, id: string }
+
+export type JobHistoryPage = { items: JobReport[], next_cursor: JobHistoryCursor | null }
+
+export type JobReport = { id: string, name: string, data: number[] | null, metadata: any | null, date_created: string, date_modified: string, status: JobStatus, task_count: number, completed_task_count: number, message: string, seconds_elapsed: number, step_retries: number, parent_id: string | null, items_processed: number, bytes_read: string, error: string | null, total_bytes: string, completed_bytes: string, throughput: string | null, eta_seconds: number | null }
+
+/**
+ *  JobSchedule is a rule running a job of a library whenever its cron expression matches.
+ */
+export type JobSchedule = { id: number, name: string | null, job: ScheduledJob, cron: string, enabled: boolean, last_run: string | null, next_run: string | null, date_created: string }
+
+export type JobScheduleCreateArgs = { name: string | null, job: ScheduledJob, cron: string }
+
+export type JobScheduleSetEnabledArgs = { id: number, enabled: boolean }
+
+/**
+ *  JobStatistics sums up the finished jobs with a name.
+ */
+export type JobStatistics = { name: string, completed: number, failed: number, canceled: number, seconds_elapsed: string, items_processed: string, bytes_read: string, last_run: string | null }
 
 export type JobStatus = "Queued" | "Running" | "Completed" | "Canceled" | "Failed" | "Paused"
 
+/**
+ *  JobWebhookConfig sends the jobs of every library which finish or fail to webhooks.
+ */
+export type JobWebhookConfig = { urls: string[], min_seconds: number | null }
+
+/**
+ *  JournalMode is the SQLite journal mode of library databases.
+ */
+export type JournalMode = "Delete" | "Truncate" | "Persist" | "Wal"
+
 export type KeyAddArgs = { algorithm: Algorithm, hashing_algorithm: HashingAlgorithm, key: string, library_sync: boolean, automount: boolean }
 
 /**
@@ -168,16 +505,59 @@ export type KeyAddArgs = { algorithm: Algorithm, hashing_algorithm: HashingAlgor
  */
 export type LibraryArgs<T> = { library_id: string, arg: T }
 
+export type LibraryBackupJobInit = { output_dir: string | null, include_thumbnails: boolean }
+
+/**
+ *  LibraryBackupSchedule is the configuration for automatic library backups.
+ */
+export type LibraryBackupSchedule = { interval_secs: number, output_dir: string | null, include_thumbnails: boolean }
+
 /**
  *  LibraryConfig holds the configuration for a specific library. This is stored as a '{uuid}.sdlibrary' file.
  */
-export type LibraryConfig = ({ version: string | null }) & { name: string, description: string }
+export type LibraryConfig = ({ version: string | null }) & { name: string, description: string, cas_id_algorithm: CasIdAlgorithm, perceptual_hashing: boolean }
 
 export type LibraryConfigWrapped = { uuid: string, config: LibraryConfig }
 
+export type LibraryExportJobInit = { output_path: string, include_thumbnails: boolean }
+
+/**
+ *  `LibraryImportReport` describes how the locations of an imported library were matched
+ *  against the locations already present on this node.
+ */
+export type LibraryImportReport = { relinked_locations: string[], kept_locations: string[], missing_locations: string[] }
+
+export type LibraryOverview = { id: string, name: string, object_count: number, bytes_used: string, library_db_size: string, queued_jobs: number, paused_jobs: number, failed_jobs: number }
+
+/**
+ *  LibraryRole is what a user is allowed to do in a library. Roles are ordered from the least to the most privileged.
+ */
+export type LibraryRole = "ReadOnly" | "ReadWrite" | "Admin"
+
+/**
+ *  LibraryStorageBudget is a soft cap on the size of the thumbnails, database and backups of a library.
+ *  Exceeding it never blocks anything, it only triggers alerts.
+ */
+export type LibraryStorageBudget = { max_bytes: string, webhook_url: string | null }
+
+/**
+ *  LibraryStorageUsage is the disk space used by a library, split by what uses it.
+ */
+export type LibraryStorageUsage = { database_bytes: string, thumbnails_bytes: string, managed_bytes: string }
+
+/**
+ *  LibraryTemplate holds the setup of a library which can be reused when creating new libraries.
+ *  This is stored as a '{uuid}.sdtemplate' file in the templates directory.
+ */
+export type LibraryTemplate = { id: string, name: string, description: string, indexer_rules: LibraryTemplateIndexerRule[], tags: LibraryTemplateTag[], backup: LibraryBackupSchedule | null, maintenance_interval_secs: number | null }
+
+export type LibraryTemplateIndexerRule = { kind: number, name: string, parameters: number[] }
+
+export type LibraryTemplateTag = { name: string | null, color: string | null }
+
 export type LightScanArgs = { location_id: number, sub_path: string }
 
-export type Location = { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, hidden: boolean, date_created: string }
+export type Location = { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, hidden: boolean, file_versioning: boolean, symlink_policy: number, date_created: string, volume_uuid: string | null, volume_subpath: string | null }
 
 /**
  *  `LocationCreateArgs` is the argument received from the client using `rspc` to create a new location.
@@ -186,7 +566,9 @@ export type Location = { id: number, pub_id: number[], node_id: number, name: st
  */
 export type LocationCreateArgs = { path: string, indexer_rules_ids: number[] }
 
-export type LocationExplorerArgs = { location_id: number, path: string, limit: number, cursor: string | null }
+export type LocationExplorerArgs = { location_id: number, path: string, limit: number, cursor: string | null, filter: ExplorerFilter | null, show_hidden: boolean | null }
+
+export type LocationExplorerStreamArgs = { location_id: number, path: string, batch_size: number | null, filter: ExplorerFilter | null }
 
 /**
  *  `LocationUpdateArgs` is the argument received from the client using `rspc` to update a location.
@@ -196,20 +578,46 @@ export type LocationExplorerArgs = { location_id: number, path: string, limit: n
  *  It is important to note that only the indexer rule ids in this vector will be used from now on.
  *  Old rules that aren't in this vector will be purged.
  */
-export type LocationUpdateArgs = { id: number, name: string | null, generate_preview_media: boolean | null, sync_preview_media: boolean | null, hidden: boolean | null, indexer_rules_ids: number[] }
+export type LocationUpdateArgs = { id: number, name: string | null, generate_preview_media: boolean | null, sync_preview_media: boolean | null, hidden: boolean | null, file_versioning: boolean | null, symlink_policy: SymlinkPolicy | null, indexer_rules_ids: number[] }
+
+/**
+ *  LocationVolume is a volume which backs at least one location of the library.
+ */
+export type LocationVolume = { volume: Volume, location_ids: number[] }
+
+/**
+ *  LogEntry is a single line of the structured logs.
+ */
+export type LogEntry = { timestamp: string, level: string, target: string, fields: any }
+
+/**
+ *  LogQuery filters the logs returned by [`read_logs`].
+ */
+export type LogQuery = { level: string | null, target: string | null, since: string | null, until: string | null, limit: number | null }
 
 export type MasterPasswordChangeArgs = { password: string, algorithm: Algorithm, hashing_algorithm: HashingAlgorithm }
 
-export type MediaData = { id: number, pixel_width: number | null, pixel_height: number | null, longitude: number | null, latitude: number | null, fps: number | null, capture_device_make: string | null, capture_device_model: string | null, capture_device_software: string | null, duration_seconds: number | null, codecs: string | null, streams: number | null }
+export type MeasureDiskUsageArgs = { target: DiskUsageTarget, full_rescan: boolean }
+
+export type MediaData = { id: number, pixel_width: number | null, pixel_height: number | null, longitude: number | null, latitude: number | null, fps: number | null, capture_device_make: string | null, capture_device_model: string | null, capture_device_software: string | null, duration_seconds: number | null, codecs: string | null, streams: number | null, capture_date: string | null, tags: string | null, date_extracted: string }
+
+export type MediaDataJobInit = { location_id: number, regenerate: boolean }
 
 export type Node = { id: number, pub_id: number[], name: string, platform: number, version: string | null, last_seen: string, timezone: string | null, date_created: string }
 
 /**
  *  NodeConfig is the configuration for a node. This is shared between all libraries and is stored in a JSON file on disk.
  */
-export type NodeConfig = ({ version: string | null }) & { id: string, name: string, p2p_port: number | null, p2p_email: string | null, p2p_img_url: string | null }
+export type NodeConfig = ({ version: string | null }) & { id: string, name: string, p2p_port: number | null, p2p_email: string | null, p2p_img_url: string | null, otlp: OtlpConfig | null, log_level: string | null, max_running_jobs: number | null, max_interactive_jobs: number | null, max_background_jobs: number | null, thumbnail: ThumbnailConfig, resources: ResourceLimits, throttling: ThrottlingConfig, job_read_limits: { [key: string]: string }, job_webhooks: JobWebhookConfig, telemetry: TelemetryConfig, updates: UpdateConfig, database: DatabaseConfig }
 
-export type NodeState = (({ version: string | null }) & { id: string, name: string, p2p_port: number | null, p2p_email: string | null, p2p_img_url: string | null }) & { data_path: string }
+export type NodeOverview = { libraries: LibraryOverview[], total_object_count: number, total_bytes_used: string, total_library_db_size: string, running_jobs: number }
+
+export type NodeState = (({ version: string | null }) & { id: string, name: string, p2p_port: number | null, p2p_email: string | null, p2p_img_url: string | null, otlp: OtlpConfig | null, log_level: string | null, max_running_jobs: number | null, max_interactive_jobs: number | null, max_background_jobs: number | null, thumbnail: ThumbnailConfig, resources: ResourceLimits, telemetry: TelemetryConfig, updates: UpdateConfig, database: DatabaseConfig }) & { data_path: string }
+
+/**
+ *  NodeUser is an account which can access the node through a token, used when a node is shared between multiple people.
+ */
+export type NodeUser = { id: string, name: string, node_admin: boolean, libraries: { [key: string]: LibraryRole } }
 
 /**
  *  This should be used for providing a nonce to encrypt/decrypt functions.
@@ -218,7 +626,7 @@ export type NodeState = (({ version: string | null }) & { id: string, name: stri
  */
 export type Nonce = { XChaCha20Poly1305: number[] } | { Aes256Gcm: number[] }
 
-export type Object = { id: number, pub_id: number[], name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, date_modified: string, date_indexed: string }
+export type Object = { id: number, pub_id: number[], name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, integrity_checksum: string | null, last_verified_at: string | null, perceptual_hash: string | null, date_created: string, date_modified: string, date_indexed: string }
 
 export type ObjectValidatorArgs = { id: number, path: string }
 
@@ -228,6 +636,12 @@ export type ObjectValidatorArgs = { id: number, path: string }
  */
 export type OperatingSystem = "Windows" | "Linux" | "MacOS" | "Ios" | "Android" | { Other: string }
 
+/**
+ *  OtlpConfig configures the export of tracing spans to an OpenTelemetry collector.
+ *  Changes are applied when the node is restarted.
+ */
+export type OtlpConfig = { endpoint: string, service_name: string | null }
+
 /**
  *  TODO: P2P event for the frontend
  */
@@ -240,11 +654,47 @@ export type P2PEvent = { type: "DiscoveredPeer", peer_id: string, metadata: Peer
  */
 export type Params = "Standard" | "Hardened" | "Paranoid"
 
+/**
+ *  PathScope narrows down an invalidation of directory listings to the directories of a location which changed,
+ *  so clients don't refetch the listings of the other directories.
+ */
+export type PathScope = { location_id: number, 
+/**
+ * path_prefix is the materialized path of the changed directory, whose listing and the ones of its subdirectories are invalidated.
+ * Every listing of the location is invalidated if it's not set.
+ */
+path_prefix: string | null }
+
+/**
+ *  PathSearchCursor is where a page of results ends, the next page starts right after it.
+ */
+export type PathSearchCursor = { score: number, location_id: number, id: number }
+
+export type PathSearchResult = { item: file_path_with_object, score: number }
+
 export type PeerMetadata = { name: string, operating_system: OperatingSystem | null, version: string | null, email: string | null, img_url: string | null }
 
+export type ProcedureKind = "Query" | "Mutation"
+
+export type RebuildIndexArgs = { location_id: number | null }
+
+export type RegenerateThumbnailsArgs = { location_id: number, sub_path: string | null }
+
+/**
+ *  ReleaseMetadata describes a release of Spacedrive, as returned by the update endpoint.
+ */
+export type ReleaseMetadata = { version: string, channel: UpdateChannel, url: string, notes: string | null, published_at: string | null }
+
+/**
+ *  ResourceLimits caps the resources used by jobs. Every limit defaults to a value fit for the current device when unset.
+ */
+export type ResourceLimits = { worker_threads: number | null, max_open_files: number | null, cache_memory_bytes: string | null }
+
 export type RestoreBackupArgs = { password: string, secret_key: string, path: string }
 
-export type RuleKind = "AcceptFilesByGlob" | "RejectFilesByGlob" | "AcceptIfChildrenDirectoriesArePresent" | "RejectIfChildrenDirectoriesArePresent"
+export type RuleKind = "AcceptFilesByGlob" | "RejectFilesByGlob" | "AcceptIfChildrenDirectoriesArePresent" | "RejectIfChildrenDirectoriesArePresent" | "IgnoreFile" | "AcceptFilesByRegex" | "RejectFilesByRegex" | "RejectFilesLargerThan" | "RejectFilesSmallerThan" | "RejectFilesModifiedBefore" | "RejectFilesModifiedAfter"
+
+export type RunStepHandlerArgs = { location_id: number, extension: string, handler: string }
 
 /**
  *  This should be used for passing a salt around.
@@ -253,13 +703,56 @@ export type RuleKind = "AcceptFilesByGlob" | "RejectFilesByGlob" | "AcceptIfChil
  */
 export type Salt = number[]
 
+export type SaveTemplateArgs = { name: string, description: string }
+
+export type SavedSearch = { id: number, name: string | null, filter: SearchFilter, smart: boolean, date_created: string, date_modified: string }
+
+export type SavedSearchCreateArgs = { name: string | null, filter: SearchFilter, smart: boolean }
+
+export type SavedSearchUpdateArgs = { id: number, name: string | null, filter: SearchFilter | null, smart: boolean | null }
+
+/**
+ *  ScheduledJob is a job which can be run on a schedule.
+ */
+export type ScheduledJob = { type: "Rescan", location_id: number } | { type: "Verify", location_id: number } | { type: "Validate", location_id: number }
+
+/**
+ *  SearchFilter is what the objects found by a saved search must match, every field which is set must match.
+ */
+export type SearchFilter = { kinds: number[], tag_ids: number[], location_ids: number[], created_after: string | null, created_before: string | null, modified_after: string | null, modified_before: string | null, min_size: number | null, max_size: number | null }
+
+export type SearchPaths = { items: PathSearchResult[], cursor: PathSearchCursor | null, truncated: boolean }
+
+export type SearchPathsArgs = { query: string, location_id: number | null, take: number | null, cursor: PathSearchCursor | null }
+
 export type SetFavoriteArgs = { id: number, favorite: boolean }
 
+export type SetLibraryRoleArgs = { user_id: string, library_id: string, role: LibraryRole | null }
+
 export type SetNoteArgs = { id: number, note: string | null }
 
+export type SimilarImage = { object: Object, distance: number }
+
+export type SimilarImagesArgs = { id: number, max_distance: number | null }
+
 export type SpacedropArgs = { peer_id: string, file_path: string }
 
-export type Statistics = { id: number, date_captured: string, total_object_count: number, library_db_size: string, total_bytes_used: string, total_bytes_capacity: string, total_unique_bytes: string, total_bytes_free: string, preview_media_bytes: string }
+export type Statistics = { id: number, date_captured: string, total_object_count: number, library_db_size: string, total_bytes_used: string, total_bytes_capacity: string, total_unique_bytes: string, total_bytes_free: string, preview_media_bytes: string, trash_bytes: string }
+
+/**
+ *  StepHandler is a job step registered by an extension, which can be run on a location with the extension job.
+ */
+export type StepHandler = { name: string, kind: StepHandlerKind, extensions: string[] }
+
+/**
+ *  StepHandlerKind is what a step handler does with the files of a location.
+ */
+export type StepHandlerKind = "MetadataExtractor" | "Exporter"
+
+/**
+ *  StorageBudgetAlert is emitted when a library crosses one of the [`STORAGE_BUDGET_THRESHOLDS`] of its budget.
+ */
+export type StorageBudgetAlert = { library_id: string, library_name: string, threshold: number, used_bytes: string, max_bytes: string }
 
 /**
  *  This is a stored key, and can be freely written to the database.
@@ -278,24 +771,119 @@ export type StoredKeyType = "User" | "Root"
  */
 export type StoredKeyVersion = "V1"
 
-export type Tag = { id: number, pub_id: number[], name: string | null, color: string | null, total_objects: number | null, redundancy_goal: number | null, date_created: string, date_modified: string }
+/**
+ *  `SymlinkPolicy` is how the indexer handles the symlinks of a location, stored in its `symlink_policy` column.
+ *  Following them indexes their targets as if they were in the location, while indexing them as links keeps
+ *  where they point to without reading their targets, so backup-style locations don't count the same data twice.
+ */
+export type SymlinkPolicy = "Skip" | "Follow" | "IndexAsLink"
+
+/**
+ *  Synchronous is how often SQLite waits for writes to reach the disk.
+ */
+export type Synchronous = "Off" | "Normal" | "Full"
+
+export type Tag = { id: number, pub_id: number[], name: string | null, color: string | null, total_objects: number | null, redundancy_goal: number | null, file_versioning: boolean, date_created: string, date_modified: string }
 
 export type TagAssignArgs = { object_id: number, tag_id: number, unassign: boolean }
 
 export type TagCreateArgs = { name: string, color: string }
 
-export type TagUpdateArgs = { id: number, name: string | null, color: string | null }
+export type TagUpdateArgs = { id: number, name: string | null, color: string | null, file_versioning: boolean | null }
+
+/**
+ *  TelemetryConfig controls the anonymous telemetry of the node, which is off by default.
+ */
+export type TelemetryConfig = { enabled: boolean, endpoint: string | null }
+
+/**
+ *  TelemetryReport is everything sent about a node, which never includes names, paths or identifiers of the node or its libraries.
+ */
+export type TelemetryReport = { anonymous_id: string, version: string, platform: string, arch: string, library_sizes: string[], job_failures: { [key: string]: number } }
+
+/**
+ *  ThrottlingConfig overrides how CPU and disk heavy jobs, like the identifier and the thumbnailer, are slowed down
+ *  to spare the battery and the temperature of the device. Every setting defaults to a value fit for laptops when unset.
+ */
+export type ThrottlingConfig = { mode: ThrottlingMode, ignore_battery: boolean, thermal_threshold_celsius: number | null, parallelism_factor: number | null, max_read_bytes_per_second: string | null }
+
+/**
+ *  ThrottlingMode tells when jobs are throttled.
+ */
+export type ThrottlingMode = "Auto" | "Always" | "Never"
+
+/**
+ *  ThumbnailCacheInfo describes the thumbnail cache of the node.
+ */
+export type ThumbnailCacheInfo = { thumbnails: string, size_bytes: string, limit_bytes: string | null }
+
+/**
+ *  ThumbnailConfig configures how thumbnails are generated.
+ */
+export type ThumbnailConfig = { size_factor: number, quality: number, format: ThumbnailFormat, workers: number | null, worker_memory_bytes: string | null, variants: ThumbnailVariant[], video_preview_strips: boolean, animated_thumbnails: boolean, cache_limit_bytes: string | null, concurrency: number | null, battery_throttle: boolean }
+
+/**
+ *  ThumbnailFormat is the image format of thumbnails.
+ *  AVIF makes smaller files than WebP but is slower to encode.
+ */
+export type ThumbnailFormat = "Webp" | "Avif"
+
+/**
+ *  ThumbnailVariant is an extra size of thumbnails, written to a directory of its own in the thumbnails directory.
+ */
+export type ThumbnailVariant = { name: string, size: number, quality: number | null }
 
 export type TokenizeKeyArgs = { secret_key: string }
 
 export type TokenizeResponse = { token: string }
 
+export type TrashedFile = { id: number, pub_id: number[], location_id: number, materialized_path: string, name: string, is_dir: boolean, size_in_bytes: string, date_trashed: string }
+
 export type UnlockKeyManagerArgs = { password: string, secret_key: string }
 
-export type Volume = { name: string, mount_point: string, total_capacity: string, available_capacity: string, is_removable: boolean, disk_type: string | null, file_system: string | null, is_root_filesystem: boolean }
+/**
+ *  UpdateChannel is the release channel the node is updated from.
+ */
+export type UpdateChannel = "stable" | "beta"
+
+/**
+ *  UpdateConfig configures where the node checks for updates.
+ */
+export type UpdateConfig = { channel: UpdateChannel, endpoint: string | null }
+
+/**
+ *  VideoPreviewStrip describes the preview strip of a video, served by `thumbnail/preview-strip/<cas_id>`.
+ *  Its frames are taken at regular intervals of the video and laid side by side, the first one on the left.
+ */
+export type VideoPreviewStrip = { cas_id: string, frames: number, frame_width: number, frame_height: number }
+
+export type VideoPreviewStripArgs = { location_id: number, file_path_id: number }
+
+export type Volume = { name: string, uuid: string | null, mount_point: string, total_capacity: string, available_capacity: string, is_removable: boolean, disk_type: string | null, file_system: string | null, is_root_filesystem: boolean, kind: VolumeKind, smart: VolumeSmart | null, health_warning: boolean }
+
+/**
+ *  VolumeBenchmark is the result of a read benchmark of a volume.
+ */
+export type VolumeBenchmark = { mount_point: string, sequential_read_mbps: number, random_read_iops: number, date_created: string }
+
+export type VolumeKind = "Local" | "Removable" | "Network"
+
+/**
+ *  `VolumeLocationCreateArgs` is the argument received from the client using `rspc` to create a new location
+ *  bound to a volume. The location path is resolved from the volume's current mount point, and
+ *  followed when the volume is mounted somewhere else, like under a new drive letter.
+ */
+export type VolumeLocationCreateArgs = { volume_id: string, subpath: string, indexer_rules_ids: number[] }
+
+export type VolumeSmart = { passed: boolean | null, reallocated_sectors: number | null, wear_level: number | null, temperature: number | null }
+
+/**
+ *  VolumeThroughput is the measured sequential speed of a volume, in MB/s.
+ */
+export type VolumeThroughput = { write_mb_per_sec: number, read_mb_per_sec: number }
 
-export type file_path_with_object = { id: number, is_dir: boolean, cas_id: string | null, integrity_checksum: string | null, location_id: number, materialized_path: string, name: string, extension: string, object_id: number | null, parent_id: number | null, key_id: number | null, date_created: string, date_modified: string, date_indexed: string, object: Object | null }
+export type file_path_with_object = { id: number, is_dir: boolean, cas_id: string | null, cas_id_algorithm: number | null, integrity_checksum: string | null, location_id: number, materialized_path: string, name: string, extension: string, object_id: number | null, parent_id: number | null, key_id: number | null, inode: number[] | null, device: number[] | null, is_symlink: boolean, symlink_target: string | null, hidden: boolean, in_archive: boolean, date_created: string, date_modified: string, date_indexed: string, object: Object | null }
 
 export type location_with_indexer_rules = { id: number, pub_id: number[], node_id: number, name: string, path: string, total_capacity: number | null, available_capacity: number | null, is_archived: boolean, generate_preview_media: boolean, sync_preview_media: boolean, hidden: boolean, date_created: string, indexer_rules: { indexer_rule: IndexerRule }[] }
 
-export type object_with_file_paths = { id: number, pub_id: number[], name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, date_created: string, date_modified: string, date_indexed: string, file_paths: FilePath[] }
+export type object_with_file_paths = { id: number, pub_id: number[], name: string | null, extension: string | null, kind: number, size_in_bytes: string, key_id: number | null, hidden: boolean, favorite: boolean, important: boolean, has_thumbnail: boolean, has_thumbstrip: boolean, has_video_preview: boolean, ipfs_id: string | null, note: string | null, integrity_checksum: string | null, last_verified_at: string | null, perceptual_hash: string | null, date_created: string, date_modified: string, date_indexed: string, file_paths: FilePath[] }
@@ -1,6 +1,8 @@
 import { ProcedureDef } from '@rspc/client';
 import { internal_createReactHooksFactory } from '@rspc/react';
-import { LibraryArgs, Procedures } from './core';
+import { useInfiniteQuery } from '@tanstack/react-query';
+import { useEffect, useMemo } from 'react';
+import { ExplorerData, LibraryArgs, LocationExplorerArgs, Procedures } from './core';
 import { currentLibraryCache } from './hooks';
 import { normiCustomHooks } from './normi';
 
@@ -77,15 +79,86 @@ export const useBridgeSubscription = nonLibraryHooks.useSubscription;
 export const useLibraryQuery = libraryHooks.useQuery;
 export const useLibraryMutation = libraryHooks.useMutation;
 
-export function useInvalidateQuery() {
+/**
+ * Loads the listing of a directory of a location page by page, each page starting at the cursor of the previous one.
+ * It's keyed like `locations.getExplorerData`, so the invalidations of the listing refetch every loaded page.
+ */
+export function useExplorerData(args: Omit<LocationExplorerArgs, 'cursor'>) {
 	const context = rspc.useContext();
-	rspc.useSubscription(['invalidateQuery'], {
+	const libraryId = currentLibraryCache.id;
+
+	const query = useInfiniteQuery({
+		queryKey: ['locations.getExplorerData', { library_id: libraryId, arg: args }],
+		queryFn: ({ pageParam = null }) => {
+			if (libraryId === null)
+				throw new Error('Attempted to do library operation with no library set!');
+			return context.client.query([
+				'locations.getExplorerData',
+				{ library_id: libraryId, arg: { ...args, cursor: pageParam } }
+			]);
+		},
+		getNextPageParam: (lastPage: ExplorerData) => lastPage.cursor ?? undefined
+	});
+
+	// The explorer shows the loaded pages as a single listing
+	const data = useMemo<ExplorerData | undefined>(() => {
+		const pages = query.data?.pages;
+		const lastPage = pages?.[pages.length - 1];
+		if (!pages || !lastPage) return undefined;
+
+		return { ...lastPage, items: pages.flatMap((page) => page.items) };
+	}, [query.data]);
+
+	return { ...query, data };
+}
+
+/**
+ * Invalidates the queries the node tells to refetch. With a library, only the invalidations of that library and of the node are received,
+ * without one only the ones of the node. The cached queries of other libraries are reset, as their invalidations aren't received.
+ */
+export function useInvalidateQuery(libraryId: string | null = null) {
+	const context = rspc.useContext();
+
+	// The invalidations of other libraries aren't received, so their cached queries can't be trusted anymore
+	useEffect(() => {
+		context.queryClient.resetQueries({
+			predicate: (query) => {
+				const queryLibraryId = (query.queryKey[1] as { library_id?: string } | undefined)
+					?.library_id;
+				return queryLibraryId !== undefined && queryLibraryId !== libraryId;
+			}
+		});
+	}, [context.queryClient, libraryId]);
+
+	rspc.useSubscription(['invalidateQuery', libraryId], {
 		onData: (invalidateOperation) => {
-			const key = [invalidateOperation.key];
+			// Some invalidations were missed, so everything is refetched
+			if (invalidateOperation.all) {
+				context.queryClient.invalidateQueries();
+				return;
+			}
+
+			const key: unknown[] = [invalidateOperation.key];
+			// Library queries are keyed by `{ library_id, arg }`, an argument scopes the invalidation to the matching queries
 			if (invalidateOperation.arg !== null) {
-				key.concat(invalidateOperation.arg);
+				key.push({ arg: invalidateOperation.arg });
 			}
-			context.queryClient.invalidateQueries(key);
+
+			// A scope narrows down the invalidation to the listings of the directories of a location under a path
+			const { scope } = invalidateOperation;
+			context.queryClient.invalidateQueries({
+				queryKey: key,
+				predicate: scope
+					? (query) => {
+							const arg = (query.queryKey[1] as { arg?: any } | undefined)?.arg;
+							if (arg?.location_id !== scope.location_id) return false;
+							if (scope.path_prefix === null) return true;
+
+							const path: string = arg.path.endsWith('/') ? arg.path : `${arg.path}/`;
+							return path.startsWith(scope.path_prefix);
+					  }
+					: undefined
+			});
 		}
 	});
 }
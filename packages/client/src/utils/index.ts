@@ -12,6 +12,14 @@ export function isObject(item: ExplorerItem): item is Extract<ExplorerItem, { ty
 	return item.type === 'Object';
 }
 
+// The archives whose contents can be indexed, as in the core `ArchiveKind`
+const ARCHIVE_SUFFIXES = ['.zip', '.tar', '.tar.gz', '.tgz', '.7z'];
+
+export function isArchive(path: string) {
+	const lowercasePath = path.toLowerCase();
+	return ARCHIVE_SUFFIXES.some((suffix) => lowercasePath.endsWith(suffix));
+}
+
 export function arraysEqual<T>(a: T[], b: T[]) {
 	if (a === b) return true;
 	if (a == null || b == null) return false;